@@ -0,0 +1,250 @@
+// Keychain entries on Android, which keyring has no store for
+// Each secret is sealed with an AES key kept in the AndroidKeyStore, which never lets it
+// leave the device's secure hardware, and the sealed bytes are kept in the app's private
+// config dir. secrets.rs keeps going through keyring and doesn't know the difference
+
+use std::any::Any;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use base64::Engine;
+use jni::objects::{JByteArray, JObject, JValue};
+use jni::JNIEnv;
+use keyring::credential::{Credential, CredentialApi, CredentialBuilderApi};
+use tauri::{AppHandle, Manager};
+
+use crate::fs_util;
+
+const KEY_STORE: &str = "AndroidKeyStore";
+/// Alias of the key that seals every secret
+const KEY_ALIAS: &str = "subspace_voice_secrets";
+const TRANSFORMATION: &str = "AES/GCM/NoPadding";
+/// Length of the GCM nonce that starts each sealed secret
+const IV_LEN: usize = 12;
+const TAG_BITS: i32 = 128;
+/// `KeyProperties.PURPOSE_ENCRYPT | KeyProperties.PURPOSE_DECRYPT`
+const PURPOSE_ENCRYPT_DECRYPT: i32 = 3;
+/// `Cipher.ENCRYPT_MODE` and `Cipher.DECRYPT_MODE`
+const ENCRYPT_MODE: i32 = 1;
+const DECRYPT_MODE: i32 = 2;
+
+/// Make keyring entries live in the keystore; must run before the first entry is made
+pub fn init(app: &AppHandle) {
+    match app.path().app_config_dir() {
+        Ok(dir) => keyring::set_default_credential_builder(Box::new(KeystoreBuilder {
+            dir: dir.join("keystore"),
+        })),
+        Err(e) => tracing::warn!("Keys can't be kept in the Android keystore: {}", e),
+    }
+}
+
+struct KeystoreBuilder {
+    dir: PathBuf,
+}
+
+impl CredentialBuilderApi for KeystoreBuilder {
+    fn build(
+        &self,
+        _target: Option<&str>,
+        service: &str,
+        user: &str,
+    ) -> keyring::Result<Box<Credential>> {
+        // Accounts hold characters that aren't safe in file names, like a profile's ':'
+        let name = URL_SAFE_NO_PAD.encode(format!("{}/{}", service, user));
+        Ok(Box::new(KeystoreCredential {
+            path: self.dir.join(name),
+        }))
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// One secret: its sealed bytes, base64 encoded, in a file of its own
+#[derive(Debug)]
+struct KeystoreCredential {
+    path: PathBuf,
+}
+
+impl CredentialApi for KeystoreCredential {
+    fn set_secret(&self, secret: &[u8]) -> keyring::Result<()> {
+        let sealed = with_key(|env, key| seal(env, key, secret))?;
+        fs_util::write_atomic(&self.path, STANDARD.encode(sealed).as_bytes(), true)
+            .map_err(platform_failure)
+    }
+
+    fn get_secret(&self) -> keyring::Result<Vec<u8>> {
+        let encoded = match fs::read_to_string(&self.path) {
+            Ok(encoded) => encoded,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Err(keyring::Error::NoEntry),
+            Err(e) => return Err(platform_failure(e)),
+        };
+        let sealed = STANDARD
+            .decode(encoded.trim())
+            .map_err(|_| keyring::Error::BadEncoding(encoded.into_bytes()))?;
+        if sealed.len() <= IV_LEN {
+            return Err(keyring::Error::BadEncoding(sealed));
+        }
+        with_key(|env, key| open(env, key, &sealed))
+    }
+
+    fn delete_credential(&self) -> keyring::Result<()> {
+        match fs::remove_file(&self.path) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == ErrorKind::NotFound => Err(keyring::Error::NoEntry),
+            Err(e) => Err(platform_failure(e)),
+        }
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+fn platform_failure(e: impl std::error::Error + Send + Sync + 'static) -> keyring::Error {
+    keyring::Error::PlatformFailure(Box::new(e))
+}
+
+/// Run `f` with the sealing key, made on first use
+fn with_key<T>(
+    f: impl FnOnce(&mut JNIEnv, &JObject) -> jni::errors::Result<T>,
+) -> keyring::Result<T> {
+    super::with_activity(|env, _| {
+        let key = secret_key(env)?;
+        f(env, &key)
+    })
+    .map_err(platform_failure)
+}
+
+fn secret_key<'local>(env: &mut JNIEnv<'local>) -> jni::errors::Result<JObject<'local>> {
+    let provider = env.new_string(KEY_STORE)?;
+    let store = env
+        .call_static_method(
+            "java/security/KeyStore",
+            "getInstance",
+            "(Ljava/lang/String;)Ljava/security/KeyStore;",
+            &[(&provider).into()],
+        )?
+        .l()?;
+    env.call_method(
+        &store,
+        "load",
+        "(Ljava/security/KeyStore$LoadStoreParameter;)V",
+        &[(&JObject::null()).into()],
+    )?;
+    let alias = env.new_string(KEY_ALIAS)?;
+    let key = env
+        .call_method(
+            &store,
+            "getKey",
+            "(Ljava/lang/String;[C)Ljava/security/Key;",
+            &[(&alias).into(), (&JObject::null()).into()],
+        )?
+        .l()?;
+    if !key.is_null() {
+        return Ok(key);
+    }
+
+    const BUILDER: &str = "android/security/keystore/KeyGenParameterSpec$Builder";
+    const SET_STRINGS: &str =
+        "([Ljava/lang/String;)Landroid/security/keystore/KeyGenParameterSpec$Builder;";
+    let builder = env.new_object(
+        BUILDER,
+        "(Ljava/lang/String;I)V",
+        &[(&alias).into(), JValue::Int(PURPOSE_ENCRYPT_DECRYPT)],
+    )?;
+    let mode = env.new_string("GCM")?;
+    let modes = env.new_object_array(1, "java/lang/String", &mode)?;
+    env.call_method(&builder, "setBlockModes", SET_STRINGS, &[(&modes).into()])?;
+    let padding = env.new_string("NoPadding")?;
+    let paddings = env.new_object_array(1, "java/lang/String", &padding)?;
+    env.call_method(
+        &builder,
+        "setEncryptionPaddings",
+        SET_STRINGS,
+        &[(&paddings).into()],
+    )?;
+    let spec = env
+        .call_method(
+            &builder,
+            "build",
+            "()Landroid/security/keystore/KeyGenParameterSpec;",
+            &[],
+        )?
+        .l()?;
+
+    let algorithm = env.new_string("AES")?;
+    let generator = env
+        .call_static_method(
+            "javax/crypto/KeyGenerator",
+            "getInstance",
+            "(Ljava/lang/String;Ljava/lang/String;)Ljavax/crypto/KeyGenerator;",
+            &[(&algorithm).into(), (&provider).into()],
+        )?
+        .l()?;
+    env.call_method(
+        &generator,
+        "init",
+        "(Ljava/security/spec/AlgorithmParameterSpec;)V",
+        &[(&spec).into()],
+    )?;
+    env.call_method(&generator, "generateKey", "()Ljavax/crypto/SecretKey;", &[])?
+        .l()
+}
+
+fn cipher<'local>(env: &mut JNIEnv<'local>) -> jni::errors::Result<JObject<'local>> {
+    let transformation = env.new_string(TRANSFORMATION)?;
+    env.call_static_method(
+        "javax/crypto/Cipher",
+        "getInstance",
+        "(Ljava/lang/String;)Ljavax/crypto/Cipher;",
+        &[(&transformation).into()],
+    )?
+    .l()
+}
+
+/// The keystore picks the nonce; it goes in front of the ciphertext
+fn seal(env: &mut JNIEnv, key: &JObject, secret: &[u8]) -> jni::errors::Result<Vec<u8>> {
+    let cipher = cipher(env)?;
+    env.call_method(
+        &cipher,
+        "init",
+        "(ILjava/security/Key;)V",
+        &[JValue::Int(ENCRYPT_MODE), key.into()],
+    )?;
+    let iv = JByteArray::from(env.call_method(&cipher, "getIV", "()[B", &[])?.l()?);
+    let input = env.byte_array_from_slice(secret)?;
+    let output = JByteArray::from(
+        env.call_method(&cipher, "doFinal", "([B)[B", &[(&input).into()])?
+            .l()?,
+    );
+    let mut sealed = env.convert_byte_array(iv)?;
+    sealed.extend(env.convert_byte_array(output)?);
+    Ok(sealed)
+}
+
+fn open(env: &mut JNIEnv, key: &JObject, sealed: &[u8]) -> jni::errors::Result<Vec<u8>> {
+    let (iv, ciphertext) = sealed.split_at(IV_LEN);
+    let iv = env.byte_array_from_slice(iv)?;
+    let spec = env.new_object(
+        "javax/crypto/spec/GCMParameterSpec",
+        "(I[B)V",
+        &[JValue::Int(TAG_BITS), (&iv).into()],
+    )?;
+    let cipher = cipher(env)?;
+    env.call_method(
+        &cipher,
+        "init",
+        "(ILjava/security/Key;Ljava/security/spec/AlgorithmParameterSpec;)V",
+        &[JValue::Int(DECRYPT_MODE), key.into(), (&spec).into()],
+    )?;
+    let input = env.byte_array_from_slice(ciphertext)?;
+    let output = JByteArray::from(
+        env.call_method(&cipher, "doFinal", "([B)[B", &[(&input).into()])?
+            .l()?,
+    );
+    env.convert_byte_array(output)
+}
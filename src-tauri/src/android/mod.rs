@@ -0,0 +1,147 @@
+// Calls into the Android framework over JNI, for what the NDK doesn't expose: runtime
+// permissions, the audio mode, the locale and the hardware-backed keystore
+// Everything goes through the app's Activity, which the Tauri runtime hands to ndk-context
+
+pub mod keystore;
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use jni::objects::{JObject, JValue};
+use jni::{JNIEnv, JavaVM};
+
+use crate::error::AppError;
+
+const RECORD_AUDIO: &str = "android.permission.RECORD_AUDIO";
+/// `PackageManager.PERMISSION_GRANTED`
+const PERMISSION_GRANTED: i32 = 0;
+/// Tags our request in `onRequestPermissionsResult`, which nothing here overrides
+const PERMISSION_REQUEST_CODE: i32 = 4_417;
+/// How long the permission prompt may take to cover the window
+const PROMPT_OPEN_TIMEOUT: Duration = Duration::from_secs(2);
+/// How long the user may take to answer it
+const PROMPT_ANSWER_TIMEOUT: Duration = Duration::from_secs(120);
+const FOCUS_POLL: Duration = Duration::from_millis(100);
+
+/// Run `f` with the Activity, on this thread attached to the VM
+/// Local references `f` makes are freed when it returns, and a Java exception it left
+/// pending is cleared so it can't break later calls
+pub fn with_activity<T>(
+    f: impl FnOnce(&mut JNIEnv, &JObject) -> jni::errors::Result<T>,
+) -> Result<T, AppError> {
+    let context = ndk_context::android_context();
+    // SAFETY: ndk-context holds the process's VM and a global reference to the Activity
+    // for the life of the app
+    let vm = unsafe { JavaVM::from_raw(context.vm().cast()) }.map_err(jni_error)?;
+    let mut env = vm.attach_current_thread().map_err(jni_error)?;
+    let activity = unsafe { JObject::from_raw(context.context().cast()) };
+    let result = env.with_local_frame(16, |env| f(env, &activity));
+    if env.exception_check().unwrap_or(false) {
+        let _ = env.exception_describe();
+        let _ = env.exception_clear();
+    }
+    result.map_err(jni_error)
+}
+
+fn jni_error(e: jni::errors::Error) -> AppError {
+    AppError::Internal(format!("Android call failed: {}", e))
+}
+
+/// Whether the app may record audio
+pub fn record_audio_granted() -> Result<bool, AppError> {
+    with_activity(|env, activity| {
+        let permission = env.new_string(RECORD_AUDIO)?;
+        let status = env
+            .call_method(
+                activity,
+                "checkSelfPermission",
+                "(Ljava/lang/String;)I",
+                &[(&permission).into()],
+            )?
+            .i()?;
+        Ok(status == PERMISSION_GRANTED)
+    })
+}
+
+/// Whether Android wants the request explained first, which is only the case after the
+/// user declined once and can still be asked again
+pub fn should_explain_record_audio() -> Result<bool, AppError> {
+    with_activity(|env, activity| {
+        let permission = env.new_string(RECORD_AUDIO)?;
+        env.call_method(
+            activity,
+            "shouldShowRequestPermissionRationale",
+            "(Ljava/lang/String;)Z",
+            &[(&permission).into()],
+        )?
+        .z()
+    })
+}
+
+/// Show the system prompt for recording audio and wait for an answer
+/// The answer is delivered to the Activity, not to us, so the prompt is followed by the
+/// window losing and then regaining focus, and the permission is read after that
+pub fn request_record_audio() -> Result<bool, AppError> {
+    with_activity(|env, activity| {
+        let permission = env.new_string(RECORD_AUDIO)?;
+        let permissions = env.new_object_array(1, "java/lang/String", &permission)?;
+        env.call_method(
+            activity,
+            "requestPermissions",
+            "([Ljava/lang/String;I)V",
+            &[(&permissions).into(), JValue::Int(PERMISSION_REQUEST_CODE)],
+        )?;
+        Ok(())
+    })?;
+    // No prompt appears once the user told Android to stop asking
+    wait_for_focus(false, PROMPT_OPEN_TIMEOUT);
+    wait_for_focus(true, PROMPT_ANSWER_TIMEOUT);
+    record_audio_granted()
+}
+
+fn wait_for_focus(focused: bool, timeout: Duration) {
+    let deadline = Instant::now() + timeout;
+    while Instant::now() < deadline {
+        let has_focus = with_activity(|env, activity| {
+            env.call_method(activity, "hasWindowFocus", "()Z", &[])?.z()
+        });
+        match has_focus {
+            Ok(has_focus) if has_focus != focused => thread::sleep(FOCUS_POLL),
+            _ => return,
+        }
+    }
+}
+
+/// `Locale.getDefault()` as a language tag, which follows the system's language settings
+pub fn default_locale() -> Result<String, AppError> {
+    with_activity(|env, _| {
+        let locale = env
+            .call_static_method(
+                "java/util/Locale",
+                "getDefault",
+                "()Ljava/util/Locale;",
+                &[],
+            )?
+            .l()?;
+        let tag = env
+            .call_method(&locale, "toLanguageTag", "()Ljava/lang/String;", &[])?
+            .l()?;
+        Ok(env.get_string(&tag.into())?.into())
+    })
+}
+
+/// `AudioManager.getMode()`: 0 normal, 1 ringing, 2 in a call, 3 in a VoIP call
+pub fn audio_mode() -> Result<i32, AppError> {
+    with_activity(|env, activity| {
+        let service = env.new_string("audio")?;
+        let manager = env
+            .call_method(
+                activity,
+                "getSystemService",
+                "(Ljava/lang/String;)Ljava/lang/Object;",
+                &[(&service).into()],
+            )?
+            .l()?;
+        env.call_method(&manager, "getMode", "()I", &[])?.i()
+    })
+}
@@ -0,0 +1,813 @@
+// Microphone and system audio capture with cpal
+// cpal streams aren't Send, so they live on their own thread for the lifetime of a capture
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc as std_mpsc, Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Instant;
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{
+    Device, FromSample, Host, SampleFormat, SizedSample, Stream, StreamConfig, StreamError,
+    SupportedStreamConfig, SupportedStreamConfigRange,
+};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::sync::mpsc;
+
+use super::meter::{LevelMeter, MeterState, EVENT_MIC_LEVEL};
+use super::mix::Mixer;
+use super::preprocess::{self, PreprocessStage, Preprocessor};
+use super::{
+    loopback, MonoResampler, Pcm16Chunker, StereoResampler, MULTICHANNEL_CHANNELS, TARGET_CHANNELS,
+    TARGET_ENCODING, TARGET_SAMPLE_RATE,
+};
+use crate::config::{EngineKind, PreprocessSettings, TranscriptionSettings, CHUNK_DURATION_MS};
+use crate::deepgram::stats::StreamMetrics;
+use crate::error::AppError;
+use crate::permissions::MicrophonePermission;
+use crate::state::{blocking, AppState};
+
+/// Event carrying captured PCM when no Deepgram stream is active
+pub const EVENT_AUDIO_CHUNK: &str = "audio-chunk";
+/// Event emitted when the capture device disappears (e.g. USB mic unplugged)
+pub const EVENT_AUDIO_DEVICE_LOST: &str = "audio-device-lost";
+
+/// Chunk duration for captures that don't follow the settings, like the wake word's
+#[cfg(feature = "wake-word")]
+const DEFAULT_CHUNK_MS: u32 = 100;
+/// Audio buffered between the audio callback and the forwarding task, whatever the
+/// chunk duration; chunks beyond it are dropped
+const CHUNK_QUEUE_MS: u32 = 6400;
+/// How far one input of a "both" capture may run ahead before it's sent unmixed (100 ms)
+const MIX_MAX_LAG: usize = 1600;
+
+/// Sample rates we report for a device when they fall inside its supported ranges
+const COMMON_SAMPLE_RATES: [u32; 9] = [
+    8_000, 16_000, 22_050, 24_000, 32_000, 44_100, 48_000, 88_200, 96_000,
+];
+
+/// An input device as shown in the device picker
+#[derive(Debug, Clone, Serialize)]
+pub struct InputDevice {
+    pub id: String,
+    pub name: String,
+    pub default: bool,
+    pub sample_rates: Vec<u32>,
+    /// Carries what the system plays rather than a microphone; capture it as "system"
+    pub loopback: bool,
+}
+
+/// What a capture records
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CaptureSource {
+    #[default]
+    Microphone,
+    /// Everything the system plays, e.g. the other side of a call
+    System,
+    /// Microphone and system audio mixed together
+    Both,
+}
+
+impl CaptureSource {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Microphone => "microphone",
+            Self::System => "system",
+            Self::Both => "both",
+        }
+    }
+
+    fn uses_microphone(self) -> bool {
+        self != Self::System
+    }
+}
+
+/// A sample format, as read from the device or sent to Deepgram
+#[derive(Debug, Clone, Serialize)]
+pub struct AudioFormat {
+    pub sample_rate: u32,
+    pub channels: u16,
+    /// cpal's name for device formats ("i16", "f32", ...), Deepgram's for the output
+    pub encoding: String,
+}
+
+/// How captured audio is converted before it is sent, for the diagnostics screen
+#[derive(Debug, Clone, Serialize)]
+pub struct AudioPipelineInfo {
+    pub device: String,
+    pub input: AudioFormat,
+    /// Conversion steps in order, e.g. "downmix", "low_pass", "resample_linear"
+    pub stages: Vec<&'static str>,
+    pub output: AudioFormat,
+    /// False when this describes the device's format without a capture running
+    pub capturing: bool,
+    pub source: CaptureSource,
+    /// The system audio device mixed in, for source "both"
+    pub mixed: Option<Box<AudioPipelineInfo>>,
+    /// Preprocessing stages that are on, with their parameters; they run after mixing
+    pub preprocessing: Vec<PreprocessStage>,
+}
+
+impl AudioPipelineInfo {
+    fn new(
+        device: String,
+        format: SampleFormat,
+        config: &StreamConfig,
+        source: CaptureSource,
+        output_channels: u16,
+    ) -> Self {
+        Self {
+            device,
+            input: AudioFormat {
+                sample_rate: config.sample_rate.0,
+                channels: config.channels,
+                encoding: format.to_string(),
+            },
+            stages: super::pipeline_stages(config.sample_rate.0, config.channels, output_channels),
+            output: AudioFormat {
+                sample_rate: TARGET_SAMPLE_RATE,
+                channels: output_channels,
+                encoding: TARGET_ENCODING.to_string(),
+            },
+            capturing: false,
+            source,
+            mixed: None,
+            preprocessing: Vec::new(),
+        }
+    }
+}
+
+/// A device opened for capture, in the format it will be read in
+struct CaptureInput {
+    device: Device,
+    name: String,
+    format: SampleFormat,
+    config: StreamConfig,
+}
+
+impl CaptureInput {
+    fn new(device: Device, supported: SupportedStreamConfig) -> Self {
+        Self {
+            name: device
+                .name()
+                .unwrap_or_else(|_| "Unknown device".to_string()),
+            device,
+            format: supported.sample_format(),
+            config: supported.into(),
+        }
+    }
+}
+
+struct ActiveCapture {
+    stop_tx: std_mpsc::Sender<()>,
+    thread: JoinHandle<()>,
+    pipeline: AudioPipelineInfo,
+    /// What it was started with, for restarting after an interruption
+    #[cfg(mobile)]
+    device_id: Option<String>,
+    #[cfg(mobile)]
+    source: CaptureSource,
+}
+
+/// Source of the latest capture and when it stopped, if it has
+struct LastCapture {
+    source: CaptureSource,
+    stopped_at: Option<Instant>,
+}
+
+/// Managed state holding the running capture, if any
+#[derive(Default)]
+pub struct CaptureState {
+    active: Mutex<Option<ActiveCapture>>,
+    last: Mutex<Option<LastCapture>>,
+}
+
+impl CaptureState {
+    /// Stop the running capture and wait for its thread to exit
+    pub fn shutdown(&self) {
+        let active = self.active.lock().ok().and_then(|mut active| active.take());
+        if let Some(ActiveCapture {
+            stop_tx, thread, ..
+        }) = active
+        {
+            let _ = stop_tx.send(());
+            let _ = thread.join();
+            super::session::deactivate();
+            if let Ok(mut last) = self.last.lock() {
+                if let Some(last) = last.as_mut() {
+                    last.stopped_at.get_or_insert_with(Instant::now);
+                }
+            }
+        }
+    }
+
+    /// Device and source of the running capture, to start it again the same way
+    #[cfg(mobile)]
+    pub fn running(&self) -> Option<(Option<String>, CaptureSource)> {
+        let active = self.active.lock().ok()?;
+        active
+            .as_ref()
+            .filter(|active| !active.thread.is_finished())
+            .map(|active| (active.device_id.clone(), active.source))
+    }
+
+    /// Source of a backend capture running at some point since `since`, which is
+    /// what a session started then was fed from
+    pub fn source_since(&self, since: Instant) -> Option<CaptureSource> {
+        let last = self.last.lock().ok()?;
+        last.as_ref()
+            .filter(|last| last.stopped_at.is_none_or(|at| at >= since))
+            .map(|last| last.source)
+    }
+}
+
+/// List audio input devices with their supported sample rates
+#[tauri::command]
+pub async fn list_input_devices() -> Result<Vec<InputDevice>, AppError> {
+    blocking(input_devices).await
+}
+
+/// Name of the system default input device, if there is one
+/// Blocks while the audio host enumerates devices
+pub fn default_input_device_name() -> Option<String> {
+    cpal::default_host()
+        .default_input_device()
+        .and_then(|device| device.name().ok())
+}
+
+/// Blocks while the audio host enumerates devices
+pub(crate) fn input_devices() -> Result<Vec<InputDevice>, AppError> {
+    let host = cpal::default_host();
+    let default_name = host.default_input_device().and_then(|d| d.name().ok());
+    let devices = host
+        .input_devices()
+        .map_err(|e| AppError::AudioDevice(format!("Failed to list input devices: {}", e)))?;
+
+    let mut listed: Vec<InputDevice> = devices
+        .filter_map(|device| {
+            // cpal has no stable device id, so the name doubles as one
+            let name = device.name().ok()?;
+            Some(InputDevice {
+                id: name.clone(),
+                default: default_name.as_deref() == Some(name.as_str()),
+                sample_rates: device
+                    .supported_input_configs()
+                    .map(sample_rates)
+                    .unwrap_or_default(),
+                loopback: loopback::is_loopback_input(&name),
+                name,
+            })
+        })
+        .collect();
+    // Where outputs can be recorded directly, they're listed too
+    listed.extend(
+        loopback::output_devices(&host)
+            .into_iter()
+            .filter_map(|device| {
+                let name = device.name().ok()?;
+                Some(InputDevice {
+                    id: name.clone(),
+                    default: false,
+                    sample_rates: device
+                        .supported_output_configs()
+                        .map(sample_rates)
+                        .unwrap_or_default(),
+                    loopback: true,
+                    name,
+                })
+            }),
+    );
+    Ok(listed)
+}
+
+/// Start capturing `source` (the microphone when None)
+/// `device_id` picks the microphone, or the loopback device for "system"; the system
+/// default is used when it's None, and "both" always mixes in the default loopback device
+#[tauri::command]
+pub async fn start_capture(
+    app: AppHandle,
+    device_id: Option<String>,
+    source: Option<CaptureSource>,
+) -> Result<(), AppError> {
+    // Opening a device can take a while, and waits for the capture thread to start
+    blocking(move || start(&app, device_id.as_deref(), source.unwrap_or_default())).await
+}
+
+pub fn start(
+    app: &AppHandle,
+    device_id: Option<&str>,
+    source: CaptureSource,
+) -> Result<(), AppError> {
+    let state = app.state::<CaptureState>();
+    let mut active = state
+        .active
+        .lock()
+        .map_err(|_| AppError::poisoned("Capture state"))?;
+
+    // A capture whose thread already exited (device lost) can be replaced
+    if let Some(existing) = active.as_ref() {
+        if !existing.thread.is_finished() {
+            return Err(AppError::AudioDevice(
+                "Audio capture is already running".to_string(),
+            ));
+        }
+    }
+
+    // Opening a denied mic succeeds but records silence, so fail loudly instead
+    if source.uses_microphone()
+        && crate::permissions::microphone_permission() == MicrophonePermission::Denied
+    {
+        return Err(AppError::MicrophonePermissionDenied);
+    }
+    if source.uses_microphone() {
+        super::session::activate()?;
+    }
+
+    let inputs = open_inputs(device_id, source)?;
+    let settings = crate::config::transcription_settings(app);
+    let preprocess = crate::config::load(app)?.preprocess;
+    let channels = output_channels(&settings, &inputs);
+    if settings.multichannel && inputs.len() > 1 {
+        tracing::info!("Mixing the two devices of a \"both\" capture; multichannel keeps one device's channels");
+    }
+    let pipeline = describe(&inputs, source, channels, &preprocess);
+    let chunk_ms = settings.chunk_duration_ms;
+    let output = OutputConfig {
+        chunk_ms,
+        channels,
+        preprocess,
+        meter_enabled: app.state::<MeterState>().enabled_flag(),
+        metrics: app.state::<AppState>().stream.metrics(),
+    };
+    let (chunk_tx, chunk_rx) = mpsc::channel(output.queue_capacity());
+    let (stop_tx, thread) = spawn_streams(app, inputs, chunk_tx, output)?;
+
+    tauri::async_runtime::spawn(forward_chunks(app.clone(), chunk_rx, channels));
+    for info in std::iter::once(&pipeline).chain(pipeline.mixed.as_deref()) {
+        tracing::info!(
+            "Capturing {} audio from: {} ({} Hz, {} ch, {}; {}; {} ms chunks)",
+            source.as_str(),
+            info.device,
+            info.input.sample_rate,
+            info.input.channels,
+            info.input.encoding,
+            info.stages.join(" → "),
+            chunk_ms
+        );
+    }
+    *active = Some(ActiveCapture {
+        stop_tx,
+        thread,
+        pipeline,
+        #[cfg(mobile)]
+        device_id: device_id.map(str::to_string),
+        #[cfg(mobile)]
+        source,
+    });
+    if let Ok(mut last) = state.last.lock() {
+        *last = Some(LastCapture {
+            source,
+            stopped_at: None,
+        });
+    }
+    Ok(())
+}
+
+/// Run `inputs` on their own thread until the returned sender signals (or a stream fails)
+/// Returns once the streams are playing
+fn spawn_streams(
+    app: &AppHandle,
+    inputs: Vec<CaptureInput>,
+    chunk_tx: mpsc::Sender<Vec<u8>>,
+    output: OutputConfig,
+) -> Result<(std_mpsc::Sender<()>, JoinHandle<()>), AppError> {
+    let (stop_tx, stop_rx) = std_mpsc::channel();
+    let (ready_tx, ready_rx) = std_mpsc::channel();
+
+    let thread_app = app.clone();
+    let thread_stop_tx = stop_tx.clone();
+    let thread = std::thread::spawn(move || {
+        let streams = match build_streams(&thread_app, &inputs, chunk_tx, thread_stop_tx, output) {
+            Ok(streams) => streams,
+            Err(e) => {
+                let _ = ready_tx.send(Err(e));
+                return;
+            }
+        };
+        for stream in &streams {
+            if let Err(e) = stream.play() {
+                let _ = ready_tx.send(Err(AppError::AudioDevice(format!(
+                    "Failed to start audio stream: {}",
+                    e
+                ))));
+                return;
+            }
+        }
+        let _ = ready_tx.send(Ok(()));
+
+        // Keep the streams alive until stopped (or an error callback signals)
+        let _ = stop_rx.recv();
+        drop(streams);
+    });
+
+    ready_rx.recv().map_err(|_| {
+        AppError::Internal("Audio capture thread exited unexpectedly".to_string())
+    })??;
+    Ok((stop_tx, thread))
+}
+
+/// A default-microphone capture whose chunks only go to its own receiver: not to a
+/// stream, not to the frontend and not through the level meter. Stops when dropped
+#[cfg(feature = "wake-word")]
+pub(crate) struct Listener {
+    stop_tx: std_mpsc::Sender<()>,
+    thread: Option<JoinHandle<()>>,
+}
+
+#[cfg(feature = "wake-word")]
+impl Drop for Listener {
+    fn drop(&mut self) {
+        let _ = self.stop_tx.send(());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Start a `Listener` feeding 16 kHz mono linear16 chunks to `chunk_tx`
+/// Runs alongside any other capture; both read the same device
+#[cfg(feature = "wake-word")]
+pub(crate) fn listen(
+    app: &AppHandle,
+    chunk_tx: mpsc::Sender<Vec<u8>>,
+) -> Result<Listener, AppError> {
+    if crate::permissions::microphone_permission() == MicrophonePermission::Denied {
+        return Err(AppError::MicrophonePermissionDenied);
+    }
+    super::session::activate()?;
+    let inputs = open_inputs(None, CaptureSource::Microphone)?;
+    let output = OutputConfig {
+        chunk_ms: DEFAULT_CHUNK_MS,
+        channels: TARGET_CHANNELS,
+        preprocess: crate::config::load(app)?.preprocess,
+        meter_enabled: Arc::default(),
+        // Drops here aren't the stream's
+        metrics: Arc::default(),
+    };
+    let (stop_tx, thread) = spawn_streams(app, inputs, chunk_tx, output)?;
+    Ok(Listener {
+        stop_tx,
+        thread: Some(thread),
+    })
+}
+
+/// Stop the running capture
+#[tauri::command]
+pub async fn stop_capture(app: AppHandle) -> Result<(), AppError> {
+    // Joining the capture thread blocks
+    blocking(move || {
+        app.state::<CaptureState>().shutdown();
+        Ok(())
+    })
+    .await
+}
+
+/// Command to describe the capture pipeline: the running capture's, or else the one
+/// `start_capture` would set up for the same `device_id` and `source`
+#[tauri::command]
+pub async fn get_audio_pipeline_info(
+    app: AppHandle,
+    device_id: Option<String>,
+    source: Option<CaptureSource>,
+) -> Result<AudioPipelineInfo, AppError> {
+    blocking(move || {
+        let state = app.state::<CaptureState>();
+        let active = state
+            .active
+            .lock()
+            .map_err(|_| AppError::poisoned("Capture state"))?;
+        if let Some(active) = active
+            .as_ref()
+            .filter(|active| !active.thread.is_finished())
+        {
+            return Ok(AudioPipelineInfo {
+                capturing: true,
+                ..active.pipeline.clone()
+            });
+        }
+        drop(active);
+        let source = source.unwrap_or_default();
+        let inputs = open_inputs(device_id.as_deref(), source)?;
+        let channels = output_channels(&crate::config::transcription_settings(&app), &inputs);
+        let preprocess = crate::config::load(&app)?.preprocess;
+        Ok(describe(&inputs, source, channels, &preprocess))
+    })
+    .await
+}
+
+/// The devices a capture of `source` reads, microphone first; "both" mixes two
+fn open_inputs(
+    device_id: Option<&str>,
+    source: CaptureSource,
+) -> Result<Vec<CaptureInput>, AppError> {
+    let host = cpal::default_host();
+    let system = |device_id| {
+        loopback::find(&host, device_id).map(|(device, config)| CaptureInput::new(device, config))
+    };
+    Ok(match source {
+        CaptureSource::Microphone => vec![microphone(&host, device_id)?],
+        CaptureSource::System => vec![system(device_id)?],
+        CaptureSource::Both => vec![microphone(&host, device_id)?, system(None)?],
+    })
+}
+
+fn microphone(host: &Host, device_id: Option<&str>) -> Result<CaptureInput, AppError> {
+    let device = find_device(host, device_id)?;
+    let supported = device
+        .default_input_config()
+        .map_err(|e| AppError::AudioDevice(format!("Failed to get input config: {}", e)))?;
+    Ok(CaptureInput::new(device, supported))
+}
+
+/// Channels a capture of `inputs` keeps: a multichannel Deepgram session's single device
+/// stays stereo when it has two or more channels; everything else is downmixed
+fn output_channels(settings: &TranscriptionSettings, inputs: &[CaptureInput]) -> u16 {
+    match inputs {
+        [input] if settings.multichannel && settings.engine == EngineKind::Deepgram => input
+            .config
+            .channels
+            .clamp(TARGET_CHANNELS, MULTICHANNEL_CHANNELS),
+        _ => TARGET_CHANNELS,
+    }
+}
+
+/// Pipeline info for `inputs`, with the second one (if any) as the mixed-in device
+fn describe(
+    inputs: &[CaptureInput],
+    source: CaptureSource,
+    channels: u16,
+    preprocess: &PreprocessSettings,
+) -> AudioPipelineInfo {
+    let mut infos = inputs.iter().map(|input| {
+        AudioPipelineInfo::new(
+            input.name.clone(),
+            input.format,
+            &input.config,
+            source,
+            channels,
+        )
+    });
+    let mut info = infos.next().expect("a capture has at least one input");
+    if let Some(mixed) = infos.next() {
+        // Mixing happens on 16 kHz mono samples, just before encoding
+        info.stages.insert(info.stages.len() - 1, "mix");
+        info.mixed = Some(Box::new(mixed));
+    }
+    // Then preprocessing, on the mixed samples
+    info.preprocessing = preprocess::stages(preprocess);
+    let encode = info.stages.len() - 1;
+    info.stages.splice(
+        encode..encode,
+        info.preprocessing.iter().map(PreprocessStage::name),
+    );
+    info
+}
+
+fn find_device(host: &Host, device_id: Option<&str>) -> Result<Device, AppError> {
+    match device_id {
+        None => host
+            .default_input_device()
+            .ok_or_else(|| AppError::AudioDevice("No default input device found".to_string())),
+        Some(id) => host
+            .input_devices()
+            .map_err(|e| AppError::AudioDevice(format!("Failed to list input devices: {}", e)))?
+            .find(|device| device.name().is_ok_and(|name| name == id))
+            .ok_or_else(|| AppError::NotFound(format!("Input device not found: {}", id))),
+    }
+}
+
+fn sample_rates(configs: impl Iterator<Item = SupportedStreamConfigRange>) -> Vec<u32> {
+    let mut rates: Vec<u32> = configs
+        .flat_map(|range| {
+            let (min, max) = (range.min_sample_rate().0, range.max_sample_rate().0);
+            COMMON_SAMPLE_RATES
+                .iter()
+                .copied()
+                .filter(move |rate| (min..=max).contains(rate))
+                .chain([min, max])
+        })
+        .collect();
+    rates.sort_unstable();
+    rates.dedup();
+    rates
+}
+
+/// How a capture chunks and meters its audio
+struct OutputConfig {
+    /// Audio per chunk, clamped to `CHUNK_DURATION_MS`
+    chunk_ms: u32,
+    /// 1, or 2 to keep a stereo device's channels apart
+    channels: u16,
+    preprocess: PreprocessSettings,
+    meter_enabled: Arc<AtomicBool>,
+    /// Counts the chunks dropped because the queue was full
+    metrics: Arc<StreamMetrics>,
+}
+
+impl OutputConfig {
+    fn chunk_samples(&self) -> usize {
+        (TARGET_SAMPLE_RATE / 1000 * self.clamped_ms()) as usize * usize::from(self.channels)
+    }
+
+    fn queue_capacity(&self) -> usize {
+        (CHUNK_QUEUE_MS / self.clamped_ms()) as usize
+    }
+
+    fn clamped_ms(&self) -> u32 {
+        self.chunk_ms
+            .clamp(*CHUNK_DURATION_MS.start(), *CHUNK_DURATION_MS.end())
+    }
+}
+
+/// Shared end of the pipeline: every input's 16 kHz mono samples are mixed (when there
+/// are two), preprocessed, metered and chunked here
+struct CaptureOutput {
+    app: AppHandle,
+    mixer: Option<Mixer>,
+    preprocessor: Option<Preprocessor>,
+    chunker: Pcm16Chunker,
+    meter: LevelMeter,
+    meter_enabled: Arc<AtomicBool>,
+    chunk_tx: mpsc::Sender<Vec<u8>>,
+    metrics: Arc<StreamMetrics>,
+}
+
+impl CaptureOutput {
+    fn push(&mut self, input: usize, samples: &[f32]) {
+        let samples = match self.mixer.as_mut() {
+            Some(mixer) => mixer.push(input, samples),
+            None => samples,
+        };
+        let samples = match self.preprocessor.as_mut() {
+            Some(preprocessor) => preprocessor.process(samples),
+            None => samples,
+        };
+        let (chunk_tx, metrics) = (&self.chunk_tx, &self.metrics);
+        // Never block the audio thread; drop chunks if the consumer falls behind
+        self.chunker.push(samples, |chunk| {
+            if let Err(mpsc::error::TrySendError::Full(_)) = chunk_tx.try_send(chunk) {
+                metrics.dropped();
+            }
+        });
+
+        if self.meter_enabled.load(Ordering::Relaxed) {
+            let app = &self.app;
+            self.meter.push(samples, |level| {
+                let _ = app.emit(EVENT_MIC_LEVEL, level);
+            });
+        } else {
+            self.meter.reset();
+        }
+    }
+}
+
+/// Build one input stream per device, all feeding the same output
+fn build_streams(
+    app: &AppHandle,
+    inputs: &[CaptureInput],
+    chunk_tx: mpsc::Sender<Vec<u8>>,
+    stop_tx: std_mpsc::Sender<()>,
+    config: OutputConfig,
+) -> Result<Vec<Stream>, AppError> {
+    let output = Arc::new(Mutex::new(CaptureOutput {
+        app: app.clone(),
+        mixer: (inputs.len() > 1).then(|| Mixer::new(MIX_MAX_LAG)),
+        preprocessor: Preprocessor::new(&config.preprocess, config.channels),
+        chunker: Pcm16Chunker::new(config.chunk_samples()),
+        // Stereo is metered as it comes, both channels' samples together
+        meter: LevelMeter::new(TARGET_SAMPLE_RATE * u32::from(config.channels)),
+        meter_enabled: config.meter_enabled,
+        chunk_tx,
+        metrics: config.metrics,
+    }));
+    inputs
+        .iter()
+        .enumerate()
+        .map(|(index, input)| {
+            build_stream(app, input, index, config.channels, &output, stop_tx.clone())
+        })
+        .collect()
+}
+
+/// Build an input stream in the device's native format, converting to 16 kHz with
+/// `channels` channels
+/// 24-bit devices arrive from cpal as i32 or f32 samples, so every format below covers them
+fn build_stream(
+    app: &AppHandle,
+    input: &CaptureInput,
+    index: usize,
+    channels: u16,
+    output: &Arc<Mutex<CaptureOutput>>,
+    stop_tx: std_mpsc::Sender<()>,
+) -> Result<Stream, AppError> {
+    let error_app = app.clone();
+    let on_error = move |error: StreamError| {
+        tracing::error!("Audio stream error: {}", error);
+        if let StreamError::DeviceNotAvailable = error {
+            let _ = error_app.emit(EVENT_AUDIO_DEVICE_LOST, error.to_string());
+            let _ = stop_tx.send(());
+        }
+    };
+
+    let output = Arc::clone(output);
+    let converter = Converter::new(&input.config, channels);
+    match input.format {
+        SampleFormat::I8 => build_typed::<i8>(input, converter, index, output, on_error),
+        SampleFormat::I16 => build_typed::<i16>(input, converter, index, output, on_error),
+        SampleFormat::I32 => build_typed::<i32>(input, converter, index, output, on_error),
+        SampleFormat::U8 => build_typed::<u8>(input, converter, index, output, on_error),
+        SampleFormat::U16 => build_typed::<u16>(input, converter, index, output, on_error),
+        SampleFormat::U32 => build_typed::<u32>(input, converter, index, output, on_error),
+        SampleFormat::F32 => build_typed::<f32>(input, converter, index, output, on_error),
+        SampleFormat::F64 => build_typed::<f64>(input, converter, index, output, on_error),
+        other => Err(AppError::Unsupported(format!(
+            "Unsupported sample format: {}",
+            other
+        ))),
+    }
+}
+
+fn build_typed<T>(
+    input: &CaptureInput,
+    mut converter: Converter,
+    index: usize,
+    output: Arc<Mutex<CaptureOutput>>,
+    on_error: impl FnMut(StreamError) + Send + 'static,
+) -> Result<Stream, AppError>
+where
+    T: SizedSample,
+    f32: FromSample<T>,
+{
+    let mut samples = Vec::new();
+
+    input
+        .device
+        .build_input_stream(
+            &input.config,
+            move |data: &[T], _| {
+                samples.clear();
+                samples.extend(data.iter().map(|s| s.to_sample::<f32>()));
+                let resampled = converter.push(&samples);
+                // Only the other input's callback contends for this, and it holds it briefly
+                if let Ok(mut output) = output.lock() {
+                    output.push(index, resampled);
+                }
+            },
+            on_error,
+            None,
+        )
+        .map_err(|e| AppError::AudioDevice(format!("Failed to open input stream: {}", e)))
+}
+
+/// Device samples to 16 kHz, downmixed or kept stereo; boxed, the filters are large
+enum Converter {
+    Mono(Box<MonoResampler>),
+    Stereo(Box<StereoResampler>),
+}
+
+impl Converter {
+    fn new(config: &StreamConfig, channels: u16) -> Self {
+        if channels == MULTICHANNEL_CHANNELS {
+            Self::Stereo(Box::new(StereoResampler::new(
+                config.sample_rate.0,
+                config.channels,
+            )))
+        } else {
+            Self::Mono(Box::new(MonoResampler::new(
+                config.sample_rate.0,
+                config.channels,
+            )))
+        }
+    }
+
+    fn push(&mut self, interleaved: &[f32]) -> &[f32] {
+        match self {
+            Self::Mono(resampler) => resampler.push(interleaved),
+            Self::Stereo(resampler) => resampler.push(interleaved),
+        }
+    }
+}
+
+/// Route captured chunks of `channels` channels to the Deepgram stream, or to the
+/// frontend, always in mono, when none is running
+async fn forward_chunks(app: AppHandle, mut chunk_rx: mpsc::Receiver<Vec<u8>>, channels: u16) {
+    while let Some(chunk) = chunk_rx.recv().await {
+        let state = app.state::<AppState>();
+        if let Err(chunk) = state.stream.forward_audio(chunk, channels).await {
+            let chunk = super::convert_channels(chunk, channels, TARGET_CHANNELS);
+            let _ = app.emit(EVENT_AUDIO_CHUNK, chunk);
+        }
+    }
+}
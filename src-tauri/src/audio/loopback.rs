@@ -0,0 +1,108 @@
+// System audio ("what's playing") capture, per platform
+// - Windows: WASAPI loopback. cpal records an output device when an input stream is
+//   built on it, so every output device can be captured
+// - macOS: there is no loopback API; a virtual device such as BlackHole, made part of the
+//   system output, shows up as an input carrying what's played
+// - Linux: PulseAudio and PipeWire expose a "Monitor of <sink>" source per output, but
+//   cpal only sees ALSA PCMs. Defining one for the monitor in ~/.asoundrc makes it an input:
+//     pcm.monitor { type pulse; device "@DEFAULT_MONITOR@"; hint.description "Monitor of default output" }
+//   This needs the ALSA PulseAudio plugin (pipewire-alsa provides the same)
+
+use cpal::traits::{DeviceTrait, HostTrait};
+use cpal::{Device, Host, SupportedStreamConfig};
+
+use crate::error::AppError;
+
+/// Lowercase name fragments of input devices that carry system output
+#[cfg(target_os = "macos")]
+const VIRTUAL_DEVICE_NAMES: &[&str] = &["blackhole"];
+#[cfg(not(target_os = "macos"))]
+const VIRTUAL_DEVICE_NAMES: &[&str] = &["monitor"];
+
+/// How to get a loopback device when there is none
+#[cfg(target_os = "macos")]
+const GUIDANCE: &str = "Capturing system audio on macOS needs a virtual audio device. Install BlackHole (https://existential.audio/blackhole), add it to a Multi-Output Device in Audio MIDI Setup, and make that the system output.";
+#[cfg(not(target_os = "macos"))]
+const GUIDANCE: &str = "No monitor device found. Add `pcm.monitor { type pulse; device \"@DEFAULT_MONITOR@\"; hint.description \"Monitor of default output\" }` to ~/.asoundrc (needs the ALSA PulseAudio plugin), then restart the app.";
+
+/// Whether an input device carries system output rather than a microphone
+pub fn is_loopback_input(name: &str) -> bool {
+    // Windows captures output devices directly instead
+    if cfg!(windows) {
+        return false;
+    }
+    let name = name.to_lowercase();
+    VIRTUAL_DEVICE_NAMES
+        .iter()
+        .any(|fragment| name.contains(fragment))
+}
+
+/// Output devices that can be recorded directly; only WASAPI can
+pub fn output_devices(host: &Host) -> Vec<Device> {
+    if !cfg!(windows) {
+        return Vec::new();
+    }
+    host.output_devices()
+        .map(|devices| devices.collect())
+        .unwrap_or_default()
+}
+
+/// The loopback device named `device_id` (the default one when None) and the
+/// config to open it with
+pub fn find(
+    host: &Host,
+    device_id: Option<&str>,
+) -> Result<(Device, SupportedStreamConfig), AppError> {
+    let device = match device_id {
+        Some(id) => devices(host)?
+            .into_iter()
+            .find(|device| device.name().is_ok_and(|name| name == id))
+            .ok_or_else(|| AppError::NotFound(format!("Loopback device not found: {}", id)))?,
+        None if cfg!(windows) => host
+            .default_output_device()
+            .ok_or_else(|| unsupported("No audio output device to capture"))?,
+        None => devices(host)?
+            .into_iter()
+            .next()
+            .ok_or_else(|| unsupported(GUIDANCE))?,
+    };
+    // WASAPI opens a loopback stream in the output's own format
+    let config = if cfg!(windows) {
+        device.default_output_config()
+    } else {
+        device.default_input_config()
+    };
+    let config = config
+        .map_err(|e| AppError::AudioDevice(format!("Failed to get loopback config: {}", e)))?;
+    Ok((device, config))
+}
+
+fn devices(host: &Host) -> Result<Vec<Device>, AppError> {
+    if cfg!(windows) {
+        return Ok(output_devices(host));
+    }
+    let devices = host
+        .input_devices()
+        .map_err(|e| AppError::AudioDevice(format!("Failed to list input devices: {}", e)))?;
+    Ok(devices
+        .filter(|device| device.name().is_ok_and(|name| is_loopback_input(&name)))
+        .collect())
+}
+
+fn unsupported(message: &str) -> AppError {
+    AppError::LoopbackUnsupported(message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn monitor_sources_are_loopback_inputs() {
+        assert!(is_loopback_input("Monitor of Built-in Audio Analog Stereo"));
+        assert!(is_loopback_input("monitor"));
+        assert!(!is_loopback_input("HDA Intel PCH, ALC3246 Analog"));
+        assert!(!is_loopback_input("default"));
+    }
+}
@@ -0,0 +1,114 @@
+// Microphone level meter for the recording pill
+// Computes RMS and peak over ~50 ms windows of the 16 kHz mono stream
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use serde::Serialize;
+use tauri::State;
+
+/// Event carrying one window's levels
+pub const EVENT_MIC_LEVEL: &str = "mic-level";
+
+/// Length of one metering window
+const WINDOW_MS: u32 = 50;
+/// Floor for reported levels (silence, or the 16-bit noise floor)
+const MIN_DB: f32 = -96.0;
+/// Peaks at or above this amplitude count as clipping
+const CLIP_THRESHOLD: f32 = 0.999;
+
+/// Payload of the `mic-level` event
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct MicLevel {
+    pub rms_db: f32,
+    pub peak_db: f32,
+    pub clipping: bool,
+}
+
+/// Managed flag shared with the capture callback
+pub struct MeterState {
+    enabled: Arc<AtomicBool>,
+}
+
+impl Default for MeterState {
+    fn default() -> Self {
+        Self {
+            enabled: Arc::new(AtomicBool::new(true)),
+        }
+    }
+}
+
+impl MeterState {
+    /// Shared handle to the enabled flag, read on every audio callback
+    pub fn enabled_flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.enabled)
+    }
+}
+
+/// Turn level events on or off (e.g. off while the pill is hidden)
+#[tauri::command]
+pub fn set_meter_enabled(state: State<'_, MeterState>, enabled: bool) {
+    state.enabled.store(enabled, Ordering::Relaxed);
+}
+
+/// Accumulates samples and reports levels once per window
+pub struct LevelMeter {
+    window: usize,
+    count: usize,
+    sum_squares: f64,
+    peak: f32,
+}
+
+impl LevelMeter {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            window: (sample_rate * WINDOW_MS / 1000).max(1) as usize,
+            count: 0,
+            sum_squares: 0.0,
+            peak: 0.0,
+        }
+    }
+
+    /// Feed samples in [-1, 1]; `emit` is called for every completed window
+    pub fn push(&mut self, samples: &[f32], mut emit: impl FnMut(MicLevel)) {
+        for &sample in samples {
+            // Treat garbage from the device as silence rather than poisoning the sums
+            let sample = if sample.is_finite() {
+                sample.abs()
+            } else {
+                0.0
+            };
+            self.sum_squares += (sample as f64) * (sample as f64);
+            self.peak = self.peak.max(sample);
+            self.count += 1;
+
+            if self.count >= self.window {
+                let rms = (self.sum_squares / self.count as f64).sqrt() as f32;
+                emit(MicLevel {
+                    rms_db: to_db(rms),
+                    peak_db: to_db(self.peak),
+                    clipping: self.peak >= CLIP_THRESHOLD,
+                });
+                self.count = 0;
+                self.sum_squares = 0.0;
+                self.peak = 0.0;
+            }
+        }
+    }
+
+    /// Drop any partial window (used when the meter is re-enabled)
+    pub fn reset(&mut self) {
+        self.count = 0;
+        self.sum_squares = 0.0;
+        self.peak = 0.0;
+    }
+}
+
+/// Convert a linear amplitude to dBFS, clamped to [MIN_DB, 0]
+fn to_db(amplitude: f32) -> f32 {
+    if amplitude > 0.0 {
+        (20.0 * amplitude.log10()).clamp(MIN_DB, 0.0)
+    } else {
+        MIN_DB
+    }
+}
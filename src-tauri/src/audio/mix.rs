@@ -0,0 +1,84 @@
+// Mixing two capture devices (microphone and system audio) into one stream
+// Each device has its own clock and callback, so samples queue per input and are summed
+// once both have them. WASAPI loopback delivers nothing while nothing is playing, so an
+// input that runs too far ahead is let through alone rather than waiting forever
+
+use std::collections::VecDeque;
+
+/// Mixed level above which the limiter starts compressing
+const KNEE: f32 = 0.8;
+
+/// Sums two 16 kHz mono inputs, keeping the result within [-1, 1]
+pub struct Mixer {
+    queues: [VecDeque<f32>; 2],
+    /// Samples an input may queue while the other has none
+    max_lag: usize,
+    out: Vec<f32>,
+}
+
+impl Mixer {
+    pub fn new(max_lag: usize) -> Self {
+        Self {
+            queues: Default::default(),
+            max_lag,
+            out: Vec::new(),
+        }
+    }
+
+    /// Queue samples from `input` (0 or 1); returns what could be mixed this call
+    pub fn push(&mut self, input: usize, samples: &[f32]) -> &[f32] {
+        self.queues[input].extend(samples);
+        self.out.clear();
+
+        let [first, second] = &mut self.queues;
+        let both = first.len().min(second.len());
+        self.out.extend(
+            first
+                .drain(..both)
+                .zip(second.drain(..both))
+                .map(|(a, b)| limit(a + b)),
+        );
+
+        // At most one queue is left non-empty; past the lag its excess is mixed with silence
+        for queue in &mut self.queues {
+            let excess = queue.len().saturating_sub(self.max_lag);
+            self.out.extend(queue.drain(..excess).map(limit));
+        }
+        &self.out
+    }
+}
+
+/// Soft limiter: unchanged up to the knee, then eased towards full scale
+pub(super) fn limit(sample: f32) -> f32 {
+    let magnitude = sample.abs();
+    if magnitude <= KNEE {
+        return sample;
+    }
+    let headroom = 1.0 - KNEE;
+    (KNEE + headroom * ((magnitude - KNEE) / headroom).tanh()).copysign(sample)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quiet_inputs_sum_and_loud_ones_stay_in_range() {
+        let mut mixer = Mixer::new(100);
+        assert!(mixer.push(0, &[0.25, -0.5, 1.0, -1.0]).is_empty());
+        let mixed = mixer.push(1, &[0.25, 0.25, 1.0, -1.0]).to_vec();
+        assert_eq!(mixed[..2], [0.5, -0.25]);
+        assert!(mixed[2] > 0.95 && mixed[2] < 1.0, "{}", mixed[2]);
+        assert!(mixed[3] < -0.95 && mixed[3] > -1.0, "{}", mixed[3]);
+        assert!(limit(1.5) < limit(2.0));
+    }
+
+    #[test]
+    fn input_running_ahead_is_released_after_the_lag() {
+        let mut mixer = Mixer::new(3);
+        assert!(mixer.push(0, &[0.1, 0.1]).is_empty());
+        // Five queued, three may wait for the silent input
+        assert_eq!(mixer.push(0, &[0.5, 0.5, 0.5]), [0.1, 0.1]);
+        assert_eq!(mixer.push(1, &[0.25]), [0.75]);
+    }
+}
@@ -0,0 +1,249 @@
+// Backend audio pipeline
+// Device samples (any format/rate/channels) → 16 kHz mono → mixed, when capturing two
+// devices → preprocessed, when any stage is on → linear16 PCM chunks
+// Multichannel sessions keep the first two channels of a single device instead, as
+// interleaved stereo
+
+pub mod capture;
+pub mod loopback;
+pub mod meter;
+pub mod mix;
+pub mod preprocess;
+pub mod recording;
+pub mod resample;
+pub mod session;
+pub mod vad;
+
+use resample::Resampler;
+
+/// Sample rate of the PCM we send to Deepgram
+pub const TARGET_SAMPLE_RATE: u32 = 16_000;
+/// Channels of the PCM we send to Deepgram
+pub const TARGET_CHANNELS: u16 = 1;
+/// Channels kept for sessions that transcribe each channel on its own
+pub const MULTICHANNEL_CHANNELS: u16 = 2;
+/// Deepgram's name for signed 16-bit little-endian PCM, the only encoding we send
+pub const TARGET_ENCODING: &str = "linear16";
+/// Bytes of one `TARGET_ENCODING` sample
+pub const BYTES_PER_SAMPLE: u32 = 2;
+/// Bytes per millisecond of one channel of what we send
+pub const BYTES_PER_MS: u32 = TARGET_SAMPLE_RATE / 1000 * BYTES_PER_SAMPLE;
+
+/// Steps from device samples to what Deepgram receives, for diagnostics
+pub fn pipeline_stages(input_rate: u32, channels: u16, output_channels: u16) -> Vec<&'static str> {
+    let mut stages = vec!["to_f32"];
+    if channels > output_channels {
+        stages.push(if output_channels == TARGET_CHANNELS {
+            "downmix"
+        } else {
+            "drop_channels"
+        });
+    }
+    if input_rate != TARGET_SAMPLE_RATE {
+        if Resampler::filters(input_rate, TARGET_SAMPLE_RATE) {
+            stages.push("low_pass");
+        }
+        stages.push("resample_linear");
+    }
+    stages.push("to_s16le");
+    stages
+}
+
+/// Downmixes interleaved device frames and resamples them to 16 kHz mono
+pub struct MonoResampler {
+    channels: usize,
+    resampler: Resampler,
+    mono: Vec<f32>,
+    resampled: Vec<f32>,
+}
+
+impl MonoResampler {
+    pub fn new(input_rate: u32, channels: u16) -> Self {
+        Self {
+            channels: channels.max(1) as usize,
+            resampler: Resampler::new(input_rate, TARGET_SAMPLE_RATE),
+            mono: Vec::new(),
+            resampled: Vec::new(),
+        }
+    }
+
+    /// Feed interleaved samples in [-1, 1]; returns this call's 16 kHz mono samples
+    pub fn push(&mut self, interleaved: &[f32]) -> &[f32] {
+        // Downmix by averaging the channels of each frame
+        self.mono.clear();
+        self.mono.extend(
+            interleaved
+                .chunks(self.channels)
+                .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32),
+        );
+
+        self.resampled.clear();
+        self.resampler.process(&self.mono, &mut self.resampled);
+        &self.resampled
+    }
+}
+
+/// Resamples the first two channels of interleaved device frames to 16 kHz each, keeping
+/// them interleaved
+pub struct StereoResampler {
+    channels: usize,
+    resamplers: [Resampler; 2],
+    split: [Vec<f32>; 2],
+    resampled: [Vec<f32>; 2],
+    interleaved: Vec<f32>,
+}
+
+impl StereoResampler {
+    /// `channels` is at least two
+    pub fn new(input_rate: u32, channels: u16) -> Self {
+        Self {
+            channels: channels.max(2) as usize,
+            resamplers: [
+                Resampler::new(input_rate, TARGET_SAMPLE_RATE),
+                Resampler::new(input_rate, TARGET_SAMPLE_RATE),
+            ],
+            split: Default::default(),
+            resampled: Default::default(),
+            interleaved: Vec::new(),
+        }
+    }
+
+    /// Feed interleaved samples in [-1, 1]; returns this call's 16 kHz stereo samples
+    pub fn push(&mut self, interleaved: &[f32]) -> &[f32] {
+        for (channel, split) in self.split.iter_mut().enumerate() {
+            split.clear();
+            split.extend(
+                interleaved
+                    .chunks_exact(self.channels)
+                    .map(|frame| frame[channel]),
+            );
+        }
+        for ((resampler, split), resampled) in self
+            .resamplers
+            .iter_mut()
+            .zip(&self.split)
+            .zip(&mut self.resampled)
+        {
+            resampled.clear();
+            resampler.process(split, resampled);
+        }
+        // Both resamplers see the same number of samples, so they return the same number
+        let [left, right] = &self.resampled;
+        self.interleaved.clear();
+        self.interleaved.extend(
+            left.iter()
+                .zip(right)
+                .flat_map(|(&left, &right)| [left, right]),
+        );
+        &self.interleaved
+    }
+}
+
+/// Convert linear16 PCM between mono and stereo: mono goes on the first channel with the
+/// second one silent, so it isn't transcribed twice, and stereo is averaged down
+pub fn convert_channels(chunk: Vec<u8>, from: u16, to: u16) -> Vec<u8> {
+    let sample = |bytes: &[u8]| i16::from_le_bytes([bytes[0], bytes[1]]);
+    match (from, to) {
+        (1, 2) => chunk
+            .chunks_exact(2)
+            .flat_map(|bytes| [bytes[0], bytes[1], 0, 0])
+            .collect(),
+        (2, 1) => chunk
+            .chunks_exact(4)
+            .flat_map(|frame| {
+                let mixed = (i32::from(sample(&frame[..2])) + i32::from(sample(&frame[2..]))) / 2;
+                (mixed as i16).to_le_bytes()
+            })
+            .collect(),
+        _ => chunk,
+    }
+}
+
+/// Packs 16 kHz samples into fixed-size linear16 chunks
+pub struct Pcm16Chunker {
+    pending: Vec<u8>,
+    chunk_bytes: usize,
+}
+
+impl Pcm16Chunker {
+    /// `chunk_samples` counts every channel's samples
+    pub fn new(chunk_samples: usize) -> Self {
+        Self {
+            pending: Vec::with_capacity(chunk_samples * 2),
+            chunk_bytes: chunk_samples * 2,
+        }
+    }
+
+    /// Feed samples in [-1, 1]; `emit` is called for every full chunk
+    pub fn push(&mut self, samples: &[f32], mut emit: impl FnMut(Vec<u8>)) {
+        for &sample in samples {
+            let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            self.pending.extend_from_slice(&value.to_le_bytes());
+            if self.pending.len() >= self.chunk_bytes {
+                emit(std::mem::replace(
+                    &mut self.pending,
+                    Vec::with_capacity(self.chunk_bytes),
+                ));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stages_match_the_device_format() {
+        assert_eq!(
+            pipeline_stages(48_000, 2, 1),
+            [
+                "to_f32",
+                "downmix",
+                "low_pass",
+                "resample_linear",
+                "to_s16le"
+            ]
+        );
+        assert_eq!(pipeline_stages(16_000, 1, 1), ["to_f32", "to_s16le"]);
+        assert_eq!(
+            pipeline_stages(8_000, 1, 1),
+            ["to_f32", "resample_linear", "to_s16le"]
+        );
+        assert_eq!(pipeline_stages(16_000, 2, 2), ["to_f32", "to_s16le"]);
+        assert_eq!(
+            pipeline_stages(16_000, 4, 2),
+            ["to_f32", "drop_channels", "to_s16le"]
+        );
+    }
+
+    #[test]
+    fn stereo_keeps_its_channels_apart() {
+        let mut resampler = StereoResampler::new(16_000, 4);
+        let frames = [[0.5, -0.5, 0.9, 0.9], [0.25, -0.25, 0.9, 0.9]].concat();
+        // The resampler holds one frame back until the next call
+        assert_eq!(resampler.push(&frames), [0.5, -0.5]);
+        assert_eq!(resampler.push(&frames), [0.25, -0.25, 0.5, -0.5]);
+
+        let mono = [100i16, -200]
+            .iter()
+            .flat_map(|s| s.to_le_bytes())
+            .collect();
+        let stereo = convert_channels(mono, 1, 2);
+        assert_eq!(
+            stereo,
+            [100i16, 0, -200, 0]
+                .iter()
+                .flat_map(|s| s.to_le_bytes())
+                .collect::<Vec<_>>()
+        );
+        let back = convert_channels(stereo, 2, 1);
+        assert_eq!(
+            back,
+            [50i16, -100]
+                .iter()
+                .flat_map(|s| s.to_le_bytes())
+                .collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,413 @@
+// Optional clean-up of the 16 kHz stream before it's chunked: a high-pass filter for fan,
+// HVAC and handling rumble, a noise gate, then gain normalization towards a target RMS,
+// each switched on separately. It runs on the audio thread after mixing, so whatever an
+// engine hears or the recording keeps has been through it, and nothing here allocates
+// once the capture is running
+// Stereo frames share the gate and the gain, so the channels keep their balance
+
+use serde::Serialize;
+use tauri::AppHandle;
+
+use super::mix::limit;
+use super::resample::Biquad;
+use super::TARGET_SAMPLE_RATE;
+use crate::config::{self, PreprocessSettings};
+use crate::error::AppError;
+use crate::state::blocking;
+
+/// Biquad sections in the high-pass filter; each adds 12 dB/octave of roll-off
+const HIGH_PASS_SECTIONS: usize = 2;
+/// Most channels a frame has, a multichannel session's two
+const MAX_CHANNELS: usize = 2;
+/// How long the gate's level detector takes to fall away after a peak
+const GATE_DETECTOR_MS: f32 = 20.0;
+/// Normalization measures the level in blocks this long, skipping the silent ones
+const NORMALIZE_BLOCK_MS: u32 = 20;
+/// How much audio its level is averaged over
+const NORMALIZE_WINDOW_MS: f32 = 400.0;
+/// How slowly normalization's gain follows the level, so it doesn't pump
+const NORMALIZE_SMOOTHING_MS: f32 = 300.0;
+/// Quieter than this (dBFS RMS) is silence, which normalization leaves the gain alone for
+const NORMALIZE_FLOOR_DB: f32 = -60.0;
+/// Output buffer reserved up front: a second of stereo, far more than one callback brings
+const PREALLOCATED_SAMPLES: usize = TARGET_SAMPLE_RATE as usize * MAX_CHANNELS;
+
+/// A stage that's switched on, with its parameters, for `get_audio_pipeline_info`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "stage", rename_all = "snake_case")]
+pub enum PreprocessStage {
+    HighPass {
+        cutoff_hz: f32,
+    },
+    NoiseGate {
+        threshold_db: f32,
+        attack_ms: u32,
+        release_ms: u32,
+    },
+    Normalize {
+        target_db: f32,
+        max_gain_db: f32,
+    },
+}
+
+impl PreprocessStage {
+    /// Name among the pipeline's `stages`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::HighPass { .. } => "high_pass",
+            Self::NoiseGate { .. } => "noise_gate",
+            Self::Normalize { .. } => "normalize",
+        }
+    }
+}
+
+/// The stages `settings` switches on, in the order they run
+pub fn stages(settings: &PreprocessSettings) -> Vec<PreprocessStage> {
+    let mut stages = Vec::new();
+    if settings.high_pass_enabled {
+        stages.push(PreprocessStage::HighPass {
+            cutoff_hz: settings.high_pass_cutoff_hz,
+        });
+    }
+    if settings.gate_enabled {
+        stages.push(PreprocessStage::NoiseGate {
+            threshold_db: settings.gate_threshold_db,
+            attack_ms: settings.gate_attack_ms,
+            release_ms: settings.gate_release_ms,
+        });
+    }
+    if settings.normalize_enabled {
+        stages.push(PreprocessStage::Normalize {
+            target_db: settings.normalize_target_db,
+            max_gain_db: settings.normalize_max_gain_db,
+        });
+    }
+    stages
+}
+
+/// Runs the enabled stages over interleaved 16 kHz frames, keeping state across calls
+pub struct Preprocessor {
+    channels: usize,
+    high_pass: Option<[[Biquad; HIGH_PASS_SECTIONS]; MAX_CHANNELS]>,
+    gate: Option<NoiseGate>,
+    normalizer: Option<Normalizer>,
+    out: Vec<f32>,
+}
+
+impl Preprocessor {
+    /// None when every stage is off, so the stream passes through untouched
+    pub fn new(settings: &PreprocessSettings, channels: u16) -> Option<Self> {
+        if !(settings.high_pass_enabled || settings.gate_enabled || settings.normalize_enabled) {
+            return None;
+        }
+        let cutoff = f64::from(settings.high_pass_cutoff_hz);
+        Some(Self {
+            channels: usize::from(channels).clamp(1, MAX_CHANNELS),
+            high_pass: settings.high_pass_enabled.then(|| {
+                std::array::from_fn(|_| {
+                    std::array::from_fn(|_| {
+                        Biquad::high_pass(f64::from(TARGET_SAMPLE_RATE), cutoff)
+                    })
+                })
+            }),
+            gate: settings.gate_enabled.then(|| NoiseGate::new(settings)),
+            normalizer: settings
+                .normalize_enabled
+                .then(|| Normalizer::new(settings)),
+            out: Vec::with_capacity(PREALLOCATED_SAMPLES),
+        })
+    }
+
+    /// Process interleaved samples in [-1, 1]; returns as many, cleaned up
+    pub fn process(&mut self, samples: &[f32]) -> &[f32] {
+        self.out.clear();
+        self.out.extend_from_slice(samples);
+        for frame in self.out.chunks_mut(self.channels) {
+            if let Some(filters) = self.high_pass.as_mut() {
+                for (sample, sections) in frame.iter_mut().zip(filters.iter_mut()) {
+                    *sample = sections
+                        .iter_mut()
+                        .fold(*sample, |sample, section| section.process(sample));
+                }
+            }
+            let gate = match self.gate.as_mut() {
+                Some(gate) => gate.gain(peak(frame)),
+                None => 1.0,
+            };
+            match self.normalizer.as_mut() {
+                Some(normalizer) => {
+                    // Measured after the gate, so a closed gate reads as silence
+                    let gain = gate * normalizer.gain(mean_square(frame) * gate * gate);
+                    for sample in frame.iter_mut() {
+                        *sample = limit(*sample * gain);
+                    }
+                }
+                None if gate < 1.0 => {
+                    for sample in frame.iter_mut() {
+                        *sample *= gate;
+                    }
+                }
+                None => {}
+            }
+        }
+        &self.out
+    }
+}
+
+/// Opens while the level is at or above the threshold, fading in over the attack time and
+/// out over the release time
+struct NoiseGate {
+    threshold: f32,
+    attack: f32,
+    release: f32,
+    /// What's left of the detected level after each sample
+    detector_decay: f32,
+    level: f32,
+    gain: f32,
+}
+
+impl NoiseGate {
+    fn new(settings: &PreprocessSettings) -> Self {
+        Self {
+            threshold: from_db(settings.gate_threshold_db),
+            attack: coefficient(settings.gate_attack_ms as f32),
+            release: coefficient(settings.gate_release_ms as f32),
+            detector_decay: 1.0 - coefficient(GATE_DETECTOR_MS),
+            level: 0.0,
+            gain: 0.0,
+        }
+    }
+
+    /// Gain for the frame whose loudest sample is `peak`
+    fn gain(&mut self, peak: f32) -> f32 {
+        self.level = peak.max(self.level * self.detector_decay);
+        let (target, rate) = if self.level >= self.threshold {
+            (1.0, self.attack)
+        } else {
+            (0.0, self.release)
+        };
+        self.gain += (target - self.gain) * rate;
+        self.gain
+    }
+}
+
+/// Slowly steers the gain so the RMS level sits at the target, within the gain limits
+/// Only blocks above the silence floor count towards the level, so a pause between
+/// sentences doesn't wind the gain up into the room noise
+struct Normalizer {
+    target: f32,
+    min_gain: f32,
+    max_gain: f32,
+    /// Block mean square that counts as silence
+    floor: f32,
+    block_frames: usize,
+    /// Frames and summed mean squares of the block being measured
+    measured: usize,
+    sum: f32,
+    /// Averaged mean square of the blocks that counted; None until the first one
+    level: Option<f32>,
+    /// Per-block step of that average
+    window: f32,
+    /// Per-sample step of the gain towards `wanted`
+    smoothing: f32,
+    wanted: f32,
+    gain: f32,
+}
+
+impl Normalizer {
+    fn new(settings: &PreprocessSettings) -> Self {
+        Self {
+            target: from_db(settings.normalize_target_db),
+            min_gain: from_db(-settings.normalize_max_gain_db),
+            max_gain: from_db(settings.normalize_max_gain_db),
+            floor: from_db(NORMALIZE_FLOOR_DB).powi(2),
+            block_frames: (TARGET_SAMPLE_RATE / 1000 * NORMALIZE_BLOCK_MS) as usize,
+            measured: 0,
+            sum: 0.0,
+            level: None,
+            window: 1.0 - (-(NORMALIZE_BLOCK_MS as f32) / NORMALIZE_WINDOW_MS).exp(),
+            smoothing: coefficient(NORMALIZE_SMOOTHING_MS),
+            wanted: 1.0,
+            gain: 1.0,
+        }
+    }
+
+    /// Gain for a frame whose mean square is `mean_square`
+    fn gain(&mut self, mean_square: f32) -> f32 {
+        self.sum += mean_square;
+        self.measured += 1;
+        if self.measured == self.block_frames {
+            let block = self.sum / self.measured as f32;
+            (self.sum, self.measured) = (0.0, 0);
+            if block > self.floor {
+                let level = match self.level {
+                    Some(level) => level + (block - level) * self.window,
+                    None => block,
+                };
+                self.level = Some(level);
+                self.wanted = (self.target / level.sqrt()).clamp(self.min_gain, self.max_gain);
+            }
+        }
+        self.gain += (self.wanted - self.gain) * self.smoothing;
+        self.gain
+    }
+}
+
+/// Per-sample step of a one-pole smoother reaching ~63% of the way in `ms`
+fn coefficient(ms: f32) -> f32 {
+    1.0 - (-1000.0 / (ms.max(0.001) * TARGET_SAMPLE_RATE as f32)).exp()
+}
+
+fn from_db(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+fn peak(frame: &[f32]) -> f32 {
+    frame
+        .iter()
+        .fold(0.0, |peak: f32, sample| peak.max(sample.abs()))
+}
+
+fn mean_square(frame: &[f32]) -> f32 {
+    frame.iter().map(|sample| sample * sample).sum::<f32>() / frame.len() as f32
+}
+
+#[tauri::command]
+pub async fn get_preprocess_settings(app: AppHandle) -> Result<PreprocessSettings, AppError> {
+    blocking(move || Ok(config::load(&app)?.preprocess)).await
+}
+
+/// Command to change the preprocessing stages; they apply from the next capture
+#[tauri::command]
+pub async fn set_preprocess_settings(
+    app: AppHandle,
+    settings: PreprocessSettings,
+) -> Result<PreprocessSettings, AppError> {
+    settings.validate()?;
+    blocking(move || {
+        config::update(&app, |config| {
+            config.preprocess = PreprocessSettings {
+                extra: std::mem::take(&mut config.preprocess.extra),
+                ..settings
+            };
+            Ok(config.preprocess.clone())
+        })
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use super::*;
+
+    const RATE: f32 = TARGET_SAMPLE_RATE as f32;
+
+    fn tone(frequency: f32, amplitude: f32, seconds: f32) -> Vec<f32> {
+        (0..(RATE * seconds) as usize)
+            .map(|i| (2.0 * PI * frequency * i as f32 / RATE).sin() * amplitude)
+            .collect()
+    }
+
+    /// Process in 100 ms chunks, as the capture delivers them, checking that the output
+    /// buffer is never reallocated
+    fn run(preprocessor: &mut Preprocessor, input: &[f32]) -> Vec<f32> {
+        let buffer = (preprocessor.out.as_ptr(), preprocessor.out.capacity());
+        let mut out = Vec::new();
+        for chunk in input.chunks(1600) {
+            out.extend_from_slice(preprocessor.process(chunk));
+            assert_eq!(
+                (preprocessor.out.as_ptr(), preprocessor.out.capacity()),
+                buffer
+            );
+        }
+        out
+    }
+
+    fn db(samples: &[f32]) -> f32 {
+        20.0 * mean_square(samples).sqrt().log10()
+    }
+
+    fn mean(samples: &[f32]) -> f32 {
+        samples.iter().sum::<f32>() / samples.len() as f32
+    }
+
+    #[test]
+    fn high_pass_removes_dc_offset_and_hum_but_keeps_speech() {
+        let settings = PreprocessSettings {
+            high_pass_enabled: true,
+            ..PreprocessSettings::default()
+        };
+        assert_eq!(
+            stages(&settings),
+            [PreprocessStage::HighPass { cutoff_hz: 80.0 }]
+        );
+        assert!(Preprocessor::new(&PreprocessSettings::default(), 1).is_none());
+
+        let hum = tone(50.0, 0.2, 2.0);
+        let input: Vec<f32> = hum.iter().map(|sample| sample + 0.25).collect();
+        let out = run(&mut Preprocessor::new(&settings, 1).unwrap(), &input);
+        // Skip the filter settling in
+        let settled = &out[RATE as usize..];
+        assert!(mean(settled).abs() < 0.001, "DC left: {}", mean(settled));
+        let cut = db(&hum[RATE as usize..]) - db(settled);
+        assert!(cut > 15.0, "50 Hz hum only {} dB lower", cut);
+
+        let speech = tone(1000.0, 0.2, 1.0);
+        let out = run(&mut Preprocessor::new(&settings, 1).unwrap(), &speech);
+        let change = db(&out[1600..]) - db(&speech[1600..]);
+        assert!(change.abs() < 0.1, "1 kHz changed by {} dB", change);
+    }
+
+    #[test]
+    fn gate_silences_background_noise_and_opens_for_speech() {
+        let settings = PreprocessSettings {
+            gate_enabled: true,
+            ..PreprocessSettings::default()
+        };
+        let mut preprocessor = Preprocessor::new(&settings, 1).unwrap();
+        // A -60 dBFS fan under the -50 dB threshold
+        let fan = tone(120.0, 0.001, 1.0);
+        let out = run(&mut preprocessor, &fan);
+        assert!(
+            peak(&out[1600..]) < 1e-6,
+            "fan let through: {}",
+            peak(&out[1600..])
+        );
+
+        let speech = tone(300.0, 0.3, 0.5);
+        let out = run(&mut preprocessor, &speech);
+        // Open within five attack times (25 ms)
+        let open = &out[400..];
+        assert!(db(open) - db(&speech[400..]) > -0.1);
+
+        let out = run(&mut preprocessor, &fan);
+        // Closed again within the second
+        assert!(peak(&out[14_400..]) < 1e-5);
+    }
+
+    #[test]
+    fn normalization_lifts_quiet_speech_to_the_target_but_not_silence() {
+        let settings = PreprocessSettings {
+            normalize_enabled: true,
+            ..PreprocessSettings::default()
+        };
+        let target = settings.normalize_target_db;
+        // 0.025 peak is -35 dBFS RMS
+        let quiet = tone(250.0, 0.025, 3.0);
+        let out = run(&mut Preprocessor::new(&settings, 1).unwrap(), &quiet);
+        let level = db(&out[2 * RATE as usize..]);
+        assert!((level - target).abs() < 1.0, "quiet speech at {} dB", level);
+
+        let loud = tone(250.0, 0.7, 3.0);
+        let out = run(&mut Preprocessor::new(&settings, 1).unwrap(), &loud);
+        let level = db(&out[2 * RATE as usize..]);
+        assert!((level - target).abs() < 1.0, "loud speech at {} dB", level);
+
+        // Room tone under the floor isn't raised into hiss
+        let hiss = tone(4000.0, 0.0005, 2.0);
+        let out = run(&mut Preprocessor::new(&settings, 1).unwrap(), &hiss);
+        assert!(db(&out[RATE as usize..]) < -65.0);
+    }
+}
@@ -0,0 +1,135 @@
+// Session audio saved as WAV next to the transcript history
+// Writing happens on a dedicated thread so a slow disk never holds up the live stream
+
+use std::path::{Path, PathBuf};
+use std::sync::mpsc as std_mpsc;
+use std::thread::JoinHandle;
+
+use tauri::{AppHandle, Manager};
+
+use super::TARGET_SAMPLE_RATE;
+use crate::error::AppError;
+use crate::storage::Storage;
+
+/// Directory inside the app data dir holding session recordings
+const RECORDINGS_DIR: &str = "recordings";
+/// Chunks queued for the writer thread before new audio is dropped (~25 s of 100 ms chunks)
+const WRITE_QUEUE_CAPACITY: usize = 256;
+/// The header is patched this often, so a crash leaves at most this much unplayable
+const FLUSH_INTERVAL_SAMPLES: u64 = TARGET_SAMPLE_RATE as u64;
+
+/// Directory for recordings, created if needed
+pub fn recordings_dir(app: &AppHandle) -> Result<PathBuf, AppError> {
+    let dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| AppError::Config(format!("Could not resolve app data directory: {}", e)))?
+        .join(RECORDINGS_DIR);
+    std::fs::create_dir_all(&dir)
+        .map_err(|e| AppError::Io(format!("Failed to create {}: {}", dir.display(), e)))?;
+    Ok(dir)
+}
+
+/// Where a running session's audio is recorded until the session is saved
+pub fn in_progress_path(dir: &Path, started_at: i64) -> PathBuf {
+    dir.join(format!("in-progress-{}.wav", started_at))
+}
+
+/// Final location of a saved session's audio
+pub fn session_audio_path(dir: &Path, session_id: i64) -> PathBuf {
+    dir.join(format!("session-{}.wav", session_id))
+}
+
+/// Rename a finished recording after its session and link it from the history entry
+pub fn attach(storage: &Storage, session_id: i64, path: PathBuf) {
+    let final_path = match path.parent() {
+        Some(dir) => session_audio_path(dir, session_id),
+        None => path.clone(),
+    };
+    let path = match std::fs::rename(&path, &final_path) {
+        Ok(()) => final_path,
+        Err(e) => {
+            tracing::warn!("Failed to rename {}: {}", path.display(), e);
+            path
+        }
+    };
+    if let Err(e) = storage.set_audio_path(session_id, &path) {
+        tracing::error!("{}", e);
+    }
+}
+
+/// Streams 16 kHz linear16 chunks into a WAV file on a background thread
+pub struct SessionRecorder {
+    chunk_tx: std_mpsc::SyncSender<Vec<u8>>,
+    thread: JoinHandle<bool>,
+    path: PathBuf,
+    dropped: bool,
+}
+
+impl SessionRecorder {
+    /// Start recording `channels`-channel audio to `path`; the file is created on the
+    /// writer thread
+    pub fn start(path: PathBuf, channels: u16) -> Self {
+        let (chunk_tx, chunk_rx) = std_mpsc::sync_channel(WRITE_QUEUE_CAPACITY);
+        let thread_path = path.clone();
+        let thread =
+            std::thread::spawn(move || match write_wav(&thread_path, channels, chunk_rx) {
+                Ok(()) => true,
+                Err(e) => {
+                    tracing::error!("Failed to record {}: {}", thread_path.display(), e);
+                    false
+                }
+            });
+        Self {
+            chunk_tx,
+            thread,
+            path,
+            dropped: false,
+        }
+    }
+
+    /// Queue a chunk without blocking; it is dropped if the writer has fallen behind
+    pub fn write(&mut self, chunk: Vec<u8>) {
+        if self.chunk_tx.try_send(chunk).is_err() && !self.dropped {
+            self.dropped = true;
+            tracing::warn!("Recording writer fell behind; the saved audio will have gaps");
+        }
+    }
+
+    /// Finalize the file and return its path, or None if nothing usable was written
+    /// Blocks until the writer thread has drained its queue
+    pub fn finish(self) -> Option<PathBuf> {
+        drop(self.chunk_tx);
+        match self.thread.join() {
+            Ok(true) => Some(self.path),
+            _ => None,
+        }
+    }
+}
+
+fn write_wav(
+    path: &Path,
+    channels: u16,
+    chunk_rx: std_mpsc::Receiver<Vec<u8>>,
+) -> hound::Result<()> {
+    let spec = hound::WavSpec {
+        channels,
+        sample_rate: TARGET_SAMPLE_RATE,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = hound::WavWriter::create(path, spec)?;
+    let mut unflushed = 0;
+    while let Ok(chunk) = chunk_rx.recv() {
+        for bytes in chunk.chunks_exact(2) {
+            writer.write_sample(i16::from_le_bytes([bytes[0], bytes[1]]))?;
+        }
+        unflushed += chunk.len() as u64 / 2;
+        // flush() rewrites the header sizes, keeping the file playable up to here
+        if unflushed >= FLUSH_INTERVAL_SAMPLES {
+            writer.flush()?;
+            unflushed = 0;
+        }
+    }
+    writer.finalize()
+}
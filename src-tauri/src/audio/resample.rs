@@ -0,0 +1,250 @@
+// Sample rate conversion for the capture pipeline
+// Linear interpolation, preceded by a low-pass filter when downsampling so content above
+// the new Nyquist frequency doesn't fold back into the speech band
+
+use std::f64::consts::PI;
+
+/// Low-pass cutoff as a fraction of the output sample rate, just under its Nyquist (0.5)
+const CUTOFF_RATIO: f64 = 0.4;
+/// Cascaded biquad sections; each adds 12 dB/octave of roll-off
+const LOW_PASS_SECTIONS: usize = 2;
+
+/// Converts mono samples from one rate to another, keeping state across calls
+pub struct Resampler {
+    low_pass: Option<LowPass>,
+    linear: LinearResampler,
+    filtered: Vec<f32>,
+}
+
+impl Resampler {
+    pub fn new(input_rate: u32, output_rate: u32) -> Self {
+        Self {
+            low_pass: Self::filters(input_rate, output_rate)
+                .then(|| LowPass::new(input_rate, output_rate as f64 * CUTOFF_RATIO)),
+            linear: LinearResampler::new(input_rate, output_rate),
+            filtered: Vec::new(),
+        }
+    }
+
+    /// Whether converting between these rates runs the anti-aliasing filter first
+    pub fn filters(input_rate: u32, output_rate: u32) -> bool {
+        input_rate > output_rate
+    }
+
+    /// Resample `input`, appending to `out`
+    pub fn process(&mut self, input: &[f32], out: &mut Vec<f32>) {
+        match self.low_pass.as_mut() {
+            Some(low_pass) => {
+                self.filtered.clear();
+                self.filtered
+                    .extend(input.iter().map(|&sample| low_pass.process(sample)));
+                self.linear.process(&self.filtered, out);
+            }
+            None => self.linear.process(input, out),
+        }
+    }
+}
+
+/// Streaming linear-interpolation resampler
+/// Keeps its position across calls so chunk boundaries don't click
+struct LinearResampler {
+    step: f64,
+    pos: f64,
+    prev: f32,
+}
+
+impl LinearResampler {
+    fn new(input_rate: u32, output_rate: u32) -> Self {
+        Self {
+            step: input_rate as f64 / output_rate as f64,
+            pos: 1.0,
+            prev: 0.0,
+        }
+    }
+
+    fn process(&mut self, input: &[f32], out: &mut Vec<f32>) {
+        let Some(&last) = input.last() else {
+            return;
+        };
+
+        // Index 0 is the last sample of the previous call, index k is input[k - 1]
+        let len = input.len() as f64;
+        while self.pos < len {
+            let index = self.pos as usize;
+            let frac = (self.pos - index as f64) as f32;
+            let a = if index == 0 {
+                self.prev
+            } else {
+                input[index - 1]
+            };
+            let b = input[index];
+            out.push(a + (b - a) * frac);
+            self.pos += self.step;
+        }
+        self.pos -= len;
+        self.prev = last;
+    }
+}
+
+/// Butterworth-Q biquad sections in series
+struct LowPass {
+    sections: [Biquad; LOW_PASS_SECTIONS],
+}
+
+impl LowPass {
+    fn new(sample_rate: u32, cutoff: f64) -> Self {
+        Self {
+            sections: std::array::from_fn(|_| Biquad::low_pass(sample_rate as f64, cutoff)),
+        }
+    }
+
+    fn process(&mut self, sample: f32) -> f32 {
+        self.sections
+            .iter_mut()
+            .fold(sample, |sample, section| section.process(sample))
+    }
+}
+
+/// One second-order section (RBJ cookbook coefficients, normalized by a0)
+pub(super) struct Biquad {
+    b0: f64,
+    b1: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64,
+    x1: f64,
+    x2: f64,
+    y1: f64,
+    y2: f64,
+}
+
+impl Biquad {
+    fn low_pass(sample_rate: f64, cutoff: f64) -> Self {
+        let (cos, alpha) = Self::angle(sample_rate, cutoff);
+        let a0 = 1.0 + alpha;
+        Self::new(
+            [(1.0 - cos) / 2.0, 1.0 - cos, (1.0 - cos) / 2.0],
+            [-2.0 * cos, 1.0 - alpha],
+            a0,
+        )
+    }
+
+    pub(super) fn high_pass(sample_rate: f64, cutoff: f64) -> Self {
+        let (cos, alpha) = Self::angle(sample_rate, cutoff);
+        let a0 = 1.0 + alpha;
+        Self::new(
+            [(1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0],
+            [-2.0 * cos, 1.0 - alpha],
+            a0,
+        )
+    }
+
+    /// cos(w0) and alpha for a Butterworth Q
+    fn angle(sample_rate: f64, cutoff: f64) -> (f64, f64) {
+        let w0 = 2.0 * PI * cutoff / sample_rate;
+        (w0.cos(), w0.sin() / (2.0 * std::f64::consts::FRAC_1_SQRT_2))
+    }
+
+    fn new([b0, b1, b2]: [f64; 3], [a1, a2]: [f64; 2], a0: f64) -> Self {
+        Self {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+            x1: 0.0,
+            x2: 0.0,
+            y1: 0.0,
+            y2: 0.0,
+        }
+    }
+
+    pub(super) fn process(&mut self, sample: f32) -> f32 {
+        let x = sample as f64;
+        let y = self.b0 * x + self.b1 * self.x1 + self.b2 * self.x2
+            - self.a1 * self.y1
+            - self.a2 * self.y2;
+        (self.x2, self.x1) = (self.x1, x);
+        (self.y2, self.y1) = (self.y1, y);
+        y as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OUTPUT_RATE: u32 = 16_000;
+
+    fn tone(frequency: f64, sample_rate: u32, seconds: f64) -> Vec<f32> {
+        let count = (sample_rate as f64 * seconds) as usize;
+        (0..count)
+            .map(|i| (2.0 * PI * frequency * i as f64 / sample_rate as f64).sin() as f32 * 0.5)
+            .collect()
+    }
+
+    /// Resample in uneven chunks, as the audio callback delivers them
+    fn resample(input: &[f32], input_rate: u32) -> Vec<f32> {
+        let mut resampler = Resampler::new(input_rate, OUTPUT_RATE);
+        let mut out = Vec::new();
+        for chunk in input.chunks(441) {
+            resampler.process(chunk, &mut out);
+        }
+        out
+    }
+
+    /// Frequency from rising zero crossings, skipping the filter's settling time
+    fn frequency(samples: &[f32], sample_rate: u32) -> f64 {
+        let settled = &samples[sample_rate as usize / 10..];
+        let rising = settled
+            .windows(2)
+            .filter(|pair| pair[0] < 0.0 && pair[1] >= 0.0)
+            .count();
+        rising as f64 * sample_rate as f64 / settled.len() as f64
+    }
+
+    fn peak(samples: &[f32], sample_rate: u32) -> f32 {
+        samples[sample_rate as usize / 10..]
+            .iter()
+            .fold(0.0, |peak: f32, sample| peak.max(sample.abs()))
+    }
+
+    #[test]
+    fn tone_keeps_its_frequency_at_common_device_rates() {
+        for input_rate in [44_100, 48_000, 8_000] {
+            let out = resample(&tone(1000.0, input_rate, 1.0), input_rate);
+            let expected_len = OUTPUT_RATE as f64;
+            assert!(
+                (out.len() as f64 - expected_len).abs() <= 2.0,
+                "{} Hz produced {} samples",
+                input_rate,
+                out.len()
+            );
+            let measured = frequency(&out, OUTPUT_RATE);
+            assert!(
+                (measured - 1000.0).abs() < 10.0,
+                "{} Hz input measured {} Hz",
+                input_rate,
+                measured
+            );
+            assert!(
+                peak(&out, OUTPUT_RATE) > 0.45,
+                "{} Hz input lost level",
+                input_rate
+            );
+        }
+    }
+
+    #[test]
+    fn content_above_the_output_nyquist_is_filtered_not_folded() {
+        // Without the filter, 12 kHz at 48 kHz folds to 4 kHz at full level
+        let out = resample(&tone(12_000.0, 48_000, 0.5), 48_000);
+        assert!(
+            peak(&out, OUTPUT_RATE) < 0.1,
+            "peak {}",
+            peak(&out, OUTPUT_RATE)
+        );
+        assert!(Resampler::new(48_000, OUTPUT_RATE).low_pass.is_some());
+        assert!(Resampler::new(8_000, OUTPUT_RATE).low_pass.is_none());
+    }
+}
@@ -0,0 +1,267 @@
+// The platform audio session on mobile, and interruptions like an incoming call
+// iOS only lets an app record once its AVAudioSession is set up for it, and either OS
+// takes the microphone away for a call. The capture is stopped and the session paused
+// (with the usual `session-state` events) when that happens, and both pick up again
+// once the call ends. Desktop has neither, so everything here is a no-op there
+
+#[cfg(mobile)]
+use std::sync::Mutex;
+
+use tauri::AppHandle;
+#[cfg(mobile)]
+use tauri::Manager;
+
+#[cfg(mobile)]
+use super::capture::{CaptureSource, CaptureState};
+#[cfg(mobile)]
+use crate::engine::manager::SessionManager;
+use crate::error::AppError;
+#[cfg(mobile)]
+use crate::state::AppState;
+
+/// What the current interruption stopped, to start again when it ends
+#[cfg(mobile)]
+struct Interrupted {
+    capture: Option<(Option<String>, CaptureSource)>,
+    paused: bool,
+}
+
+/// Held while an interruption is handled, so its end can't overtake its start
+#[cfg(mobile)]
+static INTERRUPTED: Mutex<Option<Interrupted>> = Mutex::new(None);
+
+/// Get the audio session ready to record; called before the microphone is opened
+pub fn activate() -> Result<(), AppError> {
+    platform::activate()
+}
+
+/// Give the audio session back once nothing records
+pub fn deactivate() {
+    platform::deactivate();
+}
+
+/// Start following interruptions; a platform that can't tell us is logged, not fatal
+pub fn init(app: &AppHandle) {
+    platform::init(app);
+}
+
+/// The microphone was taken away; stop the capture and pause the session
+#[cfg(mobile)]
+fn interruption_began(app: &AppHandle) {
+    let Ok(mut interrupted) = INTERRUPTED.lock() else {
+        return;
+    };
+    if interrupted.is_some() {
+        return;
+    }
+    let capture_state = app.state::<CaptureState>();
+    let capture = capture_state.running();
+    if capture.is_some() {
+        capture_state.shutdown();
+    }
+    let pause = tauri::async_runtime::block_on(app.state::<AppState>().stream.running_pause());
+    let paused = pause.is_ok_and(|pause| pause.pause());
+    if paused {
+        app.state::<SessionManager>().set_paused(app, true);
+    }
+    if capture.is_some() || paused {
+        tracing::info!("Audio interrupted; stopped the capture and paused the session");
+    }
+    *interrupted = Some(Interrupted { capture, paused });
+}
+
+/// The interruption is over; `should_resume` is false when the OS suggests staying quiet,
+/// in which case the capture comes back but the session stays paused for the user
+#[cfg(mobile)]
+fn interruption_ended(app: &AppHandle, should_resume: bool) {
+    let Ok(mut interrupted) = INTERRUPTED.lock() else {
+        return;
+    };
+    let Some(Interrupted { capture, paused }) = interrupted.take() else {
+        return;
+    };
+    if let Some((device_id, source)) = capture {
+        if let Err(e) = super::capture::start(app, device_id.as_deref(), source) {
+            // Without audio there's nothing to resume; the session stays paused
+            tracing::warn!(
+                "Failed to restart audio capture after an interruption: {}",
+                e
+            );
+            return;
+        }
+    }
+    if paused && should_resume {
+        let pause = tauri::async_runtime::block_on(app.state::<AppState>().stream.running_pause());
+        if pause.is_ok_and(|pause| pause.resume()) {
+            app.state::<SessionManager>().set_paused(app, false);
+            tracing::info!("Audio interruption ended; resumed the session");
+        }
+    }
+}
+
+#[cfg(target_os = "ios")]
+mod platform {
+    use std::ptr::NonNull;
+
+    use block2::RcBlock;
+    use objc2_avf_audio::{
+        AVAudioSession, AVAudioSessionCategoryPlayAndRecord,
+        AVAudioSessionInterruptionNotification, AVAudioSessionInterruptionOptionKey,
+        AVAudioSessionInterruptionOptions, AVAudioSessionInterruptionType,
+        AVAudioSessionInterruptionTypeKey,
+    };
+    use objc2_foundation::{NSNotification, NSNotificationCenter, NSNumber, NSString};
+    use tauri::AppHandle;
+
+    use crate::error::AppError;
+
+    /// Recording needs the PlayAndRecord category, which also keeps other apps' audio
+    /// from being cut off by ours
+    pub fn activate() -> Result<(), AppError> {
+        let Some(category) = (unsafe { AVAudioSessionCategoryPlayAndRecord }) else {
+            return Ok(());
+        };
+        // SAFETY: the shared session is safe to configure from any thread
+        unsafe {
+            let session = AVAudioSession::sharedInstance();
+            session
+                .setCategory_error(category)
+                .and_then(|()| session.setActive_error(true))
+        }
+        .map_err(|e| {
+            AppError::AudioDevice(format!(
+                "Failed to activate the audio session: {}",
+                e.localizedDescription()
+            ))
+        })
+    }
+
+    pub fn deactivate() {
+        if let Err(e) = unsafe { AVAudioSession::sharedInstance().setActive_error(false) } {
+            tracing::debug!(
+                "Failed to deactivate the audio session: {}",
+                e.localizedDescription()
+            );
+        }
+    }
+
+    pub fn init(app: &AppHandle) {
+        let Some(name) = (unsafe { AVAudioSessionInterruptionNotification }) else {
+            tracing::warn!("Audio interruptions can't be followed on this iOS version");
+            return;
+        };
+        let app = app.clone();
+        let on_interruption = RcBlock::new(move |notification: NonNull<NSNotification>| {
+            // SAFETY: the notification outlives the block call
+            interrupted(&app, unsafe { notification.as_ref() });
+        });
+        // SAFETY: no object filter and no queue, so the block runs on the posting thread;
+        // it only captures an app handle, which is Send
+        let observer = unsafe {
+            NSNotificationCenter::defaultCenter().addObserverForName_object_queue_usingBlock(
+                Some(name),
+                None,
+                None,
+                &on_interruption,
+            )
+        };
+        // Observing for the life of the app
+        std::mem::forget(observer);
+    }
+
+    fn interrupted(app: &AppHandle, notification: &NSNotification) {
+        let Some(info) = notification.userInfo() else {
+            return;
+        };
+        let number = |key: Option<&NSString>| {
+            key.and_then(|key| info.objectForKey(key))
+                .and_then(|value| value.downcast::<NSNumber>().ok())
+                .map(|value| value.unsignedIntegerValue())
+        };
+        match number(unsafe { AVAudioSessionInterruptionTypeKey }) {
+            Some(kind) if kind == AVAudioSessionInterruptionType::Began.0 => {
+                super::interruption_began(app)
+            }
+            Some(_) => {
+                let options = number(unsafe { AVAudioSessionInterruptionOptionKey }).unwrap_or(0);
+                super::interruption_ended(
+                    app,
+                    AVAudioSessionInterruptionOptions(options)
+                        .contains(AVAudioSessionInterruptionOptions::ShouldResume),
+                );
+            }
+            None => {}
+        }
+    }
+}
+
+#[cfg(target_os = "android")]
+mod platform {
+    use std::thread;
+    use std::time::Duration;
+
+    use tauri::AppHandle;
+
+    use crate::android;
+    use crate::error::AppError;
+
+    /// `AudioManager` modes of a ringing phone, a call and a VoIP call
+    const CALL_MODES: [i32; 3] = [1, 2, 3];
+    const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+    /// AAudio needs no session set up before recording
+    pub fn activate() -> Result<(), AppError> {
+        Ok(())
+    }
+
+    pub fn deactivate() {}
+
+    /// Audio focus changes are only delivered to a Java listener, which a Rust app can't
+    /// implement without its own Java class, so the audio mode is polled instead
+    pub fn init(app: &AppHandle) {
+        let app = app.clone();
+        let spawned = thread::Builder::new()
+            .name("audio-mode".to_string())
+            .spawn(move || {
+                let mut in_call = false;
+                loop {
+                    thread::sleep(POLL_INTERVAL);
+                    let mode = match android::audio_mode() {
+                        Ok(mode) => mode,
+                        Err(e) => {
+                            tracing::warn!("Calls won't pause sessions: {}", e);
+                            return;
+                        }
+                    };
+                    let call = CALL_MODES.contains(&mode);
+                    if call == in_call {
+                        continue;
+                    }
+                    in_call = call;
+                    if call {
+                        super::interruption_began(&app);
+                    } else {
+                        super::interruption_ended(&app, true);
+                    }
+                }
+            });
+        if let Err(e) = spawned {
+            tracing::warn!("Calls won't pause sessions: {}", e);
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "ios", target_os = "android")))]
+mod platform {
+    use tauri::AppHandle;
+
+    use crate::error::AppError;
+
+    pub fn activate() -> Result<(), AppError> {
+        Ok(())
+    }
+
+    pub fn deactivate() {}
+
+    pub fn init(_app: &AppHandle) {}
+}
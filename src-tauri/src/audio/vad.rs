@@ -0,0 +1,182 @@
+// Energy-based voice activity detection on the 16 kHz linear16 stream
+// Gates audio to Deepgram so long silences aren't streamed (and billed)
+
+use std::collections::VecDeque;
+
+use serde::Serialize;
+
+/// Event emitted when the stream pauses for silence or resumes on speech
+pub const EVENT_VAD_STATE: &str = "vad-state";
+
+/// Audio kept while paused and sent ahead of resumed speech, so its onset isn't cut off
+const PRE_ROLL_MS: u64 = 300;
+
+/// Payload of the `vad-state` event
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VadState {
+    Speaking,
+    Silent,
+}
+
+/// Decides per chunk whether audio is sent; pauses after `silence_timeout_ms` without speech
+pub struct VoiceDetector {
+    /// Chunk RMS (linear, in [0, 1]) at or above which a chunk counts as speech
+    threshold: f32,
+    silence_timeout_ms: u64,
+    silent_ms: u64,
+    paused: bool,
+    pre_roll: VecDeque<Vec<u8>>,
+    pre_roll_bytes: usize,
+    /// Bytes per millisecond of the audio, whatever its channels
+    bytes_per_ms: u64,
+}
+
+impl VoiceDetector {
+    /// Stereo chunks are judged on both channels' samples together
+    pub fn new(threshold_db: f32, silence_timeout_secs: u32, channels: u16) -> Self {
+        Self {
+            threshold: 10f32.powf(threshold_db / 20.0),
+            silence_timeout_ms: u64::from(silence_timeout_secs.max(1)) * 1000,
+            silent_ms: 0,
+            paused: false,
+            pre_roll: VecDeque::new(),
+            pre_roll_bytes: 0,
+            bytes_per_ms: u64::from(super::BYTES_PER_MS) * u64::from(channels.max(1)),
+        }
+    }
+
+    /// Whether audio is currently held back
+    #[cfg(test)]
+    fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Feed one chunk; `send` is called for every chunk that should go to Deepgram,
+    /// including the pre-roll on resume. Returns the new state when it changes
+    pub fn push(&mut self, chunk: Vec<u8>, mut send: impl FnMut(Vec<u8>)) -> Option<VadState> {
+        let speech = rms(&chunk) >= self.threshold;
+        if !self.paused {
+            let duration_ms = chunk.len() as u64 / self.bytes_per_ms;
+            send(chunk);
+            if speech {
+                self.silent_ms = 0;
+                return None;
+            }
+            self.silent_ms += duration_ms;
+            if self.silent_ms < self.silence_timeout_ms {
+                return None;
+            }
+            self.paused = true;
+            return Some(VadState::Silent);
+        }
+
+        if speech {
+            self.paused = false;
+            self.silent_ms = 0;
+            self.pre_roll_bytes = 0;
+            self.pre_roll.drain(..).for_each(&mut send);
+            send(chunk);
+            return Some(VadState::Speaking);
+        }
+
+        // Keep whole chunks covering at least PRE_ROLL_MS
+        self.pre_roll_bytes += chunk.len();
+        self.pre_roll.push_back(chunk);
+        let min_bytes = (PRE_ROLL_MS * self.bytes_per_ms) as usize;
+        while let Some(oldest) = self.pre_roll.front() {
+            if self.pre_roll_bytes - oldest.len() < min_bytes {
+                break;
+            }
+            self.pre_roll_bytes -= oldest.len();
+            self.pre_roll.pop_front();
+        }
+        None
+    }
+}
+
+/// RMS of little-endian i16 samples, scaled to [0, 1]
+pub fn rms(chunk: &[u8]) -> f32 {
+    let samples = chunk.len() / 2;
+    if samples == 0 {
+        return 0.0;
+    }
+    let sum_squares: f64 = chunk
+        .chunks_exact(2)
+        .map(|bytes| {
+            let sample = i16::from_le_bytes([bytes[0], bytes[1]]) as f64 / i16::MAX as f64;
+            sample * sample
+        })
+        .sum();
+    (sum_squares / samples as f64).sqrt() as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 100 ms of a constant-amplitude signal
+    fn chunk(amplitude: i16) -> Vec<u8> {
+        (0..1600)
+            .flat_map(|i| {
+                let sample = if i % 2 == 0 { amplitude } else { -amplitude };
+                sample.to_le_bytes()
+            })
+            .collect()
+    }
+
+    fn push(vad: &mut VoiceDetector, chunk: Vec<u8>) -> (Vec<Vec<u8>>, Option<VadState>) {
+        let mut sent = Vec::new();
+        let state = vad.push(chunk, |c| sent.push(c));
+        (sent, state)
+    }
+
+    #[test]
+    fn pauses_after_silence_timeout() {
+        let mut vad = VoiceDetector::new(-45.0, 1, 1);
+        for _ in 0..9 {
+            let (sent, state) = push(&mut vad, chunk(0));
+            assert_eq!(sent.len(), 1);
+            assert_eq!(state, None);
+        }
+        let (sent, state) = push(&mut vad, chunk(0));
+        assert_eq!(sent.len(), 1);
+        assert_eq!(state, Some(VadState::Silent));
+        assert!(vad.is_paused());
+
+        let (sent, state) = push(&mut vad, chunk(0));
+        assert!(sent.is_empty());
+        assert_eq!(state, None);
+    }
+
+    #[test]
+    fn speech_resets_the_silence_timer() {
+        let mut vad = VoiceDetector::new(-45.0, 1, 1);
+        for _ in 0..9 {
+            push(&mut vad, chunk(0));
+        }
+        push(&mut vad, chunk(8000));
+        for _ in 0..9 {
+            assert_eq!(push(&mut vad, chunk(0)).1, None);
+        }
+        assert!(!vad.is_paused());
+    }
+
+    #[test]
+    fn resumes_with_pre_roll() {
+        let mut vad = VoiceDetector::new(-45.0, 1, 1);
+        for _ in 0..10 {
+            push(&mut vad, chunk(0));
+        }
+        for _ in 0..20 {
+            push(&mut vad, chunk(10));
+        }
+        let (sent, state) = push(&mut vad, chunk(8000));
+        assert_eq!(state, Some(VadState::Speaking));
+        // 300 ms of 100 ms chunks, then the speech itself
+        assert_eq!(sent.len(), 4);
+        assert_eq!(sent[..3], [chunk(10), chunk(10), chunk(10)]);
+        assert_eq!(sent[3], chunk(8000));
+        assert!(!vad.is_paused());
+    }
+}
@@ -0,0 +1,278 @@
+// Launching at login
+// Each platform gets one login entry with a fixed name, so enabling twice overwrites it
+// instead of adding a second: a Run registry value on Windows, a LaunchAgent on macOS and
+// an XDG autostart entry elsewhere. The preference is kept in the config as well, so a
+// removed entry can be told apart from one that was never wanted
+
+use serde::Serialize;
+use tauri::AppHandle;
+
+use crate::config::{self, AutostartSettings};
+use crate::error::AppError;
+use crate::state;
+
+/// Argument the login entry passes when the app should start in the tray
+pub const MINIMIZED_FLAG: &str = "--minimized";
+
+/// Whether the app was started with `--minimized`
+pub fn launched_minimized() -> bool {
+    std::env::args().skip(1).any(|arg| arg == MINIMIZED_FLAG)
+}
+
+/// What the autostart toggle shows
+#[derive(Debug, Clone, Serialize)]
+pub struct AutostartStatus {
+    /// A login entry for this app is registered with the OS
+    pub enabled: bool,
+    pub minimized: bool,
+    /// Autostart was turned on here, but the login entry has since been removed
+    /// (e.g. in the OS startup settings)
+    pub removed_externally: bool,
+}
+
+#[tauri::command]
+pub async fn get_autostart_status(app: AppHandle) -> Result<AutostartStatus, AppError> {
+    state::blocking(move || {
+        let settings = config::load(&app)?.autostart;
+        Ok(status(&app, &settings))
+    })
+    .await
+}
+
+/// Register or remove the login entry; safe to call repeatedly with the same values
+#[tauri::command]
+pub async fn set_autostart(
+    app: AppHandle,
+    enabled: bool,
+    minimized: bool,
+) -> Result<AutostartStatus, AppError> {
+    state::blocking(move || {
+        if enabled {
+            platform::register(&app, &launch_command(minimized)?)?;
+        } else {
+            platform::unregister(&app)?;
+        }
+        let settings = config::update(&app, |config| {
+            config.autostart.enabled = enabled;
+            config.autostart.minimized = minimized;
+            Ok(config.autostart.clone())
+        })?;
+        Ok(status(&app, &settings))
+    })
+    .await
+}
+
+fn status(app: &AppHandle, settings: &AutostartSettings) -> AutostartStatus {
+    let registered = platform::is_registered(app);
+    AutostartStatus {
+        enabled: registered,
+        minimized: settings.minimized,
+        removed_externally: settings.enabled && !registered,
+    }
+}
+
+/// Program and arguments the login entry runs
+fn launch_command(minimized: bool) -> Result<Vec<String>, AppError> {
+    // An AppImage runs from a temporary mount, so point at the image itself
+    let program = match std::env::var_os("APPIMAGE") {
+        Some(image) => image.into(),
+        None => std::env::current_exe()
+            .map_err(|e| AppError::Io(format!("Failed to locate the app executable: {}", e)))?,
+    };
+    let mut command = vec![program.to_string_lossy().into_owned()];
+    if minimized {
+        command.push(MINIMIZED_FLAG.to_string());
+    }
+    Ok(command)
+}
+
+#[cfg(windows)]
+mod platform {
+    use tauri::AppHandle;
+    use winreg::enums::{HKEY_CURRENT_USER, KEY_SET_VALUE};
+    use winreg::RegKey;
+
+    use crate::error::AppError;
+
+    const RUN_KEY: &str = r"Software\Microsoft\Windows\CurrentVersion\Run";
+
+    fn value_name(app: &AppHandle) -> String {
+        app.package_info().name.clone()
+    }
+
+    pub fn register(app: &AppHandle, command: &[String]) -> Result<(), AppError> {
+        let line = command
+            .iter()
+            .map(|arg| format!("\"{}\"", arg))
+            .collect::<Vec<_>>()
+            .join(" ");
+        RegKey::predef(HKEY_CURRENT_USER)
+            .create_subkey(RUN_KEY)
+            .and_then(|(key, _)| key.set_value(value_name(app), &line))
+            .map_err(|e| AppError::Io(format!("Failed to add the login item: {}", e)))
+    }
+
+    pub fn unregister(app: &AppHandle) -> Result<(), AppError> {
+        let result = RegKey::predef(HKEY_CURRENT_USER)
+            .open_subkey_with_flags(RUN_KEY, KEY_SET_VALUE)
+            .and_then(|key| key.delete_value(value_name(app)));
+        match result {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(AppError::Io(format!(
+                "Failed to remove the login item: {}",
+                e
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn is_registered(app: &AppHandle) -> bool {
+        RegKey::predef(HKEY_CURRENT_USER)
+            .open_subkey(RUN_KEY)
+            .and_then(|key| key.get_value::<String, _>(value_name(app)))
+            .is_ok()
+    }
+}
+
+#[cfg(not(windows))]
+mod platform {
+    use std::path::PathBuf;
+
+    use tauri::{AppHandle, Manager};
+
+    use crate::error::AppError;
+
+    pub fn register(app: &AppHandle, command: &[String]) -> Result<(), AppError> {
+        let path = entry_path(app)?;
+        let write = || {
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            std::fs::write(&path, entry(app, command))
+        };
+        write().map_err(|e| AppError::Io(format!("Failed to add the login item: {}", e)))
+    }
+
+    pub fn unregister(app: &AppHandle) -> Result<(), AppError> {
+        match std::fs::remove_file(entry_path(app)?) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(AppError::Io(format!(
+                "Failed to remove the login item: {}",
+                e
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn is_registered(app: &AppHandle) -> bool {
+        entry_path(app).is_ok_and(|path| path.is_file())
+    }
+
+    #[cfg(target_os = "macos")]
+    fn entry_path(app: &AppHandle) -> Result<PathBuf, AppError> {
+        let home = app
+            .path()
+            .home_dir()
+            .map_err(|e| AppError::Io(format!("Failed to find the home directory: {}", e)))?;
+        Ok(home
+            .join("Library/LaunchAgents")
+            .join(format!("{}.plist", app.config().identifier)))
+    }
+
+    #[cfg(target_os = "macos")]
+    fn entry(app: &AppHandle, command: &[String]) -> String {
+        super::launch_agent(&app.config().identifier, command)
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    fn entry_path(app: &AppHandle) -> Result<PathBuf, AppError> {
+        let config = app
+            .path()
+            .config_dir()
+            .map_err(|e| AppError::Io(format!("Failed to find the config directory: {}", e)))?;
+        Ok(config
+            .join("autostart")
+            .join(format!("{}.desktop", app.config().identifier)))
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    fn entry(app: &AppHandle, command: &[String]) -> String {
+        super::desktop_entry(&app.package_info().name, command)
+    }
+}
+
+/// LaunchAgent that starts `command` at login
+#[cfg(target_os = "macos")]
+fn launch_agent(label: &str, command: &[String]) -> String {
+    let escape = |text: &str| {
+        text.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+    };
+    let arguments: String = command
+        .iter()
+        .map(|arg| format!("        <string>{}</string>\n", escape(arg)))
+        .collect();
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{}</string>
+    <key>ProgramArguments</key>
+    <array>
+{}    </array>
+    <key>RunAtLoad</key>
+    <true/>
+</dict>
+</plist>
+"#,
+        escape(label),
+        arguments
+    )
+}
+
+/// XDG autostart entry that starts `command` at login
+#[cfg(not(any(windows, target_os = "macos")))]
+fn desktop_entry(name: &str, command: &[String]) -> String {
+    // Exec arguments are double-quoted, with ", `, $ and \ backslash-escaped inside
+    let exec = command
+        .iter()
+        .map(|arg| {
+            let mut quoted = String::from('"');
+            for c in arg.chars() {
+                if matches!(c, '"' | '`' | '$' | '\\') {
+                    quoted.push('\\');
+                }
+                quoted.push(c);
+            }
+            quoted.push('"');
+            quoted
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+    format!(
+        "[Desktop Entry]\nType=Application\nName={}\nExec={}\nX-GNOME-Autostart-enabled=true\n",
+        name, exec
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(not(any(windows, target_os = "macos")))]
+    #[test]
+    fn desktop_entry_quotes_the_command() {
+        let entry = super::desktop_entry(
+            "SubSpace Voice",
+            &[
+                "/opt/Sub \"Space\"/$voice".to_string(),
+                super::MINIMIZED_FLAG.to_string(),
+            ],
+        );
+        assert!(
+            entry.contains("Exec=\"/opt/Sub \\\"Space\\\"/\\$voice\" \"--minimized\"\n"),
+            "{}",
+            entry
+        );
+        assert!(entry.starts_with("[Desktop Entry]\n"));
+    }
+}
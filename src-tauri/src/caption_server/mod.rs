@@ -0,0 +1,435 @@
+// Local caption server for OBS browser sources and other overlays
+// Serves a caption page and a Server-Sent Events stream of live transcripts,
+// bound to 127.0.0.1 only so nothing outside this machine can connect
+
+use std::io::ErrorKind;
+use std::net::Ipv4Addr;
+use std::time::Duration;
+
+use serde::Serialize;
+use tauri::async_runtime::JoinHandle;
+use tauri::{AppHandle, Manager, State};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, watch, Mutex};
+
+use crate::deepgram::proxy::{EVENT_TRANSCRIPT_FINAL, EVENT_TRANSCRIPT_PARTIAL};
+use crate::deepgram::TranscriptEvent;
+use crate::error::AppError;
+use crate::state::{blocking, AppState};
+
+/// Caption page; listens to `/events` and draws the latest lines
+const PAGE: &str = include_str!("page.html");
+/// Transcripts queued per client before a slow one starts missing them
+const CAPTION_QUEUE_CAPACITY: usize = 64;
+/// Comment sent to idle clients so closed connections are noticed
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+/// Longest request head we read; browsers send far less
+const MAX_REQUEST_BYTES: usize = 8 * 1024;
+/// How long a client gets to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A transcript event, serialized once for every client
+#[derive(Clone)]
+struct Caption {
+    event: &'static str,
+    data: String,
+}
+
+struct Running {
+    port: u16,
+    shutdown: watch::Sender<bool>,
+    task: JoinHandle<()>,
+}
+
+/// The (at most one) caption server; part of `AppState`
+pub struct CaptionServer {
+    running: Mutex<Option<Running>>,
+    captions: broadcast::Sender<Caption>,
+}
+
+impl Default for CaptionServer {
+    fn default() -> Self {
+        let (captions, _) = broadcast::channel(CAPTION_QUEUE_CAPACITY);
+        Self {
+            running: Mutex::new(None),
+            captions,
+        }
+    }
+}
+
+/// Returned by the caption server commands
+#[derive(Debug, Clone, Serialize)]
+pub struct CaptionServerStatus {
+    pub running: bool,
+    pub port: Option<u16>,
+    /// Address to paste into an OBS browser source
+    pub url: Option<String>,
+    /// Pages currently listening for captions
+    pub clients: usize,
+}
+
+impl CaptionServer {
+    /// Bind 127.0.0.1:`port` and start serving
+    pub async fn start(&self, port: u16) -> Result<(), AppError> {
+        if port == 0 {
+            return Err(AppError::InvalidInput(
+                "Caption server port must be between 1 and 65535".to_string(),
+            ));
+        }
+        let mut running = self.running.lock().await;
+        if let Some(current) = running.as_ref() {
+            return Err(AppError::CaptionServer(if current.port == port {
+                format!("Caption server is already running on port {}", port)
+            } else {
+                format!(
+                    "Caption server is already running on port {}; stop it first",
+                    current.port
+                )
+            }));
+        }
+
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))
+            .await
+            .map_err(|e| {
+                AppError::CaptionServer(match e.kind() {
+                    ErrorKind::AddrInUse => {
+                        format!("Port {} is already in use by another program", port)
+                    }
+                    _ => format!("Failed to start caption server on port {}: {}", port, e),
+                })
+            })?;
+        let (shutdown, shutdown_rx) = watch::channel(false);
+        let task =
+            tauri::async_runtime::spawn(serve(listener, port, self.captions.clone(), shutdown_rx));
+        *running = Some(Running {
+            port,
+            shutdown,
+            task,
+        });
+        tracing::info!("Caption server listening on {}", page_url(port));
+        Ok(())
+    }
+
+    /// Stop accepting connections and close the open ones
+    pub async fn stop(&self) {
+        let Some(Running { shutdown, task, .. }) = self.running.lock().await.take() else {
+            return;
+        };
+        let _ = shutdown.send(true);
+        let _ = task.await;
+        tracing::info!("Caption server stopped");
+    }
+
+    pub async fn status(&self) -> CaptionServerStatus {
+        let port = self
+            .running
+            .lock()
+            .await
+            .as_ref()
+            .map(|running| running.port);
+        CaptionServerStatus {
+            running: port.is_some(),
+            port,
+            url: port.map(page_url),
+            clients: self.captions.receiver_count(),
+        }
+    }
+
+    /// Pass a transcript event on to connected pages; free when none are connected
+    pub fn relay(&self, is_final: bool, event: &TranscriptEvent) {
+        if self.captions.receiver_count() == 0 {
+            return;
+        }
+        let event_name = if is_final {
+            EVENT_TRANSCRIPT_FINAL
+        } else {
+            EVENT_TRANSCRIPT_PARTIAL
+        };
+        match serde_json::to_string(event) {
+            Ok(data) => {
+                let _ = self.captions.send(Caption {
+                    event: event_name,
+                    data,
+                });
+            }
+            Err(e) => tracing::warn!("Failed to serialize caption: {}", e),
+        }
+    }
+}
+
+/// Start the caption server at launch if it was left enabled
+pub fn init(app: &AppHandle) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let handle = app.clone();
+        let Ok(settings) = blocking(move || Ok(crate::config::load(&handle)?.caption_server)).await
+        else {
+            return;
+        };
+        if !settings.enabled {
+            return;
+        }
+        if let Err(e) = app.state::<AppState>().captions.start(settings.port).await {
+            tracing::warn!("{}; captions are off until it is started again", e);
+        }
+    });
+}
+
+/// Command to start the caption server, by default on the saved port
+/// It is remembered as enabled and comes back on the next launch
+#[tauri::command]
+pub async fn start_caption_server(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    port: Option<u16>,
+) -> Result<CaptionServerStatus, AppError> {
+    let handle = app.clone();
+    let port = match port {
+        Some(port) => port,
+        None => blocking(move || Ok(crate::config::load(&handle)?.caption_server.port)).await?,
+    };
+    state.captions.start(port).await?;
+    if let Err(e) = blocking(move || save_settings(&app, true, Some(port))).await {
+        tracing::warn!("Failed to remember caption server settings: {}", e);
+    }
+    Ok(state.captions.status().await)
+}
+
+/// Command to stop the caption server; does nothing if it isn't running
+#[tauri::command]
+pub async fn stop_caption_server(
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<(), AppError> {
+    state.captions.stop().await;
+    blocking(move || save_settings(&app, false, None)).await
+}
+
+#[tauri::command]
+pub async fn get_caption_server_status(
+    state: State<'_, AppState>,
+) -> Result<CaptionServerStatus, AppError> {
+    Ok(state.captions.status().await)
+}
+
+fn save_settings(app: &AppHandle, enabled: bool, port: Option<u16>) -> Result<(), AppError> {
+    crate::config::update(app, |config| {
+        config.caption_server.enabled = enabled;
+        if let Some(port) = port {
+            config.caption_server.port = port;
+        }
+        Ok(())
+    })
+}
+
+fn page_url(port: u16) -> String {
+    format!("http://127.0.0.1:{}/", port)
+}
+
+/// Accept connections until told to shut down
+async fn serve(
+    listener: TcpListener,
+    port: u16,
+    captions: broadcast::Sender<Caption>,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    loop {
+        tokio::select! {
+            _ = shutdown.changed() => break,
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => {
+                    tauri::async_runtime::spawn(handle_connection(
+                        stream,
+                        port,
+                        captions.clone(),
+                        shutdown.clone(),
+                    ));
+                }
+                Err(e) => {
+                    // Usually out of file descriptors; back off instead of spinning
+                    tracing::warn!("Caption server failed to accept a connection: {}", e);
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                }
+            },
+        }
+    }
+}
+
+/// What a request asked for
+#[derive(Debug, PartialEq, Eq)]
+enum Route {
+    Page,
+    Events,
+    Reject(u16, &'static str),
+}
+
+async fn handle_connection(
+    mut stream: TcpStream,
+    port: u16,
+    captions: broadcast::Sender<Caption>,
+    shutdown: watch::Receiver<bool>,
+) {
+    let head = match tokio::time::timeout(REQUEST_TIMEOUT, read_request_head(&mut stream)).await {
+        Ok(Ok(head)) => head,
+        _ => return,
+    };
+    match route(&head, port) {
+        Route::Page => respond(&mut stream, 200, "OK", "text/html; charset=utf-8", PAGE).await,
+        Route::Events => stream_captions(stream, captions.subscribe(), shutdown).await,
+        Route::Reject(status, reason) => {
+            respond(
+                &mut stream,
+                status,
+                reason,
+                "text/plain; charset=utf-8",
+                reason,
+            )
+            .await
+        }
+    }
+}
+
+/// Read up to the blank line ending the request head; requests here have no body
+async fn read_request_head(stream: &mut TcpStream) -> std::io::Result<String> {
+    let mut head = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !head.windows(4).any(|window| window == b"\r\n\r\n") {
+        if head.len() > MAX_REQUEST_BYTES {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidData,
+                "Request head too large",
+            ));
+        }
+        let read = stream.read(&mut buf).await?;
+        if read == 0 {
+            return Err(ErrorKind::UnexpectedEof.into());
+        }
+        head.extend_from_slice(&buf[..read]);
+    }
+    Ok(String::from_utf8_lossy(&head).into_owned())
+}
+
+/// Route a request by its head
+/// The Host header must name this server, so a web page can't reach it through
+/// DNS rebinding
+fn route(head: &str, port: u16) -> Route {
+    let mut lines = head.lines();
+    let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+    let method = request_line.next().unwrap_or_default();
+    let target = request_line.next().unwrap_or_default();
+
+    let host = lines
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("host"))
+        .map(|(_, value)| value.trim());
+    let allowed = [format!("127.0.0.1:{}", port), format!("localhost:{}", port)];
+    if !host.is_some_and(|host| allowed.iter().any(|name| name.eq_ignore_ascii_case(host))) {
+        return Route::Reject(403, "Forbidden");
+    }
+    if method != "GET" {
+        return Route::Reject(405, "Method Not Allowed");
+    }
+    match target.split('?').next() {
+        Some("/") => Route::Page,
+        Some("/events") => Route::Events,
+        _ => Route::Reject(404, "Not Found"),
+    }
+}
+
+async fn respond(
+    stream: &mut TcpStream,
+    status: u16,
+    reason: &str,
+    content_type: &str,
+    body: &str,
+) {
+    let response = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n{}",
+        status,
+        reason,
+        content_type,
+        body.len(),
+        body
+    );
+    let _ = stream.write_all(response.as_bytes()).await;
+    let _ = stream.shutdown().await;
+}
+
+/// Send captions as Server-Sent Events until the client leaves or the server stops
+async fn stream_captions(
+    mut stream: TcpStream,
+    mut captions: broadcast::Receiver<Caption>,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let head = "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: keep-alive\r\n\r\n";
+    if stream.write_all(head.as_bytes()).await.is_err() {
+        return;
+    }
+    let mut heartbeat = tokio::time::interval(HEARTBEAT_INTERVAL);
+    loop {
+        let message = tokio::select! {
+            _ = shutdown.changed() => break,
+            _ = heartbeat.tick() => ": ping\n\n".to_string(),
+            caption = captions.recv() => match caption {
+                Ok(caption) => format!("event: {}\ndata: {}\n\n", caption.event, caption.data),
+                // A slow client misses some interim text; the next final catches it up
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            },
+        };
+        if stream.write_all(message.as_bytes()).await.is_err() {
+            break;
+        }
+    }
+    let _ = stream.shutdown().await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(method: &str, target: &str, host: &str) -> String {
+        format!(
+            "{} {} HTTP/1.1\r\nHost: {}\r\nAccept: */*\r\n\r\n",
+            method, target, host
+        )
+    }
+
+    #[test]
+    fn routes_page_and_events() {
+        assert_eq!(
+            route(&request("GET", "/", "127.0.0.1:8765"), 8765),
+            Route::Page
+        );
+        assert_eq!(
+            route(&request("GET", "/events?v=1", "localhost:8765"), 8765),
+            Route::Events
+        );
+        assert_eq!(
+            route(&request("GET", "/favicon.ico", "127.0.0.1:8765"), 8765),
+            Route::Reject(404, "Not Found")
+        );
+        assert_eq!(
+            route(&request("POST", "/", "127.0.0.1:8765"), 8765),
+            Route::Reject(405, "Method Not Allowed")
+        );
+    }
+
+    #[test]
+    fn rejects_foreign_hosts() {
+        for host in ["evil.example:8765", "127.0.0.1:9000", ""] {
+            assert_eq!(
+                route(&request("GET", "/events", host), 8765),
+                Route::Reject(403, "Forbidden"),
+                "{}",
+                host
+            );
+        }
+        assert_eq!(
+            route("GET /events HTTP/1.1\r\n\r\n", 8765),
+            Route::Reject(403, "Forbidden")
+        );
+    }
+}
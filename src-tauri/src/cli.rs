@@ -0,0 +1,322 @@
+// Headless file transcription for scripts and CI, from the same binary as the app:
+//   subspace-voice transcribe <file> [--format srt|vtt|txt|json|review] [--output <path>] [--language <code>]
+// It runs the same upload and export as the app, without a window or tray, and saves the
+// session to history like any other file. Progress and logs go to stderr, so the export
+// can be piped from stdout. Tauri still starts its event loop, so on Linux it needs a
+// display; run it under xvfb-run on a machine without one
+
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+use tauri::{AppHandle, Context, Manager, Wry};
+
+use crate::config;
+use crate::deepgram::prerecorded::{self, Progress, ProgressFn, TranscriptionPhase};
+use crate::env_loader::ENV_FILE_ARG;
+use crate::error::AppError;
+use crate::export::{self, ExportFormat, TextVersion, DEFAULT_MAX_CHARS_PER_CUE};
+use crate::state::{blocking, AppState};
+
+/// Exit codes besides 0; scripts branch on these, so never change one
+const EXIT_FAILURE: i32 = 1;
+const EXIT_KEY_NOT_CONFIGURED: i32 = 2;
+const EXIT_NETWORK: i32 = 3;
+const EXIT_BAD_INPUT: i32 = 4;
+
+const USAGE: &str = "\
+Usage: subspace-voice transcribe <file> [options]
+
+Transcribes an audio file with Deepgram and writes the transcript to stdout or a file.
+
+Options:
+  --format <srt|vtt|txt|json|review>  Export format (default: txt)
+  --output <path>                     Write to this file instead of stdout
+  --language <code>                   Language of the audio instead of the saved setting
+  --env-file <path>                   Load DEEPGRAM_API_KEY and friends from a .env file
+
+Exit codes: 0 done, 1 other failure, 2 no API key, 3 network failure, 4 bad input file";
+
+/// What the command line asks for
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Transcribe(Transcribe),
+    Help,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Transcribe {
+    pub file: String,
+    pub format: ExportFormat,
+    /// Stdout when None
+    pub output: Option<String>,
+    pub language: Option<String>,
+}
+
+/// The command in `args` (without the program name), None when there's none and the app
+/// should start as usual; Err is a message for a command line that doesn't parse
+pub fn parse(args: impl IntoIterator<Item = String>) -> Option<Result<Command, String>> {
+    let mut args = without_env_file(args).into_iter();
+    if args.next()? != "transcribe" {
+        return None;
+    }
+    Some(parse_transcribe(args))
+}
+
+fn parse_transcribe(mut args: impl Iterator<Item = String>) -> Result<Command, String> {
+    let mut file = None;
+    let mut format = ExportFormat::Txt;
+    let mut output = None;
+    let mut language = None;
+    while let Some(arg) = args.next() {
+        let (name, inline) = match arg.split_once('=') {
+            Some((name, value)) if name.starts_with("--") => {
+                (name.to_string(), Some(value.to_string()))
+            }
+            _ => (arg, None),
+        };
+        let mut value = || {
+            inline
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| format!("{} needs a value", name))
+        };
+        match name.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--format" => format = value()?.parse().map_err(|e: AppError| e.to_string())?,
+            "--output" | "-o" => output = Some(value()?),
+            "--language" => language = Some(value()?),
+            _ if name.starts_with('-') && name != "-" => {
+                return Err(format!("Unknown option {}", name))
+            }
+            _ if file.is_none() => file = Some(name),
+            _ => return Err(format!("Unexpected argument {}", name)),
+        }
+    }
+    let file = file.ok_or_else(|| "No file to transcribe".to_string())?;
+    Ok(Command::Transcribe(Transcribe {
+        file,
+        format,
+        output,
+        language,
+    }))
+}
+
+/// Drop `--env-file`, which `env_loader` has already handled
+fn without_env_file(args: impl IntoIterator<Item = String>) -> Vec<String> {
+    let mut args = args.into_iter();
+    let mut kept = Vec::new();
+    while let Some(arg) = args.next() {
+        if arg == ENV_FILE_ARG {
+            args.next();
+        } else if !arg.starts_with(&format!("{}=", ENV_FILE_ARG)) {
+            kept.push(arg);
+        }
+    }
+    kept
+}
+
+/// Run a parsed command line and return the process exit code
+pub fn run(mut context: Context<Wry>, command: Result<Command, String>) -> i32 {
+    attach_console();
+    let command = match command {
+        Ok(Command::Transcribe(command)) => command,
+        Ok(Command::Help) => {
+            println!("{}", USAGE);
+            return 0;
+        }
+        Err(message) => {
+            eprintln!("{}\n\n{}", message, USAGE);
+            return EXIT_FAILURE;
+        }
+    };
+
+    // Only the windows in the config are created at startup; the tray is never set up
+    context.config_mut().app.windows.clear();
+    let command = Mutex::new(Some(command));
+    let app = tauri::Builder::default()
+        .setup(move |app| {
+            crate::logging::attach_file(app.handle());
+            app.manage(AppState::init(app.handle()));
+            app.manage(crate::postprocess::replacements::init(app.handle()));
+            let handle = app.handle().clone();
+            let command = command.lock().ok().and_then(|mut command| command.take());
+            tauri::async_runtime::spawn(async move {
+                let code = match command {
+                    Some(command) => match transcribe(&handle, command).await {
+                        Ok(()) => 0,
+                        Err(e) => {
+                            eprintln!("Error: {}", e);
+                            exit_code(&e)
+                        }
+                    },
+                    None => EXIT_FAILURE,
+                };
+                handle.exit(code);
+            });
+            Ok(())
+        })
+        .build(context);
+    match app {
+        Ok(app) => app.run_return(|_, _| {}),
+        Err(e) => {
+            eprintln!("Error: couldn't start: {}", e);
+            EXIT_FAILURE
+        }
+    }
+}
+
+/// Transcribe the file as `transcribe_file` does, then export it as `export_session` does
+async fn transcribe(app: &AppHandle, command: Transcribe) -> Result<(), AppError> {
+    let session = prerecorded::transcribe(
+        app,
+        command.file,
+        None,
+        command.language,
+        progress_printer(),
+    )
+    .await?;
+    let storage = Arc::clone(&app.state::<AppState>().storage);
+    let (format, max_chars) = (command.format, DEFAULT_MAX_CHARS_PER_CUE);
+    let threshold = config::transcription_settings(app).confidence_threshold;
+    blocking(move || match command.output {
+        Some(path) => {
+            export::write_export(
+                &storage,
+                session.id,
+                format,
+                TextVersion::Original,
+                &path,
+                max_chars,
+                threshold,
+            )?;
+            eprintln!("Wrote {}", path);
+            Ok(())
+        }
+        None => {
+            let contents = export::render(
+                &storage,
+                session.id,
+                format,
+                TextVersion::Original,
+                max_chars,
+                threshold,
+            )?;
+            let mut stdout = std::io::stdout().lock();
+            stdout
+                .write_all(contents.as_bytes())
+                .and_then(|()| stdout.flush())
+                .map_err(|e| AppError::Io(format!("Failed to write to stdout: {}", e)))
+        }
+    })
+    .await
+}
+
+/// Prints a line to stderr per phase change and every tenth of the upload
+fn progress_printer() -> ProgressFn {
+    let last = Mutex::new(None);
+    Arc::new(move |progress: Progress| {
+        let tenth = progress.bytes_sent * 10 / progress.total_bytes.max(1);
+        let step = (progress.phase, progress.attempt, tenth);
+        let Ok(mut last) = last.lock() else {
+            return;
+        };
+        if *last == Some(step) {
+            return;
+        }
+        *last = Some(step);
+        let attempt = match progress.attempt {
+            1 => String::new(),
+            attempt => format!(" (attempt {} of {})", attempt, progress.max_attempts),
+        };
+        match progress.phase {
+            TranscriptionPhase::Uploading => eprintln!("Uploading {}%{}", tenth * 10, attempt),
+            TranscriptionPhase::Transcribing => eprintln!("Transcribing{}", attempt),
+            TranscriptionPhase::Retrying => eprintln!("Retrying{}", attempt),
+            TranscriptionPhase::Done => eprintln!("Done"),
+        }
+    })
+}
+
+fn exit_code(error: &AppError) -> i32 {
+    match error {
+        AppError::KeyNotConfigured | AppError::KeyInvalid(_) | AppError::KeyUnreadable(_) => {
+            EXIT_KEY_NOT_CONFIGURED
+        }
+        AppError::Network { .. } | AppError::Tls(_) | AppError::Proxy(_) => EXIT_NETWORK,
+        AppError::InvalidInput(_)
+        | AppError::Unsupported(_)
+        | AppError::Io(_)
+        | AppError::NoTimingData(_) => EXIT_BAD_INPUT,
+        _ => EXIT_FAILURE,
+    }
+}
+
+/// Release builds on Windows are GUI programs with no console; borrow the one they were
+/// started from so output shows up there
+#[cfg(windows)]
+fn attach_console() {
+    use windows_sys::Win32::System::Console::{AttachConsole, ATTACH_PARENT_PROCESS};
+    // Fails harmlessly when there's no parent console, or one is already attached
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+#[cfg(not(windows))]
+fn attach_console() {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn transcribe_arguments_parse_and_anything_else_starts_the_app() {
+        assert_eq!(parse(args("")), None);
+        assert_eq!(parse(args("--env-file .env")), None);
+        assert_eq!(
+            parse(args(
+                "--env-file .env transcribe talk.wav --format=SRT -o out.srt --language en"
+            )),
+            Some(Ok(Command::Transcribe(Transcribe {
+                file: "talk.wav".to_string(),
+                format: ExportFormat::Srt,
+                output: Some("out.srt".to_string()),
+                language: Some("en".to_string()),
+            })))
+        );
+        assert_eq!(
+            parse(args("transcribe talk.wav")),
+            Some(Ok(Command::Transcribe(Transcribe {
+                file: "talk.wav".to_string(),
+                format: ExportFormat::Txt,
+                output: None,
+                language: None,
+            })))
+        );
+        assert_eq!(parse(args("transcribe --help")), Some(Ok(Command::Help)));
+        for bad in [
+            "transcribe",
+            "transcribe a.wav b.wav",
+            "transcribe a.wav --format doc",
+            "transcribe a.wav --output",
+            "transcribe a.wav --fast",
+        ] {
+            assert!(matches!(parse(args(bad)), Some(Err(_))), "{}", bad);
+        }
+
+        assert_eq!(exit_code(&AppError::KeyNotConfigured), 2);
+        assert_eq!(
+            exit_code(&AppError::Network {
+                status: Some(503),
+                message: String::new()
+            }),
+            3
+        );
+        assert_eq!(exit_code(&AppError::InvalidInput(String::new())), 4);
+        assert_eq!(exit_code(&AppError::Storage(String::new())), 1);
+    }
+}
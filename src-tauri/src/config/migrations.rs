@@ -0,0 +1,99 @@
+// Upgrades of settings files written by older versions, one version at a time
+// Each migration works on the raw JSON, so a setting can move or change shape before the
+// typed structs read it. Files from before versioning have no `version` and are version 1
+
+use serde_json::{Map, Value};
+
+use super::{DEFAULT_PROFILE, SETTINGS_VERSION};
+
+/// One upgrade, from the version at its index plus one to the next
+type Migration = fn(&mut Map<String, Value>);
+
+/// In order: the first turns version 1 into version 2
+const MIGRATIONS: &[Migration] = &[v1_to_v2];
+
+const _: () = assert!(MIGRATIONS.len() as u32 + 1 == SETTINGS_VERSION);
+
+/// Version a settings file was written in
+pub fn file_version(object: &Map<String, Value>) -> u32 {
+    object
+        .get("version")
+        .and_then(Value::as_u64)
+        .map_or(1, |version| u32::try_from(version).unwrap_or(u32::MAX))
+        .max(1)
+}
+
+/// Bring a file up to `SETTINGS_VERSION`; one from a newer version is left alone
+pub fn migrate(object: &mut Map<String, Value>) {
+    let version = file_version(object);
+    if version > SETTINGS_VERSION {
+        return;
+    }
+    for migration in &MIGRATIONS[version as usize - 1..] {
+        migration(object);
+    }
+    object.insert("version".to_string(), SETTINGS_VERSION.into());
+}
+
+/// Files from before profiles kept the transcription settings at the top level; they
+/// belong to the default profile
+fn v1_to_v2(object: &mut Map<String, Value>) {
+    let Some(transcription) = object.remove("transcription") else {
+        return;
+    };
+    let profiles = object
+        .entry("profiles")
+        .or_insert_with(|| Value::Object(Map::new()));
+    if let Value::Object(profiles) = profiles {
+        let default = profiles
+            .entry(DEFAULT_PROFILE)
+            .or_insert_with(|| Value::Object(Map::new()));
+        if let Value::Object(default) = default {
+            default.insert("transcription".to_string(), transcription);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AppConfig;
+
+    fn fixture(json: &str) -> Map<String, Value> {
+        match serde_json::from_str(json) {
+            Ok(Value::Object(object)) => object,
+            other => panic!("fixture isn't an object: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn v1_moves_top_level_transcription_into_the_default_profile() {
+        let mut object = fixture(include_str!("fixtures/settings_v1.json"));
+        assert_eq!(file_version(&object), 1);
+        migrate(&mut object);
+        assert_eq!(file_version(&object), SETTINGS_VERSION);
+        assert!(!object.contains_key("transcription"));
+
+        let config: AppConfig = serde_json::from_value(Value::Object(object)).unwrap();
+        let transcription = &config.profiles[DEFAULT_PROFILE].transcription;
+        assert_eq!(transcription.model, "nova-2");
+        assert_eq!(transcription.language, "de");
+        // Everything else is untouched, unknown sections included
+        assert_eq!(config.hotkey.push_to_talk, "CommandOrControl+Alt+D");
+        assert!(config.extra.contains_key("window"));
+    }
+
+    #[test]
+    fn current_and_newer_files_are_left_as_they_are() {
+        for version in [SETTINGS_VERSION, SETTINGS_VERSION + 1] {
+            let object = fixture(
+                &serde_json::json!({ "version": version, "transcription": { "model": "x" } })
+                    .to_string(),
+            );
+            let mut migrated = object.clone();
+            migrate(&mut migrated);
+            assert_eq!(migrated, object);
+            assert_eq!(file_version(&migrated), version);
+        }
+    }
+}
@@ -0,0 +1,341 @@
+// subspace:// links, so launchers and scripts can drive dictation
+//   subspace://dictate?profile=work&output=clipboard   start a session
+//   subspace://stop                                     stop the running one
+// `profile` switches the active profile the way the profile menu does, and switches back
+// once the session ends unless another profile was picked meanwhile; `output` (one or
+// more comma-separated output targets) replaces the output targets for that session only.
+// Dictation starts as a toggle-mode press does, and with the main window out of sight the
+// caption overlay comes up to show it. A link that can't be followed (an unknown action
+// or parameter, an unknown profile, a session already running, nothing to stop) is
+// reported with `deep-link-rejected` instead of being dropped. A link that launched the
+// app waits for the main window to load, so the frontend is listening when it starts
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager};
+use tauri_plugin_deep_link::DeepLinkExt;
+use url::Url;
+
+use crate::config::OutputTarget;
+use crate::engine::manager::SessionManager;
+use crate::engine::SessionState;
+use crate::error::AppError;
+use crate::state::blocking;
+
+/// URL scheme the app registers, as in tauri.conf.json
+pub const SCHEME: &str = "subspace";
+/// Event emitted with a `DeepLinkRejected` when a link couldn't be followed
+pub const EVENT_DEEP_LINK_REJECTED: &str = "deep-link-rejected";
+
+/// How long a link's output targets wait for the session it started to pick them up
+const OUTPUT_OVERRIDE_TTL: Duration = Duration::from_secs(30);
+
+/// Payload of the `deep-link-rejected` event
+#[derive(Debug, Serialize)]
+pub struct DeepLinkRejected {
+    pub url: String,
+    pub error: AppError,
+}
+
+/// What a link asks for
+#[derive(Debug, PartialEq)]
+enum Action {
+    Dictate {
+        profile: Option<String>,
+        outputs: Option<Vec<OutputTarget>>,
+    },
+    Stop,
+}
+
+/// Managed state
+#[derive(Default)]
+pub struct DeepLinkState {
+    /// Output targets for the next session, and when the link asked for them
+    outputs: Mutex<Option<(Vec<OutputTarget>, Instant)>>,
+    /// Profile to go back to when the session a link started ends
+    restore: Mutex<Option<ProfileRestore>>,
+    /// The links the app was launched with have been followed
+    launch_followed: AtomicBool,
+}
+
+/// A profile a link switched away from for its session
+struct ProfileRestore {
+    previous: String,
+    /// What the link switched to; if the active profile isn't this any more, the user
+    /// picked another one and that choice stays
+    switched_to: String,
+}
+
+/// Start following links opened while the app runs
+pub fn init(app: &AppHandle) {
+    // Installed builds register the scheme at install time; a dev build or an AppImage
+    // has to do it itself
+    #[cfg(any(target_os = "linux", all(debug_assertions, windows)))]
+    if let Err(e) = app.deep_link().register_all() {
+        tracing::warn!("Failed to register the {}:// scheme: {}", SCHEME, e);
+    }
+    let handle = app.clone();
+    app.deep_link().on_open_url(move |event| {
+        for url in event.urls() {
+            follow(&handle, url);
+        }
+    });
+}
+
+/// The main window loaded; follow the links the app was launched with, once
+pub fn main_window_loaded(app: &AppHandle) {
+    if app
+        .state::<DeepLinkState>()
+        .launch_followed
+        .swap(true, Ordering::AcqRel)
+    {
+        return;
+    }
+    match app.deep_link().get_current() {
+        Ok(urls) => {
+            for url in urls.unwrap_or_default() {
+                follow(app, url);
+            }
+        }
+        Err(e) => tracing::warn!("Failed to read the link the app was opened with: {}", e),
+    }
+}
+
+/// Output targets a link asked for, for the session starting now; taken once
+pub fn take_outputs(app: &AppHandle) -> Option<Vec<OutputTarget>> {
+    let (outputs, at) = app.state::<DeepLinkState>().outputs.lock().ok()?.take()?;
+    (at.elapsed() < OUTPUT_OVERRIDE_TTL).then_some(outputs)
+}
+
+/// Called on every session state change; an ended session puts back the profile its
+/// link switched away from
+pub fn session_changed(app: &AppHandle, state: SessionState) {
+    if state != SessionState::Idle {
+        return;
+    }
+    let Some(restore) = app
+        .state::<DeepLinkState>()
+        .restore
+        .lock()
+        .ok()
+        .and_then(|mut restore| restore.take())
+    else {
+        return;
+    };
+    // Off the session manager's lock, which is held while this runs
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let handle = app.clone();
+        let restored = blocking(move || {
+            if crate::config::active_profile(&handle) != restore.switched_to {
+                return Ok(());
+            }
+            crate::profiles::activate(&handle, &restore.previous)
+        })
+        .await;
+        if let Err(e) = restored {
+            tracing::warn!("Failed to switch back after a linked session: {}", e);
+        }
+    });
+}
+
+fn follow(app: &AppHandle, url: Url) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let followed = match parse(&url) {
+            Ok(action) => act(&app, action).await,
+            Err(e) => Err(e),
+        };
+        if let Err(error) = followed {
+            tracing::warn!("Didn't follow {}: {}", url, error);
+            let _ = app.emit(
+                EVENT_DEEP_LINK_REJECTED,
+                &DeepLinkRejected {
+                    url: url.to_string(),
+                    error,
+                },
+            );
+        }
+    });
+}
+
+/// Read a link, checking its action and parameters but not that the profile exists
+fn parse(url: &Url) -> Result<Action, AppError> {
+    let invalid = |message: String| Err(AppError::InvalidInput(message));
+    if url.scheme() != SCHEME {
+        return invalid(format!("Not a {}:// link", SCHEME));
+    }
+    // subspace://dictate has the action as its host; subspace:dictate as its path
+    let (action, rest) = match url.host_str() {
+        Some(host) => (host, url.path()),
+        None => (url.path(), ""),
+    };
+    if !rest.trim_start_matches('/').is_empty() {
+        return invalid(format!("Unexpected path \"{}\"", rest));
+    }
+
+    let mut profile = None;
+    let mut outputs = None;
+    for (name, value) in url.query_pairs() {
+        let repeated = match name.as_ref() {
+            "profile" if action == "dictate" => profile.replace(value.trim().to_string()).is_some(),
+            "output" if action == "dictate" => outputs.replace(output_targets(&value)?).is_some(),
+            _ => return invalid(format!("Unknown parameter \"{}\"", name)),
+        };
+        if repeated {
+            return invalid(format!("\"{}\" is given more than once", name));
+        }
+    }
+    match action {
+        "dictate" => {
+            if profile.as_deref() == Some("") {
+                return invalid("\"profile\" must not be empty".to_string());
+            }
+            Ok(Action::Dictate { profile, outputs })
+        }
+        "stop" => Ok(Action::Stop),
+        other => invalid(format!("Unknown action \"{}\"; use dictate or stop", other)),
+    }
+}
+
+/// `display,clipboard` and the like
+fn output_targets(value: &str) -> Result<Vec<OutputTarget>, AppError> {
+    let mut targets = Vec::new();
+    for name in value.split(',').map(str::trim) {
+        let target =
+            serde_json::from_value(serde_json::Value::String(name.to_string())).map_err(|_| {
+                AppError::InvalidInput(format!(
+                    "Unknown output \"{}\"; use display, clipboard or inject",
+                    name
+                ))
+            })?;
+        if !targets.contains(&target) {
+            targets.push(target);
+        }
+    }
+    Ok(targets)
+}
+
+async fn act(app: &AppHandle, action: Action) -> Result<(), AppError> {
+    let state = app.state::<SessionManager>().state();
+    match action {
+        Action::Dictate { profile, outputs } => {
+            // One session at a time, as for the hotkey
+            if state != SessionState::Idle {
+                return Err(AppError::SessionAlreadyActive(state));
+            }
+            if let Some(profile) = profile {
+                let handle = app.clone();
+                let switched_to = profile.clone();
+                let previous = blocking(move || {
+                    let previous = crate::config::active_profile(&handle);
+                    crate::profiles::activate(&handle, &profile)?;
+                    Ok(previous)
+                })
+                .await?;
+                if previous != switched_to {
+                    if let Ok(mut restore) = app.state::<DeepLinkState>().restore.lock() {
+                        // A link whose session never started still has the first profile
+                        let previous = restore.take().map_or(previous, |restore| restore.previous);
+                        *restore = Some(ProfileRestore {
+                            previous,
+                            switched_to,
+                        });
+                    }
+                }
+            }
+            if let Ok(mut pending) = app.state::<DeepLinkState>().outputs.lock() {
+                *pending = outputs.map(|outputs| (outputs, Instant::now()));
+            }
+            crate::hotkey::set_dictation(app, true);
+            #[cfg(desktop)]
+            if !main_window_visible(app) {
+                if let Err(e) = crate::overlay::show_caption_overlay(app.clone(), None).await {
+                    tracing::warn!("{}", e);
+                }
+            }
+            Ok(())
+        }
+        Action::Stop => {
+            if state == SessionState::Idle {
+                return Err(AppError::Stream("No session is running".to_string()));
+            }
+            crate::hotkey::set_dictation(app, false);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(desktop)]
+fn main_window_visible(app: &AppHandle) -> bool {
+    app.get_webview_window("main")
+        .and_then(|window| window.is_visible().ok())
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_str(url: &str) -> Result<Action, String> {
+        parse(&Url::parse(url).unwrap()).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn reads_dictate_and_stop_links() {
+        assert_eq!(
+            parse_str("subspace://dictate?profile=work&output=clipboard"),
+            Ok(Action::Dictate {
+                profile: Some("work".to_string()),
+                outputs: Some(vec![OutputTarget::Clipboard]),
+            })
+        );
+        assert_eq!(
+            parse_str("subspace://dictate/?output=display,%20inject,display"),
+            Ok(Action::Dictate {
+                profile: None,
+                outputs: Some(vec![OutputTarget::Display, OutputTarget::Inject]),
+            })
+        );
+        assert_eq!(
+            parse_str("subspace:dictate?profile=Personal%20Mac"),
+            Ok(Action::Dictate {
+                profile: Some("Personal Mac".to_string()),
+                outputs: None,
+            })
+        );
+        assert_eq!(parse_str("subspace://stop"), Ok(Action::Stop));
+    }
+
+    #[test]
+    fn rejects_anything_it_does_not_know() {
+        for (url, error) in [
+            ("subspace://record", "Unknown action \"record\""),
+            ("subspace://dictate?lang=en", "Unknown parameter \"lang\""),
+            (
+                "subspace://stop?profile=work",
+                "Unknown parameter \"profile\"",
+            ),
+            (
+                "subspace://dictate?output=speaker",
+                "Unknown output \"speaker\"",
+            ),
+            (
+                "subspace://dictate?profile=",
+                "\"profile\" must not be empty",
+            ),
+            (
+                "subspace://dictate?output=inject&output=clipboard",
+                "\"output\" is given more than once",
+            ),
+            ("subspace://dictate/now", "Unexpected path \"/now\""),
+            ("https://dictate", "Not a subspace:// link"),
+        ] {
+            let rejected = parse_str(url).unwrap_err();
+            assert!(rejected.starts_with(error), "{}: {}", url, rejected);
+        }
+    }
+}
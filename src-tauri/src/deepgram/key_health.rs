@@ -0,0 +1,310 @@
+// Background checks of the Deepgram key, so an expired trial or an empty balance shows up
+// as a banner before a session fails with a bare 401
+// Runs at launch, every `check_interval_hours`, and whenever the key (`key_watch` sees
+// profile switches too) or these settings change: validates the key, then reads its
+// expiry and the account's balance where the key's scopes allow. The latest result is
+// kept for `get_app_health`, and `key-health` is only emitted when the state changes; a
+// check that can't reach Deepgram changes nothing
+
+use std::mem::discriminant;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager, State};
+use tokio::sync::Notify;
+
+use super::management::{self, Balance, ValidationFailure};
+use crate::config::{self, KeyHealthSettings};
+use crate::error::AppError;
+use crate::state::{blocking, AppState};
+
+/// Event emitted with a `KeyHealthStatus` when the key's state changes
+pub const EVENT_KEY_HEALTH: &str = "key-health";
+
+const HOUR: Duration = Duration::from_secs(60 * 60);
+
+/// What the last check found, most urgent first
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum KeyHealth {
+    /// Deepgram rejected the key
+    Invalid,
+    /// The key expires within `expiry_warning_days`
+    Expiring {
+        days_left: i64,
+    },
+    /// The account's USD balance is below `quota_low_usd`
+    QuotaLow {
+        remaining_usd: f64,
+    },
+    Ok,
+}
+
+/// Payload of `key-health`, and the latest one is in `get_app_health`
+#[derive(Debug, Clone, Serialize)]
+pub struct KeyHealthStatus {
+    #[serde(flatten)]
+    pub health: KeyHealth,
+    /// Milliseconds since the Unix epoch, when Deepgram says the key expires
+    pub expires_at_ms: Option<i64>,
+    /// None when the key can't read the balance
+    pub remaining_usd: Option<f64>,
+    pub checked_at_ms: u64,
+}
+
+/// Managed state holding the latest result
+#[derive(Default)]
+pub struct KeyHealthState {
+    latest: Mutex<Option<KeyHealthStatus>>,
+    wake: Notify,
+}
+
+impl KeyHealthState {
+    /// The latest result; None before the first check, or while there's no key
+    pub fn latest(&self) -> Option<KeyHealthStatus> {
+        self.latest.lock().ok().and_then(|latest| latest.clone())
+    }
+}
+
+/// Start checking in the background
+pub fn init(app: &AppHandle) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        loop {
+            if let Err(e) = check(&app).await {
+                tracing::info!("Couldn't check the Deepgram key: {}", e);
+            }
+            let handle = app.clone();
+            let hours = blocking(move || Ok(config::load(&handle)?.key_health))
+                .await
+                .unwrap_or_default()
+                .check_interval_hours;
+            let state = app.state::<KeyHealthState>();
+            if hours == 0 {
+                state.wake.notified().await;
+            } else {
+                tokio::select! {
+                    _ = tokio::time::sleep(HOUR * hours) => {}
+                    _ = state.wake.notified() => {}
+                }
+            }
+        }
+    });
+}
+
+/// Check again now, e.g. because the key changed
+pub fn recheck(app: &AppHandle) {
+    app.state::<KeyHealthState>().wake.notify_one();
+}
+
+/// Check the key and record the result; Ok(None) when there's no key to check
+async fn check(app: &AppHandle) -> Result<Option<KeyHealthStatus>, AppError> {
+    let handle = app.clone();
+    let (key, settings) = blocking(move || {
+        let key = if crate::engine::mock::enabled(&handle) {
+            None
+        } else {
+            crate::deepgram_api_key(&handle).ok()
+        };
+        Ok((key, config::load(&handle)?.key_health))
+    })
+    .await?;
+    let state = app.state::<KeyHealthState>();
+    let Some(key) = key else {
+        if let Ok(mut latest) = state.latest.lock() {
+            *latest = None;
+        }
+        return Ok(None);
+    };
+
+    let app_state = app.state::<AppState>();
+    let validation = management::check_key(&app_state.http(), &app_state.route(), &key).await;
+    let (expires_at, remaining_usd) = match validation.failure {
+        None => (
+            allowed(management::key_expiry(app, &key).await)?.flatten(),
+            allowed(management::balance(app, &key, true).await)?
+                .and_then(|balance| usd(&balance.balances)),
+        ),
+        Some(ValidationFailure::InvalidKey) => (None, None),
+        Some(_) => {
+            return Err(AppError::Network {
+                status: None,
+                message: validation.reason.unwrap_or_default(),
+            })
+        }
+    };
+    let now_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default();
+    let status = KeyHealthStatus {
+        health: assess(
+            validation.valid,
+            expires_at,
+            remaining_usd,
+            &settings,
+            Utc::now(),
+        ),
+        expires_at_ms: expires_at.map(|at| at.timestamp_millis()),
+        remaining_usd,
+        checked_at_ms: now_ms,
+    };
+
+    let changed = {
+        let mut latest = state
+            .latest
+            .lock()
+            .map_err(|_| AppError::poisoned("Key health"))?;
+        let changed = changes_state(latest.as_ref().map(|latest| &latest.health), &status.health);
+        *latest = Some(status.clone());
+        changed
+    };
+    if changed {
+        let _ = app.emit(EVENT_KEY_HEALTH, &status);
+    }
+    Ok(Some(status))
+}
+
+/// A part of the check the key's scopes don't allow is skipped, not a failure
+fn allowed<T>(result: Result<T, AppError>) -> Result<Option<T>, AppError> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(AppError::KeyLacksPermission(_)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// The account's balance in dollars, if it has one in dollars
+fn usd(balances: &[Balance]) -> Option<f64> {
+    balances
+        .iter()
+        .filter(|balance| balance.units.eq_ignore_ascii_case("usd"))
+        .map(|balance| balance.amount)
+        .reduce(|total, amount| total + amount)
+}
+
+fn assess(
+    valid: bool,
+    expires_at: Option<DateTime<Utc>>,
+    remaining_usd: Option<f64>,
+    settings: &KeyHealthSettings,
+    now: DateTime<Utc>,
+) -> KeyHealth {
+    if !valid {
+        return KeyHealth::Invalid;
+    }
+    let days_left = expires_at.map(|at| (at - now).num_days());
+    if let Some(days_left) =
+        days_left.filter(|&days| days <= i64::from(settings.expiry_warning_days))
+    {
+        return KeyHealth::Expiring { days_left };
+    }
+    match remaining_usd {
+        Some(remaining_usd) if remaining_usd < settings.quota_low_usd => {
+            KeyHealth::QuotaLow { remaining_usd }
+        }
+        _ => KeyHealth::Ok,
+    }
+}
+
+/// Whether going from `previous` to `next` is news; the days left or the balance moving
+/// within the same state isn't
+fn changes_state(previous: Option<&KeyHealth>, next: &KeyHealth) -> bool {
+    previous.is_none_or(|previous| discriminant(previous) != discriminant(next))
+}
+
+/// Command to check the key now instead of waiting for the next scheduled check
+#[tauri::command]
+pub async fn check_key_health(app: AppHandle) -> Result<Option<KeyHealthStatus>, AppError> {
+    check(&app).await
+}
+
+#[tauri::command]
+pub async fn get_key_health_settings(app: AppHandle) -> Result<KeyHealthSettings, AppError> {
+    blocking(move || Ok(config::load(&app)?.key_health)).await
+}
+
+/// Command to change the key health settings; checks again with them straight away
+#[tauri::command]
+pub async fn set_key_health_settings(
+    app: AppHandle,
+    state: State<'_, KeyHealthState>,
+    settings: KeyHealthSettings,
+) -> Result<KeyHealthSettings, AppError> {
+    settings.validate()?;
+    let settings = blocking(move || {
+        config::update(&app, |config| {
+            config.key_health = KeyHealthSettings {
+                extra: std::mem::take(&mut config.key_health.extra),
+                ..settings
+            };
+            Ok(config.key_health.clone())
+        })
+    })
+    .await?;
+    state.wake.notify_one();
+    Ok(settings)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn the_most_urgent_problem_wins() {
+        let settings = KeyHealthSettings::default();
+        let now = Utc.with_ymd_and_hms(2024, 3, 20, 9, 0, 0).unwrap();
+        let in_days =
+            |days: i64| Some(now + chrono::Duration::days(days) + chrono::Duration::hours(1));
+
+        assert_eq!(
+            assess(false, in_days(3), Some(1.0), &settings, now),
+            KeyHealth::Invalid
+        );
+        assert_eq!(
+            assess(true, in_days(3), Some(1.0), &settings, now),
+            KeyHealth::Expiring { days_left: 3 }
+        );
+        assert_eq!(
+            assess(true, in_days(14), None, &settings, now),
+            KeyHealth::Expiring { days_left: 14 }
+        );
+        assert_eq!(
+            assess(true, in_days(15), Some(4.5), &settings, now),
+            KeyHealth::QuotaLow { remaining_usd: 4.5 }
+        );
+        assert_eq!(assess(true, None, Some(5.0), &settings, now), KeyHealth::Ok);
+        let off = KeyHealthSettings {
+            quota_low_usd: 0.0,
+            ..KeyHealthSettings::default()
+        };
+        assert_eq!(assess(true, None, Some(0.0), &off, now), KeyHealth::Ok);
+    }
+
+    #[test]
+    fn only_a_new_state_is_news_and_only_dollars_count() {
+        let low = |remaining_usd| KeyHealth::QuotaLow { remaining_usd };
+        assert!(changes_state(None, &KeyHealth::Ok));
+        assert!(changes_state(Some(&KeyHealth::Ok), &low(2.0)));
+        assert!(!changes_state(Some(&low(2.0)), &low(1.5)));
+        assert!(!changes_state(
+            Some(&KeyHealth::Expiring { days_left: 5 }),
+            &KeyHealth::Expiring { days_left: 4 }
+        ));
+        assert!(changes_state(Some(&low(1.5)), &KeyHealth::Invalid));
+
+        let balance = |amount, units: &str| Balance {
+            amount,
+            units: units.to_string(),
+        };
+        assert_eq!(
+            usd(&[balance(3.0, "USD"), balance(12.0, "hour")]),
+            Some(3.0)
+        );
+        assert_eq!(usd(&[balance(12.0, "hour")]), None);
+    }
+}
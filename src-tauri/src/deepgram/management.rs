@@ -0,0 +1,675 @@
+// Deepgram REST (management) API calls
+// Used to check keys, to mint short-lived keys for the webview, and to read the account's
+// usage and balance. Those two are cached for `ACCOUNT_CACHE_TTL` per key, along with the
+// key's project; a key without the scopes for them gets `KeyLacksPermission`
+
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, State};
+use tokio::sync::Mutex;
+
+use super::network::Route;
+use crate::error::AppError;
+use crate::net::limiter::{self, Attempt};
+use crate::state::{blocking, AppState};
+/// Validation never hangs longer than this
+const VALIDATION_TIMEOUT: Duration = Duration::from_secs(5);
+/// Reachability checks give up quickly; they only gate what the UI shows
+const REACHABILITY_TIMEOUT: Duration = Duration::from_secs(3);
+/// Timeout for management calls such as minting ephemeral keys
+const MANAGEMENT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A cached ephemeral key is re-minted once it is this close to expiring
+const TOKEN_REFRESH_MARGIN: Duration = Duration::from_secs(30);
+/// Allowed range for ephemeral key lifetimes
+const MIN_TOKEN_TTL_SECONDS: u32 = 60;
+const MAX_TOKEN_TTL_SECONDS: u32 = 24 * 60 * 60;
+/// How long fetched usage and balances are served before asking Deepgram again
+const ACCOUNT_CACHE_TTL: Duration = Duration::from_secs(10 * 60);
+/// Comment on the keys `get_ephemeral_token` mints, which tells them apart in key lists
+const EPHEMERAL_KEY_COMMENT: &str = "SubSpace Voice ephemeral key";
+
+/// Why a key failed validation, so the UI can pick the right message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ValidationFailure {
+    NotConfigured,
+    InvalidKey,
+    Network,
+    Timeout,
+    UnexpectedResponse,
+}
+
+/// Result of `validate_api_key`
+#[derive(Debug, Clone, Serialize)]
+pub struct KeyValidation {
+    pub valid: bool,
+    pub reason: Option<String>,
+    pub failure: Option<ValidationFailure>,
+    pub project_name: Option<String>,
+}
+
+impl KeyValidation {
+    fn valid(project_name: Option<String>) -> Self {
+        Self {
+            valid: true,
+            reason: None,
+            failure: None,
+            project_name,
+        }
+    }
+
+    fn invalid(failure: ValidationFailure, reason: impl Into<String>) -> Self {
+        Self {
+            valid: false,
+            reason: Some(reason.into()),
+            failure: Some(failure),
+            project_name: None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ProjectsResponse {
+    #[serde(default)]
+    projects: Vec<Project>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Project {
+    project_id: String,
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct BalancesResponse {
+    #[serde(default)]
+    balances: Vec<Balance>,
+}
+
+/// One of a project's balances
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Balance {
+    pub amount: f64,
+    /// Currency or unit of `amount`, e.g. "usd" or "hour"
+    pub units: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct KeysResponse {
+    #[serde(default)]
+    api_keys: Vec<ProjectKey>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProjectKey {
+    api_key: KeyDetails,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct KeyDetails {
+    comment: Option<String>,
+    /// RFC 3339; missing for keys that never expire
+    expiration_date: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct UsageResponse {
+    #[serde(default)]
+    results: Vec<UsageResult>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct UsageResult {
+    hours: f64,
+    total_hours: f64,
+    requests: u64,
+}
+
+/// Result of `get_deepgram_balance`
+#[derive(Debug, Clone, Serialize)]
+pub struct DeepgramBalance {
+    pub project_id: String,
+    pub project_name: String,
+    pub balances: Vec<Balance>,
+    /// When Deepgram was asked, as milliseconds since the Unix epoch
+    pub fetched_at_ms: u64,
+}
+
+/// Result of `get_deepgram_account_usage`: this calendar month (UTC) so far, as Deepgram
+/// bills it
+#[derive(Debug, Clone, Serialize)]
+pub struct DeepgramAccountUsage {
+    pub project_id: String,
+    pub project_name: String,
+    /// First and last day covered, inclusive, as YYYY-MM-DD
+    pub start: String,
+    pub end: String,
+    /// Hours of audio transcribed
+    pub hours: f64,
+    /// Hours billed, which counts each channel of multichannel audio
+    pub total_hours: f64,
+    pub requests: u64,
+    pub fetched_at_ms: u64,
+}
+
+struct Cached<T> {
+    /// Key it was fetched with; another key (profile) fetches again
+    api_key: String,
+    value: T,
+    fetched_at: Instant,
+}
+
+impl<T: Clone> Cached<T> {
+    fn fresh(cached: &Option<Self>, api_key: &str) -> Option<T> {
+        cached
+            .as_ref()
+            .filter(|cached| {
+                cached.api_key == api_key && cached.fetched_at.elapsed() < ACCOUNT_CACHE_TTL
+            })
+            .map(|cached| cached.value.clone())
+    }
+}
+
+#[derive(Default)]
+struct AccountCache {
+    project: Option<Cached<Project>>,
+    usage: Option<Cached<DeepgramAccountUsage>>,
+    balance: Option<Cached<DeepgramBalance>>,
+}
+
+/// Managed state caching the account's project, usage and balance
+#[derive(Default)]
+pub struct AccountState {
+    cached: Mutex<AccountCache>,
+}
+
+#[derive(Debug, Serialize)]
+struct CreateKeyRequest<'a> {
+    comment: &'a str,
+    scopes: &'a [&'a str],
+    time_to_live_in_seconds: u32,
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateKeyResponse {
+    key: String,
+}
+
+/// Short-lived Deepgram key handed to the webview instead of the permanent one
+#[derive(Debug, Clone, Serialize)]
+pub struct EphemeralToken {
+    pub token: String,
+    /// Expiry as milliseconds since the Unix epoch
+    pub expires_at_ms: u64,
+}
+
+struct CachedToken {
+    /// Permanent key the token was minted from; a different key (profile) needs a new token
+    api_key: String,
+    token: EphemeralToken,
+    expires_at: Instant,
+}
+
+/// Managed state caching the most recently minted ephemeral key
+#[derive(Default)]
+pub struct EphemeralTokenState {
+    cached: Mutex<Option<CachedToken>>,
+}
+
+/// Check a key against Deepgram by listing its projects
+/// Uses the configured key when `key` is None
+#[tauri::command]
+pub async fn validate_api_key(app: AppHandle, key: Option<String>) -> KeyValidation {
+    let key = match key {
+        Some(key) => match crate::key_store::normalize_key(&key) {
+            Ok(key) => {
+                crate::logging::register_secret(&key);
+                key
+            }
+            Err(e) => return KeyValidation::invalid(ValidationFailure::InvalidKey, e.to_string()),
+        },
+        None => match blocking({
+            let app = app.clone();
+            move || crate::deepgram_api_key(&app)
+        })
+        .await
+        {
+            Ok(key) => key,
+            Err(e) => {
+                return KeyValidation::invalid(ValidationFailure::NotConfigured, e.to_string())
+            }
+        },
+    };
+
+    let state = app.state::<AppState>();
+    check_key(&state.http(), &state.route(), &key).await
+}
+
+/// Mint (or reuse) a short-lived key for the webview's WebSocket handshake
+/// The permanent key stays in the backend; a cached key is reused until it is
+/// within 30 seconds of expiry
+#[tauri::command]
+pub async fn get_ephemeral_token(
+    app: AppHandle,
+    state: State<'_, EphemeralTokenState>,
+    ttl_seconds: u32,
+) -> Result<EphemeralToken, AppError> {
+    if !(MIN_TOKEN_TTL_SECONDS..=MAX_TOKEN_TTL_SECONDS).contains(&ttl_seconds) {
+        return Err(AppError::InvalidInput(format!(
+            "ttl_seconds must be between {} and {}",
+            MIN_TOKEN_TTL_SECONDS, MAX_TOKEN_TTL_SECONDS
+        )));
+    }
+
+    let key = blocking({
+        let app = app.clone();
+        move || crate::deepgram_api_key(&app)
+    })
+    .await?;
+    let mut cached = state.cached.lock().await;
+    if let Some(existing) = cached.as_ref() {
+        if existing.api_key == key && existing.expires_at > Instant::now() + TOKEN_REFRESH_MARGIN {
+            return Ok(existing.token.clone());
+        }
+    }
+
+    let app_state = app.state::<AppState>();
+    let token =
+        mint_ephemeral_key(&app_state.http(), &app_state.route(), &key, ttl_seconds).await?;
+
+    let ttl = Duration::from_secs(ttl_seconds.into());
+    let expires_at_ms = (SystemTime::now() + ttl)
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default();
+    let token = EphemeralToken {
+        token,
+        expires_at_ms,
+    };
+    *cached = Some(CachedToken {
+        api_key: key,
+        token: token.clone(),
+        expires_at: Instant::now() + ttl,
+    });
+    Ok(token)
+}
+
+/// Command to read the account's balances, from cache when fetched in the last 10
+/// minutes unless `force_refresh`
+#[tauri::command]
+pub async fn get_deepgram_balance(
+    app: AppHandle,
+    force_refresh: Option<bool>,
+) -> Result<DeepgramBalance, AppError> {
+    let key = stored_key(&app).await?;
+    balance(&app, &key, force_refresh.unwrap_or(false)).await
+}
+
+/// The balances of `key`'s account, as `get_deepgram_balance` reads them
+pub async fn balance(
+    app: &AppHandle,
+    key: &str,
+    force_refresh: bool,
+) -> Result<DeepgramBalance, AppError> {
+    let state = app.state::<AccountState>();
+    let mut cached = state.cached.lock().await;
+    if !force_refresh {
+        if let Some(balance) = Cached::fresh(&cached.balance, key) {
+            return Ok(balance);
+        }
+    }
+    let app_state = app.state::<AppState>();
+    let (client, route) = (&app_state.http(), &app_state.route());
+    let project = cached_project(&mut cached, client, route, key).await?;
+    let response: BalancesResponse = send_management(
+        client
+            .get(route.api_url(&format!("projects/{}/balances", project.project_id)))
+            .timeout(MANAGEMENT_TIMEOUT)
+            .header("Authorization", format!("Token {}", key)),
+    )
+    .await?;
+    let balance = DeepgramBalance {
+        project_id: project.project_id,
+        project_name: project.name,
+        balances: response.balances,
+        fetched_at_ms: now_ms(),
+    };
+    cached.balance = Some(Cached {
+        api_key: key.to_string(),
+        value: balance.clone(),
+        fetched_at: Instant::now(),
+    });
+    Ok(balance)
+}
+
+/// Command to read this month's usage as Deepgram records it, from cache when fetched in
+/// the last 10 minutes unless `force_refresh`
+#[tauri::command]
+pub async fn get_deepgram_account_usage(
+    app: AppHandle,
+    state: State<'_, AccountState>,
+    force_refresh: Option<bool>,
+) -> Result<DeepgramAccountUsage, AppError> {
+    let key = stored_key(&app).await?;
+    let mut cached = state.cached.lock().await;
+    if !force_refresh.unwrap_or(false) {
+        if let Some(usage) = Cached::fresh(&cached.usage, &key) {
+            return Ok(usage);
+        }
+    }
+    let app_state = app.state::<AppState>();
+    let (client, route) = (&app_state.http(), &app_state.route());
+    let project = cached_project(&mut cached, client, route, &key).await?;
+    let (start, end) = month_so_far(Utc::now().date_naive());
+    let response: UsageResponse = send_management(
+        client
+            .get(route.api_url(&format!(
+                "projects/{}/usage?start={}&end={}",
+                project.project_id, start, end
+            )))
+            .timeout(MANAGEMENT_TIMEOUT)
+            .header("Authorization", format!("Token {}", key)),
+    )
+    .await?;
+    let total = sum_usage(&response.results);
+    let usage = DeepgramAccountUsage {
+        project_id: project.project_id,
+        project_name: project.name,
+        start: start.to_string(),
+        end: end.to_string(),
+        hours: total.hours,
+        total_hours: total.total_hours,
+        requests: total.requests,
+        fetched_at_ms: now_ms(),
+    };
+    cached.usage = Some(Cached {
+        api_key: key,
+        value: usage.clone(),
+        fetched_at: Instant::now(),
+    });
+    Ok(usage)
+}
+
+/// When `key` expires, if it does and Deepgram lets it list the project's keys
+/// The list doesn't say which entry is the key asking, so this only answers when the
+/// project has one key besides the short-lived ones this app mints
+pub async fn key_expiry(app: &AppHandle, key: &str) -> Result<Option<DateTime<Utc>>, AppError> {
+    let app_state = app.state::<AppState>();
+    let (client, route) = (&app_state.http(), &app_state.route());
+    let project = {
+        let state = app.state::<AccountState>();
+        let mut cached = state.cached.lock().await;
+        cached_project(&mut cached, client, route, key).await?
+    };
+    let response: KeysResponse = send_management(
+        client
+            .get(route.api_url(&format!("projects/{}/keys", project.project_id)))
+            .timeout(MANAGEMENT_TIMEOUT)
+            .header("Authorization", format!("Token {}", key)),
+    )
+    .await?;
+    Ok(sole_expiry(&response.api_keys))
+}
+
+/// Expiry of the only key that isn't one of ours
+fn sole_expiry(keys: &[ProjectKey]) -> Option<DateTime<Utc>> {
+    let mut keys = keys
+        .iter()
+        .map(|key| &key.api_key)
+        .filter(|key| key.comment.as_deref() != Some(EPHEMERAL_KEY_COMMENT));
+    match (keys.next(), keys.next()) {
+        (Some(key), None) => key
+            .expiration_date
+            .as_deref()
+            .and_then(|date| DateTime::parse_from_rfc3339(date).ok())
+            .map(|date| date.with_timezone(&Utc)),
+        _ => None,
+    }
+}
+
+async fn stored_key(app: &AppHandle) -> Result<String, AppError> {
+    let app = app.clone();
+    blocking(move || crate::deepgram_api_key(&app)).await
+}
+
+/// The key's project, discovered once per key
+async fn cached_project(
+    cached: &mut AccountCache,
+    client: &reqwest::Client,
+    route: &Route,
+    key: &str,
+) -> Result<Project, AppError> {
+    if let Some(project) = cached
+        .project
+        .as_ref()
+        .filter(|project| project.api_key == key)
+    {
+        return Ok(project.value.clone());
+    }
+    let project = first_project(client, route, key).await?;
+    cached.project = Some(Cached {
+        api_key: key.to_string(),
+        value: project.clone(),
+        fetched_at: Instant::now(),
+    });
+    Ok(project)
+}
+
+/// The first of the key's projects, which is the one its usage is billed to
+async fn first_project(
+    client: &reqwest::Client,
+    route: &Route,
+    key: &str,
+) -> Result<Project, AppError> {
+    let projects: ProjectsResponse = send_management(
+        client
+            .get(route.api_url("projects"))
+            .timeout(MANAGEMENT_TIMEOUT)
+            .header("Authorization", format!("Token {}", key)),
+    )
+    .await?;
+    projects
+        .projects
+        .into_iter()
+        .next()
+        .ok_or_else(|| AppError::NotFound("This API key has no Deepgram project".to_string()))
+}
+
+/// From the first of `today`'s month through `today`
+fn month_so_far(today: NaiveDate) -> (NaiveDate, NaiveDate) {
+    (today.with_day(1).unwrap_or(today), today)
+}
+
+fn sum_usage(results: &[UsageResult]) -> UsageResult {
+    results
+        .iter()
+        .fold(UsageResult::default(), |total, result| UsageResult {
+            hours: total.hours + result.hours,
+            total_hours: total.total_hours + result.total_hours,
+            requests: total.requests + result.requests,
+        })
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Create a usage-only member key with a time-to-live on the key's first project
+async fn mint_ephemeral_key(
+    client: &reqwest::Client,
+    route: &Route,
+    key: &str,
+    ttl_seconds: u32,
+) -> Result<String, AppError> {
+    let project = first_project(client, route, key).await?;
+
+    let created: CreateKeyResponse = send_management(
+        client
+            .post(route.api_url(&format!("projects/{}/keys", project.project_id)))
+            .timeout(MANAGEMENT_TIMEOUT)
+            .header("Authorization", format!("Token {}", key))
+            .json(&CreateKeyRequest {
+                comment: EPHEMERAL_KEY_COMMENT,
+                scopes: &["usage:write"],
+                time_to_live_in_seconds: ttl_seconds,
+            }),
+    )
+    .await?;
+    crate::logging::register_secret(&created.key);
+    Ok(created.key)
+}
+
+/// Send a management request and decode the JSON body
+async fn send_management<T: for<'de> Deserialize<'de>>(
+    request: reqwest::RequestBuilder,
+) -> Result<T, AppError> {
+    let response = limiter::send(request).await?;
+
+    let status = response.status().as_u16();
+    match status {
+        200..=299 => response.json::<T>().await.map_err(|e| AppError::Network {
+            status: Some(status),
+            message: format!("Unexpected response from Deepgram: {}", e),
+        }),
+        401 => Err(AppError::KeyInvalid(
+            "Deepgram rejected the API key".to_string(),
+        )),
+        // The key works but its role or scopes don't cover this call
+        403 => Err(AppError::KeyLacksPermission(
+            "The API key doesn't have permission for this. Create a key with the Member role, or one scoped for usage and billing, in the Deepgram console."
+                .to_string(),
+        )),
+        _ => Err(AppError::Network {
+            status: Some(status),
+            message: format!("Deepgram management API returned HTTP {}", status),
+        }),
+    }
+}
+
+/// Whether Deepgram answers at all; any HTTP response counts
+pub async fn is_reachable(client: &reqwest::Client, route: &Route) -> bool {
+    limiter::send(client.head(route.api_base()).timeout(REACHABILITY_TIMEOUT))
+        .await
+        .is_ok()
+}
+
+pub async fn check_key(client: &reqwest::Client, route: &Route, key: &str) -> KeyValidation {
+    if let Err(e) = limiter::admit(Attempt::Request) {
+        return KeyValidation::invalid(ValidationFailure::Network, e.to_string());
+    }
+    let result = client
+        .get(route.api_url("projects"))
+        .timeout(VALIDATION_TIMEOUT)
+        .header("Authorization", format!("Token {}", key))
+        .send()
+        .await;
+    limiter::record_response(&result);
+    let response = match result {
+        Ok(response) => response,
+        Err(e) if e.is_timeout() => {
+            return KeyValidation::invalid(
+                ValidationFailure::Timeout,
+                "Deepgram did not respond in time. Please check your internet connection.",
+            )
+        }
+        Err(e) => {
+            return KeyValidation::invalid(
+                ValidationFailure::Network,
+                format!(
+                    "Could not reach Deepgram. Please check your internet connection. ({})",
+                    e
+                ),
+            )
+        }
+    };
+
+    match response.status().as_u16() {
+        200..=299 => {
+            let project_name = response
+                .json::<ProjectsResponse>()
+                .await
+                .ok()
+                .and_then(|body| body.projects.into_iter().next())
+                .map(|project| project.name);
+            KeyValidation::valid(project_name)
+        }
+        401 | 403 => KeyValidation::invalid(
+            ValidationFailure::InvalidKey,
+            "Deepgram rejected this API key. It may be expired, revoked, or mistyped.",
+        ),
+        status => KeyValidation::invalid(
+            ValidationFailure::UnexpectedResponse,
+            format!("Unexpected response from Deepgram (HTTP {})", status),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn usage_covers_the_month_so_far_and_adds_up() {
+        let today = NaiveDate::from_ymd_opt(2024, 3, 17).unwrap();
+        let (start, end) = month_so_far(today);
+        assert_eq!(
+            (start.to_string(), end.to_string()),
+            ("2024-03-01".to_string(), "2024-03-17".to_string())
+        );
+
+        let response: UsageResponse = serde_json::from_value(serde_json::json!({
+            "start": "2024-03-01",
+            "end": "2024-03-17",
+            "resolution": { "units": "day", "amount": 1 },
+            "results": [
+                { "start": "2024-03-01", "end": "2024-03-01", "hours": 1.5, "total_hours": 3.0, "requests": 4 },
+                { "start": "2024-03-02", "end": "2024-03-02", "hours": 0.25, "total_hours": 0.25, "requests": 1 }
+            ]
+        }))
+        .unwrap();
+        let total = sum_usage(&response.results);
+        assert_eq!(
+            (total.hours, total.total_hours, total.requests),
+            (1.75, 3.25, 5)
+        );
+    }
+
+    #[test]
+    fn expiry_is_read_only_when_one_key_is_left_after_ours() {
+        let response = |keys: serde_json::Value| -> KeysResponse {
+            serde_json::from_value(serde_json::json!({ "api_keys": keys })).unwrap()
+        };
+        let trial = serde_json::json!({
+            "member": { "member_id": "m1", "email": "me@example.com" },
+            "api_key": {
+                "api_key_id": "k1",
+                "comment": "Trial",
+                "scopes": ["member"],
+                "created": "2024-03-01T00:00:00Z",
+                "expiration_date": "2024-04-01T12:00:00+02:00"
+            }
+        });
+        let ephemeral = serde_json::json!({
+            "member": { "member_id": "m1", "email": "me@example.com" },
+            "api_key": {
+                "api_key_id": "k2",
+                "comment": EPHEMERAL_KEY_COMMENT,
+                "expiration_date": "2024-03-02T00:00:00Z"
+            }
+        });
+        let other = serde_json::json!({ "api_key": { "api_key_id": "k3", "comment": "CI" } });
+
+        let sole = sole_expiry(&response(serde_json::json!([ephemeral, trial])).api_keys);
+        assert_eq!(sole.unwrap().to_rfc3339(), "2024-04-01T10:00:00+00:00");
+        assert!(sole_expiry(&response(serde_json::json!([trial, other])).api_keys).is_none());
+        assert!(sole_expiry(&response(serde_json::json!([other])).api_keys).is_none());
+    }
+}
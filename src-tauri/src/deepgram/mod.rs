@@ -0,0 +1,754 @@
+// Deepgram API integration
+// Everything that needs the API key talks to Deepgram from here, never from the frontend
+
+pub mod key_health;
+pub mod management;
+pub mod network;
+pub mod prerecorded;
+pub mod proxy;
+pub mod retry;
+pub mod stats;
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter};
+
+use self::network::Route;
+use crate::audio::{MULTICHANNEL_CHANNELS, TARGET_CHANNELS, TARGET_ENCODING, TARGET_SAMPLE_RATE};
+use crate::config::{TranscriptionSettings, VocabularyTerm};
+use crate::error::AppError;
+use crate::storage::{Segment, Word};
+
+/// Event emitted when Deepgram reports the language being spoken
+pub const EVENT_LANGUAGE_DETECTED: &str = "language-detected";
+/// Event emitted when a streaming connection opens, and again once Deepgram says which
+/// model serves it, see `SessionMetadata`
+pub const EVENT_SESSION_METADATA: &str = "session-metadata";
+/// `language` setting value that asks Deepgram to detect the language
+pub const AUTO_LANGUAGE: &str = "auto";
+
+/// Payload of the `language-detected` event
+#[derive(Debug, Clone, Serialize)]
+pub struct LanguageDetected {
+    pub language: String,
+    /// Only batch transcription reports a confidence
+    pub confidence: Option<f64>,
+}
+
+pub fn emit_language_detected(app: &AppHandle, language: &str, confidence: Option<f64>) {
+    let _ = app.emit(
+        EVENT_LANGUAGE_DETECTED,
+        LanguageDetected {
+            language: language.to_string(),
+            confidence,
+        },
+    );
+}
+
+/// Payload of `session-metadata`: which Deepgram request serves the session, and with
+/// what model, for when support asks. Fields Deepgram hasn't sent yet are None
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct SessionMetadata {
+    pub request_id: Option<String>,
+    /// Deepgram's name for the model, e.g. "2-general-nova"
+    pub model_name: Option<String>,
+    pub model_version: Option<String>,
+    /// What the `model` setting calls it, e.g. "nova-2"
+    pub model_arch: Option<String>,
+    pub channels: Option<u32>,
+}
+
+impl SessionMetadata {
+    /// Name and version together, as sessions store it: "2-general-nova 2024-01-09.29447"
+    pub fn model(&self) -> Option<String> {
+        match (&self.model_name, &self.model_version) {
+            (Some(name), Some(version)) => Some(format!("{} {}", name, version)),
+            (None, Some(version)) => Some(version.clone()),
+            (Some(name), None) => Some(name.clone()),
+            (None, None) => None,
+        }
+    }
+
+    /// Fill in what's still missing from `other`; returns whether anything was
+    pub fn merge(&mut self, other: SessionMetadata) -> bool {
+        let before = self.clone();
+        self.request_id = self.request_id.take().or(other.request_id);
+        self.model_name = self.model_name.take().or(other.model_name);
+        self.model_version = self.model_version.take().or(other.model_version);
+        self.model_arch = self.model_arch.take().or(other.model_arch);
+        self.channels = self.channels.or(other.channels);
+        *self != before
+    }
+}
+
+pub fn emit_session_metadata(app: &AppHandle, metadata: &SessionMetadata) {
+    let _ = app.emit(EVENT_SESSION_METADATA, metadata);
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ModelInfo {
+    pub name: Option<String>,
+    pub version: Option<String>,
+    pub arch: Option<String>,
+}
+
+/// The `Metadata` message a stream ends with, and the `metadata` of a batch response
+#[derive(Debug, Default, Deserialize)]
+pub struct RequestMetadata {
+    pub request_id: Option<String>,
+    /// Model ids, keys into `model_info`
+    #[serde(default)]
+    pub models: Vec<String>,
+    #[serde(default)]
+    pub model_info: BTreeMap<String, ModelInfo>,
+    pub channels: Option<u32>,
+    /// Seconds of audio
+    #[serde(default)]
+    pub duration: f64,
+}
+
+impl RequestMetadata {
+    pub fn to_session_metadata(&self) -> SessionMetadata {
+        let model = self
+            .models
+            .first()
+            .and_then(|id| self.model_info.get(id))
+            .or_else(|| self.model_info.values().next())
+            .cloned()
+            .unwrap_or_default();
+        SessionMetadata {
+            request_id: self.request_id.clone(),
+            model_name: model.name,
+            model_version: model.version,
+            model_arch: model.arch,
+            channels: self.channels,
+        }
+    }
+}
+
+/// `metadata` of a streaming result
+#[derive(Debug, Default, Deserialize)]
+pub struct ResultMetadata {
+    pub request_id: Option<String>,
+    #[serde(default)]
+    pub model_info: ModelInfo,
+}
+
+impl ResultMetadata {
+    pub fn to_session_metadata(&self) -> SessionMetadata {
+        SessionMetadata {
+            request_id: self.request_id.clone(),
+            model_name: self.model_info.name.clone(),
+            model_version: self.model_info.version.clone(),
+            model_arch: self.model_info.arch.clone(),
+            channels: None,
+        }
+    }
+}
+
+/// Trim a per-session language override, rejecting an empty one
+pub fn language_override(language: Option<String>) -> Result<Option<String>, AppError> {
+    match language.map(|language| language.trim().to_string()) {
+        Some(language) if language.is_empty() => Err(AppError::InvalidInput(
+            "Language must not be empty".to_string(),
+        )),
+        language => Ok(language),
+    }
+}
+
+/// Build the streaming URL from the user's transcription settings
+/// Audio is always 16 kHz linear16, matching what the recorder produces: mono, or stereo
+/// with `multichannel`
+pub fn listen_url(
+    route: &Route,
+    settings: &TranscriptionSettings,
+    vocabulary: &[VocabularyTerm],
+) -> String {
+    let mut url = route.listen_url();
+    {
+        let mut query = url.query_pairs_mut();
+        append_options(&mut query, settings, vocabulary);
+        // Streaming can't detect the language up front; `multi` follows code-switching instead
+        if settings.language == AUTO_LANGUAGE {
+            query.append_pair("language", "multi");
+        } else {
+            query.append_pair("language", &settings.language);
+        }
+        if settings.interim_results {
+            query.append_pair("interim_results", "true");
+        }
+        query
+            .append_pair("encoding", TARGET_ENCODING)
+            .append_pair("sample_rate", &TARGET_SAMPLE_RATE.to_string())
+            .append_pair("channels", &live_channels(settings).to_string());
+        match settings.endpointing_ms {
+            Some(0) => {
+                query.append_pair("endpointing", "false");
+            }
+            Some(endpointing_ms) => {
+                query.append_pair("endpointing", &endpointing_ms.to_string());
+            }
+            None => {}
+        }
+        if let Some(utterance_end_ms) = settings.utterance_end_ms {
+            query.append_pair("utterance_end_ms", &utterance_end_ms.to_string());
+        }
+    }
+    url.into()
+}
+
+/// Channels of the audio a live session sends
+pub fn live_channels(settings: &TranscriptionSettings) -> u16 {
+    if settings.multichannel {
+        MULTICHANNEL_CHANNELS
+    } else {
+        TARGET_CHANNELS
+    }
+}
+
+/// Build the batch URL; Deepgram detects the encoding from the file itself
+/// `multichannel` must only be on for files with more than one channel
+pub fn prerecorded_url(
+    route: &Route,
+    settings: &TranscriptionSettings,
+    vocabulary: &[VocabularyTerm],
+) -> String {
+    let mut url = route.prerecorded_url();
+    {
+        let mut query = url.query_pairs_mut();
+        append_options(&mut query, settings, vocabulary);
+        if settings.language == AUTO_LANGUAGE {
+            query.append_pair("detect_language", "true");
+        } else {
+            query.append_pair("language", &settings.language);
+        }
+    }
+    url.into()
+}
+
+/// Options shared by the streaming and batch endpoints (language is set per endpoint)
+/// Toggles are only sent when on, since Deepgram defaults them off; keeps the URL short
+fn append_options(
+    query: &mut url::form_urlencoded::Serializer<'_, url::UrlQuery<'_>>,
+    settings: &TranscriptionSettings,
+    vocabulary: &[VocabularyTerm],
+) {
+    query.append_pair("model", &settings.model);
+    for (param, enabled) in settings.formatting() {
+        if enabled {
+            query.append_pair(param, "true");
+        }
+    }
+    if settings.profanity_filter {
+        query.append_pair("profanity_filter", "true");
+    }
+    if settings.diarize {
+        query.append_pair("diarize", "true");
+    }
+    if settings.multichannel {
+        query.append_pair("multichannel", "true");
+    }
+    for category in &settings.redact {
+        query.append_pair("redact", category);
+    }
+
+    // Nova-3 replaced keyword boosting with keyterm prompting, which takes no intensifier
+    let keyterms = settings.model.starts_with("nova-3");
+    for entry in vocabulary {
+        match entry.boost {
+            _ if keyterms => query.append_pair("keyterm", &entry.term),
+            Some(boost) => query.append_pair("keywords", &format!("{}:{}", entry.term, boost)),
+            None => query.append_pair("keywords", &entry.term),
+        };
+    }
+}
+
+/// Messages received on the streaming socket, tagged by their `type` field
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type")]
+pub enum StreamMessage {
+    Results(ResultsMessage),
+    /// Sent with `utterance_end_ms` once the gap after the last word is long enough
+    UtteranceEnd(UtteranceEndMessage),
+    /// Sent as the stream closes
+    Metadata(RequestMetadata),
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UtteranceEndMessage {
+    /// End of the utterance's last word, in seconds from the start of the stream
+    #[serde(default)]
+    pub last_word_end: f64,
+}
+
+/// A transcription result for one chunk of audio
+#[derive(Debug, Deserialize)]
+pub struct ResultsMessage {
+    #[serde(default)]
+    pub start: f64,
+    #[serde(default)]
+    pub duration: f64,
+    #[serde(default)]
+    pub is_final: bool,
+    #[serde(default)]
+    pub speech_final: bool,
+    pub channel: Channel,
+    /// `[channel, channels]` of the audio the result is for
+    #[serde(default)]
+    pub channel_index: Vec<u32>,
+    /// The request and the model that served it
+    #[serde(default)]
+    pub metadata: Option<ResultMetadata>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Channel {
+    pub alternatives: Vec<Alternative>,
+    /// Batch results with `detect_language`
+    pub detected_language: Option<String>,
+    pub language_confidence: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Alternative {
+    pub transcript: String,
+    #[serde(default)]
+    pub words: Vec<WordTiming>,
+    /// Streaming results with `language=multi`, most prominent first
+    #[serde(default)]
+    pub languages: Vec<String>,
+}
+
+/// Timing for one recognized word, in seconds from the start of the stream
+#[derive(Debug, Deserialize)]
+pub struct WordTiming {
+    pub word: String,
+    pub start: f64,
+    pub end: f64,
+    #[serde(default)]
+    pub confidence: f64,
+    /// Present when smart_format or punctuate is on
+    pub punctuated_word: Option<String>,
+    /// Present when diarize is on; numbered from 0
+    #[serde(default)]
+    pub speaker: Option<u32>,
+}
+
+impl WordTiming {
+    pub fn to_word(&self) -> Word {
+        Word {
+            text: self
+                .punctuated_word
+                .clone()
+                .unwrap_or_else(|| self.word.clone()),
+            start_ms: seconds_to_ms(self.start),
+            end_ms: seconds_to_ms(self.end),
+            confidence: self.confidence,
+        }
+    }
+}
+
+/// Payload of the transcript events pushed to the frontend
+#[derive(Debug, Clone, Serialize)]
+pub struct TranscriptEvent {
+    pub transcript: String,
+    pub start: f64,
+    pub duration: f64,
+    pub speech_final: bool,
+    /// Present when diarization is on
+    pub speaker: Option<u32>,
+    /// Word timings of final transcripts; empty for interim ones
+    pub words: Vec<TranscriptWord>,
+    /// Final text as recognized, before dictation commands and replacements;
+    /// None for results that don't go through them, like interim ones
+    pub raw_transcript: Option<String>,
+}
+
+/// One word of a final transcript event, timed in seconds like the event itself
+#[derive(Debug, Clone, Serialize)]
+pub struct TranscriptWord {
+    /// Punctuated form when Deepgram sent one
+    pub word: String,
+    pub start: f64,
+    pub end: f64,
+    pub confidence: f64,
+    /// Confidence is below the `confidence_threshold` setting
+    pub low_confidence: bool,
+}
+
+impl TranscriptWord {
+    /// Convert stored words for an event, flagging those below `threshold`
+    pub fn from_words(words: &[Word], threshold: f64) -> Vec<Self> {
+        words
+            .iter()
+            .map(|word| Self {
+                word: word.text.clone(),
+                start: word.start_ms as f64 / 1000.0,
+                end: word.end_ms as f64 / 1000.0,
+                confidence: word.confidence,
+                low_confidence: word.confidence < threshold,
+            })
+            .collect()
+    }
+}
+
+impl ResultsMessage {
+    /// Convert to the frontend event payload, using the top alternative
+    /// Interim results carry the first word's speaker; Deepgram may still change it
+    pub fn to_event(&self) -> Option<TranscriptEvent> {
+        let alternative = self.channel.alternatives.first()?;
+        Some(TranscriptEvent {
+            transcript: alternative.transcript.clone(),
+            start: self.start,
+            duration: self.duration,
+            speech_final: self.speech_final,
+            speaker: alternative.words.first().and_then(|word| word.speaker),
+            words: Vec::new(),
+            raw_transcript: None,
+        })
+    }
+
+    /// Covered audio as start and end in milliseconds
+    pub fn span_ms(&self) -> (i64, i64) {
+        (
+            seconds_to_ms(self.start),
+            seconds_to_ms(self.start + self.duration),
+        )
+    }
+
+    /// Move the result and its words `seconds` later, e.g. past earlier connections
+    pub fn shift(&mut self, seconds: f64) {
+        self.start += seconds;
+        for alternative in &mut self.channel.alternatives {
+            for word in &mut alternative.words {
+                word.start += seconds;
+                word.end += seconds;
+            }
+        }
+    }
+
+    /// The audio channel of a multichannel result; None when there's only one
+    pub fn audio_channel(&self) -> Option<u32> {
+        match self.channel_index[..] {
+            [channel, channels, ..] if channels > 1 => Some(channel),
+            _ => None,
+        }
+    }
+
+    /// Most prominent language of a `language=multi` result
+    pub fn detected_language(&self) -> Option<&str> {
+        let alternative = self.channel.alternatives.first()?;
+        alternative.languages.first().map(String::as_str)
+    }
+
+    /// Convert a final result into history segments, one per speaker turn;
+    /// empty for silent results
+    pub fn to_segments(&self) -> Vec<Segment> {
+        let Some(alternative) = self.channel.alternatives.first() else {
+            return Vec::new();
+        };
+        split_by_speaker(
+            &alternative.transcript,
+            seconds_to_ms(self.start),
+            seconds_to_ms(self.start + self.duration),
+            &alternative.words,
+        )
+    }
+}
+
+/// Build segments spanning `start_ms..end_ms`, splitting wherever the speaker changes
+/// Without speaker labels this is a single segment holding the whole transcript
+pub fn split_by_speaker(
+    transcript: &str,
+    start_ms: i64,
+    end_ms: i64,
+    words: &[WordTiming],
+) -> Vec<Segment> {
+    let text = transcript.trim();
+    if text.is_empty() {
+        return Vec::new();
+    }
+    let whole = |speaker, words: Vec<Word>| Segment {
+        start_ms,
+        end_ms,
+        text: text.to_string(),
+        words: (!words.is_empty()).then_some(words),
+        speaker,
+        translated: None,
+        channel: None,
+        speaker_name: None,
+    };
+    let speakers: Vec<Option<u32>> = words.iter().map(|word| word.speaker).collect();
+    let words: Vec<Word> = words.iter().map(WordTiming::to_word).collect();
+    if speakers.windows(2).all(|pair| pair[0] == pair[1]) {
+        return vec![whole(speakers.first().copied().flatten(), words)];
+    }
+
+    let mut segments: Vec<Segment> = Vec::new();
+    for (word, speaker) in words.into_iter().zip(speakers) {
+        match segments.last_mut() {
+            Some(segment) if segment.speaker == speaker => {
+                segment.text.push(' ');
+                segment.text.push_str(&word.text);
+                segment.end_ms = word.end_ms;
+                segment.words.get_or_insert_with(Vec::new).push(word);
+            }
+            _ => segments.push(Segment {
+                start_ms: word.start_ms,
+                end_ms: word.end_ms,
+                text: word.text.clone(),
+                words: Some(vec![word]),
+                speaker,
+                translated: None,
+                channel: None,
+                speaker_name: None,
+            }),
+        }
+    }
+    // The turns together cover the same span as the result did
+    if let Some(first) = segments.first_mut() {
+        first.start_ms = first.start_ms.min(start_ms);
+    }
+    if let Some(last) = segments.last_mut() {
+        last.end_ms = last.end_ms.max(end_ms);
+    }
+    segments
+}
+
+/// Mark segments as said on `channel`, by the speaker named for it in `settings`
+pub fn label_channel(segments: &mut [Segment], channel: u32, settings: &TranscriptionSettings) {
+    let name = settings.channel_name(channel);
+    for segment in segments {
+        segment.channel = Some(channel);
+        segment.speaker_name = Some(name.clone());
+    }
+}
+
+fn seconds_to_ms(seconds: f64) -> i64 {
+    (seconds.max(0.0) * 1000.0).round() as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn word(text: &str, start: f64, speaker: Option<u32>) -> WordTiming {
+        WordTiming {
+            word: text.to_lowercase(),
+            start,
+            end: start + 0.4,
+            confidence: 0.9,
+            punctuated_word: Some(text.to_string()),
+            speaker,
+        }
+    }
+
+    #[test]
+    fn auto_language_uses_multi_when_streaming_and_detection_for_files() {
+        let settings = TranscriptionSettings {
+            language: AUTO_LANGUAGE.to_string(),
+            ..TranscriptionSettings::default()
+        };
+        let live = listen_url(&Route::default(), &settings, &[]);
+        assert!(live.contains("language=multi"), "{}", live);
+        assert!(!live.contains("detect_language"), "{}", live);
+
+        let batch = prerecorded_url(&Route::default(), &settings, &[]);
+        assert!(batch.contains("detect_language=true"), "{}", batch);
+        assert!(!batch.contains("language=auto"), "{}", batch);
+    }
+
+    #[test]
+    fn multichannel_sends_stereo_and_labels_results_by_channel() {
+        let settings = TranscriptionSettings {
+            multichannel: true,
+            channel_names: vec!["Me".to_string(), " ".to_string()],
+            ..TranscriptionSettings::default()
+        };
+        let live = listen_url(&Route::default(), &settings, &[]);
+        assert!(live.contains("channels=2"), "{}", live);
+        assert!(live.contains("multichannel=true"), "{}", live);
+        let mono = listen_url(&Route::default(), &TranscriptionSettings::default(), &[]);
+        assert!(mono.contains("channels=1") && !mono.contains("multichannel"));
+
+        let message = r#"{"type":"Results","start":1.0,"duration":1.0,"is_final":true,
+            "channel_index":[1,2],"channel":{"alternatives":[{"transcript":"Hi there"}]}}"#;
+        let Ok(StreamMessage::Results(results)) = serde_json::from_str(message) else {
+            panic!("not a result");
+        };
+        assert_eq!(results.audio_channel(), Some(1));
+        let mut segments = results.to_segments();
+        label_channel(&mut segments, 1, &settings);
+        assert_eq!(segments[0].channel, Some(1));
+        assert_eq!(segments[0].speaker_name.as_deref(), Some("Channel 2"));
+        assert_eq!(settings.channel_name(0), "Me");
+    }
+
+    #[test]
+    fn metadata_names_the_request_and_model() {
+        let result = r#"{"type":"Results","channel":{"alternatives":[{"transcript":"Hi"}]},
+            "metadata":{"request_id":"5c2f-req","model_uuid":"1dbd",
+            "model_info":{"name":"2-general-nova","version":"2024-01-09.29447","arch":"nova-2"}}}"#;
+        let Ok(StreamMessage::Results(results)) = serde_json::from_str(result) else {
+            panic!("not a result");
+        };
+        let mut metadata = SessionMetadata {
+            request_id: Some("5c2f-req".to_string()),
+            channels: Some(1),
+            ..SessionMetadata::default()
+        };
+        assert!(metadata.merge(results.metadata.unwrap().to_session_metadata()));
+        assert_eq!(
+            metadata.model().as_deref(),
+            Some("2-general-nova 2024-01-09.29447")
+        );
+        assert_eq!(metadata.model_arch.as_deref(), Some("nova-2"));
+
+        let closing = r#"{"type":"Metadata","request_id":"5c2f-req","channels":1,
+            "models":["1dbd"],"model_info":{"1dbd":{"name":"2-general-nova",
+            "version":"2024-01-09.29447","arch":"nova-2"}}}"#;
+        let Ok(StreamMessage::Metadata(closing)) = serde_json::from_str(closing) else {
+            panic!("not metadata");
+        };
+        assert!(!metadata.merge(closing.to_session_metadata()));
+        // A result without its metadata block still parses
+        let bare = r#"{"type":"Results","channel":{"alternatives":[]}}"#;
+        let Ok(StreamMessage::Results(bare)) = serde_json::from_str(bare) else {
+            panic!("not a result");
+        };
+        assert!(bare.metadata.is_none());
+    }
+
+    #[test]
+    fn only_toggles_that_are_on_reach_the_url() {
+        let settings = TranscriptionSettings {
+            numerals: true,
+            punctuate: false,
+            interim_results: false,
+            ..TranscriptionSettings::default()
+        };
+        for url in [
+            listen_url(&Route::default(), &settings, &[]),
+            prerecorded_url(&Route::default(), &settings, &[]),
+        ] {
+            assert!(url.contains("smart_format=true"), "{}", url);
+            assert!(url.contains("numerals=true"), "{}", url);
+            assert!(url.contains("utterances=true"), "{}", url);
+            for absent in [
+                "punctuate",
+                "paragraphs",
+                "filler_words",
+                "profanity_filter",
+            ] {
+                assert!(!url.contains(absent), "{} in {}", absent, url);
+            }
+            assert!(!url.contains("interim_results"), "{}", url);
+        }
+        let conflicting = TranscriptionSettings {
+            paragraphs: true,
+            punctuate: false,
+            ..TranscriptionSettings::default()
+        };
+        let error = conflicting.validate_formatting().unwrap_err().to_string();
+        assert!(error.contains("paragraphs requires punctuate"), "{}", error);
+    }
+
+    #[test]
+    fn endpointing_can_be_turned_off_and_utterance_end_needs_interim_results() {
+        let settings = TranscriptionSettings {
+            endpointing_ms: Some(0),
+            utterance_end_ms: Some(1500),
+            ..TranscriptionSettings::default()
+        };
+        settings.validate_endpointing().unwrap();
+        let url = listen_url(&Route::default(), &settings, &[]);
+        assert!(url.contains("endpointing=false"), "{}", url);
+        assert!(url.contains("utterance_end_ms=1500"), "{}", url);
+        let batch = prerecorded_url(&Route::default(), &settings, &[]);
+        assert!(!batch.contains("utterance_end_ms"), "{}", batch);
+
+        for invalid in [
+            TranscriptionSettings {
+                endpointing_ms: Some(5),
+                ..TranscriptionSettings::default()
+            },
+            TranscriptionSettings {
+                utterance_end_ms: Some(500),
+                ..TranscriptionSettings::default()
+            },
+            TranscriptionSettings {
+                interim_results: false,
+                ..settings
+            },
+        ] {
+            assert!(invalid.validate_endpointing().is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn one_segment_without_speaker_changes() {
+        let words = [word("Hello", 0.1, None), word("there.", 0.6, None)];
+        let segments = split_by_speaker(" Hello there. ", 0, 1200, &words);
+        assert_eq!(segments.len(), 1);
+        assert_eq!(segments[0].text, "Hello there.");
+        assert_eq!((segments[0].start_ms, segments[0].end_ms), (0, 1200));
+        assert_eq!(segments[0].speaker, None);
+    }
+
+    #[test]
+    fn splits_at_speaker_changes() {
+        let words = [
+            word("Hi.", 0.2, Some(0)),
+            word("Hey,", 0.8, Some(1)),
+            word("you.", 1.3, Some(1)),
+            word("Bye.", 2.0, Some(0)),
+        ];
+        let segments = split_by_speaker("Hi. Hey, you. Bye.", 0, 3000, &words);
+        let turns: Vec<_> = segments
+            .iter()
+            .map(|segment| {
+                (
+                    segment.speaker,
+                    segment.text.as_str(),
+                    segment.start_ms,
+                    segment.end_ms,
+                )
+            })
+            .collect();
+        assert_eq!(
+            turns,
+            [
+                (Some(0), "Hi.", 0, 600),
+                (Some(1), "Hey, you.", 800, 1700),
+                (Some(0), "Bye.", 2000, 3000),
+            ]
+        );
+        assert_eq!(segments[1].words.as_ref().map(Vec::len), Some(2));
+    }
+
+    #[test]
+    fn event_words_are_in_seconds_and_flag_low_confidence() {
+        let words = [
+            word("Sure.", 0.5, None).to_word(),
+            WordTiming {
+                confidence: 0.3,
+                ..word("maybe", 1.0, None)
+            }
+            .to_word(),
+        ];
+        let flagged: Vec<_> = TranscriptWord::from_words(&words, 0.6)
+            .into_iter()
+            .map(|word| (word.word, word.start, word.low_confidence))
+            .collect();
+        assert_eq!(
+            flagged,
+            [
+                ("Sure.".to_string(), 0.5, false),
+                ("maybe".to_string(), 1.0, true)
+            ]
+        );
+    }
+}
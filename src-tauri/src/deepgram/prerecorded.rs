@@ -0,0 +1,708 @@
+// Transcription of audio files through Deepgram's pre-recorded (batch) API
+// The file is streamed from disk as the request body, so large files never sit in memory
+// Transient failures re-send the whole file under `retry::RetryPolicy`: Deepgram has no
+// resumable uploads, nor a way to poll for a result without giving it a callback URL
+// A multi-channel WAV is transcribed per channel with `multichannel` on, and its segments
+// are labeled with the channel's speaker name
+
+use std::io::Read;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager};
+use tokio_util::io::ReaderStream;
+
+use super::network;
+use super::retry::{self, RetryPolicy};
+use super::{label_channel, seconds_to_ms, split_by_speaker, Channel, RequestMetadata, WordTiming};
+use crate::config::{FileTranscriptionSettings, Provider, TranscriptionSettings, VocabularyTerm};
+use crate::error::AppError;
+use crate::net::limiter::{self, Attempt};
+use crate::postprocess;
+use crate::postprocess::dictation_commands::{self, DictationCommands};
+use crate::postprocess::replacements::ReplacementState;
+use crate::postprocess::translation::{self, Translator};
+use crate::state::{blocking, AppState};
+use crate::storage::{NewSession, Segment, Session};
+
+/// Receives upload progress; called whenever the uploaded percentage, the phase or the
+/// attempt changes
+pub type ProgressFn = Arc<dyn Fn(Progress) + Send + Sync>;
+
+/// Event reporting upload progress and phase changes
+pub const EVENT_FILE_TRANSCRIPTION_PROGRESS: &str = "file-transcription-progress";
+
+/// Read size for the streamed request body
+const UPLOAD_CHUNK_SIZE: usize = 64 * 1024;
+/// Slowest upload an attempt is given time for, in bytes per second
+const MIN_UPLOAD_RATE: u64 = 32 * 1024;
+/// Time an attempt is given on top of the upload for Deepgram to transcribe the file
+const PROCESSING_ALLOWANCE: Duration = Duration::from_secs(20 * 60);
+/// How much of a WAV file is searched for its format chunk
+const WAV_HEADER_SIZE: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TranscriptionPhase {
+    Uploading,
+    /// Upload finished, waiting for Deepgram's result
+    Transcribing,
+    /// An attempt failed; waiting to upload the file again
+    Retrying,
+    Done,
+}
+
+/// One progress report
+#[derive(Debug, Clone, Copy)]
+pub struct Progress {
+    pub phase: TranscriptionPhase,
+    pub bytes_sent: u64,
+    pub total_bytes: u64,
+    /// The current attempt, from 1
+    pub attempt: u32,
+    pub max_attempts: u32,
+}
+
+/// Payload of the `file-transcription-progress` event
+#[derive(Debug, Clone, Serialize)]
+pub struct FileTranscriptionProgress {
+    pub path: String,
+    pub phase: TranscriptionPhase,
+    pub bytes_sent: u64,
+    pub total_bytes: u64,
+    pub attempt: u32,
+    pub max_attempts: u32,
+}
+
+#[derive(Debug, Deserialize)]
+struct PrerecordedResponse {
+    #[serde(default)]
+    metadata: RequestMetadata,
+    results: PrerecordedResults,
+}
+
+#[derive(Debug, Deserialize)]
+struct PrerecordedResults {
+    channels: Vec<Channel>,
+    #[serde(default)]
+    utterances: Vec<Utterance>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Utterance {
+    start: f64,
+    end: f64,
+    /// Channel index with `multichannel`
+    #[serde(default)]
+    channel: u32,
+    transcript: String,
+    #[serde(default)]
+    words: Vec<WordTiming>,
+}
+
+/// Error body returned by Deepgram on 4xx responses
+#[derive(Debug, Deserialize)]
+struct ApiError {
+    err_msg: Option<String>,
+}
+
+/// Transcribe an audio file and save the result to history
+/// Uses the saved transcription settings when `settings` is None, and `language`
+/// (when given) instead of their language
+#[tauri::command]
+pub async fn transcribe_file(
+    app: AppHandle,
+    path: String,
+    settings: Option<TranscriptionSettings>,
+    language: Option<String>,
+) -> Result<Session, AppError> {
+    let progress = progress_emitter(app.clone(), path.clone());
+    transcribe(&app, path, settings, language, progress).await
+}
+
+/// Transcribe an audio file and save the result to history, reporting progress as it goes
+/// Transient failures are retried under the file transcription settings' retry policy
+/// Dropping the future aborts the upload
+pub async fn transcribe(
+    app: &AppHandle,
+    path: String,
+    settings: Option<TranscriptionSettings>,
+    language: Option<String>,
+    progress: ProgressFn,
+) -> Result<Session, AppError> {
+    let language = super::language_override(language)?;
+    if let Some(settings) = &settings {
+        crate::postprocess::redaction::validate(&settings.redact)?;
+        settings.validate_formatting()?;
+    }
+    let PreparedRequest {
+        settings,
+        vocabulary,
+        total_bytes,
+        content_type,
+        api_key,
+        policy,
+        translator,
+    } = PreparedRequest::load(app.clone(), path.clone(), settings).await?;
+    let settings = match language {
+        Some(language) => TranscriptionSettings {
+            language,
+            ..settings
+        },
+        None => settings,
+    };
+
+    let started_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0);
+    let state = app.state::<AppState>();
+    let upload = Upload {
+        // Waiting for the result of a long file easily outlasts the shared client's read
+        // timeout; the request's own timeout, scaled to the file, bounds it instead
+        client: network::upload_client(&state.route())?,
+        url: super::prerecorded_url(&state.route(), &settings, &vocabulary),
+        path: &path,
+        api_key: &api_key,
+        content_type,
+        total_bytes,
+        max_attempts: policy.max_attempts,
+        progress: &progress,
+    };
+
+    let mut attempt = 1;
+    let response = loop {
+        let failure = match upload.send(attempt).await {
+            Ok(response) => break response,
+            Err(failure) => failure,
+        };
+        if !failure.retryable || attempt >= policy.max_attempts {
+            if attempt > 1 {
+                tracing::warn!("Giving up on {} after {} attempts", path, attempt);
+            }
+            return Err(failure.error);
+        }
+        let delay = policy.delay(attempt, failure.retry_after);
+        tracing::warn!(
+            "Attempt {} of {} to transcribe {} failed, retrying in {:.1} s: {}",
+            attempt,
+            policy.max_attempts,
+            path,
+            delay.as_secs_f64(),
+            failure.error
+        );
+        attempt += 1;
+        progress(Progress {
+            phase: TranscriptionPhase::Retrying,
+            bytes_sent: 0,
+            total_bytes,
+            attempt,
+            max_attempts: policy.max_attempts,
+        });
+        tokio::time::sleep(delay).await;
+    };
+
+    let detected = response.results.channels.first().and_then(|channel| {
+        Some((
+            channel.detected_language.clone()?,
+            channel.language_confidence,
+        ))
+    });
+    if let Some((language, confidence)) = &detected {
+        super::emit_language_detected(app, language, *confidence);
+    }
+    let detected_language = detected.map(|(language, _)| language);
+
+    let replacements = app.state::<ReplacementState>().current();
+    // Dictation commands follow the spoken language when it was detected
+    let commands_language = detected_language.as_deref().unwrap_or(&settings.language);
+    let mut commands = DictationCommands::load(app, commands_language);
+    let mut segments: Vec<Segment> = segments_from(&response, &settings)
+        .into_iter()
+        .map(|segment| {
+            let words = segment.words.as_deref().unwrap_or_default();
+            let text =
+                postprocess::rewrite_final(&mut commands, &replacements, &segment.text, words);
+            Segment { text, ..segment }
+        })
+        // Nothing but fillers, which the disfluency pass took out
+        .filter(|segment| !segment.text.is_empty())
+        .collect();
+    let text = dictation_commands::join(segments.iter().map(|segment| segment.text.as_str()));
+    if let Some(translator) = &translator {
+        translator.translate_segments(&mut segments).await;
+    }
+    let translated_text = translation::translated_text(&segments);
+    let translation_language = translator
+        .filter(|_| translated_text.is_some())
+        .map(|translator| translator.target_language().to_string());
+    let metadata = response.metadata.to_session_metadata();
+    let session = NewSession {
+        started_at,
+        duration_ms: seconds_to_ms(response.metadata.duration),
+        model: settings.model,
+        language: settings.language,
+        detected_language,
+        text,
+        // The source file belongs to the user; deleting the session must not delete it
+        audio_path: None,
+        segments,
+        recovered: false,
+        imported: false,
+        audio_source: None,
+        translated_text,
+        translation_language,
+        provider: Some(Provider::Deepgram.as_str().to_string()),
+        model_version: metadata.model(),
+        disfluencies_removed: commands.disfluencies_removed(),
+        request_id: metadata.request_id,
+    };
+
+    let storage = Arc::clone(&app.state::<AppState>().storage);
+    let session = blocking(move || {
+        crate::usage::record(&storage, started_at, &session.model, session.duration_ms);
+        let id = storage.insert_session(&session)?;
+        storage
+            .get_session(id)?
+            .ok_or_else(|| AppError::Internal("Saved session disappeared".to_string()))
+    })
+    .await?;
+    progress(Progress {
+        phase: TranscriptionPhase::Done,
+        bytes_sent: total_bytes,
+        total_bytes,
+        attempt,
+        max_attempts: policy.max_attempts,
+    });
+    Ok(session)
+}
+
+/// One file upload, sent once per attempt
+struct Upload<'a> {
+    client: reqwest::Client,
+    url: String,
+    path: &'a str,
+    api_key: &'a str,
+    content_type: &'static str,
+    total_bytes: u64,
+    max_attempts: u32,
+    progress: &'a ProgressFn,
+}
+
+/// Why an attempt failed, and whether another one might not
+struct Failure {
+    error: AppError,
+    retryable: bool,
+    /// How long the server asked to wait before trying again
+    retry_after: Option<Duration>,
+}
+
+impl Failure {
+    fn fatal(error: AppError) -> Self {
+        Self {
+            error,
+            retryable: false,
+            retry_after: None,
+        }
+    }
+
+    /// Refused by `net::limiter`; worth another attempt once it says the wait is over
+    fn refused(error: AppError) -> Self {
+        let retry_after = match &error {
+            AppError::CircuitOpen { retry_after_secs }
+            | AppError::RateLimited { retry_after_secs } => {
+                Some(Duration::from_secs(*retry_after_secs))
+            }
+            _ => None,
+        };
+        Self {
+            error,
+            retryable: retry_after.is_some(),
+            retry_after,
+        }
+    }
+}
+
+impl Upload<'_> {
+    /// Stream the file from its start and read Deepgram's result
+    async fn send(&self, attempt: u32) -> Result<PrerecordedResponse, Failure> {
+        let file = tokio::fs::File::open(self.path).await.map_err(|e| {
+            Failure::fatal(AppError::Io(format!("Failed to open {}: {}", self.path, e)))
+        })?;
+        let sent = Arc::new(AtomicU64::new(0));
+        let body_sent = Arc::clone(&sent);
+        let progress = Arc::clone(self.progress);
+        let (total_bytes, max_attempts) = (self.total_bytes, self.max_attempts);
+        let mut last_percent = None;
+        let body_stream =
+            ReaderStream::with_capacity(file, UPLOAD_CHUNK_SIZE).inspect(move |chunk| {
+                if let Ok(chunk) = chunk {
+                    let bytes_sent = body_sent.fetch_add(chunk.len() as u64, Ordering::Relaxed)
+                        + chunk.len() as u64;
+                    let percent = bytes_sent * 100 / total_bytes;
+                    if last_percent == Some(percent) {
+                        return;
+                    }
+                    last_percent = Some(percent);
+                    let phase = if bytes_sent >= total_bytes {
+                        TranscriptionPhase::Transcribing
+                    } else {
+                        TranscriptionPhase::Uploading
+                    };
+                    progress(Progress {
+                        phase,
+                        bytes_sent,
+                        total_bytes,
+                        attempt,
+                        max_attempts,
+                    });
+                }
+            });
+
+        limiter::admit(Attempt::Request).map_err(Failure::refused)?;
+        let result = self
+            .client
+            .post(&self.url)
+            .header("Authorization", format!("Token {}", self.api_key))
+            .header("Content-Type", self.content_type)
+            .header("Content-Length", total_bytes)
+            .timeout(attempt_timeout(total_bytes))
+            .body(reqwest::Body::wrap_stream(body_stream))
+            .send()
+            .await;
+        limiter::record_response(&result);
+        let uploaded = || sent.load(Ordering::Relaxed) >= total_bytes;
+        let response = result.map_err(|e| request_failure(e, uploaded()))?;
+
+        let status = response.status().as_u16();
+        match status {
+            200..=299 => response
+                .json::<PrerecordedResponse>()
+                .await
+                .map_err(|e| request_failure(e, uploaded())),
+            401 | 403 => Err(Failure::fatal(AppError::KeyInvalid(
+                "Authentication failed. Please check your Deepgram API key.".to_string(),
+            ))),
+            _ => {
+                let retry_after = response
+                    .headers()
+                    .get(reqwest::header::RETRY_AFTER)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| retry::retry_after(value, SystemTime::now()));
+                let detail = response
+                    .json::<ApiError>()
+                    .await
+                    .ok()
+                    .and_then(|error| error.err_msg)
+                    .unwrap_or_else(|| format!("HTTP {}", status));
+                Err(Failure {
+                    error: AppError::Network {
+                        status: Some(status),
+                        message: format!("Deepgram could not transcribe the file: {}", detail),
+                    },
+                    retryable: retry::retryable_status(status),
+                    retry_after,
+                })
+            }
+        }
+    }
+}
+
+/// Classify an error sending the request or reading its response
+/// A timeout once the whole file went out means Deepgram was still transcribing it
+fn request_failure(error: reqwest::Error, uploaded: bool) -> Failure {
+    let retryable = retry::retryable_error(&error) && !error.is_decode();
+    let error = if error.is_timeout() && uploaded {
+        AppError::Network {
+            status: None,
+            message: "The file was uploaded, but Deepgram's response timed out".to_string(),
+        }
+    } else {
+        error.into()
+    };
+    Failure {
+        error,
+        retryable,
+        retry_after: None,
+    }
+}
+
+/// Overall time allowed for one attempt: the upload at a slow but workable rate, then the
+/// transcription, so a long file on a slow connection isn't cut off
+fn attempt_timeout(total_bytes: u64) -> Duration {
+    Duration::from_secs(total_bytes / MIN_UPLOAD_RATE) + PROCESSING_ALLOWANCE
+}
+
+/// Everything read from disk or the keychain before the upload starts
+struct PreparedRequest {
+    settings: TranscriptionSettings,
+    vocabulary: Vec<VocabularyTerm>,
+    total_bytes: u64,
+    content_type: &'static str,
+    api_key: String,
+    policy: RetryPolicy,
+    /// Present when translation is on
+    translator: Option<Translator>,
+}
+
+impl PreparedRequest {
+    /// Uses the saved transcription settings when `settings` is None
+    /// Fails if the file is empty, too large or not a supported format
+    async fn load(
+        app: AppHandle,
+        path: String,
+        settings: Option<TranscriptionSettings>,
+    ) -> Result<Self, AppError> {
+        blocking(move || {
+            let mut settings =
+                settings.unwrap_or_else(|| crate::config::transcription_settings(&app));
+            let file_settings = crate::config::load(&app)
+                .map(|config| config.file_transcription)
+                .unwrap_or_else(|_| FileTranscriptionSettings::default());
+            let max_size_mb = file_settings.max_file_size_mb;
+            let total_bytes = std::fs::metadata(&path)
+                .map_err(|e| AppError::Io(format!("Failed to read {}: {}", path, e)))?
+                .len();
+            if total_bytes == 0 {
+                return Err(AppError::InvalidInput(format!("{} is empty", path)));
+            }
+            if total_bytes > max_size_mb * 1024 * 1024 {
+                return Err(AppError::InvalidInput(format!(
+                    "{} is {} MB, larger than the {} MB limit",
+                    path,
+                    total_bytes.div_ceil(1024 * 1024),
+                    max_size_mb
+                )));
+            }
+            if settings.multichannel {
+                // Deepgram would transcribe a mono file as one channel anyway, but a
+                // compressed file's channels aren't known here
+                let channels = wav_channels(Path::new(&path));
+                settings.multichannel = channels.is_some_and(|channels| channels > 1);
+                if !settings.multichannel {
+                    tracing::info!(
+                        "{} isn't a multi-channel WAV file; transcribing it as one",
+                        path
+                    );
+                }
+            }
+            Ok(Self {
+                settings,
+                vocabulary: crate::config::vocabulary(&app),
+                total_bytes,
+                content_type: detect_content_type(Path::new(&path))?,
+                api_key: crate::deepgram_api_key(&app)?,
+                policy: RetryPolicy::from_settings(&file_settings),
+                // Translation fails open, down to a misconfigured provider
+                translator: Translator::load(&app).unwrap_or_else(|e| {
+                    tracing::warn!("Translation unavailable, keeping the original text: {}", e);
+                    None
+                }),
+            })
+        })
+        .await
+    }
+}
+
+/// Returns a callback that emits each progress report as a `file-transcription-progress` event
+fn progress_emitter(app: AppHandle, path: String) -> ProgressFn {
+    Arc::new(move |progress: Progress| {
+        let _ = app.emit(
+            EVENT_FILE_TRANSCRIPTION_PROGRESS,
+            FileTranscriptionProgress {
+                path: path.clone(),
+                phase: progress.phase,
+                bytes_sent: progress.bytes_sent,
+                total_bytes: progress.total_bytes,
+                attempt: progress.attempt,
+                max_attempts: progress.max_attempts,
+            },
+        );
+    })
+}
+
+/// Split the result into history segments, one per utterance when available
+/// and one per speaker turn when diarized
+/// With `multichannel`, segments are labeled by channel and interleaved by start time
+fn segments_from(response: &PrerecordedResponse, settings: &TranscriptionSettings) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    if !response.results.utterances.is_empty() {
+        for utterance in &response.results.utterances {
+            let mut split = split_by_speaker(
+                &utterance.transcript,
+                seconds_to_ms(utterance.start),
+                seconds_to_ms(utterance.end),
+                &utterance.words,
+            );
+            if settings.multichannel {
+                label_channel(&mut split, utterance.channel, settings);
+            }
+            segments.extend(split);
+        }
+    } else {
+        let channels = if settings.multichannel {
+            &response.results.channels[..]
+        } else {
+            &response.results.channels[..response.results.channels.len().min(1)]
+        };
+        for (index, channel) in channels.iter().enumerate() {
+            let Some(alternative) = channel.alternatives.first() else {
+                continue;
+            };
+            let mut split = split_by_speaker(
+                &alternative.transcript,
+                alternative
+                    .words
+                    .first()
+                    .map(|word| seconds_to_ms(word.start))
+                    .unwrap_or(0),
+                seconds_to_ms(response.metadata.duration),
+                &alternative.words,
+            );
+            if settings.multichannel {
+                label_channel(&mut split, index as u32, settings);
+            }
+            segments.extend(split);
+        }
+    }
+    if settings.multichannel {
+        // Stable, so a channel's own segments keep their order
+        segments.sort_by_key(|segment| segment.start_ms);
+    }
+    segments
+}
+
+/// Channel count from a WAV file's format chunk; None for anything else
+fn wav_channels(path: &Path) -> Option<u16> {
+    let mut header = Vec::with_capacity(WAV_HEADER_SIZE);
+    std::fs::File::open(path)
+        .ok()?
+        .take(WAV_HEADER_SIZE as u64)
+        .read_to_end(&mut header)
+        .ok()?;
+    wav_header_channels(&header)
+}
+
+/// Walk the RIFF chunks to "fmt ", whose channel count follows the format tag
+fn wav_header_channels(header: &[u8]) -> Option<u16> {
+    if !header.starts_with(b"RIFF") || header.get(8..12) != Some(b"WAVE") {
+        return None;
+    }
+    let mut offset = 12;
+    while let Some(chunk) = header.get(offset..offset + 8) {
+        let size = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]) as usize;
+        let body = offset + 8;
+        if &chunk[..4] == b"fmt " {
+            let channels = header.get(body + 2..body + 4)?;
+            return Some(u16::from_le_bytes([channels[0], channels[1]]));
+        }
+        // Chunks are padded to an even size
+        offset = body.checked_add(size)?.checked_add(size % 2)?;
+    }
+    None
+}
+
+/// Pick the Content-Type from the file's magic bytes, falling back to its extension
+fn detect_content_type(path: &Path) -> Result<&'static str, AppError> {
+    let mut header = [0u8; 12];
+    let read = std::fs::File::open(path)
+        .and_then(|mut file| file.read(&mut header))
+        .map_err(|e| AppError::Io(format!("Failed to read {}: {}", path.display(), e)))?;
+    let header = &header[..read];
+
+    let sniffed = if header.starts_with(b"RIFF") && header.get(8..12) == Some(b"WAVE") {
+        Some("audio/wav")
+    } else if header.starts_with(b"fLaC") {
+        Some("audio/flac")
+    } else if header.starts_with(b"OggS") {
+        Some("audio/ogg")
+    } else if header.starts_with(&[0x1A, 0x45, 0xDF, 0xA3]) {
+        Some("audio/webm")
+    } else if header.get(4..8) == Some(b"ftyp") {
+        Some("audio/mp4")
+    } else if header.starts_with(b"ID3")
+        || (header.len() >= 2 && header[0] == 0xFF && header[1] & 0xE0 == 0xE0)
+    {
+        Some("audio/mpeg")
+    } else {
+        None
+    };
+    if let Some(content_type) = sniffed {
+        return Ok(content_type);
+    }
+
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("wav") => Ok("audio/wav"),
+        Some("mp3") => Ok("audio/mpeg"),
+        Some("m4a" | "mp4") => Ok("audio/mp4"),
+        Some("aac") => Ok("audio/aac"),
+        Some("flac") => Ok("audio/flac"),
+        Some("ogg" | "opus") => Ok("audio/ogg"),
+        Some("webm") => Ok("audio/webm"),
+        _ => Err(AppError::Unsupported(format!(
+            "Unsupported audio file: {}",
+            path.display()
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn multichannel_files_are_detected_and_their_channels_interleaved() {
+        let mut wav = b"RIFF\0\0\0\0WAVE".to_vec();
+        // An odd-sized chunk before the format, padded to an even size
+        wav.extend(b"LIST\x03\0\0\0abc\0");
+        wav.extend(b"fmt \x10\0\0\0\x01\0\x02\0");
+        assert_eq!(wav_header_channels(&wav), Some(2));
+        assert_eq!(wav_header_channels(b"RIFF\0\0\0\0WAVEdata"), None);
+        assert_eq!(wav_header_channels(b"OggS"), None);
+
+        let response: PrerecordedResponse = serde_json::from_value(serde_json::json!({
+            "metadata": { "duration": 9.0 },
+            "results": { "channels": [
+                { "alternatives": [{ "transcript": "hello there", "words": [
+                    { "word": "hello", "start": 0.5, "end": 0.9 },
+                    { "word": "there", "start": 1.0, "end": 1.4 }
+                ] }] },
+                { "alternatives": [{ "transcript": "hi", "words": [
+                    { "word": "hi", "start": 0.2, "end": 0.4 }
+                ] }] }
+            ] }
+        }))
+        .unwrap();
+        let settings = TranscriptionSettings {
+            multichannel: true,
+            ..TranscriptionSettings::default()
+        };
+        let segments = segments_from(&response, &settings);
+        let labeled: Vec<_> = segments
+            .iter()
+            .map(|s| (s.text.as_str(), s.channel, s.speaker_name.as_deref()))
+            .collect();
+        assert_eq!(
+            labeled,
+            [
+                ("hi", Some(1), Some("Guest")),
+                ("hello there", Some(0), Some("Me"))
+            ]
+        );
+
+        let mono = segments_from(&response, &TranscriptionSettings::default());
+        assert_eq!(mono.len(), 1);
+        assert_eq!(
+            (mono[0].text.as_str(), mono[0].channel),
+            ("hello there", None)
+        );
+    }
+}
@@ -0,0 +1,1529 @@
+// Backend WebSocket proxy to Deepgram
+// The frontend streams PCM chunks over IPC and receives transcripts as events,
+// so the API key never leaves the Rust process
+
+use std::collections::{BTreeMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use futures_util::stream::{SplitSink, SplitStream};
+use futures_util::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use tauri::async_runtime::JoinHandle;
+use tauri::{AppHandle, Emitter, Manager, State};
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, Mutex};
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+use tokio_tungstenite::tungstenite::{Error as WsError, Message};
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+use super::stats::StreamMetrics;
+use super::{SessionMetadata, StreamMessage, TranscriptEvent, TranscriptWord};
+use crate::audio::capture::CaptureState;
+use crate::audio::recording::{self, SessionRecorder};
+use crate::audio::vad::{VadState, VoiceDetector, EVENT_VAD_STATE};
+use crate::audio::{BYTES_PER_MS, TARGET_CHANNELS};
+use crate::config::{BufferOverflow, EngineKind, Provider, SilenceAction, TranscriptionSettings};
+use crate::diagnostics::ConnectionLog;
+use crate::engine::manager::{emitter, SessionManager, Stop};
+use crate::engine::openai::OpenaiWhisper;
+use crate::engine::pause::SessionPause;
+use crate::engine::providers::{ProviderFallback, EVENT_PROVIDER_FALLBACK};
+use crate::engine::{self, watchdog, SessionState, TranscriptionEngine};
+use crate::error::AppError;
+use crate::net::limiter::{self, Attempt};
+use crate::postprocess;
+use crate::postprocess::dictation_commands::DictationCommands;
+use crate::postprocess::replacements::ReplacementState;
+use crate::recovery::{self, JournalHeader, SessionJournal};
+use crate::state::{blocking, AppState};
+use crate::storage::{NewSession, Segment};
+use crate::transcript::revoke::RevokeState;
+use crate::transcript::{FinishedTranscript, LiveTranscript};
+
+/// Event emitted for interim (not yet final) transcripts
+pub const EVENT_TRANSCRIPT_PARTIAL: &str = "transcript-partial";
+/// Event emitted for final transcripts
+pub const EVENT_TRANSCRIPT_FINAL: &str = "transcript-final";
+/// Event emitted when Deepgram reports the end of an utterance, for closing a paragraph
+pub const EVENT_UTTERANCE_END: &str = "utterance-end";
+/// Event emitted when the stream dies and can't be recovered
+pub const EVENT_STREAM_ERROR: &str = "stream-error";
+/// Event emitted on every connection state change
+pub const EVENT_CONNECTION_STATE: &str = "connection-state";
+
+/// Max audio chunks buffered in front of the socket (~8 s of the frontend's 4096-sample
+/// chunks, 3.2 s of 100 ms capture chunks); past half of it the stream counts as lagging
+const AUDIO_QUEUE_CAPACITY: usize = 32;
+/// How long `send_audio_chunk` waits for room in the queue before giving up
+const AUDIO_SEND_TIMEOUT: Duration = Duration::from_secs(2);
+/// How long `stop_session` waits for the socket task to save the session, on top of
+/// the finalize timeout
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(3);
+/// Upper bound on an engine finalizing; local engines may still be transcribing the tail
+const ENGINE_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// First reconnect delay; doubles on every failed attempt
+const RECONNECT_BASE_DELAY: Duration = Duration::from_millis(500);
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(30);
+/// Give up after this many failed reconnects in a row (a few minutes in total)
+const MAX_RECONNECT_ATTEMPTS: u32 = 10;
+const KEEP_ALIVE_MESSAGE: &str = r#"{"type":"KeepAlive"}"#;
+/// Asks Deepgram to finalize what it has heard so far
+const FINALIZE_MESSAGE: &str = r#"{"type":"Finalize"}"#;
+/// Asks Deepgram to send its last results and close the connection
+const CLOSE_STREAM_MESSAGE: &str = r#"{"type":"CloseStream"}"#;
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+type SocketWriter = SplitSink<Socket, Message>;
+type SocketReader = SplitStream<Socket>;
+
+/// `Closed` reason when the stream was stopped on purpose rather than lost
+pub const STOPPED_REASON: &str = "stopped";
+
+/// Payload of the `connection-state` event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum ConnectionState {
+    Connecting,
+    Open,
+    Reconnecting { attempt: u32 },
+    Closed { reason: String },
+}
+
+/// Payload of the `utterance-end` event
+#[derive(Debug, Clone, Serialize)]
+pub struct UtteranceEnd {
+    /// End of the last word, in seconds from the start of the session
+    pub last_word_end: f64,
+}
+
+enum ConnectError {
+    Unauthorized,
+    Tls(String),
+    Proxy(String),
+    Failed(String),
+    /// Refused locally by `net::limiter` without trying
+    Refused(AppError),
+}
+
+impl ConnectError {
+    fn message(&self) -> String {
+        match self {
+            Self::Unauthorized => {
+                "Authentication failed. Please check your Deepgram API key.".to_string()
+            }
+            Self::Tls(message) | Self::Proxy(message) | Self::Failed(message) => message.clone(),
+            Self::Refused(error) => error.to_string(),
+        }
+    }
+}
+
+impl From<ConnectError> for AppError {
+    fn from(error: ConnectError) -> Self {
+        match error {
+            ConnectError::Unauthorized => Self::KeyInvalid(error.message()),
+            ConnectError::Tls(message) => Self::Tls(message),
+            ConnectError::Proxy(message) => Self::Proxy(message),
+            ConnectError::Failed(message) => Self::network(message),
+            ConnectError::Refused(error) => error,
+        }
+    }
+}
+
+/// How one connection ended
+enum ConnectionEnd {
+    /// stop_session was called
+    Stopped,
+    /// The connection dropped; worth reconnecting
+    Lost(String),
+    /// Unrecoverable, e.g. the key was rejected on reconnect
+    Failed(String),
+}
+
+/// An open streaming connection
+struct Connection {
+    socket: Socket,
+    /// Deepgram's id for it, from the `dg-request-id` response header
+    request_id: Option<String>,
+}
+
+/// Handle to the running engine task
+struct ActiveStream {
+    /// Its number in `SessionManager`
+    session: u64,
+    audio_tx: mpsc::Sender<Vec<u8>>,
+    /// Channels of the audio the engine takes; other audio is converted on the way in
+    channels: u16,
+    task: JoinHandle<String>,
+    pause: Arc<SessionPause>,
+}
+
+/// The (at most one) active transcription stream, whatever its engine; part of `AppState`
+#[derive(Default)]
+pub struct StreamState {
+    active: Mutex<Option<ActiveStream>>,
+    metrics: Arc<StreamMetrics>,
+}
+
+impl StreamState {
+    /// Send and latency figures of the Deepgram session, shared with the capture
+    pub fn metrics(&self) -> Arc<StreamMetrics> {
+        Arc::clone(&self.metrics)
+    }
+
+    /// Stop the running session, or cancel the one starting, and wait for the engine to
+    /// finish. Returns the session's final transcript, or None if nothing was running or
+    /// it timed out
+    pub async fn shutdown(&self, app: &AppHandle) -> Option<String> {
+        self.stop(&app.state::<SessionManager>(), &emitter(app), None)
+            .await
+    }
+
+    /// `shutdown`, with the session's state changes going to `emit`
+    pub(crate) async fn shutdown_with(
+        &self,
+        sessions: &SessionManager,
+        emit: &impl Fn(SessionState),
+    ) -> Option<String> {
+        self.stop(sessions, emit, None).await
+    }
+
+    /// `shutdown`, but only while `pause` is the running session's, so whatever watches a
+    /// session can't stop the one after it
+    pub async fn shutdown_session(
+        &self,
+        app: &AppHandle,
+        pause: &Arc<SessionPause>,
+    ) -> Option<String> {
+        self.stop(&app.state::<SessionManager>(), &emitter(app), Some(pause))
+            .await
+    }
+
+    async fn stop(
+        &self,
+        sessions: &SessionManager,
+        emit: &impl Fn(SessionState),
+        pause: Option<&Arc<SessionPause>>,
+    ) -> Option<String> {
+        let (session, active) = {
+            let mut active = self.active.lock().await;
+            // A session that's still starting isn't in `active` yet, and stopping it
+            // cancels it; one whose engine has exited is already stopping by itself
+            let running = active
+                .as_ref()
+                .filter(|running| !running.audio_tx.is_closed());
+            let session = match (running, pause) {
+                (Some(running), Some(pause)) if Arc::ptr_eq(&running.pause, pause) => {
+                    Some(running.session)
+                }
+                (_, Some(_)) => return None,
+                (running, None) => running.map(|running| running.session),
+            };
+            match sessions.try_stop(emit, session) {
+                Stop::Running(session) => (session, active.take()),
+                Stop::Cancelled | Stop::Nothing => return None,
+            }
+        };
+        let text = match active {
+            Some(active) if active.session == session => Self::finish(active).await,
+            _ => None,
+        };
+        sessions.mark_ended(emit, session);
+        text
+    }
+
+    /// Make `stream` the running one, unless its session was stopped while it started;
+    /// then it's finished and false is returned
+    async fn activate(
+        &self,
+        sessions: &SessionManager,
+        emit: &impl Fn(SessionState),
+        stream: ActiveStream,
+    ) -> bool {
+        let session = stream.session;
+        // Held across `mark_started` so a stop either sees the stream or cancels it
+        let mut active = self.active.lock().await;
+        if !sessions.mark_started(emit, session) {
+            drop(active);
+            Self::finish(stream).await;
+            sessions.mark_ended(emit, session);
+            return false;
+        }
+        *active = Some(stream);
+        true
+    }
+
+    /// Start a session with `engine` in place of a real one, taking mono audio
+    #[cfg(test)]
+    pub(crate) async fn start_with(
+        &self,
+        sessions: &SessionManager,
+        emit: &impl Fn(SessionState),
+        engine: impl FnOnce(mpsc::Receiver<Vec<u8>>) -> JoinHandle<String>,
+    ) -> Result<(), AppError> {
+        let ticket = sessions.try_start(emit)?;
+        let (audio_tx, audio_rx) = mpsc::channel(AUDIO_QUEUE_CAPACITY);
+        let stream = ActiveStream {
+            session: ticket.session,
+            audio_tx,
+            channels: 1,
+            task: engine(audio_rx),
+            pause: SessionPause::new(),
+        };
+        if !self.activate(sessions, emit, stream).await {
+            return Err(cancelled());
+        }
+        Ok(())
+    }
+
+    async fn finish(active: ActiveStream) -> Option<String> {
+        let ActiveStream {
+            audio_tx, mut task, ..
+        } = active;
+        // Dropping the sender tells the engine to finalize
+        drop(audio_tx);
+        match tokio::time::timeout(ENGINE_SHUTDOWN_TIMEOUT, &mut task).await {
+            Ok(result) => result.ok(),
+            Err(_) => {
+                task.abort();
+                None
+            }
+        }
+    }
+
+    /// Forward backend-captured audio of `channels` channels to the active stream; a
+    /// paused session drops it
+    /// Hands the chunk back if no stream is running so the caller can route it elsewhere
+    pub async fn forward_audio(&self, chunk: Vec<u8>, channels: u16) -> Result<(), Vec<u8>> {
+        let (audio_tx, stream_channels) = match self.active.lock().await.as_ref() {
+            Some(active) if active.pause.is_paused() => return Ok(()),
+            Some(active) if !active.audio_tx.is_closed() => {
+                (active.audio_tx.clone(), active.channels)
+            }
+            _ => return Err(chunk),
+        };
+        let chunk = crate::audio::convert_channels(chunk, channels, stream_channels);
+        audio_tx.send(chunk).await.map_err(|e| e.0)
+    }
+
+    /// Pause control of the running session, which also tells sessions apart
+    pub async fn running_pause(&self) -> Result<Arc<SessionPause>, AppError> {
+        match self.active.lock().await.as_ref() {
+            Some(active) if !active.audio_tx.is_closed() => Ok(Arc::clone(&active.pause)),
+            _ => Err(AppError::Stream(
+                "No active transcription stream".to_string(),
+            )),
+        }
+    }
+}
+
+/// Start live transcription with the engine chosen in the settings
+/// `language` overrides the saved language for this session only. Fails with
+/// `SessionAlreadyActive` unless the session is idle, and with a stream error when
+/// `stop_session` cancels it before it has started
+#[tauri::command]
+pub async fn start_session(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    language: Option<String>,
+) -> Result<(), AppError> {
+    let language = super::language_override(language)?;
+    let manager = app.state::<SessionManager>();
+    let ticket = manager.begin_start(&app)?;
+    let session = ticket.session;
+    // Dropping the start closes a connection that's still being opened
+    let started = tokio::select! {
+        started = start_engine(&app, session, language) => started,
+        () = ticket.cancel.cancelled() => {
+            emit_state(&app, ConnectionState::Closed { reason: STOPPED_REASON.to_string() });
+            Err(cancelled())
+        }
+    };
+    let (stream, max_duration) = match started {
+        Ok(started) => started,
+        Err(e) => {
+            manager.ended(&app, session);
+            return Err(e);
+        }
+    };
+    let pause = Arc::clone(&stream.pause);
+    if !state
+        .stream
+        .activate(&manager, &emitter(&app), stream)
+        .await
+    {
+        return Err(cancelled());
+    }
+    watchdog::watch(&app, max_duration, pause);
+    Ok(())
+}
+
+fn cancelled() -> AppError {
+    AppError::Stream("The session was stopped before it started".to_string())
+}
+
+/// Start the engine for `session`; returns its stream and the duration limit in minutes
+async fn start_engine(
+    app: &AppHandle,
+    session: u64,
+    language: Option<String>,
+) -> Result<(ActiveStream, u32), AppError> {
+    let handle = app.clone();
+    let mut settings = blocking(move || Ok(crate::config::transcription_settings(&handle))).await?;
+    if let Some(language) = language {
+        settings.language = language;
+    }
+    let (audio_tx, audio_rx) = mpsc::channel(AUDIO_QUEUE_CAPACITY);
+    let pause = SessionPause::new();
+    let engine_pause = Arc::clone(&pause);
+    let max_duration = settings.max_session_duration_minutes;
+    let (task, channels) = match settings.engine {
+        EngineKind::Deepgram => match start_deepgram(app, &settings, &engine_pause).await {
+            Ok(engine) => (
+                engine::spawn(app, engine, audio_rx, session),
+                super::live_channels(&settings),
+            ),
+            Err(e) => match settings.fallback_provider {
+                Some(provider) if matches!(e, AppError::Network { .. }) => (
+                    fall_back(app, provider, e, settings, engine_pause, audio_rx, session).await?,
+                    TARGET_CHANNELS,
+                ),
+                _ => return Err(e),
+            },
+        },
+        EngineKind::Mock => (
+            engine::spawn(
+                app,
+                engine::mock::MockEngine::start(app, settings, engine_pause).await?,
+                audio_rx,
+                session,
+            ),
+            TARGET_CHANNELS,
+        ),
+        #[cfg(feature = "whisper-local")]
+        EngineKind::WhisperLocal => (
+            engine::spawn(
+                app,
+                crate::engine::whisper_local::WhisperLocal::start(app, settings, engine_pause)
+                    .await?,
+                audio_rx,
+                session,
+            ),
+            TARGET_CHANNELS,
+        ),
+        #[cfg(not(feature = "whisper-local"))]
+        EngineKind::WhisperLocal => {
+            return Err(AppError::Unsupported(
+                "This build doesn't include local transcription".to_string(),
+            ))
+        }
+    };
+
+    let stream = ActiveStream {
+        session,
+        audio_tx,
+        channels,
+        task,
+        pause,
+    };
+    Ok((stream, max_duration))
+}
+
+/// Connect to Deepgram, trying again on network failures up to `connect_attempts` times
+/// in all; the outcome is recorded as Deepgram's last known reachability
+async fn start_deepgram(
+    app: &AppHandle,
+    settings: &TranscriptionSettings,
+    pause: &Arc<SessionPause>,
+) -> Result<DeepgramEngine, AppError> {
+    let providers = &app.state::<AppState>().providers;
+    let mut attempt = 1;
+    loop {
+        let result = DeepgramEngine::start(app, settings.clone(), Arc::clone(pause)).await;
+        match &result {
+            Ok(_) | Err(AppError::KeyInvalid(_)) => providers.record(Provider::Deepgram, true),
+            Err(AppError::Network { .. }) => providers.record(Provider::Deepgram, false),
+            Err(_) => {}
+        }
+        match result {
+            Err(AppError::Network { .. }) if attempt < settings.connect_attempts => {
+                tokio::time::sleep(
+                    RECONNECT_BASE_DELAY
+                        .saturating_mul(1 << (attempt - 1).min(16))
+                        .min(RECONNECT_MAX_DELAY),
+                )
+                .await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Start the session on `provider` since Deepgram couldn't be reached with `error`, and
+/// tell the frontend; if that fails too, the session fails with Deepgram's error
+async fn fall_back(
+    app: &AppHandle,
+    provider: Provider,
+    error: AppError,
+    settings: TranscriptionSettings,
+    pause: Arc<SessionPause>,
+    audio_rx: mpsc::Receiver<Vec<u8>>,
+    session: u64,
+) -> Result<JoinHandle<String>, AppError> {
+    tracing::warn!("{}; falling back to {}", error, provider.as_str());
+    let started = match provider {
+        Provider::Openai => OpenaiWhisper::start(app, settings, pause).await,
+        // `validate_fallback` rules this out
+        Provider::Deepgram => return Err(error),
+    };
+    let engine = match started {
+        Ok(engine) => engine,
+        Err(e) => {
+            tracing::warn!("The fallback provider failed too: {}", e);
+            return Err(error);
+        }
+    };
+    let _ = app.emit(
+        EVENT_PROVIDER_FALLBACK,
+        ProviderFallback {
+            from: Provider::Deepgram,
+            to: provider,
+            reason: error.to_string(),
+        },
+    );
+    Ok(engine::spawn(app, engine, audio_rx, session))
+}
+
+/// Stop sending audio but keep the session, and its connection, open
+/// Deepgram is sent KeepAlive frames meanwhile; pausing twice is a no-op
+#[tauri::command]
+pub async fn pause_session(app: AppHandle, state: State<'_, AppState>) -> Result<(), AppError> {
+    if state.stream.running_pause().await?.pause() {
+        app.state::<SessionManager>().set_paused(&app, true);
+    }
+    Ok(())
+}
+
+/// Start sending audio to the paused session again
+#[tauri::command]
+pub async fn resume_session(app: AppHandle, state: State<'_, AppState>) -> Result<(), AppError> {
+    if !state.stream.running_pause().await?.resume() {
+        return Err(AppError::NotPaused(
+            "The transcription session is not paused".to_string(),
+        ));
+    }
+    app.state::<SessionManager>().set_paused(&app, false);
+    Ok(())
+}
+
+/// The Deepgram streaming engine: a socket task that reconnects on its own
+pub(crate) struct DeepgramEngine {
+    audio_tx: mpsc::Sender<Vec<u8>>,
+    task: JoinHandle<String>,
+    finalize_timeout: Duration,
+}
+
+impl DeepgramEngine {
+    /// Stream to `engine::mock`'s replay server at `url` instead of Deepgram
+    pub(crate) async fn mock(
+        app: &AppHandle,
+        settings: TranscriptionSettings,
+        pause: Arc<SessionPause>,
+        url: String,
+    ) -> Result<Self, AppError> {
+        let api_key = engine::mock::PROVIDER.to_string();
+        Self::open(app, settings, pause, api_key, url, None, true).await
+    }
+
+    /// Connect and start the socket task; a `mock` stream's attempts aren't limited,
+    /// and its session is kept out of usage
+    async fn open(
+        app: &AppHandle,
+        settings: TranscriptionSettings,
+        pause: Arc<SessionPause>,
+        api_key: String,
+        url: String,
+        proxy: Option<url::Url>,
+        mock: bool,
+    ) -> Result<Self, AppError> {
+        emit_state(app, ConnectionState::Connecting);
+        let connected = if mock {
+            handshake(&api_key, &url, None).await
+        } else {
+            connect(&api_key, &url, proxy.as_ref()).await
+        };
+        let connection = match connected {
+            Ok(connection) => connection,
+            Err(e) => {
+                emit_state(
+                    app,
+                    ConnectionState::Closed {
+                        reason: e.message(),
+                    },
+                );
+                return Err(e.into());
+            }
+        };
+        let (audio_tx, audio_rx) = mpsc::channel(AUDIO_QUEUE_CAPACITY);
+        let finalize_timeout = Duration::from_millis(settings.finalize_timeout_ms.into());
+        let mut stream =
+            LiveStream::new(app.clone(), api_key, url, proxy, settings, pause, audio_rx);
+        stream.mock = mock;
+        let task = tauri::async_runtime::spawn(stream.run(connection));
+        Ok(Self {
+            audio_tx,
+            task,
+            finalize_timeout,
+        })
+    }
+}
+
+impl TranscriptionEngine for DeepgramEngine {
+    async fn start(
+        app: &AppHandle,
+        settings: TranscriptionSettings,
+        pause: Arc<SessionPause>,
+    ) -> Result<Self, AppError> {
+        let route = app.state::<AppState>().route();
+        let (handle, url_settings, url_route) = (app.clone(), settings.clone(), route.clone());
+        let (url, api_key) = blocking(move || {
+            let vocabulary = crate::config::vocabulary(&handle);
+            let url = super::listen_url(&url_route, &url_settings, &vocabulary);
+            Ok((url, crate::deepgram_api_key(&handle)?))
+        })
+        .await?;
+        let proxy = route.proxy().cloned();
+        Self::open(app, settings, pause, api_key, url, proxy, false).await
+    }
+
+    async fn feed_audio(&mut self, chunk: Vec<u8>) -> Result<(), AppError> {
+        self.audio_tx
+            .send(chunk)
+            .await
+            .map_err(|_| AppError::Stream("Transcription stream has closed".to_string()))
+    }
+
+    async fn finalize(self) -> String {
+        let Self {
+            audio_tx,
+            mut task,
+            finalize_timeout,
+        } = self;
+        // Dropping the sender tells the socket task to close the connection
+        drop(audio_tx);
+        match tokio::time::timeout(finalize_timeout + SHUTDOWN_TIMEOUT, &mut task).await {
+            Ok(result) => result.unwrap_or_default(),
+            Err(_) => {
+                task.abort();
+                String::new()
+            }
+        }
+    }
+}
+
+/// Queue a chunk of 16 kHz mono linear16 PCM for the active stream; a multichannel
+/// session gets it on its first channel
+/// Waits while the queue is full so a slow socket pushes back on the caller
+#[tauri::command]
+pub async fn send_audio_chunk(chunk: Vec<u8>, state: State<'_, AppState>) -> Result<(), AppError> {
+    // Clone the sender so the lock isn't held while waiting for queue space
+    let (audio_tx, channels) = match state.stream.active.lock().await.as_ref() {
+        // Audio sent while paused isn't part of the session
+        Some(active) if active.pause.is_paused() => return Ok(()),
+        Some(active) => (active.audio_tx.clone(), active.channels),
+        None => {
+            return Err(AppError::Stream(
+                "No active transcription stream".to_string(),
+            ))
+        }
+    };
+
+    let chunk = crate::audio::convert_channels(chunk, TARGET_CHANNELS, channels);
+    match audio_tx.send_timeout(chunk, AUDIO_SEND_TIMEOUT).await {
+        Ok(()) => Ok(()),
+        Err(mpsc::error::SendTimeoutError::Timeout(_)) => Err(AppError::Stream(
+            "Audio stream is backed up, dropping chunk".to_string(),
+        )),
+        Err(mpsc::error::SendTimeoutError::Closed(_)) => Err(AppError::Stream(
+            "Transcription stream has closed".to_string(),
+        )),
+    }
+}
+
+/// Close the active stream once its last results are in, or cancel the session if it's
+/// still starting
+/// Returns the session's assembled transcript, translated when translation is on, for
+/// injection; empty if no stream was running
+#[tauri::command]
+pub async fn stop_session(app: AppHandle, state: State<'_, AppState>) -> Result<String, AppError> {
+    Ok(state.stream.shutdown(&app).await.unwrap_or_default())
+}
+
+/// Open a streaming connection and close it again, to check the route works
+pub async fn probe(api_key: &str, url: &str, proxy: Option<&url::Url>) -> Result<(), AppError> {
+    let mut socket = connect(api_key, url, proxy).await?.socket;
+    let _ = socket
+        .send(Message::Text(CLOSE_STREAM_MESSAGE.into()))
+        .await;
+    let _ = socket.close(None).await;
+    Ok(())
+}
+
+/// Connect to Deepgram, if `net::limiter` allows another attempt
+async fn connect(
+    api_key: &str,
+    url: &str,
+    proxy: Option<&url::Url>,
+) -> Result<Connection, ConnectError> {
+    limiter::admit(Attempt::Connection).map_err(ConnectError::Refused)?;
+    let result = handshake(api_key, url, proxy).await;
+    // A rejected key still means Deepgram is up
+    limiter::record(matches!(result, Ok(_) | Err(ConnectError::Unauthorized)));
+    result
+}
+
+/// Open the WebSocket, authenticating with the Authorization header
+/// With a proxy, the connection runs through a CONNECT tunnel to Deepgram's host
+async fn handshake(
+    api_key: &str,
+    url: &str,
+    proxy: Option<&url::Url>,
+) -> Result<Connection, ConnectError> {
+    let mut request = url
+        .into_client_request()
+        .map_err(|e| ConnectError::Failed(format!("Invalid Deepgram URL: {}", e)))?;
+    let auth = HeaderValue::from_str(&format!("Token {}", api_key))
+        .map_err(|_| ConnectError::Failed("API key contains invalid characters".to_string()))?;
+    request.headers_mut().insert("Authorization", auth);
+
+    let result = match proxy {
+        None => tokio_tungstenite::connect_async(request).await,
+        Some(proxy) => {
+            let uri = request.uri();
+            let host = uri.host().unwrap_or_default().to_string();
+            let port = uri.port_u16().unwrap_or(match uri.scheme_str() {
+                Some("ws") => 80,
+                _ => 443,
+            });
+            let stream = super::network::tunnel(proxy, &host, port)
+                .await
+                .map_err(|e| ConnectError::Proxy(e.to_string()))?;
+            tokio_tungstenite::client_async_tls(request, stream).await
+        }
+    };
+    match result {
+        Ok((socket, response)) => Ok(Connection {
+            socket,
+            request_id: response
+                .headers()
+                .get("dg-request-id")
+                .and_then(|id| id.to_str().ok())
+                .map(str::to_string),
+        }),
+        Err(WsError::Http(response)) if matches!(response.status().as_u16(), 401 | 403) => {
+            Err(ConnectError::Unauthorized)
+        }
+        Err(WsError::Tls(e)) => Err(ConnectError::Tls(format!(
+            "Secure connection to Deepgram failed: {}",
+            e
+        ))),
+        Err(e) => Err(ConnectError::Failed(format!(
+            "Failed to connect to Deepgram: {}",
+            e
+        ))),
+    }
+}
+
+/// Audio held while disconnected, replayed once the connection is back
+struct ReplayBuffer {
+    chunks: VecDeque<Vec<u8>>,
+    bytes: usize,
+    capacity: usize,
+    overflow: BufferOverflow,
+}
+
+impl ReplayBuffer {
+    fn new(settings: &TranscriptionSettings) -> Self {
+        Self {
+            chunks: VecDeque::new(),
+            bytes: 0,
+            capacity: settings.reconnect_buffer_seconds as usize
+                * 1000
+                * BYTES_PER_MS as usize
+                * usize::from(super::live_channels(settings)),
+            overflow: settings.buffer_overflow,
+        }
+    }
+
+    /// Buffer a chunk; returns false if it was rejected because the buffer is full
+    fn push(&mut self, chunk: Vec<u8>) -> bool {
+        if chunk.len() > self.capacity {
+            return false;
+        }
+        if self.bytes + chunk.len() > self.capacity {
+            match self.overflow {
+                BufferOverflow::StopCapture => return false,
+                BufferOverflow::DropOldest => {
+                    while self.bytes + chunk.len() > self.capacity {
+                        let Some(oldest) = self.chunks.pop_front() else {
+                            break;
+                        };
+                        self.bytes -= oldest.len();
+                    }
+                }
+            }
+        }
+        self.bytes += chunk.len();
+        self.chunks.push_back(chunk);
+        true
+    }
+
+    /// Put a chunk that failed to send back at the front, ignoring the capacity
+    fn unshift(&mut self, chunk: Vec<u8>) {
+        self.bytes += chunk.len();
+        self.chunks.push_front(chunk);
+    }
+
+    fn pop(&mut self) -> Option<Vec<u8>> {
+        let chunk = self.chunks.pop_front()?;
+        self.bytes -= chunk.len();
+        Some(chunk)
+    }
+}
+
+/// What Deepgram reports over a session besides its transcripts
+#[derive(Default)]
+struct Reported {
+    languages: DetectedLanguages,
+    /// One per connection, in order
+    requests: Vec<SessionMetadata>,
+}
+
+impl Reported {
+    /// A connection opened as request `request_id`; emits `session-metadata`
+    fn connected(&mut self, app: &AppHandle, request_id: Option<String>, channels: u16) {
+        let metadata = SessionMetadata {
+            request_id,
+            channels: Some(u32::from(channels)),
+            ..SessionMetadata::default()
+        };
+        super::emit_session_metadata(app, &metadata);
+        self.requests.push(metadata);
+    }
+
+    /// What a result or the closing message says about the current connection's request;
+    /// emits `session-metadata` again when that's something new
+    fn observe(&mut self, app: &AppHandle, metadata: SessionMetadata) {
+        match self.requests.last_mut() {
+            Some(current) => {
+                if current.merge(metadata) {
+                    super::emit_session_metadata(app, current);
+                }
+            }
+            None => {
+                super::emit_session_metadata(app, &metadata);
+                self.requests.push(metadata);
+            }
+        }
+    }
+
+    /// Request ids of every connection, comma-separated
+    fn request_id(&self) -> Option<String> {
+        let ids: Vec<&str> = self
+            .requests
+            .iter()
+            .filter_map(|request| request.request_id.as_deref())
+            .collect();
+        (!ids.is_empty()).then(|| ids.join(", "))
+    }
+
+    /// The model that served the latest connection, see `SessionMetadata::model`
+    fn model_version(&self) -> Option<String> {
+        self.requests.iter().rev().find_map(SessionMetadata::model)
+    }
+}
+
+/// Languages reported by `language=multi` results over a session
+#[derive(Default)]
+struct DetectedLanguages {
+    current: Option<String>,
+    /// Final results per language
+    counts: BTreeMap<String, usize>,
+}
+
+impl DetectedLanguages {
+    /// Count one final result, emitting `language-detected` when the language changes
+    fn observe(&mut self, app: &AppHandle, language: &str) {
+        *self.counts.entry(language.to_string()).or_default() += 1;
+        if self.current.as_deref() != Some(language) {
+            super::emit_language_detected(app, language, None);
+            self.current = Some(language.to_string());
+        }
+    }
+
+    /// The language most results were in, which is what the session is filed under
+    fn dominant(&self) -> Option<String> {
+        self.counts
+            .iter()
+            .max_by_key(|(_, count)| **count)
+            .map(|(language, _)| language.clone())
+    }
+}
+
+/// One transcription stream, which may span several connections
+struct LiveStream {
+    app: AppHandle,
+    api_key: String,
+    /// Streaming URL built once from the settings and vocabulary, reused on reconnect
+    url: String,
+    /// Proxy from the network settings when the stream started
+    proxy: Option<url::Url>,
+    settings: TranscriptionSettings,
+    /// 2 for a multichannel session
+    channels: u16,
+    pause: Arc<SessionPause>,
+    audio_rx: mpsc::Receiver<Vec<u8>>,
+    buffer: ReplayBuffer,
+    /// Present when voice activity detection is enabled
+    vad: Option<VoiceDetector>,
+    /// Present when the session's audio is being saved
+    recorder: Option<SessionRecorder>,
+    /// Final segments so far, kept on disk in case the app dies mid-session
+    journal: Option<SessionJournal>,
+    commands: DictationCommands,
+    /// What's been shown and committed, across reconnects
+    transcript: LiveTranscript,
+    reported: Reported,
+    metrics: Arc<StreamMetrics>,
+    /// Audio time covered by previous connections; Deepgram restarts at 0 on each one
+    offset_ms: i64,
+    /// Audio sent on the current connection
+    sent_bytes: u64,
+    capture_stopped: bool,
+    /// Streaming to `engine::mock` rather than Deepgram
+    mock: bool,
+}
+
+impl LiveStream {
+    fn new(
+        app: AppHandle,
+        api_key: String,
+        url: String,
+        proxy: Option<url::Url>,
+        settings: TranscriptionSettings,
+        pause: Arc<SessionPause>,
+        audio_rx: mpsc::Receiver<Vec<u8>>,
+    ) -> Self {
+        Self {
+            buffer: ReplayBuffer::new(&settings),
+            channels: super::live_channels(&settings),
+            vad: settings.vad_enabled.then(|| {
+                VoiceDetector::new(
+                    settings.vad_threshold_db,
+                    settings.silence_timeout_secs,
+                    super::live_channels(&settings),
+                )
+            }),
+            recorder: None,
+            journal: None,
+            commands: DictationCommands::load(&app, &settings.language),
+            transcript: LiveTranscript::new(&app, &settings),
+            metrics: app.state::<AppState>().stream.metrics(),
+            app,
+            api_key,
+            url,
+            proxy,
+            settings,
+            pause,
+            audio_rx,
+            reported: Reported::default(),
+            offset_ms: 0,
+            sent_bytes: 0,
+            capture_stopped: false,
+            mock: false,
+        }
+    }
+
+    /// Pump audio and transcripts until stopped, reconnecting when the connection drops,
+    /// then save the session's final transcript to history and return it
+    async fn run(mut self, connection: Connection) -> String {
+        let started = Instant::now();
+        self.metrics.start(AUDIO_QUEUE_CAPACITY, self.channels);
+        let started_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as i64)
+            .unwrap_or(0);
+        if self.settings.save_audio {
+            match recording::recordings_dir(&self.app) {
+                Ok(dir) => {
+                    let path = recording::in_progress_path(&dir, started_at);
+                    self.recorder = Some(SessionRecorder::start(path, self.channels));
+                }
+                Err(e) => tracing::error!("{}; session audio will not be saved", e),
+            }
+        }
+        self.journal = SessionJournal::start(
+            &self.app,
+            JournalHeader {
+                started_at,
+                model: self.settings.model.clone(),
+                language: self.settings.language.clone(),
+                provider: Some(self.provider().to_string()),
+            },
+        );
+
+        self.reported
+            .connected(&self.app, connection.request_id, self.channels);
+        let mut socket = connection.socket;
+        emit_state(&self.app, ConnectionState::Open);
+        let reason = loop {
+            let end = match self.pump(socket).await {
+                ConnectionEnd::Lost(reason) => {
+                    tracing::warn!("Deepgram connection lost: {}", reason);
+                    // A connection that dies while paused is only replaced on resume
+                    if self.pause.is_paused() && !self.wait_for_resume().await {
+                        break STOPPED_REASON.to_string();
+                    }
+                    match self.reconnect(reason).await {
+                        Ok(connection) => {
+                            self.reported.connected(
+                                &self.app,
+                                connection.request_id,
+                                self.channels,
+                            );
+                            socket = connection.socket;
+                            emit_state(&self.app, ConnectionState::Open);
+                            continue;
+                        }
+                        Err(end) => end,
+                    }
+                }
+                end => end,
+            };
+            match end {
+                ConnectionEnd::Stopped => break STOPPED_REASON.to_string(),
+                ConnectionEnd::Lost(reason) | ConnectionEnd::Failed(reason) => {
+                    let _ = self.app.emit(EVENT_STREAM_ERROR, reason.clone());
+                    break reason;
+                }
+            }
+        };
+        emit_state(&self.app, ConnectionState::Closed { reason });
+        self.metrics.finish();
+
+        let duration_ms = self.pause.active_since(started).as_millis() as i64;
+        let audio_source = self
+            .app
+            .state::<CaptureState>()
+            .source_since(started)
+            .map(|source| source.as_str().to_string());
+        let audio_path = match self.recorder.take() {
+            Some(recorder) => tauri::async_runtime::spawn_blocking(move || recorder.finish())
+                .await
+                .ok()
+                .flatten(),
+            None => None,
+        };
+        let transcript = self.transcript.finish().await;
+        let text = transcript.output();
+        let _ = tauri::async_runtime::spawn_blocking(move || {
+            self.save(
+                started_at,
+                duration_ms,
+                audio_path,
+                audio_source,
+                transcript,
+            )
+        })
+        .await;
+        text
+    }
+
+    /// Drive one connection: replay buffered audio, then forward live audio and results
+    async fn pump(&mut self, socket: Socket) -> ConnectionEnd {
+        let (mut write, mut read) = socket.split();
+
+        while let Some(chunk) = self.buffer.pop() {
+            let len = chunk.len() as u64;
+            if let Err(e) = write.send(Message::Binary(chunk.clone().into())).await {
+                self.buffer.unshift(chunk);
+                return ConnectionEnd::Lost(format!("Failed to send audio: {}", e));
+            }
+            self.sent_bytes += len;
+            self.metrics.replayed(len as usize);
+        }
+
+        // Fires once no audio has been sent for a whole interval, e.g. while VAD holds it back
+        let keep_alive_interval =
+            Duration::from_secs(self.settings.keep_alive_interval_secs.max(1).into());
+        let keep_alive = tokio::time::sleep(keep_alive_interval);
+        tokio::pin!(keep_alive);
+
+        loop {
+            tokio::select! {
+                chunk = self.audio_rx.recv() => match chunk {
+                    Some(chunk) => {
+                        self.metrics.queued(&self.app, self.audio_rx.len());
+                        self.record(&chunk);
+                        let (chunks, vad_state) = self.gate(chunk);
+                        let mut chunks = chunks.into_iter().peekable();
+                        if chunks.peek().is_some() {
+                            keep_alive.as_mut().reset(tokio::time::Instant::now() + keep_alive_interval);
+                        }
+                        while let Some(chunk) = chunks.next() {
+                            let len = chunk.len() as u64;
+                            if let Err(e) = write.send(Message::Binary(chunk.clone().into())).await {
+                                self.buffer_audio(chunk);
+                                chunks.for_each(|chunk| self.buffer_audio(chunk));
+                                return ConnectionEnd::Lost(format!("Failed to send audio: {}", e));
+                            }
+                            self.sent_bytes += len;
+                            self.metrics.sent(len as usize);
+                        }
+                        if vad_state == Some(VadState::Silent) {
+                            if self.settings.silence_action == SilenceAction::StopSession {
+                                tracing::info!("Stopping dictation after silence");
+                                self.stop_capture();
+                                crate::hotkey::set_dictation(&self.app, false);
+                                self.close_stream(&mut write, &mut read).await;
+                                return ConnectionEnd::Stopped;
+                            }
+                            let _ = write.send(Message::Text(FINALIZE_MESSAGE.into())).await;
+                        }
+                    }
+                    // All senders dropped: stop_session was called
+                    None => {
+                        self.close_stream(&mut write, &mut read).await;
+                        return ConnectionEnd::Stopped;
+                    }
+                },
+                _ = &mut keep_alive => {
+                    if let Err(e) = write.send(Message::Text(KEEP_ALIVE_MESSAGE.into())).await {
+                        return ConnectionEnd::Lost(format!("Failed to send KeepAlive: {}", e));
+                    }
+                    keep_alive.as_mut().reset(tokio::time::Instant::now() + keep_alive_interval);
+                }
+                message = read.next() => match message {
+                    Some(Ok(Message::Text(text))) => self.handle_text(&text),
+                    Some(Ok(Message::Close(frame))) => {
+                        return ConnectionEnd::Lost(
+                            frame
+                                .map(|f| format!("Connection closed by Deepgram (code: {})", u16::from(f.code)))
+                                .unwrap_or_else(|| "Connection closed by Deepgram".to_string()),
+                        );
+                    }
+                    Some(Ok(_)) => {}
+                    Some(Err(e)) => return ConnectionEnd::Lost(format!("WebSocket error: {}", e)),
+                    None => return ConnectionEnd::Lost("Connection closed".to_string()),
+                },
+            }
+        }
+    }
+
+    /// Send CloseStream and handle results until Deepgram closes the connection or the
+    /// finalize timeout passes, so the last utterance isn't cut off
+    async fn close_stream(&mut self, write: &mut SocketWriter, read: &mut SocketReader) {
+        if write
+            .send(Message::Text(CLOSE_STREAM_MESSAGE.into()))
+            .await
+            .is_ok()
+        {
+            let timeout = Duration::from_millis(self.settings.finalize_timeout_ms.into());
+            let drain = async {
+                while let Some(Ok(message)) = read.next().await {
+                    match message {
+                        Message::Text(text) => self.handle_text(&text),
+                        Message::Close(_) => break,
+                        _ => {}
+                    }
+                }
+            };
+            if tokio::time::timeout(timeout, drain).await.is_err() {
+                tracing::warn!(
+                    "Deepgram didn't close within {} ms; closing anyway",
+                    self.settings.finalize_timeout_ms
+                );
+            }
+        }
+        let _ = write.send(Message::Close(None)).await;
+    }
+
+    fn handle_text(&mut self, text: &str) {
+        let first = self.transcript.is_empty();
+        let segments = handle_message(
+            &self.app,
+            text,
+            self.offset_ms,
+            &self.settings,
+            &mut self.commands,
+            &mut self.reported,
+            &mut self.transcript,
+        );
+        let revoked = self.transcript.take_revoked();
+        if let Some(journal) = self.journal.as_mut() {
+            revoked.iter().for_each(|segment| journal.revoke(segment));
+            segments.iter().for_each(|segment| journal.append(segment));
+        }
+        if first && !self.transcript.is_empty() {
+            self.metrics.partial();
+        }
+    }
+
+    /// Wait for a paused session to resume; false if it was stopped instead
+    async fn wait_for_resume(&mut self) -> bool {
+        let pause = Arc::clone(&self.pause);
+        loop {
+            tokio::select! {
+                _ = pause.resumed() => return true,
+                chunk = self.audio_rx.recv() => match chunk {
+                    // Sent just as the session resumed
+                    Some(chunk) => {
+                        self.record(&chunk);
+                        self.buffer_audio(chunk);
+                    }
+                    None => return false,
+                },
+            }
+        }
+    }
+
+    /// Reconnect with exponential backoff, buffering audio in the meantime
+    async fn reconnect(&mut self, mut last_error: String) -> Result<Connection, ConnectionEnd> {
+        self.offset_ms += self.sent_ms();
+        self.sent_bytes = 0;
+
+        for attempt in 1..=MAX_RECONNECT_ATTEMPTS {
+            emit_state(&self.app, ConnectionState::Reconnecting { attempt });
+            let delay = RECONNECT_BASE_DELAY
+                .saturating_mul(1 << (attempt - 1).min(16))
+                .min(RECONNECT_MAX_DELAY);
+            if !self.buffer_for(delay).await {
+                return Err(ConnectionEnd::Stopped);
+            }
+
+            let connected = if self.mock {
+                handshake(&self.api_key, &self.url, None).await
+            } else {
+                connect(&self.api_key, &self.url, self.proxy.as_ref()).await
+            };
+            match connected {
+                Ok(connection) => return Ok(connection),
+                Err(ConnectError::Unauthorized) => {
+                    return Err(ConnectionEnd::Failed(ConnectError::Unauthorized.message()))
+                }
+                Err(e) => last_error = e.message(),
+            }
+        }
+        Err(ConnectionEnd::Failed(format!(
+            "Gave up reconnecting after {} attempts: {}",
+            MAX_RECONNECT_ATTEMPTS, last_error
+        )))
+    }
+
+    /// Buffer incoming audio for `delay`; returns false if the stream was stopped meanwhile
+    async fn buffer_for(&mut self, delay: Duration) -> bool {
+        let sleep = tokio::time::sleep(delay);
+        tokio::pin!(sleep);
+        loop {
+            tokio::select! {
+                _ = &mut sleep => return true,
+                chunk = self.audio_rx.recv() => match chunk {
+                    Some(chunk) => {
+                        self.record(&chunk);
+                        self.buffer_audio(chunk);
+                    }
+                    None => return false,
+                },
+            }
+        }
+    }
+
+    /// Save a chunk of captured audio, including audio VAD holds back
+    fn record(&mut self, chunk: &[u8]) {
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.write(chunk.to_vec());
+        }
+    }
+
+    /// Run a live chunk through VAD, returning the chunks to send and any state change
+    fn gate(&mut self, chunk: Vec<u8>) -> (Vec<Vec<u8>>, Option<VadState>) {
+        let Some(vad) = self.vad.as_mut() else {
+            return (vec![chunk], None);
+        };
+        let mut chunks = Vec::new();
+        let state = vad.push(chunk, |chunk| chunks.push(chunk));
+        if let Some(state) = state {
+            let _ = self.app.emit(EVENT_VAD_STATE, state);
+        }
+        (chunks, state)
+    }
+
+    fn buffer_audio(&mut self, chunk: Vec<u8>) {
+        if self.buffer.push(chunk) || self.capture_stopped {
+            return;
+        }
+        if self.buffer.overflow == BufferOverflow::StopCapture {
+            self.stop_capture();
+            let _ = self.app.emit(
+                EVENT_STREAM_ERROR,
+                "Reconnect buffer is full, microphone capture stopped".to_string(),
+            );
+        }
+    }
+
+    fn stop_capture(&mut self) {
+        self.capture_stopped = true;
+        let app = self.app.clone();
+        // Joining the capture thread blocks, so keep it off the async runtime
+        tauri::async_runtime::spawn_blocking(move || app.state::<CaptureState>().shutdown());
+    }
+
+    /// Provider recorded with the session
+    fn provider(&self) -> &'static str {
+        if self.mock {
+            engine::mock::PROVIDER
+        } else {
+            Provider::Deepgram.as_str()
+        }
+    }
+
+    /// Audio sent to Deepgram over all connections, which is what gets billed
+    fn audio_ms(&self) -> i64 {
+        self.offset_ms + self.sent_ms()
+    }
+
+    /// Audio sent on the current connection
+    fn sent_ms(&self) -> i64 {
+        (self.sent_bytes / (u64::from(BYTES_PER_MS) * u64::from(self.channels))) as i64
+    }
+
+    fn save(
+        mut self,
+        started_at: i64,
+        duration_ms: i64,
+        audio_path: Option<PathBuf>,
+        audio_source: Option<String>,
+        transcript: FinishedTranscript,
+    ) {
+        let storage = Arc::clone(&self.app.state::<AppState>().storage);
+        // Nothing was billed for a replay
+        if !self.mock {
+            crate::usage::record(&storage, started_at, &self.settings.model, self.audio_ms());
+        }
+        let journal = self.journal.take().map(SessionJournal::finish);
+        if transcript.segments.is_empty() {
+            // No history entry to attach the recording to
+            if let Some(path) = audio_path {
+                let _ = std::fs::remove_file(path);
+            }
+            if let Some(path) = &journal {
+                recovery::discard(path);
+            }
+            return;
+        }
+        let provider = self.provider();
+        let session = NewSession {
+            started_at,
+            duration_ms,
+            model: self.settings.model,
+            language: self.settings.language,
+            detected_language: self.reported.languages.dominant(),
+            text: transcript.text,
+            audio_path: None,
+            segments: transcript.segments,
+            recovered: false,
+            imported: false,
+            audio_source,
+            translated_text: transcript.translated_text,
+            translation_language: transcript.translation_language,
+            provider: Some(provider.to_string()),
+            request_id: self.reported.request_id(),
+            model_version: self.reported.model_version(),
+            disfluencies_removed: self.commands.disfluencies_removed(),
+        };
+        // On failure the journal stays, so the session is recovered on the next launch
+        let id = match storage.insert_session(&session) {
+            Ok(id) => id,
+            Err(e) => {
+                tracing::error!("Failed to save session: {}", e);
+                return;
+            }
+        };
+        if let Some(path) = &journal {
+            recovery::discard(path);
+        }
+        if self.mock {
+            if let Err(e) = storage.add_tag(id, engine::mock::TAG) {
+                tracing::warn!("Failed to tag mock session {}: {}", id, e);
+            }
+        }
+        crate::transcript_history::record(&self.app, id, &session.text, started_at + duration_ms);
+        if let Some(path) = audio_path {
+            recording::attach(&storage, id, path);
+        }
+    }
+}
+
+pub(crate) fn emit_state(app: &AppHandle, state: ConnectionState) {
+    app.state::<ConnectionLog>().record(&state);
+    let _ = app.emit(EVENT_CONNECTION_STATE, state);
+}
+
+/// Parse a Deepgram message and forward transcripts to the frontend
+/// Timestamps are shifted by `offset_ms` so they stay continuous across reconnects
+/// Final results go through `postprocess::rewrite_final`; interim ones are sent as-is
+/// A final segment left empty, all fillers, is dropped
+/// Final results carry their words, flagged when below `confidence_threshold`
+/// A diarized final result is sent as one event per speaker turn, since speakers
+/// assigned in interim results aren't reliable
+/// Final results also report the detected language whenever it changes, and any result
+/// the request and model serving it
+/// `UtteranceEnd` messages are forwarded as `utterance-end` events
+/// Results also go through `transcript`, which sends the display and committed events
+/// A final segment that says "scratch that" takes back the one before it and is dropped
+/// Returns the result's history segments when it is final
+fn handle_message(
+    app: &AppHandle,
+    text: &str,
+    offset_ms: i64,
+    settings: &TranscriptionSettings,
+    commands: &mut DictationCommands,
+    reported: &mut Reported,
+    transcript: &mut LiveTranscript,
+) -> Vec<Segment> {
+    let mut results = match serde_json::from_str::<StreamMessage>(text) {
+        Ok(StreamMessage::Results(results)) => results,
+        Ok(StreamMessage::UtteranceEnd(end)) => {
+            let _ = app.emit(
+                EVENT_UTTERANCE_END,
+                UtteranceEnd {
+                    last_word_end: end.last_word_end + offset_ms as f64 / 1000.0,
+                },
+            );
+            return Vec::new();
+        }
+        Ok(StreamMessage::Metadata(metadata)) => {
+            reported.observe(app, metadata.to_session_metadata());
+            return Vec::new();
+        }
+        Ok(StreamMessage::Other) => return Vec::new(),
+        Err(e) => {
+            tracing::warn!("Failed to parse Deepgram message: {}", e);
+            return Vec::new();
+        }
+    };
+
+    if let Some(metadata) = &results.metadata {
+        reported.observe(app, metadata.to_session_metadata());
+    }
+    results.shift(offset_ms as f64 / 1000.0);
+    let Some(event) = results.to_event() else {
+        return Vec::new();
+    };
+    let (start_ms, end_ms) = results.span_ms();
+    let channel = results.audio_channel();
+    if !results.is_final {
+        transcript.interim(channel, start_ms, end_ms, &event.transcript, event.speaker);
+        emit_transcript(app, false, event);
+        return Vec::new();
+    }
+    if let Some(language) = results.detected_language() {
+        reported.languages.observe(app, language);
+    }
+    let mut segments = results.to_segments();
+    if let Some(channel) = channel {
+        super::label_channel(&mut segments, channel, settings);
+    }
+    let spoken = segments.len();
+    segments = revoke_spoken(app, commands, segments);
+    if spoken > 0 && segments.is_empty() {
+        // Only the phrase was said; its interims go, and nothing else is sent
+        transcript.commit(channel, start_ms, end_ms, &segments, &[]);
+        return segments;
+    }
+    if segments.is_empty() {
+        transcript.commit(channel, start_ms, end_ms, &segments, &[]);
+        emit_transcript(app, true, event);
+        return segments;
+    }
+    let replacements = app.state::<ReplacementState>().current();
+    let mut rewritten = Vec::with_capacity(segments.len());
+    for mut segment in segments {
+        let words = segment.words.as_deref().unwrap_or_default();
+        let text = postprocess::rewrite_final(commands, &replacements, &segment.text, words);
+        // Nothing but fillers, which the disfluency pass took out
+        if text.is_empty() {
+            continue;
+        }
+        let raw = std::mem::replace(&mut segment.text, text);
+        rewritten.push((segment, raw));
+    }
+    if rewritten.is_empty() {
+        transcript.commit(channel, start_ms, end_ms, &[], &[]);
+        let raw = event.transcript.clone();
+        emit_transcript(
+            app,
+            true,
+            TranscriptEvent {
+                transcript: String::new(),
+                words: Vec::new(),
+                raw_transcript: Some(raw),
+                ..event
+            },
+        );
+        return Vec::new();
+    }
+    let last = rewritten.len() - 1;
+    for (index, (segment, raw)) in rewritten.iter().enumerate() {
+        let words = segment.words.as_deref().unwrap_or_default();
+        emit_transcript(
+            app,
+            true,
+            TranscriptEvent {
+                transcript: segment.text.clone(),
+                start: segment.start_ms as f64 / 1000.0,
+                duration: (segment.end_ms - segment.start_ms) as f64 / 1000.0,
+                speech_final: event.speech_final && index == last,
+                speaker: segment.speaker,
+                words: TranscriptWord::from_words(words, settings.confidence_threshold),
+                raw_transcript: Some(raw.clone()),
+            },
+        );
+    }
+    let (segments, raw): (Vec<Segment>, Vec<String>) = rewritten.into_iter().unzip();
+    transcript.commit(channel, start_ms, end_ms, &segments, &raw);
+    segments
+}
+
+/// `segments` without the ones saying "scratch that", each of which takes back the segment
+/// before it: an earlier one of the same result, else the last one committed
+fn revoke_spoken(
+    app: &AppHandle,
+    commands: &DictationCommands,
+    segments: Vec<Segment>,
+) -> Vec<Segment> {
+    let mut kept = Vec::with_capacity(segments.len());
+    for segment in segments {
+        if !commands.is_revocation(&segment.text) {
+            kept.push(segment);
+        } else if kept.pop().is_none() {
+            app.state::<RevokeState>()
+                .revoke(app, commands.revoke_window_ms());
+        }
+    }
+    kept
+}
+
+pub(crate) fn emit_transcript(app: &AppHandle, is_final: bool, event: TranscriptEvent) {
+    app.state::<AppState>().captions.relay(is_final, &event);
+    let name = if is_final {
+        EVENT_TRANSCRIPT_FINAL
+    } else {
+        EVENT_TRANSCRIPT_PARTIAL
+    };
+    let _ = app.emit(name, event);
+}
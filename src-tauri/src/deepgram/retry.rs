@@ -0,0 +1,173 @@
+// Retry policy for Deepgram requests that can be repeated as a whole, like file uploads
+// Connection errors, timeouts, 408, 429 and 5xx responses are retried with exponential
+// backoff and jitter; a Retry-After header is honored instead when the response has one.
+// Authentication and validation errors (other 4xx) never are: they'd fail the same way again
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use ring::rand::{SecureRandom, SystemRandom};
+
+use crate::config::FileTranscriptionSettings;
+
+/// Longest backoff between two attempts
+const MAX_DELAY: Duration = Duration::from_secs(60);
+/// Longest Retry-After honored; a server asking for more gets this instead
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Attempts in total, the first one included; 1 means no retries
+    pub max_attempts: u32,
+    /// Backoff ceiling after the first failure; doubles after each one
+    pub base_delay: Duration,
+}
+
+impl RetryPolicy {
+    pub fn from_settings(settings: &FileTranscriptionSettings) -> Self {
+        Self {
+            max_attempts: settings.max_attempts.max(1),
+            base_delay: Duration::from_millis(settings.retry_base_delay_ms),
+        }
+    }
+
+    /// Wait before the attempt after `attempt` (from 1) failed, with `retry_after` from
+    /// the response when it had one
+    pub fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        match retry_after {
+            Some(retry_after) => retry_after.min(MAX_RETRY_AFTER),
+            None => self.backoff(attempt, jitter()),
+        }
+    }
+
+    /// Half the ceiling fixed and half random ("equal jitter"), so uploads that failed
+    /// together spread out without ever retrying immediately; `jitter` is in 0..1
+    fn backoff(&self, attempt: u32, jitter: f64) -> Duration {
+        let ceiling = self
+            .base_delay
+            .saturating_mul(1 << (attempt.saturating_sub(1)).min(16))
+            .min(MAX_DELAY);
+        ceiling / 2 + ceiling.mul_f64(jitter.clamp(0.0, 1.0) / 2.0)
+    }
+}
+
+/// Whether a response with `status` is worth sending the request again for
+pub fn retryable_status(status: u16) -> bool {
+    matches!(status, 408 | 429 | 500..=599)
+}
+
+/// Whether a request that got no response at all is worth sending again
+/// Everything but a request that couldn't be built is presumed to be the network's fault
+pub fn retryable_error(error: &reqwest::Error) -> bool {
+    !error.is_builder() && !error.is_redirect()
+}
+
+/// Parse a Retry-After header: a number of seconds, or an HTTP date relative to `now`
+pub fn retry_after(value: &str, now: SystemTime) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let at = UNIX_EPOCH + Duration::from_secs(http_date(value)?);
+    // A date in the past means "now"
+    Some(at.duration_since(now).unwrap_or_default())
+}
+
+/// Seconds since the epoch of an IMF-fixdate, e.g. "Sun, 06 Nov 1994 08:49:37 GMT",
+/// the only format servers may send
+fn http_date(value: &str) -> Option<u64> {
+    let (_, rest) = value.split_once(", ")?;
+    let mut parts = rest.split(' ');
+    let day: u32 = parts.next()?.parse().ok()?;
+    let month = match parts.next()? {
+        "Jan" => 1,
+        "Feb" => 2,
+        "Mar" => 3,
+        "Apr" => 4,
+        "May" => 5,
+        "Jun" => 6,
+        "Jul" => 7,
+        "Aug" => 8,
+        "Sep" => 9,
+        "Oct" => 10,
+        "Nov" => 11,
+        "Dec" => 12,
+        _ => return None,
+    };
+    let year: i64 = parts.next()?.parse().ok()?;
+    let mut time = parts
+        .next()?
+        .split(':')
+        .map(|part| part.parse::<u64>().ok());
+    let (hour, minute, second) = (time.next()??, time.next()??, time.next()??);
+    if parts.next()? != "GMT" || !(1..=31).contains(&day) || hour > 23 || minute > 59 {
+        return None;
+    }
+    let days = u64::try_from(days_from_civil(year, month, day)).ok()?;
+    Some(days * 86_400 + hour * 3600 + minute * 60 + second.min(60))
+}
+
+/// Days from 1970-01-01 to the given date (proleptic Gregorian)
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let shifted_month = i64::from((month + 9) % 12);
+    let day_of_year = (153 * shifted_month + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// A random number in 0..1; without a random source the delay just isn't jittered
+fn jitter() -> f64 {
+    let mut bytes = [0u8; 4];
+    match SystemRandom::new().fill(&mut bytes) {
+        Ok(()) => f64::from(u32::from_le_bytes(bytes)) / (f64::from(u32::MAX) + 1.0),
+        Err(_) => 0.5,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_within_bounds_and_retry_after_wins() {
+        let policy = RetryPolicy {
+            max_attempts: 5,
+            base_delay: Duration::from_secs(1),
+        };
+        assert_eq!(policy.backoff(1, 0.0), Duration::from_millis(500));
+        assert_eq!(policy.backoff(1, 0.5), Duration::from_millis(750));
+        assert_eq!(policy.backoff(3, 0.0), Duration::from_secs(2));
+        assert_eq!(policy.backoff(30, 0.0), MAX_DELAY / 2);
+        for _ in 0..20 {
+            let delay = policy.delay(2, None);
+            assert!((Duration::from_secs(1)..=Duration::from_secs(2)).contains(&delay));
+        }
+        assert_eq!(
+            policy.delay(1, Some(Duration::from_secs(7))),
+            Duration::from_secs(7)
+        );
+        assert_eq!(
+            policy.delay(1, Some(Duration::from_secs(3600))),
+            MAX_RETRY_AFTER
+        );
+
+        assert!(retryable_status(429) && retryable_status(503) && retryable_status(408));
+        assert!(!retryable_status(400) && !retryable_status(401) && !retryable_status(403));
+
+        // 1994-11-06T08:49:37Z
+        let now = UNIX_EPOCH + Duration::from_secs(784_111_777);
+        assert_eq!(retry_after(" 120 ", now), Some(Duration::from_secs(120)));
+        assert_eq!(
+            retry_after("Sun, 06 Nov 1994 08:50:07 GMT", now),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            retry_after("Sun, 06 Nov 1994 08:00:00 GMT", now),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(retry_after("Sunday, 06-Nov-94 08:49:37 GMT", now), None);
+        assert_eq!(retry_after("soon", now), None);
+    }
+}
@@ -0,0 +1,289 @@
+// Latency figures for the live Deepgram stream: how evenly audio goes out, how much of it
+// is waiting for the socket and how long the first words take to come back
+// The socket task records them as it goes; `get_stream_stats` and the diagnostics bundle
+// read them. Every queue on the way is bounded, so a slow connection shows up here as a
+// full queue and a `stream-lagging` event, and the capture drops chunks rather than
+// holding on to them
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, State};
+
+use crate::audio::BYTES_PER_MS;
+use crate::state::AppState;
+
+/// Event emitted with a `StreamLagging` when audio starts piling up in front of the
+/// socket, and again once it has caught up
+pub const EVENT_STREAM_LAGGING: &str = "stream-lagging";
+
+/// Gaps between chunks longer than this are pauses, held-back silence or reconnects,
+/// not send jitter, and are left out of the average
+const MAX_SEND_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Payload of the `stream-lagging` event
+#[derive(Debug, Clone, Serialize)]
+pub struct StreamLagging {
+    /// False when the queue has drained again
+    pub lagging: bool,
+    pub buffer_depth: usize,
+    pub buffer_capacity: usize,
+}
+
+/// Figures for the active session, as returned by `get_stream_stats`
+#[derive(Debug, Clone, Serialize)]
+pub struct StreamStats {
+    /// False once the session has ended; only the diagnostics bundle sees those
+    pub active: bool,
+    pub chunks_sent: u64,
+    pub bytes_sent: u64,
+    /// Mean audio per chunk sent
+    pub avg_chunk_ms: Option<f64>,
+    /// Mean time between two chunks going out; None before the second one
+    pub avg_send_interval_ms: Option<f64>,
+    /// Chunks waiting for the socket, and how many fit
+    pub buffer_depth: usize,
+    pub buffer_capacity: usize,
+    /// From the first audio sent to the first words shown; None until then
+    pub first_partial_latency_ms: Option<u64>,
+    /// Chunks the capture dropped because the queues were full
+    pub dropped_chunks: u64,
+    pub lagging: bool,
+}
+
+/// Running figures for one session
+struct SessionStats {
+    capacity: usize,
+    channels: u16,
+    depth: usize,
+    lagging: bool,
+    chunks_sent: u64,
+    bytes_sent: u64,
+    /// Live chunks only; replayed audio goes out in a burst
+    intervals: u64,
+    interval_total: Duration,
+    first_sent: Option<Instant>,
+    last_sent: Option<Instant>,
+    first_partial: Option<Duration>,
+}
+
+impl SessionStats {
+    fn new(capacity: usize, channels: u16) -> Self {
+        Self {
+            capacity,
+            channels: channels.max(1),
+            depth: 0,
+            lagging: false,
+            chunks_sent: 0,
+            bytes_sent: 0,
+            intervals: 0,
+            interval_total: Duration::ZERO,
+            first_sent: None,
+            last_sent: None,
+            first_partial: None,
+        }
+    }
+
+    fn sent(&mut self, now: Instant, bytes: usize) {
+        if let Some(interval) = self
+            .last_sent
+            .map(|last| now.saturating_duration_since(last))
+        {
+            if interval <= MAX_SEND_INTERVAL {
+                self.intervals += 1;
+                self.interval_total += interval;
+            }
+        }
+        self.last_sent = Some(now);
+        self.first_sent.get_or_insert(now);
+        self.chunks_sent += 1;
+        self.bytes_sent += bytes as u64;
+    }
+
+    fn replayed(&mut self, bytes: usize) {
+        self.chunks_sent += 1;
+        self.bytes_sent += bytes as u64;
+    }
+
+    /// Take the queue depth; returns the new lagging state when it changed
+    /// Lagging starts at half the queue and ends at a quarter, so a queue hovering
+    /// around the threshold doesn't flap
+    fn queued(&mut self, depth: usize) -> Option<bool> {
+        self.depth = depth;
+        let lagging = if self.lagging {
+            depth > self.capacity / 4
+        } else {
+            depth >= (self.capacity / 2).max(1)
+        };
+        (lagging != self.lagging).then(|| {
+            self.lagging = lagging;
+            lagging
+        })
+    }
+
+    fn partial(&mut self, now: Instant) {
+        if let (None, Some(first_sent)) = (self.first_partial, self.first_sent) {
+            self.first_partial = Some(now.saturating_duration_since(first_sent));
+        }
+    }
+
+    fn snapshot(&self, active: bool, dropped_chunks: u64) -> StreamStats {
+        StreamStats {
+            active,
+            chunks_sent: self.chunks_sent,
+            bytes_sent: self.bytes_sent,
+            avg_chunk_ms: (self.chunks_sent > 0).then(|| {
+                self.bytes_sent as f64
+                    / self.chunks_sent as f64
+                    / (f64::from(BYTES_PER_MS) * f64::from(self.channels))
+            }),
+            avg_send_interval_ms: (self.intervals > 0)
+                .then(|| self.interval_total.as_secs_f64() * 1000.0 / self.intervals as f64),
+            buffer_depth: self.depth,
+            buffer_capacity: self.capacity,
+            first_partial_latency_ms: self.first_partial.map(|d| d.as_millis() as u64),
+            dropped_chunks,
+            lagging: self.lagging,
+        }
+    }
+}
+
+/// The current session's figures, kept after it ends for the diagnostics bundle;
+/// part of `StreamState`
+#[derive(Default)]
+pub struct StreamMetrics {
+    /// The session and whether it's still running
+    session: Mutex<Option<(SessionStats, bool)>>,
+    dropped_chunks: AtomicU64,
+}
+
+impl StreamMetrics {
+    /// Start over for a session of `channels`-channel audio whose socket queue holds
+    /// `capacity` chunks
+    pub fn start(&self, capacity: usize, channels: u16) {
+        self.dropped_chunks.store(0, Ordering::Relaxed);
+        if let Ok(mut session) = self.session.lock() {
+            *session = Some((SessionStats::new(capacity, channels), true));
+        }
+    }
+
+    pub fn finish(&self) {
+        if let Ok(mut session) = self.session.lock() {
+            if let Some((_, active)) = session.as_mut() {
+                *active = false;
+            }
+        }
+    }
+
+    /// A chunk of live audio went out
+    pub fn sent(&self, bytes: usize) {
+        let now = Instant::now();
+        self.update(|stats| stats.sent(now, bytes));
+    }
+
+    /// A chunk buffered during a reconnect went out
+    pub fn replayed(&self, bytes: usize) {
+        self.update(|stats| stats.replayed(bytes));
+    }
+
+    /// `depth` chunks are waiting for the socket; emits `stream-lagging` on a change
+    pub fn queued(&self, app: &AppHandle, depth: usize) {
+        let mut capacity = 0;
+        let changed = self
+            .update(|stats| {
+                capacity = stats.capacity;
+                stats.queued(depth)
+            })
+            .flatten();
+        if let Some(lagging) = changed {
+            if lagging {
+                tracing::warn!(
+                    "Audio is backing up in front of Deepgram ({} chunks)",
+                    depth
+                );
+            }
+            let _ = app.emit(
+                EVENT_STREAM_LAGGING,
+                StreamLagging {
+                    lagging,
+                    buffer_depth: depth,
+                    buffer_capacity: capacity,
+                },
+            );
+        }
+    }
+
+    /// Words were shown; the first time, that's the first-partial latency
+    pub fn partial(&self) {
+        let now = Instant::now();
+        self.update(|stats| stats.partial(now));
+    }
+
+    /// The capture dropped a chunk; called from the audio thread, so it never locks
+    pub fn dropped(&self) {
+        self.dropped_chunks.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// The running session's figures, or with `ended` the last session's
+    pub fn snapshot(&self, ended: bool) -> Option<StreamStats> {
+        let session = self.session.lock().ok()?;
+        let (stats, active) = session.as_ref()?;
+        (*active || ended)
+            .then(|| stats.snapshot(*active, self.dropped_chunks.load(Ordering::Relaxed)))
+    }
+
+    /// Apply `f` to the running session; None when there's none
+    fn update<T>(&self, f: impl FnOnce(&mut SessionStats) -> T) -> Option<T> {
+        let mut session = self.session.lock().ok()?;
+        match session.as_mut() {
+            Some((stats, true)) => Some(f(stats)),
+            _ => None,
+        }
+    }
+}
+
+/// Command to read the active session's send and latency figures; None between sessions
+#[tauri::command]
+pub fn get_stream_stats(state: State<'_, AppState>) -> Option<StreamStats> {
+    state.stream.metrics().snapshot(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn figures_cover_live_sends_and_lagging_has_hysteresis() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut stats = SessionStats::new(32, 1);
+        assert_eq!(stats.snapshot(true, 0).avg_send_interval_ms, None);
+
+        stats.partial(at(0));
+        assert_eq!(stats.first_partial, None, "nothing was sent yet");
+        stats.sent(at(0), 3200);
+        stats.sent(at(90), 3200);
+        stats.sent(at(200), 3200);
+        // A pause isn't jitter
+        stats.sent(at(5_000), 3200);
+        stats.replayed(3200);
+        stats.partial(at(450));
+        stats.partial(at(900));
+
+        let snapshot = stats.snapshot(true, 2);
+        assert_eq!(snapshot.chunks_sent, 5);
+        assert_eq!(snapshot.bytes_sent, 16_000);
+        assert_eq!(snapshot.avg_chunk_ms, Some(100.0));
+        assert_eq!(snapshot.avg_send_interval_ms, Some(100.0));
+        assert_eq!(snapshot.first_partial_latency_ms, Some(450));
+        assert_eq!(snapshot.dropped_chunks, 2);
+
+        assert_eq!(stats.queued(15), None);
+        assert_eq!(stats.queued(16), Some(true));
+        assert_eq!(stats.queued(10), None);
+        assert_eq!(stats.queued(8), Some(false));
+        assert_eq!(stats.queued(8), None);
+    }
+}
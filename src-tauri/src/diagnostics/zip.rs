@@ -0,0 +1,174 @@
+// Minimal zip writer for the diagnostics bundle
+// Entries are stored uncompressed, which every unzip tool reads; the bundle is a few
+// hundred KB of text, so compression isn't worth it
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::AppError;
+
+const LOCAL_HEADER: u32 = 0x0403_4b50;
+const CENTRAL_HEADER: u32 = 0x0201_4b50;
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x0605_4b50;
+/// Zip 1.0: stored entries need nothing newer
+const VERSION: u16 = 10;
+/// Names are UTF-8
+const FLAG_UTF8: u16 = 0x0800;
+const METHOD_STORED: u16 = 0;
+
+pub struct ZipWriter {
+    out: Vec<u8>,
+    central: Vec<u8>,
+    entries: u16,
+    time: u16,
+    date: u16,
+}
+
+impl ZipWriter {
+    /// Every entry is stamped with `modified` (UTC; zip has no time zones)
+    pub fn new(modified: SystemTime) -> Self {
+        let (time, date) = dos_timestamp(modified);
+        Self {
+            out: Vec::new(),
+            central: Vec::new(),
+            entries: 0,
+            time,
+            date,
+        }
+    }
+
+    pub fn add(&mut self, name: &str, data: &[u8]) -> Result<(), AppError> {
+        let too_large = || AppError::Internal("Diagnostics bundle is too large".to_string());
+        let size = u32::try_from(data.len()).map_err(|_| too_large())?;
+        let offset = u32::try_from(self.out.len()).map_err(|_| too_large())?;
+        let name_len = u16::try_from(name.len()).map_err(|_| too_large())?;
+        self.entries = self.entries.checked_add(1).ok_or_else(too_large)?;
+        let crc = crc32fast::hash(data);
+
+        put_u32(&mut self.out, LOCAL_HEADER);
+        for value in [VERSION, FLAG_UTF8, METHOD_STORED, self.time, self.date] {
+            put_u16(&mut self.out, value);
+        }
+        for value in [crc, size, size] {
+            put_u32(&mut self.out, value);
+        }
+        put_u16(&mut self.out, name_len);
+        put_u16(&mut self.out, 0);
+        self.out.extend_from_slice(name.as_bytes());
+        self.out.extend_from_slice(data);
+
+        put_u32(&mut self.central, CENTRAL_HEADER);
+        for value in [
+            VERSION,
+            VERSION,
+            FLAG_UTF8,
+            METHOD_STORED,
+            self.time,
+            self.date,
+        ] {
+            put_u16(&mut self.central, value);
+        }
+        for value in [crc, size, size] {
+            put_u32(&mut self.central, value);
+        }
+        // Name length, then no extra field, comment, disk number or internal attributes
+        for value in [name_len, 0, 0, 0, 0] {
+            put_u16(&mut self.central, value);
+        }
+        put_u32(&mut self.central, 0);
+        put_u32(&mut self.central, offset);
+        self.central.extend_from_slice(name.as_bytes());
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<Vec<u8>, AppError> {
+        let too_large = || AppError::Internal("Diagnostics bundle is too large".to_string());
+        let offset = u32::try_from(self.out.len()).map_err(|_| too_large())?;
+        let size = u32::try_from(self.central.len()).map_err(|_| too_large())?;
+        self.out.append(&mut self.central);
+        put_u32(&mut self.out, END_OF_CENTRAL_DIRECTORY);
+        // Single disk
+        for value in [0, 0, self.entries, self.entries] {
+            put_u16(&mut self.out, value);
+        }
+        put_u32(&mut self.out, size);
+        put_u32(&mut self.out, offset);
+        put_u16(&mut self.out, 0);
+        Ok(self.out)
+    }
+}
+
+fn put_u16(out: &mut Vec<u8>, value: u16) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+/// MS-DOS time and date fields; they can't go before 1980 and count seconds in twos
+fn dos_timestamp(time: SystemTime) -> (u16, u16) {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+    if year < 1980 {
+        return (0, (1 << 5) | 1);
+    }
+    let of_day = secs % 86_400;
+    let time = ((of_day / 3600) << 11) | ((of_day / 60 % 60) << 5) | ((of_day % 60) / 2);
+    let date = (((year - 1980) as u64) << 9) | ((month as u64) << 5) | day as u64;
+    (time as u16, date as u16)
+}
+
+/// Year, month and day of the `days`th day since 1970-01-01 (proleptic Gregorian)
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn stored_entries_and_directory_line_up() {
+        // 2024-02-29 13:45:30 UTC
+        let modified = UNIX_EPOCH + Duration::from_secs(1_709_214_330);
+        let mut zip = ZipWriter::new(modified);
+        zip.add("a.txt", b"hello").unwrap();
+        zip.add("b.json", b"{}").unwrap();
+        let bytes = zip.finish().unwrap();
+
+        assert_eq!(bytes[..4], LOCAL_HEADER.to_le_bytes());
+        // CRC-32 of "hello"
+        assert_eq!(bytes[14..18], 0x3610_a686u32.to_le_bytes());
+        let end = bytes.len() - 22;
+        assert_eq!(bytes[end..end + 4], END_OF_CENTRAL_DIRECTORY.to_le_bytes());
+        assert_eq!(u16::from_le_bytes([bytes[end + 10], bytes[end + 11]]), 2);
+        let directory = u32::from_le_bytes(bytes[end + 16..end + 20].try_into().unwrap());
+        assert_eq!(
+            bytes[directory as usize..directory as usize + 4],
+            CENTRAL_HEADER.to_le_bytes()
+        );
+
+        let (time, date) = dos_timestamp(modified);
+        assert_eq!(date, (44 << 9) | (2 << 5) | 29);
+        assert_eq!(time, (13 << 11) | (45 << 5) | 15);
+    }
+}
@@ -0,0 +1,286 @@
+// The one live session there may be, as a state machine:
+// Idle → Starting → Recording ⇄ Paused → Stopping → Idle
+// Starting is claimed before anything connects, so a hotkey and a button racing each
+// other can't open two streams; the loser gets `SessionAlreadyActive`. Stopping a session
+// that's still starting cancels its connection attempt, and the start then puts itself
+// back to Idle. Every transition is sent as one `session-state` event, in order
+// Sessions are numbered so whoever finishes an old one can't end the one after it
+
+use std::sync::Mutex;
+
+use tauri::{AppHandle, Manager};
+use tokio_util::sync::CancellationToken;
+
+use super::SessionState;
+use crate::error::AppError;
+
+/// A claim on Starting, from `SessionManager::begin_start`
+pub struct StartTicket {
+    pub session: u64,
+    /// Cancelled when the session is stopped before it's started
+    pub cancel: CancellationToken,
+}
+
+/// What `SessionManager::begin_stop` found
+#[derive(Debug, PartialEq, Eq)]
+pub enum Stop {
+    /// Nothing to stop, or it's already stopping
+    Nothing,
+    /// The session was still starting; its start cleans up
+    Cancelled,
+    /// This session is now stopping; end it with `ended` once it's finished
+    Running(u64),
+}
+
+#[derive(Default)]
+struct Inner {
+    state: SessionState,
+    /// The current session's number, or the last one's while Idle
+    session: u64,
+    cancel: Option<CancellationToken>,
+}
+
+/// Managed state
+#[derive(Default)]
+pub struct SessionManager {
+    inner: Mutex<Inner>,
+}
+
+impl SessionManager {
+    pub fn state(&self) -> SessionState {
+        self.inner
+            .lock()
+            .map(|inner| inner.state)
+            .unwrap_or_default()
+    }
+
+    /// Claim Starting for a new session; fails with the current state unless Idle
+    pub fn begin_start(&self, app: &AppHandle) -> Result<StartTicket, AppError> {
+        self.try_start(&emitter(app))
+    }
+
+    /// Pause or resume the running session; false if that changed nothing
+    pub fn set_paused(&self, app: &AppHandle, paused: bool) -> bool {
+        self.mark_paused(&emitter(app), paused)
+    }
+
+    /// Move to Stopping; `session` limits this to that session, None stops any
+    pub fn begin_stop(&self, app: &AppHandle, session: Option<u64>) -> Stop {
+        self.try_stop(&emitter(app), session)
+    }
+
+    /// `session` is over: its start failed or was cancelled, it was stopped, or it ended
+    /// on its own. Only the first call for a session does anything
+    pub fn ended(&self, app: &AppHandle, session: u64) {
+        self.mark_ended(&emitter(app), session);
+    }
+
+    pub(crate) fn try_start(&self, emit: &impl Fn(SessionState)) -> Result<StartTicket, AppError> {
+        let mut inner = self
+            .inner
+            .lock()
+            .map_err(|_| AppError::poisoned("Session state"))?;
+        if inner.state != SessionState::Idle {
+            return Err(AppError::SessionAlreadyActive(inner.state));
+        }
+        let cancel = CancellationToken::new();
+        inner.session += 1;
+        inner.cancel = Some(cancel.clone());
+        set(&mut inner, SessionState::Starting, emit);
+        Ok(StartTicket {
+            session: inner.session,
+            cancel,
+        })
+    }
+
+    /// The starting `session` is up; false if it was stopped meanwhile, in which case
+    /// the caller tears it down and calls `ended`
+    pub(crate) fn mark_started(&self, emit: &impl Fn(SessionState), session: u64) -> bool {
+        let Ok(mut inner) = self.inner.lock() else {
+            return false;
+        };
+        if inner.session != session || inner.state != SessionState::Starting {
+            return false;
+        }
+        inner.cancel = None;
+        set(&mut inner, SessionState::Recording, emit);
+        true
+    }
+
+    fn mark_paused(&self, emit: &impl Fn(SessionState), paused: bool) -> bool {
+        let Ok(mut inner) = self.inner.lock() else {
+            return false;
+        };
+        let next = match (inner.state, paused) {
+            (SessionState::Recording, true) => SessionState::Paused,
+            (SessionState::Paused, false) => SessionState::Recording,
+            _ => return false,
+        };
+        set(&mut inner, next, emit);
+        true
+    }
+
+    pub(crate) fn try_stop(&self, emit: &impl Fn(SessionState), session: Option<u64>) -> Stop {
+        let Ok(mut inner) = self.inner.lock() else {
+            return Stop::Nothing;
+        };
+        if session.is_some_and(|session| session != inner.session) {
+            return Stop::Nothing;
+        }
+        match inner.state {
+            SessionState::Idle | SessionState::Stopping => Stop::Nothing,
+            SessionState::Starting => {
+                if let Some(cancel) = inner.cancel.take() {
+                    cancel.cancel();
+                }
+                set(&mut inner, SessionState::Stopping, emit);
+                Stop::Cancelled
+            }
+            SessionState::Recording | SessionState::Paused => {
+                set(&mut inner, SessionState::Stopping, emit);
+                Stop::Running(inner.session)
+            }
+        }
+    }
+
+    pub(crate) fn mark_ended(&self, emit: &impl Fn(SessionState), session: u64) {
+        let Ok(mut inner) = self.inner.lock() else {
+            return;
+        };
+        if inner.session != session || inner.state == SessionState::Idle {
+            return;
+        }
+        // A session that ended by itself still passes through Stopping
+        if matches!(inner.state, SessionState::Recording | SessionState::Paused) {
+            set(&mut inner, SessionState::Stopping, emit);
+        }
+        inner.cancel = None;
+        set(&mut inner, SessionState::Idle, emit);
+    }
+}
+
+/// Emitted while the lock is held, so events go out in the order of the transitions
+fn set(inner: &mut Inner, state: SessionState, emit: &impl Fn(SessionState)) {
+    inner.state = state;
+    emit(state);
+}
+
+/// Sends each state change as `session-state`
+pub(crate) fn emitter(app: &AppHandle) -> impl Fn(SessionState) + '_ {
+    move |state| super::emit_session_state(app, state)
+}
+
+/// Command to read where the session is, for a UI that reloaded mid-session
+#[tauri::command]
+pub fn get_session_state(app: AppHandle) -> SessionState {
+    app.state::<SessionManager>().state()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicI32, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn a_second_start_is_refused_and_a_starting_session_can_be_cancelled() {
+        let manager = SessionManager::default();
+        let events = Mutex::new(Vec::new());
+        let emit = |state| events.lock().unwrap().push(state);
+
+        let ticket = manager.try_start(&emit).unwrap();
+        assert!(matches!(
+            manager.try_start(&emit),
+            Err(AppError::SessionAlreadyActive(SessionState::Starting))
+        ));
+        assert_eq!(manager.try_stop(&emit, None), Stop::Cancelled);
+        assert!(ticket.cancel.is_cancelled());
+        assert!(!manager.mark_started(&emit, ticket.session));
+        manager.mark_ended(&emit, ticket.session);
+
+        let ticket = manager.try_start(&emit).unwrap();
+        assert!(manager.mark_started(&emit, ticket.session));
+        assert!(manager.mark_paused(&emit, true));
+        assert!(!manager.mark_paused(&emit, true));
+        // An old session's end doesn't touch this one
+        manager.mark_ended(&emit, ticket.session - 1);
+        manager.mark_ended(&emit, ticket.session);
+        manager.mark_ended(&emit, ticket.session);
+
+        use SessionState::*;
+        assert_eq!(
+            *events.lock().unwrap(),
+            [Starting, Stopping, Idle, Starting, Recording, Paused, Stopping, Idle]
+        );
+    }
+
+    #[test]
+    fn racing_starts_and_stops_never_open_two_streams() {
+        let manager = Arc::new(SessionManager::default());
+        let events = Arc::new(Mutex::new(Vec::new()));
+        // Streams open right now, as a stand-in for sockets
+        let open = Arc::new(AtomicI32::new(0));
+
+        let starters: Vec<_> = (0..4)
+            .map(|_| {
+                let (manager, events, open) = (manager.clone(), events.clone(), open.clone());
+                thread::spawn(move || {
+                    let emit = |state| events.lock().unwrap().push(state);
+                    for _ in 0..500 {
+                        let Ok(ticket) = manager.try_start(&emit) else {
+                            thread::yield_now();
+                            continue;
+                        };
+                        assert_eq!(open.fetch_add(1, Ordering::SeqCst), 0);
+                        if !manager.mark_started(&emit, ticket.session) {
+                            open.fetch_sub(1, Ordering::SeqCst);
+                            manager.mark_ended(&emit, ticket.session);
+                        }
+                    }
+                })
+            })
+            .collect();
+        let stoppers: Vec<_> = (0..2)
+            .map(|_| {
+                let (manager, events, open) = (manager.clone(), events.clone(), open.clone());
+                thread::spawn(move || {
+                    let emit = |state| events.lock().unwrap().push(state);
+                    for _ in 0..2_000 {
+                        if let Stop::Running(session) = manager.try_stop(&emit, None) {
+                            open.fetch_sub(1, Ordering::SeqCst);
+                            manager.mark_ended(&emit, session);
+                        }
+                    }
+                })
+            })
+            .collect();
+        for handle in starters.into_iter().chain(stoppers) {
+            handle.join().unwrap();
+        }
+        // Whatever is left running is stopped like on quit
+        let emit = |state| events.lock().unwrap().push(state);
+        if let Stop::Running(session) = manager.try_stop(&emit, None) {
+            open.fetch_sub(1, Ordering::SeqCst);
+            manager.mark_ended(&emit, session);
+        }
+
+        assert_eq!(open.load(Ordering::SeqCst), 0);
+        assert_eq!(manager.state(), SessionState::Idle);
+        let events = events.lock().unwrap();
+        use SessionState::*;
+        let mut previous = Idle;
+        for &state in events.iter() {
+            let allowed = match previous {
+                Idle => state == Starting,
+                Starting => matches!(state, Recording | Stopping | Idle),
+                Recording => state == Stopping,
+                Paused => matches!(state, Recording | Stopping),
+                Stopping => state == Idle,
+            };
+            assert!(allowed, "{:?} after {:?}", state, previous);
+            previous = state;
+        }
+    }
+}
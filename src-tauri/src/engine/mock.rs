@@ -0,0 +1,348 @@
+// Mock transcription, for working on the frontend without a Deepgram key or credits
+// A script of timed interim and final results is served as Deepgram streaming messages
+// from a WebSocket on 127.0.0.1, and the Deepgram engine streams to it as it would to
+// Deepgram, so events, dictation commands, history and recovery all behave as in a real
+// session. On with `engine: "mock"` or SUBSPACE_MOCK_TRANSCRIPTION=1; its sessions are
+// tagged `mock` and left out of usage
+//
+// Script lines, each waiting `delay_ms` after the one before (times are in seconds):
+//   {"delay_ms": 400, "type": "interim", "start": 0.0, "end": 1.2, "text": "so the"}
+//   {"delay_ms": 500, "type": "final", "start": 0.0, "end": 2.1, "text": "So the plan.",
+//    "speech_final": true, "speaker": 0, "uncertain": ["plan."]}
+//   {"delay_ms": 500, "type": "final", ..., "turns": [{"speaker": 1, "text": "Yes."}]}
+//   {"delay_ms": 300, "type": "utterance_end", "last_word_end": 2.1}
+//   {"delay_ms": 0, "type": "raw", "message": { any Deepgram message }}
+
+use std::net::Ipv4Addr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures_util::{SinkExt, StreamExt};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tauri::async_runtime::JoinHandle;
+use tauri::AppHandle;
+use tokio::net::{TcpListener, TcpStream};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+
+use super::pause::SessionPause;
+use super::TranscriptionEngine;
+use crate::config::{NetworkSettings, TranscriptionSettings};
+use crate::deepgram::network::Route;
+use crate::deepgram::proxy::DeepgramEngine;
+use crate::error::AppError;
+use crate::state::blocking;
+
+/// Set to 1 to use the mock engine whatever the settings say
+pub const ENV_MOCK: &str = "SUBSPACE_MOCK_TRANSCRIPTION";
+/// Provider recorded with mock sessions
+pub const PROVIDER: &str = "mock";
+/// Tag given to mock sessions in history
+pub const TAG: &str = "mock";
+const BUNDLED_SCRIPT: &str = include_str!("fixtures/mock_session.jsonl");
+/// Confidence of scripted words, and of the ones listed as `uncertain`
+const CONFIDENCE: f64 = 0.95;
+const UNCERTAIN_CONFIDENCE: f64 = 0.4;
+
+/// Whether the environment turns the mock engine on
+pub fn forced_by_env() -> bool {
+    std::env::var(ENV_MOCK).is_ok_and(|value| matches!(value.trim(), "1" | "true"))
+}
+
+/// Whether live sessions are mocked, so no key is needed and nothing is billed
+pub fn enabled(app: &AppHandle) -> bool {
+    crate::config::transcription_settings(app).engine == crate::config::EngineKind::Mock
+}
+
+#[derive(Debug, Deserialize)]
+struct Line {
+    #[serde(default)]
+    delay_ms: u64,
+    #[serde(flatten)]
+    message: Scripted,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Scripted {
+    Interim(ScriptedResult),
+    Final(ScriptedResult),
+    UtteranceEnd {
+        last_word_end: f64,
+    },
+    /// Sent as it is, for anything else Deepgram sends
+    Raw {
+        message: Value,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+struct ScriptedResult {
+    start: f64,
+    end: f64,
+    #[serde(default)]
+    text: String,
+    #[serde(default)]
+    speaker: Option<u32>,
+    /// Speaker turns of a diarized result, in place of `text` and `speaker`
+    #[serde(default)]
+    turns: Vec<Turn>,
+    #[serde(default)]
+    speech_final: bool,
+    /// Words given a low confidence
+    #[serde(default)]
+    uncertain: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Turn {
+    speaker: u32,
+    text: String,
+}
+
+/// The script as Deepgram messages, each with the wait before it
+/// Speakers are dropped unless `diarize` is on, as Deepgram would
+fn parse(script: &str, diarize: bool) -> Result<Vec<(Duration, String)>, AppError> {
+    script
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            let line: Line = serde_json::from_str(line).map_err(|e| {
+                AppError::InvalidInput(format!("Mock script line {}: {}", index + 1, e))
+            })?;
+            let message = match line.message {
+                Scripted::Interim(result) => results(&result, false, diarize),
+                Scripted::Final(result) => results(&result, true, diarize),
+                Scripted::UtteranceEnd { last_word_end } => json!({
+                    "type": "UtteranceEnd",
+                    "channel": [0, 1],
+                    "last_word_end": last_word_end,
+                }),
+                Scripted::Raw { message } => message,
+            };
+            Ok((Duration::from_millis(line.delay_ms), message.to_string()))
+        })
+        .collect()
+}
+
+/// A `Results` message with the words spread evenly over the result's span
+fn results(result: &ScriptedResult, is_final: bool, diarize: bool) -> Value {
+    let turns: Vec<(Option<u32>, &str)> = if result.turns.is_empty() {
+        vec![(result.speaker, result.text.as_str())]
+    } else {
+        result
+            .turns
+            .iter()
+            .map(|turn| (Some(turn.speaker), turn.text.as_str()))
+            .collect()
+    };
+    let spoken: Vec<(Option<u32>, &str)> = turns
+        .iter()
+        .flat_map(|(speaker, text)| text.split_whitespace().map(move |word| (*speaker, word)))
+        .collect();
+    let step = (result.end - result.start) / spoken.len().max(1) as f64;
+    let words: Vec<Value> = spoken
+        .iter()
+        .enumerate()
+        .map(|(index, (speaker, word))| {
+            let confidence = if result.uncertain.iter().any(|uncertain| uncertain == word) {
+                UNCERTAIN_CONFIDENCE
+            } else {
+                CONFIDENCE
+            };
+            json!({
+                "word": word
+                    .trim_matches(|c: char| !c.is_alphanumeric() && c != '\'')
+                    .to_lowercase(),
+                "punctuated_word": word,
+                "start": result.start + step * index as f64,
+                "end": result.start + step * (index + 1) as f64,
+                "confidence": confidence,
+                "speaker": speaker.filter(|_| diarize),
+            })
+        })
+        .collect();
+    let transcript: Vec<&str> = spoken.iter().map(|(_, word)| *word).collect();
+    json!({
+        "type": "Results",
+        "start": result.start,
+        "duration": result.end - result.start,
+        "is_final": is_final,
+        "speech_final": is_final && result.speech_final,
+        "channel_index": [0, 1],
+        "channel": {
+            "alternatives": [{
+                "transcript": transcript.join(" "),
+                "confidence": CONFIDENCE,
+                "words": words,
+            }],
+        },
+    })
+}
+
+/// Serve `script` until aborted, one connection at a time; a reconnect carries on where
+/// the dropped connection left off
+async fn serve(listener: TcpListener, script: Vec<(Duration, String)>, pause: Arc<SessionPause>) {
+    let mut next = 0;
+    while let Ok((stream, _)) = listener.accept().await {
+        match tokio_tungstenite::accept_async(stream).await {
+            Ok(socket) => replay(socket, &script, &mut next, &pause).await,
+            Err(e) => tracing::warn!("Mock transcription handshake failed: {}", e),
+        }
+    }
+}
+
+/// Send the script from `next` on, on time and not while paused, dropping the audio, until
+/// the client closes the stream; once the script is done the connection just stays open
+async fn replay(
+    socket: WebSocketStream<TcpStream>,
+    script: &[(Duration, String)],
+    next: &mut usize,
+    pause: &SessionPause,
+) {
+    let (mut write, mut read) = socket.split();
+    let due_after = |next: usize| {
+        script
+            .get(next)
+            .map(|(delay, _)| tokio::time::Instant::now() + *delay)
+    };
+    let mut due = due_after(*next);
+    loop {
+        let send = async {
+            match due {
+                Some(due) => {
+                    tokio::time::sleep_until(due).await;
+                    pause.resumed().await;
+                }
+                None => std::future::pending().await,
+            }
+        };
+        tokio::select! {
+            () = send => {
+                let (_, message) = &script[*next];
+                if write.send(Message::Text(message.as_str().into())).await.is_err() {
+                    return;
+                }
+                *next += 1;
+                due = due_after(*next);
+            }
+            message = read.next() => match message {
+                Some(Ok(Message::Text(text))) if text.contains("CloseStream") => break,
+                Some(Ok(Message::Close(_)) | Err(_)) | None => return,
+                // Audio, KeepAlive and Finalize
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+    let _ = write.send(Message::Close(None)).await;
+}
+
+/// The Deepgram engine streaming to a local replay of the script
+pub struct MockEngine {
+    stream: DeepgramEngine,
+    server: JoinHandle<()>,
+}
+
+impl TranscriptionEngine for MockEngine {
+    async fn start(
+        app: &AppHandle,
+        mut settings: TranscriptionSettings,
+        pause: Arc<SessionPause>,
+    ) -> Result<Self, AppError> {
+        // The script is one channel
+        settings.multichannel = false;
+        let path = settings.mock_script.clone();
+        let diarize = settings.diarize;
+        let script = blocking(move || {
+            let script = match &path {
+                Some(path) => std::fs::read_to_string(path)
+                    .map_err(|e| AppError::Io(format!("Failed to read {}: {}", path, e)))?,
+                None => BUNDLED_SCRIPT.to_string(),
+            };
+            parse(&script, diarize)
+        })
+        .await?;
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+            .await
+            .map_err(|e| AppError::Stream(format!("Failed to start mock transcription: {}", e)))?;
+        let port = listener.local_addr()?.port();
+        let route = Route::from_settings(&NetworkSettings {
+            api_base_url: format!("http://{}:{}", Ipv4Addr::LOCALHOST, port),
+            ..NetworkSettings::default()
+        })?;
+        let url = crate::deepgram::listen_url(&route, &settings, &[]);
+        tracing::warn!("Mock transcription is on; replaying a script instead of using Deepgram");
+        let server = tauri::async_runtime::spawn(serve(listener, script, Arc::clone(&pause)));
+        match DeepgramEngine::mock(app, settings, pause, url).await {
+            Ok(stream) => Ok(Self { stream, server }),
+            Err(e) => {
+                server.abort();
+                Err(e)
+            }
+        }
+    }
+
+    async fn feed_audio(&mut self, chunk: Vec<u8>) -> Result<(), AppError> {
+        self.stream.feed_audio(chunk).await
+    }
+
+    async fn finalize(self) -> String {
+        let text = self.stream.finalize().await;
+        self.server.abort();
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::deepgram::StreamMessage;
+
+    #[test]
+    fn bundled_script_is_deepgram_messages() {
+        let script = parse(BUNDLED_SCRIPT, true).unwrap();
+        let (mut interims, mut finals, mut utterance_ends, mut speakers) = (0, 0, 0, Vec::new());
+        for (_, message) in &script {
+            match serde_json::from_str::<StreamMessage>(message).unwrap() {
+                StreamMessage::Results(results) if results.is_final => {
+                    finals += 1;
+                    speakers.extend(results.to_segments().iter().map(|segment| segment.speaker));
+                }
+                StreamMessage::Results(_) => interims += 1,
+                StreamMessage::UtteranceEnd(_) => utterance_ends += 1,
+                other => panic!("unexpected {:?}", other),
+            }
+        }
+        assert!(interims > finals && finals > utterance_ends && utterance_ends > 0);
+        // The diarized result splits into two turns
+        assert!(speakers
+            .windows(3)
+            .any(|turns| turns == [Some(0), Some(1), Some(0)]));
+    }
+
+    #[test]
+    fn scripted_words_follow_the_settings_and_bad_lines_name_their_number() {
+        let script = "\n{\"type\": \"final\", \"start\": 1.0, \"end\": 2.0, \"speaker\": 2, \
+                      \"text\": \"Ship it, Synder.\", \"uncertain\": [\"Synder.\"]}\n";
+        let (delay, message) = &parse(script, false).unwrap()[0];
+        assert_eq!(*delay, Duration::ZERO);
+        let Ok(StreamMessage::Results(results)) = serde_json::from_str(message) else {
+            panic!("expected results");
+        };
+        let words = &results.channel.alternatives[0].words;
+        assert_eq!(words[1].word, "it");
+        assert_eq!(words[2].punctuated_word.as_deref(), Some("Synder."));
+        assert_eq!(words[2].confidence, UNCERTAIN_CONFIDENCE);
+        assert_eq!((words[0].start, words[2].end), (1.0, 2.0));
+        assert!(words.iter().all(|word| word.speaker.is_none()));
+
+        let err = parse("{\"type\": \"final\"}\n{\"type\": \"shout\"}", false).unwrap_err();
+        assert!(
+            err.to_string().starts_with("Mock script line 1:"),
+            "{}",
+            err
+        );
+    }
+}
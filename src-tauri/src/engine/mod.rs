@@ -0,0 +1,108 @@
+// Live transcription engines behind one interface
+// Every engine emits the same transcript and connection-state events, so the frontend
+// doesn't know or care which one is running
+
+pub mod manager;
+pub mod mock;
+pub mod models;
+pub mod openai;
+pub mod pause;
+pub mod providers;
+pub mod utterances;
+pub mod watchdog;
+#[cfg(feature = "whisper-local")]
+pub mod whisper_local;
+
+use std::future::Future;
+use std::sync::Arc;
+
+use serde::Serialize;
+use tauri::async_runtime::JoinHandle;
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::sync::mpsc;
+
+use self::manager::SessionManager;
+use self::pause::SessionPause;
+use crate::config::TranscriptionSettings;
+use crate::error::AppError;
+
+/// Event emitted with a `SessionState` on every transition of the live session, see
+/// `manager::SessionManager`
+pub const EVENT_SESSION_STATE: &str = "session-state";
+
+/// Payload of the `session-state` event
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionState {
+    #[default]
+    Idle,
+    /// Connecting, or loading the model
+    Starting,
+    Recording,
+    Paused,
+    /// Its last results are coming in and it's being saved
+    Stopping,
+}
+
+impl SessionState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Idle => "idle",
+            Self::Starting => "starting",
+            Self::Recording => "recording",
+            Self::Paused => "paused",
+            Self::Stopping => "stopping",
+        }
+    }
+}
+
+pub fn emit_session_state(app: &AppHandle, state: SessionState) {
+    crate::wake_word::session_changed(app, state);
+    crate::overlay::session_changed(app, state);
+    crate::deep_link::session_changed(app, state);
+    let _ = app.emit(EVENT_SESSION_STATE, state);
+}
+
+/// One live transcription session
+pub trait TranscriptionEngine: Sized + Send + 'static {
+    /// Connect or load the model; errors surface from `start_session`
+    /// No audio arrives while `pause` is paused; only un-paused time counts as duration
+    fn start(
+        app: &AppHandle,
+        settings: TranscriptionSettings,
+        pause: Arc<SessionPause>,
+    ) -> impl Future<Output = Result<Self, AppError>> + Send;
+
+    /// Take one chunk of 16 kHz mono linear16 audio
+    /// An error means the session has ended on its own and wants no more audio
+    fn feed_audio(&mut self, chunk: Vec<u8>) -> impl Future<Output = Result<(), AppError>> + Send;
+
+    /// Flush what's left, emit the last results and save the session to history
+    /// Returns the session's final transcript, or its translation when translation is on
+    fn finalize(self) -> impl Future<Output = String> + Send;
+}
+
+/// Run a started engine for `session` on its own task, feeding it audio until the sender
+/// is dropped. The task yields the final transcript, and the session is Idle once it has
+pub fn spawn<E: TranscriptionEngine>(
+    app: &AppHandle,
+    mut engine: E,
+    mut audio_rx: mpsc::Receiver<Vec<u8>>,
+    session: u64,
+) -> JoinHandle<String> {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        while let Some(chunk) = audio_rx.recv().await {
+            if engine.feed_audio(chunk).await.is_err() {
+                break;
+            }
+        }
+        drop(audio_rx);
+        // Already Stopping unless the engine ended by itself
+        let manager = app.state::<SessionManager>();
+        manager.begin_stop(&app, Some(session));
+        let text = engine.finalize().await;
+        manager.ended(&app, session);
+        text
+    })
+}
@@ -0,0 +1,233 @@
+// whisper.cpp model files: download with progress and checksum check, and listing
+// Models come from the ggerganov/whisper.cpp repository on Hugging Face
+// The checksum is fetched from that same host, so it catches truncated or corrupted
+// downloads but is no defence against a tampered repository or host
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::io::AsyncWriteExt;
+
+use crate::error::AppError;
+use crate::state::{blocking, AppState};
+
+/// Event emitted while a model downloads
+pub const EVENT_MODEL_DOWNLOAD_PROGRESS: &str = "whisper-model-download-progress";
+
+/// Directory inside the app data dir holding downloaded models
+const MODELS_DIR: &str = "whisper-models";
+const DOWNLOAD_URL: &str = "https://huggingface.co/ggerganov/whisper.cpp/resolve/main";
+/// Lists the repository's files with the SHA-256 of each LFS object
+/// Served by the same host as the models, so it is only an integrity check, not authentication
+const TREE_URL: &str = "https://huggingface.co/api/models/ggerganov/whisper.cpp/tree/main";
+
+/// Model sizes offered for download
+pub const MODEL_SIZES: &[&str] = &[
+    "tiny",
+    "tiny.en",
+    "base",
+    "base.en",
+    "small",
+    "small.en",
+    "medium",
+    "medium.en",
+    "large-v3",
+    "large-v3-turbo",
+];
+
+/// Payload of the `whisper-model-download-progress` event
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelDownloadProgress {
+    pub size: String,
+    pub downloaded_bytes: u64,
+    pub total_bytes: Option<u64>,
+}
+
+/// A model on disk, as returned by `list_downloaded_models`
+#[derive(Debug, Clone, Serialize)]
+pub struct DownloadedModel {
+    pub size: String,
+    pub path: String,
+    pub bytes: u64,
+}
+
+/// One entry of the Hugging Face tree listing
+#[derive(Debug, Deserialize)]
+struct TreeEntry {
+    path: String,
+    lfs: Option<LfsInfo>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LfsInfo {
+    /// SHA-256 of the file contents
+    oid: String,
+}
+
+fn file_name(size: &str) -> String {
+    format!("ggml-{}.bin", size)
+}
+
+fn models_dir(app: &AppHandle) -> Result<PathBuf, AppError> {
+    app.path()
+        .app_data_dir()
+        .map(|dir| dir.join(MODELS_DIR))
+        .map_err(|e| AppError::Config(format!("Could not resolve app data directory: {}", e)))
+}
+
+/// Where a model of `size` lives once downloaded
+pub fn model_path(app: &AppHandle, size: &str) -> Result<PathBuf, AppError> {
+    Ok(models_dir(app)?.join(file_name(size)))
+}
+
+fn validate_size(size: &str) -> Result<(), AppError> {
+    if MODEL_SIZES.contains(&size) {
+        Ok(())
+    } else {
+        Err(AppError::InvalidInput(format!(
+            "Unknown Whisper model \"{}\"; expected one of {}",
+            size,
+            MODEL_SIZES.join(", ")
+        )))
+    }
+}
+
+/// Expected SHA-256 of a model file, from the repository listing
+/// Whoever can swap the model can swap this too; it only proves the download arrived intact
+async fn expected_sha256(client: &reqwest::Client, size: &str) -> Result<String, AppError> {
+    let name = file_name(size);
+    let entries: Vec<TreeEntry> = client
+        .get(TREE_URL)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    entries
+        .into_iter()
+        .find(|entry| entry.path == name)
+        .and_then(|entry| entry.lfs)
+        .map(|lfs| lfs.oid.to_ascii_lowercase())
+        .ok_or_else(|| AppError::NotFound(format!("No checksum published for {}", name)))
+}
+
+/// Command to download a model into the app data dir, verifying its checksum for integrity
+/// Downloads go to a `.part` file, so an interrupted download never looks complete
+#[tauri::command]
+pub async fn download_whisper_model(
+    app: AppHandle,
+    size: String,
+) -> Result<DownloadedModel, AppError> {
+    validate_size(&size)?;
+    let path = model_path(&app, &size)?;
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+
+    let client = app.state::<AppState>().http();
+    let expected = expected_sha256(&client, &size).await?;
+    let mut response = client
+        .get(format!("{}/{}", DOWNLOAD_URL, file_name(&size)))
+        .send()
+        .await?
+        .error_for_status()?;
+    let total_bytes = response.content_length();
+
+    let part_path = path.with_extension("bin.part");
+    let mut file = tokio::fs::File::create(&part_path).await?;
+    let mut hasher = Sha256::new();
+    let mut downloaded_bytes = 0;
+    let mut last_percent = None;
+    let result: Result<(), AppError> = async {
+        while let Some(chunk) = response.chunk().await? {
+            hasher.update(&chunk);
+            file.write_all(&chunk).await?;
+            downloaded_bytes += chunk.len() as u64;
+
+            // One event per percent (or per MB when the size is unknown)
+            let step = match total_bytes {
+                Some(total) if total > 0 => downloaded_bytes * 100 / total,
+                _ => downloaded_bytes / (1024 * 1024),
+            };
+            if last_percent != Some(step) {
+                last_percent = Some(step);
+                let _ = app.emit(
+                    EVENT_MODEL_DOWNLOAD_PROGRESS,
+                    ModelDownloadProgress {
+                        size: size.clone(),
+                        downloaded_bytes,
+                        total_bytes,
+                    },
+                );
+            }
+        }
+        file.flush().await?;
+        Ok(())
+    }
+    .await;
+    drop(file);
+    if let Err(e) = result {
+        let _ = tokio::fs::remove_file(&part_path).await;
+        return Err(e);
+    }
+
+    let actual: String = hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    if actual != expected {
+        let _ = tokio::fs::remove_file(&part_path).await;
+        return Err(AppError::Network {
+            status: None,
+            message: format!(
+                "Downloaded {} is corrupt (checksum mismatch); please try again",
+                file_name(&size)
+            ),
+        });
+    }
+    tokio::fs::rename(&part_path, &path).await?;
+    tracing::info!("Downloaded Whisper model {}", size);
+    Ok(DownloadedModel {
+        size,
+        path: path.to_string_lossy().into_owned(),
+        bytes: downloaded_bytes,
+    })
+}
+
+fn downloaded_model(path: &Path) -> Option<DownloadedModel> {
+    let size = path
+        .file_name()?
+        .to_str()?
+        .strip_prefix("ggml-")?
+        .strip_suffix(".bin")?
+        .to_string();
+    let bytes = std::fs::metadata(path).ok()?.len();
+    Some(DownloadedModel {
+        size,
+        path: path.to_string_lossy().into_owned(),
+        bytes,
+    })
+}
+
+/// Command to list models that have finished downloading
+#[tauri::command]
+pub async fn list_downloaded_models(app: AppHandle) -> Result<Vec<DownloadedModel>, AppError> {
+    let dir = models_dir(&app)?;
+    blocking(move || downloaded_models(&dir)).await
+}
+
+fn downloaded_models(dir: &Path) -> Result<Vec<DownloadedModel>, AppError> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut models: Vec<DownloadedModel> = entries
+        .filter_map(|entry| downloaded_model(&entry.ok()?.path()))
+        .collect();
+    models.sort_by(|a, b| a.size.cmp(&b.size));
+    Ok(models)
+}
@@ -0,0 +1,381 @@
+// The fallback provider: OpenAI's hosted Whisper
+// There's no streaming endpoint, so audio is cut into utterances like the local engine
+// does (see `utterances`) and each one is uploaded as a WAV file as soon as it ends.
+// Its key is stored like the other secrets, and shared by all profiles
+
+use std::io::Cursor;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::Deserialize;
+use tauri::async_runtime::JoinHandle;
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::sync::mpsc;
+
+use super::pause::SessionPause;
+use super::utterances::{self, Segments, SessionInfo, Splitter, Utterance};
+use super::TranscriptionEngine;
+use crate::config::{Provider, TranscriptionSettings};
+use crate::deepgram::proxy::{emit_state, ConnectionState, EVENT_STREAM_ERROR, STOPPED_REASON};
+use crate::deepgram::retry::{self, RetryPolicy};
+use crate::error::AppError;
+use crate::key_store;
+use crate::recovery::JournalHeader;
+use crate::secrets;
+use crate::state::{blocking, AppState};
+
+const TRANSCRIPTIONS_URL: &str = "https://api.openai.com/v1/audio/transcriptions";
+/// Cheap authenticated request, for checking the key and the connection
+const MODELS_URL: &str = "https://api.openai.com/v1/models";
+/// The one model that returns segment timings
+const MODEL: &str = "whisper-1";
+/// Longest wait for one utterance's transcription
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+/// Attempts per utterance, the first included, when OpenAI fails transiently
+const RETRY_POLICY: RetryPolicy = RetryPolicy {
+    max_attempts: 3,
+    base_delay: Duration::from_millis(500),
+};
+/// Segments Whisper thinks are more likely silence than speech are dropped
+const NO_SPEECH_THRESHOLD: f64 = 0.6;
+
+#[tauri::command]
+pub async fn set_openai_api_key(app: AppHandle, key: String) -> Result<(), AppError> {
+    let key = key_store::normalize_key(&key)?;
+    blocking(move || secrets::save_openai_api_key(&app, &key).map(|_| ())).await
+}
+
+#[tauri::command]
+pub async fn clear_openai_api_key(app: AppHandle) -> Result<(), AppError> {
+    blocking(move || secrets::delete_openai_api_key(&app)).await
+}
+
+/// Whether OpenAI takes `key`; records the outcome as its last known reachability
+pub async fn check(app: &AppHandle, key: &str) -> Result<(), AppError> {
+    let state = app.state::<AppState>();
+    let result = state
+        .http()
+        .get(MODELS_URL)
+        .bearer_auth(key)
+        .timeout(CHECK_TIMEOUT)
+        .send()
+        .await;
+    state.providers.record(
+        Provider::Openai,
+        result.as_ref().is_ok_and(|r| r.status().as_u16() < 500),
+    );
+    let response = result?;
+    match response.status().as_u16() {
+        200..=299 => Ok(()),
+        401 | 403 => Err(AppError::KeyInvalid(
+            "OpenAI rejected the API key".to_string(),
+        )),
+        status => Err(AppError::Network {
+            status: Some(status),
+            message: format!("OpenAI returned HTTP {}", status),
+        }),
+    }
+}
+
+/// A running OpenAI session; audio goes to the upload task over a channel
+pub struct OpenaiWhisper {
+    app: AppHandle,
+    settings: TranscriptionSettings,
+    audio_tx: mpsc::UnboundedSender<Vec<u8>>,
+    task: JoinHandle<Segments>,
+    started: Instant,
+    started_at: i64,
+    pause: Arc<SessionPause>,
+}
+
+impl TranscriptionEngine for OpenaiWhisper {
+    async fn start(
+        app: &AppHandle,
+        settings: TranscriptionSettings,
+        pause: Arc<SessionPause>,
+    ) -> Result<Self, AppError> {
+        emit_state(app, ConnectionState::Connecting);
+        let handle = app.clone();
+        let api_key = blocking(move || {
+            secrets::load_openai_api_key(&handle)?.ok_or(AppError::KeyNotConfigured)
+        })
+        .await?;
+        if let Err(e) = check(app, &api_key).await {
+            emit_state(
+                app,
+                ConnectionState::Closed {
+                    reason: e.to_string(),
+                },
+            );
+            return Err(e);
+        }
+
+        let started_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as i64)
+            .unwrap_or(0);
+        let (audio_tx, audio_rx) = mpsc::unbounded_channel();
+        let uploader = Uploader {
+            app: app.clone(),
+            http: app.state::<AppState>().http(),
+            api_key,
+            language: utterances::whisper_language(&settings.language),
+            splitter: Splitter::new(&settings),
+            segments: Segments::start(
+                app,
+                &settings,
+                JournalHeader {
+                    started_at,
+                    model: model_name(),
+                    language: settings.language.clone(),
+                    provider: Some(Provider::Openai.as_str().to_string()),
+                },
+            ),
+        };
+        let task = tauri::async_runtime::spawn(uploader.run(audio_rx));
+
+        emit_state(app, ConnectionState::Open);
+        Ok(Self {
+            app: app.clone(),
+            settings,
+            audio_tx,
+            task,
+            started: Instant::now(),
+            started_at,
+            pause,
+        })
+    }
+
+    async fn feed_audio(&mut self, chunk: Vec<u8>) -> Result<(), AppError> {
+        self.audio_tx
+            .send(chunk)
+            .map_err(|_| AppError::Stream("OpenAI transcription has stopped".to_string()))
+    }
+
+    async fn finalize(self) -> String {
+        // Closing the channel makes the task upload what's left and return
+        drop(self.audio_tx);
+        let finished = match self.task.await {
+            Ok(segments) => Some(segments.finish().await),
+            Err(e) => {
+                // The journal, if written, is recovered on the next launch
+                tracing::error!("OpenAI upload task failed: {}; session not saved", e);
+                None
+            }
+        };
+        emit_state(
+            &self.app,
+            ConnectionState::Closed {
+                reason: STOPPED_REASON.to_string(),
+            },
+        );
+        let Some(finished) = finished else {
+            return String::new();
+        };
+        let info = SessionInfo {
+            started: self.started,
+            started_at: self.started_at,
+            pause: self.pause,
+            model: model_name(),
+            language: self.settings.language,
+            provider: Some(Provider::Openai.as_str().to_string()),
+        };
+        utterances::save(&self.app, info, finished).await
+    }
+}
+
+/// Sessions are filed under the model, e.g. "openai-whisper-1"
+fn model_name() -> String {
+    format!("openai-{}", MODEL)
+}
+
+/// Part of a `verbose_json` transcription
+#[derive(Deserialize)]
+struct TranscriptionResponse {
+    #[serde(default)]
+    segments: Vec<ResponseSegment>,
+}
+
+#[derive(Deserialize)]
+struct ResponseSegment {
+    /// Seconds from the start of the upload
+    start: f64,
+    end: f64,
+    text: String,
+    #[serde(default)]
+    no_speech_prob: f64,
+}
+
+/// Uploads each utterance as the splitter finds it, one at a time
+struct Uploader {
+    app: AppHandle,
+    http: reqwest::Client,
+    api_key: String,
+    /// None lets Whisper detect it
+    language: Option<String>,
+    splitter: Splitter,
+    segments: Segments,
+}
+
+impl Uploader {
+    async fn run(mut self, mut audio_rx: mpsc::UnboundedReceiver<Vec<u8>>) -> Segments {
+        while let Some(chunk) = audio_rx.recv().await {
+            if let Some(utterance) = self.splitter.push(chunk) {
+                self.transcribe(utterance).await;
+            }
+        }
+        if let Some(utterance) = self.splitter.finish() {
+            self.transcribe(utterance).await;
+        }
+        self.segments
+    }
+
+    /// Upload one utterance, keeping each non-blank segment; a failure loses only it
+    async fn transcribe(&mut self, utterance: Utterance) {
+        let response = match self.upload(&utterance.audio).await {
+            Ok(response) => response,
+            Err(e) => {
+                tracing::warn!("OpenAI failed on an utterance: {}", e);
+                let _ = self.app.emit(
+                    EVENT_STREAM_ERROR,
+                    format!("An utterance couldn't be transcribed: {}", e),
+                );
+                return;
+            }
+        };
+        for segment in response.segments {
+            let text = segment.text.trim();
+            if text.is_empty() || segment.no_speech_prob > NO_SPEECH_THRESHOLD {
+                continue;
+            }
+            let start_ms = utterance.offset_ms + (segment.start * 1000.0).round() as i64;
+            let end_ms = utterance.offset_ms + (segment.end * 1000.0).round() as i64;
+            self.segments.add(start_ms, end_ms, text);
+        }
+    }
+
+    async fn upload(&self, audio: &[u8]) -> Result<TranscriptionResponse, AppError> {
+        let wav = wav_file(audio)?;
+        let boundary = boundary();
+        let mut fields = vec![("model", MODEL), ("response_format", "verbose_json")];
+        if let Some(language) = &self.language {
+            fields.push(("language", language.as_str()));
+        }
+        let body = multipart_body(&boundary, &fields, &wav);
+        let mut attempt = 1;
+        loop {
+            let result = self
+                .http
+                .post(TRANSCRIPTIONS_URL)
+                .bearer_auth(&self.api_key)
+                .header(
+                    reqwest::header::CONTENT_TYPE,
+                    format!("multipart/form-data; boundary={}", boundary),
+                )
+                .body(body.clone())
+                .timeout(REQUEST_TIMEOUT)
+                .send()
+                .await;
+            let retryable = match &result {
+                Ok(response) => retry::retryable_status(response.status().as_u16()),
+                Err(e) => retry::retryable_error(e),
+            };
+            if !retryable || attempt >= RETRY_POLICY.max_attempts {
+                let response = result?;
+                let status = response.status().as_u16();
+                if !response.status().is_success() {
+                    let detail = response.text().await.unwrap_or_default();
+                    return Err(AppError::Network {
+                        status: Some(status),
+                        message: format!("OpenAI returned HTTP {}: {}", status, detail.trim()),
+                    });
+                }
+                return response.json().await.map_err(|e| AppError::Network {
+                    status: Some(status),
+                    message: format!("Unexpected response from OpenAI: {}", e),
+                });
+            }
+            tokio::time::sleep(RETRY_POLICY.delay(attempt, None)).await;
+            attempt += 1;
+        }
+    }
+}
+
+/// 16 kHz mono linear16 as a WAV file
+fn wav_file(audio: &[u8]) -> Result<Vec<u8>, AppError> {
+    let spec = hound::WavSpec {
+        channels: 1,
+        sample_rate: 16_000,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut file = Cursor::new(Vec::with_capacity(audio.len() + 44));
+    let mut writer = hound::WavWriter::new(&mut file, spec)
+        .map_err(|e| AppError::Internal(format!("Failed to encode audio: {}", e)))?;
+    for bytes in audio.chunks_exact(2) {
+        writer
+            .write_sample(i16::from_le_bytes([bytes[0], bytes[1]]))
+            .map_err(|e| AppError::Internal(format!("Failed to encode audio: {}", e)))?;
+    }
+    writer
+        .finalize()
+        .map_err(|e| AppError::Internal(format!("Failed to encode audio: {}", e)))?;
+    Ok(file.into_inner())
+}
+
+/// A form boundary that won't turn up in the audio by chance
+fn boundary() -> String {
+    let mut bytes = [0u8; 16];
+    let _ = SystemRandom::new().fill(&mut bytes);
+    let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("subspace-{}", hex)
+}
+
+/// A multipart/form-data body of text `fields` and the WAV as "file"
+fn multipart_body(boundary: &str, fields: &[(&str, &str)], wav: &[u8]) -> Vec<u8> {
+    let mut body = Vec::with_capacity(wav.len() + 512);
+    for (name, value) in fields {
+        body.extend_from_slice(
+            format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n",
+                boundary, name, value
+            )
+            .as_bytes(),
+        );
+    }
+    body.extend_from_slice(
+        format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"utterance.wav\"\r\nContent-Type: audio/wav\r\n\r\n",
+            boundary
+        )
+        .as_bytes(),
+    );
+    body.extend_from_slice(wav);
+    body.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uploads_are_wav_files_in_a_multipart_form() {
+        let wav = wav_file(&[1, 0, 255, 255]).unwrap();
+        assert_eq!(&wav[..4], b"RIFF");
+        assert_eq!(&wav[8..12], b"WAVE");
+        assert_eq!(&wav[wav.len() - 4..], &[1, 0, 255, 255]);
+
+        let body = multipart_body("b0undary", &[("model", "whisper-1")], b"RIFF");
+        let body = String::from_utf8(body).unwrap();
+        assert_eq!(
+            body,
+            "--b0undary\r\nContent-Disposition: form-data; name=\"model\"\r\n\r\nwhisper-1\r\n\
+             --b0undary\r\nContent-Disposition: form-data; name=\"file\"; filename=\"utterance.wav\"\r\n\
+             Content-Type: audio/wav\r\n\r\nRIFF\r\n--b0undary--\r\n"
+        );
+        assert_ne!(boundary(), boundary());
+    }
+}
@@ -0,0 +1,129 @@
+// Pausing a live session without ending it
+// While paused no audio reaches the engine; the clock here excludes paused time
+// from the session's duration, and engines can wait on it to act on resume
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tokio::sync::watch;
+
+/// Pause state of one live session, shared by its stream handle and its engine
+pub struct SessionPause {
+    paused: watch::Sender<bool>,
+    clock: Mutex<PauseClock>,
+}
+
+impl SessionPause {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            paused: watch::Sender::new(false),
+            clock: Mutex::new(PauseClock::default()),
+        })
+    }
+
+    /// Returns false if the session was already paused
+    pub fn pause(&self) -> bool {
+        let changed = self
+            .paused
+            .send_if_modified(|paused| !std::mem::replace(paused, true));
+        if changed {
+            if let Ok(mut clock) = self.clock.lock() {
+                clock.pause_at(Instant::now());
+            }
+        }
+        changed
+    }
+
+    /// Returns false if the session wasn't paused
+    pub fn resume(&self) -> bool {
+        let changed = self
+            .paused
+            .send_if_modified(|paused| std::mem::replace(paused, false));
+        if changed {
+            if let Ok(mut clock) = self.clock.lock() {
+                clock.resume_at(Instant::now());
+            }
+        }
+        changed
+    }
+
+    pub fn is_paused(&self) -> bool {
+        *self.paused.borrow()
+    }
+
+    /// Wait until the session isn't paused
+    pub async fn resumed(&self) {
+        let mut paused = self.paused.subscribe();
+        // The sender lives as long as `self`, so this only ends once un-paused
+        let _ = paused.wait_for(|paused| !paused).await;
+    }
+
+    /// Time since `started` that the session wasn't paused
+    pub fn active_since(&self, started: Instant) -> Duration {
+        let now = Instant::now();
+        let paused = self
+            .clock
+            .lock()
+            .map(|clock| clock.paused_at(now))
+            .unwrap_or_default();
+        now.saturating_duration_since(started)
+            .saturating_sub(paused)
+    }
+}
+
+/// Time spent paused, including a pause still in progress
+#[derive(Debug, Default)]
+struct PauseClock {
+    since: Option<Instant>,
+    total: Duration,
+}
+
+impl PauseClock {
+    fn pause_at(&mut self, now: Instant) {
+        self.since.get_or_insert(now);
+    }
+
+    fn resume_at(&mut self, now: Instant) {
+        if let Some(since) = self.since.take() {
+            self.total += now.saturating_duration_since(since);
+        }
+    }
+
+    fn paused_at(&self, now: Instant) -> Duration {
+        self.total
+            + self
+                .since
+                .map(|since| now.saturating_duration_since(since))
+                .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clock_counts_finished_and_running_pauses() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut clock = PauseClock::default();
+        clock.pause_at(at(10));
+        clock.pause_at(at(12));
+        clock.resume_at(at(15));
+        clock.resume_at(at(16));
+        assert_eq!(clock.paused_at(at(20)), Duration::from_secs(5));
+        clock.pause_at(at(30));
+        assert_eq!(clock.paused_at(at(34)), Duration::from_secs(9));
+    }
+
+    #[test]
+    fn pause_and_resume_report_changes_only() {
+        let pause = SessionPause::new();
+        assert!(!pause.resume());
+        assert!(pause.pause());
+        assert!(!pause.pause());
+        assert!(pause.is_paused());
+        assert!(pause.resume());
+        assert!(!pause.is_paused());
+    }
+}
@@ -0,0 +1,78 @@
+// Cloud providers a live session can run on: Deepgram, and the fallback that takes over
+// when a session can't reach it
+// What was last learned about each one's reachability, from sessions starting and from
+// health checks, is kept for `get_app_health`
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+use crate::config::Provider;
+
+/// Event emitted with a `ProviderFallback` when a session starts on the fallback provider
+pub const EVENT_PROVIDER_FALLBACK: &str = "provider-fallback";
+
+/// Payload of the `provider-fallback` event
+#[derive(Debug, Clone, Serialize)]
+pub struct ProviderFallback {
+    pub from: Provider,
+    pub to: Provider,
+    /// Why `from` couldn't be used
+    pub reason: String,
+}
+
+/// The last time a provider was tried
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Reachability {
+    pub reachable: bool,
+    /// Unix time in milliseconds
+    pub checked_at: i64,
+}
+
+/// What a provider does for the active profile
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProviderRole {
+    Primary,
+    Fallback,
+}
+
+/// One configured provider, as reported by `get_app_health`
+#[derive(Debug, Clone, Serialize)]
+pub struct ProviderHealth {
+    pub provider: Provider,
+    pub role: ProviderRole,
+    pub key_configured: bool,
+    /// None until it has been tried
+    pub last_reachability: Option<Reachability>,
+}
+
+/// Last known reachability by provider; part of `AppState`
+#[derive(Default)]
+pub struct ProviderStatus {
+    checks: Mutex<HashMap<Provider, Reachability>>,
+}
+
+impl ProviderStatus {
+    pub fn record(&self, provider: Provider, reachable: bool) {
+        let checked_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as i64)
+            .unwrap_or(0);
+        if let Ok(mut checks) = self.checks.lock() {
+            checks.insert(
+                provider,
+                Reachability {
+                    reachable,
+                    checked_at,
+                },
+            );
+        }
+    }
+
+    pub fn last(&self, provider: Provider) -> Option<Reachability> {
+        self.checks.lock().ok()?.get(&provider).copied()
+    }
+}
@@ -0,0 +1,319 @@
+// What the engines that transcribe whole utterances share: local whisper.cpp and OpenAI's
+// hosted Whisper
+// Audio is cut into utterances at pauses and each one is transcribed as it ends, so
+// results arrive as final segments a moment after the speaker stops
+
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Instant;
+
+use tauri::{AppHandle, Manager};
+
+use super::pause::SessionPause;
+use crate::audio::capture::CaptureState;
+use crate::audio::{vad, BYTES_PER_MS};
+use crate::config::TranscriptionSettings;
+use crate::deepgram::proxy::emit_transcript;
+use crate::deepgram::TranscriptEvent;
+use crate::postprocess;
+use crate::postprocess::dictation_commands::DictationCommands;
+use crate::postprocess::replacements::ReplacementState;
+use crate::recovery::{self, JournalHeader, SessionJournal};
+use crate::state::{blocking, AppState};
+use crate::storage::{NewSession, Segment};
+use crate::transcript::revoke::RevokeState;
+use crate::transcript::{FinishedTranscript, LiveTranscript};
+
+/// Silence after speech that ends an utterance
+const UTTERANCE_PAUSE_MS: usize = 700;
+/// Longest utterance transcribed in one go, so long monologues still produce output
+const MAX_UTTERANCE_MS: usize = 15_000;
+/// Audio kept ahead of detected speech so its onset isn't cut off
+const PRE_ROLL_MS: usize = 300;
+
+/// One stretch of speech, as 16 kHz mono linear16
+pub struct Utterance {
+    /// Where it starts in the session's audio
+    pub offset_ms: i64,
+    pub audio: Vec<u8>,
+}
+
+/// Cuts a session's audio into utterances at pauses
+pub struct Splitter {
+    /// Chunk RMS at or above which a chunk counts as speech
+    threshold: f32,
+    pre_roll: VecDeque<Vec<u8>>,
+    utterance: Vec<u8>,
+    /// Stream position of the utterance's first byte, and of the next chunk
+    utterance_start: usize,
+    position: usize,
+    silent_ms: usize,
+}
+
+impl Splitter {
+    pub fn new(settings: &TranscriptionSettings) -> Self {
+        Self {
+            threshold: 10f32.powf(settings.vad_threshold_db / 20.0),
+            pre_roll: VecDeque::new(),
+            utterance: Vec::new(),
+            utterance_start: 0,
+            position: 0,
+            silent_ms: 0,
+        }
+    }
+
+    /// Take a chunk; returns the utterance it ended, if any
+    pub fn push(&mut self, chunk: Vec<u8>) -> Option<Utterance> {
+        let speech = vad::rms(&chunk) >= self.threshold;
+        let chunk_len = chunk.len();
+        let mut ended = None;
+        if self.utterance.is_empty() {
+            if speech {
+                self.utterance_start =
+                    self.position - self.pre_roll.iter().map(Vec::len).sum::<usize>();
+                self.utterance.extend(self.pre_roll.drain(..).flatten());
+                self.utterance.extend(chunk);
+                self.silent_ms = 0;
+            } else {
+                self.pre_roll.push_back(chunk);
+                while self.pre_roll.iter().map(Vec::len).sum::<usize>()
+                    > PRE_ROLL_MS * BYTES_PER_MS as usize
+                {
+                    self.pre_roll.pop_front();
+                }
+            }
+        } else {
+            self.utterance.extend(chunk);
+            self.silent_ms = if speech {
+                0
+            } else {
+                self.silent_ms + chunk_len / BYTES_PER_MS as usize
+            };
+            if self.silent_ms >= UTTERANCE_PAUSE_MS
+                || self.utterance.len() >= MAX_UTTERANCE_MS * BYTES_PER_MS as usize
+            {
+                ended = self.take();
+            }
+        }
+        self.position += chunk_len;
+        ended
+    }
+
+    /// The utterance still going when the audio ended
+    pub fn finish(&mut self) -> Option<Utterance> {
+        self.take()
+    }
+
+    fn take(&mut self) -> Option<Utterance> {
+        (!self.utterance.is_empty()).then(|| Utterance {
+            offset_ms: (self.utterance_start / BYTES_PER_MS as usize) as i64,
+            audio: std::mem::take(&mut self.utterance),
+        })
+    }
+}
+
+/// A session's final segments: adjusted, shown, journaled and kept for history
+pub struct Segments {
+    app: AppHandle,
+    commands: DictationCommands,
+    transcript: LiveTranscript,
+    journal: Option<SessionJournal>,
+}
+
+impl Segments {
+    pub fn start(app: &AppHandle, settings: &TranscriptionSettings, header: JournalHeader) -> Self {
+        Self {
+            app: app.clone(),
+            commands: DictationCommands::load(app, &settings.language),
+            transcript: LiveTranscript::new(app, settings),
+            journal: SessionJournal::start(app, header),
+        }
+    }
+
+    /// Add one segment of `text` as the engine heard it, times from the session start
+    /// "Scratch that" isn't added but takes back the segment before it
+    pub fn add(&mut self, start_ms: i64, end_ms: i64, text: &str) {
+        if self.commands.is_revocation(text) {
+            self.app
+                .state::<RevokeState>()
+                .revoke(&self.app, self.commands.revoke_window_ms());
+            self.transcript.commit(None, start_ms, end_ms, &[], &[]);
+            self.journal_revoked();
+            return;
+        }
+        let replacements = self.app.state::<ReplacementState>().current();
+        let raw = text.to_string();
+        let text = postprocess::rewrite_final(&mut self.commands, &replacements, text, &[]);
+        // Nothing but fillers, which the disfluency pass took out
+        if text.is_empty() {
+            self.transcript.commit(None, start_ms, end_ms, &[], &[]);
+            return;
+        }
+        emit_transcript(
+            &self.app,
+            true,
+            TranscriptEvent {
+                transcript: text.clone(),
+                start: start_ms as f64 / 1000.0,
+                duration: (end_ms - start_ms) as f64 / 1000.0,
+                speech_final: true,
+                speaker: None,
+                words: Vec::new(),
+                raw_transcript: Some(raw.clone()),
+            },
+        );
+        let segment = Segment {
+            start_ms,
+            end_ms,
+            text,
+            words: None,
+            speaker: None,
+            translated: None,
+            channel: None,
+            speaker_name: None,
+        };
+        self.transcript.commit(
+            None,
+            start_ms,
+            end_ms,
+            std::slice::from_ref(&segment),
+            std::slice::from_ref(&raw),
+        );
+        self.journal_revoked();
+        if let Some(journal) = self.journal.as_mut() {
+            journal.append(&segment);
+        }
+    }
+
+    fn journal_revoked(&mut self) {
+        let revoked = self.transcript.take_revoked();
+        if let Some(journal) = self.journal.as_mut() {
+            revoked.iter().for_each(|segment| journal.revoke(segment));
+        }
+    }
+
+    /// The transcript, once outstanding translations are in, and the journal's path
+    pub async fn finish(mut self) -> Finished {
+        Finished {
+            transcript: self.transcript.finish().await,
+            journal: self.journal.map(SessionJournal::finish),
+            disfluencies_removed: self.commands.disfluencies_removed(),
+        }
+    }
+}
+
+/// What `Segments::finish` leaves to save
+pub struct Finished {
+    pub transcript: FinishedTranscript,
+    pub journal: Option<PathBuf>,
+    pub disfluencies_removed: Option<u32>,
+}
+
+/// What a finished session is filed under
+pub struct SessionInfo {
+    pub started: Instant,
+    /// Unix time in milliseconds
+    pub started_at: i64,
+    pub pause: Arc<SessionPause>,
+    pub model: String,
+    pub language: String,
+    pub provider: Option<String>,
+}
+
+/// Save a finished session to history, unless nothing was said
+/// Returns the text to hand back for injection
+pub async fn save(app: &AppHandle, info: SessionInfo, finished: Finished) -> String {
+    let Finished {
+        transcript,
+        journal,
+        disfluencies_removed,
+    } = finished;
+    if transcript.segments.is_empty() {
+        if let Some(path) = &journal {
+            recovery::discard(path);
+        }
+        return String::new();
+    }
+
+    let output = transcript.output();
+    let text = transcript.text.clone();
+    let session = NewSession {
+        started_at: info.started_at,
+        duration_ms: info.pause.active_since(info.started).as_millis() as i64,
+        model: info.model,
+        language: info.language,
+        detected_language: None,
+        text: transcript.text,
+        audio_path: None,
+        segments: transcript.segments,
+        recovered: false,
+        imported: false,
+        audio_source: app
+            .state::<CaptureState>()
+            .source_since(info.started)
+            .map(|source| source.as_str().to_string()),
+        translated_text: transcript.translated_text,
+        translation_language: transcript.translation_language,
+        provider: info.provider,
+        request_id: None,
+        model_version: None,
+        disfluencies_removed,
+    };
+    let storage = Arc::clone(&app.state::<AppState>().storage);
+    match blocking(move || storage.insert_session(&session)).await {
+        Ok(id) => {
+            if let Some(path) = &journal {
+                recovery::discard(path);
+            }
+            let ended_at = info.started_at + info.started.elapsed().as_millis() as i64;
+            crate::transcript_history::record(app, id, &text, ended_at);
+        }
+        // The journal stays, so the session is recovered on the next launch
+        Err(e) => tracing::error!("Failed to save session: {}", e),
+    }
+    output
+}
+
+/// Whisper takes a bare language code ("en"), not a tag like "en-US"; None for "multi",
+/// which means detect
+pub fn whisper_language(language: &str) -> Option<String> {
+    match language.split(['-', '_']).next() {
+        Some("multi") | Some("") | None => None,
+        Some(code) => Some(code.to_ascii_lowercase()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(ms: usize, level: i16) -> Vec<u8> {
+        std::iter::repeat_n(level.to_le_bytes(), ms * BYTES_PER_MS as usize / 2)
+            .flatten()
+            .collect()
+    }
+
+    #[test]
+    fn utterances_end_at_pauses_and_keep_their_onset() {
+        let mut splitter = Splitter::new(&TranscriptionSettings::default());
+        for _ in 0..5 {
+            assert!(splitter.push(chunk(100, 0)).is_none());
+        }
+        assert!(splitter.push(chunk(100, 8_000)).is_none());
+        for _ in 0..6 {
+            assert!(splitter.push(chunk(100, 0)).is_none());
+        }
+        let utterance = splitter.push(chunk(100, 0)).unwrap();
+        // 300 ms of pre-roll ahead of the speech at 500 ms
+        assert_eq!(utterance.offset_ms, 200);
+        assert_eq!(utterance.audio.len(), 1_100 * BYTES_PER_MS as usize);
+
+        assert!(splitter.push(chunk(100, 8_000)).is_none());
+        let tail = splitter.finish().unwrap();
+        assert_eq!(tail.offset_ms, 1_300);
+
+        assert_eq!(whisper_language("en-US").as_deref(), Some("en"));
+        assert_eq!(whisper_language("multi"), None);
+    }
+}
@@ -0,0 +1,161 @@
+// Ending sessions nobody is attending to: one left running past its duration limit, one
+// the machine is about to sleep through, or one whose Deepgram key was removed
+// Either way the session is finalized and saved as if it had been stopped by hand, then
+// `session-auto-stopped` says why. Duration is un-paused time, the same clock history
+// and usage record
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager};
+
+use super::pause::SessionPause;
+use crate::audio::capture::CaptureState;
+use crate::state::{blocking, AppState};
+
+/// Event emitted with a `SessionDurationWarning` once a session has used most of its limit
+pub const EVENT_SESSION_DURATION_WARNING: &str = "session-duration-warning";
+/// Event emitted with a `SessionAutoStopped` when a session was ended for the user
+pub const EVENT_SESSION_AUTO_STOPPED: &str = "session-auto-stopped";
+
+/// How often a session's duration is checked
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+/// Share of the limit at which the warning goes out
+const WARNING_SHARE: f64 = 0.8;
+
+/// Payload of the `session-duration-warning` event
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionDurationWarning {
+    pub elapsed_secs: u64,
+    pub limit_secs: u64,
+    pub remaining_secs: u64,
+}
+
+/// Why a session was stopped without being asked to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AutoStopReason {
+    MaxDuration,
+    SystemSleep,
+    /// The Deepgram key went away mid-session, e.g. from the .env file
+    KeyRemoved,
+}
+
+/// Payload of the `session-auto-stopped` event
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionAutoStopped {
+    pub reason: AutoStopReason,
+}
+
+/// What a session's duration calls for
+#[derive(Debug, PartialEq, Eq)]
+enum Check {
+    Fine,
+    Warn,
+    Stop,
+}
+
+fn check(active: Duration, limit: Duration, warned: bool) -> Check {
+    if active >= limit {
+        Check::Stop
+    } else if !warned && active >= limit.mul_f64(WARNING_SHARE) {
+        Check::Warn
+    } else {
+        Check::Fine
+    }
+}
+
+/// Hold the session `pause` belongs to to `limit_minutes`; 0 means no limit
+/// The watch ends by itself once the session does
+pub fn watch(app: &AppHandle, limit_minutes: u32, pause: Arc<SessionPause>) {
+    if limit_minutes == 0 {
+        return;
+    }
+    let limit = Duration::from_secs(u64::from(limit_minutes) * 60);
+    let app = app.clone();
+    let started = Instant::now();
+    tauri::async_runtime::spawn(async move {
+        let mut warned = false;
+        loop {
+            tokio::time::sleep(CHECK_INTERVAL).await;
+            let running = app.state::<AppState>().stream.running_pause().await;
+            if !running.is_ok_and(|running| Arc::ptr_eq(&running, &pause)) {
+                return;
+            }
+            let active = pause.active_since(started);
+            match check(active, limit, warned) {
+                Check::Fine => {}
+                Check::Warn => {
+                    warned = true;
+                    let _ = app.emit(
+                        EVENT_SESSION_DURATION_WARNING,
+                        SessionDurationWarning {
+                            elapsed_secs: active.as_secs(),
+                            limit_secs: limit.as_secs(),
+                            remaining_secs: limit.saturating_sub(active).as_secs(),
+                        },
+                    );
+                }
+                Check::Stop => {
+                    tracing::info!(
+                        "Stopping the session after {} minutes, its limit",
+                        limit_minutes
+                    );
+                    stop(&app, &pause, AutoStopReason::MaxDuration).await;
+                    return;
+                }
+            }
+        }
+    });
+}
+
+/// Stop whichever session is running for `reason`; false if none was
+pub async fn stop_running(app: &AppHandle, reason: AutoStopReason) -> bool {
+    match app.state::<AppState>().stream.running_pause().await {
+        Ok(pause) => stop(app, &pause, reason).await,
+        Err(_) => false,
+    }
+}
+
+/// Stop the session `pause` belongs to the way silence does: capture first, then the
+/// stream, waiting until the transcript is saved; false if that session had already
+/// ended or didn't finish in time
+async fn stop(app: &AppHandle, pause: &Arc<SessionPause>, reason: AutoStopReason) -> bool {
+    let handle = app.clone();
+    // Joining the capture thread blocks
+    let _ = blocking(move || {
+        handle.state::<CaptureState>().shutdown();
+        Ok(())
+    })
+    .await;
+    let stopped = app
+        .state::<AppState>()
+        .stream
+        .shutdown_session(app, pause)
+        .await
+        .is_some();
+    // The frontend stops its side as for a toggle-mode press; the stream is already gone
+    crate::hotkey::set_dictation(app, false);
+    if stopped {
+        let _ = app.emit(EVENT_SESSION_AUTO_STOPPED, SessionAutoStopped { reason });
+    }
+    stopped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn warns_once_at_four_fifths_and_stops_at_the_limit() {
+        let limit = Duration::from_secs(600);
+        let at = Duration::from_secs;
+        assert_eq!(check(at(479), limit, false), Check::Fine);
+        assert_eq!(check(at(480), limit, false), Check::Warn);
+        assert_eq!(check(at(500), limit, true), Check::Fine);
+        assert_eq!(check(at(600), limit, true), Check::Stop);
+        // A session that skipped past the warning, e.g. across a suspended check, still stops
+        assert_eq!(check(at(700), limit, false), Check::Stop);
+    }
+}
@@ -0,0 +1,214 @@
+// Offline transcription with whisper.cpp
+// Each utterance is transcribed on a worker thread, see `utterances`
+
+use std::sync::mpsc as std_mpsc;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use tauri::AppHandle;
+use whisper_rs::{FullParams, SamplingStrategy, WhisperContext, WhisperContextParameters};
+
+use super::pause::SessionPause;
+use super::utterances::{self, Segments, SessionInfo, Splitter, Utterance};
+use super::{models, TranscriptionEngine};
+use crate::config::TranscriptionSettings;
+use crate::deepgram::proxy::{emit_state, ConnectionState, STOPPED_REASON};
+use crate::error::AppError;
+use crate::recovery::JournalHeader;
+
+/// Segments whisper thinks are more likely silence than speech are dropped
+const NO_SPEECH_THRESHOLD: f32 = 0.6;
+
+/// A running local session; audio goes to the worker thread over a channel
+pub struct WhisperLocal {
+    app: AppHandle,
+    settings: TranscriptionSettings,
+    audio_tx: std_mpsc::Sender<Vec<u8>>,
+    worker: JoinHandle<Segments>,
+    started: Instant,
+    started_at: i64,
+    pause: Arc<SessionPause>,
+}
+
+impl TranscriptionEngine for WhisperLocal {
+    async fn start(
+        app: &AppHandle,
+        settings: TranscriptionSettings,
+        pause: Arc<SessionPause>,
+    ) -> Result<Self, AppError> {
+        emit_state(app, ConnectionState::Connecting);
+        let path = models::model_path(app, &settings.whisper_model)?;
+        if !path.exists() {
+            return Err(AppError::NotFound(format!(
+                "Whisper model \"{}\" is not downloaded",
+                settings.whisper_model
+            )));
+        }
+
+        // Loading a model reads hundreds of MB, so keep it off the async runtime
+        let context = tauri::async_runtime::spawn_blocking(move || {
+            WhisperContext::new_with_params(&path, WhisperContextParameters::default())
+        })
+        .await
+        .map_err(|e| AppError::Internal(format!("Model loading task failed: {}", e)))?
+        .map_err(|e| AppError::Internal(format!("Failed to load Whisper model: {}", e)))?;
+
+        let started_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as i64)
+            .unwrap_or(0);
+        let (audio_tx, audio_rx) = std_mpsc::channel();
+        let worker = Worker {
+            splitter: Splitter::new(&settings),
+            language: utterances::whisper_language(&settings.language)
+                .unwrap_or_else(|| "auto".to_string()),
+            segments: Segments::start(
+                app,
+                &settings,
+                JournalHeader {
+                    started_at,
+                    model: model_name(&settings),
+                    language: settings.language.clone(),
+                    provider: None,
+                },
+            ),
+        };
+        let worker = std::thread::Builder::new()
+            .name("whisper-local".to_string())
+            .spawn(move || worker.run(context, audio_rx))
+            .map_err(|e| AppError::Internal(format!("Failed to start Whisper thread: {}", e)))?;
+
+        emit_state(app, ConnectionState::Open);
+        Ok(Self {
+            app: app.clone(),
+            settings,
+            audio_tx,
+            worker,
+            started: Instant::now(),
+            started_at,
+            pause,
+        })
+    }
+
+    async fn feed_audio(&mut self, chunk: Vec<u8>) -> Result<(), AppError> {
+        self.audio_tx
+            .send(chunk)
+            .map_err(|_| AppError::Stream("Local transcription has stopped".to_string()))
+    }
+
+    async fn finalize(self) -> String {
+        // Closing the channel makes the worker transcribe what's left and return
+        drop(self.audio_tx);
+        let worker = self.worker;
+        let finished = match tauri::async_runtime::spawn_blocking(move || worker.join()).await {
+            Ok(Ok(segments)) => Some(segments.finish().await),
+            _ => {
+                // The journal, if written, is recovered on the next launch
+                tracing::error!("Whisper worker panicked; session not saved");
+                None
+            }
+        };
+        emit_state(
+            &self.app,
+            ConnectionState::Closed {
+                reason: STOPPED_REASON.to_string(),
+            },
+        );
+        let Some(finished) = finished else {
+            return String::new();
+        };
+        // Local sessions cost nothing, so there's no usage record
+        let info = SessionInfo {
+            started: self.started,
+            started_at: self.started_at,
+            pause: self.pause,
+            model: model_name(&self.settings),
+            language: self.settings.language,
+            provider: None,
+        };
+        utterances::save(&self.app, info, finished).await
+    }
+}
+
+/// Sessions are filed under the whisper model, e.g. "whisper-base.en"
+fn model_name(settings: &TranscriptionSettings) -> String {
+    format!("whisper-{}", settings.whisper_model)
+}
+
+/// Transcribes each utterance as the splitter finds it
+struct Worker {
+    splitter: Splitter,
+    language: String,
+    segments: Segments,
+}
+
+impl Worker {
+    fn run(mut self, context: WhisperContext, audio_rx: std_mpsc::Receiver<Vec<u8>>) -> Segments {
+        let mut state = match context.create_state() {
+            Ok(state) => state,
+            Err(e) => {
+                tracing::error!("Failed to create Whisper state: {}", e);
+                return self.segments;
+            }
+        };
+        for chunk in audio_rx {
+            if let Some(utterance) = self.splitter.push(chunk) {
+                self.transcribe(&mut state, utterance);
+            }
+        }
+        if let Some(utterance) = self.splitter.finish() {
+            self.transcribe(&mut state, utterance);
+        }
+        self.segments
+    }
+
+    /// Run whisper on one utterance, keeping each non-blank segment
+    fn transcribe(&mut self, state: &mut whisper_rs::WhisperState, utterance: Utterance) {
+        let samples: Vec<f32> = utterance
+            .audio
+            .chunks_exact(2)
+            .map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]]) as f32 / i16::MAX as f32)
+            .collect();
+
+        let mut params = FullParams::new(SamplingStrategy::Greedy { best_of: 1 });
+        params.set_language(Some(&self.language));
+        params.set_n_threads(num_threads());
+        params.set_no_context(true);
+        params.set_suppress_blank(true);
+        params.set_suppress_nst(true);
+        params.set_print_special(false);
+        params.set_print_progress(false);
+        params.set_print_realtime(false);
+        params.set_print_timestamps(false);
+        if let Err(e) = state.full(params, &samples) {
+            tracing::warn!("Whisper failed on an utterance: {}", e);
+            return;
+        }
+
+        for result in state.as_iter() {
+            if result.no_speech_probability() > NO_SPEECH_THRESHOLD {
+                continue;
+            }
+            let Ok(text) = result.to_str_lossy() else {
+                continue;
+            };
+            let text = text.trim();
+            // Non-speech annotations like "[BLANK_AUDIO]" or "(music)"
+            if text.is_empty() || text.starts_with('[') || text.starts_with('(') {
+                continue;
+            }
+            // Timestamps are in centiseconds from the utterance start
+            let start_ms = utterance.offset_ms + result.start_timestamp() * 10;
+            let end_ms = utterance.offset_ms + result.end_timestamp() * 10;
+            self.segments.add(start_ms, end_ms, text);
+        }
+    }
+}
+
+/// Leave a couple of cores for audio capture and the UI
+fn num_threads() -> i32 {
+    std::thread::available_parallelism()
+        .map(|n| n.get().saturating_sub(2).clamp(1, 8) as i32)
+        .unwrap_or(4)
+}
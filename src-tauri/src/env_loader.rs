@@ -0,0 +1,394 @@
+// .env file loading
+// Debug builds search the usual project locations; release builds only load an
+// explicit `--env-file <path>` or `SUBSPACE_ENV_FILE`. Reloading also unsets what an
+// earlier load set and the file no longer has
+// Parsing is kept pure (`parse_env`) so the quoting rules can be unit tested
+
+use std::collections::BTreeSet;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use tauri::{AppHandle, Emitter};
+
+use crate::error::AppError;
+use crate::state;
+
+/// Event emitted after `reload_env` with the names of the variables that changed
+pub const EVENT_ENV_RELOADED: &str = "env-reloaded";
+
+/// Command-line flag and environment variable pointing at an explicit .env file
+pub const ENV_FILE_ARG: &str = "--env-file";
+const ENV_FILE_VAR: &str = "SUBSPACE_ENV_FILE";
+
+/// Names of the variables that were set from the .env file
+static ENV_FILE_KEYS: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+
+/// Whether an environment variable came from the .env file rather than the real environment
+pub fn loaded_from_env_file(key: &str) -> bool {
+    ENV_FILE_KEYS.lock().is_ok_and(|keys| keys.contains(key))
+}
+
+/// Parse the contents of a .env file into key/value pairs, in file order
+///
+/// Supports `export KEY=value`, values containing `=`, single quotes (literal),
+/// double quotes (with `\"`, `\\`, `\n`, `\r`, `\t` escapes), and inline
+/// comments on unquoted values when the `#` is preceded by whitespace.
+pub fn parse_env(contents: &str) -> Vec<(String, String)> {
+    contents.lines().filter_map(parse_line).collect()
+}
+
+fn parse_line(line: &str) -> Option<(String, String)> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+
+    // `export KEY=value` is common in files that are also sourced by shells
+    let line = line
+        .strip_prefix("export")
+        .filter(|rest| rest.starts_with(char::is_whitespace))
+        .map_or(line, str::trim_start);
+
+    let (key, raw_value) = line.split_once('=')?;
+    let key = key.trim();
+    if key.is_empty() || key.contains(char::is_whitespace) {
+        return None;
+    }
+
+    Some((key.to_string(), parse_value(raw_value)))
+}
+
+fn parse_value(raw: &str) -> String {
+    let value = raw.trim_start();
+
+    if let Some(rest) = value.strip_prefix('"') {
+        return parse_double_quoted(rest);
+    }
+    if let Some(rest) = value.strip_prefix('\'') {
+        // Single quotes are literal; anything after the closing quote is ignored
+        return rest
+            .split_once('\'')
+            .map_or(rest, |(inner, _)| inner)
+            .to_string();
+    }
+
+    // Unquoted: a `#` starts a comment only when preceded by whitespace
+    let mut end = raw.len();
+    let mut previous = None;
+    for (index, c) in raw.char_indices() {
+        if c == '#' && previous.is_some_and(char::is_whitespace) {
+            end = index;
+            break;
+        }
+        previous = Some(c);
+    }
+    raw[..end].trim().to_string()
+}
+
+/// Parse the body of a double-quoted value up to the closing quote
+fn parse_double_quoted(rest: &str) -> String {
+    let mut value = String::with_capacity(rest.len());
+    let mut chars = rest.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => break,
+            '\\' => match chars.next() {
+                Some('n') => value.push('\n'),
+                Some('r') => value.push('\r'),
+                Some('t') => value.push('\t'),
+                Some('"') => value.push('"'),
+                Some('\\') => value.push('\\'),
+                Some(other) => {
+                    value.push('\\');
+                    value.push(other);
+                }
+                None => value.push('\\'),
+            },
+            _ => value.push(c),
+        }
+    }
+    value
+}
+
+/// Set parsed variables that aren't already present in the real environment
+/// Variables we set from a previous load may be updated.
+/// Returns the names that were added or changed (never the values)
+fn apply_env(pairs: Vec<(String, String)>) -> Vec<String> {
+    let mut changed = Vec::new();
+    for (key, value) in pairs {
+        let previous = env::var(&key).ok();
+        if previous.is_some() && !loaded_from_env_file(&key) {
+            continue;
+        }
+        if let Ok(mut keys) = ENV_FILE_KEYS.lock() {
+            keys.insert(key.clone());
+        }
+        if previous.as_deref() != Some(value.as_str()) {
+            env::set_var(&key, value);
+            changed.push(key);
+        }
+    }
+    changed
+}
+
+/// Explicit .env path from `--env-file <path>`, `--env-file=<path>`, or `SUBSPACE_ENV_FILE`
+fn explicit_env_file() -> Option<PathBuf> {
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == ENV_FILE_ARG {
+            return args.next().map(PathBuf::from);
+        }
+        if let Some(path) = arg
+            .strip_prefix(ENV_FILE_ARG)
+            .and_then(|a| a.strip_prefix('='))
+        {
+            return Some(PathBuf::from(path));
+        }
+    }
+    env::var_os(ENV_FILE_VAR).map(PathBuf::from)
+}
+
+/// The .env file a reload would read now, if any
+pub fn env_file() -> Option<PathBuf> {
+    resolve_env_file()
+}
+
+/// Find the .env file to load, if any
+fn resolve_env_file() -> Option<PathBuf> {
+    if let Some(path) = explicit_env_file() {
+        return path.canonicalize().ok();
+    }
+    if !cfg!(debug_assertions) {
+        return None;
+    }
+
+    // Try multiple locations for .env file
+    let possible_paths = [
+        // Project root (parent of src-tauri)
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../.env"),
+        // Current directory
+        PathBuf::from(".env"),
+        // src-tauri directory
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(".env"),
+    ];
+    possible_paths
+        .iter()
+        .find_map(|path| path.canonicalize().ok().filter(|path| path.is_file()))
+}
+
+/// Why a .env file couldn't be loaded
+#[derive(Debug, thiserror::Error)]
+pub enum EnvError {
+    #[error("No .env file found")]
+    NotFound,
+    #[error("Failed to read {}: {source}", path.display())]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+}
+
+/// Load the .env file and return the names of the variables that changed
+fn load_from(path: &Path) -> Result<Vec<String>, EnvError> {
+    let contents = std::fs::read_to_string(path).map_err(|source| EnvError::Read {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let pairs = parse_env(&contents);
+    let mut changed = unset_missing(&pairs);
+    changed.extend(apply_env(pairs));
+    Ok(changed)
+}
+
+/// Unset the variables an earlier load set that `pairs` no longer has, returning their names
+fn unset_missing(pairs: &[(String, String)]) -> Vec<String> {
+    let Ok(mut keys) = ENV_FILE_KEYS.lock() else {
+        return Vec::new();
+    };
+    let missing = missing_keys(&keys, pairs);
+    for key in &missing {
+        keys.remove(key);
+        env::remove_var(key);
+    }
+    missing
+}
+
+fn missing_keys(loaded: &BTreeSet<String>, pairs: &[(String, String)]) -> Vec<String> {
+    loaded
+        .iter()
+        .filter(|key| !pairs.iter().any(|(name, _)| name == *key))
+        .cloned()
+        .collect()
+}
+
+/// Read the .env file again, or unset its variables if it has gone away
+/// Returns the names of variables that were added, changed or removed
+pub fn reload() -> Result<Vec<String>, EnvError> {
+    match resolve_env_file() {
+        Some(path) => load_from(&path),
+        None => Ok(unset_missing(&[])),
+    }
+}
+
+/// Load environment variables from .env file, returning the file that was loaded
+/// Runs before logging is set up (so SUBSPACE_LOG can come from .env); the caller logs the outcome
+pub fn load_env_file() -> Result<Option<PathBuf>, EnvError> {
+    let Some(path) = resolve_env_file() else {
+        return Ok(None);
+    };
+    load_from(&path)?;
+    Ok(Some(path))
+}
+
+/// Command to re-read the .env file without restarting the app
+/// Returns the names of variables that were added, changed or removed
+#[tauri::command]
+pub async fn reload_env(app: AppHandle) -> Result<Vec<String>, AppError> {
+    let changed = state::blocking(|| {
+        let path = resolve_env_file().ok_or(EnvError::NotFound)?;
+        Ok(load_from(&path)?)
+    })
+    .await?;
+    let _ = app.emit(EVENT_ENV_RELOADED, &changed);
+    Ok(changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_one(line: &str) -> (String, String) {
+        let mut pairs = parse_env(line);
+        assert_eq!(pairs.len(), 1, "expected one pair from {:?}", line);
+        pairs.remove(0)
+    }
+
+    #[test]
+    fn parses_plain_pairs_and_skips_comments() {
+        let pairs = parse_env("# comment\n\nA=1\n  B = two  \n");
+        assert_eq!(
+            pairs,
+            vec![
+                ("A".to_string(), "1".to_string()),
+                ("B".to_string(), "two".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn strips_export_prefix() {
+        assert_eq!(
+            parse_one("export KEY=value"),
+            ("KEY".into(), "value".into())
+        );
+        assert_eq!(
+            parse_one("export\tKEY=value"),
+            ("KEY".into(), "value".into())
+        );
+        // A key that merely starts with "export" is not a prefix
+        assert_eq!(parse_one("exported=1"), ("exported".into(), "1".into()));
+    }
+
+    #[test]
+    fn keeps_equals_signs_in_values() {
+        assert_eq!(parse_one("URL=a=b=c"), ("URL".into(), "a=b=c".into()));
+        assert_eq!(parse_one("TOKEN=\"x==\""), ("TOKEN".into(), "x==".into()));
+    }
+
+    #[test]
+    fn strips_inline_comments_only_after_whitespace() {
+        assert_eq!(parse_one("KEY=abc # comment"), ("KEY".into(), "abc".into()));
+        assert_eq!(parse_one("KEY=abc#def"), ("KEY".into(), "abc#def".into()));
+        assert_eq!(
+            parse_one("KEY= # only a comment"),
+            ("KEY".into(), "".into())
+        );
+    }
+
+    #[test]
+    fn keeps_hash_inside_quotes() {
+        assert_eq!(parse_one("KEY=\"a # b\""), ("KEY".into(), "a # b".into()));
+        assert_eq!(parse_one("KEY='a # b'"), ("KEY".into(), "a # b".into()));
+        assert_eq!(
+            parse_one("KEY=\"a # b\" # trailing"),
+            ("KEY".into(), "a # b".into())
+        );
+    }
+
+    #[test]
+    fn handles_escapes_in_double_quotes() {
+        assert_eq!(
+            parse_one(r#"KEY="say \"hi\"""#),
+            ("KEY".into(), "say \"hi\"".into())
+        );
+        assert_eq!(
+            parse_one(r#"KEY="line1\nline2""#),
+            ("KEY".into(), "line1\nline2".into())
+        );
+        assert_eq!(parse_one(r#"KEY="a\\b""#), ("KEY".into(), "a\\b".into()));
+    }
+
+    #[test]
+    fn single_quotes_are_literal() {
+        assert_eq!(
+            parse_one(r"KEY='no\nescape'"),
+            ("KEY".into(), r"no\nescape".into())
+        );
+    }
+
+    #[test]
+    fn skips_lines_without_a_key() {
+        assert!(parse_env("=value\nnot a pair\n").is_empty());
+    }
+
+    #[test]
+    fn does_not_override_real_environment() {
+        let key = "SUBSPACE_ENV_LOADER_TEST_EXISTING";
+        env::set_var(key, "from-shell");
+
+        let applied = apply_env(vec![(key.to_string(), "from-file".to_string())]);
+
+        assert!(applied.is_empty());
+        assert_eq!(env::var(key).unwrap(), "from-shell");
+        assert!(!loaded_from_env_file(key));
+    }
+
+    #[test]
+    fn reports_only_changed_variables() {
+        let key = "SUBSPACE_ENV_LOADER_TEST_RELOAD";
+        env::remove_var(key);
+
+        assert_eq!(
+            apply_env(vec![(key.to_string(), "one".to_string())]),
+            vec![key.to_string()]
+        );
+        assert!(apply_env(vec![(key.to_string(), "one".to_string())]).is_empty());
+        assert_eq!(
+            apply_env(vec![(key.to_string(), "two".to_string())]),
+            vec![key.to_string()]
+        );
+        assert_eq!(env::var(key).unwrap(), "two");
+    }
+
+    #[test]
+    fn finds_variables_the_file_no_longer_has() {
+        let loaded: BTreeSet<String> = ["KEPT", "DROPPED"].map(String::from).into();
+        let pairs = parse_env("KEPT=1\nNEW=2\n");
+        assert_eq!(missing_keys(&loaded, &pairs), vec!["DROPPED".to_string()]);
+        assert_eq!(missing_keys(&loaded, &[]).len(), 2);
+    }
+
+    #[test]
+    fn sets_missing_variables() {
+        let key = "SUBSPACE_ENV_LOADER_TEST_MISSING";
+        env::remove_var(key);
+
+        let applied = apply_env(vec![(key.to_string(), "from-file".to_string())]);
+
+        assert_eq!(applied, vec![key.to_string()]);
+        assert_eq!(env::var(key).unwrap(), "from-file");
+        assert!(loaded_from_env_file(key));
+    }
+}
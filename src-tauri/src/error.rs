@@ -0,0 +1,231 @@
+// Error type returned by every command
+// Serialized as { code, message } (plus `status` for network errors, `session_state`
+// for a session already active and `retry_after_secs` for attempts refused locally) so the UI can branch on a stable code and localize,
+// instead of string-matching messages
+
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+
+use crate::engine::SessionState;
+use crate::env_loader::EnvError;
+
+#[derive(Debug, thiserror::Error)]
+pub enum AppError {
+    #[error("DEEPGRAM_API_KEY is not configured. Please set it in the app or in your .env file.")]
+    KeyNotConfigured,
+    #[error("{0}")]
+    KeyInvalid(String),
+    /// The key is valid but its scopes don't allow a management call
+    #[error("{0}")]
+    KeyLacksPermission(String),
+    /// A saved key exists but can't be decrypted, e.g. the file came from another machine
+    #[error("{0}")]
+    KeyUnreadable(String),
+    #[error("{message}")]
+    Network {
+        status: Option<u16>,
+        message: String,
+    },
+    #[error("{0}")]
+    AudioDevice(String),
+    #[error("Microphone access is denied. {}", crate::permissions::SETTINGS_HINT)]
+    MicrophonePermissionDenied,
+    /// System audio capture needs a loopback device this machine doesn't have
+    #[error("{0}")]
+    LoopbackUnsupported(String),
+    #[error("{0}")]
+    Io(String),
+    /// Settings or environment could not be read or written
+    #[error("{0}")]
+    Config(String),
+    /// History database failure
+    #[error("{0}")]
+    Storage(String),
+    #[error("{0}")]
+    InvalidInput(String),
+    #[error("{0}")]
+    NotFound(String),
+    /// A timed export was requested for a session without timings
+    #[error("{0}")]
+    NoTimingData(String),
+    /// A confidence review was requested for a session saved without word confidences
+    #[error("{0}")]
+    NoConfidenceData(String),
+    /// An export template couldn't be read, compiled or rendered
+    #[error("{0}")]
+    Template(String),
+    /// Wrong stream state, e.g. sending without a stream
+    #[error("{0}")]
+    Stream(String),
+    /// `start_session` while a session is in the given state
+    #[error("A transcription session is already {}", .0.as_str())]
+    SessionAlreadyActive(SessionState),
+    /// `resume_session` was called while nothing was paused
+    #[error("{0}")]
+    NotPaused(String),
+    #[error("{0}")]
+    Shortcut(String),
+    /// Typing or pasting into another application failed
+    #[error("{0}")]
+    Injection(String),
+    /// The TLS handshake with Deepgram failed, e.g. a proxy re-signing certificates
+    #[error("{0}")]
+    Tls(String),
+    /// The configured proxy couldn't be reached or refused to tunnel
+    #[error("{0}")]
+    Proxy(String),
+    /// The local caption server couldn't start, e.g. its port is taken
+    #[error("{0}")]
+    CaptionServer(String),
+    /// Deepgram failed too many times in a row; attempts are refused until the cooldown ends
+    #[error("Deepgram is failing, so attempts are paused for {retry_after_secs} s")]
+    CircuitOpen { retry_after_secs: u64 },
+    /// Too many connection attempts or requests in the last minute
+    #[error("Too many attempts to reach Deepgram; try again in {retry_after_secs} s")]
+    RateLimited { retry_after_secs: u64 },
+    #[error("{0}")]
+    Unsupported(String),
+    #[error("{0}")]
+    Internal(String),
+}
+
+impl AppError {
+    /// Stable identifier for the UI; never change an existing code
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::KeyNotConfigured => "key_not_configured",
+            Self::KeyInvalid(_) => "key_invalid",
+            Self::KeyLacksPermission(_) => "key_lacks_permission",
+            Self::KeyUnreadable(_) => "key_unreadable",
+            Self::Network { .. } => "network",
+            Self::AudioDevice(_) => "audio_device",
+            Self::MicrophonePermissionDenied => "microphone_permission_denied",
+            Self::LoopbackUnsupported(_) => "loopback_unsupported",
+            Self::Io(_) => "io",
+            Self::Config(_) => "config",
+            Self::Storage(_) => "storage",
+            Self::InvalidInput(_) => "invalid_input",
+            Self::NotFound(_) => "not_found",
+            Self::NoTimingData(_) => "no_timing_data",
+            Self::NoConfidenceData(_) => "no_confidence_data",
+            Self::Template(_) => "template",
+            Self::Stream(_) => "stream",
+            Self::SessionAlreadyActive(_) => "session_already_active",
+            Self::NotPaused(_) => "not_paused",
+            Self::Shortcut(_) => "shortcut",
+            Self::Injection(_) => "injection",
+            Self::CaptionServer(_) => "caption_server",
+            Self::CircuitOpen { .. } => "circuit_open",
+            Self::RateLimited { .. } => "rate_limited",
+            Self::Tls(_) => "tls",
+            Self::Proxy(_) => "proxy",
+            Self::Unsupported(_) => "unsupported",
+            Self::Internal(_) => "internal",
+        }
+    }
+
+    pub fn network(message: impl Into<String>) -> Self {
+        Self::Network {
+            status: None,
+            message: message.into(),
+        }
+    }
+
+    /// Error for a poisoned lock around `what`
+    pub fn poisoned(what: &str) -> Self {
+        Self::Internal(format!("{} is poisoned", what))
+    }
+}
+
+impl Serialize for AppError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let status = match self {
+            Self::Network { status, .. } => *status,
+            _ => None,
+        };
+        let mut state = serializer.serialize_struct("AppError", 5)?;
+        state.serialize_field("code", self.code())?;
+        state.serialize_field("message", &self.to_string())?;
+        if status.is_some() {
+            state.serialize_field("status", &status)?;
+        } else {
+            state.skip_field("status")?;
+        }
+        match self {
+            Self::SessionAlreadyActive(session_state) => {
+                state.serialize_field("session_state", session_state)?
+            }
+            _ => state.skip_field("session_state")?,
+        }
+        match self {
+            Self::CircuitOpen { retry_after_secs } | Self::RateLimited { retry_after_secs } => {
+                state.serialize_field("retry_after_secs", retry_after_secs)?
+            }
+            _ => state.skip_field("retry_after_secs")?,
+        }
+        state.end()
+    }
+}
+
+impl From<std::io::Error> for AppError {
+    fn from(error: std::io::Error) -> Self {
+        Self::Io(error.to_string())
+    }
+}
+
+impl From<reqwest::Error> for AppError {
+    fn from(error: reqwest::Error) -> Self {
+        if error.is_connect() {
+            // reqwest doesn't say which hop failed; only the messages of its sources do
+            let chain = error_chain(&error);
+            let lower = chain.to_lowercase();
+            if lower.contains("tunnel") || lower.contains("proxy") {
+                return Self::Proxy(format!("The proxy refused the connection: {}", chain));
+            }
+            if ["certificate", "tls", "ssl", "handshake"]
+                .iter()
+                .any(|word| lower.contains(word))
+            {
+                return Self::Tls(format!("Secure connection to Deepgram failed: {}", chain));
+            }
+        }
+        let message = if error.is_timeout() {
+            "Deepgram took too long to respond".to_string()
+        } else if error.is_decode() {
+            format!("Unexpected response from Deepgram: {}", error)
+        } else {
+            format!("Could not reach Deepgram: {}", error)
+        };
+        Self::Network {
+            status: error.status().map(|status| status.as_u16()),
+            message,
+        }
+    }
+}
+
+/// An error and all of its sources, joined with ": "
+fn error_chain(error: &dyn std::error::Error) -> String {
+    let mut chain = error.to_string();
+    let mut source = error.source();
+    while let Some(error) = source {
+        chain.push_str(": ");
+        chain.push_str(&error.to_string());
+        source = error.source();
+    }
+    chain
+}
+
+impl From<EnvError> for AppError {
+    fn from(error: EnvError) -> Self {
+        match error {
+            EnvError::NotFound => Self::NotFound(error.to_string()),
+            EnvError::Read { .. } => Self::Config(error.to_string()),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for AppError {
+    fn from(error: rusqlite::Error) -> Self {
+        Self::Storage(format!("History database error: {}", error))
+    }
+}
@@ -0,0 +1,363 @@
+// A session as one self-contained HTML page, for sending to someone without the app
+// The page comes from `share.html` with the title, date, duration and speaker-labelled
+// segments filled in; doubtful words are highlighted, and the saved recording can be
+// embedded as a data URI with the segment timestamps seeking it. Everything the user or
+// Deepgram wrote is escaped
+
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use base64::Engine as _;
+use chrono::{DateTime, Local, SecondsFormat};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+
+use super::TextVersion;
+use crate::error::AppError;
+use crate::fs_util::write_atomic;
+use crate::review;
+use crate::state::{blocking, AppState};
+use crate::storage::{Segment, Session, Storage};
+
+const TEMPLATE: &str = include_str!("share.html");
+/// Size above which the result warns that the file may be too big to send
+pub const DEFAULT_WARN_ABOVE_MB: u64 = 25;
+const BYTES_PER_MB: u64 = 1024 * 1024;
+
+/// Options for `export_session_html`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct HtmlExportOptions {
+    /// Embed the saved recording, when the session has one
+    pub embed_audio: bool,
+    pub version: TextVersion,
+    /// Words below this confidence (0-1) are highlighted; None uses the
+    /// `confidence_threshold` setting
+    pub confidence_threshold: Option<f64>,
+    pub warn_above_mb: u64,
+}
+
+impl Default for HtmlExportOptions {
+    fn default() -> Self {
+        Self {
+            embed_audio: false,
+            version: TextVersion::default(),
+            confidence_threshold: None,
+            warn_above_mb: DEFAULT_WARN_ABOVE_MB,
+        }
+    }
+}
+
+/// Result of `export_session_html`
+#[derive(Debug, Clone, Serialize)]
+pub struct HtmlExport {
+    pub path: String,
+    pub bytes: u64,
+    pub audio_embedded: bool,
+    /// E.g. that the file came out larger than `warn_above_mb`
+    pub warnings: Vec<String>,
+}
+
+/// Command to write session `id` to `path` as a page that opens in any browser
+#[tauri::command]
+pub async fn export_session_html(
+    app: AppHandle,
+    id: i64,
+    path: String,
+    options: Option<HtmlExportOptions>,
+) -> Result<HtmlExport, AppError> {
+    let options = options.unwrap_or_default();
+    let threshold = review::resolve_threshold(&app, options.confidence_threshold)?;
+    let storage = Arc::clone(&app.state::<AppState>().storage);
+    blocking(move || write_html(&storage, id, &path, &options, threshold)).await
+}
+
+/// Write session `id` to `path`, as `export_session_html` does
+pub fn write_html(
+    storage: &Storage,
+    id: i64,
+    path: &str,
+    options: &HtmlExportOptions,
+    threshold: f64,
+) -> Result<HtmlExport, AppError> {
+    let (session, segments) = super::load(storage, id, options.version)?;
+    let mut warnings = Vec::new();
+    let audio = match session.audio_path.as_deref() {
+        Some(audio_path) if options.embed_audio && Path::new(audio_path).is_file() => Some(
+            std::fs::read(audio_path)
+                .map_err(|e| AppError::Io(format!("Failed to read {}: {}", audio_path, e)))?,
+        ),
+        _ if options.embed_audio => {
+            warnings.push("The session has no saved audio, so none was embedded".to_string());
+            None
+        }
+        _ => None,
+    };
+
+    let page = render(&session, &segments, threshold, audio.as_deref());
+    let bytes = page.len() as u64;
+    if bytes > options.warn_above_mb.saturating_mul(BYTES_PER_MB) {
+        warnings.push(format!(
+            "The file is {:.1} MB, which may be too large to email or upload",
+            bytes as f64 / BYTES_PER_MB as f64
+        ));
+    }
+    write_atomic(&PathBuf::from(path), page.as_bytes(), false)
+        .map_err(|e| AppError::Io(format!("Failed to write {}: {}", path, e)))?;
+    Ok(HtmlExport {
+        path: path.to_string(),
+        bytes,
+        audio_embedded: audio.is_some(),
+        warnings,
+    })
+}
+
+/// The page for a session, with `audio` (the saved WAV) embedded when given
+fn render(session: &Session, segments: &[Segment], threshold: f64, audio: Option<&[u8]>) -> String {
+    let title = session
+        .title
+        .clone()
+        .filter(|title| !title.trim().is_empty())
+        .unwrap_or_else(|| "Transcript".to_string());
+    let started = DateTime::from_timestamp_millis(session.started_at).unwrap_or_default();
+    let language = session
+        .detected_language
+        .as_deref()
+        .unwrap_or(&session.language);
+    let audio = audio
+        .map(|audio| {
+            format!(
+                "<audio controls preload=\"metadata\" src=\"data:audio/wav;base64,{}\"></audio>",
+                base64::engine::general_purpose::STANDARD.encode(audio)
+            )
+        })
+        .unwrap_or_default();
+    let segments = render_segments(segments, threshold, !audio.is_empty());
+    fill(
+        TEMPLATE,
+        &[
+            ("lang", &escape(language)),
+            ("title", &escape(&title)),
+            (
+                "date_iso",
+                &started.to_rfc3339_opts(SecondsFormat::Secs, true),
+            ),
+            (
+                "date",
+                &started
+                    .with_timezone(&Local)
+                    .format("%Y-%m-%d %H:%M")
+                    .to_string(),
+            ),
+            ("duration", &clock(session.duration_ms)),
+            ("audio", &audio),
+            ("segments", &segments),
+        ],
+    )
+}
+
+/// One line per segment, with the speaker above each change of speaker
+/// With audio, the timestamps are buttons that seek it
+fn render_segments(segments: &[Segment], threshold: f64, seekable: bool) -> String {
+    let mut out = String::new();
+    let mut speaker = None;
+    for segment in segments {
+        let label = super::segment_label(segment);
+        if let Some(label) = label.as_ref().filter(|_| label != speaker) {
+            let _ = writeln!(out, "<p class=\"speaker\">{}</p>", escape(label));
+        }
+        speaker = label;
+        let time = clock(segment.start_ms);
+        let time = if seekable {
+            format!(
+                "<button class=\"time\" type=\"button\" data-ms=\"{}\">{}</button>",
+                segment.start_ms.max(0),
+                time
+            )
+        } else {
+            format!("<span class=\"time\">{}</span>", time)
+        };
+        let _ = writeln!(
+            out,
+            "<div class=\"segment\">{}<span class=\"text\">{}</span></div>",
+            time,
+            segment_text(segment, threshold)
+        );
+    }
+    out
+}
+
+/// The segment's text, escaped; when some of its words fall below `threshold` it's built
+/// from the words instead, so those can be marked
+fn segment_text(segment: &Segment, threshold: f64) -> String {
+    let words = segment.words.as_deref().unwrap_or_default();
+    if !words.iter().any(|word| word.confidence < threshold) {
+        return escape(&segment.text);
+    }
+    words
+        .iter()
+        .map(|word| {
+            let text = escape(&word.text);
+            if word.confidence < threshold {
+                format!(
+                    "<span class=\"low\" title=\"{:.0}% confidence\">{}</span>",
+                    word.confidence * 100.0,
+                    text
+                )
+            } else {
+                text
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// `template` with each `{{name}}` replaced by its value
+/// Values aren't searched again, so text that looks like a placeholder stays as it is
+fn fill(template: &str, values: &[(&str, &str)]) -> String {
+    let mut out = String::with_capacity(
+        template.len() + values.iter().map(|(_, value)| value.len()).sum::<usize>(),
+    );
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let value = after.find("}}").and_then(|end| {
+            values
+                .iter()
+                .find(|(name, _)| *name == &after[..end])
+                .map(|(_, value)| (end, value))
+        });
+        match value {
+            Some((end, value)) => {
+                out.push_str(value);
+                rest = &after[end + 2..];
+            }
+            None => {
+                out.push_str("{{");
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Escape text for HTML content and attribute values
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Milliseconds as m:ss, or h:mm:ss from an hour on
+pub(super) fn clock(ms: i64) -> String {
+    let seconds = ms.max(0) / 1000;
+    if seconds >= 3600 {
+        format!(
+            "{}:{:02}:{:02}",
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        )
+    } else {
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{NewSession, Word};
+
+    fn stored(segments: Vec<Segment>) -> (Session, Vec<Segment>) {
+        let storage = Storage::open_in_memory().unwrap();
+        let id = storage
+            .insert_session(&NewSession {
+                duration_ms: 3_725_000,
+                segments,
+                ..NewSession::for_test(1_700_000_000_000, "")
+            })
+            .unwrap();
+        super::super::load(&storage, id, TextVersion::Original).unwrap()
+    }
+
+    fn segment(start_ms: i64, text: &str, speaker: Option<u32>) -> Segment {
+        Segment {
+            start_ms,
+            end_ms: start_ms + 1000,
+            text: text.to_string(),
+            words: None,
+            speaker,
+            translated: None,
+            channel: None,
+            speaker_name: None,
+        }
+    }
+
+    #[test]
+    fn user_text_is_escaped_and_placeholders_are_not_filled_twice() {
+        let (mut session, segments) = stored(vec![segment(
+            0,
+            "<script>alert('hi')</script> & {{title}}",
+            None,
+        )]);
+        session.title = Some("Q3 \"plan\" {{segments}}".to_string());
+        let page = render(&session, &segments, 0.5, None);
+        assert!(page.contains("<title>Q3 &quot;plan&quot; {{segments}}</title>"));
+        assert!(page.contains("&lt;script&gt;alert(&#39;hi&#39;)&lt;/script&gt; &amp; {{title}}"));
+        assert!(!page.contains("<script>alert"));
+        assert!(page.contains("1:02:05"));
+        assert!(page.contains("datetime=\"2023-11-14T22:13:20Z\""));
+    }
+
+    #[test]
+    fn speakers_low_confidence_words_and_seekable_audio() {
+        let mut doubtful = segment(61_000, "Ship it on Friday.", Some(0));
+        doubtful.words = Some(
+            [
+                ("Ship", 0.98),
+                ("it", 0.95),
+                ("on", 0.97),
+                ("Friday.", 0.41),
+            ]
+            .iter()
+            .map(|(text, confidence)| Word {
+                text: text.to_string(),
+                start_ms: 61_000,
+                end_ms: 61_500,
+                confidence: *confidence,
+            })
+            .collect(),
+        );
+        let (session, segments) = stored(vec![
+            segment(0, "Hello.", Some(0)),
+            doubtful,
+            segment(62_000, "Agreed.", Some(1)),
+        ]);
+
+        let page = render(&session, &segments, 0.5, None);
+        assert_eq!(
+            page.matches("<p class=\"speaker\">Speaker 1</p>").count(),
+            1
+        );
+        assert!(page.contains("<p class=\"speaker\">Speaker 2</p>"));
+        assert!(page.contains("on <span class=\"low\" title=\"41% confidence\">Friday.</span>"));
+        assert!(page.contains("<span class=\"time\">1:01</span>"));
+        assert!(!page.contains("<audio"));
+
+        let page = render(&session, &segments, 0.5, Some(b"RIFF"));
+        assert!(page.contains("src=\"data:audio/wav;base64,UklGRg==\""));
+        assert!(page.contains("data-ms=\"61000\">1:01</button>"));
+    }
+}
@@ -0,0 +1,461 @@
+// Export saved sessions as subtitles (SRT, WebVTT), plain text, JSON or a Markdown
+// confidence review
+// Subtitle cues are built from word timings when available, otherwise from segment timings
+// Diarized sessions get a "Speaker N:" prefix on each cue and paragraph, multichannel
+// sessions the channel's speaker name, with the channels interleaved by start time
+// Translated sessions can be exported in either version of the text
+// `html` writes a self-contained page to share, `template` lays a session out with a
+// Handlebars template, see there
+
+pub mod html;
+pub mod template;
+
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+
+use crate::error::AppError;
+use crate::fs_util::write_atomic;
+use crate::postprocess::dictation_commands;
+use crate::review;
+use crate::state::{blocking, AppState};
+use crate::storage::{Segment, Session, Storage};
+
+/// Default cue length, the usual broadcast subtitle line limit
+pub const DEFAULT_MAX_CHARS_PER_CUE: usize = 42;
+/// Shortest cue limit we accept; anything smaller is one word per cue anyway
+const MIN_MAX_CHARS_PER_CUE: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Srt,
+    Vtt,
+    Txt,
+    Json,
+    /// Markdown with low-confidence words highlighted, see `review`
+    Review,
+}
+
+impl FromStr for ExportFormat {
+    type Err = AppError;
+
+    /// The names the frontend sends, for the command line
+    fn from_str(name: &str) -> Result<Self, AppError> {
+        match name.to_ascii_lowercase().as_str() {
+            "srt" => Ok(Self::Srt),
+            "vtt" => Ok(Self::Vtt),
+            "txt" => Ok(Self::Txt),
+            "json" => Ok(Self::Json),
+            "review" => Ok(Self::Review),
+            _ => Err(AppError::InvalidInput(format!(
+                "Unknown export format \"{}\"; use srt, vtt, txt, json or review",
+                name
+            ))),
+        }
+    }
+}
+
+/// Which version of a translated session's text to export
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TextVersion {
+    #[default]
+    Original,
+    Translated,
+}
+
+/// JSON export document
+#[derive(Serialize)]
+struct JsonExport<'a> {
+    #[serde(flatten)]
+    session: &'a Session,
+    segments: &'a [Segment],
+}
+
+/// One subtitle cue
+#[derive(Debug)]
+struct Cue {
+    start_ms: i64,
+    end_ms: i64,
+    text: String,
+}
+
+/// A word with its timing, either from Deepgram or interpolated within its segment
+struct TimedWord<'a> {
+    text: &'a str,
+    start_ms: i64,
+    end_ms: i64,
+}
+
+/// Write a session to `path` in the requested format
+/// `version` picks the original text (the default) or the translation; `review_threshold`
+/// overrides the `confidence_threshold` setting for the review format
+#[tauri::command]
+pub async fn export_session(
+    app: AppHandle,
+    id: i64,
+    format: ExportFormat,
+    path: String,
+    max_chars_per_cue: Option<usize>,
+    version: Option<TextVersion>,
+    review_threshold: Option<f64>,
+) -> Result<(), AppError> {
+    let max_chars = max_chars_per_cue.unwrap_or(DEFAULT_MAX_CHARS_PER_CUE);
+    if max_chars < MIN_MAX_CHARS_PER_CUE {
+        return Err(AppError::InvalidInput(format!(
+            "max_chars_per_cue must be at least {}",
+            MIN_MAX_CHARS_PER_CUE
+        )));
+    }
+
+    let threshold = review::resolve_threshold(&app, review_threshold)?;
+    let storage = Arc::clone(&app.state::<AppState>().storage);
+    let version = version.unwrap_or_default();
+    blocking(move || write_export(&storage, id, format, version, &path, max_chars, threshold)).await
+}
+
+/// Write a session to `path`, as `export_session` does
+pub fn write_export(
+    storage: &Storage,
+    id: i64,
+    format: ExportFormat,
+    version: TextVersion,
+    path: &str,
+    max_chars: usize,
+    review_threshold: f64,
+) -> Result<(), AppError> {
+    let contents = render(storage, id, format, version, max_chars, review_threshold)?;
+    write_atomic(&PathBuf::from(path), contents.as_bytes(), false)
+        .map_err(|e| AppError::Io(format!("Failed to write {}: {}", path, e)))
+}
+
+/// A session's export in `format`
+pub fn render(
+    storage: &Storage,
+    id: i64,
+    format: ExportFormat,
+    version: TextVersion,
+    max_chars: usize,
+    review_threshold: f64,
+) -> Result<String, AppError> {
+    let (session, segments) = load(storage, id, version)?;
+    Ok(match format {
+        ExportFormat::Txt => render_txt(&session, &segments),
+        ExportFormat::Json => serde_json::to_string_pretty(&JsonExport {
+            session: &session,
+            segments: &segments,
+        })
+        .map_err(|e| AppError::Internal(format!("Failed to serialize session: {}", e)))?,
+        ExportFormat::Review => review::render_markdown(id, &segments, review_threshold)?,
+        ExportFormat::Srt | ExportFormat::Vtt => {
+            let cues = build_cues(&segments, max_chars);
+            if cues.is_empty() {
+                return Err(AppError::NoTimingData(format!(
+                    "Session {} has no timing data, so it can't be exported as subtitles",
+                    id
+                )));
+            }
+            match format {
+                ExportFormat::Srt => render_srt(&cues),
+                _ => render_vtt(&cues),
+            }
+        }
+    })
+}
+
+/// Session `id` and its segments in the order they were spoken, in `version`
+fn load(
+    storage: &Storage,
+    id: i64,
+    version: TextVersion,
+) -> Result<(Session, Vec<Segment>), AppError> {
+    let session = storage
+        .get_session(id)?
+        .ok_or_else(|| AppError::NotFound(format!("Session {} not found", id)))?;
+    let mut segments = storage.segments(id)?;
+    if segments.iter().any(|segment| segment.channel.is_some()) {
+        // Stable, so each channel's segments keep their order
+        segments.sort_by_key(|segment| segment.start_ms);
+    }
+    match version {
+        TextVersion::Original => Ok((session, segments)),
+        TextVersion::Translated => translated(session, segments),
+    }
+}
+
+/// The session with its translation in place of its text
+/// Word timings belong to the original words, so translated cues are timed by segment
+fn translated(
+    session: Session,
+    segments: Vec<Segment>,
+) -> Result<(Session, Vec<Segment>), AppError> {
+    let Some(text) = session.translated_text.clone() else {
+        return Err(AppError::InvalidInput(format!(
+            "Session {} has no translation",
+            session.id
+        )));
+    };
+    let segments = segments
+        .into_iter()
+        .map(|segment| Segment {
+            text: segment.translated.clone().unwrap_or(segment.text),
+            words: None,
+            ..segment
+        })
+        .collect();
+    Ok((Session { text, ..session }, segments))
+}
+
+/// Split segments into cues of at most `max_chars` characters
+/// Cues never span segments, so pauses between utterances and speaker changes stay visible
+fn build_cues(segments: &[Segment], max_chars: usize) -> Vec<Cue> {
+    let mut cues = Vec::new();
+    for segment in segments {
+        let first = cues.len();
+        let mut current: Option<Cue> = None;
+        for word in timed_words(segment) {
+            match current.as_mut() {
+                Some(cue)
+                    if cue.text.chars().count() + 1 + word.text.chars().count() <= max_chars =>
+                {
+                    cue.text.push(' ');
+                    cue.text.push_str(word.text);
+                    cue.end_ms = word.end_ms;
+                }
+                _ => {
+                    // A single word longer than the limit still gets its own cue
+                    cues.extend(current.take());
+                    current = Some(Cue {
+                        start_ms: word.start_ms,
+                        end_ms: word.end_ms,
+                        text: word.text.to_string(),
+                    });
+                }
+            }
+        }
+        cues.extend(current);
+        if let Some(label) = segment_label(segment) {
+            for cue in &mut cues[first..] {
+                cue.text = format!("{}: {}", label, cue.text);
+            }
+        }
+    }
+    cues
+}
+
+/// The channel's speaker name, or "Speaker 1" for Deepgram's speaker 0; None when the
+/// session is neither multichannel nor diarized
+pub fn segment_label(segment: &Segment) -> Option<String> {
+    segment.speaker_name.clone().or_else(|| {
+        segment
+            .speaker
+            .map(|speaker| format!("Speaker {}", speaker + 1))
+    })
+}
+
+/// Plain text; diarized and multichannel sessions get one labelled paragraph per turn
+fn render_txt(session: &Session, segments: &[Segment]) -> String {
+    let labels: Vec<Option<String>> = segments.iter().map(segment_label).collect();
+    if labels.iter().all(Option::is_none) {
+        return format!("{}\n", session.text);
+    }
+    let mut turns: Vec<(Option<String>, Vec<&str>)> = Vec::new();
+    for (segment, label) in segments.iter().zip(labels) {
+        match turns.last_mut() {
+            Some((speaker, texts)) if *speaker == label => texts.push(&segment.text),
+            _ => turns.push((label, vec![&segment.text])),
+        }
+    }
+    let paragraphs: Vec<String> = turns
+        .into_iter()
+        .map(|(label, texts)| {
+            let text = dictation_commands::join(texts);
+            match label {
+                Some(label) => format!("{}: {}", label, text),
+                None => text,
+            }
+        })
+        .collect();
+    format!("{}\n", paragraphs.join("\n\n"))
+}
+
+/// Words of a segment with timings; without word timings the segment's span
+/// is shared out in proportion to word length
+fn timed_words(segment: &Segment) -> Vec<TimedWord<'_>> {
+    if let Some(words) = segment.words.as_ref().filter(|words| !words.is_empty()) {
+        return words
+            .iter()
+            .map(|word| TimedWord {
+                text: word.text.as_str(),
+                start_ms: word.start_ms,
+                end_ms: word.end_ms.max(word.start_ms),
+            })
+            .collect();
+    }
+
+    let words: Vec<&str> = segment.text.split_whitespace().collect();
+    let total_chars: usize = words.iter().map(|word| word.chars().count()).sum();
+    let span = (segment.end_ms - segment.start_ms).max(0);
+    let mut elapsed_chars = 0;
+    words
+        .into_iter()
+        .map(|text| {
+            let start_ms = segment.start_ms + span * elapsed_chars as i64 / total_chars as i64;
+            elapsed_chars += text.chars().count();
+            let end_ms = segment.start_ms + span * elapsed_chars as i64 / total_chars as i64;
+            TimedWord {
+                text,
+                start_ms,
+                end_ms,
+            }
+        })
+        .collect()
+}
+
+fn render_srt(cues: &[Cue]) -> String {
+    let mut out = String::new();
+    for (index, cue) in cues.iter().enumerate() {
+        out.push_str(&format!(
+            "{}\n{} --> {}\n{}\n\n",
+            index + 1,
+            format_timestamp(cue.start_ms, ','),
+            format_timestamp(cue.end_ms, ','),
+            cue.text
+        ));
+    }
+    out
+}
+
+fn render_vtt(cues: &[Cue]) -> String {
+    let mut out = String::from("WEBVTT\n\n");
+    for cue in cues {
+        out.push_str(&format!(
+            "{} --> {}\n{}\n\n",
+            format_timestamp(cue.start_ms, '.'),
+            format_timestamp(cue.end_ms, '.'),
+            cue.text
+        ));
+    }
+    out
+}
+
+/// Format milliseconds as hh:mm:ss followed by `separator` and mmm
+/// (SRT uses a comma, WebVTT a period)
+fn format_timestamp(ms: i64, separator: char) -> String {
+    let ms = ms.max(0);
+    format!(
+        "{:02}:{:02}:{:02}{}{:03}",
+        ms / 3_600_000,
+        ms / 60_000 % 60,
+        ms / 1000 % 60,
+        separator,
+        ms % 1000
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{NewSession, Word};
+
+    fn word(text: &str, start_ms: i64) -> Word {
+        Word {
+            text: text.to_string(),
+            start_ms,
+            end_ms: start_ms + 400,
+            confidence: 0.9,
+        }
+    }
+
+    fn segment(text: &str, words: Option<Vec<Word>>) -> Segment {
+        Segment {
+            start_ms: 0,
+            end_ms: 2000,
+            text: text.to_string(),
+            words,
+            speaker: None,
+            translated: None,
+            channel: None,
+            speaker_name: None,
+        }
+    }
+
+    #[test]
+    fn timestamps_use_the_format_separator_and_run_past_99_hours() {
+        assert_eq!(format_timestamp(3_723_004, ','), "01:02:03,004");
+        assert_eq!(format_timestamp(3_723_004, '.'), "01:02:03.004");
+        assert_eq!(
+            format_timestamp(100 * 3_600_000 + 59_999, '.'),
+            "100:00:59.999"
+        );
+        assert_eq!(format_timestamp(-5, ','), "00:00:00,000");
+    }
+
+    #[test]
+    fn cues_split_at_the_limit_and_an_overlong_word_gets_its_own() {
+        let words = vec![
+            word("one", 0),
+            word("two", 500),
+            word("three", 1000),
+            word("extraordinarily", 1500),
+            word("so", 2000),
+        ];
+        let cues = build_cues(&[segment("", Some(words))], 8);
+        let texts: Vec<&str> = cues.iter().map(|cue| cue.text.as_str()).collect();
+        assert_eq!(texts, ["one two", "three", "extraordinarily", "so"]);
+        assert_eq!((cues[0].start_ms, cues[0].end_ms), (0, 900));
+        assert_eq!((cues[2].start_ms, cues[2].end_ms), (1500, 1900));
+
+        // Without word timings the segment's span is shared out by word length
+        let cues = build_cues(&[segment("ab cd", None)], 8);
+        assert_eq!(cues.len(), 1);
+        assert_eq!((cues[0].start_ms, cues[0].end_ms), (0, 2000));
+        let cues = build_cues(&[segment("ab cd", None)], 4);
+        assert_eq!((cues[1].start_ms, cues[1].end_ms), (1000, 2000));
+    }
+
+    #[test]
+    fn srt_cues_are_numbered_from_one_and_vtt_has_a_header() {
+        let cues = build_cues(
+            &[segment(
+                "",
+                Some(vec![word("hello", 0), word("there", 1000)]),
+            )],
+            8,
+        );
+        assert_eq!(
+            render_srt(&cues),
+            "1\n00:00:00,000 --> 00:00:00,400\nhello\n\n\
+             2\n00:00:01,000 --> 00:00:01,400\nthere\n\n"
+        );
+        assert_eq!(
+            render_vtt(&cues[..1]),
+            "WEBVTT\n\n00:00:00.000 --> 00:00:00.400\nhello\n\n"
+        );
+    }
+
+    #[test]
+    fn subtitles_need_timing_data() {
+        let storage = Storage::open_in_memory().unwrap();
+        let id = storage
+            .insert_session(&NewSession::for_test(
+                1_700_000_000_000,
+                "Typed, not spoken",
+            ))
+            .unwrap();
+        let render = |format| render(&storage, id, format, TextVersion::Original, 42, 0.5);
+        assert!(matches!(
+            render(ExportFormat::Srt),
+            Err(AppError::NoTimingData(_))
+        ));
+        assert!(matches!(
+            render(ExportFormat::Vtt),
+            Err(AppError::NoTimingData(_))
+        ));
+        assert_eq!(render(ExportFormat::Txt).unwrap(), "Typed, not spoken\n");
+    }
+}
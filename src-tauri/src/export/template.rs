@@ -0,0 +1,578 @@
+// Exports laid out by Handlebars templates, so a session can come out as meeting notes,
+// a report or whatever format someone else expects
+// Templates are `<name>.hbs` files in the `templates` folder of the app config directory,
+// next to the built-in ones in `templates/`, which `copy_export_template` puts there to
+// be changed; a file with a built-in's name is used in its place. Templates see the
+// session's title, date, duration, tags and note, and its segments with speaker labels and
+// word timings (see `TemplateSession`). Output isn't HTML-escaped, and a field a template
+// names that doesn't exist is an error rather than nothing, so a mistyped name shows up in
+// `validate_export_template` instead of as a gap in an export. Every error names the
+// template and, where the engine says where it is, the line and the `{{...}}` at fault
+
+use std::ffi::OsStr;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use chrono::{DateTime, Local, SecondsFormat};
+use handlebars::{Handlebars, RenderError, RenderErrorReason, TemplateError};
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+
+use super::html::clock;
+use super::TextVersion;
+use crate::error::AppError;
+use crate::fs_util::write_atomic;
+use crate::state::{blocking, AppState};
+use crate::storage::{Segment, Session, Storage, Word};
+
+const EXTENSION: &str = "hbs";
+/// Templates that ship with the app, by name
+const BUILTIN: [(&str, &str); 2] = [
+    ("meeting-notes", include_str!("templates/meeting-notes.hbs")),
+    ("timestamped", include_str!("templates/timestamped.hbs")),
+];
+
+/// Entry of `list_export_templates`
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportTemplate {
+    pub name: String,
+    /// Ships with the app
+    pub builtin: bool,
+    /// The file in the templates folder; None for a built-in without a copy there
+    pub path: Option<String>,
+}
+
+/// What `validate_export_template` found wrong with a template
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TemplateProblem {
+    /// 1-based, when the engine could place the problem
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    /// The `{{...}}` at fault, when it could be pointed at
+    pub expression: Option<String>,
+    pub message: String,
+}
+
+impl TemplateProblem {
+    fn into_error(self, template: &str) -> AppError {
+        let mut message = format!("Template \"{}\"", template);
+        if let Some(line) = self.line {
+            message.push_str(&format!(", line {}", line));
+        }
+        if let Some(column) = self.column {
+            message.push_str(&format!(", column {}", column));
+        }
+        if let Some(expression) = &self.expression {
+            message.push_str(&format!(", {}", expression));
+        }
+        AppError::Template(format!("{}: {}", message, self.message))
+    }
+}
+
+/// What a template sees
+#[derive(Debug, Serialize)]
+struct TemplateSession {
+    id: i64,
+    /// "Transcript" for an untitled session
+    title: String,
+    /// Local start time, 2024-05-01 14:30
+    date: String,
+    /// RFC 3339 in UTC
+    date_iso: String,
+    /// m:ss, or h:mm:ss from an hour on
+    duration: String,
+    duration_ms: i64,
+    /// The detected language for "auto"
+    language: String,
+    model: String,
+    tags: Vec<String>,
+    note: Option<String>,
+    text: String,
+    word_count: i64,
+    /// Speaker labels in the order they first speak; empty when nobody is labelled
+    speakers: Vec<String>,
+    segments: Vec<TemplateSegment>,
+}
+
+#[derive(Debug, Serialize)]
+struct TemplateSegment {
+    /// As m:ss or h:mm:ss
+    start: String,
+    end: String,
+    start_ms: i64,
+    end_ms: i64,
+    /// "Speaker 1" or the channel's speaker name; None when nobody is labelled
+    speaker: Option<String>,
+    /// The first segment of a labelled speaker's turn
+    speaker_changed: bool,
+    text: String,
+    /// Empty when the session has no word timings
+    words: Vec<TemplateWord>,
+}
+
+#[derive(Debug, Serialize)]
+struct TemplateWord {
+    text: String,
+    start: String,
+    start_ms: i64,
+    end_ms: i64,
+    confidence: f64,
+}
+
+impl From<&Word> for TemplateWord {
+    fn from(word: &Word) -> Self {
+        Self {
+            text: word.text.clone(),
+            start: clock(word.start_ms),
+            start_ms: word.start_ms,
+            end_ms: word.end_ms,
+            confidence: word.confidence,
+        }
+    }
+}
+
+impl TemplateSession {
+    fn new(session: &Session, segments: &[Segment]) -> Self {
+        let started = DateTime::from_timestamp_millis(session.started_at).unwrap_or_default();
+        let (speakers, segments) = template_segments(segments);
+        Self {
+            id: session.id,
+            title: session
+                .title
+                .clone()
+                .filter(|title| !title.trim().is_empty())
+                .unwrap_or_else(|| "Transcript".to_string()),
+            date: started
+                .with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M")
+                .to_string(),
+            date_iso: started.to_rfc3339_opts(SecondsFormat::Secs, true),
+            duration: clock(session.duration_ms),
+            duration_ms: session.duration_ms,
+            language: session
+                .detected_language
+                .clone()
+                .unwrap_or_else(|| session.language.clone()),
+            model: session.model.clone(),
+            tags: session.tags.clone(),
+            note: session.note.clone(),
+            text: session.text.clone(),
+            word_count: session.word_count,
+            speakers,
+            segments,
+        }
+    }
+
+    /// A made-up diarized session with every field filled in, to check templates against
+    fn sample() -> Self {
+        let segment = |start_ms: i64, speaker: u32, text: &str| {
+            let mut at = start_ms;
+            let words = text
+                .split_whitespace()
+                .map(|word| {
+                    at += 400;
+                    Word {
+                        text: word.to_string(),
+                        start_ms: at - 400,
+                        end_ms: at - 50,
+                        confidence: 0.95,
+                    }
+                })
+                .collect();
+            Segment {
+                start_ms,
+                end_ms: at,
+                text: text.to_string(),
+                words: Some(words),
+                speaker: Some(speaker),
+                translated: None,
+                channel: None,
+                speaker_name: None,
+            }
+        };
+        let segments = [
+            segment(0, 0, "Let's go through the release plan."),
+            segment(3_000, 0, "The build is ready for testing."),
+            segment(6_500, 1, "Then we ship on Friday."),
+        ];
+        let (speakers, segments) = template_segments(&segments);
+        let text = segments
+            .iter()
+            .map(|segment| segment.text.as_str())
+            .collect::<Vec<_>>()
+            .join(" ");
+        Self {
+            id: 1,
+            title: "Release planning".to_string(),
+            date: "2024-05-01 14:30".to_string(),
+            date_iso: "2024-05-01T12:30:00Z".to_string(),
+            duration: clock(8_500),
+            duration_ms: 8_500,
+            language: "en".to_string(),
+            model: "nova-3".to_string(),
+            tags: vec!["planning".to_string(), "team".to_string()],
+            note: Some("Follow up with QA.".to_string()),
+            word_count: text.split_whitespace().count() as i64,
+            text,
+            speakers,
+            segments,
+        }
+    }
+}
+
+/// The segments as templates see them, and the speakers in the order they first speak
+fn template_segments(segments: &[Segment]) -> (Vec<String>, Vec<TemplateSegment>) {
+    let mut speakers: Vec<String> = Vec::new();
+    let mut previous = None;
+    let mut out = Vec::with_capacity(segments.len());
+    for segment in segments {
+        let speaker = super::segment_label(segment);
+        if let Some(label) = speaker.as_ref().filter(|label| !speakers.contains(label)) {
+            speakers.push(label.clone());
+        }
+        out.push(TemplateSegment {
+            start: clock(segment.start_ms),
+            end: clock(segment.end_ms),
+            start_ms: segment.start_ms,
+            end_ms: segment.end_ms,
+            speaker_changed: speaker.is_some() && speaker != previous,
+            speaker: speaker.clone(),
+            text: segment.text.clone(),
+            words: segment
+                .words
+                .iter()
+                .flatten()
+                .map(TemplateWord::from)
+                .collect(),
+        });
+        previous = speaker;
+    }
+    (speakers, out)
+}
+
+/// Command listing the built-in templates and those in the templates folder, by name
+/// Creates the folder, so it can be opened to add one
+#[tauri::command]
+pub async fn list_export_templates(app: AppHandle) -> Result<Vec<ExportTemplate>, AppError> {
+    let dir = templates_dir(&app)?;
+    blocking(move || list(&dir)).await
+}
+
+/// Command to put a copy of built-in template `name` in the templates folder, to be
+/// changed there; returns the file's path
+#[tauri::command]
+pub async fn copy_export_template(app: AppHandle, name: String) -> Result<String, AppError> {
+    let dir = templates_dir(&app)?;
+    blocking(move || copy_builtin(&dir, &name)).await
+}
+
+/// Command to render template `name` against a sample session
+/// Returns what's wrong with it, empty when nothing is; the engine stops at the first
+/// problem, so there's at most one
+#[tauri::command]
+pub async fn validate_export_template(
+    app: AppHandle,
+    name: String,
+) -> Result<Vec<TemplateProblem>, AppError> {
+    let dir = templates_dir(&app)?;
+    blocking(move || {
+        let source = template_source(&dir, &name)?;
+        Ok(render(&name, &source, &TemplateSession::sample())
+            .err()
+            .into_iter()
+            .collect())
+    })
+    .await
+}
+
+/// Command to write session `id` to `path` laid out by `template`
+/// `version` picks the original text (the default) or the translation
+#[tauri::command]
+pub async fn export_session_with_template(
+    app: AppHandle,
+    id: i64,
+    template: String,
+    path: String,
+    version: Option<TextVersion>,
+) -> Result<(), AppError> {
+    let dir = templates_dir(&app)?;
+    let storage = Arc::clone(&app.state::<AppState>().storage);
+    let version = version.unwrap_or_default();
+    blocking(move || write_with_template(&storage, &dir, id, &template, version, &path)).await
+}
+
+/// Write session `id` to `path`, as `export_session_with_template` does
+pub fn write_with_template(
+    storage: &Storage,
+    dir: &Path,
+    id: i64,
+    template: &str,
+    version: TextVersion,
+    path: &str,
+) -> Result<(), AppError> {
+    let source = template_source(dir, template)?;
+    let (session, segments) = super::load(storage, id, version)?;
+    let contents = render(
+        template,
+        &source,
+        &TemplateSession::new(&session, &segments),
+    )
+    .map_err(|problem| problem.into_error(template))?;
+    write_atomic(&PathBuf::from(path), contents.as_bytes(), false)
+        .map_err(|e| AppError::Io(format!("Failed to write {}: {}", path, e)))
+}
+
+/// The templates folder in the app config directory, whether or not it exists yet
+pub fn templates_dir(app: &AppHandle) -> Result<PathBuf, AppError> {
+    app.path()
+        .app_config_dir()
+        .map(|dir| dir.join("templates"))
+        .map_err(|e| AppError::Config(format!("Could not resolve app config directory: {}", e)))
+}
+
+fn list(dir: &Path) -> Result<Vec<ExportTemplate>, AppError> {
+    fs::create_dir_all(dir)
+        .map_err(|e| AppError::Io(format!("Failed to create {}: {}", dir.display(), e)))?;
+    let mut templates: Vec<ExportTemplate> = BUILTIN
+        .iter()
+        .map(|(name, _)| ExportTemplate {
+            name: name.to_string(),
+            builtin: true,
+            path: None,
+        })
+        .collect();
+    let entries = fs::read_dir(dir)
+        .map_err(|e| AppError::Io(format!("Failed to read {}: {}", dir.display(), e)))?;
+    for path in entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+    {
+        let name = match path.file_stem().and_then(OsStr::to_str) {
+            Some(name) if path.extension() == Some(OsStr::new(EXTENSION)) && path.is_file() => {
+                name.to_string()
+            }
+            _ => continue,
+        };
+        if check_name(&name).is_err() {
+            continue;
+        }
+        let file = Some(path.to_string_lossy().into_owned());
+        match templates.iter_mut().find(|template| template.name == name) {
+            Some(builtin) => builtin.path = file,
+            None => templates.push(ExportTemplate {
+                name,
+                builtin: false,
+                path: file,
+            }),
+        }
+    }
+    templates.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(templates)
+}
+
+fn copy_builtin(dir: &Path, name: &str) -> Result<String, AppError> {
+    let source = builtin(name).ok_or_else(|| {
+        AppError::NotFound(format!("There is no built-in export template \"{}\"", name))
+    })?;
+    let path = template_path(dir, name);
+    if path.exists() {
+        return Err(AppError::InvalidInput(format!(
+            "{} already exists; edit it, or remove it to start over from the built-in one",
+            path.display()
+        )));
+    }
+    fs::create_dir_all(dir)
+        .map_err(|e| AppError::Io(format!("Failed to create {}: {}", dir.display(), e)))?;
+    write_atomic(&path, source.as_bytes(), false)
+        .map_err(|e| AppError::Io(format!("Failed to write {}: {}", path.display(), e)))?;
+    Ok(path.to_string_lossy().into_owned())
+}
+
+fn builtin(name: &str) -> Option<&'static str> {
+    BUILTIN
+        .iter()
+        .find(|(builtin, _)| *builtin == name)
+        .map(|(_, source)| *source)
+}
+
+fn template_path(dir: &Path, name: &str) -> PathBuf {
+    dir.join(format!("{}.{}", name, EXTENSION))
+}
+
+/// A template name is a file name in the templates folder, without its extension
+fn check_name(name: &str) -> Result<(), AppError> {
+    if name.trim().is_empty()
+        || name.starts_with('.')
+        || name.contains(['/', '\\'])
+        || name.chars().any(char::is_control)
+    {
+        return Err(AppError::InvalidInput(format!(
+            "\"{}\" isn't a template name; use the file name without .{}",
+            name, EXTENSION
+        )));
+    }
+    Ok(())
+}
+
+/// Template `name`'s source: the file in `dir` when there is one, else the built-in
+fn template_source(dir: &Path, name: &str) -> Result<String, AppError> {
+    check_name(name)?;
+    let path = template_path(dir, name);
+    match fs::read_to_string(&path) {
+        Ok(source) => return Ok(source),
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        Err(e) => {
+            return Err(AppError::Template(format!(
+                "Template \"{}\": failed to read {}: {}",
+                name,
+                path.display(),
+                e
+            )))
+        }
+    }
+    builtin(name)
+        .map(str::to_string)
+        .ok_or_else(|| AppError::NotFound(format!("There is no export template \"{}\"", name)))
+}
+
+/// `source`, compiled as template `name`, rendered for `session`
+fn render(name: &str, source: &str, session: &TemplateSession) -> Result<String, TemplateProblem> {
+    let mut registry = Handlebars::new();
+    registry.set_strict_mode(true);
+    registry.register_escape_fn(handlebars::no_escape);
+    registry
+        .register_template_string(name, source)
+        .map_err(|e| compile_problem(source, &e))?;
+    registry
+        .render(name, session)
+        .map_err(|e| render_problem(source, &e))
+}
+
+fn compile_problem(source: &str, error: &TemplateError) -> TemplateProblem {
+    let (line, column) = error.pos().unzip();
+    TemplateProblem {
+        line,
+        column,
+        expression: error
+            .pos()
+            .and_then(|(line, column)| expression_at(source, line, column)),
+        message: error.reason().to_string(),
+    }
+}
+
+fn render_problem(source: &str, error: &RenderError) -> TemplateProblem {
+    let mut expression = error
+        .line_no
+        .zip(error.column_no)
+        .and_then(|(line, column)| expression_at(source, line, column));
+    let message = match error.reason() {
+        RenderErrorReason::MissingVariable(Some(path)) => {
+            expression.get_or_insert_with(|| format!("{{{{{}}}}}", path));
+            format!("\"{}\" isn't a field here", path)
+        }
+        RenderErrorReason::MissingVariable(None) => "A field it names isn't there".to_string(),
+        RenderErrorReason::TemplateError(e) => e.reason().to_string(),
+        reason => reason.to_string(),
+    };
+    TemplateProblem {
+        line: error.line_no,
+        column: error.column_no,
+        expression,
+        message,
+    }
+}
+
+/// The `{{...}}` that starts at, or contains, 1-based `line` and `column` of `source`
+fn expression_at(source: &str, line: usize, column: usize) -> Option<String> {
+    let line_start: usize = source
+        .split_inclusive('\n')
+        .take(line.checked_sub(1)?)
+        .map(str::len)
+        .sum();
+    let (at, _) = source
+        .get(line_start..)?
+        .char_indices()
+        .nth(column.checked_sub(1)?)?;
+    let at = line_start + at;
+    let start = if source[at..].starts_with("{{") {
+        at
+    } else {
+        let start = source[..at].rfind("{{")?;
+        if source[start + 2..at].contains("}}") {
+            return None;
+        }
+        // The first brace of a triple-stash
+        start - usize::from(source[..start].ends_with('{'))
+    };
+    let end = start + source[start..].find("}}")? + 2;
+    // A triple-stash is closed by one more brace
+    let end = if source[start..].starts_with("{{{") && source[end..].starts_with('}') {
+        end + 1
+    } else {
+        end
+    };
+    Some(source[start..end].to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtins_render_the_sample() {
+        let sample = TemplateSession::sample();
+        let notes = render("meeting-notes", BUILTIN[0].1, &sample).unwrap();
+        assert!(notes.starts_with("# Release planning\n"), "{}", notes);
+        assert!(
+            notes.contains("**Speaker 2**\n\n[0:06] Then we ship on Friday.\n"),
+            "{}",
+            notes
+        );
+        assert!(
+            notes.contains("- **Speakers:** Speaker 1, Speaker 2\n"),
+            "{}",
+            notes
+        );
+        assert!(notes.contains("- **Tags:** #planning #team\n"), "{}", notes);
+        assert_eq!(notes.matches("**Speaker 1**").count(), 1, "{}", notes);
+
+        let timestamped = render("timestamped", BUILTIN[1].1, &sample).unwrap();
+        assert_eq!(
+            timestamped,
+            "Release planning (2024-05-01 14:30, 0:08)\n\n\
+             [0:00] Speaker 1: Let's go through the release plan.\n\
+             [0:03] Speaker 1: The build is ready for testing.\n\
+             [0:06] Speaker 2: Then we ship on Friday.\n"
+        );
+    }
+
+    #[test]
+    fn problems_name_the_line_and_expression() {
+        let sample = TemplateSession::sample();
+        let source = "# {{title}}\n{{#each segments}}\n[{{start}}] {{speaker_name}}\n{{/each}}\n";
+        let problem = render("notes", source, &sample).unwrap_err();
+        assert_eq!(problem.line, Some(3));
+        assert_eq!(problem.expression.as_deref(), Some("{{speaker_name}}"));
+        assert!(problem
+            .clone()
+            .into_error("notes")
+            .to_string()
+            .starts_with("Template \"notes\", line 3, column 13, {{speaker_name}}: "));
+
+        let unclosed = render(
+            "notes",
+            "{{title}}\n{{#each segments}}\n{{text}}\n",
+            &sample,
+        )
+        .unwrap_err();
+        assert!(unclosed.line.is_some(), "{:?}", unclosed);
+
+        assert_eq!(
+            expression_at("a\nb {{{ note }}} c", 2, 6).as_deref(),
+            Some("{{{ note }}}")
+        );
+        assert_eq!(expression_at("{{a}} b", 1, 7), None);
+    }
+}
@@ -0,0 +1,239 @@
+// Which application has focus, for picking an output profile at injection time
+// Wayland compositors don't tell regular clients which window is focused, so there
+// (and wherever the platform query fails) the answer is None
+
+use serde::Serialize;
+
+use crate::error::AppError;
+use crate::state;
+
+/// The focused application, as far as the platform reveals it
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct FrontmostApp {
+    /// Executable name on Windows and Linux, localized app name on macOS
+    pub process_name: Option<String>,
+    /// macOS only, e.g. "com.apple.Terminal"
+    pub bundle_id: Option<String>,
+    /// X11 only, e.g. "Gnome-terminal"
+    pub window_class: Option<String>,
+    pub pid: Option<u32>,
+}
+
+impl FrontmostApp {
+    /// Whether an output profile's `app` names this application
+    /// ".exe" is optional, so "WindowsTerminal" matches "WindowsTerminal.exe"
+    pub fn matches(&self, app: &str) -> bool {
+        let normalize = |name: &str| {
+            let name = name.trim().to_lowercase();
+            match name.strip_suffix(".exe") {
+                Some(stem) => stem.to_string(),
+                None => name,
+            }
+        };
+        let app = normalize(app);
+        !app.is_empty()
+            && [&self.bundle_id, &self.process_name, &self.window_class]
+                .into_iter()
+                .flatten()
+                .any(|name| normalize(name) == app)
+    }
+}
+
+/// Command to report the focused application, so a profile can be made for it
+#[tauri::command]
+pub async fn get_frontmost_app() -> Result<Option<FrontmostApp>, AppError> {
+    state::blocking(|| Ok(frontmost_app())).await
+}
+
+/// The focused application; blocks briefly on the platform query
+pub fn frontmost_app() -> Option<FrontmostApp> {
+    platform::frontmost_app()
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use objc2_app_kit::NSWorkspace;
+
+    use super::FrontmostApp;
+
+    pub fn frontmost_app() -> Option<FrontmostApp> {
+        // Some of these are unsafe in older objc2-app-kit releases
+        #[allow(unused_unsafe)]
+        let app = unsafe { NSWorkspace::sharedWorkspace().frontmostApplication()? };
+        #[allow(unused_unsafe)]
+        let (bundle_id, name, pid) = unsafe {
+            (
+                app.bundleIdentifier(),
+                app.localizedName(),
+                app.processIdentifier(),
+            )
+        };
+        Some(FrontmostApp {
+            process_name: name.map(|name| name.to_string()),
+            bundle_id: bundle_id.map(|id| id.to_string()),
+            window_class: None,
+            pid: u32::try_from(pid).ok(),
+        })
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::path::Path;
+
+    use windows_sys::Win32::Foundation::CloseHandle;
+    use windows_sys::Win32::System::Threading::{
+        OpenProcess, QueryFullProcessImageNameW, PROCESS_NAME_WIN32,
+        PROCESS_QUERY_LIMITED_INFORMATION,
+    };
+    use windows_sys::Win32::UI::WindowsAndMessaging::{
+        GetForegroundWindow, GetWindowThreadProcessId,
+    };
+
+    use super::FrontmostApp;
+
+    pub fn frontmost_app() -> Option<FrontmostApp> {
+        // SAFETY: plain Win32 queries on handles we own; the process handle is closed
+        // before returning and the buffer length is passed alongside it
+        unsafe {
+            let window = GetForegroundWindow();
+            if window.is_null() {
+                return None;
+            }
+            let mut pid = 0u32;
+            GetWindowThreadProcessId(window, &mut pid);
+            if pid == 0 {
+                return None;
+            }
+            let mut path = [0u16; 1024];
+            let mut len = path.len() as u32;
+            let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+            let found = !process.is_null()
+                && QueryFullProcessImageNameW(
+                    process,
+                    PROCESS_NAME_WIN32,
+                    path.as_mut_ptr(),
+                    &mut len,
+                ) != 0;
+            if !process.is_null() {
+                CloseHandle(process);
+            }
+            let process_name = found
+                .then(|| String::from_utf16_lossy(&path[..len as usize]))
+                .and_then(|path| {
+                    Path::new(&path)
+                        .file_name()
+                        .map(|name| name.to_string_lossy().into_owned())
+                });
+            Some(FrontmostApp {
+                process_name,
+                pid: Some(pid),
+                ..FrontmostApp::default()
+            })
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use x11rb::connection::Connection;
+    use x11rb::protocol::xproto::{Atom, AtomEnum, ConnectionExt};
+    use x11rb::rust_connection::RustConnection;
+
+    use super::FrontmostApp;
+
+    /// Asks the window manager through EWMH properties on the root window
+    pub fn frontmost_app() -> Option<FrontmostApp> {
+        if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+            return None;
+        }
+        let (conn, screen) = x11rb::connect(None).ok()?;
+        let root = conn.setup().roots.get(screen)?.root;
+        let active = intern(&conn, b"_NET_ACTIVE_WINDOW")?;
+        let window = property(&conn, root, active, AtomEnum::WINDOW.into(), 1)?
+            .value32()?
+            .next()
+            .filter(|&window| window != 0)?;
+
+        let pid = intern(&conn, b"_NET_WM_PID")
+            .and_then(|atom| property(&conn, window, atom, AtomEnum::CARDINAL.into(), 1))
+            .and_then(|reply| reply.value32()?.next());
+        let process_name = pid
+            .and_then(|pid| std::fs::read_to_string(format!("/proc/{}/comm", pid)).ok())
+            .map(|comm| comm.trim().to_string());
+        // WM_CLASS holds "instance\0class\0"
+        let window_class = property(
+            &conn,
+            window,
+            AtomEnum::WM_CLASS.into(),
+            AtomEnum::STRING.into(),
+            256,
+        )
+        .and_then(|reply| {
+            reply
+                .value
+                .split(|&byte| byte == 0)
+                .filter(|part| !part.is_empty())
+                .nth(1)
+                .map(|class| String::from_utf8_lossy(class).into_owned())
+        });
+        Some(FrontmostApp {
+            process_name,
+            bundle_id: None,
+            window_class,
+            pid,
+        })
+    }
+
+    fn intern(conn: &RustConnection, name: &[u8]) -> Option<Atom> {
+        let atom = conn.intern_atom(true, name).ok()?.reply().ok()?.atom;
+        (atom != 0).then_some(atom)
+    }
+
+    fn property(
+        conn: &RustConnection,
+        window: u32,
+        property: Atom,
+        kind: Atom,
+        length: u32,
+    ) -> Option<x11rb::protocol::xproto::GetPropertyReply> {
+        conn.get_property(false, window, property, kind, 0, length)
+            .ok()?
+            .reply()
+            .ok()
+    }
+}
+
+#[cfg(not(any(target_os = "macos", windows, target_os = "linux")))]
+mod platform {
+    use super::FrontmostApp;
+
+    pub fn frontmost_app() -> Option<FrontmostApp> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profiles_match_any_name_ignoring_case_and_exe() {
+        let terminal = FrontmostApp {
+            process_name: Some("WindowsTerminal.exe".to_string()),
+            ..FrontmostApp::default()
+        };
+        assert!(terminal.matches("windowsterminal"));
+        assert!(terminal.matches("WindowsTerminal.EXE"));
+        assert!(!terminal.matches("Terminal"));
+        assert!(!terminal.matches(" "));
+
+        let mail = FrontmostApp {
+            process_name: Some("Mail".to_string()),
+            bundle_id: Some("com.apple.mail".to_string()),
+            ..FrontmostApp::default()
+        };
+        assert!(mail.matches("com.apple.Mail"));
+        assert!(mail.matches("mail"));
+    }
+}
@@ -0,0 +1,47 @@
+// Small filesystem helpers shared by the config and key stores
+
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+/// Appended to a file's name for the temp file `write_atomic` writes first
+pub const TMP_SUFFIX: &str = ".tmp";
+
+/// Write a file atomically (temp file + rename) so a crash mid-write never
+/// leaves a truncated file behind. `private` restricts it to the owner (0600 on Unix).
+pub fn write_atomic(path: &Path, contents: &[u8], private: bool) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    // Start from a fresh temp file so the mode is applied on creation. The suffix goes
+    // after the whole name, so files that differ only in their extension (the profile
+    // key files) don't share a temp file
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(TMP_SUFFIX);
+    let tmp_path = path.with_file_name(tmp_name);
+    let _ = fs::remove_file(&tmp_path);
+    let result =
+        write_synced(&tmp_path, contents, private).and_then(|_| fs::rename(&tmp_path, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result
+}
+
+/// Write and flush a file to disk
+fn write_synced(path: &Path, contents: &[u8], private: bool) -> std::io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    if private {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    #[cfg(not(unix))]
+    let _ = private;
+
+    let mut file = options.open(path)?;
+    file.write_all(contents)?;
+    file.sync_all()
+}
@@ -0,0 +1,140 @@
+// One call for the frontend to decide what to show at launch
+// Gathers key, microphone, device, network and provider status concurrently, plus
+// first-run state
+
+use std::sync::atomic::Ordering;
+
+use serde::Serialize;
+use tauri::{AppHandle, Manager, State};
+
+use crate::audio::capture;
+use crate::config::{self, EngineKind, OutputTarget, Provider, SETTINGS_VERSION};
+use crate::deepgram::key_health::{KeyHealthState, KeyHealthStatus};
+use crate::deepgram::management::{self, ValidationFailure};
+use crate::engine::openai;
+use crate::engine::providers::{ProviderHealth, ProviderRole};
+use crate::error::AppError;
+use crate::permissions::{self, MicrophonePermission};
+use crate::state::{blocking, AppState};
+
+/// Result of `get_app_health`
+#[derive(Debug, Clone, Serialize)]
+pub struct AppHealth {
+    pub key_configured: bool,
+    /// None when there is no key, or Deepgram couldn't be asked
+    pub key_valid: Option<bool>,
+    pub mic_permission: MicrophonePermission,
+    pub default_input_device: Option<String>,
+    /// None when there is no key, since nothing would be sent to Deepgram yet
+    pub network_reachable: Option<bool>,
+    pub settings_version: u32,
+    /// Version of a settings file too new for this build, which is then using defaults
+    /// and won't save over it
+    pub settings_file_newer: Option<u32>,
+    /// No settings file existed at launch and onboarding hasn't been completed
+    pub first_run: bool,
+    /// Cloud providers the active profile uses, the primary first
+    pub providers: Vec<ProviderHealth>,
+    /// Where live sessions' committed segments go
+    pub output_targets: Vec<OutputTarget>,
+    /// Live sessions replay a script instead of transcribing; never meant for a release
+    pub mock_transcription: bool,
+    /// Latest background check of the key, for an expiry or low-balance banner
+    pub key_health: Option<KeyHealthStatus>,
+}
+
+/// Command to check everything the app needs to transcribe
+#[tauri::command]
+pub async fn get_app_health(app: AppHandle) -> Result<AppHealth, AppError> {
+    let handle = app.clone();
+    let key = blocking(move || Ok(crate::deepgram_api_key(&handle).ok())).await?;
+    let settings = config::transcription_settings(&app);
+    let handle = app.clone();
+    let output_targets = blocking(move || Ok(config::load(&handle)?.inject.output_targets)).await?;
+    let fallback = settings.fallback_provider;
+    let handle = app.clone();
+    let fallback_key = match fallback {
+        Some(Provider::Openai) => {
+            blocking(move || crate::secrets::load_openai_api_key(&handle)).await?
+        }
+        Some(Provider::Deepgram) | None => None,
+    };
+    let state = app.state::<AppState>();
+    let (http, route) = (&state.http(), &state.route());
+
+    let key_check = async {
+        let key = key.as_deref()?;
+        let validation = management::check_key(http, route, key).await;
+        match validation.failure {
+            None => Some(true),
+            Some(ValidationFailure::InvalidKey) => Some(false),
+            // Offline or an odd response says nothing about the key itself
+            Some(_) => None,
+        }
+    };
+    let network_check = async {
+        key.as_ref()?;
+        let reachable = management::is_reachable(http, route).await;
+        state.providers.record(Provider::Deepgram, reachable);
+        Some(reachable)
+    };
+    // Records its own outcome
+    let fallback_check = async {
+        if let Some(key) = fallback_key.as_deref() {
+            let _ = openai::check(&app, key).await;
+        }
+    };
+    let device_check = blocking(|| Ok(capture::default_input_device_name()));
+    let (key_valid, network_reachable, (), default_input_device) =
+        tokio::join!(key_check, network_check, fallback_check, device_check);
+
+    let mut providers = Vec::new();
+    if settings.engine == EngineKind::Deepgram {
+        providers.push(ProviderHealth {
+            provider: Provider::Deepgram,
+            role: ProviderRole::Primary,
+            key_configured: key.is_some(),
+            last_reachability: state.providers.last(Provider::Deepgram),
+        });
+    }
+    if let Some(provider) = fallback {
+        providers.push(ProviderHealth {
+            provider,
+            role: ProviderRole::Fallback,
+            key_configured: fallback_key.is_some(),
+            last_reachability: state.providers.last(provider),
+        });
+    }
+
+    Ok(AppHealth {
+        key_configured: key.is_some(),
+        key_valid,
+        mic_permission: permissions::microphone_permission(),
+        default_input_device: default_input_device?,
+        network_reachable,
+        settings_version: SETTINGS_VERSION,
+        settings_file_newer: config::newer_file_version(),
+        first_run: state.first_run.load(Ordering::Relaxed),
+        providers,
+        output_targets,
+        mock_transcription: settings.engine == EngineKind::Mock,
+        key_health: app.state::<KeyHealthState>().latest(),
+    })
+}
+
+/// Command to mark onboarding done; writes the settings file so later launches aren't first runs
+#[tauri::command]
+pub async fn complete_onboarding(
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<(), AppError> {
+    blocking(move || {
+        if !config::settings_file_exists(&app) {
+            config::update(&app, |_| Ok(()))?;
+        }
+        Ok(())
+    })
+    .await?;
+    state.first_run.store(false, Ordering::Relaxed);
+    Ok(())
+}
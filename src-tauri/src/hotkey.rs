@@ -0,0 +1,204 @@
+// Global push-to-talk shortcut
+// Works while the window is unfocused; the frontend starts/stops streaming on the events
+// Mobile has no global shortcuts, so there only the toggle that the UI drives is left
+
+#[cfg(desktop)]
+use std::str::FromStr;
+use std::sync::Mutex;
+#[cfg(desktop)]
+use std::sync::MutexGuard;
+
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager, State};
+#[cfg(desktop)]
+use tauri_plugin_global_shortcut::{GlobalShortcutExt, Shortcut, ShortcutEvent, ShortcutState};
+
+use crate::config::{HotkeySettings, PushToTalkMode};
+use crate::error::AppError;
+use crate::state::blocking;
+
+/// Hold mode: the shortcut went down
+pub const EVENT_PTT_PRESSED: &str = "ptt-pressed";
+/// Hold mode: the shortcut was let go
+pub const EVENT_PTT_RELEASED: &str = "ptt-released";
+/// Toggle mode: a press flipped recording on or off
+pub const EVENT_PTT_TOGGLED: &str = "ptt-toggled";
+
+/// Payload of the `ptt-toggled` event
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct PttToggled {
+    pub active: bool,
+}
+
+struct Binding {
+    accelerator: String,
+    #[cfg(desktop)]
+    shortcut: Shortcut,
+}
+
+/// Managed state holding the registered shortcut and the toggle position
+#[derive(Default)]
+pub struct HotkeyState {
+    binding: Mutex<Option<Binding>>,
+    mode: Mutex<PushToTalkMode>,
+    toggled_on: Mutex<bool>,
+}
+
+/// Register the saved shortcut at startup; a bad binding is logged, not fatal
+pub fn init(app: &AppHandle) {
+    let settings = crate::config::load(app)
+        .map(|config| config.hotkey)
+        .unwrap_or_default();
+    let state = app.state::<HotkeyState>();
+    if let Ok(mut mode) = state.mode.lock() {
+        *mode = settings.mode;
+    }
+    #[cfg(desktop)]
+    if let Err(e) = bind(app, &state, &settings.push_to_talk) {
+        tracing::warn!("Push-to-talk shortcut not registered: {}", e);
+    }
+}
+
+/// Plugin handler, called for every registered shortcut
+#[cfg(desktop)]
+pub fn handle_shortcut(app: &AppHandle, shortcut: &Shortcut, event: ShortcutEvent) {
+    let state = app.state::<HotkeyState>();
+    let is_ptt = state
+        .binding
+        .lock()
+        .is_ok_and(|binding| binding.as_ref().is_some_and(|b| b.shortcut == *shortcut));
+    if !is_ptt {
+        return;
+    }
+
+    let mode = state.mode.lock().map(|mode| *mode).unwrap_or_default();
+    match (mode, event.state) {
+        (PushToTalkMode::Hold, ShortcutState::Pressed) => {
+            let _ = app.emit(EVENT_PTT_PRESSED, ());
+        }
+        (PushToTalkMode::Hold, ShortcutState::Released) => {
+            let _ = app.emit(EVENT_PTT_RELEASED, ());
+        }
+        (PushToTalkMode::Toggle, ShortcutState::Pressed) => {
+            let Ok(active) = state.toggled_on.lock().map(|toggled_on| !*toggled_on) else {
+                return;
+            };
+            set_dictation(app, active);
+        }
+        (PushToTalkMode::Toggle, ShortcutState::Released) => {}
+    }
+}
+
+/// Start or stop dictation the way a toggle-mode press does
+/// Also used by the tray menu, so both go through the same frontend handler
+pub fn set_dictation(app: &AppHandle, active: bool) {
+    if let Ok(mut toggled_on) = app.state::<HotkeyState>().toggled_on.lock() {
+        *toggled_on = active;
+    }
+    let _ = app.emit(EVENT_PTT_TOGGLED, PttToggled { active });
+}
+
+/// Register `accelerator` as the push-to-talk shortcut, replacing the current one
+/// The old shortcut is only released once the new one registered successfully
+#[cfg(desktop)]
+fn bind(app: &AppHandle, state: &HotkeyState, accelerator: &str) -> Result<(), AppError> {
+    let shortcut = Shortcut::from_str(accelerator)
+        .map_err(|e| AppError::Shortcut(format!("Invalid shortcut \"{}\": {}", accelerator, e)))?;
+    let current = lock_binding(state)?.as_ref().map(|b| b.shortcut);
+    if current == Some(shortcut) {
+        // Same keys, possibly spelled differently; keep the existing registration
+        if let Some(binding) = lock_binding(state)?.as_mut() {
+            binding.accelerator = accelerator.to_string();
+        }
+        return Ok(());
+    }
+
+    // Don't hold the lock while registering: the plugin may dispatch to the main
+    // thread, which is also where the shortcut handler takes this lock
+    let global_shortcut = app.global_shortcut();
+    global_shortcut.register(shortcut).map_err(|e| {
+        AppError::Shortcut(format!(
+            "Failed to register shortcut \"{}\": {}",
+            accelerator, e
+        ))
+    })?;
+    let old = lock_binding(state)?.replace(Binding {
+        accelerator: accelerator.to_string(),
+        shortcut,
+    });
+    if let Some(old) = old {
+        if let Err(e) = global_shortcut.unregister(old.shortcut) {
+            tracing::warn!("Failed to unregister {}: {}", old.accelerator, e);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(mobile)]
+fn bind(_app: &AppHandle, _state: &HotkeyState, _accelerator: &str) -> Result<(), AppError> {
+    Err(AppError::Unsupported(
+        "Global shortcuts aren't available on mobile".to_string(),
+    ))
+}
+
+#[cfg(desktop)]
+fn lock_binding(state: &HotkeyState) -> Result<MutexGuard<'_, Option<Binding>>, AppError> {
+    state
+        .binding
+        .lock()
+        .map_err(|_| AppError::poisoned("Hotkey state"))
+}
+
+/// Persist a change to the hotkey section of the config
+fn save_settings(
+    app: &AppHandle,
+    update: impl FnOnce(&mut HotkeySettings),
+) -> Result<(), AppError> {
+    crate::config::update(app, |config| {
+        update(&mut config.hotkey);
+        Ok(())
+    })
+}
+
+/// Command to change the push-to-talk shortcut, e.g. "CommandOrControl+Shift+Space"
+#[tauri::command]
+pub async fn set_push_to_talk_shortcut(
+    app: AppHandle,
+    accelerator: String,
+) -> Result<(), AppError> {
+    blocking(move || {
+        let accelerator = accelerator.trim();
+        bind(&app, &app.state::<HotkeyState>(), accelerator)?;
+        save_settings(&app, |hotkey| hotkey.push_to_talk = accelerator.to_string())
+    })
+    .await
+}
+
+/// Command to read the active push-to-talk shortcut (None if registration failed)
+#[tauri::command]
+pub fn get_push_to_talk_shortcut(state: State<'_, HotkeyState>) -> Option<String> {
+    state
+        .binding
+        .lock()
+        .ok()
+        .and_then(|binding| binding.as_ref().map(|b| b.accelerator.clone()))
+}
+
+/// Command to switch between hold-to-talk and toggle mode
+#[tauri::command]
+pub async fn set_push_to_talk_mode(
+    app: AppHandle,
+    state: State<'_, HotkeyState>,
+    mode: PushToTalkMode,
+) -> Result<(), AppError> {
+    let handle = app.clone();
+    blocking(move || save_settings(&handle, |hotkey| hotkey.mode = mode)).await?;
+    if let Ok(mut current) = state.mode.lock() {
+        *current = mode;
+    }
+    // Start the next toggle from "off" whatever the previous mode left behind
+    if let Ok(mut toggled_on) = state.toggled_on.lock() {
+        *toggled_on = false;
+    }
+    Ok(())
+}
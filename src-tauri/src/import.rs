@@ -0,0 +1,428 @@
+// Import transcripts made elsewhere into history: plain text, SRT and WebVTT
+// A text file becomes one session without timings; subtitle cues become segments with
+// their timings. Damaged subtitle files import whatever cues can be read, with a
+// warning per problem, so one bad cue doesn't lose the rest of the file
+
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+use tauri::State;
+
+use crate::error::AppError;
+use crate::postprocess::dictation_commands;
+use crate::state::{blocking, AppState};
+use crate::storage::{NewSession, Segment, Storage};
+
+/// Largest file accepted; transcripts are text, so anything bigger is likely something else
+const MAX_FILE_BYTES: u64 = 20 * 1024 * 1024;
+/// Stored as the model of imported sessions
+const IMPORT_MODEL: &str = "import";
+/// Stored as the language of imported sessions ("undetermined" in BCP 47)
+const UNKNOWN_LANGUAGE: &str = "und";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ImportFormat {
+    Txt,
+    Srt,
+    Vtt,
+}
+
+impl ImportFormat {
+    fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "txt" => Some(Self::Txt),
+            "srt" => Some(Self::Srt),
+            "vtt" => Some(Self::Vtt),
+            _ => None,
+        }
+    }
+}
+
+/// A problem found in a file, with the 1-based line it's on when there is one
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ImportIssue {
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl ImportIssue {
+    fn at(line: usize, message: impl Into<String>) -> Self {
+        Self {
+            line: Some(line),
+            message: message.into(),
+        }
+    }
+
+    fn file(message: impl Into<String>) -> Self {
+        Self {
+            line: None,
+            message: message.into(),
+        }
+    }
+}
+
+/// Outcome for one file of an import
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportResult {
+    pub path: String,
+    /// The new session, unless the file couldn't be imported
+    pub session_id: Option<i64>,
+    /// Why nothing was imported
+    pub error: Option<ImportIssue>,
+    /// Parts of the file that were skipped or repaired
+    pub warnings: Vec<ImportIssue>,
+}
+
+/// What a file parsed into
+#[derive(Debug, Default)]
+struct Parsed {
+    text: String,
+    segments: Vec<Segment>,
+    warnings: Vec<ImportIssue>,
+}
+
+/// Command to import transcript files as history sessions, one per file
+/// Each file is dated by its modification time. A file that fails doesn't stop the others,
+/// so the result lists every path in order
+#[tauri::command]
+pub async fn import_transcripts(
+    state: State<'_, AppState>,
+    paths: Vec<String>,
+) -> Result<Vec<ImportResult>, AppError> {
+    let storage = Arc::clone(&state.storage);
+    blocking(move || {
+        Ok(paths
+            .into_iter()
+            .map(|path| import_file(&storage, path))
+            .collect())
+    })
+    .await
+}
+
+fn import_file(storage: &Storage, path: String) -> ImportResult {
+    let (session_id, error, warnings) = match import_path(storage, Path::new(&path)) {
+        Ok((id, warnings)) => (Some(id), None, warnings),
+        Err(error) => (None, Some(error), Vec::new()),
+    };
+    ImportResult {
+        path,
+        session_id,
+        error,
+        warnings,
+    }
+}
+
+fn import_path(storage: &Storage, path: &Path) -> Result<(i64, Vec<ImportIssue>), ImportIssue> {
+    let format = ImportFormat::from_path(path)
+        .ok_or_else(|| ImportIssue::file("Unsupported file type; expected .txt, .srt or .vtt"))?;
+    let metadata = std::fs::metadata(path)
+        .map_err(|e| ImportIssue::file(format!("Failed to read the file: {}", e)))?;
+    if metadata.len() > MAX_FILE_BYTES {
+        return Err(ImportIssue::file(format!(
+            "File is larger than {} MB",
+            MAX_FILE_BYTES / (1024 * 1024)
+        )));
+    }
+    let bytes = std::fs::read(path)
+        .map_err(|e| ImportIssue::file(format!("Failed to read the file: {}", e)))?;
+    // Older tools often wrote Latin-1 or Windows-1252; keep what decodes
+    let contents = String::from_utf8_lossy(&bytes);
+    let contents = contents.strip_prefix('\u{feff}').unwrap_or(&contents);
+
+    let parsed = match format {
+        ImportFormat::Txt => parse_txt(contents),
+        ImportFormat::Srt | ImportFormat::Vtt => parse_cues(contents, format)?,
+    };
+    if parsed.text.is_empty() {
+        return Err(ImportIssue::file("The file contains no transcript text"));
+    }
+
+    let started_at = metadata
+        .modified()
+        .unwrap_or_else(|_| SystemTime::now())
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0);
+    let session = NewSession {
+        started_at,
+        duration_ms: parsed
+            .segments
+            .iter()
+            .map(|segment| segment.end_ms)
+            .max()
+            .unwrap_or(0),
+        model: IMPORT_MODEL.to_string(),
+        language: UNKNOWN_LANGUAGE.to_string(),
+        detected_language: None,
+        text: parsed.text,
+        audio_path: None,
+        segments: parsed.segments,
+        recovered: false,
+        audio_source: None,
+        imported: true,
+        translated_text: None,
+        translation_language: None,
+        provider: None,
+        request_id: None,
+        model_version: None,
+        disfluencies_removed: None,
+    };
+    let id = storage
+        .insert_session(&session)
+        .map_err(|e| ImportIssue::file(e.to_string()))?;
+    Ok((id, parsed.warnings))
+}
+
+/// A text file is one session; its line breaks are kept
+fn parse_txt(contents: &str) -> Parsed {
+    let text = contents
+        .lines()
+        .map(str::trim_end)
+        .collect::<Vec<_>>()
+        .join("\n");
+    Parsed {
+        text: text.trim().to_string(),
+        ..Parsed::default()
+    }
+}
+
+/// Read the cues of an SRT or WebVTT file as segments
+/// Only a WebVTT file without its header fails outright; bad cues are skipped with a warning
+fn parse_cues(contents: &str, format: ImportFormat) -> Result<Parsed, ImportIssue> {
+    let lines: Vec<&str> = contents.lines().collect();
+    let mut blocks = blocks(&lines).into_iter();
+    if format == ImportFormat::Vtt {
+        match blocks.next() {
+            Some((_, header)) if header[0].starts_with("WEBVTT") => {}
+            _ => return Err(ImportIssue::at(1, "Missing the WEBVTT header")),
+        }
+    }
+
+    let mut parsed = Parsed::default();
+    let mut last_index: Option<u64> = None;
+    for (first_line, block) in blocks {
+        // Comments, styles and regions carry no text
+        if format == ImportFormat::Vtt
+            && ["NOTE", "STYLE", "REGION"]
+                .iter()
+                .any(|keyword| block[0].split_whitespace().next() == Some(keyword))
+        {
+            continue;
+        }
+        let Some(timing) = block.iter().position(|line| line.contains("-->")) else {
+            parsed.warnings.push(ImportIssue::at(
+                first_line,
+                "Skipped a block with no timing line",
+            ));
+            continue;
+        };
+        let timing_line = first_line + timing;
+
+        if format == ImportFormat::Srt {
+            match block[..timing]
+                .first()
+                .map(|index| index.trim().parse::<u64>())
+            {
+                Some(Ok(index)) => {
+                    if last_index.is_some_and(|last| index <= last) {
+                        parsed.warnings.push(ImportIssue::at(
+                            first_line,
+                            format!("Cue number {} is out of order", index),
+                        ));
+                    }
+                    last_index = Some(index);
+                }
+                Some(Err(_)) => parsed
+                    .warnings
+                    .push(ImportIssue::at(first_line, "Cue number is not a number")),
+                None => parsed
+                    .warnings
+                    .push(ImportIssue::at(timing_line, "Cue has no number")),
+            }
+        }
+
+        let Some((start_ms, end_ms)) = parse_timing(block[timing]) else {
+            parsed.warnings.push(ImportIssue::at(
+                timing_line,
+                format!(
+                    "Skipped a cue with a bad timestamp: {}",
+                    block[timing].trim()
+                ),
+            ));
+            continue;
+        };
+        let end_ms = if end_ms < start_ms {
+            parsed.warnings.push(ImportIssue::at(
+                timing_line,
+                "Cue ends before it starts; its end was moved to its start",
+            ));
+            start_ms
+        } else {
+            end_ms
+        };
+        if parsed
+            .segments
+            .last()
+            .is_some_and(|previous| start_ms < previous.end_ms)
+        {
+            parsed.warnings.push(ImportIssue::at(
+                timing_line,
+                "Cue overlaps the one before it",
+            ));
+        }
+
+        let text = block[timing + 1..]
+            .iter()
+            .map(|line| strip_markup(line))
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ");
+        if text.is_empty() {
+            continue;
+        }
+        parsed.segments.push(Segment {
+            start_ms,
+            end_ms,
+            text,
+            words: None,
+            speaker: None,
+            translated: None,
+            channel: None,
+            speaker_name: None,
+        });
+    }
+
+    parsed.text =
+        dictation_commands::join(parsed.segments.iter().map(|segment| segment.text.as_str()));
+    Ok(parsed)
+}
+
+/// Groups of consecutive non-blank lines, each with the 1-based number of its first line
+fn blocks<'a>(lines: &[&'a str]) -> Vec<(usize, Vec<&'a str>)> {
+    let mut blocks: Vec<(usize, Vec<&str>)> = Vec::new();
+    let mut current: Option<(usize, Vec<&str>)> = None;
+    for (index, line) in lines.iter().enumerate() {
+        if line.trim().is_empty() {
+            blocks.extend(current.take());
+        } else {
+            current
+                .get_or_insert_with(|| (index + 1, Vec::new()))
+                .1
+                .push(line);
+        }
+    }
+    blocks.extend(current);
+    blocks
+}
+
+/// Start and end of a "00:00:01,000 --> 00:00:02,500" line; WebVTT cue settings after
+/// the end are ignored
+fn parse_timing(line: &str) -> Option<(i64, i64)> {
+    let (start, rest) = line.split_once("-->")?;
+    let end = rest.split_whitespace().next()?;
+    Some((parse_timestamp(start.trim())?, parse_timestamp(end)?))
+}
+
+/// Milliseconds of "hh:mm:ss,mmm", "hh:mm:ss.mmm" or "mm:ss.mmm"
+fn parse_timestamp(timestamp: &str) -> Option<i64> {
+    let (clock, millis) = timestamp.split_once([',', '.'])?;
+    if millis.is_empty() || millis.len() > 3 || !millis.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    // "5" after the separator is 500 ms
+    let millis: i64 = format!("{:0<3}", millis).parse().ok()?;
+    let parts: Vec<i64> = clock
+        .split(':')
+        .map(|part| {
+            (!part.is_empty() && part.bytes().all(|b| b.is_ascii_digit()))
+                .then(|| part.parse().ok())
+                .flatten()
+        })
+        .collect::<Option<_>>()?;
+    let (hours, minutes, seconds) = match parts[..] {
+        [hours, minutes, seconds] => (hours, minutes, seconds),
+        [minutes, seconds] => (0, minutes, seconds),
+        _ => return None,
+    };
+    if minutes >= 60 || seconds >= 60 {
+        return None;
+    }
+    Some(((hours * 60 + minutes) * 60 + seconds) * 1000 + millis)
+}
+
+/// Cue text without formatting tags like `<i>`, `<v Speaker>` or `{\an8}`
+fn strip_markup(line: &str) -> String {
+    let mut text = String::with_capacity(line.len());
+    let mut closing = None;
+    for c in line.chars() {
+        match (closing, c) {
+            (None, '<') => closing = Some('>'),
+            (None, '{') => closing = Some('}'),
+            (Some(end), c) if c == end => closing = None,
+            (Some(_), _) => {}
+            (None, c) => text.push(c),
+        }
+    }
+    text.trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timestamps_in_both_notations() {
+        assert_eq!(parse_timestamp("01:02:03,456"), Some(3_723_456));
+        assert_eq!(parse_timestamp("00:00:01.5"), Some(1_500));
+        assert_eq!(parse_timestamp("02:03.004"), Some(123_004));
+        assert_eq!(parse_timestamp("00:61:00,000"), None);
+        assert_eq!(parse_timestamp("00:00:01"), None);
+        assert_eq!(parse_timestamp("aa:00:01,000"), None);
+        assert_eq!(
+            parse_timing("00:00:01.000 --> 00:00:02.000 align:start position:0%"),
+            Some((1_000, 2_000))
+        );
+    }
+
+    #[test]
+    fn broken_srt_keeps_the_readable_cues() {
+        let srt = "1\n00:00:01,000 --> 00:00:02,000\n<i>Hello</i> there.\n\n\
+                   1\n00:00:01,500 --> 00:00:03,000\nHow are\nyou?\n\n\
+                   3\n00:00:xx,000 --> 00:00:04,000\nLost.\n\n\
+                   4\n00:00:05,000 --> 00:00:06,000\n{\\an8}Fine.\n";
+        let parsed = parse_cues(srt, ImportFormat::Srt).unwrap();
+        let texts: Vec<_> = parsed
+            .segments
+            .iter()
+            .map(|segment| (segment.start_ms, segment.text.as_str()))
+            .collect();
+        assert_eq!(
+            texts,
+            [
+                (1_000, "Hello there."),
+                (1_500, "How are you?"),
+                (5_000, "Fine.")
+            ]
+        );
+        let lines: Vec<_> = parsed.warnings.iter().map(|issue| issue.line).collect();
+        // Repeated number and overlap on the second cue, bad timestamp on the third
+        assert_eq!(lines, [Some(5), Some(6), Some(11)]);
+        assert_eq!(parsed.text, "Hello there. How are you? Fine.");
+    }
+
+    #[test]
+    fn vtt_needs_its_header_and_skips_notes() {
+        let vtt = "WEBVTT - exported\n\nNOTE written by hand\n\n\
+                   intro\n00:01.000 --> 00:02.000\n<v Alice>Hi.\n";
+        let parsed = parse_cues(vtt, ImportFormat::Vtt).unwrap();
+        assert_eq!(parsed.segments.len(), 1);
+        assert_eq!(parsed.segments[0].text, "Hi.");
+        assert!(parsed.warnings.is_empty());
+
+        let error = parse_cues("00:01.000 --> 00:02.000\nHi.\n", ImportFormat::Vtt).unwrap_err();
+        assert_eq!(error.line, Some(1));
+    }
+}
@@ -0,0 +1,605 @@
+// Put finished transcripts into whatever application has focus
+// Either types them as keystrokes or pastes them through the clipboard; pasting puts back
+// what was copied before (text, rich text, an image or files) once the paste has landed
+// What was last typed is remembered, so a segment taken back can be backspaced over
+// Mobile apps can't type into other apps, so injection is desktop-only
+
+#[cfg(desktop)]
+use std::path::PathBuf;
+#[cfg(desktop)]
+use std::sync::Mutex;
+#[cfg(desktop)]
+use std::thread;
+use std::time::Duration;
+
+#[cfg(desktop)]
+use arboard::{Clipboard, ImageData};
+#[cfg(desktop)]
+use enigo::{Direction, Enigo, Key, Keyboard, Settings};
+use serde::Serialize;
+use tauri::AppHandle;
+
+use crate::config::{
+    AppConfig, ClipboardMode, InjectMode, InjectSettings, OutputProfile, OutputTarget, Trailing,
+};
+use crate::error::AppError;
+use crate::frontmost::{self, FrontmostApp};
+use crate::state;
+
+/// Longest accepted injection delay
+const MAX_DELAY_MS: u32 = 5_000;
+/// Time for the clipboard owner change to settle before pasting
+#[cfg(desktop)]
+const CLIPBOARD_SETTLE: Duration = Duration::from_millis(50);
+
+/// The text of the last injection, when it was typed; pasting clears it, since what was
+/// pasted can't be taken back keystroke by keystroke
+#[cfg(desktop)]
+static TYPED: Mutex<String> = Mutex::new(String::new());
+
+/// What `type_text` actually did
+#[derive(Debug, Clone, Serialize)]
+pub struct InjectResult {
+    /// Mode that was used, which may differ from the one requested
+    pub mode: InjectMode,
+    /// Why a different mode than requested was used
+    pub fallback_reason: Option<String>,
+    /// The paste keystroke couldn't be sent; the text is on the clipboard for a manual paste
+    pub left_on_clipboard: bool,
+    /// The clipboard held something that couldn't be put back after pasting
+    pub clipboard_not_restored: bool,
+    /// Application that had focus, when it could be detected
+    pub app: Option<FrontmostApp>,
+    /// `app` of the output profile that was applied, if any
+    pub profile: Option<String>,
+    /// What was typed or pasted, trailing suffix included
+    pub text: String,
+}
+
+/// Command to type or paste `text` into the focused application
+/// The focused application's output profile, if it has one, overrides the saved settings;
+/// `mode` overrides both. `raw_text` is the transcript before dictation commands and
+/// replacements (`raw_transcript` of the event), injected instead where a profile turns
+/// post-processing off
+#[tauri::command]
+pub async fn type_text(
+    app: AppHandle,
+    text: String,
+    mode: Option<InjectMode>,
+    raw_text: Option<String>,
+) -> Result<InjectResult, AppError> {
+    if text.is_empty() {
+        return Err(AppError::InvalidInput("Nothing to insert".to_string()));
+    }
+    let config = state::blocking(move || Ok(crate::config::load(&app).unwrap_or_default())).await?;
+
+    tokio::time::sleep(Duration::from_millis(u64::from(
+        config.inject.delay_ms.min(MAX_DELAY_MS),
+    )))
+    .await;
+
+    // Synthetic input blocks, so keep it off the async runtime.
+    // Focus is checked after the delay, since that's when the user has refocused
+    state::blocking(move || insert(&config, &text, raw_text.as_deref(), mode)).await
+}
+
+/// Type or paste `text` into the focused application now, as `type_text` does without
+/// its delay; blocks
+pub(crate) fn insert(
+    config: &AppConfig,
+    text: &str,
+    raw_text: Option<&str>,
+    mode: Option<InjectMode>,
+) -> Result<InjectResult, AppError> {
+    let target = frontmost::frontmost_app();
+    let profile = target
+        .as_ref()
+        .and_then(|target| find_profile(&config.output_profiles, target));
+    let output = Output::resolve(&config.inject, profile);
+    let mut result = inject(
+        &output.text(text, raw_text),
+        mode.unwrap_or(output.mode),
+        &config.inject,
+    )?;
+    result.app = target;
+    result.profile = profile.map(|profile| profile.app.clone());
+    Ok(result)
+}
+
+/// How to inject into one application: its profile over the saved settings
+pub(crate) struct Output {
+    pub(crate) mode: InjectMode,
+    pub(crate) trailing: Trailing,
+    pub(crate) postprocess: bool,
+}
+
+impl Output {
+    fn resolve(settings: &InjectSettings, profile: Option<&OutputProfile>) -> Self {
+        Self {
+            mode: profile
+                .and_then(|profile| profile.mode)
+                .unwrap_or(settings.mode),
+            trailing: profile
+                .and_then(|profile| profile.trailing)
+                .unwrap_or(settings.trailing),
+            postprocess: profile
+                .and_then(|profile| profile.postprocess)
+                .unwrap_or(settings.postprocess),
+        }
+    }
+
+    /// What to insert for `text`: `raw_text` instead where post-processing is off, then
+    /// the trailing suffix
+    pub(crate) fn text(&self, text: &str, raw_text: Option<&str>) -> String {
+        let text = match raw_text {
+            Some(raw) if !self.postprocess && !raw.is_empty() => raw,
+            _ => text,
+        };
+        format!("{}{}", text, self.trailing.suffix())
+    }
+}
+
+fn find_profile<'a>(
+    profiles: &'a [OutputProfile],
+    target: &FrontmostApp,
+) -> Option<&'a OutputProfile> {
+    profiles.iter().find(|profile| target.matches(&profile.app))
+}
+
+/// Command to list the per-application output profiles
+#[tauri::command]
+pub async fn list_output_profiles(app: AppHandle) -> Result<Vec<OutputProfile>, AppError> {
+    state::blocking(move || Ok(crate::config::load(&app)?.output_profiles)).await
+}
+
+/// Command to add an output profile, or replace the one for the same application
+/// Returns all profiles
+#[tauri::command]
+pub async fn upsert_output_profile(
+    app: AppHandle,
+    mut profile: OutputProfile,
+) -> Result<Vec<OutputProfile>, AppError> {
+    profile.app = profile.app.trim().to_string();
+    if profile.app.is_empty() {
+        return Err(AppError::InvalidInput(
+            "An output profile needs an application name".to_string(),
+        ));
+    }
+    update_profiles(app, move |profiles| {
+        match profiles
+            .iter_mut()
+            .find(|existing| existing.app.eq_ignore_ascii_case(&profile.app))
+        {
+            Some(existing) => *existing = profile,
+            None => profiles.push(profile),
+        }
+    })
+    .await
+}
+
+/// Command to remove the output profile for `name`, returning the remaining profiles
+#[tauri::command]
+pub async fn delete_output_profile(
+    app: AppHandle,
+    name: String,
+) -> Result<Vec<OutputProfile>, AppError> {
+    update_profiles(app, move |profiles| {
+        profiles.retain(|profile| !profile.app.eq_ignore_ascii_case(name.trim()))
+    })
+    .await
+}
+
+async fn update_profiles(
+    app: AppHandle,
+    update: impl FnOnce(&mut Vec<OutputProfile>) + Send + 'static,
+) -> Result<Vec<OutputProfile>, AppError> {
+    state::blocking(move || {
+        crate::config::update(&app, |config| {
+            update(&mut config.output_profiles);
+            Ok(config.output_profiles.clone())
+        })
+    })
+    .await
+}
+
+/// Command to change the injection settings, returning the saved settings
+/// These apply to every application without an output profile
+#[tauri::command]
+pub async fn set_inject_settings(
+    app: AppHandle,
+    mode: Option<InjectMode>,
+    delay_ms: Option<u32>,
+    trailing: Option<Trailing>,
+    postprocess: Option<bool>,
+    restore_clipboard: Option<bool>,
+    clipboard_restore_delay_ms: Option<u32>,
+) -> Result<InjectSettings, AppError> {
+    if [delay_ms, clipboard_restore_delay_ms]
+        .iter()
+        .flatten()
+        .any(|&delay| delay > MAX_DELAY_MS)
+    {
+        return Err(AppError::InvalidInput(format!(
+            "Delay must be at most {} ms",
+            MAX_DELAY_MS
+        )));
+    }
+    state::blocking(move || {
+        crate::config::update(&app, |config| {
+            let inject = &mut config.inject;
+            if let Some(mode) = mode {
+                inject.mode = mode;
+            }
+            if let Some(delay_ms) = delay_ms {
+                inject.delay_ms = delay_ms;
+            }
+            if let Some(trailing) = trailing {
+                inject.trailing = trailing;
+            }
+            if let Some(postprocess) = postprocess {
+                inject.postprocess = postprocess;
+            }
+            if let Some(restore_clipboard) = restore_clipboard {
+                inject.restore_clipboard = restore_clipboard;
+            }
+            if let Some(delay_ms) = clipboard_restore_delay_ms {
+                inject.clipboard_restore_delay_ms = delay_ms;
+            }
+            Ok(inject.clone())
+        })
+    })
+    .await
+}
+
+/// Command to choose where a live session's committed segments go, and when the clipboard
+/// gets them; applies from the next session. Returns the saved injection settings
+#[tauri::command]
+pub async fn set_output_targets(
+    app: AppHandle,
+    mut targets: Vec<OutputTarget>,
+    clipboard_mode: Option<ClipboardMode>,
+) -> Result<InjectSettings, AppError> {
+    if cfg!(mobile)
+        && targets
+            .iter()
+            .any(|&target| target != OutputTarget::Display)
+    {
+        return Err(AppError::Unsupported(
+            "Only the display output is available on mobile".to_string(),
+        ));
+    }
+    // Kept in the order they're sent in
+    targets.sort();
+    targets.dedup();
+    state::blocking(move || {
+        crate::config::update(&app, |config| {
+            config.inject.output_targets = targets;
+            if let Some(mode) = clipboard_mode {
+                config.inject.clipboard_mode = mode;
+            }
+            Ok(config.inject.clone())
+        })
+    })
+    .await
+}
+
+/// Command to read the injection settings
+#[tauri::command]
+pub async fn get_inject_settings(app: AppHandle) -> Result<InjectSettings, AppError> {
+    state::blocking(move || Ok(inject_settings(&app))).await
+}
+
+fn inject_settings(app: &AppHandle) -> InjectSettings {
+    crate::config::load(app)
+        .map(|config| config.inject)
+        .unwrap_or_default()
+}
+
+#[cfg(desktop)]
+fn inject(
+    text: &str,
+    requested: InjectMode,
+    settings: &InjectSettings,
+) -> Result<InjectResult, AppError> {
+    match requested {
+        // Wayland compositors don't accept synthetic key events from regular clients
+        InjectMode::Type if is_wayland() => {
+            let mut result = paste(text, settings)?;
+            result.fallback_reason =
+                Some("Typing isn't supported on Wayland, pasted instead".to_string());
+            Ok(result)
+        }
+        InjectMode::Type => {
+            let mut enigo = new_enigo()?;
+            enigo
+                .text(text)
+                .map_err(|e| AppError::Injection(format!("Failed to type text: {}", e)))?;
+            if let Ok(mut typed) = TYPED.lock() {
+                *typed = text.to_string();
+            }
+            Ok(InjectResult {
+                mode: InjectMode::Type,
+                fallback_reason: None,
+                left_on_clipboard: false,
+                clipboard_not_restored: false,
+                app: None,
+                profile: None,
+                text: text.to_string(),
+            })
+        }
+        InjectMode::Paste => paste(text, settings),
+    }
+}
+
+#[cfg(mobile)]
+fn inject(
+    _text: &str,
+    _requested: InjectMode,
+    _settings: &InjectSettings,
+) -> Result<InjectResult, AppError> {
+    Err(AppError::Unsupported(
+        "Inserting text into other apps isn't available on mobile".to_string(),
+    ))
+}
+
+/// Put `text` on the clipboard and leave it there
+#[cfg(desktop)]
+pub(crate) fn copy_to_clipboard(text: &str) -> Result<(), AppError> {
+    Clipboard::new()
+        .and_then(|mut clipboard| clipboard.set_text(text))
+        .map_err(|e| AppError::Injection(format!("Failed to copy text to the clipboard: {}", e)))
+}
+
+#[cfg(mobile)]
+pub(crate) fn copy_to_clipboard(_text: &str) -> Result<(), AppError> {
+    Err(AppError::Unsupported(
+        "Copying from the backend isn't available on mobile".to_string(),
+    ))
+}
+
+/// Backspace over `text` if the last injection typed it, trailing text included
+/// Returns whether it did
+#[cfg(desktop)]
+pub(crate) fn take_back(text: &str) -> Result<bool, AppError> {
+    let Ok(mut typed) = TYPED.lock() else {
+        return Ok(false);
+    };
+    let Some(count) = typed_after(&typed, text) else {
+        return Ok(false);
+    };
+    let mut enigo = new_enigo()?;
+    for _ in 0..count {
+        enigo
+            .key(Key::Backspace, Direction::Click)
+            .map_err(|e| AppError::Injection(format!("Failed to send backspace: {}", e)))?;
+    }
+    let kept = typed.chars().count() - count;
+    *typed = typed.chars().take(kept).collect();
+    Ok(true)
+}
+
+#[cfg(mobile)]
+pub(crate) fn take_back(_text: &str) -> Result<bool, AppError> {
+    Ok(false)
+}
+
+/// Characters typed from the last occurrence of `text` on, if only whitespace follows it
+#[cfg(desktop)]
+fn typed_after(typed: &str, text: &str) -> Option<usize> {
+    let text = text.trim();
+    if text.is_empty() {
+        return None;
+    }
+    let start = typed.trim_end().strip_suffix(text).map(str::len)?;
+    Some(typed[start..].chars().count())
+}
+
+/// Paste via the clipboard, then put back what was there before unless that's turned off
+#[cfg(desktop)]
+fn paste(text: &str, settings: &InjectSettings) -> Result<InjectResult, AppError> {
+    if let Ok(mut typed) = TYPED.lock() {
+        typed.clear();
+    }
+    let mut clipboard = Clipboard::new()
+        .map_err(|e| AppError::Injection(format!("Failed to open the clipboard: {}", e)))?;
+    let previous = settings
+        .restore_clipboard
+        .then(|| Snapshot::take(&mut clipboard));
+    clipboard
+        .set_text(text)
+        .map_err(|e| AppError::Injection(format!("Failed to copy text to the clipboard: {}", e)))?;
+    thread::sleep(CLIPBOARD_SETTLE);
+
+    if let Err(e) = new_enigo().and_then(|mut enigo| send_paste_shortcut(&mut enigo)) {
+        // Leave the transcript on the clipboard so the user can paste it themselves
+        tracing::warn!("Paste shortcut failed: {}", e);
+        return Ok(InjectResult {
+            mode: InjectMode::Paste,
+            fallback_reason: Some(e.to_string()),
+            left_on_clipboard: true,
+            clipboard_not_restored: false,
+            app: None,
+            profile: None,
+            text: text.to_string(),
+        });
+    }
+
+    let clipboard_not_restored = match previous {
+        Some(previous) if previous.is_lost() => {
+            tracing::info!("The clipboard couldn't be read, so it can't be put back after pasting");
+            true
+        }
+        Some(previous) => {
+            thread::sleep(Duration::from_millis(u64::from(
+                settings.clipboard_restore_delay_ms.min(MAX_DELAY_MS),
+            )));
+            // Something else copied in the meantime; that's newer than what we'd restore
+            if clipboard.get_text().is_ok_and(|current| current == text) {
+                if let Err(e) = previous.restore(&mut clipboard) {
+                    tracing::warn!("Failed to restore the clipboard: {}", e);
+                }
+            }
+            false
+        }
+        None => false,
+    };
+    Ok(InjectResult {
+        mode: InjectMode::Paste,
+        fallback_reason: None,
+        left_on_clipboard: false,
+        clipboard_not_restored,
+        app: None,
+        profile: None,
+        text: text.to_string(),
+    })
+}
+
+/// What was on the clipboard before pasting, in the richest format that can be put back
+#[cfg(desktop)]
+enum Snapshot {
+    Files(Vec<PathBuf>),
+    Image(ImageData<'static>),
+    Html {
+        html: String,
+        alt_text: Option<String>,
+    },
+    Text(String),
+    /// Nothing in a format we can read: empty, or (arboard can't tell these apart) only a
+    /// custom format of the app it came from. Put back by clearing the clipboard
+    Empty,
+    /// Reading it failed, e.g. another application held it open
+    Unreadable,
+}
+
+#[cfg(desktop)]
+impl Snapshot {
+    /// Copied files usually come with their paths as text, and rich text with a plain
+    /// version, so those are looked for first
+    fn take(clipboard: &mut Clipboard) -> Self {
+        let mut failed = false;
+        if let Some(files) =
+            found(clipboard.get().file_list(), &mut failed).filter(|files| !files.is_empty())
+        {
+            return Self::Files(files);
+        }
+        if let Some(image) = found(clipboard.get_image(), &mut failed) {
+            return Self::Image(image);
+        }
+        let text = found(clipboard.get_text(), &mut failed);
+        if let Some(html) = found(clipboard.get().html(), &mut failed) {
+            return Self::Html {
+                html,
+                alt_text: text,
+            };
+        }
+        Self::plain(text, failed)
+    }
+
+    /// Plain text, or what it means that there was none; `failed` when a read failed
+    fn plain(text: Option<String>, failed: bool) -> Self {
+        match text {
+            Some(text) => Self::Text(text),
+            None if failed => Self::Unreadable,
+            None => Self::Empty,
+        }
+    }
+
+    /// What was on the clipboard can't be put back
+    fn is_lost(&self) -> bool {
+        matches!(self, Self::Unreadable)
+    }
+
+    fn restore(self, clipboard: &mut Clipboard) -> Result<(), arboard::Error> {
+        match self {
+            Self::Files(files) => clipboard.set().file_list(&files),
+            Self::Image(image) => clipboard.set_image(image),
+            Self::Html { html, alt_text } => clipboard.set_html(html, alt_text),
+            Self::Text(text) => clipboard.set_text(text),
+            Self::Empty => clipboard.clear(),
+            Self::Unreadable => Ok(()),
+        }
+    }
+}
+
+/// What one clipboard read found; None when there's nothing in that format, and
+/// `failed` set when the read itself failed
+#[cfg(desktop)]
+fn found<T>(read: Result<T, arboard::Error>, failed: &mut bool) -> Option<T> {
+    match read {
+        Ok(value) => Some(value),
+        Err(arboard::Error::ContentNotAvailable) => None,
+        Err(e) => {
+            tracing::debug!("Failed to read the clipboard: {}", e);
+            *failed = true;
+            None
+        }
+    }
+}
+
+#[cfg(desktop)]
+fn send_paste_shortcut(enigo: &mut Enigo) -> Result<(), AppError> {
+    let modifier = if cfg!(target_os = "macos") {
+        Key::Meta
+    } else {
+        Key::Control
+    };
+    let send = |enigo: &mut Enigo| {
+        enigo.key(modifier, Direction::Press)?;
+        let clicked = enigo.key(Key::Unicode('v'), Direction::Click);
+        // Always release the modifier, even if the click failed
+        enigo.key(modifier, Direction::Release)?;
+        clicked
+    };
+    send(enigo)
+        .map_err(|e| AppError::Injection(format!("Failed to send the paste shortcut: {}", e)))
+}
+
+#[cfg(desktop)]
+fn new_enigo() -> Result<Enigo, AppError> {
+    Enigo::new(&Settings::default())
+        .map_err(|e| AppError::Injection(format!("Failed to start keyboard simulation: {}", e)))
+}
+
+#[cfg(desktop)]
+fn is_wayland() -> bool {
+    cfg!(target_os = "linux")
+        && (std::env::var("XDG_SESSION_TYPE").is_ok_and(|session| session == "wayland")
+            || std::env::var_os("WAYLAND_DISPLAY").is_some())
+}
+
+#[cfg(all(test, desktop))]
+mod tests {
+    use super::*;
+
+    fn snapshot(reads: Vec<Result<String, arboard::Error>>) -> Snapshot {
+        let mut failed = false;
+        let text = reads
+            .into_iter()
+            .filter_map(|read| found(read, &mut failed))
+            .next();
+        Snapshot::plain(text, failed)
+    }
+
+    #[test]
+    fn an_empty_clipboard_is_put_back_by_clearing_it() {
+        let empty = snapshot(vec![
+            Err(arboard::Error::ContentNotAvailable),
+            Err(arboard::Error::ContentNotAvailable),
+        ]);
+        assert!(matches!(empty, Snapshot::Empty));
+        assert!(!empty.is_lost());
+
+        let held = snapshot(vec![
+            Err(arboard::Error::ClipboardOccupied),
+            Err(arboard::Error::ContentNotAvailable),
+        ]);
+        assert!(matches!(held, Snapshot::Unreadable));
+        assert!(held.is_lost());
+
+        let text = snapshot(vec![
+            Err(arboard::Error::ClipboardOccupied),
+            Ok("copied".to_string()),
+        ]);
+        assert!(matches!(text, Snapshot::Text(text) if text == "copied"));
+    }
+}
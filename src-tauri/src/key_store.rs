@@ -0,0 +1,330 @@
+// Persisted API keys in the app config directory: Deepgram's, one file per profile,
+// and the optional LLM endpoint's
+// Lets packaged builds work without the user creating a .env file
+// Keys are encrypted at rest with AES-256-GCM under a key derived (HKDF-SHA256) from the
+// machine's identifier, so a copied file or backup doesn't reveal them. This stops casual
+// reads, not someone with access to the same machine. Files from before encryption hold
+// the key in plain text and are encrypted the first time they're read
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine as _;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::hkdf::{Salt, HKDF_SHA256};
+use ring::rand::{SecureRandom, SystemRandom};
+use tauri::{AppHandle, Manager};
+
+use crate::config::DEFAULT_PROFILE;
+use crate::error::AppError;
+use crate::fs_util::write_atomic;
+
+/// Marks an encrypted key file; followed by base64 of salt, nonce and ciphertext
+const ENCRYPTED_PREFIX: &str = "enc1:";
+const SALT_LEN: usize = 16;
+/// HKDF context, so the derived key is only ever used for these files
+const KDF_INFO: &[u8] = b"subspace-voice key file v1";
+
+/// File name of the default profile's key inside the app config dir
+/// Other profiles append `.<profile>`; profile names can't contain path separators
+const KEY_FILE_NAME: &str = "deepgram_api_key";
+/// File name of the LLM post-processing key
+pub const LLM_KEY_FILE_NAME: &str = "llm_api_key";
+/// File name of the translation provider's key
+pub const TRANSLATION_KEY_FILE_NAME: &str = "translation_api_key";
+/// File name of the OpenAI key, for the fallback transcription provider
+pub const OPENAI_KEY_FILE_NAME: &str = "openai_api_key";
+
+/// Check the key looks plausible before we persist it
+/// Surrounding whitespace (e.g. a pasted newline) is trimmed; anything else is rejected
+pub fn normalize_key(key: &str) -> Result<String, AppError> {
+    let key = key.trim();
+    if key.is_empty() {
+        return Err(AppError::KeyInvalid(
+            "API key must not be empty".to_string(),
+        ));
+    }
+    if key.chars().any(char::is_whitespace) {
+        return Err(AppError::KeyInvalid(
+            "API key must not contain whitespace".to_string(),
+        ));
+    }
+    Ok(key.to_string())
+}
+
+/// File name of a profile's Deepgram key
+fn profile_file_name(profile: &str) -> String {
+    if profile == DEFAULT_PROFILE {
+        KEY_FILE_NAME.to_string()
+    } else {
+        format!("{}.{}", KEY_FILE_NAME, profile)
+    }
+}
+
+fn key_path(app: &AppHandle, file_name: &str) -> Result<PathBuf, AppError> {
+    app.path()
+        .app_config_dir()
+        .map(|dir| dir.join(file_name))
+        .map_err(|e| AppError::Config(format!("Could not resolve app config directory: {}", e)))
+}
+
+/// Where a profile's key file is, whether or not one has been saved
+pub fn profile_path(app: &AppHandle, profile: &str) -> Result<PathBuf, AppError> {
+    key_path(app, &profile_file_name(profile))
+}
+
+/// Load a profile's persisted key, if one has been saved
+/// Fails with `KeyUnreadable` when the file exists but can't be decrypted
+pub fn load(app: &AppHandle, profile: &str) -> Result<Option<String>, AppError> {
+    load_file(app, &profile_file_name(profile))
+}
+
+/// Persist the key encrypted, atomically (temp file + rename) with owner-only permissions
+pub fn save(app: &AppHandle, profile: &str, key: &str) -> Result<(), AppError> {
+    save_file(app, &profile_file_name(profile), key)
+}
+
+/// Remove the persisted key; succeeds if there was nothing to remove
+pub fn clear(app: &AppHandle, profile: &str) -> Result<(), AppError> {
+    clear_file(app, &profile_file_name(profile))
+}
+
+/// Load the key saved under `file_name`, for keys that don't belong to a profile
+pub fn load_file(app: &AppHandle, file_name: &str) -> Result<Option<String>, AppError> {
+    let contents = match fs::read_to_string(key_path(app, file_name)?) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(AppError::Io(format!("Failed to read API key: {}", e))),
+    };
+    let contents = contents.trim();
+    if contents.is_empty() {
+        return Ok(None);
+    }
+    if contents.starts_with(ENCRYPTED_PREFIX) {
+        let machine_id = platform::machine_id().ok_or_else(no_machine_id)?;
+        return decrypt(&machine_id, file_name, contents).map(Some);
+    }
+
+    // Saved before keys were encrypted; keep working even if re-saving fails
+    let key = contents.to_string();
+    match save_file(app, file_name, &key) {
+        Ok(()) => tracing::info!("Encrypted the saved API key in {}", file_name),
+        Err(e) => tracing::warn!("Failed to encrypt the saved API key: {}", e),
+    }
+    Ok(Some(key))
+}
+
+pub fn save_file(app: &AppHandle, file_name: &str, key: &str) -> Result<(), AppError> {
+    let path = key_path(app, file_name)?;
+    let machine_id = platform::machine_id().ok_or_else(no_machine_id)?;
+    write_key(&path, &machine_id, file_name, key)
+}
+
+fn write_key(path: &Path, machine_id: &[u8], file_name: &str, key: &str) -> Result<(), AppError> {
+    let contents = encrypt(machine_id, file_name, key)?;
+    write_atomic(path, contents.as_bytes(), true)
+        .map_err(|e| AppError::Io(format!("Failed to save API key: {}", e)))
+}
+
+pub fn clear_file(app: &AppHandle, file_name: &str) -> Result<(), AppError> {
+    match fs::remove_file(key_path(app, file_name)?) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(AppError::Io(format!("Failed to remove API key: {}", e))),
+    }
+}
+
+fn no_machine_id() -> AppError {
+    AppError::Config(format!(
+        "This machine has no identifier to encrypt the API key with; set {} instead",
+        crate::secrets::API_KEY_ENV_VAR
+    ))
+}
+
+/// Key for one file, from the machine id and that file's salt
+fn file_key(machine_id: &[u8], salt: &[u8]) -> Result<LessSafeKey, AppError> {
+    let prk = Salt::new(HKDF_SHA256, salt).extract(machine_id);
+    let okm = prk
+        .expand(&[KDF_INFO], &AES_256_GCM)
+        .map_err(|_| AppError::Internal("Failed to derive the key file key".to_string()))?;
+    Ok(LessSafeKey::new(UnboundKey::from(okm)))
+}
+
+/// Encrypt `key` for the file `file_name`; the name is authenticated too, so one
+/// key file can't be swapped in for another
+fn encrypt(machine_id: &[u8], file_name: &str, key: &str) -> Result<String, AppError> {
+    let mut header = [0u8; SALT_LEN + NONCE_LEN];
+    SystemRandom::new().fill(&mut header).map_err(|_| {
+        AppError::Internal("No randomness available to encrypt the key".to_string())
+    })?;
+    let (salt, nonce) = header.split_at(SALT_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(nonce)
+        .map_err(|_| AppError::Internal("Invalid nonce length".to_string()))?;
+    let mut sealed = key.as_bytes().to_vec();
+    file_key(machine_id, salt)?
+        .seal_in_place_append_tag(nonce, Aad::from(file_name.as_bytes()), &mut sealed)
+        .map_err(|_| AppError::Internal("Failed to encrypt the API key".to_string()))?;
+
+    let mut bytes = header.to_vec();
+    bytes.extend_from_slice(&sealed);
+    Ok(format!("{}{}", ENCRYPTED_PREFIX, STANDARD.encode(bytes)))
+}
+
+/// Decrypt the contents of the file `file_name`
+/// Any failure is `KeyUnreadable`: the machine id changed, the file was copied from
+/// another machine or renamed, or it was damaged. Either way the key must be entered again
+fn decrypt(machine_id: &[u8], file_name: &str, contents: &str) -> Result<String, AppError> {
+    let unreadable = || {
+        AppError::KeyUnreadable(
+            "The saved API key can't be decrypted on this machine. Please enter it again."
+                .to_string(),
+        )
+    };
+    let encoded = contents
+        .strip_prefix(ENCRYPTED_PREFIX)
+        .ok_or_else(unreadable)?;
+    let mut bytes = STANDARD.decode(encoded).map_err(|_| unreadable())?;
+    if bytes.len() < SALT_LEN + NONCE_LEN {
+        return Err(unreadable());
+    }
+    let mut sealed = bytes.split_off(SALT_LEN + NONCE_LEN);
+    let (salt, nonce) = bytes.split_at(SALT_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| unreadable())?;
+    let key = file_key(machine_id, salt)?
+        .open_in_place(nonce, Aad::from(file_name.as_bytes()), &mut sealed)
+        .map_err(|_| unreadable())?;
+    String::from_utf8(key.to_vec()).map_err(|_| unreadable())
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    /// systemd's id, or D-Bus's on systems without systemd
+    pub fn machine_id() -> Option<Vec<u8>> {
+        ["/etc/machine-id", "/var/lib/dbus/machine-id"]
+            .iter()
+            .filter_map(|path| std::fs::read_to_string(path).ok())
+            .map(|id| id.trim().to_string())
+            .find(|id| !id.is_empty())
+            .map(String::into_bytes)
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::process::Command;
+
+    /// The hardware UUID, as shown in System Information
+    pub fn machine_id() -> Option<Vec<u8>> {
+        let output = Command::new("ioreg")
+            .args(["-rd1", "-c", "IOPlatformExpertDevice"])
+            .output()
+            .ok()?;
+        let output = String::from_utf8_lossy(&output.stdout);
+        // "IOPlatformUUID" = "XXXXXXXX-XXXX-XXXX-XXXX-XXXXXXXXXXXX"
+        let line = output
+            .lines()
+            .find(|line| line.contains("IOPlatformUUID"))?;
+        let uuid = line.split('"').nth(3)?;
+        (!uuid.is_empty()).then(|| uuid.as_bytes().to_vec())
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use winreg::enums::{HKEY_LOCAL_MACHINE, KEY_READ, KEY_WOW64_64KEY};
+    use winreg::RegKey;
+
+    /// The id Windows generates at install time
+    pub fn machine_id() -> Option<Vec<u8>> {
+        let id: String = RegKey::predef(HKEY_LOCAL_MACHINE)
+            .open_subkey_with_flags(
+                r"SOFTWARE\Microsoft\Cryptography",
+                KEY_READ | KEY_WOW64_64KEY,
+            )
+            .and_then(|key| key.get_value("MachineGuid"))
+            .ok()?;
+        (!id.is_empty()).then(|| id.into_bytes())
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+mod platform {
+    pub fn machine_id() -> Option<Vec<u8>> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MACHINE: &[u8] = b"4c4c4544003110518037b4c04f4e3732";
+
+    #[test]
+    fn encrypted_key_round_trips() {
+        let contents = encrypt(MACHINE, KEY_FILE_NAME, "dg_secret_123").unwrap();
+        assert!(contents.starts_with(ENCRYPTED_PREFIX));
+        assert!(!contents.contains("dg_secret_123"));
+        assert_eq!(
+            decrypt(MACHINE, KEY_FILE_NAME, &contents).unwrap(),
+            "dg_secret_123"
+        );
+        // A fresh salt and nonce every time
+        assert_ne!(
+            contents,
+            encrypt(MACHINE, KEY_FILE_NAME, "dg_secret_123").unwrap()
+        );
+    }
+
+    #[test]
+    fn key_from_elsewhere_is_unreadable() {
+        let contents = encrypt(MACHINE, KEY_FILE_NAME, "dg_secret_123").unwrap();
+        let mut tampered = contents.clone().into_bytes();
+        let last = tampered.len() - 3;
+        tampered[last] = if tampered[last] == b'A' { b'B' } else { b'A' };
+        let tampered = String::from_utf8(tampered).unwrap();
+        for error in [
+            decrypt(b"another machine", KEY_FILE_NAME, &contents),
+            decrypt(MACHINE, LLM_KEY_FILE_NAME, &contents),
+            decrypt(MACHINE, KEY_FILE_NAME, &tampered),
+            decrypt(MACHINE, KEY_FILE_NAME, "enc1:dG9vIHNob3J0"),
+        ] {
+            assert!(
+                matches!(error, Err(AppError::KeyUnreadable(_))),
+                "{:?}",
+                error
+            );
+        }
+    }
+
+    #[test]
+    fn saving_one_profile_leaves_the_others_alone() {
+        let dir = std::env::temp_dir().join(format!("subspace-key-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let profiles = [DEFAULT_PROFILE, "work"];
+        // Saved at the same time, over and over; a shared temp file would lose one
+        let savers = profiles.map(|profile| {
+            let dir = dir.clone();
+            std::thread::spawn(move || {
+                let file_name = profile_file_name(profile);
+                for round in 0..50 {
+                    let key = format!("{}_key_{}", profile, round);
+                    write_key(&dir.join(&file_name), MACHINE, &file_name, &key).unwrap();
+                }
+            })
+        });
+        for saver in savers {
+            saver.join().unwrap();
+        }
+        for profile in profiles {
+            let file_name = profile_file_name(profile);
+            let contents = fs::read_to_string(dir.join(&file_name)).unwrap();
+            assert_eq!(
+                decrypt(MACHINE, &file_name, &contents).unwrap(),
+                format!("{}_key_49", profile)
+            );
+        }
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,205 @@
+// Noticing the Deepgram key appear, change or go away while the app runs
+// Every `POLL_INTERVAL` the .env file and the active profile's key file are looked at;
+// when either changed, or a key command or profile switch asks, the .env file is read
+// again and the key resolved again. A different key is checked again and announced with
+// `api-key-changed`, so onboarding moves on without a restart; a key that went away also
+// stops a running Deepgram session. A keychain entry changed by another program is only
+// seen at the next check something else triggers
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use ring::digest::{digest, SHA256};
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Listener, Manager};
+use tokio::sync::Notify;
+
+use crate::config::{self, EngineKind};
+use crate::engine::watchdog::{self, AutoStopReason};
+use crate::error::AppError;
+use crate::profiles::EVENT_PROFILE_CHANGED;
+use crate::secrets::{self, ApiKeySource};
+use crate::state::blocking;
+use crate::{env_loader, key_store};
+
+/// Event emitted with an `ApiKeyChanged` when the active key appears, changes or goes away
+pub const EVENT_API_KEY_CHANGED: &str = "api-key-changed";
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Payload of the `api-key-changed` event; the same answers as `is_api_key_configured`
+/// and `get_api_key_source` would give now
+#[derive(Debug, Clone, Serialize)]
+pub struct ApiKeyChanged {
+    pub configured: bool,
+    pub source: Option<ApiKeySource>,
+}
+
+/// A file's modification time and length, or None while it doesn't exist
+type Stamp = Option<(SystemTime, u64)>;
+
+/// SHA-256 of a key, so the watcher doesn't keep another copy of it
+type Fingerprint = [u8; 32];
+
+/// What the watcher saw last
+#[derive(Default)]
+struct Seen {
+    env_file: Option<PathBuf>,
+    env_stamp: Stamp,
+    key_file_stamp: Stamp,
+    /// None until the first look, then the key's fingerprint if there was one
+    key: Option<Option<Fingerprint>>,
+}
+
+/// Managed state for the watcher
+#[derive(Default)]
+pub struct KeyWatchState {
+    seen: Mutex<Seen>,
+    wake: Notify,
+}
+
+/// How the key moved between two looks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum KeyChange {
+    Unchanged,
+    Added,
+    Changed,
+    Removed,
+}
+
+fn key_change(previous: Option<&Fingerprint>, next: Option<&Fingerprint>) -> KeyChange {
+    match (previous, next) {
+        (None, None) => KeyChange::Unchanged,
+        (None, Some(_)) => KeyChange::Added,
+        (Some(_), None) => KeyChange::Removed,
+        (Some(previous), Some(next)) if previous == next => KeyChange::Unchanged,
+        (Some(_), Some(_)) => KeyChange::Changed,
+    }
+}
+
+fn fingerprint(key: &str) -> Fingerprint {
+    let mut fingerprint = [0; 32];
+    fingerprint.copy_from_slice(digest(&SHA256, key.as_bytes()).as_ref());
+    fingerprint
+}
+
+fn stamp(path: &Path) -> Stamp {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+/// Start watching in the background
+pub fn init(app: &AppHandle) {
+    let handle = app.clone();
+    app.listen(EVENT_PROFILE_CHANGED, move |_| poke(&handle));
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let mut forced = true;
+        loop {
+            let handle = app.clone();
+            match blocking(move || look(&handle, forced)).await {
+                Ok(Some(change)) => on_change(&app, change).await,
+                Ok(None) => {}
+                Err(e) => tracing::debug!("Couldn't look for a new API key: {}", e),
+            }
+            let state = app.state::<KeyWatchState>();
+            forced = tokio::select! {
+                _ = tokio::time::sleep(POLL_INTERVAL) => false,
+                _ = state.wake.notified() => true,
+            };
+        }
+    });
+}
+
+/// Look again now, e.g. because a command saved or removed the key
+pub fn poke(app: &AppHandle) {
+    app.state::<KeyWatchState>().wake.notify_one();
+}
+
+/// Compare the files with the last look, and resolve the key again if they changed or
+/// `forced`; Some when the key isn't the one seen last
+fn look(app: &AppHandle, forced: bool) -> Result<Option<(KeyChange, ApiKeyChanged)>, AppError> {
+    let state = app.state::<KeyWatchState>();
+    let env_file = env_loader::env_file();
+    let env_stamp = env_file.as_deref().and_then(stamp);
+    let key_file = key_store::profile_path(app, &config::active_profile(app))?;
+    let key_file_stamp = stamp(&key_file);
+
+    let mut seen = state
+        .seen
+        .lock()
+        .map_err(|_| AppError::poisoned("Key watch"))?;
+    let env_changed = env_file != seen.env_file || env_stamp != seen.env_stamp;
+    if !(forced || env_changed || key_file_stamp != seen.key_file_stamp) {
+        return Ok(None);
+    }
+    if env_changed {
+        match env_loader::reload() {
+            Ok(changed) if !changed.is_empty() => {
+                tracing::info!("The .env file changed: {}", changed.join(", "));
+                let _ = app.emit(env_loader::EVENT_ENV_RELOADED, &changed);
+            }
+            Ok(_) => {}
+            Err(e) => tracing::warn!("{}", e),
+        }
+    }
+    seen.env_file = env_file;
+    seen.env_stamp = env_stamp;
+    seen.key_file_stamp = key_file_stamp;
+
+    let found = secrets::load_api_key(app)?;
+    let key = found.as_ref().map(|(key, _)| fingerprint(key));
+    let change = match seen.key {
+        Some(previous) => key_change(previous.as_ref(), key.as_ref()),
+        // The first look is the state at launch, which the frontend asks for itself
+        None => KeyChange::Unchanged,
+    };
+    seen.key = Some(key);
+    if change == KeyChange::Unchanged {
+        return Ok(None);
+    }
+    let source = if crate::engine::mock::enabled(app) {
+        Some(ApiKeySource::Mock)
+    } else {
+        found.map(|(_, source)| source)
+    };
+    Ok(Some((
+        change,
+        ApiKeyChanged {
+            configured: source.is_some(),
+            source,
+        },
+    )))
+}
+
+async fn on_change(app: &AppHandle, (change, payload): (KeyChange, ApiKeyChanged)) {
+    tracing::info!("The Deepgram API key changed ({:?})", change);
+    crate::deepgram::key_health::recheck(app);
+    let _ = app.emit(EVENT_API_KEY_CHANGED, &payload);
+    if change == KeyChange::Removed
+        && config::transcription_settings(app).engine == EngineKind::Deepgram
+        && watchdog::stop_running(app, AutoStopReason::KeyRemoved).await
+    {
+        tracing::warn!("Stopped the session: the Deepgram API key was removed");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_a_different_key_is_a_change() {
+        let (one, two) = (fingerprint("key-one"), fingerprint("key-two"));
+        assert_eq!(key_change(None, None), KeyChange::Unchanged);
+        assert_eq!(key_change(None, Some(&one)), KeyChange::Added);
+        assert_eq!(
+            key_change(Some(&one), Some(&fingerprint("key-one"))),
+            KeyChange::Unchanged
+        );
+        assert_eq!(key_change(Some(&one), Some(&two)), KeyChange::Changed);
+        assert_eq!(key_change(Some(&two), None), KeyChange::Removed);
+    }
+}
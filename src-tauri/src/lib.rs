@@ -1,77 +1,382 @@
 // Tauri backend for SubSpace Voice-to-Text Application
 // Handles secure API key management - NEVER expose keys to frontend
 
-use std::env;
-use std::path::PathBuf;
+#[cfg(target_os = "android")]
+mod android;
+mod audio;
+mod autostart;
+mod caption_server;
+mod cli;
+mod config;
+mod deep_link;
+mod deepgram;
+mod diagnostics;
+mod engine;
+mod env_loader;
+mod error;
+mod export;
+mod frontmost;
+mod fs_util;
+mod health;
+mod hotkey;
+mod import;
+mod inject;
+mod key_store;
+mod key_watch;
+mod locale;
+mod logging;
+mod net;
+mod overlay;
+mod permissions;
+mod postprocess;
+mod power;
+mod profiles;
+mod queue;
+mod recovery;
+mod review;
+mod secrets;
+mod shutdown;
+mod state;
+mod stats;
+mod storage;
+mod transcript;
+mod transcript_history;
+#[cfg(desktop)]
+mod tray;
+mod usage;
+mod vocabulary;
+mod wake_word;
 
-/// Load environment variables from .env file
-fn load_env_file() {
-    // Try multiple locations for .env file
-    let possible_paths = [
-        // Project root (parent of src-tauri)
-        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../.env"),
-        // Current directory
-        PathBuf::from(".env"),
-        // src-tauri directory
-        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(".env"),
-    ];
+use audio::capture::CaptureState;
+use audio::meter::MeterState;
+use deep_link::DeepLinkState;
+use deepgram::key_health::KeyHealthState;
+use deepgram::management::{AccountState, EphemeralTokenState};
+use diagnostics::ConnectionLog;
+use engine::manager::SessionManager;
+use error::AppError;
+use hotkey::HotkeyState;
+use key_watch::KeyWatchState;
+use overlay::OverlayState;
+use secrets::ApiKeySource;
+use state::AppState;
+use tauri::{AppHandle, Manager};
+use transcript::revoke::RevokeState;
+use wake_word::WakeWordState;
 
-    for env_path in possible_paths.iter() {
-        if let Ok(canonical) = env_path.canonicalize() {
-            if canonical.exists() {
-                if let Ok(contents) = std::fs::read_to_string(&canonical) {
-                    for line in contents.lines() {
-                        let line = line.trim();
-                        if line.is_empty() || line.starts_with('#') {
-                            continue;
-                        }
-                        if let Some((key, value)) = line.split_once('=') {
-                            let key = key.trim();
-                            let value = value.trim().trim_matches('"').trim_matches('\'');
-                            if !key.is_empty() {
-                                std::env::set_var(key, value);
-                            }
-                        }
-                    }
-                    println!("Loaded .env from: {:?}", canonical);
-                    return;
-                }
-            }
-        }
+/// Resolve the active profile's Deepgram API key (backend use only)
+/// Lookup order: keychain, encrypted config file, environment variable / .env file
+fn deepgram_api_key(app: &AppHandle) -> Result<String, AppError> {
+    secrets::load_api_key(app)?
+        .map(|(key, _)| key)
+        .ok_or(AppError::KeyNotConfigured)
+}
+
+/// Command to get the raw Deepgram API key, for debugging only
+/// The frontend streams through the backend proxy and never needs the key
+#[tauri::command]
+async fn get_deepgram_api_key(app: AppHandle) -> Result<String, AppError> {
+    if cfg!(debug_assertions) {
+        state::blocking(move || deepgram_api_key(&app)).await
+    } else {
+        Err(AppError::Unsupported(
+            "get_deepgram_api_key is only available in debug builds".to_string(),
+        ))
     }
-    println!("No .env file found");
 }
 
-/// Command to get the Deepgram API key securely from environment
-/// The frontend calls this to get the key for WebSocket connection
+/// Command to check if API key is configured
+/// Also nudges the key watcher, which reads a changed .env file and announces the key
+/// True with mock transcription on, which needs none; `get_api_key_source` then says so
+#[tauri::command]
+async fn is_api_key_configured(app: AppHandle) -> bool {
+    key_watch::poke(&app);
+    state::blocking(move || Ok(engine::mock::enabled(&app) || deepgram_api_key(&app).is_ok()))
+        .await
+        .unwrap_or(false)
+}
+
+/// Command to report where the API key comes from, or None if it isn't configured
 #[tauri::command]
-fn get_deepgram_api_key() -> Result<String, String> {
-    // Try to get API key from environment variable
-    // In production, this should be set via system environment or .env file
-    env::var("DEEPGRAM_API_KEY").map_err(|_| {
-        "DEEPGRAM_API_KEY environment variable not set. Please set it before running the app."
-            .to_string()
+async fn get_api_key_source(app: AppHandle) -> Option<ApiKeySource> {
+    state::blocking(move || {
+        if engine::mock::enabled(&app) {
+            return Ok(Some(ApiKeySource::Mock));
+        }
+        Ok(secrets::load_api_key(&app)?.map(|(_, source)| source))
     })
+    .await
+    .ok()
+    .flatten()
 }
 
-/// Command to check if API key is configured
+/// Command to save the Deepgram API key (keychain, or config file as a fallback)
 #[tauri::command]
-fn is_api_key_configured() -> bool {
-    env::var("DEEPGRAM_API_KEY").is_ok()
+async fn set_deepgram_api_key(app: AppHandle, key: String) -> Result<(), AppError> {
+    let key = key_store::normalize_key(&key)?;
+    let handle = app.clone();
+    state::blocking(move || secrets::save_api_key(&handle, &key).map(|_| ())).await?;
+    key_watch::poke(&app);
+    Ok(())
+}
+
+/// Command to remove the saved API key (the environment variable still applies)
+#[tauri::command]
+async fn clear_deepgram_api_key(app: AppHandle) -> Result<(), AppError> {
+    let handle = app.clone();
+    state::blocking(move || secrets::delete_api_key(&handle)).await?;
+    key_watch::poke(&app);
+    Ok(())
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
-    // Load .env file in debug mode
-    #[cfg(debug_assertions)]
-    load_env_file();
+    // Load .env file (debug builds, or an explicit --env-file / SUBSPACE_ENV_FILE)
+    let env_file = env_loader::load_env_file();
+    let command = cli::parse(std::env::args().skip(1));
+    logging::init(match command {
+        Some(_) => logging::Console::Stderr,
+        None => logging::Console::Stdout,
+    });
+    match env_file {
+        Ok(Some(path)) => tracing::info!("Loaded .env from: {:?}", path),
+        Ok(None) => tracing::info!("No .env file found"),
+        Err(e) => tracing::warn!("{}", e),
+    }
 
-    tauri::Builder::default()
+    let context = tauri::generate_context!();
+    // `subspace-voice transcribe <file>` runs without the GUI
+    if let Some(command) = command {
+        std::process::exit(cli::run(context, command));
+    }
+
+    let builder = tauri::Builder::default();
+    // Registered first, so a second launch (e.g. to open a deep link) hands over early
+    #[cfg(desktop)]
+    let builder = builder.plugin(tauri_plugin_single_instance::init(|app, argv, _| {
+        // Its deep link, if any, reaches `deep_link` through the deep link plugin
+        let link = format!("{}:", deep_link::SCHEME);
+        if !argv.iter().any(|arg| arg.starts_with(&link)) {
+            tray::show_main_window(app);
+        }
+    }));
+    let builder = builder
         .plugin(tauri_plugin_opener::init())
+        .plugin(tauri_plugin_deep_link::init());
+    #[cfg(desktop)]
+    let builder = builder.plugin(
+        tauri_plugin_global_shortcut::Builder::new()
+            .with_handler(hotkey::handle_shortcut)
+            .build(),
+    );
+    builder
+        .manage(EphemeralTokenState::default())
+        .manage(AccountState::default())
+        .manage(KeyHealthState::default())
+        .manage(KeyWatchState::default())
+        .manage(CaptureState::default())
+        .manage(RevokeState::default())
+        .manage(SessionManager::default())
+        .manage(MeterState::default())
+        .manage(HotkeyState::default())
+        .manage(ConnectionLog::default())
+        .manage(WakeWordState::default())
+        .manage(OverlayState::default())
+        .manage(DeepLinkState::default())
+        .setup(|app| {
+            logging::attach_file(app.handle());
+            // Before anything reads a key, since keyring has no Android keystore of its own
+            #[cfg(target_os = "android")]
+            android::keystore::init(app.handle());
+            app.manage(AppState::init(app.handle()));
+            app.manage(postprocess::replacements::init(app.handle()));
+            hotkey::init(app.handle());
+            caption_server::init(app.handle());
+            recovery::init(app.handle());
+            queue::init(app.handle());
+            deepgram::key_health::init(app.handle());
+            key_watch::init(app.handle());
+            wake_word::init(app.handle());
+            power::init(app.handle());
+            storage::retention::init(app.handle());
+            audio::session::init(app.handle());
+            deep_link::init(app.handle());
+            #[cfg(desktop)]
+            {
+                let has_tray = match tray::init(app.handle()) {
+                    Ok(()) => true,
+                    Err(e) => {
+                        tracing::warn!("{}; running without a tray icon", e);
+                        false
+                    }
+                };
+                // The window starts hidden; a login launch stays in the tray if there is one
+                if !(autostart::launched_minimized() && has_tray) {
+                    tray::show_main_window(app.handle());
+                }
+            }
+            Ok(())
+        })
+        .on_page_load(|webview, payload| {
+            if webview.label() == "main"
+                && payload.event() == tauri::webview::PageLoadEvent::Finished
+            {
+                deep_link::main_window_loaded(webview.app_handle());
+            }
+        })
+        .on_window_event(|window, event| match event {
+            #[cfg(desktop)]
+            tauri::WindowEvent::CloseRequested { api, .. }
+                if tray::hides_on_close(window.app_handle()) =>
+            {
+                api.prevent_close();
+                let _ = window.hide();
+            }
+            // Close the caption overlay with the main window so nothing is left keeping the
+            // app open; the exit that follows saves the session
+            tauri::WindowEvent::Destroyed if window.label() != overlay::OVERLAY_WINDOW => {
+                overlay::close(window.app_handle());
+            }
+            _ => {}
+        })
         .invoke_handler(tauri::generate_handler![
             get_deepgram_api_key,
-            is_api_key_configured
+            is_api_key_configured,
+            get_api_key_source,
+            set_deepgram_api_key,
+            clear_deepgram_api_key,
+            env_loader::reload_env,
+            logging::get_recent_logs,
+            logging::get_log_file_path,
+            diagnostics::create_diagnostics_bundle,
+            config::get_settings,
+            config::update_settings,
+            autostart::get_autostart_status,
+            autostart::set_autostart,
+            vocabulary::get_vocabulary,
+            vocabulary::set_vocabulary,
+            postprocess::dictation_commands::get_dictation_commands,
+            postprocess::dictation_commands::get_dictation_command_table,
+            postprocess::dictation_commands::set_dictation_commands,
+            postprocess::disfluency::get_disfluency_settings,
+            postprocess::disfluency::get_filler_words,
+            postprocess::disfluency::set_disfluency_settings,
+            postprocess::replacements::get_replacements,
+            postprocess::replacements::set_replacements,
+            postprocess::replacements::test_replacements,
+            locale::get_system_locale,
+            transcript::revoke::undo_last_segment,
+            engine::manager::get_session_state,
+            postprocess::snippets::list_snippets,
+            postprocess::snippets::upsert_snippet,
+            postprocess::snippets::delete_snippet,
+            postprocess::llm::get_llm_settings,
+            postprocess::llm::set_llm_settings,
+            postprocess::llm::set_llm_api_key,
+            postprocess::llm::clear_llm_api_key,
+            postprocess::llm::refine_transcript,
+            postprocess::translation::get_translation_settings,
+            postprocess::translation::set_translation_settings,
+            postprocess::translation::set_translation_api_key,
+            postprocess::translation::clear_translation_api_key,
+            profiles::list_profiles,
+            profiles::create_profile,
+            profiles::set_active_profile,
+            profiles::delete_profile,
+            hotkey::set_push_to_talk_shortcut,
+            hotkey::get_push_to_talk_shortcut,
+            hotkey::set_push_to_talk_mode,
+            wake_word::set_wake_word_enabled,
+            wake_word::set_wake_word_phrase,
+            wake_word::get_wake_word_status,
+            wake_word::list_wake_word_phrases,
+            inject::type_text,
+            inject::get_inject_settings,
+            inject::set_inject_settings,
+            inject::set_output_targets,
+            inject::list_output_profiles,
+            inject::upsert_output_profile,
+            inject::delete_output_profile,
+            frontmost::get_frontmost_app,
+            deepgram::management::validate_api_key,
+            deepgram::management::get_ephemeral_token,
+            deepgram::management::get_deepgram_balance,
+            deepgram::management::get_deepgram_account_usage,
+            deepgram::key_health::check_key_health,
+            deepgram::key_health::get_key_health_settings,
+            deepgram::key_health::set_key_health_settings,
+            deepgram::prerecorded::transcribe_file,
+            deepgram::proxy::start_session,
+            deepgram::proxy::send_audio_chunk,
+            deepgram::proxy::stop_session,
+            engine::models::download_whisper_model,
+            engine::models::list_downloaded_models,
+            engine::openai::set_openai_api_key,
+            engine::openai::clear_openai_api_key,
+            permissions::check_microphone_permission,
+            permissions::request_microphone_permission,
+            audio::capture::list_input_devices,
+            audio::capture::start_capture,
+            audio::capture::stop_capture,
+            audio::meter::set_meter_enabled,
+            storage::list_sessions,
+            storage::get_session,
+            storage::get_session_audio_path,
+            storage::delete_session,
+            storage::search_sessions,
+            storage::set_session_title,
+            storage::set_session_note,
+            storage::set_session_pinned,
+            storage::add_session_tag,
+            storage::remove_session_tag,
+            storage::list_tags,
+            recovery::get_recovered_sessions,
+            import::import_transcripts,
+            storage::retention::get_storage_usage,
+            storage::retention::run_cleanup_now,
+            storage::retention::get_retention_settings,
+            storage::retention::set_retention_settings,
+            transcript_history::get_transcript_history,
+            transcript_history::copy_transcript_to_clipboard,
+            transcript_history::clear_transcript_history,
+            deepgram::network::get_network_settings,
+            deepgram::network::set_network_settings,
+            deepgram::network::test_connection,
+            deepgram::proxy::pause_session,
+            deepgram::proxy::resume_session,
+            deepgram::stats::get_stream_stats,
+            audio::capture::get_audio_pipeline_info,
+            audio::preprocess::get_preprocess_settings,
+            audio::preprocess::set_preprocess_settings,
+            queue::enqueue_files,
+            queue::get_queue,
+            queue::cancel_job,
+            queue::retry_job,
+            health::get_app_health,
+            health::complete_onboarding,
+            usage::get_usage_summary,
+            usage::set_usage_settings,
+            usage::reset_usage_stats,
+            stats::get_dictation_stats,
+            export::export_session,
+            export::html::export_session_html,
+            export::template::list_export_templates,
+            export::template::copy_export_template,
+            export::template::validate_export_template,
+            export::template::export_session_with_template,
+            review::get_session_review,
+            caption_server::start_caption_server,
+            caption_server::stop_caption_server,
+            caption_server::get_caption_server_status,
+            overlay::list_monitors,
+            overlay::show_caption_overlay,
+            overlay::hide_caption_overlay,
+            overlay::get_overlay_options,
+            overlay::set_overlay_options,
+            net::limiter::get_circuit_state,
+            net::limiter::reset_circuit
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(context)
+        .expect("error while building tauri application")
+        .run(shutdown::handle_run_event);
 }
@@ -0,0 +1,182 @@
+// How the user's locale writes what post-processing produces: decimal and thousands
+// separators, dates and times, and quotation marks
+// The locale is the `locale` setting, else the system's, and is separate from the
+// transcription language, so English dictated in Germany can still get German numbers
+
+use crate::config::TranscriptionSettings;
+
+/// Used when neither the setting nor the system names a locale
+const FALLBACK: &str = "en-US";
+
+/// Conventions of one locale
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Locale {
+    /// BCP 47 tag, e.g. "de-DE"
+    pub tag: String,
+    pub decimal: char,
+    /// Between groups of three digits
+    pub group: char,
+    /// chrono format strings
+    pub date_format: &'static str,
+    pub time_format: &'static str,
+    /// What "open quote" and "close quote" insert, spacing included
+    pub open_quote: &'static str,
+    pub close_quote: &'static str,
+}
+
+impl Locale {
+    /// Conventions for `tag`, by language and then region; unknown ones get en-US's
+    pub fn new(tag: &str) -> Self {
+        let tag = normalize_tag(tag).unwrap_or_else(|| FALLBACK.to_string());
+        let mut parts = tag.split('-');
+        let language = parts.next().unwrap_or_default();
+        let region = parts.find(|part| part.len() == 2).unwrap_or_default();
+        #[rustfmt::skip]
+        let (decimal, group, date_format, time_format, open_quote, close_quote) =
+            match (language, region) {
+                ("en", "US" | "") => ('.', ',', "%m/%d/%Y", "%-I:%M %p", "\u{201C}", "\u{201D}"),
+                ("en", "CA") => ('.', ',', "%Y-%m-%d", "%-I:%M %p", "\u{201C}", "\u{201D}"),
+                ("en", _) => ('.', ',', "%d/%m/%Y", "%H:%M", "\u{201C}", "\u{201D}"),
+                ("de", "CH") => ('.', '\u{2019}', "%d.%m.%Y", "%H:%M", "\u{AB}", "\u{BB}"),
+                ("de", _) => (',', '.', "%d.%m.%Y", "%H:%M", "\u{201E}", "\u{201C}"),
+                ("fr", "CH") => ('.', '\u{202F}', "%d.%m.%Y", "%H:%M", "\u{AB}\u{A0}", "\u{A0}\u{BB}"),
+                ("fr", "CA") => (',', '\u{A0}', "%Y-%m-%d", "%H:%M", "\u{AB}\u{A0}", "\u{A0}\u{BB}"),
+                ("fr", _) => (',', '\u{202F}', "%d/%m/%Y", "%H:%M", "\u{AB}\u{A0}", "\u{A0}\u{BB}"),
+                ("es" | "it", _) => (',', '.', "%d/%m/%Y", "%H:%M", "\u{AB}", "\u{BB}"),
+                ("pt", "BR") => (',', '.', "%d/%m/%Y", "%H:%M", "\u{201C}", "\u{201D}"),
+                ("pt", _) => (',', '\u{A0}', "%d/%m/%Y", "%H:%M", "\u{AB}", "\u{BB}"),
+                ("nl", _) => (',', '.', "%d-%m-%Y", "%H:%M", "\u{201C}", "\u{201D}"),
+                ("sv" | "fi", _) => (',', '\u{A0}', "%Y-%m-%d", "%H:%M", "\u{201D}", "\u{201D}"),
+                ("da" | "nb" | "no", _) => (',', '.', "%d.%m.%Y", "%H:%M", "\u{AB}", "\u{BB}"),
+                ("pl" | "cs", _) => (',', '\u{A0}', "%d.%m.%Y", "%H:%M", "\u{201E}", "\u{201D}"),
+                ("ru" | "uk", _) => (',', '\u{A0}', "%d.%m.%Y", "%H:%M", "\u{AB}", "\u{BB}"),
+                ("ja" | "zh", _) => ('.', ',', "%Y/%m/%d", "%H:%M", "\u{300C}", "\u{300D}"),
+                ("ko", _) => ('.', ',', "%Y. %-m. %-d.", "%H:%M", "\u{201C}", "\u{201D}"),
+                _ => ('.', ',', "%m/%d/%Y", "%-I:%M %p", "\u{201C}", "\u{201D}"),
+            };
+        Self {
+            tag,
+            decimal,
+            group,
+            date_format,
+            time_format,
+            open_quote,
+            close_quote,
+        }
+    }
+
+    /// The locale `settings` ask for, else the system's
+    pub fn for_settings(settings: &TranscriptionSettings) -> Self {
+        let tag = settings
+            .locale
+            .as_deref()
+            .and_then(normalize_tag)
+            .or_else(system_locale)
+            .unwrap_or_else(|| FALLBACK.to_string());
+        Self::new(&tag)
+    }
+}
+
+impl Default for Locale {
+    fn default() -> Self {
+        Self::new(FALLBACK)
+    }
+}
+
+/// A BCP 47 tag from what a setting or the OS calls a locale: "de_DE.UTF-8" → "de-DE"
+/// None for "C", "POSIX" and anything that isn't a language tag
+pub fn normalize_tag(locale: &str) -> Option<String> {
+    let locale = locale.split(['.', '@']).next().unwrap_or_default().trim();
+    let mut parts = locale.split(['-', '_']);
+    let language = parts.next()?;
+    if !(2..=3).contains(&language.len()) || !language.chars().all(|c| c.is_ascii_alphabetic()) {
+        return None;
+    }
+    let mut tag = language.to_ascii_lowercase();
+    for part in parts {
+        if part.is_empty() || part.len() > 8 || !part.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return None;
+        }
+        tag.push('-');
+        if part.len() == 2 {
+            tag.push_str(&part.to_ascii_uppercase());
+        } else {
+            tag.push_str(part);
+        }
+    }
+    Some(tag)
+}
+
+/// The locale the OS formats for, if it says
+pub fn system_locale() -> Option<String> {
+    platform::system_locale().as_deref().and_then(normalize_tag)
+}
+
+/// Command to read the system locale, which the `locale` setting defaults to
+#[tauri::command]
+pub fn get_system_locale() -> Option<String> {
+    system_locale()
+}
+
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+mod platform {
+    use objc2_foundation::NSLocale;
+
+    /// GUI apps don't get LANG, so ask Foundation, which also has the region setting
+    pub fn system_locale() -> Option<String> {
+        Some(NSLocale::currentLocale().localeIdentifier().to_string())
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use windows_sys::Win32::Globalization::GetUserDefaultLocaleName;
+
+    /// `LOCALE_NAME_MAX_LENGTH`
+    const MAX_LENGTH: usize = 85;
+
+    pub fn system_locale() -> Option<String> {
+        let mut name = [0u16; MAX_LENGTH];
+        // SAFETY: the buffer holds MAX_LENGTH UTF-16 units, as passed
+        let written = unsafe { GetUserDefaultLocaleName(name.as_mut_ptr(), MAX_LENGTH as i32) };
+        // The count includes the terminating NUL; 0 means failure
+        let length = usize::try_from(written).ok()?.checked_sub(1)?;
+        String::from_utf16(&name[..length]).ok()
+    }
+}
+
+#[cfg(target_os = "android")]
+mod platform {
+    pub fn system_locale() -> Option<String> {
+        crate::android::default_locale()
+            .map_err(|e| tracing::debug!("Failed to read the system locale: {}", e))
+            .ok()
+    }
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "ios", target_os = "android", windows)))]
+mod platform {
+    /// The same variables, in the same order, that C's setlocale reads for numbers
+    pub fn system_locale() -> Option<String> {
+        ["LC_ALL", "LC_NUMERIC", "LANG"]
+            .iter()
+            .filter_map(|name| std::env::var(name).ok())
+            .find(|value| !value.is_empty())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locale_names_become_language_tags() {
+        assert_eq!(normalize_tag("de_DE.UTF-8").as_deref(), Some("de-DE"));
+        assert_eq!(normalize_tag("en_US@rg=gbzzzz").as_deref(), Some("en-US"));
+        assert_eq!(normalize_tag("zh-Hans-cn").as_deref(), Some("zh-Hans-CN"));
+        assert_eq!(normalize_tag("C"), None);
+        assert_eq!(normalize_tag("POSIX"), None);
+        assert_eq!(Locale::new("fr_CA").date_format, "%Y-%m-%d");
+        assert_eq!(Locale::new("xx").tag, "xx");
+    }
+}
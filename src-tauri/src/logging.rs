@@ -0,0 +1,435 @@
+// App-wide logging through `tracing`, to stdout and a size-capped file in the app log dir
+// Levels come from SUBSPACE_LOG (e.g. "debug" or "subspace_voice_lib::deepgram=trace").
+// Every line passes through `redact` first, so API keys never reach stdout or the file
+
+use std::borrow::Cow;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use tauri::{AppHandle, Manager};
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::EnvFilter;
+
+use crate::error::AppError;
+use crate::state;
+
+/// Environment variable holding the log filter
+pub const LOG_ENV_VAR: &str = "SUBSPACE_LOG";
+/// Filter used when SUBSPACE_LOG is unset or invalid
+const DEFAULT_FILTER: &str = "warn,subspace_voice_lib=info";
+
+const LOG_FILE_NAME: &str = "subspace-voice.log";
+/// The active file is rotated once it would grow past this
+const MAX_FILE_BYTES: u64 = 2 * 1024 * 1024;
+/// Rotated files kept next to the active one, capping the total at 10 MB
+const MAX_ROTATED_FILES: u32 = 4;
+/// Lines buffered before the log file is attached in setup
+const MAX_PENDING_BYTES: usize = 64 * 1024;
+/// Upper bound for `get_recent_logs`
+const MAX_RECENT_LINES: u32 = 10_000;
+
+const REDACTED: &str = "[REDACTED]";
+/// Hex runs at least this long look like Deepgram keys and are always redacted
+const MIN_KEY_HEX_LEN: usize = 32;
+
+/// Known secret values, redacted wherever they appear
+static SECRETS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+/// Where file output goes; lines are held in `pending` until `attach_file`
+static FILE_SINK: Mutex<FileSink> = Mutex::new(FileSink {
+    file: None,
+    pending: Vec::new(),
+});
+
+struct FileSink {
+    file: Option<RotatingFile>,
+    pending: Vec<u8>,
+}
+
+/// Where log lines go besides the log file
+#[derive(Debug, Clone, Copy)]
+pub enum Console {
+    Stdout,
+    /// For the command line, whose output is on stdout
+    Stderr,
+}
+
+/// Install the global subscriber; events before `attach_file` are buffered for the file
+pub fn init(console: Console) {
+    let filter =
+        EnvFilter::try_from_env(LOG_ENV_VAR).unwrap_or_else(|_| EnvFilter::new(DEFAULT_FILTER));
+    let installed = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_ansi(false)
+        .with_writer(Redacting(AppSink(console)))
+        .try_init();
+    if installed.is_err() {
+        eprintln!("A tracing subscriber was already installed; app logs go there instead");
+    }
+}
+
+/// Open the log file in the app log dir and flush the lines logged so far
+pub fn attach_file(app: &AppHandle) {
+    let opened = log_file_path(app).and_then(|path| {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        Ok(RotatingFile::open(path)?)
+    });
+    let Ok(mut sink) = FILE_SINK.lock() else {
+        return;
+    };
+    let pending = std::mem::take(&mut sink.pending);
+    match opened {
+        Ok(mut file) => {
+            let _ = file.write_all(&pending);
+            sink.file = Some(file);
+        }
+        Err(e) => {
+            drop(sink);
+            tracing::warn!("Logging to stdout only: {}", e);
+        }
+    }
+}
+
+/// Treat `secret` as sensitive from now on; it is replaced in every log line
+pub fn register_secret(secret: &str) {
+    let secret = secret.trim();
+    if secret.is_empty() {
+        return;
+    }
+    if let Ok(mut secrets) = SECRETS.lock() {
+        if !secrets.iter().any(|known| known == secret) {
+            secrets.push(secret.to_string());
+        }
+    }
+}
+
+/// Replace registered secrets and anything shaped like a Deepgram key
+pub fn redact(text: &str) -> Cow<'_, str> {
+    let mut redacted = Cow::Borrowed(text);
+    if let Ok(secrets) = SECRETS.lock() {
+        for secret in secrets.iter() {
+            if redacted.contains(secret.as_str()) {
+                redacted = Cow::Owned(redacted.replace(secret.as_str(), REDACTED));
+            }
+        }
+    }
+    match redact_hex_runs(&redacted) {
+        Some(scrubbed) => Cow::Owned(scrubbed),
+        None => redacted,
+    }
+}
+
+/// Returns None when there's no hex run long enough to be a key
+fn redact_hex_runs(text: &str) -> Option<String> {
+    let mut output = String::with_capacity(text.len());
+    let mut run_start = None;
+    let mut changed = false;
+    for (i, c) in text.char_indices().chain([(text.len(), ' ')]) {
+        if c.is_ascii_hexdigit() {
+            run_start.get_or_insert(i);
+            continue;
+        }
+        if let Some(start) = run_start.take() {
+            if i - start >= MIN_KEY_HEX_LEN {
+                output.push_str(REDACTED);
+                changed = true;
+            } else {
+                output.push_str(&text[start..i]);
+            }
+        }
+        if i < text.len() {
+            output.push(c);
+        }
+    }
+    changed.then_some(output)
+}
+
+fn log_file_path(app: &AppHandle) -> Result<PathBuf, AppError> {
+    app.path()
+        .app_log_dir()
+        .map(|dir| dir.join(LOG_FILE_NAME))
+        .map_err(|e| AppError::Config(format!("Could not resolve app log directory: {}", e)))
+}
+
+/// Command to read the last `lines` lines of the log, oldest first
+#[tauri::command]
+pub async fn get_recent_logs(app: AppHandle, lines: u32) -> Result<Vec<String>, AppError> {
+    let path = log_file_path(&app)?;
+    state::blocking(move || recent_lines(&path, lines.min(MAX_RECENT_LINES) as usize)).await
+}
+
+/// Command to show where the log file lives
+#[tauri::command]
+pub fn get_log_file_path(app: AppHandle) -> Result<String, AppError> {
+    log_file_path(&app).map(|path| path.display().to_string())
+}
+
+/// Tail across the active file and as many rotated files as needed
+fn recent_lines(path: &Path, count: usize) -> Result<Vec<String>, AppError> {
+    let mut lines = Vec::new();
+    for index in 0..=MAX_ROTATED_FILES {
+        if lines.len() >= count {
+            break;
+        }
+        let contents = match fs::read(rotated_path(path, index)) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => break,
+            Err(e) => return Err(AppError::Io(format!("Failed to read the log: {}", e))),
+        };
+        let older: Vec<String> = String::from_utf8_lossy(&contents)
+            .lines()
+            .map(str::to_string)
+            .collect();
+        let take = older.len().min(count - lines.len());
+        lines.splice(0..0, older[older.len() - take..].iter().cloned());
+    }
+    Ok(lines)
+}
+
+/// The last `max_bytes` of the log across the active and rotated files
+/// Starts at a line boundary, so it may be a little shorter
+pub fn recent_log(app: &AppHandle, max_bytes: usize) -> Result<String, AppError> {
+    let path = log_file_path(app)?;
+    let mut tail: Vec<u8> = Vec::new();
+    for index in 0..=MAX_ROTATED_FILES {
+        if tail.len() >= max_bytes {
+            break;
+        }
+        let contents = match fs::read(rotated_path(&path, index)) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => break,
+            Err(e) => return Err(AppError::Io(format!("Failed to read the log: {}", e))),
+        };
+        let take = contents.len().min(max_bytes - tail.len());
+        tail.splice(0..0, contents[contents.len() - take..].iter().copied());
+    }
+    let mut text = String::from_utf8_lossy(&tail).into_owned();
+    if tail.len() >= max_bytes {
+        let start = text.find('\n').map_or(text.len(), |newline| newline + 1);
+        text.drain(..start);
+    }
+    Ok(text)
+}
+
+/// `app.log` for index 0, `app.log.N` for rotated files
+fn rotated_path(path: &Path, index: u32) -> PathBuf {
+    if index == 0 {
+        return path.to_path_buf();
+    }
+    let mut name = path.as_os_str().to_os_string();
+    name.push(format!(".{}", index));
+    PathBuf::from(name)
+}
+
+/// Log file that rolls over to `.1`, `.2`, ... once it reaches `MAX_FILE_BYTES`
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+}
+
+impl RotatingFile {
+    fn open(path: PathBuf) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(Self { path, file, size })
+    }
+
+    fn write_all(&mut self, bytes: &[u8]) -> io::Result<()> {
+        if self.size > 0 && self.size + bytes.len() as u64 > MAX_FILE_BYTES {
+            self.rotate()?;
+        }
+        self.file.write_all(bytes)?;
+        self.size += bytes.len() as u64;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        // The oldest file falls off the end when renamed over
+        for index in (1..MAX_ROTATED_FILES).rev() {
+            let from = rotated_path(&self.path, index);
+            if from.exists() {
+                fs::rename(&from, rotated_path(&self.path, index + 1))?;
+            }
+        }
+        fs::rename(&self.path, rotated_path(&self.path, 1))?;
+        *self = Self::open(self.path.clone())?;
+        Ok(())
+    }
+}
+
+/// Writes each event to the console and the log file
+struct AppSink(Console);
+
+impl<'a> MakeWriter<'a> for AppSink {
+    type Writer = AppSinkWriter;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        AppSinkWriter(self.0)
+    }
+}
+
+struct AppSinkWriter(Console);
+
+impl Write for AppSinkWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let _ = match self.0 {
+            Console::Stdout => io::stdout().write_all(buf),
+            Console::Stderr => io::stderr().write_all(buf),
+        };
+        if let Ok(mut sink) = FILE_SINK.lock() {
+            let sink = &mut *sink;
+            match sink.file.as_mut() {
+                Some(file) => file.write_all(buf)?,
+                None if sink.pending.len() + buf.len() <= MAX_PENDING_BYTES => {
+                    sink.pending.extend_from_slice(buf)
+                }
+                None => {}
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.0 {
+            Console::Stdout => io::stdout().flush(),
+            Console::Stderr => io::stderr().flush(),
+        }
+    }
+}
+
+/// Buffers a whole event and redacts it before handing it to the inner writer
+/// Redacting per event (not per `write` call) catches secrets split across writes
+struct Redacting<M>(M);
+
+impl<'a, M: MakeWriter<'a>> MakeWriter<'a> for Redacting<M> {
+    type Writer = RedactingWriter<M::Writer>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        RedactingWriter {
+            inner: self.0.make_writer(),
+            buf: Vec::new(),
+        }
+    }
+}
+
+struct RedactingWriter<W: Write> {
+    inner: W,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> Write for RedactingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<W: Write> Drop for RedactingWriter<W> {
+    fn drop(&mut self) {
+        if self.buf.is_empty() {
+            return;
+        }
+        let text = String::from_utf8_lossy(&self.buf);
+        let _ = self.inner.write_all(redact(&text).as_bytes());
+        let _ = self.inner.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    /// Collects everything the subscriber writes
+    #[derive(Clone, Default)]
+    struct Capture(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Capture {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn captured_lines(log: impl FnOnce()) -> Vec<String> {
+        let capture = Capture::default();
+        let writer = capture.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_ansi(false)
+            .with_max_level(tracing::Level::TRACE)
+            .with_writer(Redacting(move || writer.clone()))
+            .finish();
+        tracing::subscriber::with_default(subscriber, log);
+        let bytes = capture.0.lock().unwrap().clone();
+        String::from_utf8(bytes)
+            .unwrap()
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn registered_secrets_never_reach_the_log() {
+        let key = "dg_test_secret_value_not_hex";
+        register_secret(key);
+        let lines = captured_lines(|| {
+            tracing::info!("Connecting with key {}", key);
+            tracing::warn!(api_key = %key, "Request failed");
+            tracing::debug!(header = ?format!("Token {}", key), "Sending");
+        });
+        assert_eq!(lines.len(), 3);
+        for line in &lines {
+            assert!(!line.contains(key), "key leaked: {}", line);
+            assert!(line.contains(REDACTED), "not redacted: {}", line);
+        }
+    }
+
+    #[test]
+    fn unregistered_hex_keys_are_redacted() {
+        let key = "0123456789abcdef0123456789abcdef01234567";
+        let lines = captured_lines(|| tracing::error!("Authorization: Token {}", key));
+        assert_eq!(lines.len(), 1);
+        assert!(!lines[0].contains(key));
+        assert!(lines[0].ends_with(&format!("Authorization: Token {}", REDACTED)));
+    }
+
+    #[test]
+    fn short_hex_runs_are_kept() {
+        assert_eq!(redact("session 42 at deadbeef"), "session 42 at deadbeef");
+    }
+
+    #[test]
+    fn rotates_and_tails_across_files() {
+        let dir = std::env::temp_dir().join(format!("subspace-log-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(LOG_FILE_NAME);
+
+        let mut file = RotatingFile::open(path.clone()).unwrap();
+        let line = format!("{}\n", "x".repeat(1023));
+        for _ in 0..(MAX_FILE_BYTES / 1024) {
+            file.write_all(line.as_bytes()).unwrap();
+        }
+        file.write_all(b"newest\n").unwrap();
+
+        assert_eq!(
+            fs::metadata(rotated_path(&path, 1)).unwrap().len(),
+            MAX_FILE_BYTES
+        );
+        let recent = recent_lines(&path, 2).unwrap();
+        assert_eq!(recent, vec!["x".repeat(1023), "newest".to_string()]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
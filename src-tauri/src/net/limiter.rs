@@ -0,0 +1,376 @@
+// Client-side limits on reaching Deepgram, shared by the streaming connector and every
+// REST call: a token bucket for each kind of attempt, and a circuit breaker that stops
+// trying for a while once Deepgram keeps failing
+// Only the start of a connection or request goes through here, so a stream that's
+// already open carries on however long it runs
+
+use std::sync::{LazyLock, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::config::LimitSettings;
+use crate::error::AppError;
+
+static DEEPGRAM: LazyLock<Mutex<Limiter>> =
+    LazyLock::new(|| Mutex::new(Limiter::new(&LimitSettings::default(), Instant::now())));
+
+/// What's being started
+#[derive(Debug, Clone, Copy)]
+pub enum Attempt {
+    /// A streaming WebSocket, new or reconnecting
+    Connection,
+    /// A REST request
+    Request,
+}
+
+/// Where the circuit breaker stands
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    /// Attempts go through
+    Closed,
+    /// Attempts are refused until the cooldown ends
+    Open,
+    /// The cooldown ended; the next attempt decides whether the circuit closes again
+    HalfOpen,
+}
+
+/// Result of `get_circuit_state`
+#[derive(Debug, Clone, Serialize)]
+pub struct CircuitStatus {
+    pub state: CircuitState,
+    /// Failures since Deepgram last answered properly
+    pub consecutive_failures: u32,
+    /// While attempts are refused, how long until one is let through
+    pub retry_after_secs: Option<u64>,
+    pub failure_threshold: u32,
+    pub cooldown_seconds: u64,
+}
+
+/// Tokens refilled evenly over a minute, holding at most a minute's worth
+/// The rate is at least one a minute, whatever the settings file says
+struct Bucket {
+    per_minute: u32,
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl Bucket {
+    fn new(per_minute: u32, now: Instant) -> Self {
+        let per_minute = per_minute.max(1);
+        Self {
+            per_minute,
+            tokens: f64::from(per_minute),
+            refilled: now,
+        }
+    }
+
+    fn set_rate(&mut self, per_minute: u32) {
+        self.per_minute = per_minute.max(1);
+        self.tokens = self.tokens.min(f64::from(self.per_minute));
+    }
+
+    /// Take a token, or say how long until there is one
+    fn take(&mut self, now: Instant) -> Result<(), Duration> {
+        let per_second = f64::from(self.per_minute) / 60.0;
+        let elapsed = now.saturating_duration_since(self.refilled).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * per_second).min(f64::from(self.per_minute));
+        self.refilled = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            // Never more than a minute at one a minute or faster
+            Err(
+                Duration::try_from_secs_f64((1.0 - self.tokens) / per_second)
+                    .unwrap_or(Duration::from_secs(60)),
+            )
+        }
+    }
+}
+
+enum Circuit {
+    Closed,
+    Open {
+        until: Instant,
+    },
+    /// A trial attempt is out; another is let through `until` passes without hearing back,
+    /// in case the first was cancelled
+    HalfOpen {
+        until: Instant,
+    },
+}
+
+struct Limiter {
+    connections: Bucket,
+    requests: Bucket,
+    circuit: Circuit,
+    failures: u32,
+    failure_threshold: u32,
+    cooldown: Duration,
+}
+
+impl Limiter {
+    fn new(settings: &LimitSettings, now: Instant) -> Self {
+        Self {
+            connections: Bucket::new(settings.connections_per_minute, now),
+            requests: Bucket::new(settings.requests_per_minute, now),
+            circuit: Circuit::Closed,
+            failures: 0,
+            failure_threshold: settings.failure_threshold,
+            cooldown: Duration::from_secs(settings.cooldown_seconds),
+        }
+    }
+
+    fn configure(&mut self, settings: &LimitSettings) {
+        self.connections.set_rate(settings.connections_per_minute);
+        self.requests.set_rate(settings.requests_per_minute);
+        self.failure_threshold = settings.failure_threshold;
+        self.cooldown = Duration::from_secs(settings.cooldown_seconds);
+    }
+
+    fn admit(&mut self, attempt: Attempt, now: Instant) -> Result<(), AppError> {
+        let trial = match self.circuit {
+            Circuit::Closed => false,
+            Circuit::Open { until } | Circuit::HalfOpen { until } => {
+                if now < until {
+                    return Err(AppError::CircuitOpen {
+                        retry_after_secs: whole_seconds(until - now),
+                    });
+                }
+                true
+            }
+        };
+        let bucket = match attempt {
+            Attempt::Connection => &mut self.connections,
+            Attempt::Request => &mut self.requests,
+        };
+        bucket.take(now).map_err(|wait| AppError::RateLimited {
+            retry_after_secs: whole_seconds(wait),
+        })?;
+        if trial {
+            self.circuit = Circuit::HalfOpen {
+                until: now + self.cooldown,
+            };
+        }
+        Ok(())
+    }
+
+    fn record(&mut self, success: bool, now: Instant) {
+        if success {
+            self.failures = 0;
+            self.circuit = Circuit::Closed;
+            return;
+        }
+        self.failures = self.failures.saturating_add(1);
+        let opens = match self.circuit {
+            Circuit::Closed => self.failures >= self.failure_threshold,
+            Circuit::HalfOpen { .. } => true,
+            // A straggler that started before the circuit opened
+            Circuit::Open { .. } => false,
+        };
+        if opens {
+            tracing::warn!(
+                "Deepgram failed {} times in a row; pausing attempts for {} s",
+                self.failures,
+                self.cooldown.as_secs()
+            );
+            self.circuit = Circuit::Open {
+                until: now + self.cooldown,
+            };
+        }
+    }
+
+    fn reset(&mut self) {
+        self.failures = 0;
+        self.circuit = Circuit::Closed;
+    }
+
+    fn status(&self, now: Instant) -> CircuitStatus {
+        let (state, until) = match self.circuit {
+            Circuit::Closed => (CircuitState::Closed, None),
+            Circuit::Open { until } if now < until => (CircuitState::Open, Some(until)),
+            Circuit::Open { .. } => (CircuitState::HalfOpen, None),
+            Circuit::HalfOpen { until } => (CircuitState::HalfOpen, Some(until)),
+        };
+        CircuitStatus {
+            state,
+            consecutive_failures: self.failures,
+            retry_after_secs: until
+                .filter(|until| now < *until)
+                .map(|until| whole_seconds(until - now)),
+            failure_threshold: self.failure_threshold,
+            cooldown_seconds: self.cooldown.as_secs(),
+        }
+    }
+}
+
+/// Seconds to wait, rounded up so a retry at that point isn't refused again
+fn whole_seconds(duration: Duration) -> u64 {
+    (duration.as_secs() + u64::from(duration.subsec_nanos() > 0)).max(1)
+}
+
+fn limiter() -> Result<MutexGuard<'static, Limiter>, AppError> {
+    DEEPGRAM
+        .lock()
+        .map_err(|_| AppError::poisoned("Deepgram limiter"))
+}
+
+/// Apply changed limit settings; what's been counted so far is kept
+pub fn configure(settings: &LimitSettings) {
+    if let Ok(mut limiter) = limiter() {
+        limiter.configure(settings);
+    }
+}
+
+/// Ask to start a connection or request; pair every success with `record`
+pub fn admit(attempt: Attempt) -> Result<(), AppError> {
+    limiter()?.admit(attempt, Instant::now())
+}
+
+/// Report whether an admitted attempt reached a working Deepgram
+pub fn record(success: bool) {
+    if let Ok(mut limiter) = limiter() {
+        limiter.record(success, Instant::now());
+    }
+}
+
+/// Statuses that say Deepgram is in trouble, rather than the request
+pub fn failing_status(status: u16) -> bool {
+    status == 429 || status >= 500
+}
+
+/// Record how a REST request went: an error reaching Deepgram or a failing status counts
+/// against it, while any other answer, a rejected key included, shows it's up
+pub fn record_response(result: &reqwest::Result<reqwest::Response>) {
+    record(
+        result
+            .as_ref()
+            .is_ok_and(|response| !failing_status(response.status().as_u16())),
+    );
+}
+
+/// Send a Deepgram REST request, if the limits allow it
+pub async fn send(request: reqwest::RequestBuilder) -> Result<reqwest::Response, AppError> {
+    admit(Attempt::Request)?;
+    let result = request.send().await;
+    record_response(&result);
+    Ok(result?)
+}
+
+/// Command to read the circuit breaker's state, for the settings screen
+#[tauri::command]
+pub fn get_circuit_state() -> Result<CircuitStatus, AppError> {
+    Ok(limiter()?.status(Instant::now()))
+}
+
+/// Command to close the circuit so the next attempt goes straight through
+#[tauri::command]
+pub fn reset_circuit() -> Result<CircuitStatus, AppError> {
+    let mut limiter = limiter()?;
+    limiter.reset();
+    Ok(limiter.status(Instant::now()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> LimitSettings {
+        LimitSettings {
+            connections_per_minute: 2,
+            requests_per_minute: 60,
+            failure_threshold: 3,
+            cooldown_seconds: 30,
+            ..LimitSettings::default()
+        }
+    }
+
+    #[test]
+    fn buckets_refill_over_the_minute_and_are_separate() {
+        let start = Instant::now();
+        let mut limiter = Limiter::new(&settings(), start);
+        assert!(limiter.admit(Attempt::Connection, start).is_ok());
+        assert!(limiter.admit(Attempt::Connection, start).is_ok());
+        match limiter.admit(Attempt::Connection, start) {
+            Err(AppError::RateLimited { retry_after_secs }) => assert_eq!(retry_after_secs, 30),
+            other => panic!("expected rate_limited, got {:?}", other),
+        }
+        // Requests have a bucket of their own
+        assert!(limiter.admit(Attempt::Request, start).is_ok());
+        let later = start + Duration::from_secs(30);
+        assert!(limiter.admit(Attempt::Connection, later).is_ok());
+        assert!(limiter.admit(Attempt::Connection, later).is_err());
+    }
+
+    #[test]
+    fn zero_rates_from_the_file_allow_one_a_minute() {
+        let start = Instant::now();
+        let zero = LimitSettings {
+            connections_per_minute: 0,
+            requests_per_minute: 0,
+            ..settings()
+        };
+        let mut limiter = Limiter::new(&zero, start);
+        assert!(limiter.admit(Attempt::Connection, start).is_ok());
+        match limiter.admit(Attempt::Connection, start) {
+            Err(AppError::RateLimited { retry_after_secs }) => assert_eq!(retry_after_secs, 60),
+            other => panic!("expected rate_limited, got {:?}", other),
+        }
+
+        let mut limiter = Limiter::new(&settings(), start);
+        limiter.configure(&zero);
+        assert!(limiter.admit(Attempt::Request, start).is_ok());
+        assert!(limiter.admit(Attempt::Request, start).is_err());
+    }
+
+    #[test]
+    fn circuit_opens_after_consecutive_failures_and_a_trial_closes_it() {
+        let start = Instant::now();
+        let mut limiter = Limiter::new(&settings(), start);
+        limiter.record(false, start);
+        limiter.record(true, start);
+        limiter.record(false, start);
+        limiter.record(false, start);
+        assert_eq!(limiter.status(start).state, CircuitState::Closed);
+        limiter.record(false, start);
+        let status = limiter.status(start);
+        assert_eq!(status.state, CircuitState::Open);
+        assert_eq!(status.consecutive_failures, 3);
+        assert_eq!(status.retry_after_secs, Some(30));
+        let soon = start + Duration::from_millis(20_500);
+        match limiter.admit(Attempt::Request, soon) {
+            Err(AppError::CircuitOpen { retry_after_secs }) => assert_eq!(retry_after_secs, 10),
+            other => panic!("expected circuit_open, got {:?}", other),
+        }
+
+        // One trial after the cooldown, and nothing else while it's out
+        let cooled = start + Duration::from_secs(30);
+        assert_eq!(limiter.status(cooled).state, CircuitState::HalfOpen);
+        assert!(limiter.admit(Attempt::Request, cooled).is_ok());
+        assert!(limiter.admit(Attempt::Request, cooled).is_err());
+        limiter.record(false, cooled);
+        assert_eq!(limiter.status(cooled).state, CircuitState::Open);
+
+        let cooled = cooled + Duration::from_secs(30);
+        assert!(limiter.admit(Attempt::Request, cooled).is_ok());
+        limiter.record(true, cooled);
+        let status = limiter.status(cooled);
+        assert_eq!(status.state, CircuitState::Closed);
+        assert_eq!(status.consecutive_failures, 0);
+    }
+
+    #[test]
+    fn reset_closes_an_open_circuit() {
+        let start = Instant::now();
+        let mut limiter = Limiter::new(&settings(), start);
+        for _ in 0..3 {
+            limiter.record(false, start);
+        }
+        assert!(limiter.admit(Attempt::Request, start).is_err());
+        limiter.reset();
+        assert!(limiter.admit(Attempt::Request, start).is_ok());
+        assert_eq!(limiter.status(start).retry_after_secs, None);
+    }
+}
@@ -0,0 +1,3 @@
+// Networking shared by everything that reaches Deepgram, whichever transport it uses
+
+pub mod limiter;
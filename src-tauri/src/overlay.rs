@@ -0,0 +1,450 @@
+// Floating caption strip: a frameless, always-on-top window at the top or bottom of one
+// monitor's work area, for following along while the main window is out of sight
+// Its page (overlay.html) listens to the same transcript events as the main window, so
+// this side only opens, places and hides it. Clicks go through it and it never takes
+// focus, so dictated text still lands in the application underneath
+// It goes back to the monitor it was last shown on, found by name; while it's up the
+// monitors are checked every few seconds, and if that one is unplugged it moves to the
+// primary one (and back again when it returns)
+// Mobile has no windows of this kind, so there it can't be shown
+
+use std::ops::RangeInclusive;
+use std::sync::Mutex;
+#[cfg(desktop)]
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tauri::async_runtime::JoinHandle;
+use tauri::{AppHandle, Emitter, Manager};
+#[cfg(desktop)]
+use tauri::{
+    Monitor, PhysicalPosition, PhysicalRect, PhysicalSize, WebviewUrl, WebviewWindow,
+    WebviewWindowBuilder,
+};
+
+use crate::config::{OverlayPosition, OverlaySettings};
+use crate::engine::SessionState;
+use crate::error::AppError;
+use crate::state::blocking;
+
+/// Label of the overlay window
+pub const OVERLAY_WINDOW: &str = "caption-overlay";
+/// The overlay's settings changed; the payload is the new `OverlaySettings`
+pub const EVENT_OVERLAY_OPTIONS: &str = "overlay-options";
+/// Share of the work area's width the strip takes
+#[cfg(desktop)]
+const WIDTH_FRACTION: f64 = 0.6;
+/// Lines of captions it has room for, and their height relative to the font size
+#[cfg(desktop)]
+const LINES: f64 = 2.0;
+#[cfg(desktop)]
+const LINE_HEIGHT: f64 = 1.3;
+/// Logical pixels inside the strip above and below the text
+#[cfg(desktop)]
+const PADDING: f64 = 12.0;
+/// Logical pixels between the strip and the edge of the work area
+#[cfg(desktop)]
+const MARGIN: f64 = 32.0;
+#[cfg(desktop)]
+const MONITOR_CHECK_INTERVAL: Duration = Duration::from_secs(3);
+const FONT_SIZES: RangeInclusive<u32> = 12..=96;
+
+/// Managed state holding the task that follows the monitors while the overlay is up
+#[derive(Default)]
+pub struct OverlayState {
+    watch: Mutex<Option<JoinHandle<()>>>,
+}
+
+/// A monitor the overlay can go on, as listed by `list_monitors`
+#[derive(Debug, Clone, Serialize)]
+pub struct MonitorInfo {
+    /// What `show_caption_overlay` takes
+    pub index: u32,
+    pub name: Option<String>,
+    pub primary: bool,
+    /// Physical pixels
+    pub width: u32,
+    pub height: u32,
+    pub scale_factor: f64,
+}
+
+/// Changes for `set_overlay_options`; fields left out stay as they are
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct OverlayOptions {
+    pub font_size: Option<u32>,
+    pub opacity: Option<f64>,
+    pub position: Option<OverlayPosition>,
+    pub hide_on_stop: Option<bool>,
+}
+
+/// Where the strip goes, in physical pixels
+#[cfg(desktop)]
+#[derive(Debug, PartialEq, Eq)]
+struct Frame {
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+}
+
+/// The strip's frame in `work_area`, on a monitor scaled by `scale`: centred, as tall as
+/// its lines of text, and kept inside the work area however large the font
+#[cfg(desktop)]
+fn frame(work_area: &PhysicalRect<i32, u32>, scale: f64, settings: &OverlaySettings) -> Frame {
+    let area = work_area.size;
+    let width = (f64::from(area.width) * WIDTH_FRACTION).round() as u32;
+    let content = f64::from(settings.font_size) * LINE_HEIGHT * LINES + 2.0 * PADDING;
+    let height = ((content * scale).round() as u32).min(area.height);
+    let margin = ((MARGIN * scale).round() as u32).min(area.height - height);
+    let x = work_area.position.x + ((area.width - width) / 2) as i32;
+    let y = match settings.position {
+        OverlayPosition::Top => work_area.position.y + margin as i32,
+        OverlayPosition::Bottom => work_area.position.y + (area.height - height - margin) as i32,
+    };
+    Frame {
+        x,
+        y,
+        width,
+        height,
+    }
+}
+
+/// The overlay window, opened hidden the first time it's needed
+#[cfg(desktop)]
+fn window(app: &AppHandle) -> Result<WebviewWindow, AppError> {
+    if let Some(window) = app.get_webview_window(OVERLAY_WINDOW) {
+        return Ok(window);
+    }
+    let builder =
+        WebviewWindowBuilder::new(app, OVERLAY_WINDOW, WebviewUrl::App("overlay.html".into()))
+            .title("Captions")
+            .decorations(false)
+            .transparent(true)
+            .always_on_top(true)
+            .skip_taskbar(true)
+            .resizable(false)
+            .shadow(false)
+            .focusable(false)
+            .visible(false);
+    // Stays on screen when switching Spaces
+    #[cfg(target_os = "macos")]
+    let builder = builder.visible_on_all_workspaces(true);
+    let window = builder
+        .build()
+        .map_err(|e| AppError::Internal(format!("Failed to open the caption overlay: {}", e)))?;
+    click_through(&window);
+    Ok(window)
+}
+
+/// Let clicks through to whatever is underneath
+/// Windows and macOS pass them on as asked and X11 gets an empty input region; Wayland
+/// compositors may not allow it, which leaves the strip clickable but harmless
+#[cfg(desktop)]
+fn click_through(window: &WebviewWindow) {
+    if let Err(e) = window.set_ignore_cursor_events(true) {
+        tracing::warn!("Caption overlay won't let clicks through: {}", e);
+    }
+}
+
+/// The monitor called `name`, else the primary one, else any
+#[cfg(desktop)]
+fn monitor(app: &AppHandle, name: Option<&str>) -> Result<Monitor, AppError> {
+    let monitors = app
+        .available_monitors()
+        .map_err(|e| AppError::Internal(format!("Failed to list monitors: {}", e)))?;
+    let named = name.and_then(|name| {
+        monitors
+            .iter()
+            .find(|monitor| monitor.name().map(String::as_str) == Some(name))
+            .cloned()
+    });
+    named
+        .or_else(|| app.primary_monitor().ok().flatten())
+        .or_else(|| monitors.into_iter().next())
+        .ok_or_else(|| AppError::NotFound("No monitor to show captions on".to_string()))
+}
+
+/// Move the overlay to its place on `monitor`, unless it's there already
+#[cfg(desktop)]
+fn place(window: &WebviewWindow, monitor: &Monitor, settings: &OverlaySettings) {
+    let frame = frame(monitor.work_area(), monitor.scale_factor(), settings);
+    let position = PhysicalPosition::new(frame.x, frame.y);
+    let size = PhysicalSize::new(frame.width, frame.height);
+    if window.outer_position().ok() == Some(position) && window.inner_size().ok() == Some(size) {
+        return;
+    }
+    // Sized again after the move, since moving between scale factors can resize it
+    let placed = window
+        .set_size(size)
+        .and_then(|_| window.set_position(position))
+        .and_then(|_| window.set_size(size));
+    if let Err(e) = placed {
+        tracing::warn!("Failed to place the caption overlay: {}", e);
+    }
+}
+
+/// Keep the overlay on its monitor while it's up; one task at a time
+#[cfg(desktop)]
+fn watch_monitors(app: &AppHandle) {
+    let state = app.state::<OverlayState>();
+    let Ok(mut watch) = state.watch.lock() else {
+        return;
+    };
+    if watch
+        .as_ref()
+        .is_some_and(|task| !task.inner().is_finished())
+    {
+        return;
+    }
+    let app = app.clone();
+    *watch = Some(tauri::async_runtime::spawn(async move {
+        loop {
+            tokio::time::sleep(MONITOR_CHECK_INTERVAL).await;
+            let Some(window) = app.get_webview_window(OVERLAY_WINDOW) else {
+                return;
+            };
+            let settings = crate::config::load(&app)
+                .map(|config| config.overlay)
+                .unwrap_or_default();
+            match monitor(&app, settings.monitor.as_deref()) {
+                Ok(monitor) => place(&window, &monitor, &settings),
+                Err(e) => tracing::debug!("Caption overlay not moved: {}", e),
+            }
+        }
+    }));
+}
+
+/// Hide the overlay and stop following the monitors; the window stays for next time
+fn hide(app: &AppHandle) {
+    if let Ok(mut watch) = app.state::<OverlayState>().watch.lock() {
+        if let Some(task) = watch.take() {
+            task.abort();
+        }
+    }
+    #[cfg(desktop)]
+    if let Some(window) = app.get_webview_window(OVERLAY_WINDOW) {
+        if let Err(e) = window.hide() {
+            tracing::warn!("Failed to hide the caption overlay: {}", e);
+        }
+    }
+}
+
+/// Close the overlay for good, when the main window goes and the app with it
+pub fn close(app: &AppHandle) {
+    hide(app);
+    #[cfg(desktop)]
+    if let Some(window) = app.get_webview_window(OVERLAY_WINDOW) {
+        let _ = window.destroy();
+    }
+}
+
+/// Called on every session state change; a stopped session hides the overlay if
+/// `hide_on_stop` says to
+pub fn session_changed(app: &AppHandle, state: SessionState) {
+    if state != SessionState::Idle || app.get_webview_window(OVERLAY_WINDOW).is_none() {
+        return;
+    }
+    let hide_on_stop = crate::config::load(app)
+        .map(|config| config.overlay.hide_on_stop)
+        .unwrap_or(true);
+    if hide_on_stop {
+        // Off the session manager's lock, which is held while this runs
+        let app = app.clone();
+        tauri::async_runtime::spawn(async move { hide(&app) });
+    }
+}
+
+/// Command to list the monitors, in the order `show_caption_overlay` numbers them
+#[tauri::command]
+pub async fn list_monitors(app: AppHandle) -> Result<Vec<MonitorInfo>, AppError> {
+    let monitors = app
+        .available_monitors()
+        .map_err(|e| AppError::Internal(format!("Failed to list monitors: {}", e)))?;
+    let primary = app.primary_monitor().ok().flatten();
+    Ok(monitors
+        .iter()
+        .enumerate()
+        .map(|(index, monitor)| MonitorInfo {
+            index: index as u32,
+            name: monitor.name().cloned(),
+            primary: primary.as_ref().is_some_and(|primary| {
+                primary.name() == monitor.name() && primary.position() == monitor.position()
+            }),
+            width: monitor.size().width,
+            height: monitor.size().height,
+            scale_factor: monitor.scale_factor(),
+        })
+        .collect())
+}
+
+/// Command to show the caption overlay, on monitor `monitor` of `list_monitors` when
+/// given and else where it was last; the choice is saved
+#[cfg(desktop)]
+#[tauri::command]
+pub async fn show_caption_overlay(
+    app: AppHandle,
+    monitor: Option<u32>,
+) -> Result<OverlaySettings, AppError> {
+    let chosen = match monitor {
+        Some(index) => {
+            let monitors = app
+                .available_monitors()
+                .map_err(|e| AppError::Internal(format!("Failed to list monitors: {}", e)))?;
+            let count = monitors.len();
+            let monitor = monitors.into_iter().nth(index as usize).ok_or_else(|| {
+                AppError::InvalidInput(format!(
+                    "There's no monitor {}; {} are connected",
+                    index, count
+                ))
+            })?;
+            Some(monitor.name().cloned())
+        }
+        None => None,
+    };
+    let handle = app.clone();
+    let settings = blocking(move || match chosen {
+        Some(name) => crate::config::update(&handle, |config| {
+            config.overlay.monitor = name;
+            Ok(config.overlay.clone())
+        }),
+        None => Ok(crate::config::load(&handle)?.overlay),
+    })
+    .await?;
+    let window = window(&app)?;
+    place(
+        &window,
+        &self::monitor(&app, settings.monitor.as_deref())?,
+        &settings,
+    );
+    window
+        .show()
+        .map_err(|e| AppError::Internal(format!("Failed to show the caption overlay: {}", e)))?;
+    watch_monitors(&app);
+    Ok(settings)
+}
+
+#[cfg(mobile)]
+#[tauri::command]
+pub async fn show_caption_overlay(
+    _app: AppHandle,
+    _monitor: Option<u32>,
+) -> Result<OverlaySettings, AppError> {
+    Err(AppError::Unsupported(
+        "The caption overlay isn't available on mobile".to_string(),
+    ))
+}
+
+/// Command to hide the caption overlay
+#[tauri::command]
+pub fn hide_caption_overlay(app: AppHandle) {
+    hide(&app);
+}
+
+/// Command to read the overlay's settings, which its page styles itself with
+#[tauri::command]
+pub async fn get_overlay_options(app: AppHandle) -> Result<OverlaySettings, AppError> {
+    blocking(move || crate::config::load(&app).map(|config| config.overlay)).await
+}
+
+/// Command to change the overlay's font size, background opacity, edge or hiding on stop
+/// The overlay restyles itself, and moves if it's up
+#[tauri::command]
+pub async fn set_overlay_options(
+    app: AppHandle,
+    options: OverlayOptions,
+) -> Result<OverlaySettings, AppError> {
+    if let Some(font_size) = options.font_size.filter(|size| !FONT_SIZES.contains(size)) {
+        return Err(AppError::InvalidInput(format!(
+            "Font size {} is outside {} to {}",
+            font_size,
+            FONT_SIZES.start(),
+            FONT_SIZES.end()
+        )));
+    }
+    if let Some(opacity) = options
+        .opacity
+        .filter(|opacity| !(0.0..=1.0).contains(opacity))
+    {
+        return Err(AppError::InvalidInput(format!(
+            "Opacity {} is outside 0 to 1",
+            opacity
+        )));
+    }
+    let handle = app.clone();
+    let settings = blocking(move || {
+        crate::config::update(&handle, |config| {
+            let overlay = &mut config.overlay;
+            overlay.font_size = options.font_size.unwrap_or(overlay.font_size);
+            overlay.opacity = options.opacity.unwrap_or(overlay.opacity);
+            overlay.position = options.position.unwrap_or(overlay.position);
+            overlay.hide_on_stop = options.hide_on_stop.unwrap_or(overlay.hide_on_stop);
+            Ok(overlay.clone())
+        })
+    })
+    .await?;
+    let _ = app.emit_to(OVERLAY_WINDOW, EVENT_OVERLAY_OPTIONS, &settings);
+    #[cfg(desktop)]
+    if let Some(window) = app
+        .get_webview_window(OVERLAY_WINDOW)
+        .filter(|window| window.is_visible().unwrap_or(false))
+    {
+        place(
+            &window,
+            &monitor(&app, settings.monitor.as_deref())?,
+            &settings,
+        );
+    }
+    Ok(settings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn area(x: i32, y: i32, width: u32, height: u32) -> PhysicalRect<i32, u32> {
+        PhysicalRect {
+            position: PhysicalPosition::new(x, y),
+            size: PhysicalSize::new(width, height),
+        }
+    }
+
+    #[test]
+    fn strip_is_centred_at_the_chosen_edge_of_the_work_area() {
+        let settings = OverlaySettings::default();
+        // Below it, a 40 px taskbar outside the work area
+        assert_eq!(
+            frame(&area(0, 0, 1920, 1040), 1.0, &settings),
+            Frame {
+                x: 384,
+                y: 911,
+                width: 1152,
+                height: 97
+            }
+        );
+        // A HiDPI monitor to the right, whose menu bar starts the work area lower
+        let top = OverlaySettings {
+            position: OverlayPosition::Top,
+            ..OverlaySettings::default()
+        };
+        assert_eq!(
+            frame(&area(1920, 50, 3840, 2110), 2.0, &top),
+            Frame {
+                x: 2688,
+                y: 114,
+                width: 2304,
+                height: 194
+            }
+        );
+    }
+
+    #[test]
+    fn huge_fonts_stay_inside_a_small_work_area() {
+        let settings = OverlaySettings {
+            font_size: 96,
+            ..OverlaySettings::default()
+        };
+        let frame = frame(&area(0, -600, 800, 200), 1.0, &settings);
+        assert_eq!((frame.y, frame.height), (-600, 200));
+    }
+}
@@ -0,0 +1,212 @@
+// Microphone permission checks
+// macOS/iOS ask AVFoundation, Windows reads the privacy consent store, Android asks the
+// Activity over JNI, and desktop Linux has no per-app permission the backend can query
+
+use serde::Serialize;
+use tauri::AppHandle;
+use tauri_plugin_opener::OpenerExt;
+
+/// Microphone permission as reported by the OS
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MicrophonePermission {
+    Granted,
+    Denied,
+    /// The user hasn't been asked yet; opening the mic will prompt
+    /// Desktop Linux has no prompt, so it never reports this
+    #[cfg_attr(target_os = "linux", allow(dead_code))]
+    Undetermined,
+}
+
+/// Where the user can allow microphone access, shown in permission errors
+#[cfg(target_os = "macos")]
+pub const SETTINGS_HINT: &str =
+    "Allow SubSpace Voice under System Settings > Privacy & Security > Microphone, then try again.";
+#[cfg(target_os = "ios")]
+pub const SETTINGS_HINT: &str =
+    "Allow SubSpace Voice under Settings > Privacy & Security > Microphone, then try again.";
+#[cfg(windows)]
+pub const SETTINGS_HINT: &str =
+    "Allow desktop apps to use the microphone under Settings > Privacy & security > Microphone, then try again.";
+#[cfg(target_os = "android")]
+pub const SETTINGS_HINT: &str =
+    "Allow SubSpace Voice to use the microphone under Settings > Apps > SubSpace Voice > Permissions, then try again.";
+#[cfg(not(any(target_os = "macos", target_os = "ios", target_os = "android", windows)))]
+pub const SETTINGS_HINT: &str =
+    "Check that the app is allowed to use the microphone in your system settings, then try again.";
+
+/// Current permission, without prompting
+pub fn microphone_permission() -> MicrophonePermission {
+    platform::status()
+}
+
+/// Command to read the microphone permission
+#[tauri::command]
+pub fn check_microphone_permission() -> MicrophonePermission {
+    microphone_permission()
+}
+
+/// Command to ask for microphone access
+/// Prompts when the user hasn't decided yet; once denied, the OS won't prompt again,
+/// so the system privacy settings are opened instead (where the platform has them)
+#[tauri::command]
+pub async fn request_microphone_permission(app: AppHandle) -> MicrophonePermission {
+    match microphone_permission() {
+        MicrophonePermission::Undetermined => platform::request().await,
+        MicrophonePermission::Denied => {
+            if let Some(url) = platform::SETTINGS_URL {
+                if let Err(e) = app.opener().open_url(url, None::<&str>) {
+                    tracing::warn!("Failed to open microphone privacy settings: {}", e);
+                }
+            }
+            MicrophonePermission::Denied
+        }
+        MicrophonePermission::Granted => MicrophonePermission::Granted,
+    }
+}
+
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+mod platform {
+    use block2::RcBlock;
+    use objc2::runtime::Bool;
+    use objc2_av_foundation::{AVAuthorizationStatus, AVCaptureDevice, AVMediaTypeAudio};
+
+    use super::MicrophonePermission;
+
+    #[cfg(target_os = "macos")]
+    pub const SETTINGS_URL: Option<&str> =
+        Some("x-apple.systempreferences:com.apple.preference.security?Privacy_Microphone");
+    #[cfg(target_os = "ios")]
+    pub const SETTINGS_URL: Option<&str> = Some("app-settings:");
+
+    pub fn status() -> MicrophonePermission {
+        let Some(audio) = (unsafe { AVMediaTypeAudio }) else {
+            return MicrophonePermission::Undetermined;
+        };
+        let status = unsafe { AVCaptureDevice::authorizationStatusForMediaType(audio) };
+        match status {
+            AVAuthorizationStatus::Authorized => MicrophonePermission::Granted,
+            AVAuthorizationStatus::NotDetermined => MicrophonePermission::Undetermined,
+            // Restricted means parental controls or MDM; the user can't change it either
+            _ => MicrophonePermission::Denied,
+        }
+    }
+
+    pub async fn request() -> MicrophonePermission {
+        let Some(audio) = (unsafe { AVMediaTypeAudio }) else {
+            return MicrophonePermission::Undetermined;
+        };
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        let tx = std::sync::Mutex::new(Some(tx));
+        // Called once, on an arbitrary dispatch queue
+        let handler = RcBlock::new(move |granted: Bool| {
+            if let Some(tx) = tx.lock().ok().and_then(|mut tx| tx.take()) {
+                let _ = tx.send(granted.as_bool());
+            }
+        });
+        unsafe { AVCaptureDevice::requestAccessForMediaType_completionHandler(audio, &handler) };
+        match rx.await {
+            Ok(true) => MicrophonePermission::Granted,
+            Ok(false) => MicrophonePermission::Denied,
+            Err(_) => status(),
+        }
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use winreg::enums::{HKEY_CURRENT_USER, HKEY_LOCAL_MACHINE};
+    use winreg::RegKey;
+
+    use super::MicrophonePermission;
+
+    pub const SETTINGS_URL: Option<&str> = Some("ms-settings:privacy-microphone");
+
+    const CONSENT_STORE: &str = r"Software\Microsoft\Windows\CurrentVersion\CapabilityAccessManager\ConsentStore\microphone";
+
+    /// "Deny" in any of the switches that apply to unpackaged desktop apps blocks the mic
+    /// Missing values mean the user never touched them, and Windows allows access by default
+    pub fn status() -> MicrophonePermission {
+        let machine = RegKey::predef(HKEY_LOCAL_MACHINE);
+        let user = RegKey::predef(HKEY_CURRENT_USER);
+        let switches = [
+            (&machine, CONSENT_STORE.to_string()),
+            (&user, CONSENT_STORE.to_string()),
+            (&user, format!(r"{}\NonPackaged", CONSENT_STORE)),
+        ];
+        let denied = switches.iter().any(|(root, path)| {
+            root.open_subkey(path)
+                .and_then(|key| key.get_value::<String, _>("Value"))
+                .is_ok_and(|value| value.eq_ignore_ascii_case("Deny"))
+        });
+        if denied {
+            MicrophonePermission::Denied
+        } else {
+            MicrophonePermission::Granted
+        }
+    }
+
+    /// Desktop apps can't trigger a consent prompt on Windows
+    pub async fn request() -> MicrophonePermission {
+        status()
+    }
+}
+
+#[cfg(target_os = "android")]
+mod platform {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use super::MicrophonePermission;
+    use crate::android;
+
+    /// App settings are opened with an intent, not a URL
+    pub const SETTINGS_URL: Option<&str> = None;
+
+    /// The prompt was shown since launch; Android doesn't say whether it ever was before
+    static ASKED: AtomicBool = AtomicBool::new(false);
+
+    /// Android only stops prompting after the user declined twice, and says so by no
+    /// longer wanting a rationale; before the first request that looks the same as never
+    /// asked, so it's only taken as Denied once we've asked
+    pub fn status() -> MicrophonePermission {
+        match android::record_audio_granted() {
+            Ok(true) => MicrophonePermission::Granted,
+            Ok(false)
+                if ASKED.load(Ordering::Relaxed)
+                    && !android::should_explain_record_audio().unwrap_or(true) =>
+            {
+                MicrophonePermission::Denied
+            }
+            Ok(false) => MicrophonePermission::Undetermined,
+            Err(e) => {
+                tracing::warn!("Failed to check the microphone permission: {}", e);
+                MicrophonePermission::Undetermined
+            }
+        }
+    }
+
+    pub async fn request() -> MicrophonePermission {
+        // Waits for the user to answer the prompt
+        if let Err(e) = crate::state::blocking(android::request_record_audio).await {
+            tracing::warn!("Failed to request the microphone permission: {}", e);
+        }
+        ASKED.store(true, Ordering::Relaxed);
+        status()
+    }
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "ios", target_os = "android", windows)))]
+mod platform {
+    use super::MicrophonePermission;
+
+    pub const SETTINGS_URL: Option<&str> = None;
+
+    /// Desktop Linux has no per-app microphone permission to query
+    pub fn status() -> MicrophonePermission {
+        MicrophonePermission::Granted
+    }
+
+    pub async fn request() -> MicrophonePermission {
+        status()
+    }
+}
@@ -0,0 +1,615 @@
+// Spoken punctuation and formatting ("comma", "new paragraph") turned into text
+// A phrase only counts as a command after a pause or as a whole utterance, so the
+// "period" in "a period of time" stays a word
+// Snippets are expanded here too, ahead of the commands, since both rewrite a segment,
+// and the locale's numbers, dates and quotation marks are applied to what comes out
+// Fillers and stutters are taken out in between, once the commands have placed the quotes
+
+use tauri::AppHandle;
+
+use super::disfluency::Disfluency;
+use super::localize::Localizer;
+use super::redaction;
+use super::snippets::Snippets;
+use crate::config::{CommandSpacing, DictationCommand, DictationCommandSettings};
+use crate::error::AppError;
+use crate::locale::Locale;
+use crate::state;
+use crate::storage::Word;
+
+/// Punctuation Deepgram may have added that a spoken command replaces
+const AUTO_PUNCTUATION: &[char] = &['.', ',', ';', ':', '!', '?'];
+/// What the built-in quote commands insert; replaced by the locale's marks
+const OPEN_QUOTE: &str = "\u{201C}";
+const CLOSE_QUOTE: &str = "\u{201D}";
+/// Languages `builtin` has a table for
+pub const BUILTIN_LANGUAGES: &[&str] = &["en", "de", "fr"];
+
+fn command(
+    phrase: &str,
+    output: &str,
+    spacing: CommandSpacing,
+    capitalize_next: bool,
+) -> DictationCommand {
+    DictationCommand {
+        phrase: phrase.to_string(),
+        output: output.to_string(),
+        spacing,
+        capitalize_next,
+    }
+}
+
+/// Built-in command table for a primary language code
+pub fn builtin(language: &str) -> Vec<DictationCommand> {
+    use CommandSpacing::*;
+    match language {
+        "en" => vec![
+            command("period", ".", NoSpaceBefore, true),
+            command("full stop", ".", NoSpaceBefore, true),
+            command("comma", ",", NoSpaceBefore, false),
+            command("question mark", "?", NoSpaceBefore, true),
+            command("exclamation mark", "!", NoSpaceBefore, true),
+            command("exclamation point", "!", NoSpaceBefore, true),
+            command("colon", ":", NoSpaceBefore, false),
+            command("semicolon", ";", NoSpaceBefore, false),
+            command("new line", "\n", NoSpaceAround, true),
+            command("new paragraph", "\n\n", NoSpaceAround, true),
+            command("open quote", OPEN_QUOTE, NoSpaceAfter, false),
+            command("close quote", CLOSE_QUOTE, NoSpaceBefore, false),
+            command("open paren", "(", NoSpaceAfter, false),
+            command("close paren", ")", NoSpaceBefore, false),
+            command("dash", "-", Spaced, false),
+        ],
+        "de" => vec![
+            command("punkt", ".", NoSpaceBefore, true),
+            command("komma", ",", NoSpaceBefore, false),
+            command("fragezeichen", "?", NoSpaceBefore, true),
+            command("ausrufezeichen", "!", NoSpaceBefore, true),
+            command("doppelpunkt", ":", NoSpaceBefore, false),
+            command("neue zeile", "\n", NoSpaceAround, true),
+            command("neuer absatz", "\n\n", NoSpaceAround, true),
+            command("anführungszeichen auf", OPEN_QUOTE, NoSpaceAfter, false),
+            command("anführungszeichen zu", CLOSE_QUOTE, NoSpaceBefore, false),
+        ],
+        "fr" => vec![
+            command("point", ".", NoSpaceBefore, true),
+            command("virgule", ",", NoSpaceBefore, false),
+            command("point d'interrogation", "?", NoSpaceBefore, true),
+            command("point d'exclamation", "!", NoSpaceBefore, true),
+            command("deux points", ":", NoSpaceBefore, false),
+            command("nouvelle ligne", "\n", NoSpaceAround, true),
+            command("nouveau paragraphe", "\n\n", NoSpaceAround, true),
+            command("ouvrez les guillemets", OPEN_QUOTE, NoSpaceAfter, false),
+            command("fermez les guillemets", CLOSE_QUOTE, NoSpaceBefore, false),
+        ],
+        _ => Vec::new(),
+    }
+}
+
+/// Primary subtag of a language code, e.g. "en-US" → "en"
+pub(super) fn primary_language(language: &str) -> String {
+    language
+        .split(['-', '_'])
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase()
+}
+
+/// Lowercase and strip punctuation so "Period." matches "period"
+pub(super) fn normalize(word: &str) -> String {
+    word.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// The command table in effect for `language`: the configured one, else the built-in one
+pub fn table(settings: &DictationCommandSettings, language: &str) -> Vec<DictationCommand> {
+    let language = primary_language(language);
+    settings
+        .languages
+        .get(&language)
+        .cloned()
+        .unwrap_or_else(|| builtin(&language))
+}
+
+struct Phrase {
+    words: Vec<String>,
+    command: DictationCommand,
+}
+
+/// Applies commands to consecutive final segments of one stream or file
+/// Remembers the previous segment so pauses and capitalization carry across segments
+pub struct DictationCommands {
+    /// Longest phrases first, so "point d'interrogation" wins over "point"
+    phrases: Vec<Phrase>,
+    pause_ms: i64,
+    last_word_end_ms: Option<i64>,
+    capitalize_next: bool,
+    snippets: Snippets,
+    disfluency: Option<Disfluency>,
+    localizer: Option<Localizer>,
+    /// Normalized words of the phrases that take back the previous segment
+    revoke_phrases: Vec<Vec<String>>,
+    revoke_window_ms: u32,
+}
+
+impl DictationCommands {
+    pub fn new(settings: &DictationCommandSettings, language: &str, locale: &Locale) -> Self {
+        let mut phrases: Vec<Phrase> = if settings.enabled {
+            table(settings, language)
+                .into_iter()
+                .map(|mut command| {
+                    command.output = match command.output.as_str() {
+                        OPEN_QUOTE => locale.open_quote.to_string(),
+                        CLOSE_QUOTE => locale.close_quote.to_string(),
+                        _ => command.output,
+                    };
+                    command
+                })
+                .map(|command| Phrase {
+                    words: command.phrase.split_whitespace().map(normalize).collect(),
+                    command,
+                })
+                .filter(|phrase| !phrase.words.is_empty())
+                .collect()
+        } else {
+            Vec::new()
+        };
+        phrases.sort_by_key(|phrase| std::cmp::Reverse(phrase.words.len()));
+        let revoke_phrases = if settings.enabled {
+            settings
+                .revoke_phrases
+                .iter()
+                .map(|phrase| phrase.split_whitespace().map(normalize).collect::<Vec<_>>())
+                .filter(|words| !words.is_empty())
+                .collect()
+        } else {
+            Vec::new()
+        };
+        Self {
+            phrases,
+            pause_ms: i64::from(settings.pause_ms),
+            last_word_end_ms: None,
+            capitalize_next: false,
+            snippets: Snippets::default(),
+            disfluency: None,
+            localizer: Localizer::new(language, locale),
+            revoke_phrases,
+            revoke_window_ms: settings.revoke_window_ms,
+        }
+    }
+
+    /// Commands for the active profile's language and locale, the saved snippets and the
+    /// disfluency pass, or none if the config can't be read
+    pub fn load(app: &AppHandle, language: &str) -> Self {
+        let config = crate::config::load(app).unwrap_or_default();
+        let locale = Locale::for_settings(&config.active().transcription);
+        Self {
+            snippets: Snippets::new(&config.snippets, &locale),
+            ..Self::new(&config.dictation_commands, language, &locale)
+        }
+        .with_disfluency(Disfluency::new(&config.disfluency, language, &locale))
+    }
+
+    /// Run `disfluency` on what the commands produce
+    pub fn with_disfluency(self, disfluency: Option<Disfluency>) -> Self {
+        Self { disfluency, ..self }
+    }
+
+    /// Words the disfluency pass took out so far, or None when it's off
+    pub fn disfluencies_removed(&self) -> Option<u32> {
+        self.disfluency.as_ref().map(Disfluency::removed)
+    }
+
+    /// Whether a final segment is nothing but a phrase taking back the one before it
+    /// Such a segment is dropped rather than rewritten
+    pub fn is_revocation(&self, text: &str) -> bool {
+        if self.revoke_phrases.is_empty() || redaction::contains_placeholder(text) {
+            return false;
+        }
+        let words: Vec<String> = text
+            .split_whitespace()
+            .map(normalize)
+            .filter(|word| !word.is_empty())
+            .collect();
+        self.revoke_phrases.contains(&words)
+    }
+
+    /// See `DictationCommandSettings::revoke_window_ms`
+    pub fn revoke_window_ms(&self) -> u32 {
+        self.revoke_window_ms
+    }
+
+    /// Rewrite one final segment; `words` are its timings when Deepgram sent them
+    pub fn apply(&mut self, text: &str, words: &[Word]) -> String {
+        if let Some(body) = self.snippets.expand(text) {
+            if let Some(last) = words.last() {
+                self.last_word_end_ms = Some(last.end_ms);
+            }
+            self.capitalize_next = false;
+            return body;
+        }
+        let mut output = self.apply_commands(text, words);
+        if let Some(disfluency) = &mut self.disfluency {
+            output = disfluency.apply(&output);
+        }
+        match &self.localizer {
+            Some(localizer) => localizer.apply(&output),
+            None => output,
+        }
+    }
+
+    fn apply_commands(&mut self, text: &str, words: &[Word]) -> String {
+        if self.phrases.is_empty() {
+            return text.to_string();
+        }
+        let output = if words.is_empty() {
+            self.apply_untimed(text)
+        } else {
+            self.apply_timed(words)
+        };
+        if let Some(last) = words.last() {
+            self.last_word_end_ms = Some(last.end_ms);
+        }
+        output.unwrap_or_else(|| {
+            let text = self.capitalize_first(text);
+            self.capitalize_next = false;
+            text
+        })
+    }
+
+    /// Without timings only a whole utterance can be a command
+    fn apply_untimed(&mut self, text: &str) -> Option<String> {
+        if redaction::contains_placeholder(text) {
+            return None;
+        }
+        let words: Vec<String> = text.split_whitespace().map(normalize).collect();
+        let phrase = self.phrases.iter().find(|phrase| phrase.words == words)?;
+        self.capitalize_next = phrase.command.capitalize_next;
+        Some(phrase.command.output.clone())
+    }
+
+    /// None when no command matched, so the transcript is used as Deepgram formatted it
+    fn apply_timed(&mut self, words: &[Word]) -> Option<String> {
+        // Redaction placeholders never take part in a command
+        let normalized: Vec<Option<String>> = words
+            .iter()
+            .map(|word| {
+                (!redaction::contains_placeholder(&word.text)).then(|| normalize(&word.text))
+            })
+            .collect();
+        let mut output = String::new();
+        let mut matched = false;
+        let mut capitalize = self.capitalize_next;
+        let mut space_next = false;
+        let mut index = 0;
+
+        while index < words.len() {
+            let phrase = self
+                .phrases
+                .iter()
+                .find(|phrase| {
+                    let candidate = normalized[index..].iter().take(phrase.words.len());
+                    phrase.words.len() <= words.len() - index
+                        && candidate
+                            .zip(&phrase.words)
+                            .all(|(word, expected)| word.as_ref() == Some(expected))
+                })
+                .filter(|phrase| {
+                    let isolated = index == 0 && phrase.words.len() == words.len();
+                    isolated || self.pause_before(words, index)
+                });
+            let Some(phrase) = phrase else {
+                let mut text = words[index].text.clone();
+                if capitalize {
+                    text = capitalize_first_char(&text);
+                }
+                if space_next {
+                    output.push(' ');
+                }
+                output.push_str(&text);
+                capitalize = false;
+                space_next = true;
+                index += 1;
+                continue;
+            };
+
+            let command = &phrase.command;
+            matched = true;
+            match command.spacing {
+                CommandSpacing::NoSpaceBefore | CommandSpacing::NoSpaceAround => {
+                    // The spoken mark replaces whatever Deepgram guessed there
+                    if command
+                        .output
+                        .chars()
+                        .all(|c| AUTO_PUNCTUATION.contains(&c))
+                    {
+                        output.truncate(output.trim_end_matches(AUTO_PUNCTUATION).len());
+                    }
+                }
+                CommandSpacing::NoSpaceAfter | CommandSpacing::Spaced => {
+                    if space_next {
+                        output.push(' ');
+                    }
+                }
+            }
+            output.push_str(&command.output);
+            space_next = matches!(
+                command.spacing,
+                CommandSpacing::NoSpaceBefore | CommandSpacing::Spaced
+            );
+            // An opening quote or paren passes a pending capital on to the word inside it
+            capitalize = command.capitalize_next
+                || (capitalize && command.spacing == CommandSpacing::NoSpaceAfter);
+            index += phrase.words.len();
+        }
+
+        if !matched {
+            return None;
+        }
+        self.capitalize_next = capitalize;
+        Some(output)
+    }
+
+    /// Whether there is a pause before `words[index]`; the start of a stream counts as one
+    fn pause_before(&self, words: &[Word], index: usize) -> bool {
+        let previous_end = match index {
+            0 => self.last_word_end_ms,
+            _ => Some(words[index - 1].end_ms),
+        };
+        match previous_end {
+            // Negative gaps happen when timings restart after a reconnect
+            Some(end) => {
+                let gap = words[index].start_ms - end;
+                gap < 0 || gap >= self.pause_ms
+            }
+            None => true,
+        }
+    }
+
+    fn capitalize_first(&self, text: &str) -> String {
+        if self.capitalize_next {
+            capitalize_first_char(text)
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+pub(super) fn capitalize_first_char(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// Join segments into one text, without spaces where commands attach to their neighbours
+pub fn join<'a>(parts: impl IntoIterator<Item = &'a str>) -> String {
+    let mut text = String::new();
+    for part in parts {
+        // Quotation marks of the locales, and the no-break space inside French ones; German's
+        // closing “ is English's opening one, so English wins that
+        let attaches_left = part.starts_with(|c: char| {
+            AUTO_PUNCTUATION.contains(&c)
+                || matches!(c, '\n' | ')' | '\u{201D}' | '\u{BB}' | '\u{A0}')
+        });
+        let attaches_right =
+            text.ends_with(['\n', '(', '\u{201C}', '\u{201E}', '\u{AB}', '\u{A0}']);
+        if !text.is_empty() && !attaches_left && !attaches_right {
+            text.push(' ');
+        }
+        text.push_str(part);
+    }
+    text
+}
+
+/// Command to read the dictation command settings
+#[tauri::command]
+pub async fn get_dictation_commands(app: AppHandle) -> Result<DictationCommandSettings, AppError> {
+    state::blocking(move || crate::config::load(&app).map(|config| config.dictation_commands)).await
+}
+
+/// Command to read the table in effect for a language (the active profile's when None)
+#[tauri::command]
+pub async fn get_dictation_command_table(
+    app: AppHandle,
+    language: Option<String>,
+) -> Result<Vec<DictationCommand>, AppError> {
+    let config = state::blocking(move || crate::config::load(&app)).await?;
+    let language = language.unwrap_or_else(|| config.active().transcription.language.clone());
+    Ok(table(&config.dictation_commands, &language))
+}
+
+/// Command to save the dictation command settings; applies from the next stream or file
+#[tauri::command]
+pub async fn set_dictation_commands(
+    app: AppHandle,
+    settings: DictationCommandSettings,
+) -> Result<DictationCommandSettings, AppError> {
+    let invalid: Vec<String> = settings
+        .languages
+        .iter()
+        .flat_map(|(language, commands)| {
+            commands
+                .iter()
+                .filter(|command| {
+                    normalize(&command.phrase).is_empty() || command.output.is_empty()
+                })
+                .map(move |command| format!("{}: \"{}\"", language, command.phrase))
+        })
+        .collect();
+    if !invalid.is_empty() {
+        return Err(AppError::InvalidInput(format!(
+            "Commands need a phrase with letters or digits and a non-empty output: {}",
+            invalid.join(", ")
+        )));
+    }
+    if let Some(phrase) = settings
+        .revoke_phrases
+        .iter()
+        .find(|phrase| normalize(phrase).is_empty())
+    {
+        return Err(AppError::InvalidInput(format!(
+            "\"{}\" can't take back a segment; phrases need letters or digits",
+            phrase
+        )));
+    }
+    state::blocking(move || {
+        crate::config::update(&app, |config| {
+            if let Some(collision) = super::snippets::collision(&config.snippets, &settings) {
+                return Err(AppError::InvalidInput(collision));
+            }
+            config.dictation_commands = settings.clone();
+            Ok(settings)
+        })
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Words 100 ms long, with a 500 ms pause before each word marked with '|'
+    fn words(spoken: &str) -> Vec<Word> {
+        let mut time = 0;
+        spoken
+            .split_whitespace()
+            .map(|word| {
+                let word = match word.strip_prefix('|') {
+                    Some(word) => {
+                        time += 500;
+                        word
+                    }
+                    None => word,
+                };
+                let start_ms = time;
+                time += 100;
+                Word {
+                    text: word.to_string(),
+                    start_ms,
+                    end_ms: time,
+                    confidence: 1.0,
+                }
+            })
+            .collect()
+    }
+
+    fn english() -> DictationCommands {
+        let mut commands = DictationCommands::new(
+            &DictationCommandSettings::default(),
+            "en-US",
+            &Locale::new("en-US"),
+        );
+        // Pretend the stream has been going, so a segment start isn't a pause by itself
+        commands.last_word_end_ms = Some(0);
+        commands
+    }
+
+    fn apply(commands: &mut DictationCommands, spoken: &str) -> String {
+        let words = words(spoken);
+        let text = words
+            .iter()
+            .map(|word| word.text.as_str())
+            .collect::<Vec<_>>()
+            .join(" ");
+        commands.apply(&text, &words)
+    }
+
+    #[test]
+    fn converts_commands_after_a_pause() {
+        let mut commands = english();
+        assert_eq!(
+            apply(
+                &mut commands,
+                "hello |comma how are you |question mark |new line thanks"
+            ),
+            "hello, how are you?\nThanks"
+        );
+    }
+
+    #[test]
+    fn keeps_words_spoken_without_a_pause() {
+        let mut commands = english();
+        assert_eq!(apply(&mut commands, "a period of time"), "a period of time");
+    }
+
+    #[test]
+    fn isolated_utterance_is_a_command_and_capitalizes_the_next_segment() {
+        let mut commands = english();
+        assert_eq!(apply(&mut commands, "Period."), ".");
+        assert_eq!(apply(&mut commands, "and then"), "And then");
+    }
+
+    #[test]
+    fn replaces_deepgram_punctuation() {
+        let mut commands = english();
+        assert_eq!(
+            apply(
+                &mut commands,
+                "see you tomorrow, |period. |open quote Bye |close quote"
+            ),
+            "see you tomorrow. \u{201C}Bye\u{201D}"
+        );
+    }
+
+    #[test]
+    fn disabled_pass_leaves_text_alone() {
+        let settings = DictationCommandSettings {
+            enabled: false,
+            ..DictationCommandSettings::default()
+        };
+        let mut commands = DictationCommands::new(&settings, "en", &Locale::default());
+        assert_eq!(apply(&mut commands, "period"), "period");
+    }
+
+    #[test]
+    fn quotes_and_numbers_follow_the_locale() {
+        let cases = [
+            ("en-US", "It was \u{201C}$1,234.50\u{201D}"),
+            ("de-DE", "It was \u{201E}$1.234,50\u{201C}"),
+            ("fr-FR", "It was \u{AB}\u{A0}$1\u{202F}234,50\u{A0}\u{BB}"),
+        ];
+        for (tag, expected) in cases {
+            let mut commands = DictationCommands::new(
+                &DictationCommandSettings::default(),
+                "en-US",
+                &Locale::new(tag),
+            );
+            commands.last_word_end_ms = Some(0);
+            assert_eq!(
+                apply(&mut commands, "It was |open quote $1,234.50 |close quote"),
+                expected,
+                "{}",
+                tag
+            );
+        }
+    }
+
+    #[test]
+    fn revocation_is_a_whole_segment() {
+        let commands = english();
+        assert!(commands.is_revocation("Scratch that."));
+        assert!(commands.is_revocation("delete that"));
+        assert!(!commands.is_revocation("please delete that file"));
+        let disabled = DictationCommands::new(
+            &DictationCommandSettings {
+                enabled: false,
+                ..DictationCommandSettings::default()
+            },
+            "en",
+            &Locale::default(),
+        );
+        assert!(!disabled.is_revocation("scratch that"));
+    }
+
+    #[test]
+    fn join_skips_spaces_around_attached_marks() {
+        assert_eq!(
+            join(["Hello", ".", "New", "\n", "line", "("]),
+            "Hello. New\nline ("
+        );
+    }
+}
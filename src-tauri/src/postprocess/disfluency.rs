@@ -0,0 +1,357 @@
+// Filler words ("um", "uh") and stutters ("I I think") taken out of final segments, so
+// dictation reads as written
+// Runs inside `DictationCommands::apply`, on what the commands produced, so the quotation
+// marks are already in place: a quote is kept word for word, and one left open carries
+// over to the next segment. A filler's full stop moves to the word before it, and the
+// word after a filler that opened a sentence is capitalized in its place
+
+use std::collections::HashSet;
+
+use tauri::AppHandle;
+
+use super::dictation_commands::{self, normalize};
+use super::redaction;
+use crate::config::DisfluencySettings;
+use crate::error::AppError;
+use crate::locale::Locale;
+use crate::state;
+
+/// Punctuation that ends a sentence, carried over from a removed filler
+const SENTENCE_END: &[char] = &['.', '!', '?'];
+/// Punctuation a carried-over sentence end replaces
+const PAUSE: &[char] = &[',', ';', ':'];
+
+/// Built-in fillers for a primary language code
+pub fn builtin(language: &str) -> Vec<String> {
+    let fillers: &[&str] = match language {
+        "en" => &["um", "umm", "uh", "uhm", "er", "erm", "hmm"],
+        "de" => &["äh", "ähm", "öh", "öhm", "hm"],
+        "fr" => &["euh", "heu", "hum"],
+        _ => &[],
+    };
+    fillers.iter().map(|filler| filler.to_string()).collect()
+}
+
+/// The fillers in effect for `language`: the configured ones, else the built-in ones
+pub fn fillers(settings: &DisfluencySettings, language: &str) -> Vec<String> {
+    let language = dictation_commands::primary_language(language);
+    settings
+        .fillers
+        .get(&language)
+        .cloned()
+        .unwrap_or_else(|| builtin(&language))
+}
+
+/// A word that's staying, with the whitespace before it
+struct Kept<'a> {
+    space: &'a str,
+    text: String,
+    verbatim: bool,
+}
+
+/// The pass over consecutive final segments of one stream or file
+pub struct Disfluency {
+    /// Normalized, like the words they're compared with
+    fillers: HashSet<String>,
+    collapse_repetitions: bool,
+    /// The locale's marks without their spacing
+    open_quote: &'static str,
+    close_quote: &'static str,
+    quoted: bool,
+    removed: u32,
+}
+
+impl Disfluency {
+    /// The pass for `language`, or None when it's turned off
+    pub fn new(settings: &DisfluencySettings, language: &str, locale: &Locale) -> Option<Self> {
+        settings.enabled.then(|| Self {
+            fillers: fillers(settings, language)
+                .iter()
+                .map(|filler| normalize(filler))
+                .filter(|filler| !filler.is_empty())
+                .collect(),
+            collapse_repetitions: settings.collapse_repetitions,
+            open_quote: locale.open_quote.trim(),
+            close_quote: locale.close_quote.trim(),
+            quoted: false,
+            removed: 0,
+        })
+    }
+
+    /// Words taken out of the segments so far
+    pub fn removed(&self) -> u32 {
+        self.removed
+    }
+
+    /// Rewrite one final segment, which comes out empty if it was nothing but fillers
+    pub fn apply(&mut self, text: &str) -> String {
+        let (words, trailing) = split(text);
+        let mut kept: Vec<Kept> = Vec::new();
+        // Whitespace for the next kept word when the ones before it were taken out
+        let mut gap: Option<&str> = None;
+        let mut capitalize = false;
+        for (space, word) in words {
+            let verbatim = self.track_quotes(word) || redaction::contains_placeholder(word);
+            let normalized = normalize(word);
+            if !verbatim && self.fillers.contains(&normalized) {
+                if word.starts_with(char::is_uppercase) && starts_sentence(&kept, space) {
+                    capitalize = true;
+                }
+                if let Some(end) = word.chars().last().filter(|c| SENTENCE_END.contains(c)) {
+                    end_sentence(&mut kept, end);
+                }
+                if gap.is_none() || space.contains('\n') {
+                    gap = Some(space);
+                }
+                self.removed += 1;
+                continue;
+            }
+            let mut space = match gap.take() {
+                Some(gap) if kept.is_empty() || gap.contains('\n') => gap,
+                _ => space,
+            };
+            if !verbatim && self.collapse_repetitions && repeats(kept.last(), space, &normalized) {
+                // The word said again takes the first one's place
+                if let Some(first) = kept.pop() {
+                    capitalize |= first.text.starts_with(char::is_uppercase);
+                    space = first.space;
+                }
+                self.removed += 1;
+            }
+            let text = if capitalize && !verbatim {
+                dictation_commands::capitalize_first_char(word)
+            } else {
+                word.to_string()
+            };
+            capitalize = false;
+            kept.push(Kept {
+                space,
+                text,
+                verbatim,
+            });
+        }
+        if kept.is_empty() {
+            return String::new();
+        }
+        let mut output: String = kept
+            .iter()
+            .flat_map(|word| [word.space, word.text.as_str()])
+            .collect();
+        output.push_str(trailing);
+        output
+    }
+
+    /// Whether `word` is in a quote or has one of its marks, following the quote along
+    fn track_quotes(&mut self, word: &str) -> bool {
+        let mut verbatim = self.quoted;
+        for (index, _) in word.char_indices() {
+            let rest = &word[index..];
+            if self.open_quote == self.close_quote {
+                // Swedish and Finnish open and close with the same mark
+                if !self.open_quote.is_empty() && rest.starts_with(self.open_quote) {
+                    self.quoted = !self.quoted;
+                    verbatim = true;
+                }
+            } else if !self.open_quote.is_empty() && rest.starts_with(self.open_quote) {
+                self.quoted = true;
+                verbatim = true;
+            } else if !self.close_quote.is_empty() && rest.starts_with(self.close_quote) {
+                self.quoted = false;
+                verbatim = true;
+            }
+        }
+        verbatim
+    }
+}
+
+/// The words of `text` with the whitespace before each, and the whitespace after the last
+fn split(text: &str) -> (Vec<(&str, &str)>, &str) {
+    let mut words = Vec::new();
+    let mut rest = text;
+    loop {
+        let start = rest
+            .find(|c: char| !c.is_whitespace())
+            .unwrap_or(rest.len());
+        if start == rest.len() {
+            return (words, rest);
+        }
+        let end = rest[start..]
+            .find(char::is_whitespace)
+            .map_or(rest.len(), |end| start + end);
+        words.push((&rest[..start], &rest[start..end]));
+        rest = &rest[end..];
+    }
+}
+
+/// Whether a word after `kept` and `space` opens a sentence
+fn starts_sentence(kept: &[Kept], space: &str) -> bool {
+    match kept.last() {
+        None => true,
+        Some(previous) => {
+            space.contains('\n')
+                || previous
+                    .text
+                    .trim_end_matches(|c: char| !c.is_alphanumeric() && !SENTENCE_END.contains(&c))
+                    .ends_with(SENTENCE_END)
+        }
+    }
+}
+
+/// Put a removed filler's `end` on the word before it, unless that one ends a sentence already
+fn end_sentence(kept: &mut [Kept], end: char) {
+    let Some(previous) = kept.last_mut() else {
+        return;
+    };
+    if previous.text.ends_with(SENTENCE_END) {
+        return;
+    }
+    if !previous.verbatim {
+        previous
+            .text
+            .truncate(previous.text.trim_end_matches(PAUSE).len());
+    }
+    previous.text.push(end);
+}
+
+/// Whether a word normalized to `normalized` repeats `previous` straight after it
+/// Numbers are left alone, since "4 4" may well be meant, and so is a repeat across a
+/// sentence end or a line break
+fn repeats(previous: Option<&Kept>, space: &str, normalized: &str) -> bool {
+    let Some(previous) = previous.filter(|previous| !previous.verbatim) else {
+        return false;
+    };
+    !normalized.is_empty()
+        && !normalized.chars().any(|c| c.is_numeric())
+        && !space.contains('\n')
+        && previous
+            .text
+            .ends_with(|c: char| c.is_alphanumeric() || c == ',')
+        && normalize(&previous.text) == normalized
+}
+
+/// Command to read the disfluency settings
+#[tauri::command]
+pub async fn get_disfluency_settings(app: AppHandle) -> Result<DisfluencySettings, AppError> {
+    state::blocking(move || crate::config::load(&app).map(|config| config.disfluency)).await
+}
+
+/// Command to read the fillers in effect for a language (the active profile's when None)
+#[tauri::command]
+pub async fn get_filler_words(
+    app: AppHandle,
+    language: Option<String>,
+) -> Result<Vec<String>, AppError> {
+    let config = state::blocking(move || crate::config::load(&app)).await?;
+    let language = language.unwrap_or_else(|| config.active().transcription.language.clone());
+    Ok(fillers(&config.disfluency, &language))
+}
+
+/// Command to save the disfluency settings; applies from the next stream or file
+#[tauri::command]
+pub async fn set_disfluency_settings(
+    app: AppHandle,
+    settings: DisfluencySettings,
+) -> Result<DisfluencySettings, AppError> {
+    let invalid: Vec<String> = settings
+        .fillers
+        .iter()
+        .flat_map(|(language, fillers)| {
+            fillers
+                .iter()
+                .filter(|filler| {
+                    normalize(filler).is_empty() || filler.trim().contains(char::is_whitespace)
+                })
+                .map(move |filler| format!("{}: \"{}\"", language, filler))
+        })
+        .collect();
+    if !invalid.is_empty() {
+        return Err(AppError::InvalidInput(format!(
+            "Fillers need to be one word with letters or digits: {}",
+            invalid.join(", ")
+        )));
+    }
+    state::blocking(move || {
+        crate::config::update(&app, |config| {
+            config.disfluency = settings.clone();
+            Ok(settings)
+        })
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn english() -> Disfluency {
+        let settings = DisfluencySettings {
+            enabled: true,
+            ..DisfluencySettings::default()
+        };
+        Disfluency::new(&settings, "en-US", &Locale::new("en-US")).unwrap()
+    }
+
+    #[test]
+    fn removes_fillers_and_fixes_up_punctuation_and_capitals() {
+        let mut pass = english();
+        assert_eq!(pass.apply("Um, so we went there, uh."), "So we went there.");
+        assert_eq!(
+            pass.apply("It was, er, fine.\nUh, next"),
+            "It was, fine.\nNext"
+        );
+        assert_eq!(pass.apply("Uh."), "");
+        assert_eq!(pass.removed(), 5);
+    }
+
+    #[test]
+    fn collapses_repeated_words_but_not_numbers_or_sentences() {
+        let mut pass = english();
+        assert_eq!(pass.apply("I I think the the the cat"), "I think the cat");
+        assert_eq!(pass.apply("The, the plan is 4 4"), "The plan is 4 4");
+        assert_eq!(pass.apply("Done. Done"), "Done. Done");
+        assert_eq!(pass.apply("I, um, I know"), "I know");
+        assert_eq!(pass.removed(), 6);
+    }
+
+    #[test]
+    fn quotes_are_verbatim_across_segments() {
+        let mut pass = english();
+        assert_eq!(
+            pass.apply("He said \u{201C}um, the the"),
+            "He said \u{201C}um, the the"
+        );
+        assert_eq!(
+            pass.apply("uh end\u{201D} and uh left"),
+            "uh end\u{201D} and left"
+        );
+        assert_eq!(pass.removed(), 1);
+
+        let settings = DisfluencySettings {
+            enabled: true,
+            ..DisfluencySettings::default()
+        };
+        let mut french = Disfluency::new(&settings, "fr", &Locale::new("fr-FR")).unwrap();
+        assert_eq!(
+            french.apply("Il a dit euh \u{AB}\u{A0}euh oui\u{A0}\u{BB}"),
+            "Il a dit \u{AB}\u{A0}euh oui\u{A0}\u{BB}"
+        );
+    }
+
+    #[test]
+    fn configured_fillers_replace_the_built_in_ones() {
+        let settings = DisfluencySettings {
+            enabled: true,
+            fillers: [("en".to_string(), vec!["like".to_string()])].into(),
+            collapse_repetitions: false,
+            ..DisfluencySettings::default()
+        };
+        let mut pass = Disfluency::new(&settings, "en-GB", &Locale::default()).unwrap();
+        assert_eq!(
+            pass.apply("um it was like so so good"),
+            "um it was so so good"
+        );
+        assert!(
+            Disfluency::new(&DisfluencySettings::default(), "en", &Locale::default()).is_none()
+        );
+    }
+}
@@ -0,0 +1,438 @@
+// Optional rewriting of a saved transcript by an LLM (summary, cleanup, bullet points)
+// Entirely separate from transcription: nothing here runs on its own, and a transcript
+// is only sent when the user asks for it with `refine_transcript`. Any OpenAI-compatible
+// chat completions endpoint works, including local servers that need no key
+
+use std::sync::Arc;
+
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::config::{self, LlmSettings};
+use crate::error::AppError;
+use crate::key_store;
+use crate::secrets;
+use crate::state::{blocking, AppState};
+use crate::storage::Session;
+
+/// Event carrying each piece of the rewrite as it streams in
+pub const EVENT_REFINE_PROGRESS: &str = "refine-progress";
+
+/// How to rewrite a transcript
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RefineMode {
+    Summary,
+    Cleanup,
+    Bullets,
+}
+
+impl RefineMode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Summary => "summary",
+            Self::Cleanup => "cleanup",
+            Self::Bullets => "bullets",
+        }
+    }
+
+    fn instructions(self) -> &'static str {
+        match self {
+            Self::Summary => "Summarize the following transcript in one short paragraph. Reply with the summary only.",
+            Self::Cleanup => "Clean up the following dictated transcript: fix punctuation, casing and obvious recognition errors, and remove filler words and false starts. Keep the speaker's wording and meaning and add nothing. Reply with the cleaned text only.",
+            Self::Bullets => "Turn the following transcript into concise bullet points, one idea per line, each starting with \"- \". Reply with the bullet points only.",
+        }
+    }
+}
+
+/// Per-call overrides of the configured endpoint
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ProviderSettings {
+    pub base_url: Option<String>,
+    pub model: Option<String>,
+}
+
+/// Payload of the `refine-progress` event
+#[derive(Debug, Clone, Serialize)]
+pub struct RefineProgress {
+    pub session_id: i64,
+    /// Text added since the previous event
+    pub delta: String,
+}
+
+/// The endpoint as shown in settings
+#[derive(Debug, Clone, Serialize)]
+pub struct LlmStatus {
+    pub base_url: String,
+    pub model: String,
+    pub key_configured: bool,
+}
+
+#[tauri::command]
+pub async fn get_llm_settings(app: AppHandle) -> Result<LlmStatus, AppError> {
+    blocking(move || {
+        let settings = config::load(&app)?.llm;
+        Ok(status(&app, settings))
+    })
+    .await
+}
+
+/// Point post-processing at an endpoint; an empty `base_url` turns it off
+#[tauri::command]
+pub async fn set_llm_settings(
+    app: AppHandle,
+    base_url: String,
+    model: Option<String>,
+) -> Result<LlmStatus, AppError> {
+    let base_url = base_url.trim().trim_end_matches('/').to_string();
+    if !base_url.is_empty() {
+        endpoint(&base_url)?;
+    }
+    let model = model.map(|model| model.trim().to_string());
+    if model.as_deref().is_some_and(str::is_empty) {
+        return Err(AppError::InvalidInput(
+            "Model must not be empty".to_string(),
+        ));
+    }
+    blocking(move || {
+        let settings = config::update(&app, |config| {
+            config.llm.base_url = base_url;
+            if let Some(model) = model {
+                config.llm.model = model;
+            }
+            Ok(config.llm.clone())
+        })?;
+        Ok(status(&app, settings))
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn set_llm_api_key(app: AppHandle, key: String) -> Result<(), AppError> {
+    let key = key_store::normalize_key(&key)?;
+    blocking(move || secrets::save_llm_api_key(&app, &key).map(|_| ())).await
+}
+
+#[tauri::command]
+pub async fn clear_llm_api_key(app: AppHandle) -> Result<(), AppError> {
+    blocking(move || secrets::delete_llm_api_key(&app)).await
+}
+
+fn status(app: &AppHandle, settings: LlmSettings) -> LlmStatus {
+    LlmStatus {
+        base_url: settings.base_url,
+        model: settings.model,
+        key_configured: secrets::load_llm_api_key(app).is_ok_and(|key| key.is_some()),
+    }
+}
+
+/// Command to rewrite a session's transcript and store the result on the session
+/// Streams the rewrite as `refine-progress` events and returns the updated session
+#[tauri::command]
+pub async fn refine_transcript(
+    app: AppHandle,
+    session_id: i64,
+    mode: RefineMode,
+    provider_settings: Option<ProviderSettings>,
+) -> Result<Session, AppError> {
+    let storage = Arc::clone(&app.state::<AppState>().storage);
+    let load_app = app.clone();
+    let load_storage = Arc::clone(&storage);
+    let (session, settings, api_key) = blocking(move || {
+        let session = load_storage
+            .get_session(session_id)?
+            .ok_or_else(|| AppError::NotFound(format!("Session {} not found", session_id)))?;
+        let settings = config::load(&load_app)?.llm;
+        Ok((session, settings, secrets::load_llm_api_key(&load_app)?))
+    })
+    .await?;
+
+    let overrides = provider_settings.unwrap_or_default();
+    let base_url = overrides.base_url.unwrap_or(settings.base_url);
+    if base_url.trim().is_empty() {
+        return Err(AppError::Config(
+            "No LLM endpoint is configured for post-processing".to_string(),
+        ));
+    }
+    let endpoint = endpoint(base_url.trim())?;
+    if session.text.trim().is_empty() {
+        return Err(AppError::InvalidInput(
+            "This session has no transcript to refine".to_string(),
+        ));
+    }
+
+    let request = ChatRequest {
+        model: overrides.model.unwrap_or(settings.model),
+        stream: true,
+        messages: vec![
+            ChatMessage {
+                role: "system",
+                content: mode.instructions(),
+            },
+            ChatMessage {
+                role: "user",
+                content: &session.text,
+            },
+        ],
+    };
+    let response = send_chat(
+        app.state::<AppState>().http().post(endpoint.as_str()),
+        api_key.as_deref(),
+        &request,
+    )
+    .await?;
+    let text = read_completion(response, |delta| {
+        let _ = app.emit(
+            EVENT_REFINE_PROGRESS,
+            RefineProgress {
+                session_id,
+                delta: delta.to_string(),
+            },
+        );
+    })
+    .await?;
+    let text = text.trim().to_string();
+    if text.is_empty() {
+        return Err(AppError::network("The LLM endpoint returned no text"));
+    }
+    blocking(move || {
+        storage.set_refined(session_id, &text, mode.as_str())?;
+        storage
+            .get_session(session_id)?
+            .ok_or_else(|| AppError::NotFound(format!("Session {} not found", session_id)))
+    })
+    .await
+}
+
+/// One non-streamed completion of `text` under `instructions`, for other post-processing
+/// steps that use the same kind of endpoint
+pub(crate) async fn complete(
+    builder: reqwest::RequestBuilder,
+    api_key: Option<&str>,
+    model: &str,
+    instructions: &str,
+    text: &str,
+) -> Result<String, AppError> {
+    let request = ChatRequest {
+        model: model.to_string(),
+        stream: false,
+        messages: vec![
+            ChatMessage {
+                role: "system",
+                content: instructions,
+            },
+            ChatMessage {
+                role: "user",
+                content: text,
+            },
+        ],
+    };
+    let response = send_chat(builder, api_key, &request).await?;
+    read_completion(response, |_| {}).await
+}
+
+/// Send a chat request built on `builder`, failing on any unsuccessful status
+async fn send_chat(
+    builder: reqwest::RequestBuilder,
+    api_key: Option<&str>,
+    request: &ChatRequest<'_>,
+) -> Result<reqwest::Response, AppError> {
+    let mut builder = builder.json(request);
+    // Local servers usually take no key
+    if let Some(api_key) = api_key {
+        builder = builder.bearer_auth(api_key);
+    }
+    let response = builder.send().await?;
+    let status = response.status().as_u16();
+    if !response.status().is_success() {
+        let detail = response.text().await.unwrap_or_default();
+        return Err(AppError::Network {
+            status: Some(status),
+            message: format!(
+                "The LLM endpoint returned HTTP {}: {}",
+                status,
+                detail.trim()
+            ),
+        });
+    }
+    Ok(response)
+}
+
+/// The reply's text, passing each streamed piece to `emit` as it arrives
+async fn read_completion(
+    response: reqwest::Response,
+    emit: impl Fn(&str),
+) -> Result<String, AppError> {
+    let status = response.status().as_u16();
+    let streamed = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/event-stream"));
+    if streamed {
+        let mut text = String::new();
+        let mut decoder = SseDecoder::default();
+        let mut body = response.bytes_stream();
+        'body: while let Some(chunk) = body.next().await {
+            for data in decoder.push(&chunk?) {
+                if data == "[DONE]" {
+                    break 'body;
+                }
+                if let Some(delta) = delta_content(&data) {
+                    text.push_str(&delta);
+                    emit(&delta);
+                }
+            }
+        }
+        Ok(text)
+    } else {
+        // Some servers ignore `stream` and answer in one piece
+        let response: ChatResponse = response.json().await.map_err(|e| AppError::Network {
+            status: Some(status),
+            message: format!("Unexpected response from the LLM endpoint: {}", e),
+        })?;
+        let text = response
+            .choices
+            .into_iter()
+            .next()
+            .map(|choice| choice.message.content)
+            .unwrap_or_default();
+        emit(&text);
+        Ok(text)
+    }
+}
+
+/// Chat completions URL under an OpenAI-style base URL
+pub(crate) fn endpoint(base_url: &str) -> Result<url::Url, AppError> {
+    let url = url::Url::parse(&format!(
+        "{}/chat/completions",
+        base_url.trim_end_matches('/')
+    ))
+    .map_err(|e| AppError::InvalidInput(format!("Invalid LLM base URL: {}", e)))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(AppError::InvalidInput(
+            "LLM base URL must start with http:// or https://".to_string(),
+        ));
+    }
+    Ok(url)
+}
+
+#[derive(Serialize)]
+struct ChatRequest<'a> {
+    model: String,
+    stream: bool,
+    messages: Vec<ChatMessage<'a>>,
+}
+
+#[derive(Serialize)]
+struct ChatMessage<'a> {
+    role: &'static str,
+    content: &'a str,
+}
+
+#[derive(Deserialize)]
+struct ChatResponse {
+    choices: Vec<ChatChoice>,
+}
+
+#[derive(Deserialize)]
+struct ChatChoice {
+    message: ChatResponseMessage,
+}
+
+#[derive(Deserialize)]
+struct ChatResponseMessage {
+    #[serde(default)]
+    content: String,
+}
+
+/// One streamed chunk; only the text is of interest
+#[derive(Deserialize)]
+struct ChatChunk {
+    choices: Vec<ChunkChoice>,
+}
+
+#[derive(Deserialize)]
+struct ChunkChoice {
+    delta: ChunkDelta,
+}
+
+#[derive(Deserialize)]
+struct ChunkDelta {
+    content: Option<String>,
+}
+
+/// Text added by one streamed chunk, if it has any
+fn delta_content(data: &str) -> Option<String> {
+    let chunk: ChatChunk = serde_json::from_str(data).ok()?;
+    chunk
+        .choices
+        .into_iter()
+        .next()
+        .and_then(|choice| choice.delta.content)
+        .filter(|content| !content.is_empty())
+}
+
+/// Splits a server-sent event stream into the payloads of its `data:` lines
+/// Network chunks can end mid-line (or mid-character), so the tail is kept for the next one
+#[derive(Default)]
+struct SseDecoder {
+    pending: Vec<u8>,
+}
+
+impl SseDecoder {
+    fn push(&mut self, bytes: &[u8]) -> Vec<String> {
+        self.pending.extend_from_slice(bytes);
+        let mut payloads = Vec::new();
+        while let Some(end) = self.pending.iter().position(|&byte| byte == b'\n') {
+            let line: Vec<u8> = self.pending.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            if let Some(data) = line.trim_end_matches(['\r', '\n']).strip_prefix("data:") {
+                payloads.push(data.strip_prefix(' ').unwrap_or(data).to_string());
+            }
+        }
+        payloads
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sse_lines_split_across_chunks_are_joined() {
+        let mut decoder = SseDecoder::default();
+        let first = r#"data: {"choices":[{"delta":{"content":"Hel"}}]}
+: keep-alive
+
+data: {"choices":[{"delta":{"con"#;
+        let payloads = decoder.push(first.as_bytes());
+        assert_eq!(payloads.len(), 1);
+        assert_eq!(delta_content(&payloads[0]).as_deref(), Some("Hel"));
+
+        let payloads = decoder.push(b"tent\":\"lo\"}}]}\r\ndata: [DONE]\n");
+        assert_eq!(payloads.len(), 2);
+        assert_eq!(delta_content(&payloads[0]).as_deref(), Some("lo"));
+        assert_eq!(payloads[1], "[DONE]");
+        // The role-only first chunk carries no text
+        assert_eq!(
+            delta_content(r#"{"choices":[{"delta":{"role":"assistant"}}]}"#),
+            None
+        );
+    }
+
+    #[test]
+    fn endpoint_is_under_the_base_url() {
+        assert_eq!(
+            endpoint("https://api.openai.com/v1/").unwrap().as_str(),
+            "https://api.openai.com/v1/chat/completions"
+        );
+        assert_eq!(
+            endpoint("http://localhost:11434/v1").unwrap().as_str(),
+            "http://localhost:11434/v1/chat/completions"
+        );
+        assert!(endpoint("ftp://example.com").is_err());
+        assert!(endpoint("not a url").is_err());
+    }
+}
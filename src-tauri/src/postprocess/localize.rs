@@ -0,0 +1,159 @@
+// Numbers and dates rewritten from how the transcription language writes them to how the
+// user's locale does: "1,234.5" and "3/8/2024" in an English transcript become
+// "1.234,5" and "08.03.2024" for de-DE
+// Digits glued to letters or to more separators ("v1.2.3", "10.0.0.1") aren't numbers in
+// that sense and are left alone
+
+use std::sync::LazyLock;
+
+use chrono::NaiveDate;
+use regex::{Captures, Regex};
+
+use crate::locale::{self, Locale};
+
+/// Anything shaped like a numeric date; whether it is one is up to its language's format
+static DATE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\b\d{1,4}[./-]\d{1,2}[./-]\d{1,4}\b").expect("date pattern is valid")
+});
+
+/// Rewrites the numbers and dates of final segments in one language for one locale
+pub struct Localizer {
+    from: Locale,
+    to: Locale,
+    /// None when both write numbers alike
+    numbers: Option<Regex>,
+    dates: bool,
+}
+
+impl Localizer {
+    /// None when there's nothing to rewrite, or `language` isn't one ("multi")
+    pub fn new(language: &str, to: &Locale) -> Option<Self> {
+        let from = Locale::new(&locale::normalize_tag(language)?);
+        let numbers =
+            (from.decimal != to.decimal || from.group != to.group).then(|| number_pattern(&from));
+        let dates = from.date_format != to.date_format;
+        if numbers.is_none() && !dates {
+            return None;
+        }
+        Some(Self {
+            from,
+            to: to.clone(),
+            numbers,
+            dates,
+        })
+    }
+
+    pub fn apply(&self, text: &str) -> String {
+        // Numbers first, so a rewritten date isn't then read as a number
+        let text = match &self.numbers {
+            Some(pattern) => replace_standalone(pattern, text, |number| {
+                Some(
+                    number
+                        .chars()
+                        .map(|c| match c {
+                            c if c == self.from.group => self.to.group,
+                            c if c == self.from.decimal => self.to.decimal,
+                            c => c,
+                        })
+                        .collect(),
+                )
+            }),
+            None => text.to_string(),
+        };
+        if !self.dates {
+            return text;
+        }
+        replace_standalone(&DATE, &text, |date| {
+            NaiveDate::parse_from_str(date, self.from.date_format)
+                .ok()
+                .map(|date| date.format(self.to.date_format).to_string())
+        })
+    }
+}
+
+/// Grouped numbers with an optional fraction, and plain decimals, written the way `from` does
+fn number_pattern(from: &Locale) -> Regex {
+    let group = regex::escape(&from.group.to_string());
+    let decimal = regex::escape(&from.decimal.to_string());
+    Regex::new(&format!(
+        r"\d{{1,3}}(?:{group}\d{{3}})+(?:{decimal}\d+)?|\d+{decimal}\d+"
+    ))
+    .expect("separators are escaped, so the number pattern is valid")
+}
+
+/// Replace the matches `rewrite` accepts that stand on their own
+fn replace_standalone(
+    pattern: &Regex,
+    text: &str,
+    rewrite: impl Fn(&str) -> Option<String>,
+) -> String {
+    pattern
+        .replace_all(text, |captures: &Captures| {
+            let found = captures.get(0).expect("group 0 is the whole match");
+            let standalone =
+                !glued(text[..found.start()].chars().rev()) && !glued(text[found.end()..].chars());
+            standalone
+                .then(|| rewrite(found.as_str()))
+                .flatten()
+                .unwrap_or_else(|| found.as_str().to_string())
+        })
+        .to_string()
+}
+
+/// Whether the characters next to a match, nearest first, continue it: a letter or digit,
+/// or a separator with a digit behind it
+fn glued(mut neighbours: impl Iterator<Item = char>) -> bool {
+    match neighbours.next() {
+        Some(c) if c.is_alphanumeric() => true,
+        Some('.' | ',' | '/' | '-' | '\u{A0}' | '\u{202F}') => {
+            neighbours.next().is_some_and(|c| c.is_ascii_digit())
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRANSCRIPT: &str =
+        "On 3/8/2024 we sold 1,234.5 units at 9.99 each, up 12,000 from v1.2.3.";
+
+    #[test]
+    fn english_transcript_is_written_for_each_locale() {
+        let cases = [
+            ("en-US", None),
+            (
+                "de-DE",
+                Some("On 08.03.2024 we sold 1.234,5 units at 9,99 each, up 12.000 from v1.2.3."),
+            ),
+            (
+                "fr-FR",
+                Some(
+                    "On 08/03/2024 we sold 1\u{202F}234,5 units at 9,99 each, up 12\u{202F}000 from v1.2.3.",
+                ),
+            ),
+        ];
+        for (tag, expected) in cases {
+            let localizer = Localizer::new("en-US", &Locale::new(tag));
+            assert_eq!(
+                localizer
+                    .map(|localizer| localizer.apply(TRANSCRIPT))
+                    .as_deref(),
+                expected,
+                "{}",
+                tag
+            );
+        }
+    }
+
+    #[test]
+    fn separators_next_to_more_digits_are_left_alone() {
+        let localizer = Localizer::new("en", &Locale::new("de-DE")).unwrap();
+        assert_eq!(
+            localizer.apply("ping 10.0.0.1 or 1.2, then 13/13/2024"),
+            "ping 10.0.0.1 or 1,2, then 13/13/2024"
+        );
+        assert!(Localizer::new("multi", &Locale::new("de-DE")).is_none());
+    }
+}
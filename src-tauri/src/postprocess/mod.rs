@@ -0,0 +1,89 @@
+// Text post-processing applied to final transcripts before they reach the frontend
+// Interim results are left alone; rewriting text that is about to change makes it flicker
+// A final segment goes through the passes in a fixed order, see `rewrite_final`
+// `llm` is the exception: an opt-in rewrite of a saved session, only run on request
+// `translation` runs after everything else, and only when a target language is set
+
+pub mod dictation_commands;
+pub mod disfluency;
+pub mod llm;
+pub mod localize;
+pub mod redaction;
+pub mod replacements;
+pub mod snippets;
+pub mod translation;
+
+use dictation_commands::DictationCommands;
+use replacements::Replacements;
+
+use crate::storage::Word;
+
+/// One final segment through every pass but translation, in this order:
+/// 1. snippets, which replace the whole segment and skip the rest of `commands`
+/// 2. dictation commands, which put the punctuation and quotation marks in
+/// 3. disfluency, which can tell a quote from the marks, and sees "comma" as a command
+///    rather than a word
+/// 4. the locale's numbers and dates
+/// 5. replacement rules, whose output is the user's own and so never edited after
+pub fn rewrite_final(
+    commands: &mut DictationCommands,
+    replacements: &Replacements,
+    text: &str,
+    words: &[Word],
+) -> String {
+    replacements.apply(&commands.apply(text, words))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{DictationCommandSettings, DisfluencySettings, ReplacementRule};
+    use crate::locale::Locale;
+    use disfluency::Disfluency;
+
+    #[test]
+    fn passes_run_in_a_fixed_order() {
+        let locale = Locale::new("de-DE");
+        let disfluency = DisfluencySettings {
+            enabled: true,
+            ..DisfluencySettings::default()
+        };
+        let mut commands =
+            DictationCommands::new(&DictationCommandSettings::default(), "en", &locale)
+                .with_disfluency(Disfluency::new(&disfluency, "en", &locale));
+        let replacements = Replacements::compile(&[ReplacementRule {
+            pattern: "xoxo".to_string(),
+            replacement: "hugs hugs".to_string(),
+            ..ReplacementRule::default()
+        }])
+        .unwrap();
+
+        // 100 ms words, with a pause before each quote command marked with '|'
+        let spoken = "Um so |open quote uh I I said |close quote the the total is 1,234.50 xoxo";
+        let mut time = 0;
+        let mut words = Vec::new();
+        for word in spoken.split_whitespace() {
+            if word.starts_with('|') {
+                time += 500;
+            }
+            words.push(Word {
+                text: word.trim_start_matches('|').to_string(),
+                start_ms: time,
+                end_ms: time + 100,
+                confidence: 1.0,
+            });
+            time += 100;
+        }
+        let text = words
+            .iter()
+            .map(|word| word.text.as_str())
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        assert_eq!(
+            rewrite_final(&mut commands, &replacements, &text, &words),
+            "So \u{201E}uh I I said\u{201C} the total is 1.234,50 hugs hugs"
+        );
+        assert_eq!(commands.disfluencies_removed(), Some(2));
+    }
+}
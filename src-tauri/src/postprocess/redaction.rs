@@ -0,0 +1,94 @@
+// Deepgram redaction: the categories we accept, and the placeholders it leaves in transcripts
+// Placeholders such as "[SSN_1]" or "****" must come through post-processing unchanged
+
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+use crate::error::AppError;
+
+/// Values Deepgram accepts for `redact`
+pub const CATEGORIES: &[&str] = &["pci", "pii", "phi", "ssn", "numbers"];
+
+/// Entity labels like "[CREDIT_CARD_1]", or the asterisks of the older redaction modes
+static PLACEHOLDER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\[[A-Z][A-Z0-9_]*\]|\*+").expect("placeholder pattern is valid"));
+
+/// Stand-ins for placeholders while rules run: private-use characters, which no
+/// transcript contains and `\w` doesn't match
+const SENTINEL_BASE: u32 = 0xE000;
+const MAX_SENTINELS: usize = 0x1900;
+
+/// Reject unknown categories, naming the first one
+pub fn validate(categories: &[String]) -> Result<(), AppError> {
+    match categories
+        .iter()
+        .find(|category| !CATEGORIES.contains(&category.as_str()))
+    {
+        Some(unknown) => Err(AppError::InvalidInput(format!(
+            "Unknown redaction category \"{}\"; expected one of {}",
+            unknown,
+            CATEGORIES.join(", ")
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Whether a word is, or contains, a redaction placeholder
+pub fn contains_placeholder(word: &str) -> bool {
+    PLACEHOLDER.is_match(word)
+}
+
+/// Swap each placeholder for a sentinel character, returning the originals in order
+pub fn protect(text: &str) -> (String, Vec<String>) {
+    let mut placeholders = Vec::new();
+    let protected = PLACEHOLDER.replace_all(text, |caps: &regex::Captures<'_>| {
+        if placeholders.len() >= MAX_SENTINELS {
+            return caps[0].to_string();
+        }
+        placeholders.push(caps[0].to_string());
+        sentinel(placeholders.len() - 1).to_string()
+    });
+    (protected.into_owned(), placeholders)
+}
+
+/// Put the placeholders taken out by `protect` back
+pub fn restore(text: &str, placeholders: &[String]) -> String {
+    if placeholders.is_empty() {
+        return text.to_string();
+    }
+    text.chars()
+        .fold(String::with_capacity(text.len()), |mut out, c| {
+            let index = (c as u32).wrapping_sub(SENTINEL_BASE) as usize;
+            match placeholders.get(index) {
+                Some(placeholder) => out.push_str(placeholder),
+                None => out.push(c),
+            }
+            out
+        })
+}
+
+fn sentinel(index: usize) -> char {
+    char::from_u32(SENTINEL_BASE + index as u32).expect("sentinels stay in the private-use area")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_unknown_categories_by_name() {
+        assert!(validate(&["pci".to_string(), "ssn".to_string()]).is_ok());
+        let err = validate(&["pci".to_string(), "emails".to_string()]).unwrap_err();
+        assert!(err.to_string().contains("\"emails\""), "{}", err);
+    }
+
+    #[test]
+    fn protect_and_restore_round_trip() {
+        let text = "card [CREDIT_CARD_1] and pin ****.";
+        let (protected, placeholders) = protect(text);
+        assert_eq!(placeholders, ["[CREDIT_CARD_1]", "****"]);
+        assert!(!protected.contains('[') && !protected.contains('*'));
+        assert_eq!(restore(&protected, &placeholders), text);
+    }
+}
@@ -0,0 +1,260 @@
+// Find-and-replace dictionary for final transcripts (e.g. "open paren" → "(")
+// Rules are compiled once when saved and swapped in atomically for the transcript path
+
+use std::sync::{Arc, RwLock};
+
+use regex::{NoExpand, Regex, RegexBuilder};
+use tauri::{AppHandle, Manager, State};
+
+use super::redaction;
+use crate::config::ReplacementRule;
+use crate::error::AppError;
+use crate::state;
+
+/// A rule ready to run
+struct CompiledRule {
+    regex: Regex,
+    replacement: String,
+    /// Regex rules expand `$1`; literal replacements are inserted as-is
+    expand: bool,
+}
+
+/// Compiled rules, applied in order
+#[derive(Default)]
+pub struct Replacements {
+    rules: Vec<CompiledRule>,
+}
+
+impl Replacements {
+    /// Compile every rule, failing on the first invalid one
+    pub fn compile(rules: &[ReplacementRule]) -> Result<Self, AppError> {
+        let rules = rules
+            .iter()
+            .enumerate()
+            .map(|(index, rule)| compile_rule(rule).map_err(|e| rule_error(index, rule, e)))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { rules })
+    }
+
+    /// Apply each rule to the output of the previous one
+    /// Redaction placeholders are hidden from the rules and come out unchanged
+    pub fn apply(&self, text: &str) -> String {
+        if self.rules.is_empty() {
+            return text.to_string();
+        }
+        let (mut text, placeholders) = redaction::protect(text);
+        for rule in &self.rules {
+            let replaced = if rule.expand {
+                rule.regex.replace_all(&text, rule.replacement.as_str())
+            } else {
+                rule.regex.replace_all(&text, NoExpand(&rule.replacement))
+            };
+            text = replaced.into_owned();
+        }
+        redaction::restore(&text, &placeholders)
+    }
+}
+
+fn compile_rule(rule: &ReplacementRule) -> Result<CompiledRule, regex::Error> {
+    if rule.pattern.is_empty() {
+        return Err(regex::Error::Syntax(
+            "pattern must not be empty".to_string(),
+        ));
+    }
+    let pattern = if rule.is_regex {
+        rule.pattern.clone()
+    } else {
+        regex::escape(&rule.pattern)
+    };
+    let pattern = if rule.whole_word {
+        format!(r"\b(?:{})\b", pattern)
+    } else {
+        pattern
+    };
+    Ok(CompiledRule {
+        regex: RegexBuilder::new(&pattern)
+            .case_insensitive(!rule.case_sensitive)
+            .build()?,
+        replacement: rule.replacement.clone(),
+        expand: rule.is_regex,
+    })
+}
+
+/// Describe a rule that failed to compile, with the column of the syntax error if known
+fn rule_error(index: usize, rule: &ReplacementRule, error: regex::Error) -> AppError {
+    // Parse the user's pattern on its own so the column isn't offset by our wrapping
+    let position = rule
+        .is_regex
+        .then(|| match regex_syntax::Parser::new().parse(&rule.pattern) {
+            Err(regex_syntax::Error::Parse(e)) => {
+                Some((e.span().start.column, e.kind().to_string()))
+            }
+            Err(regex_syntax::Error::Translate(e)) => {
+                Some((e.span().start.column, e.kind().to_string()))
+            }
+            _ => None,
+        })
+        .flatten();
+    let message = match position {
+        Some((column, kind)) => format!(
+            "Rule {} (\"{}\"): invalid regex at column {}: {}",
+            index + 1,
+            rule.pattern,
+            column,
+            kind
+        ),
+        None => format!("Rule {} (\"{}\"): {}", index + 1, rule.pattern, error),
+    };
+    AppError::InvalidInput(message)
+}
+
+/// Managed handle to the compiled rules
+#[derive(Default)]
+pub struct ReplacementState {
+    current: RwLock<Arc<Replacements>>,
+}
+
+impl ReplacementState {
+    /// The rules in effect; cheap to call per transcript
+    pub fn current(&self) -> Arc<Replacements> {
+        self.current
+            .read()
+            .map(|current| Arc::clone(&current))
+            .unwrap_or_default()
+    }
+
+    fn replace(&self, replacements: Replacements) {
+        if let Ok(mut current) = self.current.write() {
+            *current = Arc::new(replacements);
+        }
+    }
+}
+
+/// Compile the saved rules; a hand-edited file with a bad rule disables replacements
+pub fn init(app: &AppHandle) -> ReplacementState {
+    let state = ReplacementState::default();
+    let rules = crate::config::load(app)
+        .map(|config| config.replacements.rules)
+        .unwrap_or_default();
+    match Replacements::compile(&rules) {
+        Ok(replacements) => state.replace(replacements),
+        Err(e) => tracing::error!("{}; transcript replacements are disabled", e),
+    }
+    state
+}
+
+/// Command to read the replacement rules
+#[tauri::command]
+pub async fn get_replacements(app: AppHandle) -> Result<Vec<ReplacementRule>, AppError> {
+    state::blocking(move || crate::config::load(&app).map(|config| config.replacements.rules)).await
+}
+
+/// Command to replace the rules; nothing is saved if any rule is invalid
+#[tauri::command]
+pub async fn set_replacements(
+    app: AppHandle,
+    rules: Vec<ReplacementRule>,
+) -> Result<Vec<ReplacementRule>, AppError> {
+    let compiled = Replacements::compile(&rules)?;
+    state::blocking(move || {
+        crate::config::update(&app, |config| {
+            config.replacements.rules = rules.clone();
+            Ok(())
+        })?;
+        app.state::<ReplacementState>().replace(compiled);
+        Ok(rules)
+    })
+    .await
+}
+
+/// Command to preview the rules on sample text
+/// Uses `rules` when given (unsaved edits), otherwise the saved rules
+#[tauri::command]
+pub fn test_replacements(
+    state: State<'_, ReplacementState>,
+    sample: String,
+    rules: Option<Vec<ReplacementRule>>,
+) -> Result<String, AppError> {
+    match rules {
+        Some(rules) => Ok(Replacements::compile(&rules)?.apply(&sample)),
+        None => Ok(state.current().apply(&sample)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(pattern: &str, replacement: &str) -> ReplacementRule {
+        ReplacementRule {
+            pattern: pattern.to_string(),
+            replacement: replacement.to_string(),
+            ..ReplacementRule::default()
+        }
+    }
+
+    #[test]
+    fn literal_rules_match_whole_words_case_insensitively() {
+        let replacements =
+            Replacements::compile(&[rule("subspace", "SubSpace"), rule("open paren", "(")])
+                .unwrap();
+        assert_eq!(
+            replacements.apply("Subspace is subspaces, open paren ok"),
+            "SubSpace is subspaces, ( ok"
+        );
+    }
+
+    #[test]
+    fn regex_rules_expand_groups_and_run_in_order() {
+        let replacements = Replacements::compile(&[
+            ReplacementRule {
+                is_regex: true,
+                ..rule(r"(\d+) percent", "$1%")
+            },
+            ReplacementRule {
+                whole_word: false,
+                case_sensitive: true,
+                ..rule("%", " pct")
+            },
+        ])
+        .unwrap();
+        assert_eq!(replacements.apply("50 percent done"), "50 pct done");
+    }
+
+    #[test]
+    fn redaction_placeholders_are_left_alone() {
+        let replacements = Replacements::compile(&[
+            rule("ssn", "social security number"),
+            ReplacementRule {
+                is_regex: true,
+                ..rule(r"\w+_1", "x")
+            },
+        ])
+        .unwrap();
+        assert_eq!(
+            replacements.apply("my ssn is [SSN_1] ok"),
+            "my social security number is [SSN_1] ok"
+        );
+    }
+
+    #[test]
+    fn invalid_regex_reports_column() {
+        let err = Replacements::compile(&[
+            rule("fine", "ok"),
+            ReplacementRule {
+                is_regex: true,
+                ..rule("ab(c", "x")
+            },
+        ])
+        .err()
+        .unwrap();
+        let AppError::InvalidInput(message) = err else {
+            panic!("expected InvalidInput, got {:?}", err);
+        };
+        assert!(
+            message.starts_with("Rule 2 (\"ab(c\"): invalid regex at column 3"),
+            "{}",
+            message
+        );
+    }
+}
@@ -0,0 +1,256 @@
+// Templates inserted by voice ("my email signature", "insert meeting header")
+// A final segment that is nothing but a snippet's trigger, or "insert" and its name, is
+// replaced by the snippet's body with the date and time filled in, written the locale's
+// way. A way to insert a
+// snippet can't also be a dictation command's phrase, so neither shadows the other
+
+use chrono::{Local, NaiveDateTime};
+use tauri::AppHandle;
+
+use super::dictation_commands;
+use crate::config::{DictationCommandSettings, Snippet};
+use crate::error::AppError;
+use crate::locale::Locale;
+use crate::state;
+
+/// Said before a snippet's name to insert it
+const INSERT_WORD: &str = "insert";
+
+/// A segment the way triggers are compared: lowercase words without punctuation
+fn words(text: &str) -> Vec<String> {
+    text.split_whitespace()
+        .map(dictation_commands::normalize)
+        .filter(|word| !word.is_empty())
+        .collect()
+}
+
+/// The phrases that insert `snippet`: its trigger, if it has one, and "insert <name>"
+fn phrases(snippet: &Snippet) -> Vec<Vec<String>> {
+    let insert = std::iter::once(INSERT_WORD.to_string())
+        .chain(words(&snippet.name))
+        .collect();
+    let trigger = words(&snippet.trigger);
+    if trigger.is_empty() {
+        vec![insert]
+    } else {
+        vec![trigger, insert]
+    }
+}
+
+struct Trigger {
+    words: Vec<String>,
+    body: String,
+}
+
+/// Snippets ready to be matched against final segments
+#[derive(Default)]
+pub struct Snippets {
+    triggers: Vec<Trigger>,
+    locale: Locale,
+}
+
+impl Snippets {
+    pub fn new(snippets: &[Snippet], locale: &Locale) -> Self {
+        Self {
+            triggers: snippets
+                .iter()
+                .flat_map(|snippet| {
+                    phrases(snippet).into_iter().map(|words| Trigger {
+                        words,
+                        body: snippet.body.clone(),
+                    })
+                })
+                .collect(),
+            locale: locale.clone(),
+        }
+    }
+
+    /// The filled-in body of the snippet `text` inserts, if it inserts one
+    pub fn expand(&self, text: &str) -> Option<String> {
+        if self.triggers.is_empty() {
+            return None;
+        }
+        let words = words(text);
+        let trigger = self
+            .triggers
+            .iter()
+            .find(|trigger| trigger.words == words)?;
+        Some(render(
+            &trigger.body,
+            Local::now().naive_local(),
+            &self.locale,
+        ))
+    }
+}
+
+/// Fill in the placeholders; anything else in braces is left as written
+fn render(body: &str, now: NaiveDateTime, locale: &Locale) -> String {
+    body.replace("{date}", &now.format(locale.date_format).to_string())
+        .replace("{time}", &now.format(locale.time_format).to_string())
+        .replace("{weekday}", &now.format("%A").to_string())
+}
+
+/// Why one of `snippets` can't be inserted with `commands` in effect: a way to insert it
+/// is also a command phrase in some language. None when they don't collide
+pub fn collision(snippets: &[Snippet], commands: &DictationCommandSettings) -> Option<String> {
+    let languages = dictation_commands::BUILTIN_LANGUAGES
+        .iter()
+        .copied()
+        .chain(commands.languages.keys().map(String::as_str));
+    let command_phrases: Vec<(Vec<String>, String)> = languages
+        .flat_map(|language| dictation_commands::table(commands, language))
+        .map(|command| command.phrase)
+        .chain(commands.revoke_phrases.iter().cloned())
+        .map(|phrase| (words(&phrase), phrase))
+        .collect();
+    snippets.iter().find_map(|snippet| {
+        phrases(snippet).into_iter().find_map(|words| {
+            command_phrases
+                .iter()
+                .find(|(command, _)| *command == words)
+                .map(|(_, phrase)| {
+                    format!(
+                        "\"{}\" is both a dictation command and the phrase for snippet \"{}\"",
+                        phrase, snippet.name
+                    )
+                })
+        })
+    })
+}
+
+/// Command to list the snippets
+#[tauri::command]
+pub async fn list_snippets(app: AppHandle) -> Result<Vec<Snippet>, AppError> {
+    state::blocking(move || Ok(crate::config::load(&app)?.snippets)).await
+}
+
+/// Command to add a snippet, or replace the one with the same name
+/// Its trigger and "insert <name>" mustn't already be a dictation command or insert
+/// another snippet. Returns all snippets
+#[tauri::command]
+pub async fn upsert_snippet(
+    app: AppHandle,
+    mut snippet: Snippet,
+) -> Result<Vec<Snippet>, AppError> {
+    snippet.name = snippet.name.trim().to_string();
+    snippet.trigger = snippet.trigger.trim().to_string();
+    if words(&snippet.name).is_empty() {
+        return Err(AppError::InvalidInput(
+            "A snippet needs a name with letters or digits".to_string(),
+        ));
+    }
+    if snippet.body.is_empty() {
+        return Err(AppError::InvalidInput(
+            "A snippet needs a body to insert".to_string(),
+        ));
+    }
+    state::blocking(move || {
+        crate::config::update(&app, |config| {
+            if let Some(collision) =
+                collision(std::slice::from_ref(&snippet), &config.dictation_commands)
+            {
+                return Err(AppError::InvalidInput(collision));
+            }
+            let new_phrases = phrases(&snippet);
+            if let Some(other) = config.snippets.iter().find(|other| {
+                !other.name.eq_ignore_ascii_case(&snippet.name)
+                    && phrases(other)
+                        .iter()
+                        .any(|phrase| new_phrases.contains(phrase))
+            }) {
+                return Err(AppError::InvalidInput(format!(
+                    "Snippet \"{}\" is already inserted with the same phrase",
+                    other.name
+                )));
+            }
+            match config
+                .snippets
+                .iter_mut()
+                .find(|existing| existing.name.eq_ignore_ascii_case(&snippet.name))
+            {
+                Some(existing) => *existing = snippet,
+                None => config.snippets.push(snippet),
+            }
+            Ok(config.snippets.clone())
+        })
+    })
+    .await
+}
+
+/// Command to remove the snippet called `name`, returning the remaining ones
+#[tauri::command]
+pub async fn delete_snippet(app: AppHandle, name: String) -> Result<Vec<Snippet>, AppError> {
+    state::blocking(move || {
+        crate::config::update(&app, |config| {
+            config
+                .snippets
+                .retain(|snippet| !snippet.name.eq_ignore_ascii_case(name.trim()));
+            Ok(config.snippets.clone())
+        })
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snippet(name: &str, trigger: &str, body: &str) -> Snippet {
+        Snippet {
+            name: name.to_string(),
+            trigger: trigger.to_string(),
+            body: body.to_string(),
+            ..Snippet::default()
+        }
+    }
+
+    #[test]
+    fn whole_segments_insert_snippets_by_trigger_or_name() {
+        let snippets = Snippets::new(
+            &[snippet(
+                "signature",
+                "my email signature",
+                "Best regards,\nSam",
+            )],
+            &Locale::default(),
+        );
+        assert_eq!(
+            snippets.expand("My email signature.").as_deref(),
+            Some("Best regards,\nSam")
+        );
+        assert_eq!(
+            snippets.expand("Insert signature").as_deref(),
+            Some("Best regards,\nSam")
+        );
+        assert_eq!(snippets.expand("about my email signature"), None);
+    }
+
+    #[test]
+    fn placeholders_are_filled_in_for_the_locale() {
+        let now = NaiveDateTime::parse_from_str("2024-03-08 14:05", "%Y-%m-%d %H:%M").unwrap();
+        let cases = [
+            ("en-US", "Meeting Friday 03/08/2024 at 2:05 PM {room}"),
+            ("de-DE", "Meeting Friday 08.03.2024 at 14:05 {room}"),
+            ("fr-FR", "Meeting Friday 08/03/2024 at 14:05 {room}"),
+        ];
+        for (tag, expected) in cases {
+            assert_eq!(
+                render(
+                    "Meeting {weekday} {date} at {time} {room}",
+                    now,
+                    &Locale::new(tag)
+                ),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn triggers_may_not_be_command_phrases() {
+        let commands = DictationCommandSettings::default();
+        assert!(collision(&[snippet("header", "New Paragraph", "x")], &commands).is_some());
+        assert!(collision(&[snippet("komma", "", "x")], &commands).is_none());
+        assert!(collision(&[snippet("header", "meeting header", "x")], &commands).is_none());
+        assert!(collision(&[snippet("undo", "Scratch that", "x")], &commands).is_some());
+    }
+}
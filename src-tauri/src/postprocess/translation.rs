@@ -0,0 +1,610 @@
+// Translation of final transcripts into another language, through DeepL, Google or any
+// OpenAI-compatible endpoint
+// Live sessions hand their committed segments to a `LiveTranslation`, which puts segments
+// said in quick succession into one request and sends their committed events once they're
+// translated. Translation fails open: when a request fails, the original text goes out
+// flagged as untranslated and dictation carries on
+
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tauri::async_runtime::JoinHandle;
+use tauri::{AppHandle, Manager};
+use tokio::sync::mpsc;
+
+use super::{dictation_commands, llm};
+use crate::config::{self, TranslationProvider, TranslationSettings};
+use crate::error::AppError;
+use crate::key_store;
+use crate::secrets;
+use crate::state::{blocking, AppState};
+use crate::storage::Segment;
+use crate::transcript::assembler::CommittedSegment;
+use crate::transcript::output::Outputs;
+
+/// Most characters sent in one request, well under every provider's limit
+const MAX_BATCH_CHARS: usize = 2_000;
+/// Most segments sent in one request
+const MAX_BATCH_SEGMENTS: usize = 25;
+/// How long a live segment waits for others to share its request
+const BATCH_WINDOW: Duration = Duration::from_millis(500);
+/// Longest wait for one translation request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+/// How long the end of a session waits for translations still in flight
+const FINISH_TIMEOUT: Duration = Duration::from_secs(20);
+
+const DEEPL_URL: &str = "https://api.deepl.com/v2/translate";
+/// Keys of DeepL's free plan end in ":fx" and only work on this host
+const DEEPL_FREE_URL: &str = "https://api-free.deepl.com/v2/translate";
+const GOOGLE_URL: &str = "https://translation.googleapis.com/language/translate/v2";
+
+/// The translation settings as shown in settings
+#[derive(Debug, Clone, Serialize)]
+pub struct TranslationStatus {
+    pub target_language: Option<String>,
+    pub provider: TranslationProvider,
+    pub base_url: String,
+    pub model: String,
+    pub key_configured: bool,
+}
+
+#[tauri::command]
+pub async fn get_translation_settings(app: AppHandle) -> Result<TranslationStatus, AppError> {
+    blocking(move || {
+        let settings = config::load(&app)?.translation;
+        Ok(status(&app, settings))
+    })
+    .await
+}
+
+/// Choose the provider and target language; no `target_language` turns translation off
+/// `base_url` and `model` are for the OpenAI-compatible provider and kept when not given
+#[tauri::command]
+pub async fn set_translation_settings(
+    app: AppHandle,
+    target_language: Option<String>,
+    provider: TranslationProvider,
+    base_url: Option<String>,
+    model: Option<String>,
+) -> Result<TranslationStatus, AppError> {
+    let target_language = target_language
+        .map(|code| normalize_language(&code))
+        .transpose()?
+        .flatten();
+    let base_url = base_url.map(|url| url.trim().trim_end_matches('/').to_string());
+    if let Some(base_url) = base_url.as_deref().filter(|url| !url.is_empty()) {
+        llm::endpoint(base_url)?;
+    }
+    let model = model.map(|model| model.trim().to_string());
+    if model.as_deref().is_some_and(str::is_empty) {
+        return Err(AppError::InvalidInput(
+            "Model must not be empty".to_string(),
+        ));
+    }
+    blocking(move || {
+        let settings = config::update(&app, |config| {
+            let settings = &mut config.translation;
+            settings.target_language = target_language;
+            settings.provider = provider;
+            if let Some(base_url) = base_url {
+                settings.base_url = base_url;
+            }
+            if let Some(model) = model {
+                settings.model = model;
+            }
+            if settings.provider == TranslationProvider::OpenaiCompatible
+                && settings.target_language.is_some()
+                && settings.base_url.is_empty()
+            {
+                return Err(AppError::Config(
+                    "The OpenAI-compatible translation provider needs a base URL".to_string(),
+                ));
+            }
+            Ok(settings.clone())
+        })?;
+        Ok(status(&app, settings))
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn set_translation_api_key(app: AppHandle, key: String) -> Result<(), AppError> {
+    let key = key_store::normalize_key(&key)?;
+    blocking(move || secrets::save_translation_api_key(&app, &key).map(|_| ())).await
+}
+
+#[tauri::command]
+pub async fn clear_translation_api_key(app: AppHandle) -> Result<(), AppError> {
+    blocking(move || secrets::delete_translation_api_key(&app)).await
+}
+
+fn status(app: &AppHandle, settings: TranslationSettings) -> TranslationStatus {
+    TranslationStatus {
+        target_language: settings.target_language,
+        provider: settings.provider,
+        base_url: settings.base_url,
+        model: settings.model,
+        key_configured: secrets::load_translation_api_key(app).is_ok_and(|key| key.is_some()),
+    }
+}
+
+/// A configured provider, ready to translate
+pub struct Translator {
+    http: reqwest::Client,
+    provider: TranslationProvider,
+    endpoint: url::Url,
+    model: String,
+    target_language: String,
+    api_key: Option<String>,
+}
+
+impl Translator {
+    /// The configured translator, or None while translation is off
+    /// Blocks on reading the config and the keychain
+    pub fn load(app: &AppHandle) -> Result<Option<Self>, AppError> {
+        let settings = config::load(app)?.translation;
+        if settings.target_language.is_none() {
+            return Ok(None);
+        }
+        Self::new(app, settings).map(Some)
+    }
+
+    fn new(app: &AppHandle, settings: TranslationSettings) -> Result<Self, AppError> {
+        let target_language = settings.target_language.ok_or_else(|| {
+            AppError::Config("No target language is set for translation".to_string())
+        })?;
+        let api_key = secrets::load_translation_api_key(app)?;
+        let missing_key = || {
+            AppError::Config(format!(
+                "Translation through {} needs an API key",
+                provider_name(settings.provider)
+            ))
+        };
+        let endpoint = match settings.provider {
+            TranslationProvider::Deepl => {
+                let key = api_key.as_deref().ok_or_else(missing_key)?;
+                let url = if key.ends_with(":fx") {
+                    DEEPL_FREE_URL
+                } else {
+                    DEEPL_URL
+                };
+                url::Url::parse(url).map_err(|e| AppError::Internal(e.to_string()))?
+            }
+            TranslationProvider::Google => {
+                api_key.as_deref().ok_or_else(missing_key)?;
+                url::Url::parse(GOOGLE_URL).map_err(|e| AppError::Internal(e.to_string()))?
+            }
+            TranslationProvider::OpenaiCompatible => {
+                if settings.base_url.trim().is_empty() {
+                    return Err(AppError::Config(
+                        "The OpenAI-compatible translation provider needs a base URL".to_string(),
+                    ));
+                }
+                llm::endpoint(settings.base_url.trim())?
+            }
+        };
+        Ok(Self {
+            http: app.state::<AppState>().http(),
+            provider: settings.provider,
+            endpoint,
+            model: settings.model,
+            target_language,
+            api_key,
+        })
+    }
+
+    pub fn target_language(&self) -> &str {
+        &self.target_language
+    }
+
+    /// Translate `texts` with one request, returning their translations in order
+    pub async fn translate(&self, texts: &[&str]) -> Result<Vec<String>, AppError> {
+        let builder = self
+            .http
+            .post(self.endpoint.as_str())
+            .timeout(REQUEST_TIMEOUT);
+        let api_key = self.api_key.as_deref().unwrap_or_default();
+        let translations = match self.provider {
+            TranslationProvider::Deepl => {
+                let response = builder
+                    .header("Authorization", format!("DeepL-Auth-Key {}", api_key))
+                    .json(&DeeplRequest {
+                        text: texts,
+                        target_lang: deepl_target(&self.target_language),
+                    })
+                    .send()
+                    .await?;
+                check(self.provider, response)
+                    .await?
+                    .json::<DeeplResponse>()
+                    .await?
+                    .translations
+                    .into_iter()
+                    .map(|translation| translation.text)
+                    .collect()
+            }
+            TranslationProvider::Google => {
+                // In a header rather than the query, so it can't end up in an error message
+                let response = builder
+                    .header("X-Goog-Api-Key", api_key)
+                    .json(&GoogleRequest {
+                        q: texts,
+                        target: &self.target_language,
+                        format: "text",
+                    })
+                    .send()
+                    .await?;
+                check(self.provider, response)
+                    .await?
+                    .json::<GoogleResponse>()
+                    .await?
+                    .data
+                    .translations
+                    .into_iter()
+                    .map(|translation| translation.translated_text)
+                    .collect()
+            }
+            TranslationProvider::OpenaiCompatible => {
+                let input = serde_json::to_string(texts)
+                    .map_err(|e| AppError::Internal(format!("Failed to encode segments: {}", e)))?;
+                let reply = llm::complete(
+                    builder,
+                    self.api_key.as_deref(),
+                    &self.model,
+                    &llm_instructions(&self.target_language),
+                    &input,
+                )
+                .await?;
+                parse_array(&reply).ok_or_else(|| {
+                    AppError::network("The LLM endpoint didn't reply with a list of translations")
+                })?
+            }
+        };
+        if translations.len() != texts.len() {
+            return Err(AppError::network(format!(
+                "{} returned {} translations for {} segments",
+                provider_name(self.provider),
+                translations.len(),
+                texts.len()
+            )));
+        }
+        Ok(translations)
+    }
+
+    /// Translate `texts` a batch at a time; the texts of a batch that failed are None
+    pub async fn translate_all(&self, texts: &[&str]) -> Vec<Option<String>> {
+        let mut translated = Vec::with_capacity(texts.len());
+        for range in batches(texts) {
+            match self.translate(&texts[range.clone()]).await {
+                Ok(batch) => translated.extend(batch.into_iter().map(Some)),
+                Err(e) => {
+                    tracing::warn!(
+                        "Translating {} segments failed, keeping the original text: {}",
+                        range.len(),
+                        e
+                    );
+                    translated.extend(range.map(|_| None));
+                }
+            }
+        }
+        translated
+    }
+
+    /// Fill in the `translated` text of each segment that could be translated
+    pub async fn translate_segments(&self, segments: &mut [Segment]) {
+        let texts: Vec<&str> = segments
+            .iter()
+            .map(|segment| segment.text.as_str())
+            .collect();
+        let translated = self.translate_all(&texts).await;
+        for (segment, translated) in segments.iter_mut().zip(translated) {
+            segment.translated = translated;
+        }
+    }
+}
+
+/// Translation of one live session's committed segments, on a task of its own
+pub struct LiveTranslation {
+    target_language: String,
+    segment_tx: mpsc::UnboundedSender<Vec<CommittedSegment>>,
+    task: JoinHandle<HashMap<u64, String>>,
+}
+
+impl LiveTranslation {
+    /// Start translating for a new session, or None while translation is off
+    /// Translated segments go on to `outputs`
+    pub fn start(app: &AppHandle, outputs: Arc<Outputs>) -> Option<Self> {
+        let settings = config::load(app).ok()?.translation;
+        let target_language = settings.target_language.clone()?;
+        let (segment_tx, segment_rx) = mpsc::unbounded_channel();
+        let task =
+            tauri::async_runtime::spawn(translate_live(app.clone(), settings, outputs, segment_rx));
+        Some(Self {
+            target_language,
+            segment_tx,
+            task,
+        })
+    }
+
+    pub fn target_language(&self) -> &str {
+        &self.target_language
+    }
+
+    /// Queue committed segments; their events go out once they're translated
+    pub fn push(&self, segments: Vec<CommittedSegment>) {
+        let _ = self.segment_tx.send(segments);
+    }
+
+    /// Wait for the queued segments, returning the translations by segment id
+    /// Segments that couldn't be translated are missing
+    pub async fn finish(self) -> HashMap<u64, String> {
+        drop(self.segment_tx);
+        match tokio::time::timeout(FINISH_TIMEOUT, self.task).await {
+            Ok(Ok(translations)) => translations,
+            Ok(Err(e)) => {
+                tracing::error!("Translation task failed: {}", e);
+                HashMap::new()
+            }
+            Err(_) => {
+                tracing::warn!("Translations still pending at the end of the session are lost");
+                HashMap::new()
+            }
+        }
+    }
+}
+
+async fn translate_live(
+    app: AppHandle,
+    settings: TranslationSettings,
+    outputs: Arc<Outputs>,
+    mut segment_rx: mpsc::UnboundedReceiver<Vec<CommittedSegment>>,
+) -> HashMap<u64, String> {
+    let handle = app.clone();
+    let translator = match blocking(move || Translator::new(&handle, settings)).await {
+        Ok(translator) => Some(translator),
+        Err(e) => {
+            tracing::warn!(
+                "Translation unavailable, sending transcripts untranslated: {}",
+                e
+            );
+            None
+        }
+    };
+
+    let mut translations = HashMap::new();
+    while let Some(mut batch) = segment_rx.recv().await {
+        // Segments said in quick succession share a request
+        let deadline = tokio::time::Instant::now() + BATCH_WINDOW;
+        while batch.len() < MAX_BATCH_SEGMENTS
+            && batch.iter().map(|s| s.text.chars().count()).sum::<usize>() < MAX_BATCH_CHARS
+        {
+            match tokio::time::timeout_at(deadline, segment_rx.recv()).await {
+                Ok(Some(more)) => batch.extend(more),
+                _ => break,
+            }
+        }
+        let texts: Vec<&str> = batch.iter().map(|segment| segment.text.as_str()).collect();
+        let translated = match &translator {
+            Some(translator) => translator.translate_all(&texts).await,
+            None => vec![None; texts.len()],
+        };
+        for (mut segment, translated) in batch.into_iter().zip(translated) {
+            match translated {
+                Some(text) => {
+                    translations.insert(segment.id, text.clone());
+                    segment.translated = Some(text);
+                }
+                None => segment.translation_failed = true,
+            }
+            outputs.committed(segment);
+        }
+    }
+    translations
+}
+
+/// The translated transcript of `segments`, or None if none of them was translated
+/// Segments that weren't keep their original text
+pub fn translated_text(segments: &[Segment]) -> Option<String> {
+    if segments.iter().all(|segment| segment.translated.is_none()) {
+        return None;
+    }
+    Some(dictation_commands::join(segments.iter().map(|segment| {
+        segment.translated.as_deref().unwrap_or(&segment.text)
+    })))
+}
+
+/// Split consecutive texts into requests of at most `MAX_BATCH_SEGMENTS` texts and
+/// `MAX_BATCH_CHARS` characters; a longer text is sent on its own
+fn batches(texts: &[&str]) -> Vec<Range<usize>> {
+    let mut batches = Vec::new();
+    let (mut start, mut chars) = (0, 0);
+    for (index, text) in texts.iter().enumerate() {
+        let len = text.chars().count();
+        if index > start && (chars + len > MAX_BATCH_CHARS || index - start >= MAX_BATCH_SEGMENTS) {
+            batches.push(start..index);
+            start = index;
+            chars = 0;
+        }
+        chars += len;
+    }
+    if start < texts.len() {
+        batches.push(start..texts.len());
+    }
+    batches
+}
+
+/// A language code as stored, e.g. "en-GB"; an empty one means no translation
+fn normalize_language(code: &str) -> Result<Option<String>, AppError> {
+    let code = code.trim();
+    if code.is_empty() {
+        return Ok(None);
+    }
+    let valid = (2..=12).contains(&code.len())
+        && code.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        && code.chars().next().is_some_and(|c| c.is_ascii_alphabetic());
+    if !valid {
+        return Err(AppError::InvalidInput(format!(
+            "\"{}\" is not a language code; use one like \"en\" or \"pt-BR\"",
+            code
+        )));
+    }
+    Ok(Some(code.to_string()))
+}
+
+/// DeepL wants uppercase codes, and a variant for English and Portuguese
+fn deepl_target(code: &str) -> String {
+    match code.to_ascii_uppercase().as_str() {
+        "EN" => "EN-US".to_string(),
+        "PT" => "PT-PT".to_string(),
+        code => code.to_string(),
+    }
+}
+
+fn provider_name(provider: TranslationProvider) -> &'static str {
+    match provider {
+        TranslationProvider::Deepl => "DeepL",
+        TranslationProvider::Google => "Google Translate",
+        TranslationProvider::OpenaiCompatible => "The LLM endpoint",
+    }
+}
+
+fn llm_instructions(target_language: &str) -> String {
+    format!(
+        "Translate each string in the JSON array you are given into the language with the code \"{}\". Keep the meaning, tone and punctuation, and translate nothing else. Reply with a JSON array of the translations only, one for each string and in the same order.",
+        target_language
+    )
+}
+
+/// The JSON array of strings in an LLM reply, which may be wrapped in a code fence
+fn parse_array(reply: &str) -> Option<Vec<String>> {
+    let start = reply.find('[')?;
+    let end = reply.rfind(']')?;
+    serde_json::from_str(reply.get(start..=end)?).ok()
+}
+
+/// The response, if its status is a success
+async fn check(
+    provider: TranslationProvider,
+    response: reqwest::Response,
+) -> Result<reqwest::Response, AppError> {
+    let status = response.status().as_u16();
+    match status {
+        200..=299 => Ok(response),
+        401 | 403 => Err(AppError::KeyInvalid(format!(
+            "{} rejected the translation API key",
+            provider_name(provider)
+        ))),
+        _ => {
+            let detail = response.text().await.unwrap_or_default();
+            Err(AppError::Network {
+                status: Some(status),
+                message: format!(
+                    "{} returned HTTP {}: {}",
+                    provider_name(provider),
+                    status,
+                    detail.trim()
+                ),
+            })
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct DeeplRequest<'a> {
+    text: &'a [&'a str],
+    target_lang: String,
+}
+
+#[derive(Deserialize)]
+struct DeeplResponse {
+    translations: Vec<DeeplTranslation>,
+}
+
+#[derive(Deserialize)]
+struct DeeplTranslation {
+    text: String,
+}
+
+#[derive(Serialize)]
+struct GoogleRequest<'a> {
+    q: &'a [&'a str],
+    target: &'a str,
+    /// Plain text, so nothing comes back HTML-escaped
+    format: &'static str,
+}
+
+#[derive(Deserialize)]
+struct GoogleResponse {
+    data: GoogleData,
+}
+
+#[derive(Deserialize)]
+struct GoogleData {
+    translations: Vec<GoogleTranslation>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GoogleTranslation {
+    translated_text: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_segments_are_batched_and_long_ones_sent_alone() {
+        let long = "x".repeat(MAX_BATCH_CHARS + 10);
+        assert_eq!(
+            batches(&["a", "b", &long, "c", "d"]),
+            vec![0..2, 2..3, 3..5]
+        );
+        let many = vec!["word"; MAX_BATCH_SEGMENTS + 3];
+        assert_eq!(
+            batches(&many),
+            vec![
+                0..MAX_BATCH_SEGMENTS,
+                MAX_BATCH_SEGMENTS..MAX_BATCH_SEGMENTS + 3
+            ]
+        );
+        assert!(batches(&[]).is_empty());
+
+        assert_eq!(
+            parse_array("```json\n[\"Hello.\", \"How are you?\"]\n```"),
+            Some(vec!["Hello.".to_string(), "How are you?".to_string()])
+        );
+        assert_eq!(parse_array("Sorry, I can't."), None);
+        assert_eq!(deepl_target("en"), "EN-US");
+        assert_eq!(deepl_target("en-GB"), "EN-GB");
+        assert_eq!(deepl_target("hi"), "HI");
+        assert_eq!(
+            normalize_language(" pt-BR ").unwrap().as_deref(),
+            Some("pt-BR")
+        );
+        assert_eq!(normalize_language("").unwrap(), None);
+        assert!(normalize_language("english please").is_err());
+    }
+
+    #[test]
+    fn untranslated_segments_keep_their_text() {
+        let segment = |text: &str, translated: Option<&str>| Segment {
+            start_ms: 0,
+            end_ms: 0,
+            text: text.to_string(),
+            words: None,
+            speaker: None,
+            translated: translated.map(str::to_string),
+            channel: None,
+            speaker_name: None,
+        };
+        assert_eq!(translated_text(&[segment("नमस्ते", None)]), None);
+        assert_eq!(
+            translated_text(&[segment("नमस्ते", Some("Hello.")), segment("ठीक है", None)]).as_deref(),
+            Some("Hello. ठीक है")
+        );
+    }
+}
@@ -0,0 +1,209 @@
+// System sleep and wake
+// A session left running when the machine sleeps comes back to a socket Deepgram closed
+// long ago, so it's finalized and saved on the way down instead. On wake the frontend
+// hears whether that happened and can offer to start again
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use serde::Serialize;
+use tauri::{AppHandle, Emitter};
+
+use crate::engine::watchdog::{self, AutoStopReason};
+
+/// Event emitted with a `SystemResumed` when the machine wakes up
+pub const EVENT_SYSTEM_RESUMED: &str = "system-resumed";
+
+/// How long going to sleep waits for the session to be saved; logind holds off for 5 s by
+/// default and Windows gives handlers about 2, so what doesn't make it is still in the
+/// recovery journal
+const SLEEP_STOP_TIMEOUT: Duration = Duration::from_secs(4);
+
+/// A session was stopped for the last sleep
+static STOPPED_FOR_SLEEP: AtomicBool = AtomicBool::new(false);
+
+/// Payload of the `system-resumed` event
+#[derive(Debug, Clone, Serialize)]
+pub struct SystemResumed {
+    /// A session was running and was stopped before the sleep
+    pub session_stopped: bool,
+}
+
+/// Start following sleep and wake; a platform that can't tell us is logged, not fatal
+pub fn init(app: &AppHandle) {
+    platform::init(app);
+}
+
+/// The machine is about to sleep; blocks until the session is saved or the timeout passes
+fn will_sleep(app: &AppHandle) {
+    let stop = watchdog::stop_running(app, AutoStopReason::SystemSleep);
+    let stopped = tauri::async_runtime::block_on(tokio::time::timeout(SLEEP_STOP_TIMEOUT, stop))
+        .unwrap_or(true);
+    if stopped {
+        tracing::info!("Stopped the session for system sleep");
+    }
+    STOPPED_FOR_SLEEP.fetch_or(stopped, Ordering::Relaxed);
+}
+
+fn did_wake(app: &AppHandle) {
+    let session_stopped = STOPPED_FOR_SLEEP.swap(false, Ordering::Relaxed);
+    let _ = app.emit(EVENT_SYSTEM_RESUMED, SystemResumed { session_stopped });
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::ptr::NonNull;
+
+    use block2::RcBlock;
+    use objc2_app_kit::{
+        NSWorkspace, NSWorkspaceDidWakeNotification, NSWorkspaceWillSleepNotification,
+    };
+    use objc2_foundation::NSNotification;
+    use tauri::AppHandle;
+
+    /// NSWorkspace posts these on the main thread
+    pub fn init(app: &AppHandle) {
+        let center = NSWorkspace::sharedWorkspace().notificationCenter();
+        let (sleep_app, wake_app) = (app.clone(), app.clone());
+        let will_sleep =
+            RcBlock::new(move |_: NonNull<NSNotification>| super::will_sleep(&sleep_app));
+        let did_wake = RcBlock::new(move |_: NonNull<NSNotification>| super::did_wake(&wake_app));
+        // SAFETY: no object filter and no queue, so the blocks run on the posting thread;
+        // they only capture app handles, which are Send
+        unsafe {
+            for (name, block) in [
+                (NSWorkspaceWillSleepNotification, &will_sleep),
+                (NSWorkspaceDidWakeNotification, &did_wake),
+            ] {
+                let observer = center.addObserverForName_object_queue_usingBlock(
+                    Some(name),
+                    None,
+                    None,
+                    block,
+                );
+                // Observing for the life of the app
+                std::mem::forget(observer);
+            }
+        }
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::ffi::c_void;
+
+    use tauri::AppHandle;
+    use windows_sys::Win32::Foundation::ERROR_SUCCESS;
+    use windows_sys::Win32::System::Power::{
+        PowerRegisterSuspendResumeNotification, DEVICE_NOTIFY_SUBSCRIBE_PARAMETERS,
+    };
+    use windows_sys::Win32::UI::WindowsAndMessaging::{
+        DEVICE_NOTIFY_CALLBACK, PBT_APMRESUMEAUTOMATIC, PBT_APMSUSPEND,
+    };
+
+    pub fn init(app: &AppHandle) {
+        // Both live for the rest of the process, as does the registration
+        let context = Box::into_raw(Box::new(app.clone()));
+        let parameters = Box::leak(Box::new(DEVICE_NOTIFY_SUBSCRIBE_PARAMETERS {
+            Callback: Some(callback),
+            Context: context.cast(),
+        }));
+        let mut registration = std::ptr::null_mut();
+        // SAFETY: the parameters and the handle they point to are never freed
+        let result = unsafe {
+            PowerRegisterSuspendResumeNotification(
+                DEVICE_NOTIFY_CALLBACK,
+                (parameters as *mut DEVICE_NOTIFY_SUBSCRIBE_PARAMETERS).cast(),
+                &mut registration,
+            )
+        };
+        if result != ERROR_SUCCESS {
+            tracing::warn!("Not following system sleep: error {}", result);
+        }
+    }
+
+    unsafe extern "system" fn callback(
+        context: *const c_void,
+        event: u32,
+        _setting: *const c_void,
+    ) -> u32 {
+        // SAFETY: `context` is the leaked app handle from `init`
+        let app = unsafe { &*context.cast::<AppHandle>() };
+        match event {
+            PBT_APMSUSPEND => super::will_sleep(app),
+            PBT_APMRESUMEAUTOMATIC => super::did_wake(app),
+            _ => {}
+        }
+        ERROR_SUCCESS
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use tauri::AppHandle;
+    use zbus::blocking::{Connection, Proxy};
+    use zbus::zvariant::OwnedFd;
+
+    /// Listens to logind on the system bus, on a thread of its own
+    pub fn init(app: &AppHandle) {
+        let app = app.clone();
+        let spawned = std::thread::Builder::new()
+            .name("power-events".to_string())
+            .spawn(move || {
+                if let Err(e) = listen(&app) {
+                    tracing::warn!("Not following system sleep: {}", e);
+                }
+            });
+        if let Err(e) = spawned {
+            tracing::warn!("Not following system sleep: {}", e);
+        }
+    }
+
+    fn listen(app: &AppHandle) -> zbus::Result<()> {
+        let connection = Connection::system()?;
+        let manager = Proxy::new(
+            &connection,
+            "org.freedesktop.login1",
+            "/org/freedesktop/login1",
+            "org.freedesktop.login1.Manager",
+        )?;
+        let signals = manager.receive_signal("PrepareForSleep")?;
+        let mut lock = inhibit(&manager);
+        for message in signals {
+            let Ok(sleeping) = message.body().deserialize::<bool>() else {
+                continue;
+            };
+            if sleeping {
+                super::will_sleep(app);
+                // Letting go of the lock lets the sleep go ahead
+                lock.take();
+            } else {
+                super::did_wake(app);
+                lock = inhibit(&manager);
+            }
+        }
+        Ok(())
+    }
+
+    /// A delay lock: logind waits for it to be released before sleeping, up to its
+    /// InhibitDelayMaxSec; without one the session is still stopped, just in a race
+    fn inhibit(manager: &Proxy) -> Option<OwnedFd> {
+        manager
+            .call(
+                "Inhibit",
+                &(
+                    "sleep",
+                    "SubSpace Voice",
+                    "Saving the dictation session",
+                    "delay",
+                ),
+            )
+            .map_err(|e| tracing::warn!("No sleep delay lock: {}", e))
+            .ok()
+    }
+}
+
+#[cfg(not(any(target_os = "macos", windows, target_os = "linux")))]
+mod platform {
+    pub fn init(_: &tauri::AppHandle) {}
+}
@@ -0,0 +1,168 @@
+// Named API key profiles (e.g. personal and work Deepgram projects)
+// Each profile has its own key in `secrets` and its own transcription settings in the config
+
+use serde::Serialize;
+use tauri::{AppHandle, Emitter};
+
+use crate::config::{Profile, DEFAULT_PROFILE};
+use crate::error::AppError;
+use crate::fs_util::TMP_SUFFIX;
+use crate::secrets::ApiKeySource;
+use crate::state;
+
+/// Event emitted with the new profile name after the active profile changes
+pub const EVENT_PROFILE_CHANGED: &str = "profile-changed";
+
+const MAX_PROFILE_NAME_CHARS: usize = 64;
+
+/// Entry returned by `list_profiles`
+#[derive(Debug, Clone, Serialize)]
+pub struct ProfileInfo {
+    pub name: String,
+    pub active: bool,
+    /// Where this profile's key comes from, or None if it has no key yet
+    pub key_source: Option<ApiKeySource>,
+}
+
+/// Trim and check a new profile name against the existing ones
+/// Names also become part of key file names, so only a safe set of characters is allowed,
+/// and none that would make a key file look like another's temp file
+fn validate_name<'a>(
+    name: &str,
+    existing: impl IntoIterator<Item = &'a String>,
+) -> Result<String, AppError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(AppError::InvalidInput(
+            "Profile name must not be empty".to_string(),
+        ));
+    }
+    if name.chars().count() > MAX_PROFILE_NAME_CHARS {
+        return Err(AppError::InvalidInput(format!(
+            "Profile name must be at most {} characters",
+            MAX_PROFILE_NAME_CHARS
+        )));
+    }
+    if !name
+        .chars()
+        .all(|c| c.is_alphanumeric() || matches!(c, ' ' | '-' | '_' | '.'))
+        || name.starts_with('.')
+        || name.ends_with('.')
+    {
+        return Err(AppError::InvalidInput(
+            "Profile names may only contain letters, digits, spaces, '-', '_' and '.'".to_string(),
+        ));
+    }
+    let lower = name.to_lowercase();
+    if lower == TMP_SUFFIX[1..] || lower.ends_with(TMP_SUFFIX) {
+        return Err(AppError::InvalidInput(format!(
+            "Profile names must not be \"{}\" or end in \"{}\"",
+            &TMP_SUFFIX[1..],
+            TMP_SUFFIX
+        )));
+    }
+    if existing
+        .into_iter()
+        .any(|other| other.eq_ignore_ascii_case(name))
+    {
+        return Err(AppError::InvalidInput(format!(
+            "A profile named \"{}\" already exists",
+            name
+        )));
+    }
+    Ok(name.to_string())
+}
+
+fn profile_info(app: &AppHandle, name: &str, active: &str) -> ProfileInfo {
+    ProfileInfo {
+        name: name.to_string(),
+        active: name == active,
+        key_source: crate::secrets::load_profile_key(app, name)
+            .ok()
+            .flatten()
+            .map(|(_, source)| source),
+    }
+}
+
+/// Command to list profiles in name order
+#[tauri::command]
+pub async fn list_profiles(app: AppHandle) -> Result<Vec<ProfileInfo>, AppError> {
+    state::blocking(move || {
+        let config = crate::config::load(&app)?;
+        Ok(config
+            .profiles
+            .keys()
+            .map(|name| profile_info(&app, name, &config.active_profile))
+            .collect())
+    })
+    .await
+}
+
+/// Command to add a profile with default settings and no key; it is not activated
+#[tauri::command]
+pub async fn create_profile(app: AppHandle, name: String) -> Result<ProfileInfo, AppError> {
+    state::blocking(move || {
+        let (name, active) = crate::config::update(&app, |config| {
+            let name = validate_name(&name, config.profiles.keys())?;
+            config.profiles.insert(name.clone(), Profile::default());
+            Ok((name, config.active_profile.clone()))
+        })?;
+        Ok(profile_info(&app, &name, &active))
+    })
+    .await
+}
+
+/// Command to switch profiles; new streams and requests use its key and settings
+#[tauri::command]
+pub async fn set_active_profile(app: AppHandle, name: String) -> Result<(), AppError> {
+    state::blocking(move || activate(&app, &name)).await
+}
+
+/// Make `name` the active profile
+pub fn activate(app: &AppHandle, name: &str) -> Result<(), AppError> {
+    crate::config::update(app, |config| {
+        if !config.profiles.contains_key(name) {
+            return Err(AppError::NotFound(format!(
+                "Profile \"{}\" not found",
+                name
+            )));
+        }
+        config.active_profile = name.to_string();
+        Ok(())
+    })?;
+    let _ = app.emit(EVENT_PROFILE_CHANGED, name);
+    Ok(())
+}
+
+/// Command to delete a profile and its saved key
+/// The default profile can't be deleted; deleting the active one switches to it
+#[tauri::command]
+pub async fn delete_profile(app: AppHandle, name: String) -> Result<(), AppError> {
+    if name == DEFAULT_PROFILE {
+        return Err(AppError::InvalidInput(
+            "The default profile can't be deleted".to_string(),
+        ));
+    }
+    state::blocking(move || remove_profile(&app, &name)).await
+}
+
+fn remove_profile(app: &AppHandle, name: &str) -> Result<(), AppError> {
+    let was_active = crate::config::update(app, |config| {
+        if config.profiles.remove(name).is_none() {
+            return Err(AppError::NotFound(format!(
+                "Profile \"{}\" not found",
+                name
+            )));
+        }
+        let was_active = config.active_profile == name;
+        if was_active {
+            config.active_profile = DEFAULT_PROFILE.to_string();
+        }
+        Ok(was_active)
+    })?;
+    crate::secrets::delete_profile_key(app, name)?;
+    if was_active {
+        let _ = app.emit(EVENT_PROFILE_CHANGED, DEFAULT_PROFILE);
+    }
+    Ok(())
+}
@@ -0,0 +1,396 @@
+// Queue for transcribing many files, a few at a time
+// Each job runs `prerecorded::transcribe` once a slot is free; cancelling aborts its task,
+// which drops the upload. Jobs still pending at exit are saved and queued again at launch
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use serde::Serialize;
+use tauri::async_runtime::JoinHandle;
+use tauri::{AppHandle, Emitter, Manager, State};
+use tokio::sync::Semaphore;
+use tokio::time::Instant;
+
+use crate::deepgram::prerecorded::{self, Progress, ProgressFn, TranscriptionPhase};
+use crate::error::AppError;
+use crate::fs_util::write_atomic;
+use crate::state::AppState;
+
+/// Event emitted with a job whenever its state changes
+pub const EVENT_QUEUE_UPDATED: &str = "queue-updated";
+
+/// File in the app data dir listing the paths of jobs left over at exit
+const PENDING_FILE_NAME: &str = "queue.json";
+
+/// Where a job is; `cancelled` and `failed` jobs can be retried
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum JobState {
+    Queued,
+    Uploading {
+        pct: u8,
+        /// From 1; above 1 when earlier uploads failed
+        attempt: u32,
+    },
+    /// An upload failed; waiting to send the file again
+    Retrying {
+        attempt: u32,
+    },
+    /// Uploaded, waiting for Deepgram's result
+    Processing,
+    Done {
+        session_id: i64,
+    },
+    Failed {
+        error: String,
+    },
+    Cancelled,
+}
+
+impl JobState {
+    /// Not finished yet, so it would be lost if the app quit now
+    fn is_pending(&self) -> bool {
+        matches!(
+            self,
+            Self::Queued | Self::Uploading { .. } | Self::Retrying { .. } | Self::Processing
+        )
+    }
+
+    fn is_running(&self) -> bool {
+        matches!(
+            self,
+            Self::Uploading { .. } | Self::Retrying { .. } | Self::Processing
+        )
+    }
+}
+
+/// One file in the queue
+#[derive(Debug, Clone, Serialize)]
+pub struct QueueJob {
+    pub id: u64,
+    pub path: String,
+    #[serde(flatten)]
+    pub state: JobState,
+}
+
+/// The file queue; part of `AppState`
+pub struct FileQueue {
+    jobs: Mutex<Vec<QueueJob>>,
+    tasks: Mutex<HashMap<u64, JoinHandle<()>>>,
+    slots: Arc<Semaphore>,
+    next_id: AtomicU64,
+    /// Set at exit so waiting jobs don't start
+    draining: AtomicBool,
+}
+
+impl FileQueue {
+    /// A queue running at most `concurrency` jobs at once (at least one)
+    pub fn new(concurrency: usize) -> Self {
+        Self {
+            jobs: Mutex::new(Vec::new()),
+            tasks: Mutex::new(HashMap::new()),
+            slots: Arc::new(Semaphore::new(concurrency.max(1))),
+            next_id: AtomicU64::new(1),
+            draining: AtomicBool::new(false),
+        }
+    }
+
+    fn enqueue(&self, app: &AppHandle, paths: Vec<String>) -> Result<Vec<u64>, AppError> {
+        let mut ids = Vec::with_capacity(paths.len());
+        for path in paths {
+            let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+            let job = QueueJob {
+                id,
+                path,
+                state: JobState::Queued,
+            };
+            self.jobs()?.push(job.clone());
+            let _ = app.emit(EVENT_QUEUE_UPDATED, &job);
+            self.spawn(app, job)?;
+            ids.push(id);
+        }
+        Ok(ids)
+    }
+
+    /// Run a job once a slot is free
+    fn spawn(&self, app: &AppHandle, job: QueueJob) -> Result<(), AppError> {
+        let app = app.clone();
+        let slots = Arc::clone(&self.slots);
+        let task = tauri::async_runtime::spawn(async move {
+            let Ok(_slot) = slots.acquire_owned().await else {
+                return;
+            };
+            let state = app.state::<AppState>();
+            let queue = &state.queue;
+            if queue.draining.load(Ordering::Relaxed) {
+                return;
+            }
+            queue.update(&app, job.id, JobState::Uploading { pct: 0, attempt: 1 });
+            let progress = job_progress(app.clone(), job.id);
+            let outcome = match prerecorded::transcribe(&app, job.path, None, None, progress).await
+            {
+                Ok(session) => JobState::Done {
+                    session_id: session.id,
+                },
+                Err(e) => JobState::Failed {
+                    error: e.to_string(),
+                },
+            };
+            queue.update(&app, job.id, outcome);
+            queue.forget_task(job.id);
+        });
+        self.tasks()?.insert(job.id, task);
+        Ok(())
+    }
+
+    fn forget_task(&self, id: u64) {
+        if let Ok(mut tasks) = self.tasks() {
+            tasks.remove(&id);
+        }
+    }
+
+    /// Set a job's state and announce it; finished jobs stay finished
+    fn update(&self, app: &AppHandle, id: u64, state: JobState) {
+        let Ok(mut jobs) = self.jobs() else {
+            return;
+        };
+        let Some(job) = jobs.iter_mut().find(|job| job.id == id) else {
+            return;
+        };
+        if !job.state.is_pending() || job.state == state {
+            return;
+        }
+        job.state = state;
+        let _ = app.emit(EVENT_QUEUE_UPDATED, &*job);
+    }
+
+    fn list(&self) -> Result<Vec<QueueJob>, AppError> {
+        Ok(self.jobs()?.clone())
+    }
+
+    fn cancel(&self, app: &AppHandle, id: u64) -> Result<(), AppError> {
+        let job = self.job(id)?;
+        if !job.state.is_pending() {
+            return Err(AppError::InvalidInput(format!(
+                "Job {} has already finished",
+                id
+            )));
+        }
+        if let Some(task) = self.tasks()?.remove(&id) {
+            task.abort();
+        }
+        self.update(app, id, JobState::Cancelled);
+        Ok(())
+    }
+
+    fn retry(&self, app: &AppHandle, id: u64) -> Result<(), AppError> {
+        let job = {
+            let mut jobs = self.jobs()?;
+            let job = jobs
+                .iter_mut()
+                .find(|job| job.id == id)
+                .ok_or_else(|| AppError::NotFound(format!("No job {} in the queue", id)))?;
+            if !matches!(job.state, JobState::Failed { .. } | JobState::Cancelled) {
+                return Err(AppError::InvalidInput(format!(
+                    "Only failed or cancelled jobs can be retried; job {} is not",
+                    id
+                )));
+            }
+            job.state = JobState::Queued;
+            job.clone()
+        };
+        let _ = app.emit(EVENT_QUEUE_UPDATED, &job);
+        self.spawn(app, job)
+    }
+
+    /// Let running jobs finish until `deadline`, then save whatever is left so it is
+    /// queued again at the next launch
+    pub async fn shutdown(&self, app: &AppHandle, deadline: Instant) {
+        self.draining.store(true, Ordering::Relaxed);
+        let running: Vec<u64> = self
+            .list()
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|job| job.state.is_running())
+            .map(|job| job.id)
+            .collect();
+        let tasks: Vec<(u64, JoinHandle<()>)> = match self.tasks() {
+            Ok(mut tasks) => tasks.drain().collect(),
+            Err(_) => Vec::new(),
+        };
+        for (id, mut task) in tasks {
+            if !running.contains(&id) {
+                task.abort();
+                continue;
+            }
+            if tokio::time::timeout_at(deadline, &mut task).await.is_err() {
+                tracing::warn!("Job {} did not finish before exit; it will run again", id);
+                task.abort();
+            }
+        }
+
+        let pending: Vec<String> = self
+            .list()
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|job| job.state.is_pending())
+            .map(|job| job.path)
+            .collect();
+        if let Err(e) = save_pending(app, &pending) {
+            tracing::error!("Failed to save the file queue: {}", e);
+        }
+    }
+
+    fn job(&self, id: u64) -> Result<QueueJob, AppError> {
+        self.jobs()?
+            .iter()
+            .find(|job| job.id == id)
+            .cloned()
+            .ok_or_else(|| AppError::NotFound(format!("No job {} in the queue", id)))
+    }
+
+    fn jobs(&self) -> Result<MutexGuard<'_, Vec<QueueJob>>, AppError> {
+        self.jobs
+            .lock()
+            .map_err(|_| AppError::poisoned("File queue"))
+    }
+
+    fn tasks(&self) -> Result<MutexGuard<'_, HashMap<u64, JoinHandle<()>>>, AppError> {
+        self.tasks
+            .lock()
+            .map_err(|_| AppError::poisoned("File queue tasks"))
+    }
+}
+
+/// Maps upload progress onto the job's state
+fn job_progress(app: AppHandle, id: u64) -> ProgressFn {
+    Arc::new(move |progress: Progress| {
+        let state = match progress.phase {
+            TranscriptionPhase::Uploading => JobState::Uploading {
+                pct: (progress.bytes_sent * 100 / progress.total_bytes.max(1)).min(100) as u8,
+                attempt: progress.attempt,
+            },
+            TranscriptionPhase::Retrying => JobState::Retrying {
+                attempt: progress.attempt,
+            },
+            TranscriptionPhase::Transcribing => JobState::Processing,
+            // The session id arrives with the result
+            TranscriptionPhase::Done => return,
+        };
+        app.state::<AppState>().queue.update(&app, id, state);
+    })
+}
+
+fn pending_path(app: &AppHandle) -> Result<PathBuf, AppError> {
+    app.path()
+        .app_data_dir()
+        .map(|dir| dir.join(PENDING_FILE_NAME))
+        .map_err(|e| AppError::Config(format!("Could not resolve app data directory: {}", e)))
+}
+
+fn save_pending(app: &AppHandle, paths: &[String]) -> Result<(), AppError> {
+    let path = pending_path(app)?;
+    if paths.is_empty() {
+        return match std::fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        };
+    }
+    let contents = serde_json::to_vec_pretty(paths)
+        .map_err(|e| AppError::Internal(format!("Failed to serialize the file queue: {}", e)))?;
+    write_atomic(&path, &contents, false)
+        .map_err(|e| AppError::Io(format!("Failed to write {}: {}", path.display(), e)))
+}
+
+/// Queue again the jobs saved at the last exit
+pub fn init(app: &AppHandle) {
+    let Ok(path) = pending_path(app) else {
+        return;
+    };
+    let paths: Vec<String> = match std::fs::read(&path) {
+        Ok(contents) => serde_json::from_slice(&contents).unwrap_or_else(|e| {
+            tracing::warn!("Ignoring unreadable {}: {}", path.display(), e);
+            Vec::new()
+        }),
+        Err(_) => return,
+    };
+    let _ = std::fs::remove_file(&path);
+    if paths.is_empty() {
+        return;
+    }
+    tracing::info!("Resuming {} queued file(s) from the last run", paths.len());
+    if let Err(e) = app.state::<AppState>().queue.enqueue(app, paths) {
+        tracing::error!("Failed to resume the file queue: {}", e);
+    }
+}
+
+/// Command to queue files for transcription, returning their job ids in order
+#[tauri::command]
+pub fn enqueue_files(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    paths: Vec<String>,
+) -> Result<Vec<u64>, AppError> {
+    if paths.iter().any(|path| path.trim().is_empty()) {
+        return Err(AppError::InvalidInput(
+            "File paths must not be empty".to_string(),
+        ));
+    }
+    state.queue.enqueue(&app, paths)
+}
+
+/// Command to list every job in the queue, oldest first
+#[tauri::command]
+pub fn get_queue(state: State<'_, AppState>) -> Result<Vec<QueueJob>, AppError> {
+    state.queue.list()
+}
+
+/// Command to cancel a waiting or running job, aborting its upload
+#[tauri::command]
+pub fn cancel_job(app: AppHandle, state: State<'_, AppState>, id: u64) -> Result<(), AppError> {
+    state.queue.cancel(&app, id)
+}
+
+/// Command to queue a failed or cancelled job again
+#[tauri::command]
+pub fn retry_job(app: AppHandle, state: State<'_, AppState>, id: u64) -> Result<(), AppError> {
+    state.queue.retry(&app, id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_unfinished_jobs_are_pending() {
+        assert!(JobState::Queued.is_pending());
+        assert!(JobState::Uploading {
+            pct: 40,
+            attempt: 1
+        }
+        .is_pending());
+        assert!(JobState::Retrying { attempt: 2 }.is_running());
+        assert!(JobState::Processing.is_running());
+        assert!(!JobState::Queued.is_running());
+        assert!(!JobState::Done { session_id: 1 }.is_pending());
+        assert!(!JobState::Cancelled.is_pending());
+    }
+
+    #[test]
+    fn job_state_is_flattened_into_the_job() {
+        let job = QueueJob {
+            id: 3,
+            path: "/tmp/a.wav".to_string(),
+            state: JobState::Uploading {
+                pct: 12,
+                attempt: 2,
+            },
+        };
+        assert_eq!(
+            serde_json::to_value(&job).unwrap(),
+            serde_json::json!({ "id": 3, "path": "/tmp/a.wav", "state": "uploading", "pct": 12, "attempt": 2 })
+        );
+    }
+}
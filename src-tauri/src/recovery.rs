@@ -0,0 +1,394 @@
+// Crash-safe live sessions
+// Each final segment is appended to a JSON-lines journal as it arrives and synced to disk,
+// and so is the taking back of one;
+// the journal is deleted once the session is saved, so any left at startup belong to
+// sessions that never finished and are rebuilt into history
+
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc as std_mpsc;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::audio::recording;
+use crate::error::AppError;
+use crate::postprocess::dictation_commands;
+use crate::state::{blocking, AppState};
+use crate::storage::{NewSession, Segment, Session, Storage};
+
+/// Event emitted with the new session id for each session rebuilt at startup
+pub const EVENT_SESSION_RECOVERED: &str = "session-recovered";
+
+/// Directory inside the app data dir holding journals of running sessions
+const JOURNALS_DIR: &str = "journals";
+/// Segments queued for the writer thread before new ones are dropped
+const WRITE_QUEUE_CAPACITY: usize = 64;
+
+/// What a session is filed under, written as the journal's first line
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalHeader {
+    /// Unix time in milliseconds; also names the journal file
+    pub started_at: i64,
+    pub model: String,
+    pub language: String,
+    /// Missing from journals written before providers were recorded
+    #[serde(default)]
+    pub provider: Option<String>,
+}
+
+/// One journal line
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Entry {
+    Start(JournalHeader),
+    Segment(Segment),
+    /// The last segment that started at `start_ms` was taken back
+    Revoke {
+        start_ms: i64,
+    },
+}
+
+/// Appends a running session's final segments to its journal on a background thread
+pub struct SessionJournal {
+    segment_tx: std_mpsc::SyncSender<Entry>,
+    thread: JoinHandle<()>,
+    path: PathBuf,
+    dropped: bool,
+}
+
+impl SessionJournal {
+    /// Start a journal in the app data dir; None (logged) if the directory is unusable
+    pub fn start(app: &AppHandle, header: JournalHeader) -> Option<Self> {
+        let path = match journals_dir(app) {
+            Ok(dir) => journal_path(&dir, header.started_at),
+            Err(e) => {
+                tracing::error!("{}; this session can't be recovered after a crash", e);
+                return None;
+            }
+        };
+        let (segment_tx, segment_rx) = std_mpsc::sync_channel(WRITE_QUEUE_CAPACITY);
+        let thread_path = path.clone();
+        let thread = std::thread::spawn(move || {
+            if let Err(e) = write_journal(&thread_path, header, segment_rx) {
+                tracing::error!("Failed to journal {}: {}", thread_path.display(), e);
+            }
+        });
+        Some(Self {
+            segment_tx,
+            thread,
+            path,
+            dropped: false,
+        })
+    }
+
+    /// Queue a final segment without blocking
+    pub fn append(&mut self, segment: &Segment) {
+        self.send(Entry::Segment(segment.clone()));
+    }
+
+    /// Queue the taking back of a segment appended before
+    pub fn revoke(&mut self, segment: &Segment) {
+        self.send(Entry::Revoke {
+            start_ms: segment.start_ms,
+        });
+    }
+
+    fn send(&mut self, entry: Entry) {
+        if self.segment_tx.try_send(entry).is_err() && !self.dropped {
+            self.dropped = true;
+            tracing::warn!("Journal writer fell behind; a crash now would lose some segments");
+        }
+    }
+
+    /// Wait for queued segments to be written, returning the journal's path
+    /// Blocks, so call it off the async runtime
+    pub fn finish(self) -> PathBuf {
+        drop(self.segment_tx);
+        let _ = self.thread.join();
+        self.path
+    }
+}
+
+/// Delete a finished journal once its session is safely in history
+pub fn discard(path: &Path) {
+    if let Err(e) = fs::remove_file(path) {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            tracing::warn!("Failed to delete {}: {}", path.display(), e);
+        }
+    }
+}
+
+fn write_journal(
+    path: &Path,
+    header: JournalHeader,
+    segment_rx: std_mpsc::Receiver<Entry>,
+) -> std::io::Result<()> {
+    let mut file = OpenOptions::new()
+        .create_new(true)
+        .append(true)
+        .open(path)?;
+    append_line(&mut file, &Entry::Start(header))?;
+    while let Ok(entry) = segment_rx.recv() {
+        append_line(&mut file, &entry)?;
+    }
+    Ok(())
+}
+
+/// Write one line and sync it, so a crash loses at most the line being written
+fn append_line(file: &mut File, entry: &Entry) -> std::io::Result<()> {
+    let mut line = serde_json::to_vec(entry)?;
+    line.push(b'\n');
+    file.write_all(&line)?;
+    file.sync_data()
+}
+
+fn journals_dir(app: &AppHandle) -> Result<PathBuf, AppError> {
+    let dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| AppError::Config(format!("Could not resolve app data directory: {}", e)))?
+        .join(JOURNALS_DIR);
+    fs::create_dir_all(&dir)
+        .map_err(|e| AppError::Io(format!("Failed to create {}: {}", dir.display(), e)))?;
+    Ok(dir)
+}
+
+fn journal_path(dir: &Path, started_at: i64) -> PathBuf {
+    dir.join(format!("session-{}.jsonl", started_at))
+}
+
+/// Start time encoded in a journal's file name
+fn journal_started_at(path: &Path) -> Option<i64> {
+    if path.extension()? != "jsonl" {
+        return None;
+    }
+    path.file_stem()?
+        .to_str()?
+        .strip_prefix("session-")?
+        .parse()
+        .ok()
+}
+
+/// Rebuild sessions left behind by a previous run, in the background
+pub fn init(app: &AppHandle) {
+    let launched_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0);
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let handle = app.clone();
+        match blocking(move || recover_all(&handle, launched_at)).await {
+            Ok(ids) => {
+                for id in ids {
+                    let _ = app.emit(EVENT_SESSION_RECOVERED, id);
+                }
+            }
+            Err(e) => tracing::warn!("Session recovery failed: {}", e),
+        }
+    });
+}
+
+fn recover_all(app: &AppHandle, launched_at: i64) -> Result<Vec<i64>, AppError> {
+    let dir = journals_dir(app)?;
+    let storage = Arc::clone(&app.state::<AppState>().storage);
+    let entries = fs::read_dir(&dir)
+        .map_err(|e| AppError::Io(format!("Failed to read {}: {}", dir.display(), e)))?;
+    let mut ids = Vec::new();
+    for path in entries.flatten().map(|entry| entry.path()) {
+        // Journals from this launch belong to sessions that are still running
+        match journal_started_at(&path) {
+            Some(started_at) if started_at < launched_at => {}
+            _ => continue,
+        }
+        match recover(app, &storage, &path) {
+            Ok(Some(id)) => {
+                tracing::info!("Recovered session {} from {}", id, path.display());
+                ids.push(id);
+            }
+            Ok(None) => {}
+            Err(e) => tracing::warn!("Failed to recover {}: {}", path.display(), e),
+        }
+    }
+    Ok(ids)
+}
+
+/// Save one journal's session to history, with its partial recording if there is one
+/// Returns None for a journal with no segments, which is simply deleted
+fn recover(app: &AppHandle, storage: &Storage, path: &Path) -> Result<Option<i64>, AppError> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| AppError::Io(format!("Failed to read {}: {}", path.display(), e)))?;
+    let (header, segments) = parse_journal(&contents)
+        .ok_or_else(|| AppError::Io(format!("{} is not a session journal", path.display())))?;
+    let audio_path = recording::recordings_dir(app)
+        .ok()
+        .map(|dir| recording::in_progress_path(&dir, header.started_at))
+        .filter(|audio_path| audio_path.exists());
+
+    let Some(last) = segments.last() else {
+        if let Some(audio_path) = audio_path {
+            let _ = fs::remove_file(audio_path);
+        }
+        discard(path);
+        return Ok(None);
+    };
+    let session = NewSession {
+        started_at: header.started_at,
+        duration_ms: last.end_ms,
+        model: header.model,
+        language: header.language,
+        detected_language: None,
+        text: dictation_commands::join(segments.iter().map(|segment| segment.text.as_str())),
+        audio_path: None,
+        recovered: true,
+        imported: false,
+        audio_source: None,
+        segments,
+        // Translations aren't journaled
+        translated_text: None,
+        translation_language: None,
+        provider: header.provider,
+        // The stream's metadata isn't journaled either
+        request_id: None,
+        model_version: None,
+        disfluencies_removed: None,
+    };
+    let id = storage.insert_session(&session)?;
+    if session.provider.as_deref() == Some(crate::engine::mock::PROVIDER) {
+        storage.add_tag(id, crate::engine::mock::TAG)?;
+    }
+    if let Some(audio_path) = audio_path {
+        recording::attach(storage, id, audio_path);
+    }
+    discard(path);
+    Ok(Some(id))
+}
+
+/// The header and segments of a journal
+/// A crash mid-append leaves a partial last line; everything before it is intact
+fn parse_journal(contents: &str) -> Option<(JournalHeader, Vec<Segment>)> {
+    let mut lines = contents.lines();
+    let header = match serde_json::from_str(lines.next()?) {
+        Ok(Entry::Start(header)) => header,
+        _ => return None,
+    };
+    let mut segments: Vec<Segment> = Vec::new();
+    for line in lines {
+        match serde_json::from_str(line) {
+            Ok(Entry::Segment(segment)) => segments.push(segment),
+            Ok(Entry::Revoke { start_ms }) => {
+                if let Some(index) = segments
+                    .iter()
+                    .rposition(|segment| segment.start_ms == start_ms)
+                {
+                    segments.remove(index);
+                }
+            }
+            _ => break,
+        }
+    }
+    Some((header, segments))
+}
+
+/// Command to list sessions rebuilt after a crash, newest first
+#[tauri::command]
+pub async fn get_recovered_sessions(state: State<'_, AppState>) -> Result<Vec<Session>, AppError> {
+    let storage = Arc::clone(&state.storage);
+    blocking(move || storage.recovered_sessions()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(entry: &Entry) -> String {
+        serde_json::to_string(entry).unwrap()
+    }
+
+    fn segment(text: &str, start_ms: i64) -> Segment {
+        Segment {
+            start_ms,
+            end_ms: start_ms + 900,
+            text: text.to_string(),
+            words: None,
+            speaker: None,
+            translated: None,
+            channel: None,
+            speaker_name: None,
+        }
+    }
+
+    #[test]
+    fn parse_stops_at_a_torn_last_line() {
+        let header = JournalHeader {
+            started_at: 1_700_000_000_000,
+            model: "nova-2".to_string(),
+            language: "en".to_string(),
+            provider: Some("deepgram".to_string()),
+        };
+        let contents = format!(
+            "{}\n{}\n{}\n{{\"type\":\"segment\",\"start_",
+            line(&Entry::Start(header)),
+            line(&Entry::Segment(segment("Hello there.", 0))),
+            line(&Entry::Segment(segment("How are you?", 1200))),
+        );
+        let (header, segments) = parse_journal(&contents).unwrap();
+        assert_eq!(header.started_at, 1_700_000_000_000);
+        assert_eq!(header.provider.as_deref(), Some("deepgram"));
+        let texts: Vec<_> = segments
+            .iter()
+            .map(|segment| segment.text.as_str())
+            .collect();
+        assert_eq!(texts, ["Hello there.", "How are you?"]);
+    }
+
+    #[test]
+    fn revoked_segments_are_left_out() {
+        let header = JournalHeader {
+            started_at: 1,
+            model: "nova-2".to_string(),
+            language: "en".to_string(),
+            provider: None,
+        };
+        let contents = [
+            line(&Entry::Start(header)),
+            line(&Entry::Segment(segment("Hello there.", 0))),
+            line(&Entry::Segment(segment("Oops.", 1200))),
+            line(&Entry::Revoke { start_ms: 1200 }),
+            line(&Entry::Segment(segment("Bye.", 2400))),
+        ]
+        .join("\n");
+        let (_, segments) = parse_journal(&contents).unwrap();
+        let texts: Vec<_> = segments
+            .iter()
+            .map(|segment| segment.text.as_str())
+            .collect();
+        assert_eq!(texts, ["Hello there.", "Bye."]);
+    }
+
+    #[test]
+    fn journal_needs_a_header() {
+        let contents = format!("{}\n", line(&Entry::Segment(segment("Hi.", 0))));
+        assert!(parse_journal(&contents).is_none());
+        assert!(parse_journal("").is_none());
+        // Journals from before providers were recorded
+        let old =
+            "{\"type\":\"start\",\"started_at\":1,\"model\":\"nova-2\",\"language\":\"en\"}\n";
+        assert_eq!(parse_journal(old).unwrap().0.provider, None);
+    }
+
+    #[test]
+    fn start_time_comes_from_the_file_name() {
+        assert_eq!(
+            journal_started_at(Path::new("/data/journals/session-1700000000000.jsonl")),
+            Some(1_700_000_000_000)
+        );
+        assert_eq!(journal_started_at(Path::new("session-1.jsonl.tmp")), None);
+        assert_eq!(journal_started_at(Path::new("notes.jsonl")), None);
+    }
+}
@@ -0,0 +1,267 @@
+// Which parts of a transcript to double-check, from Deepgram's word confidences
+// A session's words are grouped into runs that are either fine or worth reviewing, and
+// the `review` export renders those as Markdown with the doubtful runs highlighted
+
+use std::sync::Arc;
+
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+
+use crate::config;
+use crate::error::AppError;
+use crate::export;
+use crate::state::{blocking, AppState};
+use crate::storage::Segment;
+
+/// Whether a run needs a second look
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunFlag {
+    Ok,
+    Review,
+}
+
+/// Consecutive words of one segment on the same side of the threshold
+#[derive(Debug, Clone, Serialize)]
+pub struct ReviewRun {
+    pub flag: RunFlag,
+    pub text: String,
+    pub start_ms: i64,
+    pub end_ms: i64,
+    /// Position of its segment in the session, so runs can be laid out by segment
+    pub segment: usize,
+}
+
+/// Result of `get_session_review`
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionReview {
+    pub session_id: i64,
+    pub threshold: f64,
+    pub runs: Vec<ReviewRun>,
+    /// Words with a confidence
+    pub word_count: usize,
+    /// Of those, the ones below the threshold
+    pub review_word_count: usize,
+    /// `review_word_count` as a percentage of `word_count`
+    pub review_percent: f64,
+}
+
+/// Command to split a session's transcript into runs to trust and runs to check
+/// `threshold` (0-1) defaults to the `confidence_threshold` setting
+#[tauri::command]
+pub async fn get_session_review(
+    app: AppHandle,
+    id: i64,
+    threshold: Option<f64>,
+) -> Result<SessionReview, AppError> {
+    let threshold = resolve_threshold(&app, threshold)?;
+    let storage = Arc::clone(&app.state::<AppState>().storage);
+    blocking(move || {
+        let segments = storage.segments(id)?;
+        review(id, &segments, threshold)
+    })
+    .await
+}
+
+/// `threshold`, checked, or the setting when None
+pub fn resolve_threshold(app: &AppHandle, threshold: Option<f64>) -> Result<f64, AppError> {
+    let threshold =
+        threshold.unwrap_or_else(|| config::transcription_settings(app).confidence_threshold);
+    if !(0.0..=1.0).contains(&threshold) {
+        return Err(AppError::InvalidInput(
+            "The confidence threshold must be between 0 and 1".to_string(),
+        ));
+    }
+    Ok(threshold)
+}
+
+/// The review of session `id`, whose segments are `segments`
+/// Sessions saved without word confidences (older ones, local whisper) have none to go by
+pub fn review(id: i64, segments: &[Segment], threshold: f64) -> Result<SessionReview, AppError> {
+    if !segments.iter().any(|segment| {
+        segment
+            .words
+            .as_ref()
+            .is_some_and(|words| !words.is_empty())
+    }) {
+        return Err(AppError::NoConfidenceData(format!(
+            "Session {} has no word confidences to review",
+            id
+        )));
+    }
+
+    let mut runs: Vec<ReviewRun> = Vec::new();
+    let (mut word_count, mut review_word_count) = (0, 0);
+    for (index, segment) in segments.iter().enumerate() {
+        let words = segment.words.as_deref().unwrap_or_default();
+        if words.is_empty() {
+            // Text with no words behind it, e.g. from a dictation command, isn't in doubt
+            runs.push(ReviewRun {
+                flag: RunFlag::Ok,
+                text: segment.text.clone(),
+                start_ms: segment.start_ms,
+                end_ms: segment.end_ms,
+                segment: index,
+            });
+            continue;
+        }
+        for word in words {
+            let flag = if word.confidence < threshold {
+                review_word_count += 1;
+                RunFlag::Review
+            } else {
+                RunFlag::Ok
+            };
+            word_count += 1;
+            match runs.last_mut() {
+                Some(run) if run.segment == index && run.flag == flag => {
+                    run.text.push(' ');
+                    run.text.push_str(&word.text);
+                    run.end_ms = word.end_ms;
+                }
+                _ => runs.push(ReviewRun {
+                    flag,
+                    text: word.text.clone(),
+                    start_ms: word.start_ms,
+                    end_ms: word.end_ms,
+                    segment: index,
+                }),
+            }
+        }
+    }
+
+    Ok(SessionReview {
+        session_id: id,
+        threshold,
+        runs,
+        word_count,
+        review_word_count,
+        review_percent: if word_count == 0 {
+            0.0
+        } else {
+            review_word_count as f64 * 100.0 / word_count as f64
+        },
+    })
+}
+
+/// The `review` export: a summary line, then the transcript with runs to check in
+/// `==highlight==`, one labelled paragraph per turn for diarized and multichannel sessions
+pub fn render_markdown(id: i64, segments: &[Segment], threshold: f64) -> Result<String, AppError> {
+    let review = review(id, segments, threshold)?;
+    let mut out = format!(
+        "**{:.1}% of words ({} of {}) are below {:.0}% confidence.**\n\n",
+        review.review_percent,
+        review.review_word_count,
+        review.word_count,
+        threshold * 100.0
+    );
+
+    let mut turns: Vec<(Option<String>, String)> = Vec::new();
+    for run in &review.runs {
+        let text = match run.flag {
+            RunFlag::Ok => run.text.clone(),
+            RunFlag::Review => format!("=={}==", run.text),
+        };
+        let label = export::segment_label(&segments[run.segment]);
+        match turns.last_mut() {
+            Some((speaker, turn)) if *speaker == label => {
+                turn.push(' ');
+                turn.push_str(&text);
+            }
+            _ => turns.push((label, text)),
+        }
+    }
+    let paragraphs: Vec<String> = turns
+        .into_iter()
+        .map(|(label, text)| match label {
+            Some(label) => format!("**{}:** {}", label, text),
+            None => text,
+        })
+        .collect();
+    out.push_str(&paragraphs.join("\n\n"));
+    out.push('\n');
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::Word;
+
+    fn segment(words: &[(&str, f64)], speaker: Option<u32>) -> Segment {
+        Segment {
+            start_ms: 0,
+            end_ms: 1000,
+            text: words
+                .iter()
+                .map(|(text, _)| *text)
+                .collect::<Vec<_>>()
+                .join(" "),
+            words: Some(
+                words
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (text, confidence))| Word {
+                        text: text.to_string(),
+                        start_ms: i as i64 * 100,
+                        end_ms: i as i64 * 100 + 90,
+                        confidence: *confidence,
+                    })
+                    .collect(),
+            ),
+            speaker,
+            translated: None,
+            channel: None,
+            speaker_name: None,
+        }
+    }
+
+    #[test]
+    fn low_confidence_words_form_runs_to_review() {
+        let segments = [
+            segment(&[("the", 0.9), ("plaintiff", 0.4), ("alleges", 0.5)], None),
+            segment(&[("damages", 0.95)], None),
+        ];
+        let review = review(7, &segments, 0.6).unwrap();
+        let runs: Vec<_> = review
+            .runs
+            .iter()
+            .map(|run| (run.flag, run.text.as_str(), run.segment))
+            .collect();
+        assert_eq!(
+            runs,
+            [
+                (RunFlag::Ok, "the", 0),
+                (RunFlag::Review, "plaintiff alleges", 0),
+                (RunFlag::Ok, "damages", 1),
+            ]
+        );
+        assert_eq!((review.word_count, review.review_word_count), (4, 2));
+        assert_eq!(review.review_percent, 50.0);
+    }
+
+    #[test]
+    fn sessions_without_word_confidences_cannot_be_reviewed() {
+        let mut plain = segment(&[("hello", 1.0)], None);
+        plain.words = None;
+        assert!(matches!(
+            review(3, &[plain], 0.6),
+            Err(AppError::NoConfidenceData(_))
+        ));
+    }
+
+    #[test]
+    fn review_export_highlights_runs_by_speaker() {
+        let segments = [
+            segment(&[("we", 0.9), ("agreed", 0.3)], Some(0)),
+            segment(&[("fine", 0.9)], Some(0)),
+            segment(&[("no", 0.2)], Some(1)),
+        ];
+        assert_eq!(
+            render_markdown(1, &segments, 0.6).unwrap(),
+            "**50.0% of words (2 of 4) are below 60% confidence.**\n\n\
+             **Speaker 1:** we ==agreed== fine\n\n\
+             **Speaker 2:** ==no==\n"
+        );
+    }
+}
@@ -0,0 +1,254 @@
+// API key lookup and storage: Deepgram's, one per profile, the LLM endpoint's, the
+// translation provider's and the fallback transcription provider's
+// Prefers the OS keychain; falls back to an encrypted file in the config dir when no
+// keychain service is available (e.g. headless Linux without Secret Service)
+
+use std::env;
+
+use keyring::Entry;
+use serde::Serialize;
+use tauri::AppHandle;
+
+use crate::config::DEFAULT_PROFILE;
+use crate::error::AppError;
+use crate::key_store;
+
+/// Keychain service and account the key is stored under
+const KEYCHAIN_SERVICE: &str = "com.subspace.voice";
+const KEYCHAIN_ACCOUNT: &str = "deepgram_api_key";
+const LLM_KEYCHAIN_ACCOUNT: &str = "llm_api_key";
+const TRANSLATION_KEYCHAIN_ACCOUNT: &str = "translation_api_key";
+const OPENAI_KEYCHAIN_ACCOUNT: &str = "openai_api_key";
+
+/// Environment variable holding the key
+pub const API_KEY_ENV_VAR: &str = "DEEPGRAM_API_KEY";
+
+/// Where the active API key was found, so the settings UI can explain it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiKeySource {
+    Keychain,
+    /// The encrypted key file in the config dir
+    ConfigFile,
+    Environment,
+    EnvFile,
+    /// None is needed: live sessions are replayed by the mock engine
+    Mock,
+}
+
+/// The default profile keeps the original account so existing keys still resolve
+fn keychain_entry(profile: &str) -> keyring::Result<Entry> {
+    if profile == DEFAULT_PROFILE {
+        Entry::new(KEYCHAIN_SERVICE, KEYCHAIN_ACCOUNT)
+    } else {
+        Entry::new(
+            KEYCHAIN_SERVICE,
+            &format!("{}:{}", KEYCHAIN_ACCOUNT, profile),
+        )
+    }
+}
+
+/// Save the active profile's key to the keychain, or to the config file if the
+/// keychain is unavailable
+pub fn save_api_key(app: &AppHandle, key: &str) -> Result<ApiKeySource, AppError> {
+    let profile = crate::config::active_profile(app);
+    crate::logging::register_secret(key);
+    match keychain_entry(&profile).and_then(|entry| entry.set_password(key)) {
+        Ok(()) => {
+            // Don't leave an older plaintext copy behind on disk
+            let _ = key_store::clear(app, &profile);
+            Ok(ApiKeySource::Keychain)
+        }
+        Err(e) => {
+            tracing::warn!(
+                "Keychain unavailable ({}), saving API key to config file",
+                e
+            );
+            key_store::save(app, &profile, key)?;
+            Ok(ApiKeySource::ConfigFile)
+        }
+    }
+}
+
+/// Look up the active profile's key
+pub fn load_api_key(app: &AppHandle) -> Result<Option<(String, ApiKeySource)>, AppError> {
+    load_profile_key(app, &crate::config::active_profile(app))
+}
+
+/// Look up a profile's key: keychain, then encrypted file, then (default profile only)
+/// environment / .env
+/// A key file that can't be decrypted is a `KeyUnreadable` error rather than falling
+/// through, so the user is asked to enter the key again instead of silently using another
+/// A key that is found is registered for redaction from the logs
+pub fn load_profile_key(
+    app: &AppHandle,
+    profile: &str,
+) -> Result<Option<(String, ApiKeySource)>, AppError> {
+    let found = find_api_key(app, profile)?;
+    if let Some((key, _)) = &found {
+        crate::logging::register_secret(key);
+    }
+    Ok(found)
+}
+
+fn find_api_key(
+    app: &AppHandle,
+    profile: &str,
+) -> Result<Option<(String, ApiKeySource)>, AppError> {
+    match keychain_entry(profile).and_then(|entry| entry.get_password()) {
+        Ok(key) => return Ok(Some((key, ApiKeySource::Keychain))),
+        Err(keyring::Error::NoEntry) => {}
+        Err(e) => tracing::warn!("Keychain unavailable ({}), checking other sources", e),
+    }
+
+    if let Some(key) = key_store::load(app, profile)? {
+        return Ok(Some((key, ApiKeySource::ConfigFile)));
+    }
+
+    // The environment is global; letting it fill in for every profile would quietly
+    // bill a work profile to a personal key
+    if profile != DEFAULT_PROFILE {
+        return Ok(None);
+    }
+    let Some(key) = env::var(API_KEY_ENV_VAR).ok().filter(|key| !key.is_empty()) else {
+        return Ok(None);
+    };
+    let source = if crate::env_loader::loaded_from_env_file(API_KEY_ENV_VAR) {
+        ApiKeySource::EnvFile
+    } else {
+        ApiKeySource::Environment
+    };
+    Ok(Some((key, source)))
+}
+
+/// Remove the active profile's saved key from the keychain and the config file
+/// Keys from the environment are left alone
+pub fn delete_api_key(app: &AppHandle) -> Result<(), AppError> {
+    delete_profile_key(app, &crate::config::active_profile(app))
+}
+
+pub fn delete_profile_key(app: &AppHandle, profile: &str) -> Result<(), AppError> {
+    match keychain_entry(profile).and_then(|entry| entry.delete_credential()) {
+        Ok(()) | Err(keyring::Error::NoEntry) => {}
+        Err(e) => tracing::warn!("Keychain unavailable ({}), nothing to delete there", e),
+    }
+    key_store::clear(app, profile)
+}
+
+/// Save the LLM post-processing key, like the Deepgram key but shared by all profiles
+pub fn save_llm_api_key(app: &AppHandle, key: &str) -> Result<ApiKeySource, AppError> {
+    save_shared_key(app, LLM_KEYCHAIN_ACCOUNT, key_store::LLM_KEY_FILE_NAME, key)
+}
+
+/// Look up the LLM post-processing key; it never comes from the environment
+pub fn load_llm_api_key(app: &AppHandle) -> Result<Option<String>, AppError> {
+    load_shared_key(app, LLM_KEYCHAIN_ACCOUNT, key_store::LLM_KEY_FILE_NAME)
+}
+
+pub fn delete_llm_api_key(app: &AppHandle) -> Result<(), AppError> {
+    delete_shared_key(app, LLM_KEYCHAIN_ACCOUNT, key_store::LLM_KEY_FILE_NAME)
+}
+
+/// Save the translation provider's key, shared by all profiles like the LLM key
+pub fn save_translation_api_key(app: &AppHandle, key: &str) -> Result<ApiKeySource, AppError> {
+    save_shared_key(
+        app,
+        TRANSLATION_KEYCHAIN_ACCOUNT,
+        key_store::TRANSLATION_KEY_FILE_NAME,
+        key,
+    )
+}
+
+pub fn load_translation_api_key(app: &AppHandle) -> Result<Option<String>, AppError> {
+    load_shared_key(
+        app,
+        TRANSLATION_KEYCHAIN_ACCOUNT,
+        key_store::TRANSLATION_KEY_FILE_NAME,
+    )
+}
+
+pub fn delete_translation_api_key(app: &AppHandle) -> Result<(), AppError> {
+    delete_shared_key(
+        app,
+        TRANSLATION_KEYCHAIN_ACCOUNT,
+        key_store::TRANSLATION_KEY_FILE_NAME,
+    )
+}
+
+/// Save the OpenAI key for the fallback transcription provider, shared by all profiles
+pub fn save_openai_api_key(app: &AppHandle, key: &str) -> Result<ApiKeySource, AppError> {
+    save_shared_key(
+        app,
+        OPENAI_KEYCHAIN_ACCOUNT,
+        key_store::OPENAI_KEY_FILE_NAME,
+        key,
+    )
+}
+
+pub fn load_openai_api_key(app: &AppHandle) -> Result<Option<String>, AppError> {
+    load_shared_key(
+        app,
+        OPENAI_KEYCHAIN_ACCOUNT,
+        key_store::OPENAI_KEY_FILE_NAME,
+    )
+}
+
+pub fn delete_openai_api_key(app: &AppHandle) -> Result<(), AppError> {
+    delete_shared_key(
+        app,
+        OPENAI_KEYCHAIN_ACCOUNT,
+        key_store::OPENAI_KEY_FILE_NAME,
+    )
+}
+
+/// Keys that aren't per profile: one keychain account, or one key file without it
+fn save_shared_key(
+    app: &AppHandle,
+    account: &str,
+    file_name: &str,
+    key: &str,
+) -> Result<ApiKeySource, AppError> {
+    crate::logging::register_secret(key);
+    match Entry::new(KEYCHAIN_SERVICE, account).and_then(|entry| entry.set_password(key)) {
+        Ok(()) => {
+            let _ = key_store::clear_file(app, file_name);
+            Ok(ApiKeySource::Keychain)
+        }
+        Err(e) => {
+            tracing::warn!(
+                "Keychain unavailable ({}), saving {} to config file",
+                e,
+                account
+            );
+            key_store::save_file(app, file_name, key)?;
+            Ok(ApiKeySource::ConfigFile)
+        }
+    }
+}
+
+fn load_shared_key(
+    app: &AppHandle,
+    account: &str,
+    file_name: &str,
+) -> Result<Option<String>, AppError> {
+    let key = match Entry::new(KEYCHAIN_SERVICE, account).and_then(|entry| entry.get_password()) {
+        Ok(key) => Some(key),
+        Err(keyring::Error::NoEntry) => key_store::load_file(app, file_name)?,
+        Err(e) => {
+            tracing::warn!("Keychain unavailable ({}), checking the config file", e);
+            key_store::load_file(app, file_name)?
+        }
+    };
+    if let Some(key) = &key {
+        crate::logging::register_secret(key);
+    }
+    Ok(key)
+}
+
+fn delete_shared_key(app: &AppHandle, account: &str, file_name: &str) -> Result<(), AppError> {
+    match Entry::new(KEYCHAIN_SERVICE, account).and_then(|entry| entry.delete_credential()) {
+        Ok(()) | Err(keyring::Error::NoEntry) => {}
+        Err(e) => tracing::warn!("Keychain unavailable ({}), nothing to delete there", e),
+    }
+    key_store::clear_file(app, file_name)
+}
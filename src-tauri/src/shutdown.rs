@@ -0,0 +1,175 @@
+// Saving everything on the way out
+// Every exit (Quit in the tray, the last window closing, the OS asking) passes through
+// `RunEvent::ExitRequested`. The first one is held back while the capture stops, the live
+// session sends CloseStream, takes its last finals and is saved with its journal and WAV,
+// the database is checkpointed, and the queue, caption server, overlay and tray wind
+// down; then the app exits for real. All of it is capped at `SHUTDOWN_CAP` so a hung
+// network can't keep the app open, and a session cut off by the cap is still in its
+// journal, to be recovered at the next launch
+
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tauri::{AppHandle, Emitter, Manager, RunEvent};
+use tokio::time::Instant;
+
+use crate::audio::capture::CaptureState;
+use crate::deepgram::proxy::StreamState;
+use crate::engine::manager::{emitter, SessionManager};
+use crate::engine::SessionState;
+use crate::state::{blocking, AppState};
+use crate::storage::Storage;
+
+/// Event emitted once exit has started, so the UI can show that it's saving
+pub const EVENT_SHUTTING_DOWN: &str = "shutting-down";
+
+/// Longest exit waits for everything to be saved
+const SHUTDOWN_CAP: Duration = Duration::from_secs(5);
+/// Kept back from the cap for the queue to save the jobs it didn't finish
+const QUEUE_SAVE_MARGIN: Duration = Duration::from_millis(250);
+
+/// Saving has started; exit requests meanwhile wait for it
+static STARTED: AtomicBool = AtomicBool::new(false);
+/// Saving is over, so the next exit request goes through
+static FINISHED: AtomicBool = AtomicBool::new(false);
+
+/// Handler for the app's run events
+pub fn handle_run_event(app: &AppHandle, event: RunEvent) {
+    let RunEvent::ExitRequested { code, api, .. } = event else {
+        return;
+    };
+    if FINISHED.load(Ordering::Acquire) {
+        return;
+    }
+    api.prevent_exit();
+    if STARTED.swap(true, Ordering::AcqRel) {
+        return;
+    }
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let deadline = Instant::now() + SHUTDOWN_CAP;
+        if !capped(flush(&app, deadline), deadline).await {
+            tracing::warn!(
+                "Saving took over {} s; exiting anyway",
+                SHUTDOWN_CAP.as_secs()
+            );
+        }
+        FINISHED.store(true, Ordering::Release);
+        app.exit(code.unwrap_or(0));
+    });
+}
+
+/// Run `flush` until it's done or `deadline` passes; false if it was cut off
+async fn capped(flush: impl Future<Output = ()>, deadline: Instant) -> bool {
+    tokio::time::timeout_at(deadline, flush).await.is_ok()
+}
+
+/// Stop and save everything, most valuable first
+async fn flush(app: &AppHandle, deadline: Instant) {
+    let _ = app.emit(EVENT_SHUTTING_DOWN, ());
+    let capture_app = app.clone();
+    let _ = tauri::async_runtime::spawn_blocking(move || {
+        capture_app.state::<CaptureState>().shutdown()
+    })
+    .await;
+    let state = app.state::<AppState>();
+    save_then_wind_down(
+        &app.state::<SessionManager>(),
+        &emitter(app),
+        &state.stream,
+        &state.storage,
+        state.queue.shutdown(app, deadline - QUEUE_SAVE_MARGIN),
+        async {
+            state.captions.stop().await;
+            crate::overlay::close(app);
+            #[cfg(desktop)]
+            crate::tray::remove(app);
+        },
+    )
+    .await;
+}
+
+/// `flush` once the capture has stopped, over the parts of the app that hold data
+/// Stopping the stream finalizes the live session and saves it while `queue` drains;
+/// then the database is checkpointed, and `wind_down` closes everything else
+async fn save_then_wind_down(
+    sessions: &SessionManager,
+    emit: &impl Fn(SessionState),
+    stream: &StreamState,
+    storage: &Arc<Storage>,
+    queue: impl Future<Output = ()>,
+    wind_down: impl Future<Output = ()>,
+) {
+    tokio::join!(stream.shutdown_with(sessions, emit), queue);
+    let storage = Arc::clone(storage);
+    if let Err(e) = blocking(move || storage.checkpoint()).await {
+        tracing::warn!("{}", e);
+    }
+    wind_down.await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::NewSession;
+
+    fn recorded(text: String) -> NewSession {
+        NewSession {
+            duration_ms: 4_000,
+            ..NewSession::for_test(1_700_000_000_000, &text)
+        }
+    }
+
+    #[test]
+    fn exit_while_recording_saves_the_transcript_when_a_later_step_hangs() {
+        let sessions = SessionManager::default();
+        let stream = StreamState::default();
+        let storage = Arc::new(Storage::open_in_memory().unwrap());
+        let emit = |_| {};
+        tauri::async_runtime::block_on(async {
+            // Stands in for the engine: it finalizes and saves once its audio stops coming
+            let engine_storage = Arc::clone(&storage);
+            stream
+                .start_with(&sessions, &emit, move |mut audio_rx| {
+                    tauri::async_runtime::spawn(async move {
+                        let mut heard = Vec::new();
+                        while let Some(chunk) = audio_rx.recv().await {
+                            heard.push(String::from_utf8(chunk).unwrap());
+                        }
+                        let text = heard.join(" ");
+                        engine_storage
+                            .insert_session(&recorded(text.clone()))
+                            .unwrap();
+                        text
+                    })
+                })
+                .await
+                .unwrap();
+            assert_eq!(sessions.state(), SessionState::Recording);
+            for words in ["Ship it", "on Friday."] {
+                stream
+                    .forward_audio(words.as_bytes().to_vec(), 1)
+                    .await
+                    .unwrap();
+            }
+
+            // E.g. a caption server connection that never closes
+            let flush = save_then_wind_down(
+                &sessions,
+                &emit,
+                &stream,
+                &storage,
+                async {},
+                std::future::pending(),
+            );
+            let deadline = Instant::now() + Duration::from_millis(200);
+            assert!(!capped(flush, deadline).await);
+        });
+
+        assert_eq!(sessions.state(), SessionState::Idle);
+        let session = storage.get_session(1).unwrap().unwrap();
+        assert_eq!(session.text, "Ship it on Friday.");
+    }
+}
@@ -0,0 +1,120 @@
+// Shared backend state, managed once at startup
+// Holds what commands would otherwise rebuild on every call: the parsed config file,
+// the history database, one HTTP client and the active stream
+
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex, MutexGuard, RwLock};
+
+use tauri::AppHandle;
+
+use crate::caption_server::CaptionServer;
+use crate::config::AppConfig;
+use crate::deepgram::network::{self, Route};
+use crate::deepgram::proxy::StreamState;
+use crate::engine::providers::ProviderStatus;
+use crate::error::AppError;
+use crate::queue::FileQueue;
+use crate::storage::Storage;
+use crate::transcript_history::TranscriptHistory;
+
+pub struct AppState {
+    /// Last config read from or written to disk; None until first needed
+    config: RwLock<Option<AppConfig>>,
+    /// Held by `config::update` from reading the config to caching what it saved
+    config_update: Mutex<()>,
+    /// HTTP client and Deepgram route, replaced together when network settings change
+    network: RwLock<Network>,
+    pub storage: Arc<Storage>,
+    pub stream: StreamState,
+    /// What's known of the cloud providers' reachability
+    pub providers: ProviderStatus,
+    pub captions: CaptionServer,
+    pub queue: FileQueue,
+    /// Recent transcripts for copying again
+    pub transcripts: TranscriptHistory,
+    /// No settings file existed at launch; cleared when onboarding completes
+    pub first_run: AtomicBool,
+}
+
+struct Network {
+    http: reqwest::Client,
+    route: Route,
+}
+
+impl AppState {
+    pub fn init(app: &AppHandle) -> Self {
+        let first_run = !crate::config::settings_file_exists(app);
+        let config = crate::config::load(app).unwrap_or_default();
+        crate::net::limiter::configure(&config.network.limits);
+        let route = Route::from_settings(&config.network).unwrap_or_else(|e| {
+            tracing::error!("{}; using the default Deepgram endpoint", e);
+            Route::default()
+        });
+        let http = network::http_client(&route).unwrap_or_else(|e| {
+            tracing::error!("{}; using defaults", e);
+            reqwest::Client::new()
+        });
+        let storage = Arc::new(crate::storage::init(app));
+        Self {
+            config: RwLock::new(None),
+            config_update: Mutex::new(()),
+            network: RwLock::new(Network { http, route }),
+            transcripts: TranscriptHistory::load(&storage, config.transcript_history.size),
+            storage,
+            stream: StreamState::default(),
+            providers: ProviderStatus::default(),
+            captions: CaptionServer::default(),
+            queue: FileQueue::new(config.file_transcription.max_concurrent_jobs as usize),
+            first_run: AtomicBool::new(first_run),
+        }
+    }
+
+    /// Shared so connections are pooled; requests set their own overall timeout
+    pub fn http(&self) -> reqwest::Client {
+        self.network
+            .read()
+            .map(|network| network.http.clone())
+            .unwrap_or_default()
+    }
+
+    /// Where Deepgram requests go
+    pub fn route(&self) -> Route {
+        self.network
+            .read()
+            .map(|network| network.route.clone())
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn set_network(&self, http: reqwest::Client, route: Route) {
+        if let Ok(mut network) = self.network.write() {
+            *network = Network { http, route };
+        }
+    }
+
+    pub(crate) fn cached_config(&self) -> Option<AppConfig> {
+        self.config.read().ok()?.clone()
+    }
+
+    pub(crate) fn lock_config(&self) -> Result<MutexGuard<'_, ()>, AppError> {
+        self.config_update
+            .lock()
+            .map_err(|_| AppError::poisoned("Config update lock"))
+    }
+
+    pub(crate) fn cache_config(&self, config: &AppConfig) {
+        if let Ok(mut cached) = self.config.write() {
+            *cached = Some(config.clone());
+        }
+    }
+}
+
+/// Run file or database work on the blocking pool so async commands don't stall the runtime
+pub async fn blocking<T, F>(work: F) -> Result<T, AppError>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, AppError> + Send + 'static,
+{
+    tauri::async_runtime::spawn_blocking(work)
+        .await
+        .map_err(|e| AppError::Internal(format!("Background task failed: {}", e)))?
+}
@@ -0,0 +1,378 @@
+// Dictation statistics: words per day, session length, speaking rate, busiest hour and
+// the current streak of days with a session
+// Sessions are totalled in SQL per local day and hour. A finished day's totals are kept
+// in `stats_hours` once worked out, and triggers on `sessions` drop a day whose sessions
+// change, so opening the stats screen only scans today and days not seen before.
+// Speaking rate is words over un-paused duration, leaving out imported and mock sessions
+// whose durations aren't anyone speaking
+
+use std::sync::Arc;
+
+use rusqlite::{params, Connection};
+use serde::Serialize;
+use tauri::State;
+
+use crate::engine::mock;
+use crate::error::AppError;
+use crate::state::{blocking, AppState};
+use crate::storage::Storage;
+use crate::usage::UsageRange;
+
+/// One day of the range
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DayStats {
+    /// Local date, e.g. "2024-03-20"
+    pub day: String,
+    pub words: u64,
+    pub session_count: u64,
+}
+
+/// Result of `get_dictation_stats`
+#[derive(Debug, Clone, Serialize)]
+pub struct DictationStats {
+    pub range: UsageRange,
+    /// Every day of the range, oldest first, including days without a session
+    pub days: Vec<DayStats>,
+    pub words: u64,
+    pub session_count: u64,
+    /// None without a session in the range
+    pub average_session_ms: Option<u64>,
+    /// None without a session it can be worked out from
+    pub words_per_minute: Option<f64>,
+    /// Local hour (0-23) with the most words in the range
+    pub busiest_hour: Option<u8>,
+    /// Consecutive days with a session up to today, or up to yesterday while today has
+    /// none yet; not limited to the range
+    pub current_streak_days: u32,
+}
+
+/// Totals for one local hour of one day, in `stats_hours` column order
+#[derive(Debug, Clone, PartialEq)]
+struct HourStats {
+    hour: u8,
+    sessions: u64,
+    words: u64,
+    duration_ms: u64,
+    /// Words and duration of the sessions speaking rate counts
+    rated_words: u64,
+    rated_ms: u64,
+}
+
+/// Start of `day` (an SQL date expression) in milliseconds since the Unix epoch
+fn day_start_ms(day: &str) -> String {
+    format!("CAST(strftime('%s', {}, 'utc') AS INTEGER) * 1000", day)
+}
+
+/// Totals per local day and hour of the sessions matching `filter`
+fn hour_totals(filter: &str) -> String {
+    let rated = format!(
+        "imported = 0 AND provider IS NOT '{}' AND duration_ms > 0",
+        mock::PROVIDER
+    );
+    format!(
+        "SELECT date(started_at / 1000, 'unixepoch', 'localtime') AS day,
+                CAST(strftime('%H', started_at / 1000, 'unixepoch', 'localtime') AS INTEGER),
+                COUNT(*), SUM(word_count), SUM(duration_ms),
+                SUM(CASE WHEN {rated} THEN word_count ELSE 0 END),
+                SUM(CASE WHEN {rated} THEN duration_ms ELSE 0 END)
+         FROM sessions WHERE {filter}
+         GROUP BY 1, 2"
+    )
+}
+
+impl Storage {
+    /// Each day of `range` oldest first, with its hours that had sessions
+    fn dictation_hours(
+        &self,
+        range: UsageRange,
+    ) -> Result<Vec<(String, Vec<HourStats>)>, AppError> {
+        let mut conn = self.conn()?;
+        let tx = conn
+            .transaction()
+            .map_err(|e| AppError::Storage(format!("Failed to load statistics: {}", e)))?;
+        cache_finished_days(&tx, range)
+            .and_then(|_| tx.commit())
+            .map_err(|e| AppError::Storage(format!("Failed to cache statistics: {}", e)))?;
+        load_hours(&conn, range)
+            .map_err(|e| AppError::Storage(format!("Failed to load statistics: {}", e)))
+    }
+
+    /// Days in a row with a session, ending today or yesterday
+    fn current_streak(&self) -> Result<u32, AppError> {
+        let conn = self.conn()?;
+        let today: i64 = conn
+            .query_row(
+                "SELECT CAST(julianday(date('now', 'localtime')) AS INTEGER)",
+                [],
+                |row| row.get(0),
+            )
+            .map_err(|e| AppError::Storage(format!("Failed to load the streak: {}", e)))?;
+        // Newest first along the index, read only as far back as the streak goes
+        let mut stmt = conn
+            .prepare(
+                "SELECT CAST(julianday(date(started_at / 1000, 'unixepoch', 'localtime')) AS INTEGER)
+                 FROM sessions ORDER BY started_at DESC",
+            )
+            .map_err(|e| AppError::Storage(format!("Failed to load the streak: {}", e)))?;
+        let days = stmt
+            .query_map([], |row| row.get::<_, i64>(0))
+            .map_err(|e| AppError::Storage(format!("Failed to load the streak: {}", e)))?;
+        Ok(streak(today, days.map_while(Result::ok)))
+    }
+}
+
+/// Total the finished days of `range` that aren't cached yet
+fn cache_finished_days(conn: &Connection, range: UsageRange) -> rusqlite::Result<()> {
+    let missing = "WITH RECURSIVE days (day) AS (
+             SELECT date('now', 'localtime', ?1)
+             UNION ALL
+             SELECT date(day, '+1 day') FROM days WHERE day < date('now', 'localtime', '-1 day')
+         ),
+         missing AS (
+             SELECT day FROM days
+             WHERE day < date('now', 'localtime') AND day NOT IN (SELECT day FROM stats_days)
+         )";
+    let totals = hour_totals(&format!(
+        "started_at >= {} AND started_at < {}",
+        day_start_ms("date('now', 'localtime', ?1)"),
+        day_start_ms("date('now', 'localtime')")
+    ));
+    conn.execute(
+        &format!(
+            "{missing}
+             INSERT INTO stats_hours (day, hour, sessions, words, duration_ms, rated_words, rated_ms)
+             SELECT * FROM ({totals}) WHERE day IN missing"
+        ),
+        params![range.since_modifier()],
+    )?;
+    conn.execute(
+        &format!("{missing} INSERT INTO stats_days (day) SELECT day FROM missing"),
+        params![range.since_modifier()],
+    )?;
+    Ok(())
+}
+
+/// Cached finished days plus today, worked out afresh
+fn load_hours(
+    conn: &Connection,
+    range: UsageRange,
+) -> rusqlite::Result<Vec<(String, Vec<HourStats>)>> {
+    let today = hour_totals(&format!(
+        "started_at >= {}",
+        day_start_ms("date('now', 'localtime')")
+    ));
+    let mut stmt = conn.prepare(&format!(
+        "WITH RECURSIVE days (day) AS (
+             SELECT date('now', 'localtime', ?1)
+             UNION ALL
+             SELECT date(day, '+1 day') FROM days WHERE day < date('now', 'localtime')
+         ),
+         hours AS (
+             SELECT * FROM stats_hours
+             WHERE day >= date('now', 'localtime', ?1) AND day < date('now', 'localtime')
+             UNION ALL
+             SELECT * FROM ({today})
+         )
+         SELECT days.day, hour, sessions, words, duration_ms, rated_words, rated_ms
+         FROM days LEFT JOIN hours ON hours.day = days.day
+         ORDER BY days.day, hour"
+    ))?;
+    let rows = stmt.query_map(params![range.since_modifier()], |row| {
+        let day: String = row.get(0)?;
+        let hour = match row.get::<_, Option<u8>>(1)? {
+            Some(hour) => Some(HourStats {
+                hour,
+                sessions: row.get(2)?,
+                words: row.get(3)?,
+                duration_ms: row.get(4)?,
+                rated_words: row.get(5)?,
+                rated_ms: row.get(6)?,
+            }),
+            None => None,
+        };
+        Ok((day, hour))
+    })?;
+
+    let mut days: Vec<(String, Vec<HourStats>)> = Vec::new();
+    for row in rows {
+        let (day, hour) = row?;
+        if days.last().is_none_or(|(last, _)| *last != day) {
+            days.push((day, Vec::new()));
+        }
+        if let (Some(hour), Some((_, hours))) = (hour, days.last_mut()) {
+            hours.push(hour);
+        }
+    }
+    Ok(days)
+}
+
+/// Length of the run of consecutive days in `days` (Julian day numbers, newest first,
+/// repeats allowed) ending today, or yesterday when today has nothing yet
+fn streak(today: i64, days: impl IntoIterator<Item = i64>) -> u32 {
+    let mut streak = 0;
+    let mut next = today;
+    for day in days {
+        if day > next {
+            // Another session on a day already counted, or a clock that was ahead
+            continue;
+        }
+        let skips_today = streak == 0 && next == today && day == today - 1;
+        if day < next && !skips_today {
+            break;
+        }
+        streak += 1;
+        next = day - 1;
+    }
+    streak
+}
+
+fn summarize(
+    range: UsageRange,
+    hours: Vec<(String, Vec<HourStats>)>,
+    streak: u32,
+) -> DictationStats {
+    let mut by_hour = [0u64; 24];
+    let (mut rated_words, mut rated_ms, mut duration_ms) = (0, 0, 0);
+    let days: Vec<DayStats> = hours
+        .into_iter()
+        .map(|(day, hours)| {
+            for hour in &hours {
+                if let Some(total) = by_hour.get_mut(usize::from(hour.hour)) {
+                    *total += hour.words;
+                }
+                rated_words += hour.rated_words;
+                rated_ms += hour.rated_ms;
+                duration_ms += hour.duration_ms;
+            }
+            DayStats {
+                day,
+                words: hours.iter().map(|hour| hour.words).sum(),
+                session_count: hours.iter().map(|hour| hour.sessions).sum(),
+            }
+        })
+        .collect();
+    let words = days.iter().map(|day| day.words).sum();
+    let session_count = days.iter().map(|day| day.session_count).sum();
+    let busiest_hour = (0..24u8)
+        .filter(|&hour| by_hour[usize::from(hour)] > 0)
+        .max_by_key(|&hour| (by_hour[usize::from(hour)], std::cmp::Reverse(hour)));
+
+    DictationStats {
+        range,
+        days,
+        words,
+        session_count,
+        average_session_ms: (session_count > 0).then(|| duration_ms / session_count),
+        words_per_minute: (rated_ms > 0).then(|| rated_words as f64 / (rated_ms as f64 / 60_000.0)),
+        busiest_hour,
+        current_streak_days: streak,
+    }
+}
+
+/// Command to total up dictation over a range, for the stats screen
+#[tauri::command]
+pub async fn get_dictation_stats(
+    state: State<'_, AppState>,
+    range: UsageRange,
+) -> Result<DictationStats, AppError> {
+    let storage = Arc::clone(&state.storage);
+    blocking(move || {
+        let hours = storage.dictation_hours(range)?;
+        Ok(summarize(range, hours, storage.current_streak()?))
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::NewSession;
+
+    const DAY_MS: i64 = 24 * 60 * 60 * 1000;
+
+    fn session(started_at: i64, text: &str, duration_ms: i64) -> NewSession {
+        NewSession {
+            duration_ms,
+            ..NewSession::for_test(started_at, text)
+        }
+    }
+
+    /// Noon local time, `days_ago` days back
+    fn noon(storage: &Storage, days_ago: i64) -> i64 {
+        storage
+            .conn()
+            .unwrap()
+            .query_row(
+                &format!(
+                    "SELECT {}",
+                    day_start_ms("date('now', 'localtime', ?1 || ' days')")
+                ),
+                params![-days_ago],
+                |row| row.get::<_, i64>(0),
+            )
+            .unwrap()
+            + DAY_MS / 2
+    }
+
+    #[test]
+    fn totals_days_skip_imported_and_mock_rates_and_follow_changes_to_cached_days() {
+        let storage = Storage::open_in_memory().unwrap();
+        let (today, yesterday) = (noon(&storage, 0), noon(&storage, 1));
+        // 4 words in 2 s and 6 words in 3 s: 120 words a minute
+        storage
+            .insert_session(&session(today, "Ship it on Friday.", 2_000))
+            .unwrap();
+        storage
+            .insert_session(&session(yesterday, "Then we wait for the review.", 3_000))
+            .unwrap();
+        let mut imported = session(yesterday, "One two three four five six", 1_000);
+        imported.imported = true;
+        storage.insert_session(&imported).unwrap();
+        let mut mocked = session(today + 60 * 60 * 1000, "So the plan.", 100);
+        mocked.provider = Some(mock::PROVIDER.to_string());
+        storage.insert_session(&mocked).unwrap();
+
+        let stats = summarize(
+            UsageRange::Week,
+            storage.dictation_hours(UsageRange::Week).unwrap(),
+            0,
+        );
+        assert_eq!(stats.days.len(), 7);
+        assert_eq!(
+            stats.days[5..]
+                .iter()
+                .map(|day| (day.words, day.session_count))
+                .collect::<Vec<_>>(),
+            vec![(12, 2), (7, 2)]
+        );
+        assert_eq!((stats.words, stats.session_count), (19, 4));
+        assert_eq!(stats.average_session_ms, Some(1_525));
+        assert!((stats.words_per_minute.unwrap() - 120.0).abs() < 1e-9);
+        assert_eq!(stats.busiest_hour, Some(12));
+
+        // Yesterday is cached now; another session on it has to show up all the same
+        storage
+            .insert_session(&session(yesterday - 1_000, "Also this.", 1_000))
+            .unwrap();
+        let days = storage.dictation_hours(UsageRange::Week).unwrap();
+        let (_, hours) = &days[5];
+        assert_eq!(
+            (
+                hours.iter().map(|hour| hour.words).sum::<u64>(),
+                hours.len()
+            ),
+            (14, 2)
+        );
+        assert_eq!(storage.current_streak().unwrap(), 2);
+    }
+
+    #[test]
+    fn a_streak_survives_until_a_day_is_missed() {
+        assert_eq!(streak(100, []), 0);
+        assert_eq!(streak(100, [100, 100, 99, 98, 96]), 3);
+        // Nothing yet today still goes on from yesterday
+        assert_eq!(streak(100, [99, 99, 98]), 2);
+        assert_eq!(streak(100, [98, 97]), 0);
+        assert_eq!(streak(100, [101, 100, 99]), 2);
+    }
+}
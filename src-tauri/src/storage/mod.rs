@@ -0,0 +1,1215 @@
+// Transcript history stored in SQLite (app data dir)
+// Each finished stream becomes one session; search uses an FTS5 index over the text,
+// title and note. Sessions can be tagged, and listed filtered by tags, date and text
+// Pinned sessions are kept whatever the retention settings say, see `retention`
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, State};
+
+use crate::error::AppError;
+use crate::state::{blocking, AppState};
+
+pub mod retention;
+
+/// File name of the history database inside the app data dir
+const DB_FILE_NAME: &str = "history.db";
+/// Page size used when the frontend doesn't pass a limit
+const DEFAULT_PAGE_SIZE: u32 = 50;
+
+/// Schema migrations, applied in order; `PRAGMA user_version` records how many have run
+/// Never edit an entry once released, only append new ones
+const MIGRATIONS: &[&str] = &[
+    r#"
+    CREATE TABLE sessions (
+        id          INTEGER PRIMARY KEY AUTOINCREMENT,
+        started_at  INTEGER NOT NULL,
+        duration_ms INTEGER NOT NULL,
+        model       TEXT NOT NULL,
+        language    TEXT NOT NULL,
+        text        TEXT NOT NULL,
+        word_count  INTEGER NOT NULL,
+        audio_path  TEXT
+    );
+    CREATE INDEX sessions_started_at ON sessions (started_at DESC);
+
+    CREATE VIRTUAL TABLE sessions_fts USING fts5 (
+        text,
+        content = 'sessions',
+        content_rowid = 'id'
+    );
+    CREATE TRIGGER sessions_fts_insert AFTER INSERT ON sessions BEGIN
+        INSERT INTO sessions_fts (rowid, text) VALUES (new.id, new.text);
+    END;
+    CREATE TRIGGER sessions_fts_delete AFTER DELETE ON sessions BEGIN
+        INSERT INTO sessions_fts (sessions_fts, rowid, text) VALUES ('delete', old.id, old.text);
+    END;
+    CREATE TRIGGER sessions_fts_update AFTER UPDATE OF text ON sessions BEGIN
+        INSERT INTO sessions_fts (sessions_fts, rowid, text) VALUES ('delete', old.id, old.text);
+        INSERT INTO sessions_fts (rowid, text) VALUES (new.id, new.text);
+    END;
+"#,
+    r#"
+    CREATE TABLE segments (
+        id         INTEGER PRIMARY KEY AUTOINCREMENT,
+        session_id INTEGER NOT NULL REFERENCES sessions (id) ON DELETE CASCADE,
+        position   INTEGER NOT NULL,
+        start_ms   INTEGER NOT NULL,
+        end_ms     INTEGER NOT NULL,
+        text       TEXT NOT NULL,
+        words      TEXT
+    );
+    CREATE INDEX segments_session ON segments (session_id, position);
+"#,
+    r#"
+    CREATE TABLE usage (
+        day      TEXT NOT NULL,
+        model    TEXT NOT NULL,
+        audio_ms INTEGER NOT NULL,
+        sessions INTEGER NOT NULL,
+        PRIMARY KEY (day, model)
+    );
+"#,
+    r#"
+    ALTER TABLE segments ADD COLUMN speaker INTEGER;
+"#,
+    r#"
+    ALTER TABLE sessions ADD COLUMN detected_language TEXT;
+"#,
+    r#"
+    ALTER TABLE sessions ADD COLUMN recovered INTEGER NOT NULL DEFAULT 0;
+"#,
+    r#"
+    CREATE TABLE meta (
+        key   TEXT PRIMARY KEY,
+        value INTEGER NOT NULL
+    );
+"#,
+    r#"
+    ALTER TABLE sessions ADD COLUMN audio_source TEXT;
+"#,
+    r#"
+    ALTER TABLE sessions ADD COLUMN refined_text TEXT;
+    ALTER TABLE sessions ADD COLUMN refined_mode TEXT;
+"#,
+    r#"
+    ALTER TABLE sessions ADD COLUMN imported INTEGER NOT NULL DEFAULT 0;
+"#,
+    r#"
+    ALTER TABLE sessions ADD COLUMN title TEXT;
+    ALTER TABLE sessions ADD COLUMN note TEXT;
+
+    CREATE TABLE session_tags (
+        session_id INTEGER NOT NULL REFERENCES sessions (id) ON DELETE CASCADE,
+        tag        TEXT NOT NULL,
+        PRIMARY KEY (session_id, tag)
+    );
+    CREATE INDEX session_tags_tag ON session_tags (tag);
+
+    DROP TRIGGER sessions_fts_insert;
+    DROP TRIGGER sessions_fts_delete;
+    DROP TRIGGER sessions_fts_update;
+    DROP TABLE sessions_fts;
+    CREATE VIRTUAL TABLE sessions_fts USING fts5 (
+        text,
+        title,
+        note,
+        content = 'sessions',
+        content_rowid = 'id'
+    );
+    CREATE TRIGGER sessions_fts_insert AFTER INSERT ON sessions BEGIN
+        INSERT INTO sessions_fts (rowid, text, title, note)
+        VALUES (new.id, new.text, new.title, new.note);
+    END;
+    CREATE TRIGGER sessions_fts_delete AFTER DELETE ON sessions BEGIN
+        INSERT INTO sessions_fts (sessions_fts, rowid, text, title, note)
+        VALUES ('delete', old.id, old.text, old.title, old.note);
+    END;
+    CREATE TRIGGER sessions_fts_update AFTER UPDATE OF text, title, note ON sessions BEGIN
+        INSERT INTO sessions_fts (sessions_fts, rowid, text, title, note)
+        VALUES ('delete', old.id, old.text, old.title, old.note);
+        INSERT INTO sessions_fts (rowid, text, title, note)
+        VALUES (new.id, new.text, new.title, new.note);
+    END;
+    INSERT INTO sessions_fts (sessions_fts) VALUES ('rebuild');
+"#,
+    r#"
+    ALTER TABLE sessions ADD COLUMN translated_text TEXT;
+    ALTER TABLE sessions ADD COLUMN translation_language TEXT;
+    ALTER TABLE segments ADD COLUMN translated TEXT;
+"#,
+    r#"
+    ALTER TABLE segments ADD COLUMN channel INTEGER;
+    ALTER TABLE segments ADD COLUMN speaker_name TEXT;
+"#,
+    r#"
+    ALTER TABLE sessions ADD COLUMN provider TEXT;
+"#,
+    r#"
+    ALTER TABLE sessions ADD COLUMN pinned INTEGER NOT NULL DEFAULT 0;
+"#,
+    r#"
+    ALTER TABLE sessions ADD COLUMN request_id TEXT;
+    ALTER TABLE sessions ADD COLUMN model_version TEXT;
+"#,
+    r#"
+    ALTER TABLE sessions ADD COLUMN disfluencies_removed INTEGER;
+"#,
+    r#"
+    CREATE TABLE stats_days (
+        day TEXT PRIMARY KEY
+    );
+    CREATE TABLE stats_hours (
+        day         TEXT NOT NULL,
+        hour        INTEGER NOT NULL,
+        sessions    INTEGER NOT NULL,
+        words       INTEGER NOT NULL,
+        duration_ms INTEGER NOT NULL,
+        rated_words INTEGER NOT NULL,
+        rated_ms    INTEGER NOT NULL,
+        PRIMARY KEY (day, hour)
+    );
+    CREATE TRIGGER stats_insert AFTER INSERT ON sessions BEGIN
+        DELETE FROM stats_days WHERE day = date(new.started_at / 1000, 'unixepoch', 'localtime');
+        DELETE FROM stats_hours WHERE day = date(new.started_at / 1000, 'unixepoch', 'localtime');
+    END;
+    CREATE TRIGGER stats_delete AFTER DELETE ON sessions BEGIN
+        DELETE FROM stats_days WHERE day = date(old.started_at / 1000, 'unixepoch', 'localtime');
+        DELETE FROM stats_hours WHERE day = date(old.started_at / 1000, 'unixepoch', 'localtime');
+    END;
+    CREATE TRIGGER stats_update
+    AFTER UPDATE OF started_at, duration_ms, word_count, imported, provider ON sessions BEGIN
+        DELETE FROM stats_days WHERE day IN (
+            date(old.started_at / 1000, 'unixepoch', 'localtime'),
+            date(new.started_at / 1000, 'unixepoch', 'localtime')
+        );
+        DELETE FROM stats_hours WHERE day IN (
+            date(old.started_at / 1000, 'unixepoch', 'localtime'),
+            date(new.started_at / 1000, 'unixepoch', 'localtime')
+        );
+    END;
+"#,
+];
+
+/// Columns `Session::from_row` reads, in order; the tags come as a JSON array
+const SESSION_COLUMNS: &str = "id, started_at, duration_ms, model, language, text, word_count, audio_path, detected_language, recovered, audio_source, refined_text, refined_mode, imported, title, note, \
+    (SELECT json_group_array(tag) FROM (SELECT tag FROM session_tags WHERE session_id = sessions.id ORDER BY tag)), \
+    translated_text, translation_language, provider, pinned, request_id, model_version, disfluencies_removed";
+/// Longest tag accepted, in characters
+const MAX_TAG_CHARS: usize = 64;
+
+/// A recorded transcription session
+#[derive(Debug, Clone, Serialize)]
+pub struct Session {
+    pub id: i64,
+    /// Unix time in milliseconds
+    pub started_at: i64,
+    pub duration_ms: i64,
+    pub model: String,
+    pub language: String,
+    pub text: String,
+    pub word_count: i64,
+    pub audio_path: Option<String>,
+    /// Language Deepgram detected, when `language` was "auto"
+    pub detected_language: Option<String>,
+    /// Rebuilt from its journal after the app quit mid-session
+    pub recovered: bool,
+    /// "microphone", "system" or "both" when the backend captured the audio
+    pub audio_source: Option<String>,
+    /// Latest LLM rewrite of `text`, which itself is never changed by one
+    pub refined_text: Option<String>,
+    /// "summary", "cleanup" or "bullets"
+    pub refined_mode: Option<String>,
+    /// Read from a transcript or subtitle file rather than transcribed here
+    pub imported: bool,
+    pub title: Option<String>,
+    pub note: Option<String>,
+    /// Normalized (trimmed, lowercase) and sorted
+    pub tags: Vec<String>,
+    /// `text` translated into `translation_language`, when translation was on
+    pub translated_text: Option<String>,
+    pub translation_language: Option<String>,
+    /// Service that transcribed it, e.g. "deepgram" or "openai"; None when it was
+    /// transcribed locally, imported or saved by an older version
+    pub provider: Option<String>,
+    /// Exempt from automatic cleanup
+    pub pinned: bool,
+    /// Deepgram's id for the request that transcribed it, comma-separated when a
+    /// reconnect took more than one; None for other providers and older sessions
+    pub request_id: Option<String>,
+    /// The model that actually served it, e.g. "2-general-nova 2024-01-09.29447"
+    pub model_version: Option<String>,
+    /// Filler words and repeats the disfluency pass took out; None when it was off
+    pub disfluencies_removed: Option<u32>,
+}
+
+impl Session {
+    fn from_row(row: &Row<'_>) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            started_at: row.get(1)?,
+            duration_ms: row.get(2)?,
+            model: row.get(3)?,
+            language: row.get(4)?,
+            text: row.get(5)?,
+            word_count: row.get(6)?,
+            audio_path: row.get(7)?,
+            detected_language: row.get(8)?,
+            recovered: row.get(9)?,
+            audio_source: row.get(10)?,
+            refined_text: row.get(11)?,
+            refined_mode: row.get(12)?,
+            imported: row.get(13)?,
+            title: row.get(14)?,
+            note: row.get(15)?,
+            tags: serde_json::from_str(&row.get::<_, String>(16)?).unwrap_or_default(),
+            translated_text: row.get(17)?,
+            translation_language: row.get(18)?,
+            provider: row.get(19)?,
+            pinned: row.get(20)?,
+            request_id: row.get(21)?,
+            model_version: row.get(22)?,
+            disfluencies_removed: row.get(23)?,
+        })
+    }
+}
+
+/// One finalized stretch of a transcript with its timing
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Segment {
+    pub start_ms: i64,
+    pub end_ms: i64,
+    pub text: String,
+    /// Word-level timings, when Deepgram returned them
+    pub words: Option<Vec<Word>>,
+    /// Diarized speaker, numbered from 0
+    pub speaker: Option<u32>,
+    /// `text` in the session's translation language, when it could be translated
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub translated: Option<String>,
+    /// Audio channel of a multichannel session, numbered from 0
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel: Option<u32>,
+    /// Who's on that channel, from the channel names when it was transcribed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub speaker_name: Option<String>,
+}
+
+/// A recognized word with its timing (stored as JSON alongside its segment)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Word {
+    pub text: String,
+    pub start_ms: i64,
+    pub end_ms: i64,
+    pub confidence: f64,
+}
+
+/// The start of a session's text, for lists that don't need all of it
+pub struct SessionPreview {
+    pub id: i64,
+    /// Unix time in milliseconds the session ended
+    pub ended_at: i64,
+    /// At most the requested number of characters
+    pub text: String,
+    /// Characters in the full text
+    pub char_count: i64,
+}
+
+/// Filters for `list_sessions`; a session must match all of them
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct SessionFilter {
+    /// Has every one of these tags
+    pub tags: Vec<String>,
+    /// Started at or after, Unix time in milliseconds
+    pub date_from: Option<i64>,
+    /// Started at or before, Unix time in milliseconds
+    pub date_to: Option<i64>,
+    /// Full-text match on the transcript, title or note, like `search_sessions`
+    pub text_query: Option<String>,
+}
+
+/// A tag and how many sessions have it, as returned by `list_tags`
+#[derive(Debug, Clone, Serialize)]
+pub struct TagCount {
+    pub tag: String,
+    pub count: i64,
+}
+
+/// Which Deepgram request and model transcribed a session, for the diagnostics bundle
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionRequest {
+    pub id: i64,
+    /// Unix time in milliseconds
+    pub started_at: i64,
+    /// The model setting it was started with
+    pub model: String,
+    pub request_id: String,
+    pub model_version: Option<String>,
+}
+
+/// A finished session about to be saved
+pub struct NewSession {
+    pub started_at: i64,
+    pub duration_ms: i64,
+    pub model: String,
+    pub language: String,
+    pub detected_language: Option<String>,
+    pub text: String,
+    pub audio_path: Option<PathBuf>,
+    pub segments: Vec<Segment>,
+    pub recovered: bool,
+    pub audio_source: Option<String>,
+    pub imported: bool,
+    pub translated_text: Option<String>,
+    pub translation_language: Option<String>,
+    pub provider: Option<String>,
+    pub request_id: Option<String>,
+    pub model_version: Option<String>,
+    pub disfluencies_removed: Option<u32>,
+}
+
+#[cfg(test)]
+impl NewSession {
+    /// A one-second Deepgram session saying `text`, for tests to adjust
+    pub(crate) fn for_test(started_at: i64, text: &str) -> Self {
+        Self {
+            started_at,
+            duration_ms: 1000,
+            model: "nova-3".to_string(),
+            language: "en".to_string(),
+            detected_language: None,
+            text: text.to_string(),
+            audio_path: None,
+            segments: Vec::new(),
+            recovered: false,
+            audio_source: None,
+            imported: false,
+            translated_text: None,
+            translation_language: None,
+            provider: Some("deepgram".to_string()),
+            request_id: None,
+            model_version: None,
+            disfluencies_removed: None,
+        }
+    }
+}
+
+/// Managed handle to the history database
+pub struct Storage {
+    conn: Mutex<Connection>,
+}
+
+impl Storage {
+    /// Open (or create) the database at `path` and bring its schema up to date
+    pub fn open(path: &Path) -> Result<Self, AppError> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| {
+                AppError::Storage(format!("Failed to create data directory: {}", e))
+            })?;
+        }
+        let conn = Connection::open(path)
+            .map_err(|e| AppError::Storage(format!("Failed to open {}: {}", path.display(), e)))?;
+        conn.pragma_update(None, "journal_mode", "WAL")
+            .map_err(|e| AppError::Storage(format!("Failed to enable WAL: {}", e)))?;
+        Self::init(conn)
+    }
+
+    /// In-memory database, used when the data dir isn't writable so the app still runs
+    pub fn open_in_memory() -> Result<Self, AppError> {
+        let conn = Connection::open_in_memory()
+            .map_err(|e| AppError::Storage(format!("Failed to open in-memory database: {}", e)))?;
+        Self::init(conn)
+    }
+
+    fn init(mut conn: Connection) -> Result<Self, AppError> {
+        conn.pragma_update(None, "foreign_keys", true)
+            .map_err(|e| AppError::Storage(format!("Failed to enable foreign keys: {}", e)))?;
+        migrate(&mut conn)?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    pub(crate) fn conn(&self) -> Result<MutexGuard<'_, Connection>, AppError> {
+        self.conn
+            .lock()
+            .map_err(|_| AppError::poisoned("History database"))
+    }
+
+    /// Save a finished session and its segments, returning its id
+    pub fn insert_session(&self, session: &NewSession) -> Result<i64, AppError> {
+        let mut conn = self.conn()?;
+        let tx = conn
+            .transaction()
+            .map_err(|e| AppError::Storage(format!("Failed to save session: {}", e)))?;
+        let id = insert_with_segments(&tx, session)
+            .and_then(|id| tx.commit().map(|_| id))
+            .map_err(|e| AppError::Storage(format!("Failed to save session: {}", e)))?;
+        Ok(id)
+    }
+
+    /// Move everything committed from the write-ahead log into the database file, so
+    /// nothing is left depending on the log when the app exits
+    pub fn checkpoint(&self) -> Result<(), AppError> {
+        self.conn()?
+            .query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))
+            .map_err(|e| AppError::Storage(format!("Failed to checkpoint the database: {}", e)))
+    }
+
+    /// Sessions rebuilt from journals, newest first
+    pub fn recovered_sessions(&self) -> Result<Vec<Session>, AppError> {
+        let conn = self.conn()?;
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT {} FROM sessions WHERE recovered = 1 ORDER BY started_at DESC, id DESC",
+                SESSION_COLUMNS
+            ))
+            .map_err(|e| AppError::Storage(format!("Failed to list sessions: {}", e)))?;
+        let rows = stmt
+            .query_map([], Session::from_row)
+            .map_err(|e| AppError::Storage(format!("Failed to list sessions: {}", e)))?;
+        rows.collect::<rusqlite::Result<_>>()
+            .map_err(|e| AppError::Storage(format!("Failed to list sessions: {}", e)))
+    }
+
+    /// Previews of sessions from today (local time) that ended after `since`, newest first
+    pub fn todays_previews(
+        &self,
+        since: i64,
+        limit: usize,
+        preview_chars: usize,
+    ) -> Result<Vec<SessionPreview>, AppError> {
+        let conn = self.conn()?;
+        // substr and length count characters, so previews never split one
+        let mut stmt = conn
+            .prepare(
+                "SELECT id, started_at + duration_ms, substr(text, 1, ?3), length(text) FROM sessions
+                 WHERE date(started_at / 1000, 'unixepoch', 'localtime') = date('now', 'localtime')
+                   AND started_at + duration_ms > ?1
+                 ORDER BY started_at DESC, id DESC LIMIT ?2",
+            )
+            .map_err(|e| AppError::Storage(format!("Failed to list sessions: {}", e)))?;
+        let rows = stmt
+            .query_map(params![since, limit as i64, preview_chars as i64], |row| {
+                Ok(SessionPreview {
+                    id: row.get(0)?,
+                    ended_at: row.get(1)?,
+                    text: row.get(2)?,
+                    char_count: row.get(3)?,
+                })
+            })
+            .map_err(|e| AppError::Storage(format!("Failed to list sessions: {}", e)))?;
+        rows.collect::<rusqlite::Result<_>>()
+            .map_err(|e| AppError::Storage(format!("Failed to list sessions: {}", e)))
+    }
+
+    /// Small persistent values that don't warrant their own table
+    pub fn meta(&self, key: &str) -> Result<Option<i64>, AppError> {
+        self.conn()?
+            .query_row(
+                "SELECT value FROM meta WHERE key = ?1",
+                params![key],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| AppError::Storage(format!("Failed to read {}: {}", key, e)))
+    }
+
+    pub fn set_meta(&self, key: &str, value: i64) -> Result<(), AppError> {
+        self.conn()?
+            .execute(
+                "INSERT INTO meta (key, value) VALUES (?1, ?2)
+                 ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+                params![key, value],
+            )
+            .map_err(|e| AppError::Storage(format!("Failed to save {}: {}", key, e)))?;
+        Ok(())
+    }
+
+    /// Sessions matching `filter`, newest first
+    pub fn list_sessions(
+        &self,
+        limit: u32,
+        offset: u32,
+        filter: &SessionFilter,
+    ) -> Result<Vec<Session>, AppError> {
+        let mut conditions = vec!["1".to_string()];
+        let mut values = Vec::new();
+        for tag in &filter.tags {
+            values.push(Value::Text(normalize_tag(tag)?));
+            conditions.push(format!(
+                "EXISTS (SELECT 1 FROM session_tags WHERE session_id = sessions.id AND tag = ?{})",
+                values.len()
+            ));
+        }
+        if let Some(from) = filter.date_from {
+            values.push(Value::Integer(from));
+            conditions.push(format!("started_at >= ?{}", values.len()));
+        }
+        if let Some(to) = filter.date_to {
+            values.push(Value::Integer(to));
+            conditions.push(format!("started_at <= ?{}", values.len()));
+        }
+        if let Some(fts_query) = filter.text_query.as_deref().and_then(fts_query) {
+            values.push(Value::Text(fts_query));
+            conditions.push(format!(
+                "id IN (SELECT rowid FROM sessions_fts WHERE sessions_fts MATCH ?{})",
+                values.len()
+            ));
+        }
+        values.push(Value::Integer(limit.into()));
+        values.push(Value::Integer(offset.into()));
+
+        let conn = self.conn()?;
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT {} FROM sessions WHERE {} ORDER BY started_at DESC, id DESC LIMIT ?{} OFFSET ?{}",
+                SESSION_COLUMNS,
+                conditions.join(" AND "),
+                values.len() - 1,
+                values.len()
+            ))
+            .map_err(|e| AppError::Storage(format!("Failed to list sessions: {}", e)))?;
+        let rows = stmt
+            .query_map(params_from_iter(values), Session::from_row)
+            .map_err(|e| AppError::Storage(format!("Failed to list sessions: {}", e)))?;
+        rows.collect::<rusqlite::Result<_>>()
+            .map_err(|e| AppError::Storage(format!("Failed to list sessions: {}", e)))
+    }
+
+    pub fn get_session(&self, id: i64) -> Result<Option<Session>, AppError> {
+        let conn = self.conn()?;
+        conn.query_row(
+            &format!("SELECT {} FROM sessions WHERE id = ?1", SESSION_COLUMNS),
+            params![id],
+            Session::from_row,
+        )
+        .optional()
+        .map_err(|e| AppError::Storage(format!("Failed to load session: {}", e)))
+    }
+
+    /// Timed segments of a session in order (empty if none were recorded)
+    pub fn segments(&self, session_id: i64) -> Result<Vec<Segment>, AppError> {
+        let conn = self.conn()?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT start_ms, end_ms, text, words, speaker, translated, channel, speaker_name
+                 FROM segments
+                 WHERE session_id = ?1 ORDER BY position",
+            )
+            .map_err(|e| AppError::Storage(format!("Failed to load segments: {}", e)))?;
+        let rows = stmt
+            .query_map(params![session_id], |row| {
+                let words: Option<String> = row.get(3)?;
+                Ok(Segment {
+                    start_ms: row.get(0)?,
+                    end_ms: row.get(1)?,
+                    text: row.get(2)?,
+                    // A corrupt words column only loses word timings, not the segment
+                    words: words.and_then(|json| serde_json::from_str(&json).ok()),
+                    speaker: row.get(4)?,
+                    translated: row.get(5)?,
+                    channel: row.get(6)?,
+                    speaker_name: row.get(7)?,
+                })
+            })
+            .map_err(|e| AppError::Storage(format!("Failed to load segments: {}", e)))?;
+        rows.collect::<rusqlite::Result<_>>()
+            .map_err(|e| AppError::Storage(format!("Failed to load segments: {}", e)))
+    }
+
+    /// Point a session at its saved audio file
+    pub fn set_audio_path(&self, id: i64, path: &Path) -> Result<(), AppError> {
+        self.conn()?
+            .execute(
+                "UPDATE sessions SET audio_path = ?2 WHERE id = ?1",
+                params![id, path.to_string_lossy()],
+            )
+            .map_err(|e| AppError::Storage(format!("Failed to save audio path: {}", e)))?;
+        Ok(())
+    }
+
+    /// Store an LLM rewrite of a session, replacing any earlier one
+    pub fn set_refined(&self, id: i64, text: &str, mode: &str) -> Result<(), AppError> {
+        let updated = self
+            .conn()?
+            .execute(
+                "UPDATE sessions SET refined_text = ?2, refined_mode = ?3 WHERE id = ?1",
+                params![id, text, mode],
+            )
+            .map_err(|e| AppError::Storage(format!("Failed to save refined text: {}", e)))?;
+        if updated == 0 {
+            return Err(AppError::NotFound(format!("Session {} not found", id)));
+        }
+        Ok(())
+    }
+
+    /// Set or, with None, clear a session's title or note
+    fn set_label(&self, id: i64, column: &str, value: Option<&str>) -> Result<(), AppError> {
+        let updated = self
+            .conn()?
+            .execute(
+                &format!("UPDATE sessions SET {} = ?2 WHERE id = ?1", column),
+                params![id, value],
+            )
+            .map_err(|e| AppError::Storage(format!("Failed to save {}: {}", column, e)))?;
+        if updated == 0 {
+            return Err(AppError::NotFound(format!("Session {} not found", id)));
+        }
+        Ok(())
+    }
+
+    pub fn set_pinned(&self, id: i64, pinned: bool) -> Result<(), AppError> {
+        let updated = self
+            .conn()?
+            .execute(
+                "UPDATE sessions SET pinned = ?2 WHERE id = ?1",
+                params![id, pinned],
+            )
+            .map_err(|e| AppError::Storage(format!("Failed to pin session: {}", e)))?;
+        if updated == 0 {
+            return Err(AppError::NotFound(format!("Session {} not found", id)));
+        }
+        Ok(())
+    }
+
+    pub fn set_title(&self, id: i64, title: Option<&str>) -> Result<(), AppError> {
+        self.set_label(id, "title", title)
+    }
+
+    pub fn set_note(&self, id: i64, note: Option<&str>) -> Result<(), AppError> {
+        self.set_label(id, "note", note)
+    }
+
+    /// Tag a session; tagging it twice with the same tag is a no-op
+    pub fn add_tag(&self, id: i64, tag: &str) -> Result<(), AppError> {
+        let tag = normalize_tag(tag)?;
+        let conn = self.conn()?;
+        let exists = conn
+            .query_row("SELECT 1 FROM sessions WHERE id = ?1", params![id], |_| {
+                Ok(())
+            })
+            .optional()
+            .map_err(|e| AppError::Storage(format!("Failed to add tag: {}", e)))?;
+        if exists.is_none() {
+            return Err(AppError::NotFound(format!("Session {} not found", id)));
+        }
+        conn.execute(
+            "INSERT OR IGNORE INTO session_tags (session_id, tag) VALUES (?1, ?2)",
+            params![id, tag],
+        )
+        .map_err(|e| AppError::Storage(format!("Failed to add tag: {}", e)))?;
+        Ok(())
+    }
+
+    /// Untag a session; removing a tag it doesn't have is a no-op
+    pub fn remove_tag(&self, id: i64, tag: &str) -> Result<(), AppError> {
+        let tag = normalize_tag(tag)?;
+        self.conn()?
+            .execute(
+                "DELETE FROM session_tags WHERE session_id = ?1 AND tag = ?2",
+                params![id, tag],
+            )
+            .map_err(|e| AppError::Storage(format!("Failed to remove tag: {}", e)))?;
+        Ok(())
+    }
+
+    /// Every tag in use, most used first
+    pub fn tag_counts(&self) -> Result<Vec<TagCount>, AppError> {
+        let conn = self.conn()?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT tag, count(*) AS uses FROM session_tags
+                 GROUP BY tag ORDER BY uses DESC, tag",
+            )
+            .map_err(|e| AppError::Storage(format!("Failed to list tags: {}", e)))?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok(TagCount {
+                    tag: row.get(0)?,
+                    count: row.get(1)?,
+                })
+            })
+            .map_err(|e| AppError::Storage(format!("Failed to list tags: {}", e)))?;
+        rows.collect::<rusqlite::Result<_>>()
+            .map_err(|e| AppError::Storage(format!("Failed to list tags: {}", e)))
+    }
+
+    /// The latest `limit` sessions that recorded a Deepgram request id, newest first
+    pub fn recent_requests(&self, limit: u32) -> Result<Vec<SessionRequest>, AppError> {
+        let conn = self.conn()?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT id, started_at, model, request_id, model_version FROM sessions
+                 WHERE request_id IS NOT NULL ORDER BY started_at DESC LIMIT ?1",
+            )
+            .map_err(|e| AppError::Storage(format!("Failed to list requests: {}", e)))?;
+        let rows = stmt
+            .query_map(params![limit], |row| {
+                Ok(SessionRequest {
+                    id: row.get(0)?,
+                    started_at: row.get(1)?,
+                    model: row.get(2)?,
+                    request_id: row.get(3)?,
+                    model_version: row.get(4)?,
+                })
+            })
+            .map_err(|e| AppError::Storage(format!("Failed to list requests: {}", e)))?;
+        rows.collect::<rusqlite::Result<_>>()
+            .map_err(|e| AppError::Storage(format!("Failed to list requests: {}", e)))
+    }
+
+    /// Delete a session, returning it so the caller can clean up its files
+    pub fn delete_session(&self, id: i64) -> Result<Option<Session>, AppError> {
+        let session = self.get_session(id)?;
+        if session.is_some() {
+            self.conn()?
+                .execute("DELETE FROM sessions WHERE id = ?1", params![id])
+                .map_err(|e| AppError::Storage(format!("Failed to delete session: {}", e)))?;
+        }
+        Ok(session)
+    }
+
+    /// Full-text search over transcripts, titles and notes, best matches first
+    pub fn search_sessions(&self, query: &str, limit: u32) -> Result<Vec<Session>, AppError> {
+        let Some(fts_query) = fts_query(query) else {
+            return Ok(Vec::new());
+        };
+        let conn = self.conn()?;
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT {} FROM sessions
+                 JOIN (SELECT rowid, rank FROM sessions_fts WHERE sessions_fts MATCH ?1) f
+                   ON f.rowid = sessions.id
+                 ORDER BY f.rank LIMIT ?2",
+                SESSION_COLUMNS
+            ))
+            .map_err(|e| AppError::Storage(format!("Failed to search sessions: {}", e)))?;
+        let rows = stmt
+            .query_map(params![fts_query, limit], Session::from_row)
+            .map_err(|e| AppError::Storage(format!("Failed to search sessions: {}", e)))?;
+        rows.collect::<rusqlite::Result<_>>()
+            .map_err(|e| AppError::Storage(format!("Failed to search sessions: {}", e)))
+    }
+}
+
+fn insert_with_segments(conn: &Connection, session: &NewSession) -> rusqlite::Result<i64> {
+    conn.execute(
+        "INSERT INTO sessions (started_at, duration_ms, model, language, text, word_count, audio_path, detected_language, recovered, audio_source, imported, translated_text, translation_language, provider, request_id, model_version, disfluencies_removed)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17)",
+        params![
+            session.started_at,
+            session.duration_ms,
+            session.model,
+            session.language,
+            session.text,
+            session.text.split_whitespace().count() as i64,
+            session
+                .audio_path
+                .as_ref()
+                .map(|path| path.to_string_lossy().into_owned()),
+            session.detected_language,
+            session.recovered,
+            session.audio_source,
+            session.imported,
+            session.translated_text,
+            session.translation_language,
+            session.provider,
+            session.request_id,
+            session.model_version,
+            session.disfluencies_removed,
+        ],
+    )?;
+    let id = conn.last_insert_rowid();
+
+    let mut stmt = conn.prepare(
+        "INSERT INTO segments (session_id, position, start_ms, end_ms, text, words, speaker, translated,
+                               channel, speaker_name)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+    )?;
+    for (position, segment) in session.segments.iter().enumerate() {
+        let words = segment
+            .words
+            .as_ref()
+            .and_then(|words| serde_json::to_string(words).ok());
+        stmt.execute(params![
+            id,
+            position as i64,
+            segment.start_ms,
+            segment.end_ms,
+            segment.text,
+            words,
+            segment.speaker,
+            segment.translated,
+            segment.channel,
+            segment.speaker_name
+        ])?;
+    }
+    Ok(id)
+}
+
+/// Run any migrations the database hasn't seen yet, each in its own transaction
+fn migrate(conn: &mut Connection) -> Result<(), AppError> {
+    let applied: usize = conn
+        .pragma_query_value(None, "user_version", |row| row.get(0))
+        .map_err(|e| AppError::Storage(format!("Failed to read schema version: {}", e)))?;
+    if applied > MIGRATIONS.len() {
+        return Err(AppError::Storage(format!(
+            "History database schema v{} is newer than this app supports (v{})",
+            applied,
+            MIGRATIONS.len()
+        )));
+    }
+
+    for (index, sql) in MIGRATIONS.iter().enumerate().skip(applied) {
+        let tx = conn
+            .transaction()
+            .map_err(|e| AppError::Storage(format!("Failed to start migration: {}", e)))?;
+        tx.execute_batch(sql)
+            .and_then(|_| tx.pragma_update(None, "user_version", index + 1))
+            .and_then(|_| tx.commit())
+            .map_err(|e| AppError::Storage(format!("Migration {} failed: {}", index + 1, e)))?;
+    }
+    Ok(())
+}
+
+/// Turn free text into an FTS5 query: every word must match, the last one as a prefix
+/// Words are quoted so punctuation and FTS operators in the input can't cause syntax errors
+fn fts_query(query: &str) -> Option<String> {
+    let words: Vec<String> = query
+        .split_whitespace()
+        .map(|word| format!("\"{}\"", word.replace('"', "\"\"")))
+        .collect();
+    let last = words.last()?;
+    let mut terms = words[..words.len() - 1].to_vec();
+    terms.push(format!("{}*", last));
+    Some(terms.join(" "))
+}
+
+/// Trimmed and lowercased; tags can't be empty, overlong or contain control characters
+fn normalize_tag(tag: &str) -> Result<String, AppError> {
+    let tag = tag.trim().to_lowercase();
+    if tag.is_empty() {
+        return Err(AppError::InvalidInput("Tags can't be empty".to_string()));
+    }
+    if tag.chars().count() > MAX_TAG_CHARS {
+        return Err(AppError::InvalidInput(format!(
+            "Tags can be at most {} characters",
+            MAX_TAG_CHARS
+        )));
+    }
+    if tag.chars().any(char::is_control) {
+        return Err(AppError::InvalidInput(
+            "Tags can't contain control characters".to_string(),
+        ));
+    }
+    Ok(tag)
+}
+
+/// A trimmed title or note; blank clears it
+fn label(text: &str) -> Option<&str> {
+    Some(text.trim()).filter(|text| !text.is_empty())
+}
+
+/// Open the history database in the app data dir, falling back to memory if that fails
+pub fn init(app: &AppHandle) -> Storage {
+    let opened = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| AppError::Config(format!("Could not resolve app data directory: {}", e)))
+        .and_then(|dir| Storage::open(&dir.join(DB_FILE_NAME)));
+    opened.unwrap_or_else(|e| {
+        tracing::error!("{}; history will not be saved to disk", e);
+        Storage::open_in_memory().expect("in-memory SQLite database should always open")
+    })
+}
+
+/// Command to list sessions newest first, optionally only those matching `filter`
+#[tauri::command]
+pub async fn list_sessions(
+    state: State<'_, AppState>,
+    limit: Option<u32>,
+    offset: Option<u32>,
+    filter: Option<SessionFilter>,
+) -> Result<Vec<Session>, AppError> {
+    let storage = Arc::clone(&state.storage);
+    blocking(move || {
+        storage.list_sessions(
+            limit.unwrap_or(DEFAULT_PAGE_SIZE),
+            offset.unwrap_or(0),
+            &filter.unwrap_or_default(),
+        )
+    })
+    .await
+}
+
+/// Load a session after changing it, for the commands that return it
+fn updated(storage: &Storage, id: i64) -> Result<Session, AppError> {
+    storage
+        .get_session(id)?
+        .ok_or_else(|| AppError::NotFound(format!("Session {} not found", id)))
+}
+
+/// Command to name a session; a blank title clears it
+#[tauri::command]
+pub async fn set_session_title(
+    state: State<'_, AppState>,
+    id: i64,
+    title: String,
+) -> Result<Session, AppError> {
+    let storage = Arc::clone(&state.storage);
+    blocking(move || {
+        storage.set_title(id, label(&title))?;
+        updated(&storage, id)
+    })
+    .await
+}
+
+/// Command to attach a free-form note to a session; a blank note clears it
+#[tauri::command]
+pub async fn set_session_note(
+    state: State<'_, AppState>,
+    id: i64,
+    note: String,
+) -> Result<Session, AppError> {
+    let storage = Arc::clone(&state.storage);
+    blocking(move || {
+        storage.set_note(id, label(&note))?;
+        updated(&storage, id)
+    })
+    .await
+}
+
+/// Command to pin a session, keeping it out of automatic cleanup, or unpin it
+#[tauri::command]
+pub async fn set_session_pinned(
+    state: State<'_, AppState>,
+    id: i64,
+    pinned: bool,
+) -> Result<Session, AppError> {
+    let storage = Arc::clone(&state.storage);
+    blocking(move || {
+        storage.set_pinned(id, pinned)?;
+        updated(&storage, id)
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn add_session_tag(
+    state: State<'_, AppState>,
+    id: i64,
+    tag: String,
+) -> Result<Session, AppError> {
+    let storage = Arc::clone(&state.storage);
+    blocking(move || {
+        storage.add_tag(id, &tag)?;
+        updated(&storage, id)
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn remove_session_tag(
+    state: State<'_, AppState>,
+    id: i64,
+    tag: String,
+) -> Result<Session, AppError> {
+    let storage = Arc::clone(&state.storage);
+    blocking(move || {
+        storage.remove_tag(id, &tag)?;
+        updated(&storage, id)
+    })
+    .await
+}
+
+/// Command to list tags in use with how many sessions have each, for autocomplete
+#[tauri::command]
+pub async fn list_tags(state: State<'_, AppState>) -> Result<Vec<TagCount>, AppError> {
+    let storage = Arc::clone(&state.storage);
+    blocking(move || storage.tag_counts()).await
+}
+
+/// Command to load one session
+#[tauri::command]
+pub async fn get_session(state: State<'_, AppState>, id: i64) -> Result<Session, AppError> {
+    let storage = Arc::clone(&state.storage);
+    blocking(move || {
+        storage
+            .get_session(id)?
+            .ok_or_else(|| AppError::NotFound(format!("Session {} not found", id)))
+    })
+    .await
+}
+
+/// Command to get the path of a session's saved audio
+/// None if no audio was recorded or the file has since been removed
+#[tauri::command]
+pub async fn get_session_audio_path(
+    state: State<'_, AppState>,
+    id: i64,
+) -> Result<Option<String>, AppError> {
+    let storage = Arc::clone(&state.storage);
+    blocking(move || {
+        let session = storage
+            .get_session(id)?
+            .ok_or_else(|| AppError::NotFound(format!("Session {} not found", id)))?;
+        Ok(session.audio_path.filter(|path| Path::new(path).is_file()))
+    })
+    .await
+}
+
+/// Command to delete a session along with its saved audio, if any
+#[tauri::command]
+pub async fn delete_session(state: State<'_, AppState>, id: i64) -> Result<(), AppError> {
+    let storage = Arc::clone(&state.storage);
+    blocking(move || remove_session(&storage, id)).await
+}
+
+fn remove_session(storage: &Storage, id: i64) -> Result<(), AppError> {
+    let session = storage
+        .delete_session(id)?
+        .ok_or_else(|| AppError::NotFound(format!("Session {} not found", id)))?;
+
+    if let Some(audio_path) = session.audio_path {
+        remove_audio_file(&audio_path).map_err(|e| {
+            AppError::Io(format!("Session deleted but its audio file wasn't: {}", e))
+        })?;
+    }
+    Ok(())
+}
+
+/// Remove a session's audio file; one that's already gone is fine
+fn remove_audio_file(path: &str) -> std::io::Result<()> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+/// Command to search transcripts
+#[tauri::command]
+pub async fn search_sessions(
+    state: State<'_, AppState>,
+    query: String,
+    limit: Option<u32>,
+) -> Result<Vec<Session>, AppError> {
+    let storage = Arc::clone(&state.storage);
+    blocking(move || storage.search_sessions(&query, limit.unwrap_or(DEFAULT_PAGE_SIZE))).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(started_at: i64, text: &str) -> NewSession {
+        NewSession::for_test(started_at, text)
+    }
+
+    fn ids(sessions: Vec<Session>) -> Vec<i64> {
+        sessions.into_iter().map(|session| session.id).collect()
+    }
+
+    #[test]
+    fn filters_combine_and_search_covers_titles_and_notes() {
+        let storage = Storage::open_in_memory().unwrap();
+        let standup = storage
+            .insert_session(&session(1_000, "yesterday I fixed the build"))
+            .unwrap();
+        let review = storage
+            .insert_session(&session(2_000, "the build looks good"))
+            .unwrap();
+        let memo = storage.insert_session(&session(3_000, "buy milk")).unwrap();
+        storage.add_tag(standup, "  Work ").unwrap();
+        storage.add_tag(standup, "work").unwrap();
+        storage.add_tag(standup, "Daily").unwrap();
+        storage.add_tag(review, "work").unwrap();
+        storage.set_title(memo, Some("Groceries")).unwrap();
+        storage
+            .set_note(review, Some("follow up with Dana"))
+            .unwrap();
+
+        let list = |filter: SessionFilter| ids(storage.list_sessions(50, 0, &filter).unwrap());
+        assert_eq!(list(SessionFilter::default()), [memo, review, standup]);
+        let work = || SessionFilter {
+            tags: vec!["WORK".to_string()],
+            ..SessionFilter::default()
+        };
+        assert_eq!(list(work()), [review, standup]);
+        assert_eq!(
+            list(SessionFilter {
+                tags: vec!["work".to_string(), "daily".to_string()],
+                ..SessionFilter::default()
+            }),
+            [standup]
+        );
+        assert_eq!(
+            list(SessionFilter {
+                date_from: Some(1_500),
+                ..work()
+            }),
+            [review]
+        );
+        assert_eq!(
+            list(SessionFilter {
+                text_query: Some("build".to_string()),
+                date_to: Some(1_000),
+                ..SessionFilter::default()
+            }),
+            [standup]
+        );
+
+        assert_eq!(ids(storage.search_sessions("grocer", 10).unwrap()), [memo]);
+        assert_eq!(ids(storage.search_sessions("dana", 10).unwrap()), [review]);
+        storage.set_note(review, None).unwrap();
+        assert!(storage.search_sessions("dana", 10).unwrap().is_empty());
+
+        let tagged = storage.get_session(standup).unwrap().unwrap();
+        assert_eq!(tagged.tags, ["daily", "work"]);
+        assert_eq!(tagged.provider.as_deref(), Some("deepgram"));
+        let counts: Vec<(String, i64)> = storage
+            .tag_counts()
+            .unwrap()
+            .into_iter()
+            .map(|count| (count.tag, count.count))
+            .collect();
+        assert_eq!(counts, [("work".to_string(), 2), ("daily".to_string(), 1)]);
+        storage.remove_tag(standup, "Daily").unwrap();
+        assert_eq!(
+            storage.get_session(standup).unwrap().unwrap().tags,
+            ["work"]
+        );
+        assert!(storage.add_tag(standup, "   ").is_err());
+        assert!(storage.add_tag(4242, "work").is_err());
+    }
+
+    #[test]
+    fn request_ids_are_kept_and_listed_for_sessions_that_have_one() {
+        let storage = Storage::open_in_memory().unwrap();
+        let local = storage.insert_session(&session(1_000, "local")).unwrap();
+        let served = storage
+            .insert_session(&NewSession {
+                request_id: Some("5c2f-req".to_string()),
+                model_version: Some("2-general-nova 2024-01-09.29447".to_string()),
+                ..session(2_000, "served")
+            })
+            .unwrap();
+        assert_eq!(
+            storage.get_session(local).unwrap().unwrap().request_id,
+            None
+        );
+        let requests = storage.recent_requests(10).unwrap();
+        assert_eq!(
+            requests
+                .iter()
+                .map(|request| (request.id, request.request_id.as_str()))
+                .collect::<Vec<_>>(),
+            [(served, "5c2f-req")]
+        );
+    }
+}
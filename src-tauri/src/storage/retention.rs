@@ -0,0 +1,369 @@
+// Automatic cleanup of old history under the retention settings
+// Runs at launch and once a day: expired recordings go first, then expired sessions, then
+// the oldest recordings while audio is over its size cap. Pinned sessions are never
+// touched. When that leaves much of the database free it's vacuumed and reindexed
+
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rusqlite::params;
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+
+use super::{remove_audio_file, remove_session, Storage};
+use crate::config::{self, RetentionSettings};
+use crate::error::AppError;
+use crate::state::{blocking, AppState};
+
+/// How often cleanup runs after the one at launch
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+const DAY_MS: i64 = 24 * 60 * 60 * 1000;
+/// Share of free pages at which the database is vacuumed
+const VACUUM_FREE_SHARE: f64 = 0.25;
+
+/// Keeps a manual run and the scheduled one from deleting the same things at once
+static CLEANUP: Mutex<()> = Mutex::new(());
+
+/// What a cleanup deleted, as returned by `run_cleanup_now`
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CleanupReport {
+    /// Sessions whose recording was deleted; their transcripts are kept
+    pub audio_deleted: Vec<i64>,
+    /// Sessions deleted outright
+    pub sessions_deleted: Vec<i64>,
+    pub audio_bytes_freed: u64,
+    /// The database was compacted afterwards
+    pub vacuumed: bool,
+}
+
+/// Result of `get_storage_usage`
+#[derive(Debug, Clone, Serialize)]
+pub struct StorageUsage {
+    pub db_bytes: u64,
+    /// Recordings that are still on disk
+    pub audio_bytes: u64,
+    pub session_count: i64,
+}
+
+/// A session's recording
+struct AudioFile {
+    id: i64,
+    started_at: i64,
+    path: String,
+    pinned: bool,
+}
+
+impl Storage {
+    /// Sessions with a recording, oldest first
+    fn audio_files(&self) -> Result<Vec<AudioFile>, AppError> {
+        let conn = self.conn()?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT id, started_at, audio_path, pinned FROM sessions
+                 WHERE audio_path IS NOT NULL ORDER BY started_at, id",
+            )
+            .map_err(|e| AppError::Storage(format!("Failed to list recordings: {}", e)))?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok(AudioFile {
+                    id: row.get(0)?,
+                    started_at: row.get(1)?,
+                    path: row.get(2)?,
+                    pinned: row.get(3)?,
+                })
+            })
+            .map_err(|e| AppError::Storage(format!("Failed to list recordings: {}", e)))?;
+        rows.collect::<rusqlite::Result<_>>()
+            .map_err(|e| AppError::Storage(format!("Failed to list recordings: {}", e)))
+    }
+
+    fn clear_audio_path(&self, id: i64) -> Result<(), AppError> {
+        self.conn()?
+            .execute(
+                "UPDATE sessions SET audio_path = NULL WHERE id = ?1",
+                params![id],
+            )
+            .map_err(|e| AppError::Storage(format!("Failed to clear audio path: {}", e)))?;
+        Ok(())
+    }
+
+    /// Unpinned sessions that started before `before`, oldest first
+    fn expired_sessions(&self, before: i64) -> Result<Vec<i64>, AppError> {
+        let conn = self.conn()?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT id FROM sessions WHERE started_at < ?1 AND pinned = 0
+                 ORDER BY started_at, id",
+            )
+            .map_err(|e| AppError::Storage(format!("Failed to list old sessions: {}", e)))?;
+        let rows = stmt
+            .query_map(params![before], |row| row.get(0))
+            .map_err(|e| AppError::Storage(format!("Failed to list old sessions: {}", e)))?;
+        rows.collect::<rusqlite::Result<_>>()
+            .map_err(|e| AppError::Storage(format!("Failed to list old sessions: {}", e)))
+    }
+
+    fn session_count(&self) -> Result<i64, AppError> {
+        self.conn()?
+            .query_row("SELECT COUNT(*) FROM sessions", [], |row| row.get(0))
+            .map_err(|e| AppError::Storage(format!("Failed to count sessions: {}", e)))
+    }
+
+    /// Pages in the database and how many of them are free
+    fn pages(&self) -> Result<(u64, u64, u64), AppError> {
+        let conn = self.conn()?;
+        let pragma = |name: &str| -> Result<u64, AppError> {
+            conn.pragma_query_value(None, name, |row| row.get(0))
+                .map_err(|e| AppError::Storage(format!("Failed to read {}: {}", name, e)))
+        };
+        Ok((
+            pragma("page_count")?,
+            pragma("page_size")?,
+            pragma("freelist_count")?,
+        ))
+    }
+
+    /// Compact the database and rebuild its indexes
+    fn vacuum(&self) -> Result<(), AppError> {
+        self.conn()?
+            .execute_batch("VACUUM; REINDEX;")
+            .map_err(|e| AppError::Storage(format!("Failed to compact history: {}", e)))
+    }
+}
+
+/// Start cleaning up now and every day after
+pub fn init(app: &AppHandle) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        loop {
+            match run(&app).await {
+                Ok(report)
+                    if report.sessions_deleted.is_empty() && report.audio_deleted.is_empty() => {}
+                Ok(report) => tracing::info!(
+                    "Cleanup deleted {} sessions and {} recordings ({} bytes)",
+                    report.sessions_deleted.len(),
+                    report.audio_deleted.len(),
+                    report.audio_bytes_freed
+                ),
+                Err(e) => tracing::warn!("Cleanup failed: {}", e),
+            }
+            tokio::time::sleep(CLEANUP_INTERVAL).await;
+        }
+    });
+}
+
+async fn run(app: &AppHandle) -> Result<CleanupReport, AppError> {
+    let app = app.clone();
+    blocking(move || {
+        let settings = config::load(&app)?.retention;
+        let storage = Arc::clone(&app.state::<AppState>().storage);
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as i64)
+            .unwrap_or(0);
+        cleanup(&storage, &settings, now)
+    })
+    .await
+}
+
+/// Apply `settings` as of `now` (Unix time in milliseconds)
+pub fn cleanup(
+    storage: &Storage,
+    settings: &RetentionSettings,
+    now: i64,
+) -> Result<CleanupReport, AppError> {
+    let _running = CLEANUP.lock().map_err(|_| AppError::poisoned("Cleanup"))?;
+    let mut report = CleanupReport::default();
+
+    if settings.keep_audio_days > 0 {
+        let cutoff = now - i64::from(settings.keep_audio_days) * DAY_MS;
+        for file in storage.audio_files()? {
+            if file.started_at < cutoff && !file.pinned {
+                delete_audio(storage, &file, &mut report)?;
+            }
+        }
+    }
+
+    if settings.keep_transcripts_days > 0 {
+        let cutoff = now - i64::from(settings.keep_transcripts_days) * DAY_MS;
+        for id in storage.expired_sessions(cutoff)? {
+            let audio = storage
+                .get_session(id)?
+                .and_then(|session| session.audio_path);
+            let bytes = audio.as_deref().map_or(0, file_size);
+            match remove_session(storage, id) {
+                Ok(()) => {
+                    report.sessions_deleted.push(id);
+                    report.audio_bytes_freed += bytes;
+                }
+                // The session is gone but its recording was left behind on disk
+                Err(AppError::Io(e)) => {
+                    tracing::warn!("{}", e);
+                    report.sessions_deleted.push(id);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    if settings.max_total_audio_mb > 0 {
+        let cap = settings.max_total_audio_mb.saturating_mul(1024 * 1024);
+        let files = storage.audio_files()?;
+        let mut total: u64 = files.iter().map(|file| file_size(&file.path)).sum();
+        for file in files.iter().filter(|file| !file.pinned) {
+            if total <= cap {
+                break;
+            }
+            total -= delete_audio(storage, file, &mut report)?;
+        }
+    }
+
+    if !(report.sessions_deleted.is_empty() && report.audio_deleted.is_empty()) {
+        let (pages, page_size, free) = storage.pages()?;
+        if pages > 0 && free as f64 >= pages as f64 * VACUUM_FREE_SHARE {
+            tracing::info!("Compacting history, {} bytes free", free * page_size);
+            storage.vacuum()?;
+            report.vacuumed = true;
+        }
+    }
+    Ok(report)
+}
+
+/// Delete a recording but keep its session; returns the bytes freed
+fn delete_audio(
+    storage: &Storage,
+    file: &AudioFile,
+    report: &mut CleanupReport,
+) -> Result<u64, AppError> {
+    let bytes = file_size(&file.path);
+    if let Err(e) = remove_audio_file(&file.path) {
+        // Left pointing at it, so a later run tries again
+        tracing::warn!("Failed to delete {}: {}", file.path, e);
+        return Ok(0);
+    }
+    storage.clear_audio_path(file.id)?;
+    report.audio_deleted.push(file.id);
+    report.audio_bytes_freed += bytes;
+    Ok(bytes)
+}
+
+/// Size of a file on disk; 0 if it's gone
+fn file_size(path: &str) -> u64 {
+    std::fs::metadata(Path::new(path)).map_or(0, |metadata| metadata.len())
+}
+
+/// Command to report how much space history takes, for the settings screen
+#[tauri::command]
+pub async fn get_storage_usage(app: AppHandle) -> Result<StorageUsage, AppError> {
+    let storage = Arc::clone(&app.state::<AppState>().storage);
+    blocking(move || {
+        let (pages, page_size, _) = storage.pages()?;
+        let audio_bytes = storage
+            .audio_files()?
+            .iter()
+            .map(|file| file_size(&file.path))
+            .sum();
+        Ok(StorageUsage {
+            db_bytes: pages * page_size,
+            audio_bytes,
+            session_count: storage.session_count()?,
+        })
+    })
+    .await
+}
+
+/// Command to clean up right away instead of waiting for the daily run
+#[tauri::command]
+pub async fn run_cleanup_now(app: AppHandle) -> Result<CleanupReport, AppError> {
+    run(&app).await
+}
+
+#[tauri::command]
+pub async fn get_retention_settings(app: AppHandle) -> Result<RetentionSettings, AppError> {
+    blocking(move || Ok(config::load(&app)?.retention)).await
+}
+
+/// Command to change the retention settings; they apply from the next cleanup
+#[tauri::command]
+pub async fn set_retention_settings(
+    app: AppHandle,
+    settings: RetentionSettings,
+) -> Result<RetentionSettings, AppError> {
+    blocking(move || {
+        config::update(&app, |config| {
+            config.retention = RetentionSettings {
+                extra: std::mem::take(&mut config.retention.extra),
+                ..settings
+            };
+            Ok(config.retention.clone())
+        })
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::NewSession;
+
+    fn session(started_at: i64, audio_path: Option<&Path>) -> NewSession {
+        NewSession {
+            audio_path: audio_path.map(Path::to_path_buf),
+            provider: None,
+            ..NewSession::for_test(started_at, "hello")
+        }
+    }
+
+    #[test]
+    fn cleanup_spares_pinned_sessions_and_drops_the_oldest_audio_first() {
+        let dir =
+            std::env::temp_dir().join(format!("subspace-retention-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let wav = |name: &str, bytes: usize| {
+            let path = dir.join(name);
+            std::fs::write(&path, vec![0u8; bytes]).unwrap();
+            path
+        };
+        let mb = 1024 * 1024;
+        let now = 100 * DAY_MS;
+        let storage = Storage::open_in_memory().unwrap();
+        let ancient = storage.insert_session(&session(0, None)).unwrap();
+        let pinned = storage
+            .insert_session(&session(DAY_MS, Some(&wav("pinned.wav", mb))))
+            .unwrap();
+        storage.set_pinned(pinned, true).unwrap();
+        let stale_audio = storage
+            .insert_session(&session(80 * DAY_MS, Some(&wav("stale.wav", mb))))
+            .unwrap();
+        let older = storage
+            .insert_session(&session(95 * DAY_MS, Some(&wav("older.wav", mb))))
+            .unwrap();
+        let newer = storage
+            .insert_session(&session(99 * DAY_MS, Some(&wav("newer.wav", mb))))
+            .unwrap();
+
+        let settings = RetentionSettings {
+            keep_transcripts_days: 30,
+            keep_audio_days: 10,
+            max_total_audio_mb: 2,
+            ..RetentionSettings::default()
+        };
+        let report = cleanup(&storage, &settings, now).unwrap();
+        // The pinned recording counts towards the cap but isn't deleted for it
+        assert_eq!(report.audio_deleted, [stale_audio, older]);
+        assert_eq!(report.sessions_deleted, [ancient]);
+        assert_eq!(report.audio_bytes_freed, 2 * mb as u64);
+
+        let audio = |id| storage.get_session(id).unwrap().unwrap().audio_path;
+        assert!(storage.get_session(ancient).unwrap().is_none());
+        assert!(audio(pinned).is_some_and(|path| Path::new(&path).is_file()));
+        assert!(audio(stale_audio).is_none() && !dir.join("stale.wav").exists());
+        assert!(audio(older).is_none());
+        assert!(audio(newer).is_some());
+
+        let again = cleanup(&storage, &settings, now).unwrap();
+        assert!(again.audio_deleted.is_empty() && again.sessions_deleted.is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
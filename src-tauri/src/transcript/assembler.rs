@@ -0,0 +1,395 @@
+// Turns a stream of interim and final results into what to show and what to keep
+// Deepgram re-sends the interim transcript of the audio window it's working on, each
+// revision starting at the same time, until it sends that window's final result. Interim
+// segments are tracked by their start; a final replaces every interim it covers, taking
+// over the first one's id so the UI updates it in place rather than adding a duplicate
+// Each channel of a multichannel session has its own timeline, so it's assembled apart
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::postprocess::dictation_commands;
+use crate::storage::Segment;
+
+/// Timestamps are rounded to the millisecond, so a window that starts where the last final
+/// ended may appear to start a little before it
+const BOUNDARY_TOLERANCE_MS: i64 = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DisplayStatus {
+    /// May still be revised
+    Interim,
+    /// Won't change again
+    Final,
+    /// No longer shown, e.g. an interim whose final came back empty
+    Removed,
+}
+
+/// Payload of `transcript-display`: how one segment should be shown right now
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DisplaySegment {
+    /// Stays the same across revisions; a final keeps the id of the interim it replaces
+    pub id: u64,
+    pub text: String,
+    /// Seconds from the start of the session
+    pub start: f64,
+    pub end: f64,
+    pub speaker: Option<u32>,
+    pub status: DisplayStatus,
+    /// Channel and speaker name of a multichannel session
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channel: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub speaker_name: Option<String>,
+}
+
+/// Payload of `transcript-committed`: a final segment, sent once and never revised
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CommittedSegment {
+    /// The id its `transcript-display` updates used
+    pub id: u64,
+    /// The original, untranslated text
+    pub text: String,
+    /// `text` as it was heard, before dictation commands and replacements
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw_text: Option<String>,
+    pub start: f64,
+    pub end: f64,
+    pub speaker: Option<u32>,
+    /// `text` in the target language, when translation is on and worked
+    pub translated: Option<String>,
+    /// Translation is on but this segment couldn't be translated
+    pub translation_failed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channel: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub speaker_name: Option<String>,
+}
+
+/// Events to send for one result, display updates first
+#[derive(Debug, Default)]
+pub struct Update {
+    pub display: Vec<DisplaySegment>,
+    pub committed: Vec<CommittedSegment>,
+}
+
+/// An interim segment still waiting for its final
+struct Interim {
+    id: u64,
+    end_ms: i64,
+    speaker: Option<u32>,
+}
+
+/// Assembly state of one session
+#[derive(Default)]
+pub struct Assembler {
+    next_id: u64,
+    /// Keyed by start time in milliseconds
+    interims: BTreeMap<i64, Interim>,
+    committed: Vec<CommittedSegment>,
+    /// End of the latest final; interim results starting before it are stale
+    committed_until_ms: i64,
+    channel: Option<u32>,
+    speaker_name: Option<String>,
+}
+
+impl Assembler {
+    /// Assembly of one channel of a multichannel session, labelling what it shows
+    /// Ids are numbered from `channel << 32`, so different channels' never meet
+    pub fn for_channel(channel: u32, speaker_name: String) -> Self {
+        Self {
+            next_id: u64::from(channel) << 32,
+            channel: Some(channel),
+            speaker_name: Some(speaker_name),
+            ..Self::default()
+        }
+    }
+
+    /// Record an interim result spanning `start_ms..end_ms`
+    /// A revision of a tracked interim keeps its id; one for audio that's already final,
+    /// arriving after its final did, is ignored
+    pub fn interim(
+        &mut self,
+        start_ms: i64,
+        end_ms: i64,
+        text: &str,
+        speaker: Option<u32>,
+    ) -> Update {
+        let mut update = Update::default();
+        if start_ms + BOUNDARY_TOLERANCE_MS < self.committed_until_ms {
+            return update;
+        }
+        let text = text.trim();
+        if text.is_empty() {
+            // Deepgram took back what it had heard
+            if let Some(interim) = self.interims.remove(&start_ms) {
+                update.display.push(self.removed(start_ms, &interim));
+            }
+            return update;
+        }
+        let id = match self.interims.get(&start_ms) {
+            Some(interim) => interim.id,
+            None => self.new_id(),
+        };
+        self.interims.insert(
+            start_ms,
+            Interim {
+                id,
+                end_ms,
+                speaker,
+            },
+        );
+        update.display.push(DisplaySegment {
+            id,
+            text: text.to_string(),
+            start: seconds(start_ms),
+            end: seconds(end_ms),
+            speaker,
+            status: DisplayStatus::Interim,
+            channel: self.channel,
+            speaker_name: self.speaker_name.clone(),
+        });
+        update
+    }
+
+    /// Record the final result for `start_ms..end_ms`, as the segments to keep
+    /// It replaces every tracked interim starting before `end_ms`; an empty final just
+    /// removes them. A final for audio that's already committed is ignored
+    pub fn commit(&mut self, start_ms: i64, end_ms: i64, segments: &[Segment]) -> Update {
+        let mut update = Update::default();
+        if end_ms <= self.committed_until_ms
+            && start_ms + BOUNDARY_TOLERANCE_MS < self.committed_until_ms
+        {
+            return update;
+        }
+        let mut replaced = Vec::new();
+        while let Some(entry) = self.interims.first_entry() {
+            if *entry.key() >= end_ms {
+                break;
+            }
+            let start = *entry.key();
+            replaced.push((start, entry.remove()));
+        }
+        self.committed_until_ms = self.committed_until_ms.max(end_ms);
+
+        let mut reused = replaced.first().map(|(_, interim)| interim.id);
+        for segment in segments
+            .iter()
+            .filter(|segment| !segment.text.trim().is_empty())
+        {
+            let id = reused.take().unwrap_or_else(|| self.new_id());
+            let committed = CommittedSegment {
+                id,
+                text: segment.text.clone(),
+                raw_text: None,
+                start: seconds(segment.start_ms),
+                end: seconds(segment.end_ms),
+                speaker: segment.speaker,
+                translated: None,
+                translation_failed: false,
+                channel: self.channel,
+                speaker_name: self.speaker_name.clone(),
+            };
+            update.display.push(DisplaySegment {
+                id,
+                text: committed.text.clone(),
+                start: committed.start,
+                end: committed.end,
+                speaker: committed.speaker,
+                status: DisplayStatus::Final,
+                channel: self.channel,
+                speaker_name: self.speaker_name.clone(),
+            });
+            self.committed.push(committed.clone());
+            update.committed.push(committed);
+        }
+        // Interims no final segment took over
+        let taken = usize::from(reused.is_none() && !replaced.is_empty());
+        for (start, interim) in replaced.iter().skip(taken) {
+            update.display.push(self.removed(*start, interim));
+        }
+        update
+    }
+
+    /// Drop committed segment `id`, which was taken back; false if it isn't this one's
+    pub fn revoke(&mut self, id: u64) -> bool {
+        let before = self.committed.len();
+        self.committed.retain(|segment| segment.id != id);
+        self.committed.len() != before
+    }
+
+    /// Nothing is shown, interim or final
+    pub fn is_empty(&self) -> bool {
+        self.interims.is_empty() && self.committed.is_empty()
+    }
+
+    /// Everything committed so far, as one text
+    pub fn text(&self) -> String {
+        dictation_commands::join(self.committed.iter().map(|segment| segment.text.as_str()))
+    }
+
+    fn new_id(&mut self) -> u64 {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    fn removed(&self, start_ms: i64, interim: &Interim) -> DisplaySegment {
+        DisplaySegment {
+            id: interim.id,
+            text: String::new(),
+            start: seconds(start_ms),
+            end: seconds(interim.end_ms),
+            speaker: interim.speaker,
+            status: DisplayStatus::Removed,
+            channel: self.channel,
+            speaker_name: self.speaker_name.clone(),
+        }
+    }
+}
+
+fn seconds(ms: i64) -> f64 {
+    ms as f64 / 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::deepgram::StreamMessage;
+
+    /// Feed captured Deepgram messages through the assembler, as the live stream does
+    fn feed(assembler: &mut Assembler, messages: &[&str]) -> Vec<Update> {
+        messages
+            .iter()
+            .map(|message| {
+                let Ok(StreamMessage::Results(results)) = serde_json::from_str(message) else {
+                    panic!("not a Results message: {}", message);
+                };
+                let (start_ms, end_ms) = results.span_ms();
+                if results.is_final {
+                    assembler.commit(start_ms, end_ms, &results.to_segments())
+                } else {
+                    let event = results.to_event().unwrap();
+                    assembler.interim(start_ms, end_ms, &event.transcript, event.speaker)
+                }
+            })
+            .collect()
+    }
+
+    fn result(start: f64, duration: f64, is_final: bool, transcript: &str) -> String {
+        serde_json::json!({
+            "type": "Results",
+            "start": start,
+            "duration": duration,
+            "is_final": is_final,
+            "speech_final": is_final,
+            "channel": {"alternatives": [{"transcript": transcript, "words": []}]},
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn revised_interims_update_in_place_and_the_final_replaces_them() {
+        let mut assembler = Assembler::default();
+        let updates = feed(
+            &mut assembler,
+            &[
+                &result(0.0, 1.0, false, "the quick"),
+                &result(0.0, 2.0, false, "the quick brown box"),
+                &result(0.0, 2.5, false, "the quick brown fox jumps"),
+                &result(0.0, 2.6, true, "The quick brown fox jumps."),
+                &result(2.6, 1.0, false, "over"),
+            ],
+        );
+
+        let ids: Vec<u64> = updates[..4].iter().map(|u| u.display[0].id).collect();
+        assert!(ids.iter().all(|id| *id == ids[0]), "{:?}", ids);
+        assert_eq!(updates[1].display[0].text, "the quick brown box");
+        assert_eq!(updates[3].display.len(), 1);
+        assert_eq!(updates[3].display[0].status, DisplayStatus::Final);
+        assert_eq!(updates[3].committed[0].text, "The quick brown fox jumps.");
+        assert!(updates[..3].iter().all(|u| u.committed.is_empty()));
+        // The next window is a new segment
+        assert_ne!(updates[4].display[0].id, ids[0]);
+        assert_eq!(updates[4].display[0].status, DisplayStatus::Interim);
+        assert_eq!(assembler.text(), "The quick brown fox jumps.");
+    }
+
+    #[test]
+    fn late_interims_are_dropped_and_early_ones_kept() {
+        let mut assembler = Assembler::default();
+        let updates = feed(
+            &mut assembler,
+            &[
+                &result(0.0, 1.5, false, "hello"),
+                // The next window's interim arrives before the previous final
+                &result(2.0, 0.8, false, "how are"),
+                &result(0.0, 2.0, true, "Hello there."),
+                // A revision of the finalized window, arriving after its final
+                &result(0.0, 1.8, false, "hello there"),
+                &result(2.0, 1.4, false, "how are you"),
+                &result(2.0, 1.5, true, "How are you?"),
+            ],
+        );
+
+        let first = updates[0].display[0].id;
+        let second = updates[1].display[0].id;
+        assert_ne!(first, second);
+        // The final takes over its own window only
+        assert_eq!(updates[2].display.len(), 1);
+        assert_eq!(updates[2].display[0].id, first);
+        assert!(updates[3].display.is_empty() && updates[3].committed.is_empty());
+        assert_eq!(updates[4].display[0].id, second);
+        assert_eq!(updates[5].committed[0].id, second);
+        // Replaying a final that's already in changes nothing
+        let replay = feed(&mut assembler, &[&result(0.0, 2.0, true, "Hello there.")]);
+        assert!(replay[0].display.is_empty() && replay[0].committed.is_empty());
+        assert_eq!(assembler.text(), "Hello there. How are you?");
+    }
+
+    #[test]
+    fn an_empty_final_removes_its_interims_without_committing() {
+        let mut assembler = Assembler::default();
+        let updates = feed(
+            &mut assembler,
+            &[
+                &result(0.0, 0.6, false, "um"),
+                &result(0.6, 0.6, false, "uh"),
+                &result(0.0, 1.4, true, ""),
+                &result(1.4, 1.0, true, "Okay."),
+            ],
+        );
+
+        let removed: Vec<(u64, DisplayStatus)> = updates[2]
+            .display
+            .iter()
+            .map(|segment| (segment.id, segment.status))
+            .collect();
+        assert_eq!(
+            removed,
+            [
+                (updates[0].display[0].id, DisplayStatus::Removed),
+                (updates[1].display[0].id, DisplayStatus::Removed),
+            ]
+        );
+        assert!(updates[2].committed.is_empty());
+        assert_eq!(updates[3].committed.len(), 1);
+        assert_eq!(assembler.text(), "Okay.");
+    }
+
+    #[test]
+    fn a_revoked_segment_leaves_the_text() {
+        let mut assembler = Assembler::default();
+        let updates = feed(
+            &mut assembler,
+            &[
+                &result(0.0, 1.0, true, "Keep this."),
+                &result(1.0, 1.0, true, "Not this."),
+            ],
+        );
+        assert!(assembler.revoke(updates[1].committed[0].id));
+        assert!(!assembler.revoke(updates[1].committed[0].id));
+        assert_eq!(assembler.text(), "Keep this.");
+    }
+}
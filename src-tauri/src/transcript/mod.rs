@@ -0,0 +1,220 @@
+// Live transcript assembly, shared by the engines
+// The frontend renders `transcript-display` updates in place by segment id and appends
+// `transcript-committed` segments; the raw `transcript-partial`/`transcript-final` events
+// are still sent for the captions and anything that wants per-result detail
+// With translation on, committed events wait for their segment's translation
+// Multichannel sessions are assembled per channel and put back in time order at the end
+// A revoked segment is dropped before the next one commits, and before the session ends
+// Committed segments go wherever `output::Outputs` sends them, the window by default
+
+pub mod assembler;
+pub mod output;
+pub mod revoke;
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use tauri::{AppHandle, Emitter, Manager};
+
+use self::assembler::{Assembler, Update};
+use self::output::Outputs;
+use self::revoke::RevokeState;
+use crate::config::TranscriptionSettings;
+use crate::postprocess::dictation_commands;
+use crate::postprocess::translation::{self, LiveTranslation};
+use crate::storage::Segment;
+
+/// Display update for one segment, see `DisplaySegment`
+pub const EVENT_TRANSCRIPT_DISPLAY: &str = "transcript-display";
+/// A segment that's final, see `CommittedSegment`
+pub const EVENT_TRANSCRIPT_COMMITTED: &str = "transcript-committed";
+
+/// One session's transcript: assembles results, sends their events and keeps the
+/// committed segments for history
+pub struct LiveTranscript {
+    app: AppHandle,
+    /// By audio channel; None for a session that isn't multichannel
+    assemblers: BTreeMap<Option<u32>, Assembler>,
+    /// For the names of a multichannel session's channels
+    settings: TranscriptionSettings,
+    outputs: Arc<Outputs>,
+    /// Present while translation is on
+    translation: Option<LiveTranslation>,
+    /// Committed segments in order, with the id their events used
+    committed: Vec<(u64, Segment)>,
+    /// Segments dropped since `take_revoked` was last called
+    revoked: Vec<Segment>,
+}
+
+/// A session's transcript once its last result is in
+pub struct FinishedTranscript {
+    pub text: String,
+    pub segments: Vec<Segment>,
+    /// When translation was on and anything could be translated
+    pub translated_text: Option<String>,
+    pub translation_language: Option<String>,
+}
+
+impl FinishedTranscript {
+    /// The text to hand back for injection: the translation when there is one
+    pub fn output(&self) -> String {
+        self.translated_text
+            .clone()
+            .unwrap_or_else(|| self.text.clone())
+    }
+}
+
+impl LiveTranscript {
+    pub fn new(app: &AppHandle, settings: &TranscriptionSettings) -> Self {
+        app.state::<RevokeState>().reset();
+        let outputs = Arc::new(Outputs::new(app));
+        Self {
+            app: app.clone(),
+            assemblers: BTreeMap::new(),
+            settings: settings.clone(),
+            translation: LiveTranslation::start(app, Arc::clone(&outputs)),
+            outputs,
+            committed: Vec::new(),
+            revoked: Vec::new(),
+        }
+    }
+
+    /// See `Assembler::interim`; `channel` is the audio channel of a multichannel result
+    pub fn interim(
+        &mut self,
+        channel: Option<u32>,
+        start_ms: i64,
+        end_ms: i64,
+        text: &str,
+        speaker: Option<u32>,
+    ) {
+        let update = self
+            .assembler(channel)
+            .interim(start_ms, end_ms, text, speaker);
+        self.emit(update);
+    }
+
+    /// See `Assembler::commit`; `segments` are kept unless they were committed before
+    /// `raw` holds each segment's text as it was heard, where post-processing rewrote it
+    pub fn commit(
+        &mut self,
+        channel: Option<u32>,
+        start_ms: i64,
+        end_ms: i64,
+        segments: &[Segment],
+        raw: &[String],
+    ) {
+        self.drop_revoked();
+        let mut update = self.assembler(channel).commit(start_ms, end_ms, segments);
+        if let Some(last) = update.committed.last() {
+            self.app
+                .state::<RevokeState>()
+                .committed(last.id, &last.text);
+        }
+        // A commit takes every non-blank segment, in order, or none of them
+        let kept = segments
+            .iter()
+            .enumerate()
+            .filter(|(_, segment)| !segment.text.trim().is_empty());
+        for (committed, (index, segment)) in update.committed.iter_mut().zip(kept) {
+            committed.raw_text = raw.get(index).cloned();
+            self.committed.push((committed.id, segment.clone()));
+        }
+        self.emit(update);
+    }
+
+    /// The segments revoked since the last call, to take out of the journal
+    pub fn take_revoked(&mut self) -> Vec<Segment> {
+        self.drop_revoked();
+        std::mem::take(&mut self.revoked)
+    }
+
+    fn drop_revoked(&mut self) {
+        for id in self.app.state::<RevokeState>().take_pending() {
+            for assembler in self.assemblers.values_mut() {
+                assembler.revoke(id);
+            }
+            if let Some(index) = self.committed.iter().position(|(kept, _)| *kept == id) {
+                let (_, segment) = self.committed.remove(index);
+                self.revoked.push(segment);
+            }
+        }
+    }
+
+    /// See `Assembler::is_empty`
+    pub fn is_empty(&self) -> bool {
+        self.assemblers.values().all(Assembler::is_empty)
+    }
+
+    /// Wait for outstanding translations and hand over the transcript
+    pub async fn finish(&mut self) -> FinishedTranscript {
+        let finished = self.finish_transcript().await;
+        self.outputs.finish(&finished.output());
+        finished
+    }
+
+    async fn finish_transcript(&mut self) -> FinishedTranscript {
+        self.drop_revoked();
+        let mut committed = std::mem::take(&mut self.committed);
+        let text = match self.assemblers.values().collect::<Vec<_>>()[..] {
+            [assembler] => assembler.text(),
+            _ => {
+                // Channels interleave by when each segment started
+                committed.sort_by_key(|(_, segment)| segment.start_ms);
+                dictation_commands::join(committed.iter().map(|(_, segment)| segment.text.as_str()))
+            }
+        };
+        let Some(translation) = self.translation.take() else {
+            return FinishedTranscript {
+                text,
+                segments: committed.into_iter().map(|(_, segment)| segment).collect(),
+                translated_text: None,
+                translation_language: None,
+            };
+        };
+        let translation_language = translation.target_language().to_string();
+        let mut translations = translation.finish().await;
+        let segments: Vec<Segment> = committed
+            .into_iter()
+            .map(|(id, segment)| Segment {
+                translated: translations.remove(&id),
+                ..segment
+            })
+            .collect();
+        let translated_text = translation::translated_text(&segments);
+        FinishedTranscript {
+            text,
+            translation_language: translated_text.as_ref().map(|_| translation_language),
+            translated_text,
+            segments,
+        }
+    }
+
+    fn assembler(&mut self, channel: Option<u32>) -> &mut Assembler {
+        let settings = &self.settings;
+        self.assemblers
+            .entry(channel)
+            .or_insert_with(|| match channel {
+                Some(channel) => Assembler::for_channel(channel, settings.channel_name(channel)),
+                None => Assembler::default(),
+            })
+    }
+
+    fn emit(&self, update: Update) {
+        for segment in update.display {
+            let _ = self.app.emit(EVENT_TRANSCRIPT_DISPLAY, segment);
+        }
+        match &self.translation {
+            Some(translation) => {
+                if !update.committed.is_empty() {
+                    translation.push(update.committed);
+                }
+            }
+            None => {
+                for segment in update.committed {
+                    self.outputs.committed(segment);
+                }
+            }
+        }
+    }
+}
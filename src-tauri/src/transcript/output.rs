@@ -0,0 +1,205 @@
+// Where a live session's committed segments go, per `InjectSettings::output_targets`
+// (or the deep link that started the session): the window (`transcript-committed`
+// events), the clipboard and the focused application
+// Each segment goes to the enabled targets in that order, and a target that fails is
+// logged without holding up the others. The clipboard and injection block, so they run
+// in order on a thread of the session's. In `ClipboardMode::Session` the clipboard is
+// written once, with the whole transcript, when the session stops
+// Injection follows the focused application's output profile for each segment, as
+// `type_text` does, and spaces a segment from what was inserted before it, trailing
+// suffix included
+
+use std::sync::mpsc;
+use std::thread;
+
+use tauri::{AppHandle, Emitter};
+
+use super::assembler::CommittedSegment;
+use super::EVENT_TRANSCRIPT_COMMITTED;
+use crate::config::{AppConfig, ClipboardMode, OutputTarget};
+use crate::error::AppError;
+use crate::postprocess::dictation_commands;
+
+enum Job {
+    Copy(String),
+    Inject {
+        text: String,
+        /// As heard, for a profile that turns post-processing off
+        raw: Option<String>,
+    },
+}
+
+/// One session's output targets
+pub struct Outputs {
+    app: AppHandle,
+    display: bool,
+    clipboard: Option<ClipboardMode>,
+    inject: bool,
+    /// None when no target blocks, or the thread couldn't be started
+    jobs: Option<mpsc::Sender<Job>>,
+}
+
+impl Outputs {
+    pub fn new(app: &AppHandle) -> Self {
+        let config = crate::config::load(app).unwrap_or_default();
+        let targets = &crate::deep_link::take_outputs(app)
+            .unwrap_or_else(|| config.inject.output_targets.clone());
+        let clipboard = targets
+            .contains(&OutputTarget::Clipboard)
+            .then_some(config.inject.clipboard_mode);
+        let inject = targets.contains(&OutputTarget::Inject);
+        Self {
+            app: app.clone(),
+            display: targets.contains(&OutputTarget::Display),
+            clipboard,
+            inject,
+            jobs: (clipboard.is_some() || inject)
+                .then(|| start_worker(config))
+                .flatten(),
+        }
+    }
+
+    /// Send a committed segment on; given in the order they committed
+    pub fn committed(&self, segment: CommittedSegment) {
+        // The raw text is the untranslated one, so it only stands in for the original
+        let raw = match segment.translated {
+            Some(_) => None,
+            None => segment.raw_text.clone(),
+        };
+        let text = segment
+            .translated
+            .clone()
+            .unwrap_or_else(|| segment.text.clone());
+        if self.display {
+            let _ = self.app.emit(EVENT_TRANSCRIPT_COMMITTED, segment);
+        }
+        if self.clipboard == Some(ClipboardMode::Segment) {
+            self.send(Job::Copy(text.clone()));
+        }
+        if self.inject {
+            self.send(Job::Inject { text, raw });
+        }
+    }
+
+    /// The session stopped with `output` as its transcript
+    pub fn finish(&self, output: &str) {
+        if self.clipboard == Some(ClipboardMode::Session) && !output.is_empty() {
+            self.send(Job::Copy(output.to_string()));
+        }
+    }
+
+    fn send(&self, job: Job) {
+        if let Some(jobs) = &self.jobs {
+            let _ = jobs.send(job);
+        }
+    }
+}
+
+/// Runs jobs until the session's `Outputs` is dropped
+fn start_worker(config: AppConfig) -> Option<mpsc::Sender<Job>> {
+    let (jobs, job_rx) = mpsc::channel();
+    let spawned = thread::Builder::new()
+        .name("session-output".to_string())
+        .spawn(move || {
+            let mut injected = String::new();
+            for job in job_rx {
+                match job {
+                    Job::Copy(text) => {
+                        if let Err(e) = crate::inject::copy_to_clipboard(&text) {
+                            tracing::warn!("Failed to copy the transcript: {}", e);
+                        }
+                    }
+                    Job::Inject { text, raw } => {
+                        inject_next(&mut injected, &text, raw.as_deref(), |text, raw| {
+                            crate::inject::insert(&config, text, raw, None)
+                                .map(|result| result.text)
+                        })
+                    }
+                }
+            }
+        });
+    match spawned {
+        Ok(_) => Some(jobs),
+        Err(e) => {
+            tracing::warn!("Segments won't be copied or inserted: {}", e);
+            None
+        }
+    }
+}
+
+/// Insert a segment after `injected`, the last one that went in, and keep what went in
+/// this time; `insert` returns that, the trailing suffix included
+fn inject_next(
+    injected: &mut String,
+    text: &str,
+    raw: Option<&str>,
+    insert: impl FnOnce(&str, Option<&str>) -> Result<String, AppError>,
+) {
+    let text = separated(injected, text);
+    let raw = raw.map(|raw| separated(injected, raw));
+    match insert(&text, raw.as_deref()) {
+        Ok(inserted) => *injected = inserted,
+        Err(e) => tracing::warn!("Failed to insert a segment: {}", e),
+    }
+}
+
+/// `text` with whatever should separate it from the segment inserted before it, the way
+/// `dictation_commands::join` would
+fn separated(previous: &str, text: &str) -> String {
+    if previous.is_empty() || previous.ends_with(char::is_whitespace) {
+        return text.to_string();
+    }
+    dictation_commands::join([previous, text])[previous.len()..].to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{InjectMode, Trailing};
+    use crate::inject::Output;
+
+    /// What gets typed for `segments`, each given as (text, raw text)
+    fn typed(output: &Output, segments: &[(&str, &str)]) -> String {
+        let mut injected = String::new();
+        let mut typed = String::new();
+        for (text, raw) in segments {
+            inject_next(&mut injected, text, Some(raw), |text, raw| {
+                let text = output.text(text, raw);
+                typed.push_str(&text);
+                Ok(text)
+            });
+        }
+        typed
+    }
+
+    #[test]
+    fn inserted_segments_are_spaced_like_joined_ones() {
+        assert_eq!(separated("", "Hello."), "Hello.");
+        assert_eq!(separated("Hello.", "How are you?"), " How are you?");
+        assert_eq!(separated("Hello", ", there"), ", there");
+        assert_eq!(separated("Hello.\n", "Next"), "Next");
+    }
+
+    #[test]
+    fn a_trailing_suffix_separates_segments_once() {
+        let segments = [("Hello.", "hello period"), ("How are you?", "how are you")];
+        for (trailing, expected) in [
+            (Trailing::None, "Hello. How are you?"),
+            (Trailing::Space, "Hello. How are you? "),
+            (Trailing::Newline, "Hello.\nHow are you?\n"),
+        ] {
+            let output = Output {
+                mode: InjectMode::Type,
+                trailing,
+                postprocess: true,
+            };
+            assert_eq!(typed(&output, &segments), expected, "{:?}", trailing);
+        }
+        let raw = Output {
+            mode: InjectMode::Type,
+            trailing: Trailing::Space,
+            postprocess: false,
+        };
+        assert_eq!(typed(&raw, &segments), "hello period how are you ");
+    }
+}
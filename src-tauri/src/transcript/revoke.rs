@@ -0,0 +1,110 @@
+// Taking back the last segment of a live session, by saying "scratch that" or with the
+// undo button
+// The latest committed segment stays revocable until the next one commits, and no longer
+// than `revoke_window_ms` when that's set. Revoking it tells the frontend to erase it,
+// backspaces over it if it was already typed into another app, and leaves the session's
+// transcript to drop it before it's saved
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::error::AppError;
+use crate::state;
+
+/// A committed segment was taken back, see `RevokedSegment`
+pub const EVENT_SEGMENT_REVOKED: &str = "segment-revoked";
+
+/// Payload of `segment-revoked`
+#[derive(Debug, Clone, Serialize)]
+pub struct RevokedSegment {
+    /// The id its `transcript-display` and `transcript-committed` events used
+    pub id: u64,
+}
+
+struct Revocable {
+    id: u64,
+    text: String,
+    committed_at: Instant,
+}
+
+#[derive(Default)]
+struct Revocations {
+    revocable: Option<Revocable>,
+    /// Revoked ids the session's transcript hasn't dropped yet
+    pending: Vec<u64>,
+}
+
+/// What can be taken back in the running session; managed state
+#[derive(Default)]
+pub struct RevokeState {
+    revocations: Mutex<Revocations>,
+}
+
+impl RevokeState {
+    /// A session started; nothing from the last one can be taken back any more
+    pub(super) fn reset(&self) {
+        if let Ok(mut revocations) = self.revocations.lock() {
+            *revocations = Revocations::default();
+        }
+    }
+
+    /// `id` committed with `text` and is now the one to take back
+    pub(super) fn committed(&self, id: u64, text: &str) {
+        if let Ok(mut revocations) = self.revocations.lock() {
+            revocations.revocable = Some(Revocable {
+                id,
+                text: text.to_string(),
+                committed_at: Instant::now(),
+            });
+        }
+    }
+
+    /// The ids revoked since the last call
+    pub(super) fn take_pending(&self) -> Vec<u64> {
+        self.revocations
+            .lock()
+            .map(|mut revocations| std::mem::take(&mut revocations.pending))
+            .unwrap_or_default()
+    }
+
+    /// Take back the latest segment, if it still can be; returns its id
+    /// `window_ms` is `DictationCommandSettings::revoke_window_ms`
+    pub fn revoke(&self, app: &AppHandle, window_ms: u32) -> Option<u64> {
+        let revoked = {
+            let mut revocations = self.revocations.lock().ok()?;
+            let revocable = revocations.revocable.take()?;
+            let expired = window_ms > 0
+                && revocable.committed_at.elapsed() > Duration::from_millis(u64::from(window_ms));
+            if expired {
+                return None;
+            }
+            revocations.pending.push(revocable.id);
+            revocable
+        };
+        let _ = app.emit(EVENT_SEGMENT_REVOKED, RevokedSegment { id: revoked.id });
+        // Keystrokes block, and this runs on the session's task
+        tauri::async_runtime::spawn_blocking(move || {
+            if let Err(e) = crate::inject::take_back(&revoked.text) {
+                tracing::warn!("Failed to take back typed text: {}", e);
+            }
+        });
+        tracing::info!("Revoked segment {}", revoked.id);
+        Some(revoked.id)
+    }
+}
+
+/// Command to take back the running session's last segment, as saying "scratch that"
+/// does; returns its id, or None when there's nothing to take back
+#[tauri::command]
+pub async fn undo_last_segment(app: AppHandle) -> Result<Option<u64>, AppError> {
+    state::blocking(move || {
+        let window_ms = crate::config::load(&app)?
+            .dictation_commands
+            .revoke_window_ms;
+        Ok(app.state::<RevokeState>().revoke(&app, window_ms))
+    })
+    .await
+}
@@ -0,0 +1,202 @@
+// Recent final transcripts, for copying one again after it was pasted
+// Only previews are kept in memory; the full text is read from history when copied,
+// and today's entries are reloaded from the history database at startup
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+use tauri::{AppHandle, Manager, State};
+
+use crate::error::AppError;
+use crate::state::{blocking, AppState};
+use crate::storage::Storage;
+
+/// Characters of a transcript shown in the list
+const PREVIEW_CHARS: usize = 80;
+/// `meta` key holding when the history was last cleared, so cleared entries stay gone
+const CLEARED_AT_KEY: &str = "transcript_history_cleared_at";
+
+/// One recent transcript; its id is the history session id
+#[derive(Debug, Clone, Serialize)]
+pub struct TranscriptHistoryEntry {
+    pub id: i64,
+    pub preview: String,
+    /// Unix time in milliseconds the transcript was finished
+    pub created_at: i64,
+    pub char_count: usize,
+}
+
+/// Ring of recent transcripts, newest last; part of `AppState`
+pub struct TranscriptHistory {
+    entries: Mutex<VecDeque<TranscriptHistoryEntry>>,
+    capacity: usize,
+}
+
+impl TranscriptHistory {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity,
+        }
+    }
+
+    /// A ring holding today's sessions since the history was last cleared
+    pub fn load(storage: &Storage, capacity: usize) -> Self {
+        let history = Self::new(capacity);
+        let cleared_at = storage.meta(CLEARED_AT_KEY).unwrap_or_else(|e| {
+            tracing::warn!("{}", e);
+            None
+        });
+        match storage.todays_previews(cleared_at.unwrap_or(0), capacity, PREVIEW_CHARS + 1) {
+            Ok(previews) => {
+                if let Ok(mut entries) = history.entries() {
+                    entries.extend(previews.into_iter().rev().map(|session| {
+                        TranscriptHistoryEntry {
+                            id: session.id,
+                            preview: preview(&session.text),
+                            created_at: session.ended_at,
+                            char_count: session.char_count as usize,
+                        }
+                    }));
+                }
+            }
+            Err(e) => tracing::warn!("Failed to load recent transcripts: {}", e),
+        }
+        history
+    }
+
+    /// Add a just-saved session, dropping the oldest entry when full
+    pub fn push(&self, id: i64, text: &str, created_at: i64) {
+        if self.capacity == 0 || text.trim().is_empty() {
+            return;
+        }
+        let Ok(mut entries) = self.entries() else {
+            return;
+        };
+        while entries.len() >= self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(TranscriptHistoryEntry {
+            id,
+            preview: preview(text),
+            created_at,
+            char_count: text.chars().count(),
+        });
+    }
+
+    /// Entries newest first
+    pub fn list(&self) -> Vec<TranscriptHistoryEntry> {
+        self.entries()
+            .map(|entries| entries.iter().rev().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    fn contains(&self, id: i64) -> Result<bool, AppError> {
+        Ok(self.entries()?.iter().any(|entry| entry.id == id))
+    }
+
+    fn remove(&self, id: i64) {
+        if let Ok(mut entries) = self.entries() {
+            entries.retain(|entry| entry.id != id);
+        }
+    }
+
+    fn entries(&self) -> Result<MutexGuard<'_, VecDeque<TranscriptHistoryEntry>>, AppError> {
+        self.entries
+            .lock()
+            .map_err(|_| AppError::poisoned("Transcript history"))
+    }
+}
+
+/// Remember a live session that was just saved to history
+pub fn record(app: &AppHandle, id: i64, text: &str, created_at: i64) {
+    app.state::<AppState>()
+        .transcripts
+        .push(id, text, created_at);
+}
+
+/// The first `PREVIEW_CHARS` characters, with an ellipsis when there is more
+fn preview(text: &str) -> String {
+    let text = text.trim();
+    match text.char_indices().nth(PREVIEW_CHARS) {
+        Some((end, _)) => format!("{}…", text[..end].trim_end()),
+        None => text.to_string(),
+    }
+}
+
+/// Command to list recent transcripts, newest first
+#[tauri::command]
+pub fn get_transcript_history(state: State<'_, AppState>) -> Vec<TranscriptHistoryEntry> {
+    state.transcripts.list()
+}
+
+/// Command to put a recent transcript's full text back on the clipboard
+#[tauri::command]
+pub async fn copy_transcript_to_clipboard(
+    state: State<'_, AppState>,
+    id: i64,
+) -> Result<(), AppError> {
+    if !state.transcripts.contains(id)? {
+        return Err(AppError::NotFound(format!(
+            "Transcript {} is not in the recent history",
+            id
+        )));
+    }
+    let storage = Arc::clone(&state.storage);
+    let session = blocking(move || storage.get_session(id)).await?;
+    let Some(session) = session else {
+        // Deleted from history since it was added
+        state.transcripts.remove(id);
+        return Err(AppError::NotFound(format!(
+            "Transcript {} no longer exists",
+            id
+        )));
+    };
+    blocking(move || crate::inject::copy_to_clipboard(&session.text)).await
+}
+
+/// Command to forget all recent transcripts; history sessions are kept
+#[tauri::command]
+pub async fn clear_transcript_history(state: State<'_, AppState>) -> Result<(), AppError> {
+    if let Ok(mut entries) = state.transcripts.entries() {
+        entries.clear();
+    }
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0);
+    let storage = Arc::clone(&state.storage);
+    blocking(move || storage.set_meta(CLEARED_AT_KEY, now)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn preview_cuts_on_a_char_boundary() {
+        assert_eq!(preview("  short one "), "short one");
+        let long = "é".repeat(PREVIEW_CHARS + 5);
+        let cut = preview(&long);
+        assert_eq!(cut.chars().count(), PREVIEW_CHARS + 1);
+        assert!(cut.ends_with('…'));
+        assert_eq!(
+            preview(&"日本".repeat(PREVIEW_CHARS)).chars().count(),
+            PREVIEW_CHARS + 1
+        );
+    }
+
+    #[test]
+    fn ring_drops_the_oldest_and_lists_newest_first() {
+        let history = TranscriptHistory::new(2);
+        history.push(1, "one", 10);
+        history.push(2, "two", 20);
+        history.push(3, "three", 30);
+        history.push(4, "   ", 40);
+        let ids: Vec<i64> = history.list().iter().map(|entry| entry.id).collect();
+        assert_eq!(ids, [3, 2]);
+        assert_eq!(history.list()[0].char_count, 5);
+    }
+}
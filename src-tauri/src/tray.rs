@@ -0,0 +1,198 @@
+// System tray icon and menu
+// The icon follows the same `connection-state` events the frontend status uses
+
+use tauri::image::Image;
+use tauri::menu::{Menu, MenuEvent, MenuItem, PredefinedMenuItem};
+use tauri::tray::TrayIconBuilder;
+use tauri::{AppHandle, Emitter, Listener, Manager, Wry};
+
+use crate::deepgram::proxy::{ConnectionState, EVENT_CONNECTION_STATE, STOPPED_REASON};
+use crate::error::AppError;
+
+/// Emitted when "Settings" is picked from the tray menu
+pub const EVENT_OPEN_SETTINGS: &str = "open-settings";
+
+const TRAY_ID: &str = "main";
+const MAIN_WINDOW: &str = "main";
+
+const MENU_START: &str = "start";
+const MENU_STOP: &str = "stop";
+const MENU_SHOW: &str = "show";
+const MENU_SETTINGS: &str = "settings";
+const MENU_QUIT: &str = "quit";
+
+const RECORDING_COLOR: [u8; 3] = [0xE5, 0x39, 0x35];
+const ERROR_COLOR: [u8; 3] = [0xFB, 0x8C, 0x00];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TrayStatus {
+    Idle,
+    Recording,
+    Error,
+}
+
+impl TrayStatus {
+    fn from_connection(state: &ConnectionState) -> Self {
+        match state {
+            ConnectionState::Connecting
+            | ConnectionState::Open
+            | ConnectionState::Reconnecting { .. } => Self::Recording,
+            ConnectionState::Closed { reason } if reason == STOPPED_REASON => Self::Idle,
+            ConnectionState::Closed { .. } => Self::Error,
+        }
+    }
+
+    fn tooltip(self) -> &'static str {
+        match self {
+            Self::Idle => "SubSpace Voice",
+            Self::Recording => "SubSpace Voice - dictating",
+            Self::Error => "SubSpace Voice - transcription stopped with an error",
+        }
+    }
+}
+
+/// Managed state holding the menu items and icons that change with the status
+struct TrayState {
+    start: MenuItem<Wry>,
+    stop: MenuItem<Wry>,
+    idle_icon: Image<'static>,
+    recording_icon: Image<'static>,
+    error_icon: Image<'static>,
+}
+
+/// Create the tray icon; the app keeps working without it if the platform has no tray
+pub fn init(app: &AppHandle) -> Result<(), AppError> {
+    let tray_error = |e: tauri::Error| AppError::Internal(format!("System tray failed: {}", e));
+    let base = app
+        .default_window_icon()
+        .cloned()
+        .ok_or_else(|| AppError::Internal("The app has no icon for the tray".to_string()))?
+        .to_owned();
+
+    let start = MenuItem::with_id(app, MENU_START, "Start dictation", true, None::<&str>)
+        .map_err(tray_error)?;
+    let stop =
+        MenuItem::with_id(app, MENU_STOP, "Stop", false, None::<&str>).map_err(tray_error)?;
+    let show =
+        MenuItem::with_id(app, MENU_SHOW, "Show window", true, None::<&str>).map_err(tray_error)?;
+    let settings = MenuItem::with_id(app, MENU_SETTINGS, "Settings", true, None::<&str>)
+        .map_err(tray_error)?;
+    let quit = MenuItem::with_id(app, MENU_QUIT, "Quit", true, None::<&str>).map_err(tray_error)?;
+    let menu = Menu::with_items(
+        app,
+        &[
+            &start,
+            &stop,
+            &PredefinedMenuItem::separator(app).map_err(tray_error)?,
+            &show,
+            &settings,
+            &PredefinedMenuItem::separator(app).map_err(tray_error)?,
+            &quit,
+        ],
+    )
+    .map_err(tray_error)?;
+
+    TrayIconBuilder::with_id(TRAY_ID)
+        .icon(base.clone())
+        .tooltip(TrayStatus::Idle.tooltip())
+        .menu(&menu)
+        .on_menu_event(handle_menu)
+        .build(app)
+        .map_err(tray_error)?;
+
+    app.manage(TrayState {
+        start,
+        stop,
+        recording_icon: badged(&base, RECORDING_COLOR),
+        error_icon: badged(&base, ERROR_COLOR),
+        idle_icon: base,
+    });
+
+    let handle = app.clone();
+    app.listen(
+        EVENT_CONNECTION_STATE,
+        move |event| match serde_json::from_str::<ConnectionState>(event.payload()) {
+            Ok(state) => set_status(&handle, TrayStatus::from_connection(&state)),
+            Err(e) => tracing::warn!("Unexpected connection-state payload: {}", e),
+        },
+    );
+    Ok(())
+}
+
+/// Whether closing the main window should hide it instead of quitting
+/// Never hides when there is no tray icon to bring it back from
+pub fn hides_on_close(app: &AppHandle) -> bool {
+    let close_to_tray = crate::config::load(app)
+        .map(|config| config.tray.close_to_tray)
+        .unwrap_or_default();
+    close_to_tray && app.tray_by_id(TRAY_ID).is_some()
+}
+
+fn handle_menu(app: &AppHandle, event: MenuEvent) {
+    match event.id.as_ref() {
+        MENU_START => crate::hotkey::set_dictation(app, true),
+        MENU_STOP => crate::hotkey::set_dictation(app, false),
+        MENU_SHOW => show_main_window(app),
+        MENU_SETTINGS => {
+            show_main_window(app);
+            let _ = app.emit(EVENT_OPEN_SETTINGS, ());
+        }
+        // Exiting goes through `shutdown`, which saves the session first
+        MENU_QUIT => app.exit(0),
+        _ => {}
+    }
+}
+
+fn set_status(app: &AppHandle, status: TrayStatus) {
+    let state = app.state::<TrayState>();
+    let icon = match status {
+        TrayStatus::Idle => &state.idle_icon,
+        TrayStatus::Recording => &state.recording_icon,
+        TrayStatus::Error => &state.error_icon,
+    };
+    if let Some(tray) = app.tray_by_id(TRAY_ID) {
+        let _ = tray.set_icon(Some(icon.clone()));
+        let _ = tray.set_tooltip(Some(status.tooltip()));
+    }
+    let recording = status == TrayStatus::Recording;
+    let _ = state.start.set_enabled(!recording);
+    let _ = state.stop.set_enabled(recording);
+}
+
+pub fn show_main_window(app: &AppHandle) {
+    if let Some(window) = app.get_webview_window(MAIN_WINDOW) {
+        let _ = window.unminimize();
+        let _ = window.show();
+        let _ = window.set_focus();
+    }
+}
+
+/// Take the icon down, for exit
+pub fn remove(app: &AppHandle) {
+    let _ = app.remove_tray_by_id(TRAY_ID);
+}
+
+/// The base icon with a coloured dot in the bottom-right corner
+fn badged(base: &Image<'_>, color: [u8; 3]) -> Image<'static> {
+    let (width, height) = (base.width(), base.height());
+    let mut rgba = base.rgba().to_vec();
+    let radius = width.min(height) as f32 * 0.22;
+    let outline = radius + (radius * 0.25).max(1.0);
+    let (cx, cy) = (width as f32 - outline, height as f32 - outline);
+
+    for y in 0..height {
+        for x in 0..width {
+            let distance = (x as f32 + 0.5 - cx).hypot(y as f32 + 0.5 - cy);
+            let pixel = if distance <= radius {
+                [color[0], color[1], color[2], 0xFF]
+            } else if distance <= outline {
+                [0xFF; 4]
+            } else {
+                continue;
+            };
+            let offset = ((y * width + x) * 4) as usize;
+            rgba[offset..offset + 4].copy_from_slice(&pixel);
+        }
+    }
+    Image::new_owned(rgba, width, height)
+}
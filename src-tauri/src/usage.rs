@@ -0,0 +1,175 @@
+// Deepgram usage tracking: billed audio minutes per local day and model
+// Durations come from the audio actually sent, so pauses between sessions don't count
+
+use std::sync::Arc;
+
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, State};
+
+use crate::config::UsageSettings;
+use crate::error::AppError;
+use crate::state::{blocking, AppState};
+use crate::storage::Storage;
+
+/// Period covered by `get_usage_summary`, and by `get_dictation_stats` in `stats`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UsageRange {
+    Today,
+    /// The last 7 days, including today
+    Week,
+    /// The last 30 days, including today
+    Month,
+    /// The last 90 days, including today
+    Quarter,
+}
+
+impl UsageRange {
+    /// Earliest day included, as an SQLite date modifier relative to today
+    pub(crate) fn since_modifier(self) -> &'static str {
+        match self {
+            Self::Today => "+0 days",
+            Self::Week => "-6 days",
+            Self::Month => "-29 days",
+            Self::Quarter => "-89 days",
+        }
+    }
+}
+
+/// Usage of one model within the range
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelUsage {
+    pub model: String,
+    pub minutes: f64,
+    pub session_count: u64,
+}
+
+/// Result of `get_usage_summary`
+#[derive(Debug, Clone, Serialize)]
+pub struct UsageSummary {
+    pub range: UsageRange,
+    pub minutes: f64,
+    pub session_count: u64,
+    /// `minutes` times the configured per-minute rate
+    pub estimated_cost: f64,
+    pub cost_per_minute: f64,
+    pub by_model: Vec<ModelUsage>,
+}
+
+impl Storage {
+    /// Add one session's billed audio to the day it started on (local time)
+    pub fn record_usage(
+        &self,
+        started_at: i64,
+        model: &str,
+        audio_ms: i64,
+    ) -> Result<(), AppError> {
+        self.conn()?
+            .execute(
+                "INSERT INTO usage (day, model, audio_ms, sessions)
+                 VALUES (date(?1 / 1000, 'unixepoch', 'localtime'), ?2, ?3, 1)
+                 ON CONFLICT (day, model) DO UPDATE SET
+                     audio_ms = audio_ms + excluded.audio_ms,
+                     sessions = sessions + 1",
+                params![started_at, model, audio_ms],
+            )
+            .map_err(|e| AppError::Storage(format!("Failed to record usage: {}", e)))?;
+        Ok(())
+    }
+
+    /// Per-model totals for `range`, most used first
+    pub fn usage(&self, range: UsageRange) -> Result<Vec<ModelUsage>, AppError> {
+        let conn = self.conn()?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT model, SUM(audio_ms), SUM(sessions) FROM usage
+                 WHERE day >= date('now', 'localtime', ?1)
+                 GROUP BY model ORDER BY SUM(audio_ms) DESC",
+            )
+            .map_err(|e| AppError::Storage(format!("Failed to load usage: {}", e)))?;
+        let rows = stmt
+            .query_map(params![range.since_modifier()], |row| {
+                let audio_ms: i64 = row.get(1)?;
+                let sessions: i64 = row.get(2)?;
+                Ok(ModelUsage {
+                    model: row.get(0)?,
+                    minutes: audio_ms as f64 / 60_000.0,
+                    session_count: sessions as u64,
+                })
+            })
+            .map_err(|e| AppError::Storage(format!("Failed to load usage: {}", e)))?;
+        rows.collect::<rusqlite::Result<_>>()
+            .map_err(|e| AppError::Storage(format!("Failed to load usage: {}", e)))
+    }
+
+    pub fn reset_usage(&self) -> Result<(), AppError> {
+        self.conn()?
+            .execute("DELETE FROM usage", [])
+            .map_err(|e| AppError::Storage(format!("Failed to reset usage: {}", e)))?;
+        Ok(())
+    }
+}
+
+/// Record usage, logging instead of failing; losing a statistic mustn't lose a transcript
+pub fn record(storage: &Storage, started_at: i64, model: &str, audio_ms: i64) {
+    if audio_ms <= 0 {
+        return;
+    }
+    if let Err(e) = storage.record_usage(started_at, model, audio_ms) {
+        tracing::error!("{}", e);
+    }
+}
+
+/// Command to summarize usage and estimated cost over a range
+#[tauri::command]
+pub async fn get_usage_summary(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    range: UsageRange,
+) -> Result<UsageSummary, AppError> {
+    let storage = Arc::clone(&state.storage);
+    let (cost_per_minute, by_model) = blocking(move || {
+        let cost_per_minute = crate::config::load(&app)
+            .map(|config| config.usage.cost_per_minute)
+            .unwrap_or_else(|_| UsageSettings::default().cost_per_minute);
+        Ok((cost_per_minute, storage.usage(range)?))
+    })
+    .await?;
+    let minutes = by_model.iter().map(|usage| usage.minutes).sum::<f64>();
+    Ok(UsageSummary {
+        range,
+        minutes,
+        session_count: by_model.iter().map(|usage| usage.session_count).sum(),
+        estimated_cost: minutes * cost_per_minute,
+        cost_per_minute,
+        by_model,
+    })
+}
+
+/// Command to change the per-minute rate used for cost estimates
+#[tauri::command]
+pub async fn set_usage_settings(
+    app: AppHandle,
+    cost_per_minute: f64,
+) -> Result<UsageSettings, AppError> {
+    if !cost_per_minute.is_finite() || cost_per_minute < 0.0 {
+        return Err(AppError::InvalidInput(
+            "cost_per_minute must be zero or more".to_string(),
+        ));
+    }
+    blocking(move || {
+        crate::config::update(&app, |config| {
+            config.usage.cost_per_minute = cost_per_minute;
+            Ok(config.usage.clone())
+        })
+    })
+    .await
+}
+
+/// Command to clear all usage statistics (history is kept)
+#[tauri::command]
+pub async fn reset_usage_stats(state: State<'_, AppState>) -> Result<(), AppError> {
+    let storage = Arc::clone(&state.storage);
+    blocking(move || storage.reset_usage()).await
+}
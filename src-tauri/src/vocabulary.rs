@@ -0,0 +1,123 @@
+// Custom vocabulary boosted on every Deepgram request, live and pre-recorded
+// The URL builders in `deepgram` turn each term into a `keywords` (or `keyterm`) parameter
+
+use tauri::AppHandle;
+
+use crate::config::VocabularyTerm;
+use crate::error::AppError;
+use crate::state;
+
+/// Most keywords Deepgram accepts on one request
+pub const MAX_TERMS: usize = 100;
+
+/// Characters Deepgram accepts in a keyword besides letters and digits
+/// ':' in particular would be read as the boost separator
+fn is_allowed(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, ' ' | '\'' | '-' | '.')
+}
+
+/// Trim every term and reject the list if any entry would be refused by Deepgram
+fn validate(terms: Vec<VocabularyTerm>) -> Result<Vec<VocabularyTerm>, AppError> {
+    if terms.len() > MAX_TERMS {
+        return Err(AppError::InvalidInput(format!(
+            "Vocabulary has {} terms; Deepgram accepts at most {}",
+            terms.len(),
+            MAX_TERMS
+        )));
+    }
+    let terms: Vec<VocabularyTerm> = terms
+        .into_iter()
+        .map(|entry| VocabularyTerm {
+            term: entry.term.trim().to_string(),
+            boost: entry.boost,
+        })
+        .collect();
+    let invalid: Vec<String> = terms
+        .iter()
+        .filter(|entry| {
+            entry.term.is_empty()
+                || !entry.term.chars().all(is_allowed)
+                || entry.boost.is_some_and(|boost| !boost.is_finite())
+        })
+        .map(|entry| format!("\"{}\"", entry.term))
+        .collect();
+    if !invalid.is_empty() {
+        return Err(AppError::InvalidInput(format!(
+            "Invalid vocabulary terms: {}. Terms must be non-empty, contain only letters, \
+             digits, spaces, apostrophes, hyphens and periods, and have a finite boost",
+            invalid.join(", ")
+        )));
+    }
+    Ok(terms)
+}
+
+/// Command to read the custom vocabulary
+#[tauri::command]
+pub async fn get_vocabulary(app: AppHandle) -> Result<Vec<VocabularyTerm>, AppError> {
+    state::blocking(move || crate::config::load(&app).map(|config| config.vocabulary.terms)).await
+}
+
+/// Command to replace the custom vocabulary; it applies from the next stream or file
+#[tauri::command]
+pub async fn set_vocabulary(
+    app: AppHandle,
+    terms: Vec<VocabularyTerm>,
+) -> Result<Vec<VocabularyTerm>, AppError> {
+    let terms = validate(terms)?;
+    state::blocking(move || {
+        crate::config::update(&app, |config| {
+            config.vocabulary.terms = terms.clone();
+            Ok(terms)
+        })
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn term(term: &str, boost: Option<f32>) -> VocabularyTerm {
+        VocabularyTerm {
+            term: term.to_string(),
+            boost,
+        }
+    }
+
+    #[test]
+    fn trims_valid_terms() {
+        let terms = validate(vec![term("  Deepgram ", Some(2.0)), term("O'Neil", None)]).unwrap();
+        assert_eq!(
+            terms,
+            vec![term("Deepgram", Some(2.0)), term("O'Neil", None)]
+        );
+    }
+
+    #[test]
+    fn lists_every_invalid_term() {
+        let err = validate(vec![
+            term("ok", None),
+            term("foo:bar", None),
+            term(" ", None),
+            term("nan", Some(f32::NAN)),
+        ])
+        .unwrap_err();
+        let AppError::InvalidInput(message) = err else {
+            panic!("expected InvalidInput, got {:?}", err);
+        };
+        assert!(
+            message.contains("\"foo:bar\", \"\", \"nan\""),
+            "{}",
+            message
+        );
+        assert!(!message.contains("\"ok\""), "{}", message);
+    }
+
+    #[test]
+    fn rejects_too_many_terms() {
+        let terms = (0..=MAX_TERMS)
+            .map(|i| term(&format!("t{}", i), None))
+            .collect();
+        assert!(validate(terms).is_err());
+    }
+}
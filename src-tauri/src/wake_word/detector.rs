@@ -0,0 +1,198 @@
+// Wake word spotting with whisper.cpp on the detector's own microphone capture
+// Speech is cut into short bursts at pauses; only bursts about as long as a phrase are
+// transcribed, on a single thread, which keeps the always-on cost to a few percent of a core
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use tauri::{AppHandle, Emitter};
+use tokio::sync::mpsc;
+use whisper_rs::{FullParams, SamplingStrategy, WhisperContext, WhisperContextParameters};
+
+use super::{heard, Usage, WakeWordDetected, EVENT_WAKE_WORD_DETECTED};
+use crate::audio::capture::{self, Listener};
+use crate::audio::{vad, BYTES_PER_MS};
+use crate::config::WakeWordSettings;
+use crate::engine::models;
+use crate::error::AppError;
+
+/// Chunk level, in dBFS, at or above which a chunk counts as speech
+const SPEECH_THRESHOLD_DB: f32 = -45.0;
+/// Silence that ends a burst
+const PAUSE_MS: usize = 400;
+/// Audio kept ahead of detected speech so the phrase's first sound isn't cut off
+const PRE_ROLL_MS: usize = 300;
+/// Bursts outside this range can't be just the phrase and aren't transcribed
+const MIN_BURST_MS: usize = 300;
+const MAX_BURST_MS: usize = 3_000;
+/// Audio ignored after a detection, until the session it started takes over
+const COOLDOWN: Duration = Duration::from_secs(5);
+/// Chunks buffered between the capture and the spotting thread; ~6 s
+const CHUNK_QUEUE_CAPACITY: usize = 64;
+
+/// A running detector; dropping it stops the capture, which ends the thread
+pub struct Detector {
+    listener: Option<Listener>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Detector {
+    /// Load the spotting model and start listening; blocks while the model loads
+    pub fn start(
+        app: &AppHandle,
+        settings: &WakeWordSettings,
+        usage: Arc<Usage>,
+    ) -> Result<Self, AppError> {
+        let path = models::model_path(app, &settings.model)?;
+        if !path.exists() {
+            return Err(AppError::NotFound(format!(
+                "Whisper model \"{}\" is not downloaded",
+                settings.model
+            )));
+        }
+        let context =
+            WhisperContext::new_with_params(&path, WhisperContextParameters::default())
+                .map_err(|e| AppError::Internal(format!("Failed to load Whisper model: {}", e)))?;
+
+        let (chunk_tx, chunk_rx) = mpsc::channel(CHUNK_QUEUE_CAPACITY);
+        let listener = capture::listen(app, chunk_tx)?;
+        let spotter = Spotter {
+            app: app.clone(),
+            phrase: settings.phrase.clone(),
+            threshold: 10f32.powf(SPEECH_THRESHOLD_DB / 20.0),
+            usage,
+        };
+        let thread = std::thread::Builder::new()
+            .name("wake-word".to_string())
+            .spawn(move || spotter.run(context, chunk_rx))
+            .map_err(|e| AppError::Internal(format!("Failed to start wake word thread: {}", e)))?;
+        Ok(Self {
+            listener: Some(listener),
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for Detector {
+    fn drop(&mut self) {
+        // Stopping the capture closes the channel the thread reads from
+        drop(self.listener.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+struct Spotter {
+    app: AppHandle,
+    phrase: String,
+    /// Chunk RMS at or above which a chunk counts as speech
+    threshold: f32,
+    usage: Arc<Usage>,
+}
+
+impl Spotter {
+    fn run(self, context: WhisperContext, mut chunk_rx: mpsc::Receiver<Vec<u8>>) {
+        let mut state = match context.create_state() {
+            Ok(state) => state,
+            Err(e) => {
+                tracing::error!("Failed to create Whisper state for the wake word: {}", e);
+                return;
+            }
+        };
+
+        let mut pre_roll: VecDeque<Vec<u8>> = VecDeque::new();
+        let mut burst: Vec<u8> = Vec::new();
+        let mut silent_ms = 0;
+        let mut cooldown_until: Option<Instant> = None;
+
+        while let Some(chunk) = chunk_rx.blocking_recv() {
+            let chunk_ms = chunk.len() / BYTES_PER_MS as usize;
+            let started = Instant::now();
+            if cooldown_until.is_some_and(|until| started < until) {
+                self.usage.add(chunk_ms as u64, 0);
+                continue;
+            }
+            cooldown_until = None;
+
+            let speech = vad::rms(&chunk) >= self.threshold;
+            if burst.is_empty() {
+                if speech {
+                    burst.extend(pre_roll.drain(..).flatten());
+                    burst.extend(chunk);
+                    silent_ms = 0;
+                } else {
+                    pre_roll.push_back(chunk);
+                    while pre_roll.iter().map(Vec::len).sum::<usize>()
+                        > PRE_ROLL_MS * BYTES_PER_MS as usize
+                    {
+                        pre_roll.pop_front();
+                    }
+                }
+            } else {
+                burst.extend(chunk);
+                silent_ms = if speech { 0 } else { silent_ms + chunk_ms };
+                let burst_ms = burst.len() / BYTES_PER_MS as usize;
+                if burst_ms > MAX_BURST_MS + PAUSE_MS {
+                    // Someone talking at length; wait for them to pause before listening again
+                    burst.clear();
+                    pre_roll.clear();
+                } else if silent_ms >= PAUSE_MS {
+                    let spoken_ms = burst_ms - silent_ms;
+                    if (MIN_BURST_MS..=MAX_BURST_MS).contains(&spoken_ms)
+                        && self.spot(&mut state, &burst)
+                    {
+                        cooldown_until = Some(Instant::now() + COOLDOWN);
+                        pre_roll.clear();
+                    }
+                    burst.clear();
+                }
+            }
+            self.usage
+                .add(chunk_ms as u64, started.elapsed().as_millis() as u64);
+        }
+    }
+
+    /// Transcribe one burst; true if it held the phrase, in which case dictation is started
+    fn spot(&self, state: &mut whisper_rs::WhisperState, audio: &[u8]) -> bool {
+        let samples: Vec<f32> = audio
+            .chunks_exact(2)
+            .map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]]) as f32 / i16::MAX as f32)
+            .collect();
+
+        let mut params = FullParams::new(SamplingStrategy::Greedy { best_of: 1 });
+        params.set_language(Some("en"));
+        params.set_n_threads(1);
+        params.set_no_context(true);
+        params.set_single_segment(true);
+        params.set_suppress_blank(true);
+        params.set_suppress_nst(true);
+        params.set_print_special(false);
+        params.set_print_progress(false);
+        params.set_print_realtime(false);
+        params.set_print_timestamps(false);
+        if let Err(e) = state.full(params, &samples) {
+            tracing::warn!("Whisper failed on a wake word burst: {}", e);
+            return false;
+        }
+
+        let transcript: String = state
+            .as_iter()
+            .filter_map(|segment| segment.to_str_lossy().ok().map(|text| text.into_owned()))
+            .collect();
+        if !heard(&transcript, &self.phrase) {
+            return false;
+        }
+        tracing::info!("Wake word \"{}\" heard", self.phrase);
+        let _ = self.app.emit(
+            EVENT_WAKE_WORD_DETECTED,
+            WakeWordDetected {
+                phrase: self.phrase.clone(),
+            },
+        );
+        crate::hotkey::set_dictation(&self.app, true);
+        true
+    }
+}
@@ -0,0 +1,297 @@
+// Hands-free start of dictation: listen for a phrase and start a session when it's said
+// The detector has its own microphone capture and transcribes short bursts of speech with
+// a small Whisper model, so nothing leaves the machine until the session starts. It stops
+// while a session is running and starts again once the session ends
+
+#[cfg(feature = "wake-word")]
+mod detector;
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+use tauri::{AppHandle, Manager, State};
+
+use crate::config::WakeWordSettings;
+use crate::engine::SessionState;
+use crate::error::AppError;
+use crate::state::blocking;
+
+#[cfg(feature = "wake-word")]
+use self::detector::Detector;
+
+/// Event emitted with a `WakeWordDetected` when the phrase is heard, just before
+/// dictation starts
+#[cfg_attr(not(feature = "wake-word"), allow(dead_code))]
+pub const EVENT_WAKE_WORD_DETECTED: &str = "wake-word-detected";
+
+/// Phrases the detector can listen for; all English, to match the spotting model
+pub const PHRASES: &[&str] = &[
+    "hey subspace",
+    "start dictation",
+    "okay computer",
+    "hey computer",
+];
+
+/// Payload of the `wake-word-detected` event
+#[derive(Debug, Clone, Serialize)]
+#[cfg_attr(not(feature = "wake-word"), allow(dead_code))]
+pub struct WakeWordDetected {
+    pub phrase: String,
+}
+
+/// Detector state, as returned by the wake word commands and put in the diagnostics bundle
+#[derive(Debug, Clone, Serialize)]
+pub struct WakeWordStatus {
+    pub enabled: bool,
+    pub phrase: String,
+    /// The detector is running; false while a session is
+    pub listening: bool,
+    /// This build includes the detector
+    pub available: bool,
+    /// Audio the detector has listened to since launch
+    pub listened_secs: f64,
+    /// Time spent spotting it, on one core
+    pub busy_secs: f64,
+    /// `busy_secs` as a share of `listened_secs`, i.e. the share of one core it costs;
+    /// None until it has listened for a while
+    pub cpu_percent: Option<f64>,
+}
+
+/// Running totals the detector adds to
+#[derive(Default)]
+pub(crate) struct Usage {
+    listened_ms: AtomicU64,
+    busy_ms: AtomicU64,
+}
+
+impl Usage {
+    #[cfg_attr(not(feature = "wake-word"), allow(dead_code))]
+    pub(crate) fn add(&self, listened_ms: u64, busy_ms: u64) {
+        self.listened_ms.fetch_add(listened_ms, Ordering::Relaxed);
+        self.busy_ms.fetch_add(busy_ms, Ordering::Relaxed);
+    }
+}
+
+/// Stand-in for builds without the detector
+#[cfg(not(feature = "wake-word"))]
+struct Detector;
+
+#[cfg(not(feature = "wake-word"))]
+impl Detector {
+    fn start(_: &AppHandle, _: &WakeWordSettings, _: Arc<Usage>) -> Result<Self, AppError> {
+        Err(unavailable())
+    }
+}
+
+/// Managed state holding the running detector, if any
+#[derive(Default)]
+pub struct WakeWordState {
+    detector: Mutex<Option<Detector>>,
+    session_active: AtomicBool,
+    usage: Arc<Usage>,
+}
+
+impl WakeWordState {
+    /// Start listening with `settings`, replacing a running detector
+    /// Does nothing while a session is running; it starts when the session ends
+    fn start(&self, app: &AppHandle, settings: &WakeWordSettings) -> Result<(), AppError> {
+        if !cfg!(feature = "wake-word") {
+            return Err(unavailable());
+        }
+        validate_phrase(&settings.phrase)?;
+        let mut detector = self
+            .detector
+            .lock()
+            .map_err(|_| AppError::poisoned("Wake word detector"))?;
+        // Stop the old one first so the two don't both hold the microphone
+        detector.take();
+        if self.session_active.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        *detector = Some(Detector::start(app, settings, Arc::clone(&self.usage))?);
+        tracing::info!("Listening for the wake word \"{}\"", settings.phrase);
+        Ok(())
+    }
+
+    fn stop(&self) {
+        // Dropping the detector stops its capture and joins its thread
+        if let Ok(mut detector) = self.detector.lock() {
+            detector.take();
+        }
+    }
+
+    fn status(&self, settings: &WakeWordSettings) -> WakeWordStatus {
+        let listened_ms = self.usage.listened_ms.load(Ordering::Relaxed);
+        let busy_ms = self.usage.busy_ms.load(Ordering::Relaxed);
+        WakeWordStatus {
+            enabled: settings.enabled,
+            phrase: settings.phrase.clone(),
+            listening: self.detector.lock().is_ok_and(|d| d.is_some()),
+            available: cfg!(feature = "wake-word"),
+            listened_secs: listened_ms as f64 / 1000.0,
+            busy_secs: busy_ms as f64 / 1000.0,
+            cpu_percent: cpu_percent(listened_ms, busy_ms),
+        }
+    }
+}
+
+/// Start the detector at launch when it's enabled; failures are logged, not fatal
+pub fn init(app: &AppHandle) {
+    let app = app.clone();
+    // Loading the model reads hundreds of MB from disk
+    tauri::async_runtime::spawn_blocking(move || {
+        let settings = match crate::config::load(&app) {
+            Ok(config) if config.wake_word.enabled => config.wake_word,
+            _ => return,
+        };
+        if let Err(e) = app.state::<WakeWordState>().start(&app, &settings) {
+            tracing::warn!("Wake word detector not started: {}", e);
+        }
+    });
+}
+
+/// Follow the session state: stop listening while a session runs, so the detector
+/// neither re-triggers nor competes with it, and listen again once it has ended
+pub fn session_changed(app: &AppHandle, state: SessionState) {
+    let wake_word = app.state::<WakeWordState>();
+    match state {
+        SessionState::Starting
+        | SessionState::Recording
+        | SessionState::Paused
+        | SessionState::Stopping => {
+            if !wake_word.session_active.swap(true, Ordering::Relaxed) {
+                // Joining the detector thread can wait for a transcription in progress
+                let app = app.clone();
+                tauri::async_runtime::spawn_blocking(move || app.state::<WakeWordState>().stop());
+            }
+        }
+        SessionState::Idle => {
+            wake_word.session_active.store(false, Ordering::Relaxed);
+            init(app);
+        }
+    }
+}
+
+/// Command to turn the wake word on or off; enabling fails if the detector can't start,
+/// e.g. because its model isn't downloaded, and the setting is left off
+#[tauri::command]
+pub async fn set_wake_word_enabled(
+    app: AppHandle,
+    state: State<'_, WakeWordState>,
+    enabled: bool,
+) -> Result<WakeWordStatus, AppError> {
+    let handle = app.clone();
+    let settings = blocking(move || {
+        crate::config::update(&handle, |config| {
+            let wake_word = handle.state::<WakeWordState>();
+            if enabled {
+                wake_word.start(&handle, &config.wake_word)?;
+            } else {
+                wake_word.stop();
+            }
+            config.wake_word.enabled = enabled;
+            Ok(config.wake_word.clone())
+        })
+    })
+    .await?;
+    Ok(state.status(&settings))
+}
+
+/// Command to pick the phrase, one of `list_wake_word_phrases`
+#[tauri::command]
+pub async fn set_wake_word_phrase(
+    app: AppHandle,
+    state: State<'_, WakeWordState>,
+    phrase: String,
+) -> Result<WakeWordStatus, AppError> {
+    validate_phrase(&phrase)?;
+    let handle = app.clone();
+    let settings = blocking(move || {
+        let settings = crate::config::update(&handle, |config| {
+            config.wake_word.phrase = phrase;
+            Ok(config.wake_word.clone())
+        })?;
+        let wake_word = handle.state::<WakeWordState>();
+        // A running detector is still listening for the old phrase
+        if settings.enabled && wake_word.status(&settings).listening {
+            wake_word.start(&handle, &settings)?;
+        }
+        Ok(settings)
+    })
+    .await?;
+    Ok(state.status(&settings))
+}
+
+#[tauri::command]
+pub async fn get_wake_word_status(
+    app: AppHandle,
+    state: State<'_, WakeWordState>,
+) -> Result<WakeWordStatus, AppError> {
+    let settings = blocking(move || Ok(crate::config::load(&app)?.wake_word)).await?;
+    Ok(state.status(&settings))
+}
+
+#[tauri::command]
+pub fn list_wake_word_phrases() -> Vec<String> {
+    PHRASES.iter().map(|phrase| phrase.to_string()).collect()
+}
+
+/// Status for the diagnostics bundle
+pub fn status(app: &AppHandle, settings: &WakeWordSettings) -> WakeWordStatus {
+    app.state::<WakeWordState>().status(settings)
+}
+
+fn validate_phrase(phrase: &str) -> Result<(), AppError> {
+    if PHRASES.contains(&phrase) {
+        Ok(())
+    } else {
+        Err(AppError::Config(format!(
+            "Unknown wake word \"{}\"; choose one of: {}",
+            phrase,
+            PHRASES.join(", ")
+        )))
+    }
+}
+
+fn unavailable() -> AppError {
+    AppError::Unsupported("This build doesn't include the wake word detector".to_string())
+}
+
+/// Share of one core, once at least a minute of audio has been listened to
+fn cpu_percent(listened_ms: u64, busy_ms: u64) -> Option<f64> {
+    (listened_ms >= 60_000).then(|| busy_ms as f64 * 100.0 / listened_ms as f64)
+}
+
+/// Whether `transcript` contains `phrase`, ignoring case, punctuation and spacing,
+/// since the model may hear "hey subspace" as "Hey, sub-space!"
+#[cfg_attr(not(feature = "wake-word"), allow(dead_code))]
+pub(crate) fn heard(transcript: &str, phrase: &str) -> bool {
+    let squash = |text: &str| -> String {
+        text.chars()
+            .filter(|c| c.is_alphanumeric())
+            .flat_map(char::to_lowercase)
+            .collect()
+    };
+    let phrase = squash(phrase);
+    !phrase.is_empty() && squash(transcript).contains(&phrase)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn phrases_are_heard_however_they_are_written() {
+        assert!(heard("Hey, sub-space!", "hey subspace"));
+        assert!(heard(" okay computer start", "okay computer"));
+        assert!(!heard("hey there", "hey subspace"));
+        assert!(!heard("start the dictation", "start dictation"));
+        assert!(!heard("anything", ""));
+
+        assert_eq!(cpu_percent(30_000, 1_000), None);
+        assert_eq!(cpu_percent(100_000, 2_500), Some(2.5));
+        assert!(validate_phrase("okay computer").is_ok());
+        assert!(validate_phrase("open sesame").is_err());
+    }
+}
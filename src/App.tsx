@@ -5,6 +5,7 @@
 
 import { useState, useCallback, useEffect } from 'react';
 import { invoke } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
 import { useAudioRecorder, RecordingState } from './hooks/useAudioRecorder';
 import { useDeepgram, ConnectionState } from './hooks/useDeepgram';
 import './App.css';
@@ -44,6 +45,13 @@ function App() {
       }
     }
     checkApiKey();
+    // A key added to .env (or removed) after launch is picked up without a restart
+    const unlisten = listen<{ configured: boolean }>('api-key-changed', (event) => {
+      setIsApiKeyConfigured(event.payload.configured);
+    });
+    return () => {
+      unlisten.then((fn) => fn());
+    };
   }, []);
 
   // Combined error message
@@ -76,6 +84,26 @@ function App() {
     disconnect();
   }, [stopRecording, disconnect]);
 
+  /**
+   * Global push-to-talk shortcut (handled by the backend, works while unfocused)
+   */
+  useEffect(() => {
+    const unlisteners = Promise.all([
+      listen('ptt-pressed', () => handleStartRecording()),
+      listen('ptt-released', () => handleStopRecording()),
+      listen<{ active: boolean }>('ptt-toggled', (event) => {
+        if (event.payload.active) {
+          handleStartRecording();
+        } else {
+          handleStopRecording();
+        }
+      }),
+    ]);
+    return () => {
+      unlisteners.then((fns) => fns.forEach((unlisten) => unlisten()));
+    };
+  }, [handleStartRecording, handleStopRecording]);
+
   /**
    * Get status text for UI display
    */
@@ -1,40 +1,57 @@
 /**
- * Custom hook for Deepgram WebSocket streaming transcription
- * Handles connection, audio streaming, and transcript parsing
+ * Custom hook for Deepgram streaming transcription
+ * The WebSocket lives in the Tauri backend; this hook streams audio over IPC
+ * and listens for transcript events, so the API key never reaches the webview
  */
 
 import { useState, useCallback, useRef, useEffect } from 'react';
 import { invoke } from '@tauri-apps/api/core';
+import { listen, UnlistenFn } from '@tauri-apps/api/event';
 
 // Connection state type
 export type ConnectionState = 'disconnected' | 'connecting' | 'connected' | 'error';
 
-// Deepgram transcription response types
-interface DeepgramWord {
-  word: string;
+// One segment as it should be shown now; the backend keeps its id across revisions
+interface DisplaySegment {
+  id: number;
+  text: string;
   start: number;
   end: number;
-  confidence: number;
+  // Diarized speaker, numbered from 0; null unless diarization is on
+  speaker: number | null;
+  // Audio channel and its speaker name, in multichannel sessions only
+  channel?: number;
+  speaker_name?: string;
+  status: 'interim' | 'final' | 'removed';
 }
 
-interface DeepgramAlternative {
-  transcript: string;
-  confidence: number;
-  words: DeepgramWord[];
+// A final segment, sent once
+interface CommittedSegment {
+  id: number;
+  text: string;
+  start: number;
+  end: number;
+  speaker: number | null;
+  channel?: number;
+  speaker_name?: string;
+  // The translation, when translation is on; the original text is kept if it failed
+  translated: string | null;
+  translation_failed: boolean;
 }
 
-interface DeepgramChannel {
-  alternatives: DeepgramAlternative[];
+// Error shape returned by backend commands
+interface AppError {
+  code: string;
+  message: string;
+  status?: number;
 }
 
-interface DeepgramResponse {
-  type: 'Results' | 'Metadata' | 'UtteranceEnd' | 'SpeechStarted';
-  channel_index?: number[];
-  duration?: number;
-  start?: number;
-  is_final?: boolean;
-  speech_final?: boolean;
-  channel?: DeepgramChannel;
+function toAppError(err: unknown): AppError {
+  if (err && typeof err === 'object' && 'code' in err && 'message' in err) {
+    return err as AppError;
+  }
+  const message = typeof err === 'string' ? err : err instanceof Error ? err.message : 'Unknown error';
+  return { code: 'unknown', message };
 }
 
 interface UseDeepgramReturn {
@@ -48,169 +65,103 @@ interface UseDeepgramReturn {
   clearTranscript: () => void;
 }
 
-// Deepgram API configuration - simpler params for debugging
-const DEEPGRAM_WS_URL = 'wss://api.deepgram.com/v1/listen';
-
 export function useDeepgram(): UseDeepgramReturn {
   const [connectionState, setConnectionState] = useState<ConnectionState>('disconnected');
   const [error, setError] = useState<string | null>(null);
   const [interimTranscript, setInterimTranscript] = useState('');
   const [finalTranscript, setFinalTranscript] = useState('');
-  
-  const websocketRef = useRef<WebSocket | null>(null);
-  const reconnectTimeoutRef = useRef<ReturnType<typeof setTimeout> | null>(null);
+
+  const connectedRef = useRef(false);
+  const unlistenRef = useRef<UnlistenFn[]>([]);
+  // Interim segments by id, rendered in start order
+  const interimsRef = useRef<Map<number, DisplaySegment>>(new Map());
 
   /**
-   * Connect to Deepgram WebSocket API
+   * Stop listening to backend transcript events
+   */
+  const removeListeners = useCallback(() => {
+    unlistenRef.current.forEach(unlisten => unlisten());
+    unlistenRef.current = [];
+  }, []);
+
+  /**
+   * Start a Deepgram stream in the backend and subscribe to its events
    */
   const connect = useCallback(async () => {
     try {
       setConnectionState('connecting');
       setError(null);
 
-      // Get API key from Tauri backend (secure)
-      const apiKey = await invoke<string>('get_deepgram_api_key');
-      
-      console.log('API key retrieved, length:', apiKey?.length);
-
-      // Build WebSocket URL with parameters
-      const params = new URLSearchParams({
-        model: 'nova-2',
-        language: 'en',
-        smart_format: 'true',
-        interim_results: 'true',
-        encoding: 'linear16',
-        sample_rate: '16000',
-        channels: '1',
-      });
-      
-      // Include API key directly in URL for browser WebSocket (no header support)
-      const wsUrl = `wss://api.deepgram.com/v1/listen?${params.toString()}`;
-      console.log('Connecting to Deepgram WebSocket...');
-
-      // Create WebSocket - try with Authorization in subprotocol first
-      let ws: WebSocket;
-      try {
-        // Method 1: Use token subprotocol (Deepgram's documented approach)
-        ws = new WebSocket(wsUrl, ['token', apiKey]);
-      } catch (e) {
-        console.log('Subprotocol failed, trying direct connection...');
-        // Method 2: Fallback - some browsers may not support subprotocol auth
-        const urlWithKey = `wss://api.deepgram.com/v1/listen?${params.toString()}&token=${apiKey}`;
-        ws = new WebSocket(urlWithKey);
-      }
-      
-      websocketRef.current = ws;
-      
-      // Set binary type for audio data
-      ws.binaryType = 'arraybuffer';
-
-      // Handle connection opened
-      ws.onopen = () => {
-        console.log('Deepgram WebSocket connected successfully!');
-        setConnectionState('connected');
-        setError(null);
-      };
-
-      // Handle incoming messages (transcriptions)
-      ws.onmessage = (event) => {
-        try {
-          const response: DeepgramResponse = JSON.parse(event.data);
-
-          if (response.type === 'Results' && response.channel) {
-            const alternative = response.channel.alternatives[0];
-            if (alternative) {
-              const transcript = alternative.transcript;
-
-              if (response.is_final) {
-                // Final result - append to final transcript
-                if (transcript.trim()) {
-                  setFinalTranscript(prev => {
-                    const separator = prev && !prev.endsWith(' ') ? ' ' : '';
-                    return prev + separator + transcript;
-                  });
-                  setInterimTranscript('');
-                }
-              } else {
-                // Interim result - show as preview
-                setInterimTranscript(transcript);
-              }
-            }
+      removeListeners();
+      unlistenRef.current = await Promise.all([
+        listen<DisplaySegment>('transcript-display', (event) => {
+          const segment = event.payload;
+          if (segment.status === 'interim') {
+            interimsRef.current.set(segment.id, segment);
+          } else {
+            interimsRef.current.delete(segment.id);
           }
-        } catch (parseError) {
-          console.error('Failed to parse Deepgram response:', parseError);
-        }
-      };
-
-      // Handle errors
-      ws.onerror = (event) => {
-        console.error('Deepgram WebSocket error:', event);
-        setError('WebSocket connection error. Please check your internet connection.');
-        setConnectionState('error');
-      };
-
-      // Handle connection closed
-      ws.onclose = (event) => {
-        console.log('Deepgram WebSocket closed:', event.code, event.reason);
-        
-        if (event.code === 1000) {
-          // Normal closure
-          setConnectionState('disconnected');
-        } else if (event.code === 1008) {
-          // Policy violation (usually auth error)
-          setError('Authentication failed. Please check your Deepgram API key.');
+          const interims = [...interimsRef.current.values()].sort((a, b) => a.start - b.start);
+          setInterimTranscript(interims.map(interim => interim.text).join(' '));
+        }),
+        listen<CommittedSegment>('transcript-committed', (event) => {
+          const text = event.payload.translated ?? event.payload.text;
+          setFinalTranscript(prev => {
+            const separator = prev && !prev.endsWith(' ') ? ' ' : '';
+            return prev + separator + text;
+          });
+        }),
+        listen<string>('stream-error', (event) => {
+          console.error('Deepgram stream error:', event.payload);
+          connectedRef.current = false;
+          setError(event.payload);
           setConnectionState('error');
-        } else if (event.code === 1011) {
-          // Server error
-          setError('Deepgram server error. Please try again later.');
-          setConnectionState('error');
-        } else {
-          // Unexpected closure
-          setError(`Connection closed unexpectedly (code: ${event.code})`);
-          setConnectionState('error');
-        }
-        
-        websocketRef.current = null;
-      };
+        }),
+      ]);
 
+      await invoke('start_session');
+      connectedRef.current = true;
+      console.log('Deepgram stream started');
+      setConnectionState('connected');
     } catch (err) {
       console.error('Failed to connect to Deepgram:', err);
-      
-      if (err instanceof Error && err.message.includes('DEEPGRAM_API_KEY')) {
+      removeListeners();
+
+      // Commands reject with { code, message } (see src-tauri/src/error.rs)
+      const { code, message } = toAppError(err);
+      if (code === 'key_not_configured') {
         setError('API key not configured. Please set DEEPGRAM_API_KEY in your .env file.');
       } else {
-        setError(`Failed to connect: ${err instanceof Error ? err.message : 'Unknown error'}`);
+        setError(`Failed to connect: ${message}`);
       }
-      
+
       setConnectionState('error');
     }
-  }, []);
+  }, [removeListeners]);
 
   /**
-   * Disconnect from Deepgram WebSocket
+   * Stop the backend Deepgram stream
    */
   const disconnect = useCallback(() => {
-    if (reconnectTimeoutRef.current) {
-      clearTimeout(reconnectTimeoutRef.current);
-      reconnectTimeoutRef.current = null;
-    }
-
-    if (websocketRef.current) {
-      websocketRef.current.close(1000, 'User stopped recording');
-      websocketRef.current = null;
+    if (connectedRef.current) {
+      connectedRef.current = false;
+      invoke('stop_session').catch(err => console.error('Failed to stop stream:', err));
     }
 
+    removeListeners();
     setConnectionState('disconnected');
+    interimsRef.current.clear();
     setInterimTranscript('');
-  }, []);
+  }, [removeListeners]);
 
   /**
-   * Send audio data to Deepgram
+   * Send audio data to the backend stream
    * @param audioData - PCM audio data as ArrayBuffer
    */
   const sendAudio = useCallback((audioData: ArrayBuffer) => {
-    if (websocketRef.current?.readyState === WebSocket.OPEN) {
-      websocketRef.current.send(audioData);
+    if (connectedRef.current) {
+      invoke('send_audio_chunk', { chunk: Array.from(new Uint8Array(audioData)) })
+        .catch(err => console.error('Failed to send audio chunk:', err));
     }
   }, []);
 
@@ -218,6 +169,7 @@ export function useDeepgram(): UseDeepgramReturn {
    * Clear all transcripts
    */
   const clearTranscript = useCallback(() => {
+    interimsRef.current.clear();
     setInterimTranscript('');
     setFinalTranscript('');
   }, []);
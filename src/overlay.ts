@@ -0,0 +1,53 @@
+// Caption overlay window: the latest lines of the live transcript, styled by the
+// overlay settings. The backend opens, places and hides the window
+import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
+
+interface OverlayOptions {
+  font_size: number;
+  opacity: number;
+}
+
+interface TranscriptEvent {
+  transcript: string;
+}
+
+// Keep the last couple of final lines on screen, and clear them after a pause
+const MAX_LINES = 2;
+const CLEAR_AFTER_MS = 8000;
+
+const captions = document.getElementById("captions") as HTMLElement;
+const finalEl = document.getElementById("final") as HTMLElement;
+const interimEl = document.getElementById("interim") as HTMLElement;
+let lines: string[] = [];
+let clearTimer: number | undefined;
+
+function applyOptions(options: OverlayOptions) {
+  captions.style.fontSize = `${options.font_size}px`;
+  captions.style.background = `rgba(0, 0, 0, ${options.opacity})`;
+}
+
+function scheduleClear() {
+  window.clearTimeout(clearTimer);
+  clearTimer = window.setTimeout(() => {
+    lines = [];
+    finalEl.textContent = "";
+    interimEl.textContent = "";
+  }, CLEAR_AFTER_MS);
+}
+
+invoke<OverlayOptions>("get_overlay_options").then(applyOptions);
+listen<OverlayOptions>("overlay-options", (event) => applyOptions(event.payload));
+listen<TranscriptEvent>("transcript-partial", (event) => {
+  interimEl.textContent = event.payload.transcript;
+  scheduleClear();
+});
+listen<TranscriptEvent>("transcript-final", (event) => {
+  const text = event.payload.transcript.trim();
+  if (text) {
+    lines = lines.concat(text).slice(-MAX_LINES);
+    finalEl.textContent = lines.join(" ");
+  }
+  interimEl.textContent = "";
+  scheduleClear();
+});